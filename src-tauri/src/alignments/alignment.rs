@@ -7,6 +7,8 @@ use std::marker::PhantomData;
 use anyhow::{bail, Result};
 use serde::Serialize;
 
+use crate::alignments::stack::StackGrouping;
+
 /// Alignment trait to be implemented by concrete record types.
 pub trait Alignment {
     /// Unique id for the alignment.
@@ -18,6 +20,17 @@ pub trait Alignment {
 
     /// Genomic end position
     fn end(&self) -> u64;
+
+    /// Key of the group which the alignment is stacked in, or None if it doesn't have a value for
+    /// the grouped field.
+    fn group_key(&self, _grouping: StackGrouping) -> Option<String> {
+        None
+    }
+
+    /// Number of reads which the alignment contains (e.g two for a pair).
+    fn num_reads(&self) -> usize {
+        1
+    }
 }
 
 /// Marker struct which indicates an AlignmentSearchList is sorted by start position
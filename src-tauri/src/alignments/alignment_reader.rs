@@ -1,12 +1,184 @@
 use anyhow::Result;
+use serde::Serialize;
 
 use crate::alignments::alignment::Alignment;
+use crate::alignments::cancellation::CancellationToken;
 use crate::bio_util::genomic_coordinates::GenomicRegion;
 use crate::bio_util::sequence::SequenceView;
 
+/// Maximum number of skipped reads which are individually reported to the frontend.
+pub const MAX_REPORTED_SKIPPED_READS: usize = 100;
+
+/// A record which could not be converted into an alignment.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedRead {
+    pub qname: String,
+    pub reason: String,
+}
+
+/// Records which were skipped during a read operation.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedReads {
+    /// Total number of skipped records.
+    pub count: usize,
+
+    /// Skipped records (truncated to MAX_REPORTED_SKIPPED_READS).
+    pub reads: Vec<SkippedRead>,
+
+    /// Number of valid records which were excluded by the track's read filter (not included in
+    /// count).
+    pub filtered_count: usize,
+
+    /// Number of unmapped records which were placed in the region (e.g next to their mapped mate)
+    /// and can't be displayed (not included in count).
+    pub unmapped_count: usize,
+
+    /// Why each record was skipped and the span of the reference it was placed on (if known), so
+    /// that the records which were skipped in part of the region can be recounted.
+    #[serde(skip)]
+    spans: Vec<(SkipReason, Option<(u64, u64)>)>,
+}
+
+/// Which of the counts of SkippedReads a record is included in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum SkipReason {
+    Invalid,
+    Filtered,
+    Unmapped,
+}
+
+impl SkippedReads {
+    /// Add a record which couldn't be converted into an alignment, and whose position is unknown.
+    pub fn push(&mut self, read: SkippedRead) {
+        self.push_invalid(read, None);
+    }
+
+    /// Add a record which couldn't be converted into an alignment but was placed between start and
+    /// end.
+    pub fn push_at(&mut self, read: SkippedRead, start: u64, end: u64) {
+        self.push_invalid(read, Some((start, end)));
+    }
+
+    /// Add a record placed between start and end which was excluded by the read filter.
+    pub fn push_filtered(&mut self, start: u64, end: u64) {
+        self.filtered_count += 1;
+        self.spans.push((SkipReason::Filtered, Some((start, end))));
+    }
+
+    /// Add an unmapped record which was placed between start and end.
+    pub fn push_unmapped(&mut self, start: u64, end: u64) {
+        self.unmapped_count += 1;
+        self.spans.push((SkipReason::Unmapped, Some((start, end))));
+    }
+
+    fn push_invalid(&mut self, read: SkippedRead, span: Option<(u64, u64)>) {
+        self.count += 1;
+        if self.reads.len() < MAX_REPORTED_SKIPPED_READS {
+            self.reads.push(read);
+        }
+        self.spans.push((SkipReason::Invalid, span));
+    }
+
+    /// Get the records which were skipped within part of the region that they were read from.
+    ///
+    /// Records whose position is unknown are assumed to overlap it.
+    pub fn within(&self, region: &GenomicRegion) -> SkippedReads {
+        let mut skipped_reads = SkippedReads::default();
+        let mut invalid_reads = self.reads.iter();
+        for (reason, span) in &self.spans {
+            let read = match reason {
+                SkipReason::Invalid => invalid_reads.next(),
+                SkipReason::Filtered | SkipReason::Unmapped => None,
+            };
+            let overlaps =
+                span.map_or(true, |(start, end)| start < region.end() && end > region.start());
+            if !overlaps {
+                continue;
+            }
+            match reason {
+                SkipReason::Invalid => {
+                    // Reads which weren't reported in the whole region are only counted
+                    skipped_reads.count += 1;
+                    skipped_reads.reads.extend(read.cloned());
+                }
+                SkipReason::Filtered => skipped_reads.filtered_count += 1,
+                SkipReason::Unmapped => skipped_reads.unmapped_count += 1,
+            }
+            skipped_reads.spans.push((*reason, *span));
+        }
+        skipped_reads
+    }
+}
+
 /// Trait for a generic reader struct which reads alignments from a file.
 pub trait AlignmentReader {
     type Item: Alignment;
 
-    fn read(&mut self, region: &GenomicRegion, refseq: &SequenceView) -> Result<Vec<Self::Item>>;
+    /// Read alignments from a region of the file.
+    ///
+    /// Records which can't be converted into alignments are skipped rather than failing the whole
+    /// read.
+    fn read(
+        &mut self,
+        region: &GenomicRegion,
+        refseq: &SequenceView,
+    ) -> Result<(Vec<Self::Item>, SkippedReads)>;
+
+    /// Read alignments from a region of the file, failing with a CancelledError if the token is
+    /// cancelled before the read finishes.
+    ///
+    /// By default the token is only checked before and after reading. Readers of large files
+    /// should also check it while reading so that cancelled reads stop early.
+    fn read_cancellable(
+        &mut self,
+        region: &GenomicRegion,
+        refseq: &SequenceView,
+        cancellation: &CancellationToken,
+    ) -> Result<(Vec<Self::Item>, SkippedReads)> {
+        cancellation.check()?;
+        let result = self.read(region, refseq)?;
+        cancellation.check()?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    pub fn test_skipped_reads_are_truncated() {
+        let mut skipped_reads = SkippedReads::default();
+        for i in 0..MAX_REPORTED_SKIPPED_READS + 10 {
+            skipped_reads
+                .push(SkippedRead { qname: format!("read{}", i), reason: "Invalid".to_owned() });
+        }
+        assert_eq!(skipped_reads.count, MAX_REPORTED_SKIPPED_READS + 10);
+        assert_eq!(skipped_reads.reads.len(), MAX_REPORTED_SKIPPED_READS);
+    }
+
+    #[test]
+    pub fn test_skipped_reads_within_region() {
+        let mut skipped_reads = SkippedReads::default();
+        let read =
+            |qname: &str| SkippedRead { qname: qname.to_owned(), reason: "Invalid".to_owned() };
+        skipped_reads.push_at(read("outside"), 100, 200);
+        skipped_reads.push_at(read("inside"), 1000, 1100);
+        skipped_reads.push(read("unknown"));
+        skipped_reads.push_filtered(1050, 1150);
+        skipped_reads.push_filtered(50, 150);
+        skipped_reads.push_unmapped(1099, 1100);
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let within = skipped_reads.within(&region);
+        assert_eq!(within.count, 2);
+        let qnames: Vec<_> = within.reads.iter().map(|read| read.qname.as_str()).collect();
+        assert_eq!(qnames, vec!["inside", "unknown"]);
+        assert_eq!(within.filtered_count, 1);
+        assert_eq!(within.unmapped_count, 1);
+        assert_eq!(within.within(&region), within);
+    }
 }
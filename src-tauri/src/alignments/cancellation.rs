@@ -0,0 +1,62 @@
+//! Cancellation of alignment reads which are no longer needed (e.g because the user panned again
+//! before the read finished).
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use thiserror::Error;
+
+/// Returned by a read which was cancelled before it finished.
+#[derive(Debug, Error, Eq, PartialEq)]
+#[error("Read was cancelled")]
+pub struct CancelledError;
+
+/// Whether an error was caused by a read having been cancelled.
+pub fn is_cancelled_error(err: &anyhow::Error) -> bool {
+    err.is::<CancelledError>()
+}
+
+/// Shared flag which is checked by a read as it runs, so that it can be cancelled from another
+/// thread. Clones of a token share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fail with a CancelledError if the token has been cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(CancelledError.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_token_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(clone.check().is_ok());
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert!(is_cancelled_error(&clone.check().unwrap_err()));
+        assert!(!CancellationToken::new().is_cancelled());
+    }
+}
@@ -0,0 +1,251 @@
+//! Deciding which diff labels (mismatched bases and indel lengths) fit at a given scale.
+//!
+//! Labels which would overlap each other are hidden so that exported figures and dense views stay
+//! legible. The label dimensions mirror the drawing constants in the frontend's
+//! AlignedReadsScene.
+use serde::Serialize;
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::sam_bam::aligned_read::AlignedRead;
+use crate::file_formats::sam_bam::diff::{DiffKind, SequenceDiff};
+
+const READ_HEIGHT: f64 = 20.0;
+const MISMATCH_FONT_SIZE: f64 = READ_HEIGHT - 2.0;
+const DELETION_FONT_SIZE: f64 = MISMATCH_FONT_SIZE;
+const INSERTION_FONT_SIZE: f64 = READ_HEIGHT - 4.0;
+const DELETION_LABEL_PADDING: f64 = 2.0;
+const INSERTION_LABEL_PADDING: f64 = 3.0;
+/// Extra width of the box drawn around an insertion label.
+const INSERTION_BOX_MARGIN: f64 = 5.0;
+
+/// Character width as a fraction of the font size.
+const FONT_CHAR_WIDTH: f64 = 0.6;
+
+/// Bases are drawn as letters (rather than colored blocks) once they're wider than this in px.
+pub const DRAW_LETTER_THRESHOLD: f64 = 12.0;
+
+/// Deletions shorter than this are never labeled with their length.
+pub const MIN_DELETION_LENGTH_FOR_LABEL: u64 = 5;
+
+/// A label for a single diff and whether it should be shown at the current scale.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLabel {
+    pub read_id: String,
+    /// Index of the labeled diff in the read's diffs.
+    pub diff_index: usize,
+    /// Index of the stack row which contains the read.
+    pub row: usize,
+    pub text: String,
+    /// Horizontal extent of the label in px, relative to the start of the region.
+    pub x_start: f64,
+    pub x_end: f64,
+    /// False if the label doesn't fit in its diff or would overlap a higher priority label.
+    pub show: bool,
+}
+
+/// A label which hasn't yet been checked for collisions.
+struct LabelCandidate<'a> {
+    read_id: &'a str,
+    diff_index: usize,
+    text: String,
+    x_start: f64,
+    x_end: f64,
+    /// Whether the label fits within the diff it labels (ignoring other labels).
+    fits: bool,
+    /// Labels with a lower priority are placed first.
+    priority: u8,
+}
+
+fn text_width(text: &str, font_size: f64) -> f64 {
+    FONT_CHAR_WIDTH * font_size * text.chars().count() as f64
+}
+
+/// Get the label of a diff (if it has one) in the same layout as the frontend draws it.
+fn get_label_candidate<'a>(
+    read: &'a AlignedRead,
+    diff_index: usize,
+    diff: &SequenceDiff,
+    region: &GenomicRegion,
+    nuc_width: f64,
+) -> Option<LabelCandidate<'a>> {
+    let interval = diff.interval();
+    let x_start = (interval.start as f64 - region.start() as f64) * nuc_width;
+    let diff_width = (interval.end - interval.start) as f64 * nuc_width;
+    let candidate = |text: String, x_start: f64, x_end: f64, fits: bool, priority: u8| {
+        Some(LabelCandidate { read_id: &read.id, diff_index, text, x_start, x_end, fits, priority })
+    };
+    match diff {
+        // Insertions are always labeled since they're otherwise invisible
+        SequenceDiff::Ins { sequence, full_length, .. } => {
+            let length = full_length.map_or(sequence.len(), |length| length as usize);
+            let text = if length == 1 { sequence.clone() } else { length.to_string() };
+            let width = text_width(&text, INSERTION_FONT_SIZE)
+                + 2.0 * INSERTION_LABEL_PADDING
+                + INSERTION_BOX_MARGIN;
+            candidate(text, x_start - width / 2.0, x_start + width / 2.0, true, 0)
+        }
+        SequenceDiff::Del { interval } => {
+            let length = interval.end - interval.start;
+            if length < MIN_DELETION_LENGTH_FOR_LABEL {
+                return None;
+            }
+            let text = length.to_string();
+            let width = text_width(&text, DELETION_FONT_SIZE) + 2.0 * DELETION_LABEL_PADDING;
+            let center = x_start + diff_width / 2.0;
+            let fits = width <= diff_width;
+            candidate(text, center - width / 2.0, center + width / 2.0, fits, 1)
+        }
+        SequenceDiff::Mismatch { sequence, .. } | SequenceDiff::SoftClip { sequence, .. } => {
+            let fits = nuc_width > DRAW_LETTER_THRESHOLD;
+            candidate(sequence.clone(), x_start, x_start + diff_width, fits, 2)
+        }
+        SequenceDiff::RefSkip { .. } => None,
+    }
+}
+
+/// Decide which diff labels can be shown in each row of a stack at a given scale.
+///
+/// Within a row, insertion labels are placed first, then deletion labels, then mismatched bases.
+/// Labels which would overlap an already placed label are hidden. Only diffs which overlap the
+/// region are labeled.
+///
+/// # Arguments
+///
+/// * `rows` - Reads in each row of the stack.
+/// * `region` - Region which is drawn, the start of which is at x=0.
+/// * `nuc_width` - Width of a single base in px.
+pub fn place_diff_labels<'a, R: IntoIterator<Item = &'a AlignedRead>>(
+    rows: impl IntoIterator<Item = R>,
+    region: &GenomicRegion,
+    nuc_width: f64,
+) -> Vec<DiffLabel> {
+    let mut labels = Vec::new();
+    for (row, reads) in rows.into_iter().enumerate() {
+        let mut candidates: Vec<LabelCandidate> = reads
+            .into_iter()
+            .filter(|read| read.region.seq_name == region.seq_name)
+            .flat_map(|read| {
+                read.diffs.iter().enumerate().filter_map(move |(diff_index, diff)| {
+                    let interval = diff.interval();
+                    let overlaps = match diff.kind() {
+                        // Insertions have an empty interval
+                        DiffKind::Ins => {
+                            region.start() <= interval.start && interval.start <= region.end()
+                        }
+                        _ => interval.overlaps(&region.interval),
+                    };
+                    if !overlaps {
+                        return None;
+                    }
+                    get_label_candidate(read, diff_index, diff, region, nuc_width)
+                })
+            })
+            .collect();
+        candidates
+            .sort_by(|a, b| a.priority.cmp(&b.priority).then(a.x_start.total_cmp(&b.x_start)));
+
+        // Extents of the shown labels, sorted by start. Labels may touch (e.g adjacent bases) but not
+        // overlap.
+        let mut placed: Vec<(f64, f64)> = Vec::new();
+        for candidate in candidates {
+            let i = placed.partition_point(|(x_start, _)| *x_start < candidate.x_start);
+            let overlaps_prev = i > 0 && placed[i - 1].1 > candidate.x_start;
+            let overlaps_next = i < placed.len() && candidate.x_end > placed[i].0;
+            let show = candidate.fits && !overlaps_prev && !overlaps_next;
+            if show {
+                placed.insert(i, (candidate.x_start, candidate.x_end));
+            }
+            labels.push(DiffLabel {
+                read_id: candidate.read_id.to_owned(),
+                diff_index: candidate.diff_index,
+                row,
+                text: candidate.text,
+                x_start: candidate.x_start,
+                x_end: candidate.x_end,
+                show,
+            });
+        }
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::bio_util::genomic_coordinates::GenomicInterval;
+    use crate::test_util::aligned_reads::gen_read;
+
+    fn mismatch(position: u64, base: &str) -> SequenceDiff {
+        let interval = GenomicInterval::new(position, position + 1).unwrap();
+        SequenceDiff::Mismatch { interval, sequence: base.to_owned() }
+    }
+
+    fn shown(labels: &[DiffLabel]) -> Vec<(&str, usize, bool)> {
+        labels.iter().map(|label| (label.read_id.as_str(), label.diff_index, label.show)).collect()
+    }
+
+    #[test]
+    fn test_mismatch_labels_depend_on_scale() {
+        let region = GenomicRegion::new("X", 100, 200).unwrap();
+        let read = gen_read("a", 100, 150, vec![mismatch(101, "A"), mismatch(102, "C")]);
+        let labels = place_diff_labels([[&read]], &region, 15.0);
+        assert_eq!(shown(&labels), vec![("a", 0, true), ("a", 1, true)]);
+        assert_eq!((labels[0].x_start, labels[0].x_end), (15.0, 30.0));
+        let labels = place_diff_labels([[&read]], &region, 5.0);
+        assert_eq!(shown(&labels), vec![("a", 0, false), ("a", 1, false)]);
+    }
+
+    #[test]
+    fn test_deletion_labels_must_fit() {
+        let region = GenomicRegion::new("X", 100, 200).unwrap();
+        let short_del = SequenceDiff::Del { interval: GenomicInterval::new(110, 112).unwrap() };
+        let long_del = SequenceDiff::Del { interval: GenomicInterval::new(120, 130).unwrap() };
+        let read = gen_read("a", 100, 150, vec![short_del, long_del]);
+        // Short deletions are never labeled
+        let labels = place_diff_labels([[&read]], &region, 10.0);
+        assert_eq!(shown(&labels), vec![("a", 1, true)]);
+        assert_eq!(labels[0].text, "10");
+        let labels = place_diff_labels([[&read]], &region, 1.0);
+        assert_eq!(shown(&labels), vec![("a", 1, false)]);
+    }
+
+    #[test]
+    fn test_colliding_labels_are_hidden() {
+        let region = GenomicRegion::new("X", 100, 200).unwrap();
+        let ins = |position: u64| SequenceDiff::Ins {
+            interval: GenomicInterval::new(position, position).unwrap(),
+            sequence: "AAAAAAAAAA".to_owned(),
+            full_length: None,
+        };
+        let read_a = gen_read("a", 100, 120, vec![ins(110), mismatch(111, "G"), ins(112)]);
+        let read_b = gen_read("b", 121, 150, vec![ins(121), ins(140)]);
+        let other_row = gen_read("c", 100, 150, vec![ins(112)]);
+        let labels = place_diff_labels([vec![&read_a, &read_b], vec![&other_row]], &region, 13.0);
+        // Insertions are placed before mismatches and left to right
+        assert_eq!(
+            shown(&labels),
+            vec![
+                ("a", 0, true),
+                ("a", 2, false),
+                ("b", 0, true),
+                ("b", 1, true),
+                ("a", 1, false),
+                ("c", 0, true),
+            ]
+        );
+        assert_eq!(labels[5].row, 1);
+    }
+
+    #[test]
+    fn test_diffs_outside_region_are_ignored() {
+        let region = GenomicRegion::new("X", 100, 110).unwrap();
+        let read = gen_read("a", 90, 120, vec![mismatch(95, "A"), mismatch(105, "C")]);
+        let labels = place_diff_labels([[&read]], &region, 20.0);
+        assert_eq!(shown(&labels), vec![("a", 1, true)]);
+        let other_contig = GenomicRegion::new("Y", 100, 110).unwrap();
+        assert!(place_diff_labels([[&read]], &other_contig, 20.0).is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+//! Downsampling alignments in dense regions (e.g amplicon sequencing) to a maximum read depth.
+//!
+//! Alignments are sampled within fixed-width windows of start positions so that every part of the
+//! region keeps reads, rather than the reads which start first filling all of the available depth.
+//! The sampling is random but deterministic (it's seeded by the alignment ids) so that the same
+//! alignments are kept each time a region is read.
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::alignments::alignment::Alignment;
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::remote_cache::block_cache::fnv1a;
+
+/// Width of the windows of start positions which alignments are sampled within.
+pub const DOWNSAMPLING_WINDOW: u64 = 50;
+
+/// How many alignments were dropped from a region by downsampling.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Downsampling {
+    pub region: GenomicRegion,
+    pub max_depth: u32,
+    #[serde_as(as = "DisplayFromStr")]
+    pub num_alignments: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub num_sampled: u64,
+}
+
+impl Downsampling {
+    /// Fraction of the alignments in the region which were kept.
+    pub fn sampled_fraction(&self) -> f64 {
+        if self.num_alignments == 0 {
+            return 1.0;
+        }
+        self.num_sampled as f64 / self.num_alignments as f64
+    }
+}
+
+/// Drop alignments so that the depth of the remaining alignments never exceeds max_depth.
+///
+/// Within each window of start positions, alignments are visited in a pseudo-random order and
+/// kept while there's spare depth. Kept alignments are returned in their original order.
+pub fn downsample<T: Alignment>(alignments: Vec<T>, max_depth: usize) -> Vec<T> {
+    if alignments.len() <= max_depth {
+        return alignments;
+    }
+    let mut order: Vec<(u64, u64, usize)> = alignments
+        .iter()
+        .enumerate()
+        .map(|(i, alignment)| {
+            (alignment.start() / DOWNSAMPLING_WINDOW, fnv1a(alignment.id().as_bytes()), i)
+        })
+        .collect();
+    order.sort_unstable();
+
+    let mut keep = vec![false; alignments.len()];
+    // Ends of the kept alignments which may still overlap the current window
+    let mut kept_ends: BinaryHeap<Reverse<u64>> = BinaryHeap::new();
+    let mut current_window = None;
+    for (window, _, i) in order {
+        if current_window != Some(window) {
+            let window_start = window * DOWNSAMPLING_WINDOW;
+            while kept_ends.peek().map_or(false, |Reverse(end)| *end <= window_start) {
+                kept_ends.pop();
+            }
+            current_window = Some(window);
+        }
+        // Alignments which end within the window are still counted so that the depth is never
+        // exceeded anywhere in the window
+        if kept_ends.len() < max_depth {
+            keep[i] = true;
+            kept_ends.push(Reverse(alignments[i].end()));
+        }
+    }
+    alignments
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(alignment, keep)| keep.then_some(alignment))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Interval {
+        id: String,
+        start: u64,
+        end: u64,
+    }
+
+    impl Alignment for Interval {
+        fn id(&self) -> &str {
+            &self.id
+        }
+        fn start(&self) -> u64 {
+            self.start
+        }
+        fn end(&self) -> u64 {
+            self.end
+        }
+    }
+
+    fn gen_intervals(starts: impl Iterator<Item = u64>, length: u64) -> Vec<Interval> {
+        starts
+            .enumerate()
+            .map(|(i, start)| Interval { id: i.to_string(), start, end: start + length })
+            .collect()
+    }
+
+    fn max_depth(intervals: &[Interval]) -> usize {
+        let positions = intervals.iter().flat_map(|interval| [interval.start, interval.end - 1]);
+        positions
+            .map(|position| {
+                intervals.iter().filter(|i| i.start <= position && position < i.end).count()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_downsample_caps_depth() {
+        // 10 reads starting at every position
+        let intervals = gen_intervals((0..1000).flat_map(|start| [start; 10]), 100);
+        let sampled = downsample(intervals.clone(), 30);
+        assert!(max_depth(&sampled) <= 30);
+        // Reads are kept across the whole region rather than just at the start
+        assert!(sampled.iter().any(|interval| interval.start >= 900));
+        assert!(sampled.windows(2).all(|pair| pair[0].start <= pair[1].start));
+        // Sampling is deterministic
+        assert_eq!(downsample(intervals, 30), sampled);
+    }
+
+    #[test]
+    fn test_downsample_keeps_shallow_regions() {
+        let intervals = gen_intervals((0..10).map(|start| start * 200), 100);
+        assert_eq!(downsample(intervals.clone(), 1), intervals);
+    }
+
+    #[test]
+    fn test_sampled_fraction() {
+        let region = GenomicRegion::new("X", 0, 100).unwrap();
+        let downsampling =
+            Downsampling { region, max_depth: 10, num_alignments: 40, num_sampled: 10 };
+        assert_eq!(downsampling.sampled_fraction(), 0.25);
+    }
+}
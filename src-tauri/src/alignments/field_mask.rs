@@ -0,0 +1,110 @@
+//! Trimming unneeded fields from serialized alignment stacks.
+use std::cell::RefCell;
+
+use serde::{Deserialize, Serialize};
+
+/// Optional fields of aligned reads which should be included when a stack is sent to the frontend.
+///
+/// Overview-style displays don't render e.g cigar strings so excluding them cuts the payload size.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlignmentFieldMask {
+    pub qname: bool,
+    pub cigar_string: bool,
+    pub diffs: bool,
+    pub mate_pos: bool,
+    /// Only has an effect for tracks which show base qualities.
+    pub base_qualities: bool,
+    pub base_modifications: bool,
+}
+
+impl Default for AlignmentFieldMask {
+    fn default() -> Self {
+        Self {
+            qname: true,
+            cigar_string: true,
+            diffs: true,
+            mate_pos: true,
+            base_qualities: true,
+            base_modifications: true,
+        }
+    }
+}
+
+thread_local! {
+    /// Mask which is applied to reads which are serialized on this thread.
+    static ACTIVE_FIELD_MASK: RefCell<AlignmentFieldMask> =
+        RefCell::new(AlignmentFieldMask::default());
+}
+
+/// Restores the mask which was active before a scope, even if serialization panics.
+struct RestoreFieldMask(AlignmentFieldMask);
+
+impl Drop for RestoreFieldMask {
+    fn drop(&mut self) {
+        let previous = std::mem::take(&mut self.0);
+        ACTIVE_FIELD_MASK.with(|mask| *mask.borrow_mut() = previous);
+    }
+}
+
+impl AlignmentFieldMask {
+    /// Run a function which serializes alignments, excluding the masked fields from their reads.
+    ///
+    /// Fields are skipped while the reads are serialized (see AlignedRead), so the mask applies to
+    /// any serializer without an intermediate JSON value.
+    pub fn scope<T, F: FnOnce() -> T>(&self, func: F) -> T {
+        let previous = ACTIVE_FIELD_MASK.with(|mask| mask.replace(self.clone()));
+        let _restore = RestoreFieldMask(previous);
+        func()
+    }
+}
+
+fn is_excluded(included: fn(&AlignmentFieldMask) -> bool) -> bool {
+    ACTIVE_FIELD_MASK.with(|mask| !included(&mask.borrow()))
+}
+
+// Used with skip_serializing_if on the masked fields of AlignedRead
+
+pub fn skip_qname<T>(_: &T) -> bool {
+    is_excluded(|mask| mask.qname)
+}
+
+pub fn skip_cigar_string<T>(_: &T) -> bool {
+    is_excluded(|mask| mask.cigar_string)
+}
+
+pub fn skip_diffs<T>(_: &T) -> bool {
+    is_excluded(|mask| mask.diffs)
+}
+
+pub fn skip_mate_pos<T>(_: &T) -> bool {
+    is_excluded(|mask| mask.mate_pos)
+}
+
+pub fn skip_base_qualities<T>(_: &T) -> bool {
+    is_excluded(|mask| mask.base_qualities)
+}
+
+pub fn skip_base_modifications<T>(_: &T) -> bool {
+    is_excluded(|mask| mask.base_modifications)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_field_mask_scope() {
+        assert!(!skip_cigar_string(&()));
+        let mask = AlignmentFieldMask { cigar_string: false, ..Default::default() };
+        let nested_mask = AlignmentFieldMask { diffs: false, ..Default::default() };
+        let skipped = mask.scope(|| {
+            let nested = nested_mask.scope(|| (skip_cigar_string(&()), skip_diffs(&())));
+            (skip_cigar_string(&()), skip_diffs(&()), nested)
+        });
+        assert_eq!(skipped, (true, false, (false, true)));
+        assert!(!skip_cigar_string(&()));
+    }
+}
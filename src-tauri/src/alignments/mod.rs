@@ -1,4 +1,10 @@
 pub mod alignment;
 pub mod alignment_reader;
+pub mod cancellation;
+pub mod diff_labels;
+pub mod downsampling;
+pub mod field_mask;
+pub mod render_window;
+pub mod sorting;
 pub mod stack;
 pub mod stack_reader;
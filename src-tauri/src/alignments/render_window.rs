@@ -0,0 +1,53 @@
+//! Tuning the window in which a track's alignments are rendered to the track's read density.
+
+/// Smallest render window which tuning will pick, so that alignments are always visible once
+/// zoomed in far enough, however deep the coverage.
+pub const MIN_TUNED_RENDER_WINDOW: u64 = 200;
+
+/// Pick the largest render window in which the expected number of alignments stays under a
+/// target.
+///
+/// The read density is estimated from the number of alignments observed in a region. The window
+/// is bounded by the split's max render window, which also limits how much reference sequence is
+/// buffered.
+///
+/// # Arguments
+///
+/// * `num_alignments` - Number of alignments observed in `region_len`.
+/// * `region_len` - Length of the region in which the alignments were observed.
+/// * `target_alignments` - Target for the number of alignments in the render window.
+/// * `max_render_window` - Upper bound on the render window.
+pub fn tune_render_window(
+    num_alignments: usize,
+    region_len: u64,
+    target_alignments: u64,
+    max_render_window: u64,
+) -> u64 {
+    if num_alignments == 0 || region_len == 0 {
+        return max_render_window;
+    }
+    let density = num_alignments as f64 / region_len as f64;
+    let window = (target_alignments as f64 / density) as u64;
+    window.clamp(MIN_TUNED_RENDER_WINDOW.min(max_render_window), max_render_window)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_tune_render_window() {
+        // Sparse tracks use the full window
+        assert_eq!(tune_render_window(0, 30000, 1000, 10000), 10000);
+        assert_eq!(tune_render_window(100, 30000, 1000, 10000), 10000);
+        // 1 alignment per 10bp
+        assert_eq!(tune_render_window(3000, 30000, 1000, 10000), 10000);
+        // 1 alignment per bp
+        assert_eq!(tune_render_window(30000, 30000, 1000, 10000), 1000);
+        // Very deep coverage (e.g amplicons) is limited to the minimum window
+        assert_eq!(tune_render_window(3_000_000, 30000, 1000, 10000), MIN_TUNED_RENDER_WINDOW);
+        assert_eq!(tune_render_window(3_000_000, 30000, 1000, 100), 100);
+    }
+}
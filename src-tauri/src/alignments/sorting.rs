@@ -0,0 +1,237 @@
+//! Sorting the rows of a stack by the alignments at a position (e.g IGV's "sort by base").
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::alignments::alignment::Alignment;
+use crate::alignments::stack::AlignmentStack;
+use crate::bio_util::genomic_coordinates::GenomicInterval;
+use crate::file_formats::sam_bam::aligned_read::{AlignedPair, AlignedRead};
+use crate::file_formats::sam_bam::diff::SequenceDiff;
+
+/// What the rows of a stack are sorted by.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SortBy {
+    /// Base at the position, with alternate alleles first (the most common first), then reads
+    /// which match the reference, then rows without an aligned base at the position.
+    Base,
+    /// Start position, earliest first.
+    Start,
+    /// Absolute template length (insert size), largest first.
+    TemplateLength,
+    /// Mapping quality, highest first.
+    MappingQuality,
+    /// Forward strand reads before reverse strand reads.
+    Strand,
+}
+
+/// Allele of a single read at a reference position.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+enum Allele {
+    Mismatch(u8),
+    Del,
+    Ref,
+}
+
+fn covers(interval: &GenomicInterval, position: u64) -> bool {
+    interval.start <= position && position < interval.end
+}
+
+/// Get the allele of a read at a position, or None if the read has no aligned base there (e.g
+/// it's soft clipped or spliced).
+fn get_allele(read: &AlignedRead, position: u64) -> Option<Allele> {
+    if !covers(&read.region.interval, position) {
+        return None;
+    }
+    for diff in read.diffs.iter() {
+        let interval = diff.interval();
+        if !covers(interval, position) {
+            continue;
+        }
+        return match diff {
+            SequenceDiff::Mismatch { sequence, .. } => {
+                let base = sequence.as_bytes().get((position - interval.start) as usize)?;
+                Some(Allele::Mismatch(base.to_ascii_uppercase()))
+            }
+            SequenceDiff::Del { .. } => Some(Allele::Del),
+            SequenceDiff::SoftClip { .. } | SequenceDiff::RefSkip { .. } => None,
+            // Insertions have an empty interval, so never contain the position
+            SequenceDiff::Ins { .. } => continue,
+        };
+    }
+    Some(Allele::Ref)
+}
+
+/// Get the read of a row at a position, for sorting by the fields of a single read.
+///
+/// If the position is between the reads of a pair then the pair's first read is used. Rows which
+/// don't have a pair at the position are sorted last.
+fn get_row_read(row: &VecDeque<AlignedPair>, position: u64) -> Option<&AlignedRead> {
+    let pair = row.iter().find(|pair| pair.start() <= position && position < pair.end())?;
+    pair.reads()
+        .find(|read| covers(&read.region.interval, position))
+        .or_else(|| pair.reads().next())
+}
+
+/// Sort rows by a key of the read at the position, with rows without a read there last.
+fn sort_by_read_key<K: Ord, F: Fn(&AlignedRead) -> K>(
+    stack: &mut AlignmentStack<AlignedPair>,
+    position: u64,
+    key: F,
+) -> Result<()> {
+    let keys: Vec<Option<K>> =
+        stack.rows.iter().map(|row| get_row_read(row, position).map(&key)).collect();
+    // None is otherwise ordered first
+    stack.sort_rows(keys.into_iter().map(|key| (key.is_none(), key)).collect())
+}
+
+/// Sort the rows of a stack of reads by the alignments at a position.
+pub fn sort_stack(
+    stack: &mut AlignmentStack<AlignedPair>,
+    position: u64,
+    by: SortBy,
+) -> Result<()> {
+    match by {
+        SortBy::Base => {
+            // A row contains at most one alignment at the position
+            let alleles: Vec<Option<Allele>> = stack
+                .rows
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .flat_map(|pair| pair.reads())
+                        .find_map(|read| get_allele(read, position))
+                })
+                .collect();
+            let mut counts: HashMap<Allele, usize> = HashMap::new();
+            for allele in alleles.iter().flatten() {
+                *counts.entry(*allele).or_default() += 1;
+            }
+            let keys = alleles
+                .into_iter()
+                .map(|allele| match allele {
+                    Some(Allele::Ref) => (1, Reverse(0), allele),
+                    Some(alt) => (0, Reverse(counts[&alt]), allele),
+                    None => (2, Reverse(0), allele),
+                })
+                .collect();
+            stack.sort_rows(keys)
+        }
+        SortBy::Start => sort_by_read_key(stack, position, |read| read.region.start()),
+        SortBy::TemplateLength => {
+            sort_by_read_key(stack, position, |read| Reverse(read.template_length.unsigned_abs()))
+        }
+        SortBy::MappingQuality => sort_by_read_key(stack, position, |read| Reverse(read.mapq)),
+        SortBy::Strand => sort_by_read_key(stack, position, |read| read.is_reverse),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::alignments::stack::{PackingStrategy, StackGrouping};
+    use crate::bio_util::genomic_coordinates::GenomicRegion;
+    use crate::file_formats::sam_bam::aligned_read::UnpairedRead;
+    use crate::test_util::aligned_reads::gen_read;
+
+    fn gen_pair(id: &str, start: u64, end: u64, diffs: Vec<SequenceDiff>) -> AlignedPair {
+        let read = gen_read(id, start, end, diffs);
+        AlignedPair::UnpairedReadKind(UnpairedRead::new(read))
+    }
+
+    fn mismatch(position: u64, base: &str) -> SequenceDiff {
+        let interval = GenomicInterval::new(position, position + 1).unwrap();
+        SequenceDiff::Mismatch { interval, sequence: base.to_owned() }
+    }
+
+    fn row_ids(stack: &AlignmentStack<AlignedPair>) -> Vec<Vec<String>> {
+        stack.rows.iter().map(|row| row.iter().map(|pair| pair.id().to_owned()).collect()).collect()
+    }
+
+    #[test]
+    fn test_sort_by_base() {
+        let del = SequenceDiff::Del { interval: GenomicInterval::new(8, 12).unwrap() };
+        let clip = SequenceDiff::SoftClip {
+            interval: GenomicInterval::new(0, 12).unwrap(),
+            sequence: "A".repeat(12),
+        };
+        let pairs = vec![
+            gen_pair("ref", 0, 20, Vec::new()),
+            gen_pair("t1", 1, 20, vec![mismatch(10, "T")]),
+            gen_pair("del", 2, 20, vec![del]),
+            gen_pair("t2", 3, 20, vec![mismatch(10, "t")]),
+            gen_pair("clip", 4, 20, vec![clip]),
+            gen_pair("none", 11, 20, Vec::new()),
+            gen_pair("g", 5, 20, vec![mismatch(10, "G")]),
+        ];
+        let region = GenomicRegion::new("X", 0, 20).unwrap();
+        let mut stack = AlignmentStack::new();
+        stack.update(pairs, &region).unwrap();
+        sort_stack(&mut stack, 10, SortBy::Base).unwrap();
+        // The most common alternate allele comes first, then other alternate alleles, then the
+        // reference allele and lastly rows without an aligned base
+        let ids: Vec<String> = row_ids(&stack).into_iter().map(|row| row[0].clone()).collect();
+        let sorted_block = |range: std::ops::Range<usize>| {
+            let mut block = ids[range].to_vec();
+            block.sort();
+            block
+        };
+        assert_eq!(sorted_block(0..2), vec!["t1", "t2"]);
+        assert_eq!(sorted_block(2..4), vec!["del", "g"]);
+        assert_eq!(sorted_block(4..5), vec!["ref"]);
+        assert_eq!(sorted_block(5..7), vec!["clip", "none"]);
+    }
+
+    #[test]
+    fn test_sort_by_read_fields() {
+        let mut pairs = vec![
+            gen_pair("a", 0, 20, Vec::new()),
+            gen_pair("b", 2, 20, Vec::new()),
+            gen_pair("c", 4, 20, Vec::new()),
+            gen_pair("d", 15, 20, Vec::new()),
+        ];
+        let fields = [(30, 500, true), (60, -200, false), (10, 800, true), (60, 900, false)];
+        for (pair, (mapq, template_length, is_reverse)) in pairs.iter_mut().zip(fields) {
+            let read = pair.reads_mut().next().unwrap();
+            (read.mapq, read.template_length, read.is_reverse) =
+                (mapq, template_length, is_reverse);
+        }
+        let region = GenomicRegion::new("X", 0, 20).unwrap();
+        let mut stack = AlignmentStack::new();
+        stack.update(pairs, &region).unwrap();
+        let first_ids = |stack: &AlignmentStack<AlignedPair>| -> Vec<String> {
+            row_ids(stack).into_iter().map(|row| row[0].clone()).collect()
+        };
+        // "d" doesn't overlap the position, so it's always last
+        sort_stack(&mut stack, 10, SortBy::Start).unwrap();
+        assert_eq!(first_ids(&stack), vec!["a", "b", "c", "d"]);
+        sort_stack(&mut stack, 10, SortBy::MappingQuality).unwrap();
+        assert_eq!(first_ids(&stack), vec!["b", "a", "c", "d"]);
+        sort_stack(&mut stack, 10, SortBy::TemplateLength).unwrap();
+        assert_eq!(first_ids(&stack), vec!["c", "a", "b", "d"]);
+        sort_stack(&mut stack, 10, SortBy::Strand).unwrap();
+        assert_eq!(first_ids(&stack), vec!["b", "c", "a", "d"]);
+    }
+
+    #[test]
+    fn test_sort_within_groups() {
+        let pairs = vec![
+            gen_pair("ref", 0, 20, Vec::new()),
+            gen_pair("alt", 1, 20, vec![mismatch(10, "T")]),
+        ];
+        let region = GenomicRegion::new("X", 0, 20).unwrap();
+        let mut stack =
+            AlignmentStack::with_layout(1, PackingStrategy::Greedy, StackGrouping::Haplotype);
+        stack.update(pairs, &region).unwrap();
+        let rows = row_ids(&stack);
+        // Both reads are untagged, so they're in the same group
+        sort_stack(&mut stack, 10, SortBy::Base).unwrap();
+        assert_eq!(row_ids(&stack), vec![rows[1].clone(), rows[0].clone()]);
+        assert!(stack.sort_rows(vec![0]).is_err());
+    }
+}
@@ -1,38 +1,417 @@
 /// Stacking alignments into rows for rendering in the GUI.
-use std::collections::VecDeque;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::alignments::alignment::{Alignment, AlignmentSearchList, SortEnd, SortStart};
 use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::sam_bam::diff::DIFF_SCHEMA_VERSION;
 use crate::impl_wrapped_uuid;
 
-const PADDING: u64 = 1;
+/// Gap in bp which is left between adjacent alignments in a row so that they don't appear merged
+/// in the UI.
+pub const DEFAULT_STACK_PADDING: u64 = 1;
+
+/// Largest padding which can be set for a track.
+pub const MAX_STACK_PADDING: u64 = 1000;
+
+/// Number of alignment rows which are remembered before forgetting the rows of alignments which
+/// are no longer in the stack.
+pub const MAX_REMEMBERED_ROWS: usize = 100_000;
+
+/// How alignments are packed into rows.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PackingStrategy {
+    /// Extend the existing rows with newly read alignments, so that alignments keep their rows
+    /// while panning. Alignments which are read again return to the row they were last in. May use
+    /// more rows than necessary.
+    #[default]
+    Greedy,
+    /// Repack every alignment in order of start position, placing each in the topmost row which
+    /// has space for it. Earlier alignments are always above later ones which they overlap.
+    PileupOrder,
+    /// Repack every alignment in order of start position, placing each in the row which leaves
+    /// the smallest gap before it. Uses the fewest rows and leaves the fewest gaps.
+    Tight,
+    /// Tight packing without padding between alignments, for deep data where individual reads
+    /// don't need to be distinguished. Diffs of the alignments aren't sent to the frontend.
+    Squished,
+    /// Every alignment in a single row (one row per group), so that the stack resembles a coverage
+    /// track. Alignments in the row overlap.
+    Collapsed,
+}
+
+/// Field by which alignments are grouped into separate sections of rows.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StackGrouping {
+    #[default]
+    Ungrouped,
+    /// Haplotype which reads are phased to (HP tag), e.g for phased long reads.
+    Haplotype,
+    /// Strand which reads are aligned to, with forward strand reads above reverse strand reads
+    /// (e.g for inspecting strand bias).
+    Strand,
+}
+
+/// Group keys of forward and reverse strand alignments when stacked by strand.
+pub const FORWARD_STRAND_KEY: &str = "+";
+pub const REVERSE_STRAND_KEY: &str = "-";
+
+/// A section of a grouped stack's rows which contain the alignments of a single group.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StackGroup {
+    /// Value of the grouped field, or None for alignments which don't have one.
+    pub key: Option<String>,
+    /// Index of the first row in the group.
+    pub start_row: usize,
+    /// Index after the last row in the group.
+    pub end_row: usize,
+}
+
+/// Order group keys with numeric keys in numeric order (e.g HP=2 before HP=10), and alignments
+/// without a key last.
+fn compare_group_keys(a: &Option<String>, b: &Option<String>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            _ => a.cmp(b),
+        },
+        _ => a.is_none().cmp(&b.is_none()),
+    }
+}
+
+/// Rows and new alignments of a group which is being restacked.
+struct PendingGroup<T> {
+    key: Option<String>,
+    rows: Vec<VecDeque<T>>,
+    new_alignments: Vec<T>,
+}
+
+impl<T> PendingGroup<T> {
+    fn find_or_insert(groups: &mut Vec<Self>, key: Option<String>) -> &mut Self {
+        let i = match groups.iter().position(|group| group.key == key) {
+            Some(i) => i,
+            None => {
+                groups.push(Self { key, rows: Vec::new(), new_alignments: Vec::new() });
+                groups.len() - 1
+            }
+        };
+        &mut groups[i]
+    }
+}
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct StackId(Uuid);
 impl_wrapped_uuid!(StackId);
 
-/// Alignments packed into rows for rendering in the GUI.
+/// Alignments which were added to and removed from the visible rows of a stack by an update.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct StackDelta {
+    previous_revision: u32,
+    removed_ids: Vec<String>,
+    /// Row index and index within the row of each alignment which was added (or which changed or
+    /// moved row, in which case its id is also in removed_ids).
+    added: Vec<(usize, usize)>,
+}
+
+/// An alignment which was added to a stack, and where it was inserted.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddedAlignment<'a, T> {
+    pub row_index: usize,
+    /// Index of the alignment in its row once the removed alignments are removed and the
+    /// alignments before it in the row are added.
+    pub index: usize,
+    pub alignment: &'a T,
+}
+
+/// The changes made to a stack by its last update, so that a copy of the stack at the previous
+/// revision can be patched rather than replaced (e.g when panning a short distance).
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlignmentsDelta<'a, T> {
+    pub stack_id: StackId,
+    /// Revision of the stack which the delta applies to.
+    pub previous_revision: u32,
+    pub revision: u32,
+    /// Number of rows in the stack after the update. Rows beyond this are removed.
+    pub num_rows: usize,
+    pub groups: &'a [StackGroup],
+    pub strand_boundary: Option<usize>,
+    pub hidden_row_count: usize,
+    pub hidden_read_count: usize,
+    pub buffered_region: &'a Option<GenomicRegion>,
+    /// Ids of the alignments to remove before inserting the added alignments.
+    pub removed_ids: &'a [String],
+    /// Alignments in order of row and index within the row.
+    pub added: Vec<AddedAlignment<'a, T>>,
+}
+
+/// A range of a stack's rows (e.g only the visible rows of a deep stack). Row indices (of groups
+/// and the strand boundary) are relative to the first row in the range.
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
+pub struct AlignmentStackRows<'a, T> {
+    pub id: StackId,
+    pub diff_schema_version: u32,
+    pub revision: u32,
+    pub rows: &'a [VecDeque<T>],
+    /// Groups which overlap the range, clipped to it.
+    pub groups: Vec<StackGroup>,
+    /// Clipped to the range.
+    pub strand_boundary: Option<usize>,
+    pub hidden_row_count: usize,
+    pub hidden_read_count: usize,
+    pub buffered_region: &'a Option<GenomicRegion>,
+    /// Index of the first row of the range within the whole stack.
+    pub row_start: usize,
+    /// Number of rows in the whole stack.
+    pub total_rows: usize,
+}
+
+/// Alignments packed into rows for rendering in the GUI.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct AlignmentStack<T> {
     pub id: StackId,
+    /// Version of the compact format of the diffs in the stack's reads.
+    pub diff_schema_version: u32,
+    /// Incremented whenever the stack changes.
+    pub revision: u32,
     pub rows: Vec<VecDeque<T>>,
+    /// Sections of the rows which each contain a single group of alignments, in the order in
+    /// which they're displayed. Empty if the stack is ungrouped.
+    pub groups: Vec<StackGroup>,
+    /// Index of the first row of reverse strand alignments (or the number of rows if there are
+    /// none) when stacked by strand, None otherwise.
+    pub strand_boundary: Option<usize>,
+    /// Number of rows which aren't included because the stack has more than its maximum number
+    /// of rows.
+    pub hidden_row_count: usize,
+    /// Number of reads in the hidden rows.
+    pub hidden_read_count: usize,
     pub buffered_region: Option<GenomicRegion>,
+    /// Minimum gap in bp between adjacent alignments in a row.
+    #[serde(skip)]
+    padding: u64,
+    #[serde(skip)]
+    packing: PackingStrategy,
+    #[serde(skip)]
+    grouping: StackGrouping,
+    /// Row which each alignment was last placed in (relative to the start of its group) by greedy
+    /// packing, so that alignments return to the same row when they're read again (e.g after
+    /// panning away and back).
+    #[serde(skip)]
+    row_memory: HashMap<String, usize>,
+    #[serde(skip)]
+    max_rows: Option<usize>,
+    /// Rows beyond max_rows, which are restacked along with the other rows whenever the stack
+    /// changes.
+    #[serde(skip)]
+    hidden_rows: Vec<VecDeque<T>>,
+    /// Changes made by the last update, or None if the stack has changed in some other way since.
+    #[serde(skip)]
+    last_delta: Option<StackDelta>,
+}
+
+/// Remove any empty rows from the end of a stack's rows. Empty rows before other rows are kept so
+/// that the alignments below them don't change row.
+fn drop_trailing_empty_rows<T>(rows: &mut Vec<VecDeque<T>>) {
+    while rows.last().map_or(false, VecDeque::is_empty) {
+        rows.pop();
+    }
 }
 
-impl<T: Alignment> AlignmentStack<T> {
+impl<T: Alignment + PartialEq> AlignmentStack<T> {
     pub fn new() -> Self {
-        Self { rows: Vec::new(), id: StackId::new(), buffered_region: None }
+        Self::with_padding(DEFAULT_STACK_PADDING)
+    }
+
+    pub fn with_padding(padding: u64) -> Self {
+        Self::with_layout(padding, PackingStrategy::default(), StackGrouping::default())
+    }
+
+    pub fn with_layout(padding: u64, packing: PackingStrategy, grouping: StackGrouping) -> Self {
+        Self {
+            rows: Vec::new(),
+            id: StackId::new(),
+            diff_schema_version: DIFF_SCHEMA_VERSION,
+            revision: 0,
+            groups: Vec::new(),
+            strand_boundary: None,
+            hidden_row_count: 0,
+            hidden_read_count: 0,
+            buffered_region: None,
+            padding,
+            packing,
+            grouping,
+            row_memory: HashMap::new(),
+            max_rows: None,
+            hidden_rows: Vec::new(),
+            last_delta: None,
+        }
+    }
+
+    /// Mark the stack as changed, invalidating the last delta.
+    fn increment_revision(&mut self) {
+        self.revision = self.revision.wrapping_add(1);
+        self.last_delta = None;
+    }
+
+    pub fn padding(&self) -> u64 {
+        self.padding
+    }
+
+    pub fn packing(&self) -> PackingStrategy {
+        self.packing
+    }
+
+    /// Whether the diffs of the alignments should be excluded when the stack is sent to the
+    /// frontend.
+    pub fn suppresses_diffs(&self) -> bool {
+        matches!(self.packing, PackingStrategy::Squished | PackingStrategy::Collapsed)
+    }
+
+    /// Gap which is left between adjacent alignments by the packing strategy.
+    fn row_padding(&self) -> u64 {
+        match self.packing {
+            PackingStrategy::Squished | PackingStrategy::Collapsed => 0,
+            PackingStrategy::Greedy | PackingStrategy::PileupOrder | PackingStrategy::Tight => {
+                self.padding
+            }
+        }
+    }
+
+    pub fn grouping(&self) -> StackGrouping {
+        self.grouping
+    }
+
+    /// Change the gap between adjacent alignments, the packing strategy and the grouping,
+    /// restacking any alignments which are already in the stack.
+    pub fn set_layout(
+        &mut self,
+        padding: u64,
+        packing: PackingStrategy,
+        grouping: StackGrouping,
+    ) -> Result<()> {
+        if padding == self.padding && packing == self.packing && grouping == self.grouping {
+            return Ok(());
+        }
+        self.padding = padding;
+        self.packing = packing;
+        self.grouping = grouping;
+        self.increment_revision();
+        // Remembered rows are relative to groups which may have changed
+        self.row_memory.clear();
+        self.restore_hidden_rows();
+        let alignments: Vec<T> = self.rows.drain(..).flatten().collect();
+        self.extend_stack(alignments.into())?;
+        self.hide_excess_rows();
+        Ok(())
+    }
+
+    pub fn max_rows(&self) -> Option<usize> {
+        self.max_rows
+    }
+
+    /// Change the maximum number of rows in the stack (None for no maximum). Rows beyond the
+    /// maximum are hidden, and counted in hidden_row_count and hidden_read_count.
+    pub fn set_max_rows(&mut self, max_rows: Option<usize>) {
+        if max_rows == self.max_rows {
+            return;
+        }
+        self.max_rows = max_rows;
+        self.increment_revision();
+        self.restore_hidden_rows();
+        self.hide_excess_rows();
+    }
+
+    /// Iterate over every alignment in the stack, including those in hidden rows.
+    pub fn alignments(&self) -> impl Iterator<Item = &T> {
+        self.rows.iter().chain(self.hidden_rows.iter()).flatten()
+    }
+
+    /// Mutably iterate over every alignment in the stack, including those in hidden rows.
+    pub fn alignments_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.increment_revision();
+        self.rows.iter_mut().chain(self.hidden_rows.iter_mut()).flatten()
     }
 
-    fn count_alignments(&self) -> usize {
+    /// Move any hidden rows back to the end of the stack's rows.
+    ///
+    /// Hidden rows are always at the end of the stack so the groups of the rows are unchanged.
+    fn restore_hidden_rows(&mut self) {
+        if self.hidden_rows.is_empty() {
+            return;
+        }
+        let num_visible_rows = self.rows.len();
+        self.rows.append(&mut self.hidden_rows);
+        if let Some(last_group) = self.groups.last_mut() {
+            if last_group.end_row == num_visible_rows {
+                last_group.end_row = self.rows.len();
+            }
+        }
+        self.hidden_row_count = 0;
+        self.hidden_read_count = 0;
+    }
+
+    /// Hide any rows beyond max_rows, truncating the groups which they're in.
+    fn hide_excess_rows(&mut self) {
+        let max_rows = match self.max_rows {
+            Some(max_rows) if self.rows.len() > max_rows => max_rows,
+            _ => return,
+        };
+        self.hidden_rows = self.rows.split_off(max_rows);
+        self.hidden_row_count = self.hidden_rows.len();
+        self.hidden_read_count = self.hidden_rows.iter().flatten().map(Alignment::num_reads).sum();
+        self.groups.retain(|group| group.start_row < max_rows);
+        if let Some(last_group) = self.groups.last_mut() {
+            last_group.end_row = last_group.end_row.min(max_rows);
+        }
+        self.strand_boundary = self.strand_boundary.map(|boundary| boundary.min(max_rows));
+    }
+
+    pub fn count_alignments(&self) -> usize {
         self.rows.iter().map(|row| row.len()).sum()
     }
 
+    /// Find the alignments which overlap a position, along with the index of their row.
+    ///
+    /// Alignments in a row are ordered and don't overlap, so each row is binary searched. Collapsed
+    /// rows may contain several overlapping alignments which are all returned.
+    pub fn alignments_at(&self, position: u64) -> Vec<(usize, &T)> {
+        if self.packing == PackingStrategy::Collapsed {
+            return self
+                .rows
+                .iter()
+                .enumerate()
+                .flat_map(|(row_index, row)| {
+                    row.iter()
+                        .filter(|alignment| {
+                            alignment.start() <= position && position < alignment.end()
+                        })
+                        .map(move |alignment| (row_index, alignment))
+                })
+                .collect();
+        }
+        self.rows
+            .iter()
+            .enumerate()
+            .filter_map(|(row_index, row)| {
+                let i = row.partition_point(|alignment| alignment.end() <= position);
+                row.get(i)
+                    .filter(|alignment| alignment.start() <= position)
+                    .map(|alignment| (row_index, alignment))
+            })
+            .collect()
+    }
+
     /// Filter out any alignments which do not overlap self.buffered_region
     fn trim(&mut self) {
         let num_alignments = self.count_alignments();
@@ -43,7 +422,7 @@ impl<T: Alignment> AlignmentStack<T> {
                     && buffered_region.end() >= alignment.start()
             })
         }
-        self.rows.retain(|row| !row.is_empty());
+        drop_trailing_empty_rows(&mut self.rows);
         log::debug!(
             "Trimmed {} alignments from stack {}",
             num_alignments - self.count_alignments(),
@@ -57,10 +436,14 @@ impl<T: Alignment> AlignmentStack<T> {
     /// This is necessary in scenarios where we load a new genomic region which partially overlaps
     /// the previous region. The original region may have some reads with missing mates which
     /// are present in the new region.
+    ///
+    /// Returns the alignments which weren't duplicates, and the ids of the replaced alignments
+    /// which differ from their replacement.
     fn replace_duplicates(
         &mut self,
         alignments: AlignmentSearchList<T, SortStart>,
-    ) -> Result<AlignmentSearchList<T, SortStart>> {
+    ) -> Result<(AlignmentSearchList<T, SortStart>, HashSet<String>)> {
+        let mut changed_ids = HashSet::new();
         let mut updated_alignments = AlignmentSearchList::with_capacity(alignments.len());
         let mut stack_items: Vec<&mut T> = self.rows.iter_mut().flatten().collect();
         let num_existing_items = stack_items.len();
@@ -73,6 +456,9 @@ impl<T: Alignment> AlignmentStack<T> {
                 stack_idx += 1;
             }
             if stack_idx != num_existing_items && alignment.id() == stack_items[stack_idx].id() {
+                if *stack_items[stack_idx] != alignment {
+                    changed_ids.insert(alignment.id().to_owned());
+                }
                 *stack_items[stack_idx] = alignment;
                 num_replaced += 1;
             } else {
@@ -84,7 +470,7 @@ impl<T: Alignment> AlignmentStack<T> {
             num_replaced,
             self.id
         );
-        Ok(updated_alignments)
+        Ok((updated_alignments, changed_ids))
     }
 
     /// Update the stack with a list of alignments from a new genomic region.
@@ -93,20 +479,138 @@ impl<T: Alignment> AlignmentStack<T> {
         alignments: A,
         updated_region: &GenomicRegion,
     ) -> Result<()> {
+        let previous_revision = self.revision;
+        let previous_rows = self.visible_rows_by_id();
+        self.increment_revision();
         self.buffered_region = Some(updated_region.to_owned());
+        self.restore_hidden_rows();
         self.trim();
-        let novel_alignments = self.replace_duplicates(alignments.into())?;
+        let (novel_alignments, changed_ids) = self.replace_duplicates(alignments.into())?;
         self.extend_stack(novel_alignments)?;
+        self.hide_excess_rows();
+        self.record_delta(previous_revision, previous_rows, &changed_ids);
         Ok(())
     }
 
+    /// Map the id of every alignment in the visible rows to its row index.
+    fn visible_rows_by_id(&self) -> HashMap<String, usize> {
+        self.rows
+            .iter()
+            .enumerate()
+            .flat_map(|(row_idx, row)| row.iter().map(move |al| (al.id().to_owned(), row_idx)))
+            .collect()
+    }
+
+    /// Compare the visible rows to those before an update to find the alignments which the update
+    /// added and removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous_rows` - Row index of each visible alignment before the update.
+    /// * `changed_ids` - Ids of alignments which were replaced by a different alignment.
+    fn record_delta(
+        &mut self,
+        previous_revision: u32,
+        mut previous_rows: HashMap<String, usize>,
+        changed_ids: &HashSet<String>,
+    ) {
+        let mut added = Vec::new();
+        for (row_idx, row) in self.rows.iter().enumerate() {
+            for (i, alignment) in row.iter().enumerate() {
+                let id = alignment.id();
+                if previous_rows.get(id) == Some(&row_idx) && !changed_ids.contains(id) {
+                    previous_rows.remove(id);
+                } else {
+                    added.push((row_idx, i));
+                }
+            }
+        }
+        let mut removed_ids: Vec<String> = previous_rows.into_keys().collect();
+        removed_ids.sort();
+        self.last_delta = Some(StackDelta { previous_revision, removed_ids, added });
+    }
+
+    /// Get a range of the stack's rows. The range is clamped to the rows of the stack.
+    pub fn rows_range(
+        &self,
+        row_start: usize,
+        row_end: Option<usize>,
+    ) -> AlignmentStackRows<'_, T> {
+        let total_rows = self.rows.len();
+        let row_end = row_end.map_or(total_rows, |row_end| row_end.min(total_rows));
+        let row_start = row_start.min(row_end);
+        let groups = self
+            .groups
+            .iter()
+            .filter(|group| group.start_row < row_end && group.end_row > row_start)
+            .map(|group| StackGroup {
+                key: group.key.clone(),
+                start_row: group.start_row.max(row_start) - row_start,
+                end_row: group.end_row.min(row_end) - row_start,
+            })
+            .collect();
+        AlignmentStackRows {
+            id: self.id,
+            diff_schema_version: self.diff_schema_version,
+            revision: self.revision,
+            rows: &self.rows[row_start..row_end],
+            groups,
+            strand_boundary: self
+                .strand_boundary
+                .map(|boundary| boundary.clamp(row_start, row_end) - row_start),
+            hidden_row_count: self.hidden_row_count,
+            hidden_read_count: self.hidden_read_count,
+            buffered_region: &self.buffered_region,
+            row_start,
+            total_rows,
+        }
+    }
+
+    /// Get the changes made by the last update, or None if the stack has changed in some other way
+    /// since (e.g it was restacked with a different layout) or if the delta isn't smaller than the
+    /// stack itself (e.g after a non-greedy stack is repacked).
+    pub fn last_delta(&self) -> Option<AlignmentsDelta<'_, T>> {
+        let delta = self.last_delta.as_ref()?;
+        if delta.added.len() + delta.removed_ids.len() >= self.count_alignments() {
+            return None;
+        }
+        let added = delta
+            .added
+            .iter()
+            .map(|(row_index, index)| AddedAlignment {
+                row_index: *row_index,
+                index: *index,
+                alignment: &self.rows[*row_index][*index],
+            })
+            .collect();
+        Some(AlignmentsDelta {
+            stack_id: self.id,
+            previous_revision: delta.previous_revision,
+            revision: self.revision,
+            num_rows: self.rows.len(),
+            groups: &self.groups,
+            strand_boundary: self.strand_boundary,
+            hidden_row_count: self.hidden_row_count,
+            hidden_read_count: self.hidden_read_count,
+            buffered_region: &self.buffered_region,
+            removed_ids: &delta.removed_ids,
+            added,
+        })
+    }
+
     /// Remove all alignments from the stack.
     ///
     /// This is intended for cases where the user loads a region which is too large to render in the
     /// UI.
     pub fn clear(&mut self, updated_region: &GenomicRegion) {
+        self.increment_revision();
         self.buffered_region = Some(updated_region.to_owned());
         self.rows.clear();
+        self.hidden_rows.clear();
+        self.hidden_row_count = 0;
+        self.hidden_read_count = 0;
+        self.groups.clear();
+        self.strand_boundary = None;
     }
 
     /// Right-extend rows with new alignments.
@@ -116,12 +620,11 @@ impl<T: Alignment> AlignmentStack<T> {
         while row_idx < self.rows.len() {
             let mut min_start = 0;
             if row_idx < self.rows.len() && !self.rows[row_idx].is_empty() {
-                // Pad reads slightly so that adjacent reads don't appear merged in the UI
                 let row_length = self.rows[row_idx].len();
-                min_start = self.rows[row_idx][row_length - 1].end() + PADDING;
+                min_start = self.rows[row_idx][row_length - 1].end() + self.padding;
             }
             while let Some(next_alignment) = new_alignments.pop_after(min_start) {
-                min_start = next_alignment.end() + PADDING;
+                min_start = next_alignment.end() + self.padding;
                 self.rows[row_idx].push_back(next_alignment);
                 num_added += 1;
             }
@@ -139,12 +642,13 @@ impl<T: Alignment> AlignmentStack<T> {
             if self.rows.len() <= row_idx {
                 self.rows.push(VecDeque::new());
                 max_end = u64::MAX;
+            } else if self.rows[row_idx].is_empty() {
+                max_end = u64::MAX;
             } else {
-                // Pad reads slightly so that adjacent reads don't appear merged in the UI
-                max_end = self.rows[row_idx][0].start().saturating_sub(PADDING);
+                max_end = self.rows[row_idx][0].start().saturating_sub(self.padding);
             }
             while let Some(next_alignment) = new_alignments.pop_before(max_end) {
-                max_end = next_alignment.start().saturating_sub(PADDING);
+                max_end = next_alignment.start().saturating_sub(self.padding);
                 self.rows[row_idx].push_front(next_alignment);
                 num_added += 1;
             }
@@ -153,20 +657,231 @@ impl<T: Alignment> AlignmentStack<T> {
         log::debug!("Extended left of stack {} with {} alignments", self.id, num_added,);
     }
 
-    /// Extend rows to the left and right and add new rows to fit the remaining alignments.
+    /// Reorder the rows by a key for each row, keeping rows with equal keys in their current order.
+    ///
+    /// Rows are only reordered within their group. Greedy stacks keep the order as they're
+    /// extended, whereas other packing strategies revert to their own order when the stack is next
+    /// updated.
+    pub fn sort_rows<K: Ord>(&mut self, keys: Vec<K>) -> Result<()> {
+        if keys.len() != self.rows.len() {
+            bail!("Got {} sort keys for a stack with {} rows", keys.len(), self.rows.len());
+        }
+        let mut group_indices = vec![0; self.rows.len()];
+        for (i, group) in self.groups.iter().enumerate() {
+            group_indices[group.start_row..group.end_row].fill(i);
+        }
+        let mut keyed_rows: Vec<((usize, K), VecDeque<T>)> =
+            group_indices.into_iter().zip(keys).zip(self.rows.drain(..)).collect();
+        keyed_rows.sort_by(|(a, _), (b, _)| a.cmp(b));
+        self.increment_revision();
+        self.rows = keyed_rows.into_iter().map(|(_, row)| row).collect();
+        if self.packing == PackingStrategy::Greedy {
+            self.remember_rows();
+        }
+        Ok(())
+    }
+
+    /// Add alignments to the stack using its packing strategy.
+    ///
+    /// Greedy stacks extend their rows to the left and right and add new rows to fit the remaining
+    /// alignments. Other strategies repack the whole stack. Grouped stacks pack each group into its
+    /// own section of rows.
     pub fn extend_stack(
         &mut self,
         new_alignments: AlignmentSearchList<T, SortStart>,
     ) -> Result<()> {
-        let mut new_alignments = new_alignments;
-        self.extend_stack_right(&mut new_alignments);
-        let mut end_sorted = new_alignments.sort_by_end();
-        self.extend_stack_left(&mut end_sorted);
+        if self.grouping == StackGrouping::Ungrouped {
+            self.groups.clear();
+            self.strand_boundary = None;
+            return self.extend_rows(new_alignments);
+        }
+        // Every row contains a single group, so existing rows are grouped by their first alignment
+        // (or by the group they were in if they're empty)
+        let mut groups: Vec<PendingGroup<T>> = Vec::new();
+        for (row_idx, row) in self.rows.drain(..).enumerate() {
+            let key = match row.front() {
+                Some(alignment) => alignment.group_key(self.grouping),
+                None => self
+                    .groups
+                    .iter()
+                    .find(|group| group.start_row <= row_idx && row_idx < group.end_row)
+                    .and_then(|group| group.key.clone()),
+            };
+            PendingGroup::find_or_insert(&mut groups, key).rows.push(row);
+        }
+        for alignment in new_alignments {
+            let key = alignment.group_key(self.grouping);
+            PendingGroup::find_or_insert(&mut groups, key).new_alignments.push(alignment);
+        }
+        groups.sort_by(|a, b| compare_group_keys(&a.key, &b.key));
+
+        self.groups.clear();
+        for group in groups {
+            let mut group_stack = Self::with_layout(self.padding, self.packing, self.grouping);
+            group_stack.rows = group.rows;
+            group_stack.row_memory = std::mem::take(&mut self.row_memory);
+            group_stack.extend_rows(group.new_alignments.into())?;
+            self.row_memory = std::mem::take(&mut group_stack.row_memory);
+            drop_trailing_empty_rows(&mut group_stack.rows);
+            if group_stack.rows.is_empty() {
+                continue;
+            }
+            let start_row = self.rows.len();
+            self.rows.extend(group_stack.rows);
+            self.groups.push(StackGroup { key: group.key, start_row, end_row: self.rows.len() });
+        }
+        self.strand_boundary = match self.grouping {
+            StackGrouping::Strand => Some(
+                self.groups
+                    .iter()
+                    .find(|group| group.key.as_deref() == Some(REVERSE_STRAND_KEY))
+                    .map_or(self.rows.len(), |group| group.start_row),
+            ),
+            StackGrouping::Ungrouped | StackGrouping::Haplotype => None,
+        };
         Ok(())
     }
+
+    /// Add alignments to the rows using the stack's packing strategy, ignoring its grouping.
+    fn extend_rows(&mut self, new_alignments: AlignmentSearchList<T, SortStart>) -> Result<()> {
+        match self.packing {
+            PackingStrategy::Greedy => {
+                let mut new_alignments = self.place_remembered(new_alignments)?;
+                self.extend_stack_right(&mut new_alignments);
+                let mut end_sorted = new_alignments.sort_by_end();
+                self.extend_stack_left(&mut end_sorted);
+                self.remember_rows();
+            }
+            PackingStrategy::PileupOrder => {
+                let alignments = self.drain_rows_with(new_alignments);
+                self.pack_topmost(alignments);
+            }
+            PackingStrategy::Tight | PackingStrategy::Squished => {
+                let alignments = self.drain_rows_with(new_alignments);
+                self.pack_tightest(alignments);
+            }
+            PackingStrategy::Collapsed => {
+                let alignments = self.drain_rows_with(new_alignments);
+                if !alignments.is_empty() {
+                    self.rows.push(alignments.into_iter().collect());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Place alignments back in the rows which they were last in, if there's still space for them
+    /// there. Returns the alignments which couldn't be placed.
+    fn place_remembered(
+        &mut self,
+        new_alignments: AlignmentSearchList<T, SortStart>,
+    ) -> Result<AlignmentSearchList<T, SortStart>> {
+        if self.row_memory.is_empty() {
+            return Ok(new_alignments);
+        }
+        let mut remaining = AlignmentSearchList::with_capacity(new_alignments.len());
+        for alignment in new_alignments {
+            let row_idx = match self.row_memory.get(alignment.id()) {
+                Some(row_idx) => *row_idx,
+                None => {
+                    remaining.push(alignment)?;
+                    continue;
+                }
+            };
+            if self.rows.len() <= row_idx {
+                self.rows.resize_with(row_idx + 1, VecDeque::new);
+            }
+            let row = &mut self.rows[row_idx];
+            let i = row.partition_point(|other| other.start() < alignment.start());
+            let fits_before = i == 0 || row[i - 1].end() + self.padding <= alignment.start();
+            let fits_after = i == row.len() || alignment.end() + self.padding <= row[i].start();
+            if fits_before && fits_after {
+                row.insert(i, alignment);
+            } else {
+                remaining.push(alignment)?;
+            }
+        }
+        Ok(remaining)
+    }
+
+    /// Record the row of every alignment in the stack, relative to the start of its group.
+    ///
+    /// Rows of alignments which are no longer in the stack are forgotten once more than
+    /// MAX_REMEMBERED_ROWS are remembered.
+    fn remember_rows(&mut self) {
+        let mut group_starts = vec![0; self.rows.len()];
+        for group in self.groups.iter() {
+            group_starts[group.start_row..group.end_row].fill(group.start_row);
+        }
+        for (row_idx, row) in self.rows.iter().enumerate() {
+            for alignment in row {
+                self.row_memory.insert(alignment.id().to_owned(), row_idx - group_starts[row_idx]);
+            }
+        }
+        if self.row_memory.len() > MAX_REMEMBERED_ROWS {
+            let ids: HashSet<&str> = self.rows.iter().flatten().map(Alignment::id).collect();
+            self.row_memory.retain(|id, _| ids.contains(id.as_str()));
+        }
+    }
+
+    /// Remove every alignment from the rows and merge them with new alignments.
+    fn drain_rows_with(
+        &mut self,
+        new_alignments: AlignmentSearchList<T, SortStart>,
+    ) -> AlignmentSearchList<T, SortStart> {
+        let mut alignments: Vec<T> = self.rows.drain(..).flatten().collect();
+        alignments.extend(new_alignments);
+        alignments.into()
+    }
+
+    /// Place each alignment in the topmost row which has space for it.
+    fn pack_topmost(&mut self, alignments: AlignmentSearchList<T, SortStart>) {
+        // Rows which are still occupied, by the position from which they have space again
+        let mut occupied_rows: BinaryHeap<Reverse<(u64, usize)>> = BinaryHeap::new();
+        let mut free_rows: BinaryHeap<Reverse<usize>> = BinaryHeap::new();
+        for alignment in alignments {
+            while let Some(Reverse((free_from, row_idx))) = occupied_rows.peek().copied() {
+                if free_from > alignment.start() {
+                    break;
+                }
+                occupied_rows.pop();
+                free_rows.push(Reverse(row_idx));
+            }
+            let row_idx = match free_rows.pop() {
+                Some(Reverse(row_idx)) => row_idx,
+                None => {
+                    self.rows.push(VecDeque::new());
+                    self.rows.len() - 1
+                }
+            };
+            occupied_rows.push(Reverse((alignment.end() + self.row_padding(), row_idx)));
+            self.rows[row_idx].push_back(alignment);
+        }
+    }
+
+    /// Place each alignment in the row whose last alignment ends closest before it.
+    fn pack_tightest(&mut self, alignments: AlignmentSearchList<T, SortStart>) {
+        // Rows by the position from which they have space again
+        let mut rows_by_free_from: BTreeSet<(u64, usize)> = BTreeSet::new();
+        for alignment in alignments {
+            let best_fit = rows_by_free_from.range(..=(alignment.start(), usize::MAX)).next_back();
+            let row_idx = match best_fit.copied() {
+                Some(key) => {
+                    rows_by_free_from.remove(&key);
+                    key.1
+                }
+                None => {
+                    self.rows.push(VecDeque::new());
+                    self.rows.len() - 1
+                }
+            };
+            rows_by_free_from.insert((alignment.end() + self.row_padding(), row_idx));
+            self.rows[row_idx].push_back(alignment);
+        }
+    }
 }
 
-impl<T: Alignment> Default for AlignmentStack<T> {
+impl<T: Alignment + PartialEq> Default for AlignmentStack<T> {
     fn default() -> Self {
         Self::new()
     }
@@ -188,6 +903,35 @@ mod tests {
 
     impl_alignment!(FakeAlignment);
 
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    struct PhasedAlignment {
+        id: String,
+        interval: GenomicInterval,
+        haplotype: Option<u32>,
+    }
+
+    impl Alignment for PhasedAlignment {
+        fn id(&self) -> &str {
+            &self.id
+        }
+        fn start(&self) -> u64 {
+            self.interval.start
+        }
+        fn end(&self) -> u64 {
+            self.interval.end
+        }
+        fn group_key(&self, grouping: StackGrouping) -> Option<String> {
+            match grouping {
+                StackGrouping::Ungrouped | StackGrouping::Strand => None,
+                StackGrouping::Haplotype => self.haplotype.map(|haplotype| haplotype.to_string()),
+            }
+        }
+    }
+
+    fn gen_phased(id: &str, start: u64, end: u64, haplotype: Option<u32>) -> PhasedAlignment {
+        PhasedAlignment { id: id.to_owned(), interval: (start, end).try_into().unwrap(), haplotype }
+    }
+
     #[test]
     pub fn test_update_fresh_stack() {
         let alignments = vec![
@@ -212,6 +956,250 @@ mod tests {
         assert_eq!(stack.buffered_region.unwrap(), region);
     }
 
+    #[test]
+    pub fn test_alignments_at() {
+        let alignments = vec![
+            FakeAlignment { id: "0".to_owned(), interval: (0, 10).try_into().unwrap() },
+            FakeAlignment { id: "1".to_owned(), interval: (1, 11).try_into().unwrap() },
+            FakeAlignment { id: "2".to_owned(), interval: (2, 12).try_into().unwrap() },
+            FakeAlignment { id: "3".to_owned(), interval: (10, 20).try_into().unwrap() },
+            FakeAlignment { id: "4".to_owned(), interval: (11, 22).try_into().unwrap() },
+        ];
+        let region = GenomicRegion::new("X", 0, 25).unwrap();
+        let mut stack = AlignmentStack::new();
+        stack.update(alignments, &region).unwrap();
+        let ids_at = |position: u64| -> Vec<(usize, String)> {
+            stack
+                .alignments_at(position)
+                .into_iter()
+                .map(|(row_index, alignment)| (row_index, alignment.id().to_owned()))
+                .collect()
+        };
+        let expected = vec![(1, "3".to_owned()), (2, "2".to_owned()), (3, "1".to_owned())];
+        assert_eq!(ids_at(10), expected);
+        assert_eq!(ids_at(21), vec![(0, "4".to_owned())]);
+        assert_eq!(ids_at(22), Vec::new());
+    }
+
+    #[test]
+    pub fn test_stack_padding() {
+        let alignments = vec![
+            FakeAlignment { id: "0".to_owned(), interval: (0, 10).try_into().unwrap() },
+            FakeAlignment { id: "1".to_owned(), interval: (1, 11).try_into().unwrap() },
+            FakeAlignment { id: "2".to_owned(), interval: (2, 12).try_into().unwrap() },
+            FakeAlignment { id: "3".to_owned(), interval: (10, 20).try_into().unwrap() },
+            FakeAlignment { id: "4".to_owned(), interval: (11, 22).try_into().unwrap() },
+        ];
+        let region = GenomicRegion::new("X", 0, 25).unwrap();
+
+        // Without padding, reads which end where another starts can share a row
+        let mut stack = AlignmentStack::with_padding(0);
+        stack.update(alignments.clone(), &region).unwrap();
+        let expected_stack = vec![
+            vec![alignments[1].clone(), alignments[4].clone()],
+            vec![alignments[0].clone(), alignments[3].clone()],
+            vec![alignments[2].clone()],
+        ];
+        assert_eq!(stack.rows, expected_stack);
+
+        stack
+            .set_layout(DEFAULT_STACK_PADDING, PackingStrategy::Greedy, StackGrouping::Ungrouped)
+            .unwrap();
+        let mut default_stack = AlignmentStack::new();
+        default_stack.update(alignments.clone(), &region).unwrap();
+        assert_eq!(stack.rows, default_stack.rows);
+
+        stack
+            .set_layout(MAX_STACK_PADDING, PackingStrategy::Greedy, StackGrouping::Ungrouped)
+            .unwrap();
+        assert_eq!(stack.rows.len(), alignments.len());
+        assert_eq!(stack.count_alignments(), alignments.len());
+    }
+
+    #[test]
+    pub fn test_packing_strategies() {
+        let alignments = vec![
+            FakeAlignment { id: "0".to_owned(), interval: (0, 3).try_into().unwrap() },
+            FakeAlignment { id: "1".to_owned(), interval: (1, 10).try_into().unwrap() },
+            FakeAlignment { id: "2".to_owned(), interval: (12, 14).try_into().unwrap() },
+        ];
+        let region = GenomicRegion::new("X", 0, 25).unwrap();
+
+        let mut stack =
+            AlignmentStack::with_layout(1, PackingStrategy::PileupOrder, StackGrouping::Ungrouped);
+        stack.update(alignments.clone(), &region).unwrap();
+        let expected_stack =
+            vec![vec![alignments[0].clone(), alignments[2].clone()], vec![alignments[1].clone()]];
+        assert_eq!(stack.rows, expected_stack);
+
+        stack.set_layout(1, PackingStrategy::Tight, StackGrouping::Ungrouped).unwrap();
+        let expected_stack =
+            vec![vec![alignments[0].clone()], vec![alignments[1].clone(), alignments[2].clone()]];
+        assert_eq!(stack.rows, expected_stack);
+
+        // Alignments from a new region are packed along with the existing alignments
+        let next_alignment =
+            FakeAlignment { id: "3".to_owned(), interval: (4, 11).try_into().unwrap() };
+        stack.update(vec![next_alignment.clone()], &region).unwrap();
+        let expected_stack = vec![
+            vec![alignments[0].clone(), next_alignment, alignments[2].clone()],
+            vec![alignments[1].clone()],
+        ];
+        assert_eq!(stack.rows, expected_stack);
+    }
+
+    #[test]
+    pub fn test_squished_and_collapsed_packing() {
+        let alignments = vec![
+            FakeAlignment { id: "0".to_owned(), interval: (0, 10).try_into().unwrap() },
+            FakeAlignment { id: "1".to_owned(), interval: (1, 11).try_into().unwrap() },
+            FakeAlignment { id: "2".to_owned(), interval: (10, 20).try_into().unwrap() },
+        ];
+        let region = GenomicRegion::new("X", 0, 25).unwrap();
+
+        // Squished stacks ignore the padding, so reads which end where another starts share a row
+        let mut stack = AlignmentStack::with_layout(
+            MAX_STACK_PADDING,
+            PackingStrategy::Squished,
+            StackGrouping::Ungrouped,
+        );
+        stack.update(alignments.clone(), &region).unwrap();
+        let expected_stack =
+            vec![vec![alignments[0].clone(), alignments[2].clone()], vec![alignments[1].clone()]];
+        assert_eq!(stack.rows, expected_stack);
+        assert_eq!(stack.padding(), MAX_STACK_PADDING);
+        assert!(stack.suppresses_diffs());
+
+        stack
+            .set_layout(MAX_STACK_PADDING, PackingStrategy::Collapsed, StackGrouping::Ungrouped)
+            .unwrap();
+        assert_eq!(stack.rows, vec![VecDeque::from(alignments.clone())]);
+        let ids_at = stack
+            .alignments_at(10)
+            .into_iter()
+            .map(|(row_index, alignment)| (row_index, alignment.id().to_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(ids_at, vec![(0, "1".to_owned()), (0, "2".to_owned())]);
+
+        stack.set_layout(1, PackingStrategy::Tight, StackGrouping::Ungrouped).unwrap();
+        assert_eq!(stack.rows.len(), 3);
+        assert!(!stack.suppresses_diffs());
+    }
+
+    #[test]
+    pub fn test_max_rows() {
+        let alignments = vec![
+            FakeAlignment { id: "0".to_owned(), interval: (0, 10).try_into().unwrap() },
+            FakeAlignment { id: "1".to_owned(), interval: (1, 11).try_into().unwrap() },
+            FakeAlignment { id: "2".to_owned(), interval: (2, 12).try_into().unwrap() },
+        ];
+        let region = GenomicRegion::new("X", 0, 25).unwrap();
+        let mut stack = AlignmentStack::new();
+        stack.update(alignments.clone(), &region).unwrap();
+        let rows = stack.rows.clone();
+
+        stack.set_max_rows(Some(1));
+        assert_eq!(stack.rows, rows[..1].to_vec());
+        assert_eq!((stack.hidden_row_count, stack.hidden_read_count), (2, 2));
+        assert_eq!(stack.alignments().count(), alignments.len());
+
+        // Hidden rows are restacked when the stack is updated, so new alignments can be hidden
+        let next_alignment =
+            FakeAlignment { id: "3".to_owned(), interval: (11, 20).try_into().unwrap() };
+        stack.update(vec![next_alignment.clone()], &region).unwrap();
+        assert_eq!((stack.hidden_row_count, stack.hidden_read_count), (2, 3));
+        assert_eq!(stack.hidden_rows[1].back(), Some(&next_alignment));
+        assert_eq!(stack.alignments().count(), alignments.len() + 1);
+
+        stack.set_max_rows(None);
+        assert_eq!(stack.rows.len(), 3);
+        assert_eq!((stack.hidden_row_count, stack.hidden_read_count), (0, 0));
+    }
+
+    #[test]
+    pub fn test_group_by_haplotype() {
+        let alignments = vec![
+            gen_phased("0", 0, 10, None),
+            gen_phased("1", 1, 11, Some(2)),
+            gen_phased("2", 2, 12, Some(1)),
+            gen_phased("3", 12, 20, Some(2)),
+            gen_phased("4", 13, 22, Some(1)),
+            gen_phased("5", 14, 24, Some(10)),
+        ];
+        let region = GenomicRegion::new("X", 0, 25).unwrap();
+        let mut stack =
+            AlignmentStack::with_layout(1, PackingStrategy::Greedy, StackGrouping::Haplotype);
+        stack.update(alignments.clone(), &region).unwrap();
+        let expected_stack = vec![
+            vec![alignments[2].clone(), alignments[4].clone()],
+            vec![alignments[1].clone(), alignments[3].clone()],
+            vec![alignments[5].clone()],
+            vec![alignments[0].clone()],
+        ];
+        assert_eq!(stack.rows, expected_stack);
+        let group = |key: Option<&str>, start_row: usize, end_row: usize| StackGroup {
+            key: key.map(str::to_owned),
+            start_row,
+            end_row,
+        };
+        let expected_groups = vec![
+            group(Some("1"), 0, 1),
+            group(Some("2"), 1, 2),
+            group(Some("10"), 2, 3),
+            group(None, 3, 4),
+        ];
+        assert_eq!(stack.groups, expected_groups);
+
+        // Alignments from a new region are added to the rows of their group
+        let next_alignment = gen_phased("6", 23, 30, Some(1));
+        let region2 = GenomicRegion::new("X", 0, 30).unwrap();
+        stack.update(vec![next_alignment.clone()], &region2).unwrap();
+        assert_eq!(stack.rows[0].back(), Some(&next_alignment));
+        assert_eq!(stack.groups, expected_groups);
+        assert_eq!(stack.strand_boundary, None);
+
+        stack.set_layout(1, PackingStrategy::Greedy, StackGrouping::Ungrouped).unwrap();
+        assert!(stack.groups.is_empty());
+        assert_eq!(stack.rows.len(), 3);
+        assert_eq!(stack.count_alignments(), alignments.len() + 1);
+    }
+
+    #[test]
+    pub fn test_rows_range() {
+        let alignments = vec![
+            gen_phased("0", 0, 10, None),
+            gen_phased("1", 1, 11, Some(2)),
+            gen_phased("2", 2, 12, Some(1)),
+            gen_phased("5", 14, 24, Some(10)),
+        ];
+        let region = GenomicRegion::new("X", 0, 25).unwrap();
+        let mut stack =
+            AlignmentStack::with_layout(1, PackingStrategy::Greedy, StackGrouping::Haplotype);
+        stack.update(alignments, &region).unwrap();
+        stack.strand_boundary = Some(2);
+        let group = |key: Option<&str>, start_row: usize, end_row: usize| StackGroup {
+            key: key.map(str::to_owned),
+            start_row,
+            end_row,
+        };
+
+        // Row indices are relative to the start of the range
+        let rows = stack.rows_range(1, Some(3));
+        assert_eq!(rows.rows, &stack.rows[1..3]);
+        assert_eq!((rows.row_start, rows.total_rows), (1, 4));
+        assert_eq!(rows.groups, vec![group(Some("2"), 0, 1), group(Some("10"), 1, 2)]);
+        assert_eq!(rows.strand_boundary, Some(1));
+
+        let rows = stack.rows_range(3, None);
+        assert_eq!(rows.groups, vec![group(None, 0, 1)]);
+        assert_eq!(rows.strand_boundary, Some(0));
+
+        // Ranges are clamped to the rows of the stack
+        let rows = stack.rows_range(10, Some(20));
+        assert!(rows.rows.is_empty() && rows.groups.is_empty());
+        assert_eq!(rows.row_start, 4);
+    }
+
     #[test]
     pub fn test_update_existing_stack_with_larger_interval() {
         let alignments1 = vec![
@@ -267,6 +1255,72 @@ mod tests {
         assert_eq!(stack.rows, expected_stack);
     }
 
+    #[test]
+    pub fn test_stable_rows_across_pans() {
+        let a = FakeAlignment { id: "a".to_owned(), interval: (0, 10).try_into().unwrap() };
+        let b = FakeAlignment { id: "b".to_owned(), interval: (5, 15).try_into().unwrap() };
+        let d = FakeAlignment { id: "d".to_owned(), interval: (8, 60).try_into().unwrap() };
+        let mut stack = AlignmentStack::new();
+        stack.update(vec![a.clone(), b.clone()], &GenomicRegion::new("X", 0, 20).unwrap()).unwrap();
+        stack.update(vec![d.clone()], &GenomicRegion::new("X", 0, 60).unwrap()).unwrap();
+        let expected_stack = vec![vec![b.clone()], vec![a.clone()], vec![d.clone()]];
+        assert_eq!(stack.rows, expected_stack);
+
+        // Rows which are emptied by panning are kept so that the rows below them don't shift
+        stack.update(Vec::new(), &GenomicRegion::new("X", 40, 60).unwrap()).unwrap();
+        assert_eq!(stack.rows, vec![vec![], vec![], vec![d.clone()]]);
+        stack.update(vec![a.clone(), b.clone()], &GenomicRegion::new("X", 0, 60).unwrap()).unwrap();
+        assert_eq!(stack.rows, expected_stack);
+
+        // Alignments which are read again after leaving the stack return to their previous rows
+        stack.update(Vec::new(), &GenomicRegion::new("X", 100, 200).unwrap()).unwrap();
+        assert!(stack.rows.is_empty());
+        stack.update(vec![a, b, d], &GenomicRegion::new("X", 0, 60).unwrap()).unwrap();
+        assert_eq!(stack.rows, expected_stack);
+    }
+
+    #[test]
+    pub fn test_last_delta() {
+        let mut stack = AlignmentStack::new();
+        let alignments = vec![
+            FakeAlignment { id: "a".to_owned(), interval: (0, 10).try_into().unwrap() },
+            FakeAlignment { id: "b".to_owned(), interval: (5, 15).try_into().unwrap() },
+            FakeAlignment { id: "c".to_owned(), interval: (12, 20).try_into().unwrap() },
+        ];
+        stack.update(alignments.clone(), &GenomicRegion::new("X", 0, 20).unwrap()).unwrap();
+        let revision = stack.revision;
+        // Panning right drops "a", keeps "b" and "c" in their rows and adds "d" and "e" after them
+        let alignments = vec![
+            alignments[1].clone(),
+            alignments[2].clone(),
+            FakeAlignment { id: "d".to_owned(), interval: (16, 25).try_into().unwrap() },
+            FakeAlignment { id: "e".to_owned(), interval: (22, 30).try_into().unwrap() },
+        ];
+        stack.update(alignments, &GenomicRegion::new("X", 11, 30).unwrap()).unwrap();
+        let delta = stack.last_delta().unwrap();
+        assert_eq!((delta.previous_revision, delta.revision), (revision, stack.revision));
+        assert_eq!(delta.removed_ids, vec!["a".to_owned()]);
+        let added: Vec<(usize, usize, &str)> = delta
+            .added
+            .iter()
+            .map(|added| (added.row_index, added.index, added.alignment.id()))
+            .collect();
+        assert_eq!(added, vec![(0, 1, "e"), (1, 1, "d")]);
+        assert_eq!(delta.num_rows, 2);
+
+        // A changed alignment is removed and added again
+        let mut changed = stack.rows[1][0].clone();
+        changed.interval = (5, 16).try_into().unwrap();
+        stack.update(vec![changed], &GenomicRegion::new("X", 11, 30).unwrap()).unwrap();
+        let delta = stack.last_delta().unwrap();
+        assert_eq!(delta.removed_ids, vec!["b".to_owned()]);
+        assert_eq!(delta.added.len(), 1);
+
+        // Any other change invalidates the delta
+        stack.sort_rows(vec![0, 0]).unwrap();
+        assert!(stack.last_delta().is_none());
+    }
+
     #[test]
     pub fn test_update_stack_with_duplicate_ids() {
         let alignments1 = vec![
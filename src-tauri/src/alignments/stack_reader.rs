@@ -1,17 +1,103 @@
-use anyhow::{anyhow, Result};
-use parking_lot::RwLock;
+use anyhow::{anyhow, bail, Result};
+use parking_lot::{Mutex, MutexGuard, RwLock};
+use serde::Serialize;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use crate::alignments::alignment_reader::AlignmentReader;
-use crate::alignments::stack::AlignmentStack;
+use crate::alignments::alignment::Alignment;
+use crate::alignments::alignment_reader::{AlignmentReader, SkippedReads};
+use crate::alignments::cancellation::CancellationToken;
+use crate::alignments::downsampling::{downsample, Downsampling};
+use crate::alignments::field_mask::AlignmentFieldMask;
+use crate::alignments::sorting::{sort_stack, SortBy};
+use crate::alignments::stack::{AlignmentStack, PackingStrategy, StackGrouping};
 use crate::bio_util::genomic_coordinates::GenomicRegion;
 use crate::bio_util::sequence::SequenceView;
 use crate::file_formats::enums::{
     get_file_kind, AlignmentReaderKind, AlignmentStackKind, FileKind,
 };
-use crate::file_formats::sam_bam::aligned_read::pair_reads;
-use crate::file_formats::sam_bam::reader::BamReader;
+use crate::file_formats::nanopore::summary::SequencingSummary;
+use crate::file_formats::paf::reader::PafReader;
+use crate::file_formats::paf::record::PafRecord;
+use crate::file_formats::sam_bam::aligned_read::{pair_reads, AlignedRead};
+use crate::file_formats::sam_bam::coverage_pyramid::{BinnedCoverage, SAMPLED_COVERAGE_BINS};
+use crate::file_formats::sam_bam::insert_size::classify_insert_sizes;
+use crate::file_formats::sam_bam::pileup::{
+    get_mismatch_highlight_threshold, MismatchHighlight, Pileup,
+};
+use crate::file_formats::sam_bam::read_details::ReadDetails;
+use crate::file_formats::sam_bam::reader::{BamReader, ReadFilter, UnmappedRead};
+use crate::file_formats::sam_bam::splice_junctions::{count_splice_junctions, SpliceJunction};
+use crate::file_formats::signal::bins::{SignalBins, SignalInterval};
+use crate::file_formats::signal::reader::SignalReader;
+use crate::file_formats::tabix::reader::TabixReader;
+use crate::file_formats::tabix::record::{IntervalColumns, IntervalRecord};
+
+/// Unstacked alignments which were read from a region of a file.
+#[derive(Debug)]
+pub enum FetchedAlignments {
+    AlignedReads(Vec<AlignedRead>),
+    PafRecords(Vec<PafRecord>),
+    IntervalRecords(Vec<IntervalRecord>),
+    SignalIntervals(Vec<SignalInterval>),
+}
+
+/// Alignments which were read from a region of a file. Shared between the stack readers of a
+/// track in different splits which are loaded at the same time so that the region is only read
+/// once.
+#[derive(Debug)]
+pub struct FetchedRegion {
+    pub region: GenomicRegion,
+    pub alignments: FetchedAlignments,
+    pub skipped_reads: SkippedReads,
+}
+
+/// A read in a stack which overlaps a position.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadAtPosition {
+    pub row_index: usize,
+    pub pair_id: String,
+    pub read_id: String,
+}
+
+/// Fetched alignments which are locked while a fetch is in progress.
+pub struct FetchClaim<'a> {
+    fetched: MutexGuard<'a, Option<Arc<FetchedRegion>>>,
+    pin: FetchPin<'a>,
+}
+
+/// Keeps a reader's fetched alignments in memory until the reader holding it has stacked them.
+/// The alignments are dropped once every pin is released.
+pub struct FetchPin<'a> {
+    reader: &'a StackReader,
+}
+
+impl Drop for FetchPin<'_> {
+    fn drop(&mut self) {
+        if self.reader.fetch_pins.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.reader.release_fetched();
+        }
+    }
+}
+
+/// Copy the alignments which overlap a region (all of them if it's the region they were fetched
+/// for).
+fn copy_overlapping<T: Alignment + Clone>(
+    alignments: &[T],
+    fetched_region: &GenomicRegion,
+    region: &GenomicRegion,
+) -> Vec<T> {
+    if fetched_region == region {
+        return alignments.to_vec();
+    }
+    alignments
+        .iter()
+        .filter(|alignment| alignment.start() < region.end() && alignment.end() > region.start())
+        .cloned()
+        .collect()
+}
 
 /// Reads alignments from a file and returns them stacked into rows for rendering.
 #[derive(Debug)]
@@ -21,57 +107,783 @@ pub struct StackReader {
 
     /// Stacked alignments from the last read operation.
     ///
-    /// Mutated during each read operation.
+    /// Mutated during each read operation. The write lock is only held while the newly read
+    /// alignments are merged into the stack so that readers aren't blocked by file IO.
     stack: Arc<RwLock<AlignmentStackKind>>,
 
+    /// Records which were skipped during the last read operation.
+    skipped_reads: RwLock<SkippedReads>,
+
+    /// Read depth sampled across the region when the stack was last cleared (SAM/BAM files only),
+    /// so that something is still shown when zoomed out too far to show alignments.
+    binned_coverage: RwLock<Option<BinnedCoverage>>,
+
+    /// Positions in the stacked region with a high fraction of mismatched bases (SAM/BAM files
+    /// only), which are highlighted as likely SNVs.
+    mismatch_highlights: RwLock<Vec<MismatchHighlight>>,
+
+    /// Splice junctions of the stacked reads (SAM/BAM files only), for drawing sashimi arcs.
+    splice_junctions: RwLock<Vec<SpliceJunction>>,
+
+    /// Signal values binned across the last region which was read (bedGraph/wig files only).
+    signal_bins: RwLock<Option<SignalBins>>,
+
+    /// Maximum read depth of the stack (SAM/BAM files only). Reads are randomly sampled down to
+    /// this depth when they're stacked.
+    max_depth: RwLock<Option<u32>>,
+
+    /// How many reads were dropped when alignments were last stacked, if any were.
+    downsampling: RwLock<Option<Downsampling>>,
+
+    /// Fields which are included when the stack is sent to the frontend.
+    field_mask: RwLock<AlignmentFieldMask>,
+
+    /// Sequencing summary whose run metadata is joined onto the reads in the stack.
+    sequencing_summary: RwLock<Option<Arc<SequencingSummary>>>,
+
     /// Inner struct which reads alignments from the file.
-    reader: AlignmentReaderKind,
+    reader: Mutex<AlignmentReaderKind>,
+
+    /// Alignments which were last read from the file before being stacked. Locked while they're
+    /// being read, and only kept until they've been stacked by this reader and by the readers of
+    /// other splits which share them.
+    fetched: Mutex<Option<Arc<FetchedRegion>>>,
+
+    /// Number of readers (this one while it's reading and those of other splits waiting to share
+    /// its alignments) which still need to stack the fetched alignments.
+    fetch_pins: AtomicUsize,
+
+    /// Region of the fetched alignments, which is set as soon as a fetch starts so that it can be
+    /// checked without waiting for the fetch to finish.
+    fetched_region: RwLock<Option<GenomicRegion>>,
 }
 
 impl StackReader {
     pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        Self::with_interval_columns(path, None)
+    }
+
+    /// Initialize a reader with a column mapping for generic tabix-indexed interval files.
+    ///
+    /// The mapping is ignored for other file types. Tabix-indexed files default to BED-style
+    /// columns if no mapping is provided.
+    pub fn with_interval_columns<P: Into<PathBuf>>(
+        path: P,
+        interval_columns: Option<IntervalColumns>,
+    ) -> Result<Self> {
         let pathbuf = path.into();
-        match get_file_kind(&pathbuf)? {
-            FileKind::Bam | FileKind::Sam => {
-                let stack = AlignmentStackKind::AlignedPairKind(AlignmentStack::new());
-                let reader = AlignmentReaderKind::BamKind(BamReader::new(&pathbuf)?);
-                Ok(Self { path: pathbuf, stack: Arc::new(RwLock::new(stack)), reader })
+        let (stack, reader) = match get_file_kind(&pathbuf)? {
+            FileKind::Bam | FileKind::Sam => (
+                AlignmentStackKind::AlignedPairKind(AlignmentStack::new()),
+                AlignmentReaderKind::BamKind(BamReader::new(&pathbuf)?),
+            ),
+            FileKind::Paf => (
+                AlignmentStackKind::PafRecordKind(AlignmentStack::new()),
+                AlignmentReaderKind::PafKind(PafReader::new(&pathbuf)?),
+            ),
+            FileKind::Tabix => (
+                AlignmentStackKind::IntervalRecordKind(AlignmentStack::new()),
+                AlignmentReaderKind::TabixKind(TabixReader::new(
+                    &pathbuf,
+                    interval_columns.unwrap_or_default(),
+                )?),
+            ),
+            // Signal files don't have any alignments, so their stack is always empty
+            FileKind::BedGraph | FileKind::Wig => (
+                AlignmentStackKind::IntervalRecordKind(AlignmentStack::new()),
+                AlignmentReaderKind::SignalKind(SignalReader::new(&pathbuf)?),
+            ),
+            FileKind::Fastq => {
+                return Err(anyhow!(
+                    "FASTQ files contain unaligned reads and can only be opened in the FASTQ \
+                    viewer: {}",
+                    pathbuf.to_string_lossy()
+                ))
+            }
+            _ => {
+                return Err(anyhow!(
+                    "File extension is not a recognized alignment file format: {}",
+                    pathbuf.to_string_lossy().to_string()
+                ))
             }
-            _ => Err(anyhow!(
-                "File extension is not a recognized alignment file format: {}",
-                pathbuf.to_string_lossy().to_string()
-            )),
+        };
+        Ok(Self {
+            path: pathbuf,
+            stack: Arc::new(RwLock::new(stack)),
+            skipped_reads: RwLock::new(SkippedReads::default()),
+            binned_coverage: RwLock::new(None),
+            mismatch_highlights: RwLock::new(Vec::new()),
+            splice_junctions: RwLock::new(Vec::new()),
+            signal_bins: RwLock::new(None),
+            max_depth: RwLock::new(None),
+            downsampling: RwLock::new(None),
+            field_mask: RwLock::new(AlignmentFieldMask::default()),
+            sequencing_summary: RwLock::new(None),
+            reader: Mutex::new(reader),
+            fetched: Mutex::new(None),
+            fetch_pins: AtomicUsize::new(0),
+            fetched_region: RwLock::new(None),
+        })
+    }
+
+    /// Initialize a reader for the same file as another reader, copying its stack rather than
+    /// re-reading the file.
+    pub fn from_sibling(
+        sibling: &StackReader,
+        interval_columns: Option<IntervalColumns>,
+    ) -> Result<Self> {
+        let reader = Self::with_interval_columns(sibling.path.clone(), interval_columns)?;
+        if let (AlignmentReaderKind::BamKind(bam_reader), AlignmentReaderKind::BamKind(sibling)) =
+            (&mut *reader.reader.lock(), &*sibling.reader.lock())
+        {
+            bam_reader.set_filter(sibling.filter());
+            bam_reader.set_base_qualities(sibling.base_qualities());
         }
+        *reader.stack.write() = sibling.stack.read().duplicate();
+        *reader.skipped_reads.write() = sibling.skipped_reads();
+        *reader.binned_coverage.write() = sibling.binned_coverage();
+        *reader.mismatch_highlights.write() = sibling.mismatch_highlights();
+        *reader.splice_junctions.write() = sibling.splice_junctions();
+        *reader.signal_bins.write() = sibling.signal_bins();
+        *reader.max_depth.write() = *sibling.max_depth.read();
+        *reader.downsampling.write() = sibling.downsampling();
+        *reader.sequencing_summary.write() = sibling.sequencing_summary.read().clone();
+        Ok(reader)
     }
 
     pub fn stack(&self) -> Arc<RwLock<AlignmentStackKind>> {
         Arc::clone(&self.stack)
     }
 
+    /// Sort the rows of the stack by the alignments at a position within its buffered region.
+    pub fn sort_rows(&self, position: u64, by: SortBy) -> Result<()> {
+        match &mut *self.stack.write() {
+            AlignmentStackKind::AlignedPairKind(stack) => {
+                let in_buffered_region = stack
+                    .buffered_region
+                    .as_ref()
+                    .map_or(false, |region| region.start() <= position && position < region.end());
+                if !in_buffered_region {
+                    bail!(
+                        "Can't sort alignments at {} since it's outside of the loaded region",
+                        position
+                    );
+                }
+                sort_stack(stack, position, by)
+            }
+            AlignmentStackKind::PafRecordKind(_) | AlignmentStackKind::IntervalRecordKind(_) => {
+                bail!("Only SAM/BAM alignments can be sorted")
+            }
+        }
+    }
+
+    /// Get the read depth which was sampled when the stack was last cleared, if it's cleared.
+    pub fn binned_coverage(&self) -> Option<BinnedCoverage> {
+        self.binned_coverage.read().clone()
+    }
+
+    /// Get the positions which were highlighted when alignments were last stacked.
+    pub fn mismatch_highlights(&self) -> Vec<MismatchHighlight> {
+        self.mismatch_highlights.read().clone()
+    }
+
+    /// Get the splice junctions which were counted when alignments were last stacked.
+    pub fn splice_junctions(&self) -> Vec<SpliceJunction> {
+        self.splice_junctions.read().clone()
+    }
+
+    /// Get the signal values which were binned when the file was last read, if it's a signal file.
+    pub fn signal_bins(&self) -> Option<SignalBins> {
+        self.signal_bins.read().clone()
+    }
+
+    /// Get how many reads were dropped when alignments were last stacked, if any were.
+    pub fn downsampling(&self) -> Option<Downsampling> {
+        self.downsampling.read().clone()
+    }
+
+    /// Get the alignments which were last read from the file if they're still pinned, waiting if
+    /// they're being read.
+    pub fn fetched(&self) -> Option<Arc<FetchedRegion>> {
+        self.fetched.lock().clone()
+    }
+
+    /// Keep the alignments which are being read (or were just read) from the file in memory until
+    /// the returned pin is released, so that the reader of another split can share them.
+    pub fn pin_fetched(&self) -> FetchPin<'_> {
+        self.fetch_pins.fetch_add(1, Ordering::SeqCst);
+        FetchPin { reader: self }
+    }
+
+    /// Drop the fetched alignments unless they've been pinned again.
+    fn release_fetched(&self) {
+        let mut fetched = self.fetched.lock();
+        if self.fetch_pins.load(Ordering::SeqCst) == 0 {
+            *fetched = None;
+            *self.fetched_region.write() = None;
+        }
+    }
+
+    /// Whether the alignments which are being read from the file (or were read and are still
+    /// pinned) span a region.
+    pub fn has_fetched(&self, region: &GenomicRegion) -> bool {
+        let fetched_region = self.fetched_region.read();
+        fetched_region
+            .as_ref()
+            .map_or(false, |fetched_region| fetched_region.contains(region.clone()))
+    }
+
+    pub fn skipped_reads(&self) -> SkippedReads {
+        self.skipped_reads.read().clone()
+    }
+
+    /// Set the maximum read depth of the stack. None disables downsampling.
+    ///
+    /// Like a read filter, a new depth clears the stack so that it's re-read on the next update.
+    pub fn set_max_depth(&self, max_depth: Option<u32>) {
+        let previous = std::mem::replace(&mut *self.max_depth.write(), max_depth);
+        if previous != max_depth {
+            self.clear_fetched();
+        }
+    }
+
+    /// Set which reads are excluded when reading the file (SAM/BAM files only).
+    ///
+    /// The alignments which were already read are dropped, so the stack needs to be read again.
+    pub fn set_read_filter(&self, filter: ReadFilter) -> Result<()> {
+        match &mut *self.reader.lock() {
+            AlignmentReaderKind::BamKind(reader) => reader.set_filter(filter),
+            AlignmentReaderKind::PafKind(_)
+            | AlignmentReaderKind::SignalKind(_)
+            | AlignmentReaderKind::TabixKind(_) => {
+                bail!("Read filters can only be applied to SAM/BAM files")
+            }
+        }
+        self.clear_fetched();
+        Ok(())
+    }
+
+    /// Set whether base qualities are read for SAM/BAM files. Ignored for other files, which don't
+    /// have base qualities.
+    ///
+    /// Like a read filter, this clears the stack so that it's re-read on the next update.
+    pub fn set_base_qualities(&self, base_qualities: bool) {
+        match &mut *self.reader.lock() {
+            AlignmentReaderKind::BamKind(reader) => reader.set_base_qualities(base_qualities),
+            AlignmentReaderKind::PafKind(_)
+            | AlignmentReaderKind::SignalKind(_)
+            | AlignmentReaderKind::TabixKind(_) => return,
+        }
+        self.clear_fetched();
+    }
+
+    /// Drop the alignments which were read from the file so that they're re-read on the next
+    /// update.
+    fn clear_fetched(&self) {
+        let buffered_region = self.stack.read().buffered_region().clone();
+        if let Some(buffered_region) = buffered_region {
+            self.clear_rows(&buffered_region);
+        }
+        self.mismatch_highlights.write().clear();
+        self.splice_junctions.write().clear();
+        *self.signal_bins.write() = None;
+        *self.downsampling.write() = None;
+        *self.fetched.lock() = None;
+        *self.fetched_region.write() = None;
+    }
+
+    pub fn field_mask(&self) -> AlignmentFieldMask {
+        self.field_mask.read().clone()
+    }
+
+    pub fn set_field_mask(&self, field_mask: AlignmentFieldMask) {
+        *self.field_mask.write() = field_mask;
+    }
+
+    /// Set the minimum gap between adjacent alignments in a row, how alignments are packed into
+    /// rows and how they're grouped, restacking the alignments which are already in the stack.
+    pub fn set_stack_layout(
+        &self,
+        padding: u64,
+        packing: PackingStrategy,
+        grouping: StackGrouping,
+    ) -> Result<()> {
+        self.stack.write().set_layout(padding, packing, grouping)
+    }
+
+    /// Set the maximum number of rows in the stack, hiding any rows beyond it. None shows every
+    /// row.
+    pub fn set_max_rows(&self, max_rows: Option<u32>) {
+        self.stack.write().set_max_rows(max_rows.map(|max_rows| max_rows as usize));
+    }
+
+    /// Join the run metadata from a sequencing summary onto the reads in the stack, including
+    /// reads which are read later.
+    pub fn set_sequencing_summary(&self, summary: Arc<SequencingSummary>) -> Result<()> {
+        match &mut *self.stack.write() {
+            AlignmentStackKind::AlignedPairKind(stack) => {
+                let reads = stack.alignments_mut().flat_map(|pair| pair.reads_mut());
+                reads.for_each(|read| summary.annotate(read));
+            }
+            AlignmentStackKind::PafRecordKind(_) | AlignmentStackKind::IntervalRecordKind(_) => {
+                bail!("Sequencing summaries can only be joined onto SAM/BAM files")
+            }
+        }
+        *self.sequencing_summary.write() = Some(summary);
+        Ok(())
+    }
+
+    /// Serialize the stack, excluding any fields which are masked for this track/split and the
+    /// diffs of squished/collapsed stacks.
+    pub fn serialize_stack(&self) -> Result<serde_json::Value> {
+        Ok(self.serialize_stack_with(serde_json::to_value)?)
+    }
+
+    /// Serialize the stack as in serialize_stack, but with a field mask which only applies to this
+    /// call in place of the mask which is set for this track/split.
+    pub fn serialize_stack_masked(
+        &self,
+        field_mask: &AlignmentFieldMask,
+    ) -> Result<serde_json::Value> {
+        let stack = self.stack.read();
+        Ok(Self::stack_field_mask(&stack, field_mask).scope(|| serde_json::to_value(&*stack))?)
+    }
+
+    /// Serialize the stack with any serializer (e.g to encode it as MessagePack), with fields
+    /// masked as in serialize_stack.
+    pub fn serialize_stack_with<T, F: FnOnce(&AlignmentStackKind) -> T>(&self, serialize: F) -> T {
+        let stack = self.stack.read();
+        Self::stack_field_mask(&stack, &self.field_mask()).scope(|| serialize(&*stack))
+    }
+
+    /// Serialize the changes made to the stack by the last read, with fields masked as in
+    /// serialize_stack.
+    ///
+    /// Returns None if the stack has changed in some other way since, or if the changes aren't
+    /// smaller than the stack.
+    pub fn serialize_delta(&self) -> Result<Option<serde_json::Value>> {
+        let stack = self.stack.read();
+        Self::stack_field_mask(&stack, &self.field_mask()).scope(|| stack.serialize_last_delta())
+    }
+
+    /// Serialize a range of the stack's rows (e.g only the visible rows of a deep stack), along
+    /// with the index of the first row (rowStart) and the number of rows in the whole stack
+    /// (totalRows). Only the rows in the range are serialized.
+    ///
+    /// The range is clamped to the rows of the stack, and the row indices of groups and the strand
+    /// boundary are relative to its start. Fields are masked as in serialize_stack.
+    pub fn serialize_stack_rows(
+        &self,
+        row_start: usize,
+        row_end: Option<usize>,
+    ) -> Result<serde_json::Value> {
+        self.serialize_stack_rows_masked(&self.field_mask(), row_start, row_end)
+    }
+
+    /// Serialize a range of the stack's rows as in serialize_stack_rows, but with a field mask
+    /// which only applies to this call in place of the mask which is set for this track/split.
+    pub fn serialize_stack_rows_masked(
+        &self,
+        field_mask: &AlignmentFieldMask,
+        row_start: usize,
+        row_end: Option<usize>,
+    ) -> Result<serde_json::Value> {
+        let stack = self.stack.read();
+        Self::stack_field_mask(&stack, field_mask)
+            .scope(|| stack.serialize_rows(row_start, row_end))
+    }
+
+    /// Get the fields which are sent to the frontend, excluding diffs for squished/collapsed
+    /// stacks.
+    fn stack_field_mask(
+        stack: &AlignmentStackKind,
+        field_mask: &AlignmentFieldMask,
+    ) -> AlignmentFieldMask {
+        let mut field_mask = field_mask.clone();
+        if stack.suppresses_diffs() {
+            field_mask.diffs = false;
+        }
+        field_mask
+    }
+
+    /// Find a read in the stack by its id.
+    pub fn find_read(&self, read_id: &str) -> Option<AlignedRead> {
+        match &*self.stack.read() {
+            AlignmentStackKind::AlignedPairKind(stack) => stack
+                .rows
+                .iter()
+                .flatten()
+                .flat_map(|pair| pair.reads())
+                .find(|read| read.id == read_id)
+                .cloned(),
+            AlignmentStackKind::PafRecordKind(_) | AlignmentStackKind::IntervalRecordKind(_) => {
+                None
+            }
+        }
+    }
+
+    /// Find the reads in the stack which overlap a position, ordered by row.
+    ///
+    /// Positions between the reads of a pair don't overlap either read.
+    pub fn reads_at(&self, position: u64) -> Result<Vec<ReadAtPosition>> {
+        match &*self.stack.read() {
+            AlignmentStackKind::AlignedPairKind(stack) => Ok(stack
+                .alignments_at(position)
+                .into_iter()
+                .flat_map(|(row_index, pair)| {
+                    pair.reads()
+                        .filter(|read| {
+                            read.region.start() <= position && position < read.region.end()
+                        })
+                        .map(move |read| ReadAtPosition {
+                            row_index,
+                            pair_id: pair.id().to_owned(),
+                            read_id: read.id.clone(),
+                        })
+                })
+                .collect()),
+            AlignmentStackKind::PafRecordKind(_) | AlignmentStackKind::IntervalRecordKind(_) => {
+                bail!("Reads at a position are only available for SAM/BAM tracks")
+            }
+        }
+    }
+
+    /// Fetch the unmapped mate of a read from the file.
+    pub fn fetch_unmapped_mate(&self, read: &AlignedRead) -> Result<Option<UnmappedRead>> {
+        match &*self.reader.lock() {
+            AlignmentReaderKind::BamKind(reader) => {
+                reader.fetch_unmapped_mate(&read.qname, &read.region)
+            }
+            AlignmentReaderKind::PafKind(_)
+            | AlignmentReaderKind::SignalKind(_)
+            | AlignmentReaderKind::TabixKind(_) => {
+                bail!("Unmapped mates are only available for SAM/BAM files")
+            }
+        }
+    }
+
+    /// Fetch the full sequence of an insertion in a read from the file.
+    pub fn fetch_insertion_sequence(
+        &self,
+        read: &AlignedRead,
+        position: u64,
+        refseq: &SequenceView,
+    ) -> Result<String> {
+        match &*self.reader.lock() {
+            AlignmentReaderKind::BamKind(reader) => {
+                reader.fetch_insertion_sequence(read, position, refseq)
+            }
+            AlignmentReaderKind::PafKind(_)
+            | AlignmentReaderKind::SignalKind(_)
+            | AlignmentReaderKind::TabixKind(_) => {
+                bail!("Insertion sequences are only available for SAM/BAM files")
+            }
+        }
+    }
+
+    /// Fetch the aligned region of a read's mate from the file.
+    pub fn fetch_mate_region(&self, read: &AlignedRead) -> Result<Option<GenomicRegion>> {
+        match &*self.reader.lock() {
+            AlignmentReaderKind::BamKind(reader) => reader.fetch_mate_region(read),
+            AlignmentReaderKind::PafKind(_)
+            | AlignmentReaderKind::SignalKind(_)
+            | AlignmentReaderKind::TabixKind(_) => {
+                bail!("Mates are only available for SAM/BAM files")
+            }
+        }
+    }
+
+    /// Fetch the full details of a read (e.g all of its tags) from the file.
+    pub fn fetch_read_details(
+        &self,
+        read: &AlignedRead,
+        refseq: &SequenceView,
+    ) -> Result<ReadDetails> {
+        match &*self.reader.lock() {
+            AlignmentReaderKind::BamKind(reader) => reader.fetch_read_details(read, refseq),
+            AlignmentReaderKind::PafKind(_)
+            | AlignmentReaderKind::SignalKind(_)
+            | AlignmentReaderKind::TabixKind(_) => {
+                bail!("Read details are only available for SAM/BAM files")
+            }
+        }
+    }
+
+    /// Read the lengths of the reads which overlap a region (or at the start of the file if no
+    /// region is given) from the file.
+    pub fn sample_read_lengths(
+        &self,
+        region: Option<&GenomicRegion>,
+        max_reads: usize,
+    ) -> Result<(Vec<u64>, bool)> {
+        match &*self.reader.lock() {
+            AlignmentReaderKind::BamKind(reader) => reader.sample_read_lengths(region, max_reads),
+            AlignmentReaderKind::PafKind(_)
+            | AlignmentReaderKind::SignalKind(_)
+            | AlignmentReaderKind::TabixKind(_) => {
+                bail!("Read lengths are only available for SAM/BAM files")
+            }
+        }
+    }
+
     /// Remove all alignments from the stack.
     ///
     /// This is intended for cases where the user loads a region which is too large to render in the
-    /// UI.
-    pub fn clear_stack(&mut self, region: &GenomicRegion) -> Result<()> {
+    /// UI. The read depth across the region is sampled for SAM/BAM files so that it can be shown
+    /// instead.
+    pub fn clear_stack(&self, region: &GenomicRegion) -> Result<()> {
+        self.clear_rows(region);
+        *self.skipped_reads.write() = SkippedReads::default();
+        self.mismatch_highlights.write().clear();
+        self.splice_junctions.write().clear();
+        *self.downsampling.write() = None;
+        *self.fetched.lock() = None;
+        *self.fetched_region.write() = None;
+        let binned_coverage = match &*self.reader.lock() {
+            AlignmentReaderKind::BamKind(reader) => {
+                match reader.sample_coverage(region, SAMPLED_COVERAGE_BINS) {
+                    Ok(binned_coverage) => Some(binned_coverage),
+                    Err(err) => {
+                        log::warn!(
+                            "Failed to sample coverage of {} in {}: {:#}",
+                            self.path.display(),
+                            region,
+                            err
+                        );
+                        None
+                    }
+                }
+            }
+            AlignmentReaderKind::PafKind(_)
+            | AlignmentReaderKind::SignalKind(_)
+            | AlignmentReaderKind::TabixKind(_) => None,
+        };
+        *self.binned_coverage.write() = binned_coverage;
+        // Signal values are binned, so they're still shown when zoomed out too far for alignments
+        let signal_bins = match &*self.reader.lock() {
+            AlignmentReaderKind::SignalKind(reader) => {
+                Some(SignalBins::summarize(region, &reader.read(region)?))
+            }
+            AlignmentReaderKind::BamKind(_)
+            | AlignmentReaderKind::PafKind(_)
+            | AlignmentReaderKind::TabixKind(_) => None,
+        };
+        *self.signal_bins.write() = signal_bins;
+        Ok(())
+    }
+
+    /// Remove all alignments from the stack, leaving the rest of the reader's state as is.
+    fn clear_rows(&self, region: &GenomicRegion) {
         match &mut *self.stack.write() {
             AlignmentStackKind::AlignedPairKind(stack) => stack.clear(region),
+            AlignmentStackKind::PafRecordKind(stack) => stack.clear(region),
+            AlignmentStackKind::IntervalRecordKind(stack) => stack.clear(region),
         };
-        Ok(())
     }
 
-    /// Read alignments from the file into the stack.
-    pub fn read_stacked(&mut self, region: &GenomicRegion, seqview: &SequenceView) -> Result<()> {
-        let alignments = match &mut self.reader {
+    fn stack_kind_mismatch(&self) -> anyhow::Error {
+        anyhow!(
+            "Alignment stack kind does not match the reader for {}",
+            self.path.to_string_lossy()
+        )
+    }
+
+    /// Read alignments from a region of the file without stacking them.
+    fn fetch(
+        &self,
+        region: &GenomicRegion,
+        seqview: &SequenceView,
+        cancellation: &CancellationToken,
+    ) -> Result<FetchedRegion> {
+        let (alignments, skipped_reads) = match &mut *self.reader.lock() {
             AlignmentReaderKind::BamKind(reader) => {
-                let aligned_reads = reader.read(region, seqview)?;
-                pair_reads(aligned_reads)?
+                let (reads, skipped_reads) =
+                    reader.read_cancellable(region, seqview, cancellation)?;
+                (FetchedAlignments::AlignedReads(reads), skipped_reads)
+            }
+            AlignmentReaderKind::PafKind(reader) => {
+                let (records, skipped_reads) =
+                    reader.read_cancellable(region, seqview, cancellation)?;
+                (FetchedAlignments::PafRecords(records), skipped_reads)
+            }
+            AlignmentReaderKind::TabixKind(reader) => {
+                let (records, skipped_reads) =
+                    reader.read_cancellable(region, seqview, cancellation)?;
+                (FetchedAlignments::IntervalRecords(records), skipped_reads)
+            }
+            AlignmentReaderKind::SignalKind(reader) => {
+                cancellation.check()?;
+                (FetchedAlignments::SignalIntervals(reader.read(region)?), SkippedReads::default())
             }
         };
-        match &mut *self.stack.write() {
-            AlignmentStackKind::AlignedPairKind(stack) => stack.update(alignments, region),
-        }?;
+        Ok(FetchedRegion { region: region.clone(), alignments, skipped_reads })
+    }
+
+    /// Merge the alignments which overlap a region into the stack.
+    ///
+    /// SAM/BAM reads are downsampled to the maximum depth (if one is set) before being stacked, and
+    /// the mismatch fraction at each position is computed at the same time. Insert sizes are
+    /// classified before downsampling so that the sampled range uses every pair in the region.
+    ///
+    /// If the alignments were read for a larger region, the diffs of SAM/BAM reads are clipped to
+    /// the region's sequence and only the records which were skipped in the region are counted, as
+    /// if the region had been read by itself.
+    fn stack_fetched(
+        &self,
+        fetched: &FetchedRegion,
+        region: &GenomicRegion,
+        seqview: &SequenceView,
+    ) -> Result<()> {
+        let mut mismatch_highlights = Vec::new();
+        let mut splice_junctions = Vec::new();
+        let mut downsampling = None;
+        let mut signal_bins = None;
+        match &fetched.alignments {
+            FetchedAlignments::AlignedReads(reads) => {
+                let mut aligned_reads = copy_overlapping(reads, &fetched.region, region);
+                if fetched.region != *region {
+                    aligned_reads.iter_mut().for_each(|read| read.clip_diffs(seqview));
+                }
+                if let Some(summary) = &*self.sequencing_summary.read() {
+                    aligned_reads.iter_mut().for_each(|read| summary.annotate(read));
+                }
+                let mut alignments = pair_reads(aligned_reads)?;
+                classify_insert_sizes(&mut alignments);
+                if let Some(max_depth) = *self.max_depth.read() {
+                    let num_alignments = alignments.len();
+                    alignments = downsample(alignments, max_depth as usize);
+                    if alignments.len() < num_alignments {
+                        downsampling = Some(Downsampling {
+                            region: region.clone(),
+                            max_depth,
+                            num_alignments: num_alignments as u64,
+                            num_sampled: alignments.len() as u64,
+                        });
+                    }
+                }
+                match &mut *self.stack.write() {
+                    AlignmentStackKind::AlignedPairKind(stack) => {
+                        stack.update(alignments, region)?;
+                        let reads = || stack.alignments().flat_map(|pair| pair.reads());
+                        mismatch_highlights = Pileup::from_reads(region, reads(), seqview)?
+                            .find_mismatch_highlights(seqview, get_mismatch_highlight_threshold());
+                        splice_junctions = count_splice_junctions(reads())?;
+                        Ok(())
+                    }
+                    _ => Err(self.stack_kind_mismatch()),
+                }?;
+            }
+            FetchedAlignments::PafRecords(records) => {
+                let records = copy_overlapping(records, &fetched.region, region);
+                match &mut *self.stack.write() {
+                    AlignmentStackKind::PafRecordKind(stack) => stack.update(records, region),
+                    _ => Err(self.stack_kind_mismatch()),
+                }?;
+            }
+            FetchedAlignments::IntervalRecords(records) => {
+                let records = copy_overlapping(records, &fetched.region, region);
+                match &mut *self.stack.write() {
+                    AlignmentStackKind::IntervalRecordKind(stack) => stack.update(records, region),
+                    _ => Err(self.stack_kind_mismatch()),
+                }?;
+            }
+            FetchedAlignments::SignalIntervals(intervals) => {
+                // Intervals outside of the region are ignored when they're binned
+                signal_bins = Some(SignalBins::summarize(region, intervals));
+                match &mut *self.stack.write() {
+                    AlignmentStackKind::IntervalRecordKind(stack) => {
+                        stack.update(Vec::<IntervalRecord>::new(), region)
+                    }
+                    _ => Err(self.stack_kind_mismatch()),
+                }?;
+            }
+        };
+        *self.skipped_reads.write() = if fetched.region == *region {
+            fetched.skipped_reads.clone()
+        } else {
+            fetched.skipped_reads.within(region)
+        };
+        *self.mismatch_highlights.write() = mismatch_highlights;
+        *self.splice_junctions.write() = splice_junctions;
+        *self.signal_bins.write() = signal_bins;
+        *self.downsampling.write() = downsampling;
+        *self.binned_coverage.write() = None;
         Ok(())
     }
+
+    /// Mark a region as being read by this reader.
+    ///
+    /// Readers which share this reader's alignments wait until the returned claim is released by
+    /// read_claimed.
+    pub fn claim_fetch(&self, region: &GenomicRegion) -> FetchClaim<'_> {
+        let pin = self.pin_fetched();
+        let fetched = self.fetched.lock();
+        *self.fetched_region.write() = Some(region.clone());
+        FetchClaim { fetched, pin }
+    }
+
+    /// Read alignments from a claimed region of the file into the stack.
+    ///
+    /// The alignments are dropped once they've been stacked, unless readers of other splits have
+    /// pinned them to share them. The stack is left unchanged if the read is cancelled.
+    pub fn read_claimed(
+        &self,
+        claim: FetchClaim<'_>,
+        region: &GenomicRegion,
+        seqview: &SequenceView,
+        cancellation: &CancellationToken,
+    ) -> Result<()> {
+        let FetchClaim { fetched: mut claimed, pin } = claim;
+        let fetched = match self.fetch(region, seqview, cancellation) {
+            Ok(fetched) => Arc::new(fetched),
+            Err(err) => {
+                *claimed = None;
+                *self.fetched_region.write() = None;
+                return Err(err);
+            }
+        };
+        *claimed = Some(Arc::clone(&fetched));
+        drop(claimed);
+        let result = self.stack_fetched(&fetched, region, seqview);
+        drop(pin);
+        result
+    }
+
+    /// Read alignments from the file into the stack.
+    pub fn read_stacked(&self, region: &GenomicRegion, seqview: &SequenceView) -> Result<()> {
+        self.read_stacked_cancellable(region, seqview, &CancellationToken::new())
+    }
+
+    /// Read alignments from the file into the stack, unless the token is cancelled first.
+    pub fn read_stacked_cancellable(
+        &self,
+        region: &GenomicRegion,
+        seqview: &SequenceView,
+        cancellation: &CancellationToken,
+    ) -> Result<()> {
+        let claim = self.claim_fetch(region);
+        self.read_claimed(claim, region, seqview, cancellation)
+    }
+
+    /// Stack the alignments which another reader of the same file pinned, rather than reading
+    /// them from the file again. Waits for the other reader to finish reading if it's in progress,
+    /// and releases the pin once they're stacked.
+    ///
+    /// Returns false without changing the stack if the other reader's alignments don't span the
+    /// region (e.g because its read failed, or they were dropped before they were pinned).
+    pub fn read_stacked_from(
+        &self,
+        other: FetchPin<'_>,
+        region: &GenomicRegion,
+        seqview: &SequenceView,
+    ) -> Result<bool> {
+        let fetched = match other.reader.fetched() {
+            Some(fetched) if fetched.region.contains(region.clone()) => fetched,
+            _ => return Ok(false),
+        };
+        self.stack_fetched(&fetched, region, seqview)?;
+        Ok(true)
+    }
 }
 
 #[cfg(test)]
@@ -79,7 +891,9 @@ mod tests {
     use crate::paths::get_test_data_path;
     use pretty_assertions::assert_eq;
 
+    use crate::alignments::cancellation::is_cancelled_error;
     use crate::file_formats::fasta::reader::FastaReader;
+    use crate::file_formats::nanopore::summary::tests::{read_test_summary, SUMMARY_READ_ID};
 
     use super::*;
 
@@ -90,9 +904,59 @@ mod tests {
         assert_eq!(&reader.path, &path);
     }
 
+    #[test]
+    pub fn test_read_stacked_paf() {
+        let reader = StackReader::new(get_test_data_path("fake-genome.paf")).unwrap();
+        let mut fasta_reader = FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap();
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let sequence_view = fasta_reader.read(&region).unwrap();
+        reader.read_stacked(&region, &sequence_view).unwrap();
+        let stack = reader.stack();
+        let stack_lock = stack.read();
+        if let AlignmentStackKind::PafRecordKind(stack) = &*stack_lock {
+            // The two overlapping records can't share a row
+            assert_eq!(stack.rows.len(), 2)
+        } else {
+            panic!("Unexpected alignment stack kind")
+        }
+    }
+
+    #[test]
+    pub fn test_read_stacked_bedgraph() {
+        let reader = StackReader::new(get_test_data_path("fake-genome.bedgraph.gz")).unwrap();
+        let mut fasta_reader = FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap();
+        let region = GenomicRegion::new("mt", 0, 40).unwrap();
+        let sequence_view = fasta_reader.read(&region).unwrap();
+        reader.read_stacked(&region, &sequence_view).unwrap();
+        assert_eq!(reader.stack().read().count_alignments(), 0);
+        let signal_bins = reader.signal_bins().unwrap();
+        assert_eq!(signal_bins.bin_size, 1);
+        assert_eq!(signal_bins.values[5], Some(1.0));
+        assert_eq!(signal_bins.values[25], Some(3.0));
+
+        // Signal values are still binned when the stack is cleared
+        let region = GenomicRegion::new("mt", 0, 4000).unwrap();
+        reader.clear_stack(&region).unwrap();
+        let signal_bins = reader.signal_bins().unwrap();
+        assert_eq!(signal_bins.bin_size, 20);
+        assert_eq!(signal_bins.values[0], Some(1.25));
+        assert_eq!(signal_bins.values[10], None);
+    }
+
+    #[test]
+    pub fn test_from_sibling() {
+        let sibling = read_example_stack();
+        let reader = StackReader::from_sibling(&sibling, None).unwrap();
+        assert_ne!(reader.stack().read().id(), sibling.stack().read().id());
+        assert_eq!(
+            serde_json::to_value(&*reader.stack().read()).unwrap().get("rows"),
+            serde_json::to_value(&*sibling.stack().read()).unwrap().get("rows")
+        );
+    }
+
     fn read_example_stack() -> StackReader {
         let bam_path = get_test_data_path("fake-genome.reads.bam");
-        let mut reader = StackReader::new(bam_path).unwrap();
+        let reader = StackReader::new(bam_path).unwrap();
         let fasta_path = get_test_data_path("fake-genome.fa");
         let mut fasta_reader = FastaReader::new(fasta_path).unwrap();
         let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
@@ -101,6 +965,57 @@ mod tests {
         reader
     }
 
+    #[test]
+    pub fn test_read_stacked_from() {
+        let bam_path = get_test_data_path("fake-genome.reads.bam");
+        let sibling = StackReader::new(&bam_path).unwrap();
+        let reader = StackReader::new(&bam_path).unwrap();
+        let mut fasta_reader = FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap();
+        let sibling_region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let sibling_sequence_view = fasta_reader.read(&sibling_region).unwrap();
+        // Readers of other splits pin the alignments while the sibling is reading them
+        let claim = sibling.claim_fetch(&sibling_region);
+        let (pin, other_pin) = (sibling.pin_fetched(), sibling.pin_fetched());
+        let cancellation = CancellationToken::new();
+        sibling
+            .read_claimed(claim, &sibling_region, &sibling_sequence_view, &cancellation)
+            .unwrap();
+        assert!(sibling.fetched().is_some());
+        // Regions which the sibling didn't read can't be shared
+        let region = GenomicRegion::new("mt", 1400, 1600).unwrap();
+        let sequence_view = fasta_reader.read(&region).unwrap();
+        assert!(!sibling.has_fetched(&region));
+        assert!(!reader.read_stacked_from(other_pin, &region, &sequence_view).unwrap());
+
+        let region = GenomicRegion::new("mt", 1100, 1200).unwrap();
+        let sequence_view = fasta_reader.read(&region).unwrap();
+        assert!(sibling.has_fetched(&region));
+        assert!(reader.read_stacked_from(pin, &region, &sequence_view).unwrap());
+        // The alignments are dropped once every reader which pinned them has stacked them
+        assert!(sibling.fetched().is_none());
+        assert!(!sibling.has_fetched(&region));
+        assert!(reader.fetched().is_none());
+
+        // Diffs and skipped reads are the same as if the region had been read by itself
+        let expected = StackReader::new(&bam_path).unwrap();
+        expected.read_stacked(&region, &sequence_view).unwrap();
+        let rows = |reader: &StackReader| {
+            serde_json::to_value(&*reader.stack().read()).unwrap().get("rows").cloned()
+        };
+        assert_eq!(rows(&reader), rows(&expected));
+        assert_eq!(reader.skipped_reads(), expected.skipped_reads());
+        assert_eq!(reader.mismatch_highlights(), expected.mismatch_highlights());
+        assert_eq!(reader.splice_junctions(), expected.splice_junctions());
+    }
+
+    #[test]
+    pub fn test_read_stacked_drops_fetched() {
+        let reader = read_example_stack();
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        assert!(reader.fetched().is_none());
+        assert!(!reader.has_fetched(&region));
+    }
+
     #[test]
     pub fn test_read_stacked() {
         let reader = read_example_stack();
@@ -111,11 +1026,190 @@ mod tests {
         } else {
             panic!("Unexpected alignment stack kind")
         }
+        assert_eq!(reader.skipped_reads().count, 0);
+    }
+
+    #[test]
+    pub fn test_cancelled_read_leaves_stack_unchanged() {
+        let reader = read_example_stack();
+        let rows = |reader: &StackReader| serde_json::to_value(&*reader.stack().read()).unwrap();
+        let expected_rows = rows(&reader);
+        let fasta_path = get_test_data_path("fake-genome.fa");
+        let mut fasta_reader = FastaReader::new(fasta_path).unwrap();
+        let region = GenomicRegion::new("mt", 2000, 2500).unwrap();
+        let sequence_view = fasta_reader.read(&region).unwrap();
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let err =
+            reader.read_stacked_cancellable(&region, &sequence_view, &cancellation).unwrap_err();
+        assert!(is_cancelled_error(&err));
+        assert_eq!(rows(&reader), expected_rows);
+        assert!(reader.fetched().is_none());
+    }
+
+    #[test]
+    pub fn test_stack_by_strand() {
+        let reader = read_example_stack();
+        reader.set_stack_layout(1, PackingStrategy::Greedy, StackGrouping::Strand).unwrap();
+        match &*reader.stack().read() {
+            AlignmentStackKind::AlignedPairKind(stack) => {
+                let boundary = stack.strand_boundary.unwrap();
+                assert!(0 < boundary && boundary < stack.rows.len());
+                for (row_index, row) in stack.rows.iter().enumerate() {
+                    for pair in row {
+                        let read = pair
+                            .reads()
+                            .find(|read| !read.is_second_of_pair)
+                            .or_else(|| pair.reads().next())
+                            .unwrap();
+                        assert_eq!(read.is_reverse, row_index >= boundary);
+                    }
+                }
+            }
+            _ => panic!("Unexpected alignment stack kind"),
+        }
+        reader.set_stack_layout(1, PackingStrategy::Greedy, StackGrouping::Ungrouped).unwrap();
+        let json = serde_json::to_value(&*reader.stack().read()).unwrap();
+        assert_eq!(json.get("strandBoundary"), Some(&serde_json::Value::Null));
+    }
+
+    #[test]
+    pub fn test_serialize_squished_stack() {
+        let reader = read_example_stack();
+        let has_diffs = |reader: &StackReader| {
+            let json = reader.serialize_stack().unwrap();
+            json["rows"][0][0]["read1"].get("diffs").is_some()
+                || json["rows"][0][0]["read"].get("diffs").is_some()
+        };
+        assert!(has_diffs(&reader));
+        reader.set_stack_layout(1, PackingStrategy::Squished, StackGrouping::Ungrouped).unwrap();
+        assert!(!has_diffs(&reader));
+    }
+
+    #[test]
+    pub fn test_serialize_masked_stack() {
+        let reader = read_example_stack();
+        reader.set_field_mask(AlignmentFieldMask { cigar_string: false, ..Default::default() });
+        let read_fields = |json: serde_json::Value| {
+            let alignment = json["rows"][0][0].clone();
+            alignment.get("read1").or_else(|| alignment.get("read")).unwrap().clone()
+        };
+        let read = read_fields(reader.serialize_stack().unwrap());
+        assert!(read.get("cigarString").is_none());
+        assert!(read.get("diffs").is_some());
+        // The mask only applies while the stack reader serializes its stack
+        let unmasked = serde_json::to_value(&*reader.stack().read()).unwrap();
+        assert!(read_fields(unmasked).get("cigarString").is_some());
+
+        // Masks passed to a single call don't change the track's mask
+        let call_mask = AlignmentFieldMask { diffs: false, ..Default::default() };
+        let read = read_fields(reader.serialize_stack_masked(&call_mask).unwrap());
+        assert!(read.get("cigarString").is_some());
+        assert!(read.get("diffs").is_none());
+        let page = reader.serialize_stack_rows_masked(&call_mask, 0, Some(1)).unwrap();
+        assert!(read_fields(page).get("diffs").is_none());
+        let read = read_fields(reader.serialize_stack().unwrap());
+        assert!(read.get("cigarString").is_none());
+        assert!(read.get("diffs").is_some());
+    }
+
+    #[test]
+    pub fn test_serialize_stack_rows() {
+        let reader = read_example_stack();
+        let rows = reader.serialize_stack().unwrap()["rows"].as_array().unwrap().clone();
+        assert!(rows.len() > 2);
+        let page = reader.serialize_stack_rows(1, Some(2)).unwrap();
+        assert_eq!(page["rows"].as_array().unwrap(), &rows[1..2]);
+        assert_eq!(
+            (page["rowStart"].as_u64(), page["totalRows"].as_u64()),
+            (Some(1), Some(rows.len() as u64))
+        );
+        // Ranges are clamped to the rows of the stack
+        let page = reader.serialize_stack_rows(1, Some(rows.len() + 10)).unwrap();
+        assert_eq!(page["rows"].as_array().unwrap(), &rows[1..]);
+        let page = reader.serialize_stack_rows(rows.len() + 10, None).unwrap();
+        assert!(page["rows"].as_array().unwrap().is_empty());
+        assert_eq!(page["rowStart"].as_u64(), Some(rows.len() as u64));
+    }
+
+    #[test]
+    pub fn test_reads_at() {
+        let reader = read_example_stack();
+        let reads = reader.reads_at(1200).unwrap();
+        assert!(!reads.is_empty());
+        assert!(reads.windows(2).all(|pair| pair[0].row_index <= pair[1].row_index));
+        for read_at in reads.iter() {
+            let read = reader.find_read(&read_at.read_id).unwrap();
+            assert!(read.region.start() <= 1200 && 1200 < read.region.end());
+        }
+        assert_eq!(reader.reads_at(5000).unwrap(), Vec::new());
+
+        let reader = StackReader::new(get_test_data_path("fake-genome.paf")).unwrap();
+        assert!(reader.reads_at(1200).is_err());
+    }
+
+    #[test]
+    pub fn test_read_stacked_highlights_mismatches() {
+        let reader = read_example_stack();
+        let mut fasta_reader = FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap();
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let sequence_view = fasta_reader.read(&region).unwrap();
+        let pileup = match &*reader.stack().read() {
+            AlignmentStackKind::AlignedPairKind(stack) => Pileup::from_reads(
+                &region,
+                stack.rows.iter().flatten().flat_map(|pair| pair.reads()),
+                &sequence_view,
+            )
+            .unwrap(),
+            _ => panic!("Unexpected alignment stack kind"),
+        };
+        // The example reads have a few sequencing errors but no SNVs
+        assert!(!pileup.find_mismatch_highlights(&sequence_view, 0.0).is_empty());
+        let threshold = get_mismatch_highlight_threshold();
+        let expected = pileup.find_mismatch_highlights(&sequence_view, threshold);
+        assert_eq!(reader.mismatch_highlights(), expected);
+
+        reader.clear_stack(&region).unwrap();
+        assert_eq!(reader.mismatch_highlights(), vec![]);
+    }
+
+    #[test]
+    pub fn test_read_stacked_downsamples() {
+        let reader = read_example_stack();
+        assert_eq!(reader.downsampling(), None);
+        let num_alignments = reader.stack().read().count_alignments();
+        let mut fasta_reader = FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap();
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let sequence_view = fasta_reader.read(&region).unwrap();
+        reader.set_max_depth(Some(3));
+        reader.read_stacked(&region, &sequence_view).unwrap();
+        let downsampling = reader.downsampling().unwrap();
+        assert_eq!(downsampling.num_alignments, num_alignments as u64);
+        let num_sampled = reader.stack().read().count_alignments();
+        assert_eq!(downsampling.num_sampled, num_sampled as u64);
+        assert!(num_sampled < num_alignments);
+        // Depth is counted in pairs since mates are kept or dropped together
+        let max_depth = match &*reader.stack().read() {
+            AlignmentStackKind::AlignedPairKind(stack) => (region.start()..region.end())
+                .map(|pos| {
+                    let pairs = stack.rows.iter().flatten();
+                    pairs.filter(|pair| pair.start() <= pos && pos < pair.end()).count()
+                })
+                .max()
+                .unwrap(),
+            _ => panic!("Unexpected alignment stack kind"),
+        };
+        assert!(max_depth <= 3);
+
+        reader.set_max_depth(None);
+        reader.read_stacked(&region, &sequence_view).unwrap();
+        assert_eq!(reader.downsampling(), None);
+        assert_eq!(reader.stack().read().count_alignments(), num_alignments);
     }
 
     #[test]
     pub fn test_clear_stack() {
-        let mut reader = read_example_stack();
+        let reader = read_example_stack();
         let region = GenomicRegion::new("mt", 10000, 25000).unwrap();
         reader.clear_stack(&region).unwrap();
         let stack = reader.stack();
@@ -125,5 +1219,32 @@ mod tests {
         } else {
             panic!("Unexpected alignment stack kind")
         }
+        let binned_coverage = reader.binned_coverage().unwrap();
+        assert_eq!(binned_coverage.region, region);
+        assert_eq!(binned_coverage.depths.len(), SAMPLED_COVERAGE_BINS);
+    }
+
+    #[test]
+    pub fn test_set_sequencing_summary() {
+        let summary = Arc::new(read_test_summary());
+        let reader = read_example_stack();
+        let region = GenomicRegion::new("euk_genes", 0, 200).unwrap();
+        let mut fasta_reader = FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap();
+        let sequence_view = fasta_reader.read(&region).unwrap();
+        reader.read_stacked(&region, &sequence_view).unwrap();
+        let find_metadata = |reader: &StackReader| {
+            reader.find_read(&format!("{}/1", SUMMARY_READ_ID)).unwrap().run_metadata
+        };
+        assert_eq!(find_metadata(&reader), None);
+        // Reads which are already in the stack are annotated
+        reader.set_sequencing_summary(Arc::clone(&summary)).unwrap();
+        assert_eq!(find_metadata(&reader).unwrap().channel, Some(112));
+        // As are reads which are read later
+        let other_reader = StackReader::new(get_test_data_path("fake-genome.reads.bam")).unwrap();
+        other_reader.set_sequencing_summary(summary).unwrap();
+        other_reader.read_stacked(&region, &sequence_view).unwrap();
+        assert_eq!(find_metadata(&other_reader).unwrap().mean_qscore, Some(14.5));
+        let paf_reader = StackReader::new(get_test_data_path("fake-genome.paf")).unwrap();
+        assert!(paf_reader.set_sequencing_summary(Arc::new(read_test_summary())).is_err());
     }
 }
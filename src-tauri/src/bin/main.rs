@@ -1,24 +1,52 @@
 #![cfg_attr(all(not(debug_assertions), target_os = "windows"), windows_subsystem = "windows")]
 
 use anyhow::Result;
-use tauri::{Manager, WindowMenuEvent};
+use tauri::{AppHandle, Manager, RunEvent, WindowMenuEvent};
 use tauri_plugin_log::fern::colors::{Color, ColoredLevelConfig};
 use tauri_plugin_log::LogTarget;
 
-use gensketch_lib::interface::backend::Backend;
+use gensketch_lib::file_formats::remote::set_remote_env_vars;
+use gensketch_lib::interface::backend::{find_safe_mode_arg, read_startup_user_config, Backend};
 // TODO Figure out why I need to import these __cmd__ functions manually. This started happening
 // when I switched from a binary to a library crate.
 use gensketch_lib::interface::commands::{
-    __cmd__add_alignment_track, __cmd__add_split, __cmd__get_alignments, __cmd__get_focused_region,
-    __cmd__get_focused_sequence, __cmd__get_grid_focus, __cmd__get_reference_sequence,
-    __cmd__get_splits, __cmd__get_user_config, __cmd__initialize, __cmd__pan_focused_split,
-    __cmd__update_focused_region, __cmd__update_grid_focus,
+    __cmd__add_alignment_track, __cmd__add_interval_track, __cmd__add_remote_track,
+    __cmd__add_split, __cmd__check_softclip_contaminants, __cmd__compare_split_pileups,
+    __cmd__export_session, __cmd__find_mate, __cmd__focus_next_split, __cmd__focus_previous_split,
+    __cmd__get_alignments, __cmd__get_api_schema_version, __cmd__get_binned_coverage,
+    __cmd__get_coverage, __cmd__get_diff_labels, __cmd__get_fastq_reads, __cmd__get_focused_region,
+    __cmd__get_focused_sequence, __cmd__get_grid_focus, __cmd__get_insertion_sequence,
+    __cmd__get_pileup, __cmd__get_read_details, __cmd__get_read_length_histogram,
+    __cmd__get_reads_at, __cmd__get_reference_sequence, __cmd__get_sequence_chunk,
+    __cmd__get_sequence_logo, __cmd__get_splits, __cmd__get_unmapped_mate, __cmd__get_user_config,
+    __cmd__get_view_summary, __cmd__initialize, __cmd__list_registry_genomes,
+    __cmd__load_reference, __cmd__load_sequencing_summary, __cmd__open_reference,
+    __cmd__open_session, __cmd__pan_focused_split, __cmd__recover_default_state,
+    __cmd__remove_host_auth, __cmd__select_registry_genome, __cmd__set_alignment_field_mask,
+    __cmd__set_api_schema_version, __cmd__set_hide_duplicates, __cmd__set_hide_secondary,
+    __cmd__set_hide_supplementary, __cmd__set_host_auth, __cmd__set_track_filter,
+    __cmd__sort_alignments, __cmd__split_track_by_read_group, __cmd__split_track_by_tag,
+    __cmd__update_focused_region, __cmd__update_grid_focus, __cmd__update_split_annotation,
+    __cmd__update_track_customization,
 };
 use gensketch_lib::interface::commands::{
-    add_alignment_track, add_split, get_alignments, get_focused_region, get_focused_sequence,
-    get_grid_focus, get_reference_sequence, get_splits, get_user_config, initialize,
-    pan_focused_split, update_focused_region, update_grid_focus,
+    add_alignment_track, add_interval_track, add_remote_track, add_split,
+    check_softclip_contaminants, compare_split_pileups, export_session, find_mate,
+    focus_next_split, focus_previous_split, get_alignments, get_api_schema_version,
+    get_binned_coverage, get_coverage, get_diff_labels, get_fastq_reads, get_focused_region,
+    get_focused_sequence, get_grid_focus, get_insertion_sequence, get_pileup, get_read_details,
+    get_read_length_histogram, get_reads_at, get_reference_sequence, get_sequence_chunk,
+    get_sequence_logo, get_splits, get_unmapped_mate, get_user_config, get_view_summary,
+    initialize, list_registry_genomes, load_reference, load_sequencing_summary, open_reference,
+    open_session, pan_focused_split, recover_default_state, remove_host_auth,
+    select_registry_genome, set_alignment_field_mask, set_api_schema_version, set_hide_duplicates,
+    set_hide_secondary, set_hide_supplementary, set_host_auth, set_track_filter, sort_alignments,
+    split_track_by_read_group, split_track_by_tag, update_focused_region, update_grid_focus,
+    update_split_annotation, update_track_customization,
 };
+#[cfg(target_os = "macos")]
+use gensketch_lib::interface::open_files::handle_open_files;
+use gensketch_lib::interface::session::find_session_arg;
 use gensketch_lib::interface::system_menu::{open_files, setup_system_menu};
 
 #[cfg(debug_assertions)]
@@ -41,7 +69,21 @@ fn spawn_deadlock_detection_thread() {
     });
 }
 
+/// Save the current reference so that it's reopened the next time the app starts.
+fn save_last_reference(app: &AppHandle) {
+    if let Err(err) = app.state::<Backend>().save_last_reference() {
+        log::error!("Failed to save the last used reference: {:#}", err);
+    }
+}
+
 fn main() -> Result<()> {
+    let safe_mode = find_safe_mode_arg(std::env::args_os());
+    // The environment can only be safely modified before any threads are started
+    set_remote_env_vars(&read_startup_user_config(safe_mode)?);
+    let backend = Backend::with_safe_mode(safe_mode)?;
+    if let Some(session_path) = find_session_arg(std::env::args_os()) {
+        backend.set_startup_session(session_path);
+    }
     tauri::Builder::default()
         .plugin(
             tauri_plugin_log::Builder::new()
@@ -57,25 +99,69 @@ fn main() -> Result<()> {
                 // .level(log::LevelFilter::Warn)
                 .build(),
         )
-        .manage(Backend::new()?)
+        .manage(backend)
         .invoke_handler(tauri::generate_handler![
             add_alignment_track,
+            add_interval_track,
+            add_remote_track,
             add_split,
+            check_softclip_contaminants,
+            compare_split_pileups,
+            export_session,
+            find_mate,
+            focus_next_split,
+            focus_previous_split,
             get_alignments,
+            get_api_schema_version,
+            get_binned_coverage,
+            get_coverage,
+            get_diff_labels,
+            get_fastq_reads,
             get_focused_region,
             get_focused_sequence,
             get_grid_focus,
+            get_insertion_sequence,
+            get_pileup,
+            get_read_details,
+            get_read_length_histogram,
+            get_reads_at,
             get_reference_sequence,
+            get_sequence_chunk,
+            get_sequence_logo,
             get_splits,
+            get_unmapped_mate,
             get_user_config,
+            get_view_summary,
             initialize,
+            list_registry_genomes,
+            load_reference,
+            load_sequencing_summary,
+            open_reference,
+            open_session,
             pan_focused_split,
+            recover_default_state,
+            remove_host_auth,
+            select_registry_genome,
+            set_alignment_field_mask,
+            set_api_schema_version,
+            set_hide_duplicates,
+            set_hide_secondary,
+            set_hide_supplementary,
+            set_host_auth,
+            set_track_filter,
+            sort_alignments,
+            split_track_by_read_group,
+            split_track_by_tag,
             update_focused_region,
-            update_grid_focus
+            update_grid_focus,
+            update_split_annotation,
+            update_track_customization
         ])
         .menu(setup_system_menu()?)
         .on_menu_event(|event: WindowMenuEvent| match event.menu_item_id() {
             "quit" => {
+                // Exiting from the menu skips the exit event
+                save_last_reference(&event.window().app_handle());
                 event.window().app_handle().exit(0);
             }
             "open_file" => {
@@ -83,12 +169,22 @@ fn main() -> Result<()> {
             }
             _ => panic!("Unconfigured menu item"),
         })
-        .setup(|_| {
+        .setup(|app| {
             #[cfg(debug_assertions)]
             spawn_deadlock_detection_thread();
+            // Other platforms pass the files which the app is asked to open as arguments
+            #[cfg(target_os = "macos")]
+            handle_open_files(&app.handle());
+            #[cfg(not(target_os = "macos"))]
+            let _ = app;
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                save_last_reference(app);
+            }
+        });
     Ok(())
 }
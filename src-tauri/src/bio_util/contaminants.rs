@@ -0,0 +1,193 @@
+//! Quick k-mer based screening of sequences against known contaminants.
+//!
+//! Sequencing adapters and common cloning vector elements are bundled with the app. Screening for
+//! whole vectors, viruses or other contaminants (e.g PhiX or the full UniVec core) requires a FASTA
+//! of their sequences to be configured.
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use bio::alphabets::dna::revcomp;
+use bio::io::fasta;
+use serde::Serialize;
+
+use crate::file_formats::sam_bam::softclip::SoftClipCluster;
+
+/// Adapter sequences which are bundled with the app.
+///
+/// Users can provide their own set of sequences in FASTA format via the user config.
+const BUNDLED_ADAPTERS: &[(&str, &str)] = &[
+    ("Illumina TruSeq adapter (read 1)", "AGATCGGAAGAGCACACGTCTGAACTCCAGTCA"),
+    ("Illumina TruSeq adapter (read 2)", "AGATCGGAAGAGCGTCGTGTAGGGAAAGAGTGT"),
+    ("Illumina Nextera transposase adapter", "CTGTCTCTTATACACATCT"),
+    ("Illumina small RNA 3' adapter", "TGGAATTCTCGGGTGCCAAGG"),
+];
+
+/// Cloning vector primer sites and promoters which are bundled with the app (from UniVec core).
+///
+/// These are short, so they flag reads which run into a vector without needing whole vector
+/// sequences to be bundled.
+const BUNDLED_VECTORS: &[(&str, &str)] = &[
+    ("M13 forward (-20) primer site", "GTAAAACGACGGCCAGT"),
+    ("M13 reverse primer site", "CAGGAAACAGCTATGAC"),
+    ("T7 promoter", "TAATACGACTCACTATAGGG"),
+    ("T7 terminator", "GCTAGTTATTGCTCAGCGG"),
+    ("T3 promoter", "AATTAACCCTCACTAAAGGG"),
+    ("SP6 promoter", "ATTTAGGTGACACTATAG"),
+    ("CMV promoter forward primer site", "CGCAAATGGGCGGTAGGCGTG"),
+    ("BGH polyadenylation signal reverse primer site", "TAGAAGGCACAGTCGAGG"),
+];
+
+/// A contaminant which shares k-mers with a query sequence.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContaminantHit {
+    pub name: String,
+    /// Number of distinct k-mers in the query which are found in the contaminant.
+    pub shared_kmers: usize,
+    /// Fraction of the query's distinct k-mers which are found in the contaminant.
+    pub fraction: f64,
+}
+
+/// Result of checking the consensus sequence of a softclip cluster for contaminants.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SoftClipContaminantCheck {
+    pub cluster: SoftClipCluster,
+    pub hits: Vec<ContaminantHit>,
+}
+
+/// Index of k-mers in a set of contaminant sequences.
+///
+/// K-mers from both strands are indexed so that queries match regardless of orientation.
+#[derive(Debug)]
+pub struct ContaminantDb {
+    kmer_size: usize,
+    names: Vec<String>,
+    kmers: HashMap<Vec<u8>, Vec<usize>>,
+}
+
+impl ContaminantDb {
+    pub fn new(kmer_size: usize) -> Result<Self> {
+        if kmer_size == 0 {
+            bail!("K-mer size must be greater than zero");
+        }
+        Ok(Self { kmer_size, names: Vec::new(), kmers: HashMap::new() })
+    }
+
+    /// Initialize a database containing the bundled adapter and vector sequences.
+    pub fn bundled(kmer_size: usize) -> Result<Self> {
+        let mut db = Self::new(kmer_size)?;
+        for (name, sequence) in BUNDLED_ADAPTERS.iter().chain(BUNDLED_VECTORS) {
+            db.add(name, sequence.as_bytes());
+        }
+        Ok(db)
+    }
+
+    /// Initialize a database from the sequences in a FASTA file.
+    pub fn from_fasta<P: AsRef<Path>>(path: P, kmer_size: usize) -> Result<Self> {
+        let path = path.as_ref();
+        let reader = fasta::Reader::from_file(path).with_context(|| {
+            format!("Failed to open contaminant FASTA: {}", path.to_string_lossy())
+        })?;
+        let mut db = Self::new(kmer_size)?;
+        for record in reader.records() {
+            let record = record.with_context(|| {
+                format!("Failed to parse contaminant FASTA: {}", path.to_string_lossy())
+            })?;
+            let name = match record.desc() {
+                Some(desc) => format!("{} {}", record.id(), desc),
+                None => record.id().to_owned(),
+            };
+            db.add(&name, record.seq());
+        }
+        Ok(db)
+    }
+
+    pub fn add(&mut self, name: &str, sequence: &[u8]) {
+        let index = self.names.len();
+        self.names.push(name.to_owned());
+        let sequence = sequence.to_ascii_uppercase();
+        for strand in [sequence.clone(), revcomp(&sequence)] {
+            for kmer in strand.windows(self.kmer_size) {
+                let entry = self.kmers.entry(kmer.to_vec()).or_default();
+                if entry.last() != Some(&index) {
+                    entry.push(index);
+                }
+            }
+        }
+    }
+
+    /// Find contaminants which share k-mers with a sequence.
+    ///
+    /// Hits are sorted with the most likely source first.
+    pub fn check(&self, sequence: &[u8]) -> Vec<ContaminantHit> {
+        let sequence = sequence.to_ascii_uppercase();
+        let query_kmers: HashSet<&[u8]> = sequence.windows(self.kmer_size).collect();
+        let mut counts = vec![0; self.names.len()];
+        for kmer in query_kmers.iter() {
+            for &index in self.kmers.get(*kmer).into_iter().flatten() {
+                counts[index] += 1;
+            }
+        }
+        let mut hits: Vec<ContaminantHit> = counts
+            .into_iter()
+            .enumerate()
+            .filter(|(_, count)| *count > 0)
+            .map(|(index, count)| ContaminantHit {
+                name: self.names[index].clone(),
+                shared_kmers: count,
+                fraction: count as f64 / query_kmers.len() as f64,
+            })
+            .collect();
+        hits.sort_by(|a, b| b.shared_kmers.cmp(&a.shared_kmers).then_with(|| a.name.cmp(&b.name)));
+        hits
+    }
+
+    /// Check the consensus sequence of each softclip cluster for contaminants.
+    pub fn check_softclips(&self, clusters: Vec<SoftClipCluster>) -> Vec<SoftClipContaminantCheck> {
+        clusters
+            .into_iter()
+            .map(|cluster| {
+                let hits = self.check(cluster.consensus.as_bytes());
+                SoftClipContaminantCheck { cluster, hits }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_check_bundled_contaminants() {
+        let db = ContaminantDb::bundled(12).unwrap();
+        let hits = db.check(b"agatcggaagagcacacgtctg");
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].name, "Illumina TruSeq adapter (read 1)");
+        assert_eq!(hits[0].shared_kmers, 11);
+        assert_eq!(hits[0].fraction, 1.0);
+        assert!(db.check(b"ACGTACGTACGTACGTACGT").is_empty());
+    }
+
+    #[test]
+    fn test_check_reverse_complement() {
+        let db = ContaminantDb::bundled(12).unwrap();
+        let hits = db.check(&revcomp(b"CTGTCTCTTATACACATCT"));
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].name, "Illumina Nextera transposase adapter");
+        assert_eq!(hits[0].fraction, 1.0);
+    }
+
+    #[test]
+    fn test_check_bundled_vectors() {
+        let db = ContaminantDb::bundled(12).unwrap();
+        let hits = db.check(b"GGCCGCTAATACGACTCACTATAGGGAGACC");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].name, "T7 promoter");
+        assert_eq!(hits[0].shared_kmers, 9);
+    }
+}
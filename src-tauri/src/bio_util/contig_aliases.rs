@@ -0,0 +1,119 @@
+//! Matching up contig names which differ between files (e.g chr1 in a UCSC reference and 1 in a BAM
+//! aligned to an Ensembl reference).
+use std::collections::BTreeMap;
+
+use parking_lot::{const_rwlock, RwLock};
+
+/// User-defined aliases, set from the user config.
+static CONTIG_ALIASES: RwLock<ContigAliases> = const_rwlock(ContigAliases::new());
+
+/// Get the name which a contig is compared by, using the built-in UCSC/Ensembl naming rules.
+///
+/// The UCSC "chr" prefix is dropped and the mitochondrial contig is always named MT (chrM in
+/// UCSC).
+fn canonical_name(name: &str) -> String {
+    let has_chr_prefix =
+        name.len() > 3 && name.get(..3).map_or(false, |prefix| prefix.eq_ignore_ascii_case("chr"));
+    let name = if has_chr_prefix { &name[3..] } else { name };
+    if name.eq_ignore_ascii_case("M") || name.eq_ignore_ascii_case("MT") {
+        return "MT".to_owned();
+    }
+    name.to_owned()
+}
+
+/// Decides which contig names refer to the same contig.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ContigAliases {
+    /// Map from an alias to the name of the contig it refers to (e.g NC_012920.1 -> chrM).
+    overrides: BTreeMap<String, String>,
+}
+
+impl ContigAliases {
+    pub const fn new() -> Self {
+        Self { overrides: BTreeMap::new() }
+    }
+
+    pub fn with_overrides(overrides: BTreeMap<String, String>) -> Self {
+        Self { overrides }
+    }
+
+    fn canonical_name(&self, name: &str) -> String {
+        canonical_name(self.overrides.get(name).map(String::as_str).unwrap_or(name))
+    }
+
+    pub fn is_alias(&self, name: &str, other_name: &str) -> bool {
+        name == other_name || self.canonical_name(name) == self.canonical_name(other_name)
+    }
+
+    /// Find the name among a file's contig names which refers to the same contig as `name`.
+    ///
+    /// An exact match is always preferred over an alias.
+    pub fn resolve<'a, I: IntoIterator<Item = &'a str>>(
+        &self,
+        name: &str,
+        candidates: I,
+    ) -> Option<&'a str> {
+        let mut alias = None;
+        for candidate in candidates {
+            if candidate == name {
+                return Some(candidate);
+            }
+            if alias.is_none() && self.is_alias(name, candidate) {
+                alias = Some(candidate);
+            }
+        }
+        alias
+    }
+}
+
+/// Set the user-defined contig aliases, which are used in addition to the built-in aliases.
+pub fn configure_contig_aliases(overrides: &BTreeMap<String, String>) {
+    *CONTIG_ALIASES.write() = ContigAliases::with_overrides(overrides.clone());
+}
+
+/// Find the name among a file's contig names which refers to the same contig as `name`, using the
+/// configured aliases.
+pub fn resolve_contig_name<'a, I: IntoIterator<Item = &'a str>>(
+    name: &str,
+    candidates: I,
+) -> Option<&'a str> {
+    CONTIG_ALIASES.read().resolve(name, candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_builtin_aliases() {
+        let aliases = ContigAliases::new();
+        assert!(aliases.is_alias("chr1", "1"));
+        assert!(aliases.is_alias("CHRX", "X"));
+        assert!(aliases.is_alias("chrM", "MT"));
+        assert!(aliases.is_alias("M", "chrMT"));
+        assert!(!aliases.is_alias("chr1", "11"));
+        assert!(!aliases.is_alias("chr", "1"));
+        assert!(!aliases.is_alias("mt", "euk_genes"));
+    }
+
+    #[test]
+    fn test_user_aliases() {
+        let overrides = [("NC_012920.1".to_owned(), "chrM".to_owned())].into_iter().collect();
+        let aliases = ContigAliases::with_overrides(overrides);
+        assert!(aliases.is_alias("NC_012920.1", "MT"));
+        assert!(aliases.is_alias("chrM", "NC_012920.1"));
+        assert!(!aliases.is_alias("NC_012920.1", "chr1"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let aliases = ContigAliases::new();
+        let names = ["1", "chr1", "2", "MT"];
+        assert_eq!(aliases.resolve("chr1", names), Some("chr1"));
+        assert_eq!(aliases.resolve("chr2", names), Some("2"));
+        assert_eq!(aliases.resolve("chrM", names), Some("MT"));
+        assert_eq!(aliases.resolve("chr3", names), None);
+    }
+}
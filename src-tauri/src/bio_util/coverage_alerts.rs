@@ -0,0 +1,226 @@
+//! Checking the sequencing depth of regions against user-defined thresholds.
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::sam_bam::pileup::Pileup;
+
+/// A region (e.g an exon in a capture panel) which is checked by a coverage rule.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CoverageTarget {
+    pub region: GenomicRegion,
+    pub name: Option<String>,
+}
+
+/// Read coverage targets from a BED file.
+///
+/// Only the first four columns (sequence name, start, end and optionally name) are used.
+pub fn read_bed_targets<P: AsRef<Path>>(path: P) -> Result<Vec<CoverageTarget>> {
+    let path = path.as_ref();
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open BED file: {}", path.to_string_lossy()))?;
+    let mut targets = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let columns: Vec<&str> = line.split('\t').collect();
+        if columns.len() < 3 {
+            bail!("Expected at least 3 columns in BED line {}", i + 1);
+        }
+        let parse_coord = |column: &str| {
+            column
+                .parse::<u64>()
+                .with_context(|| format!("Invalid coordinate {} in BED line {}", column, i + 1))
+        };
+        let region =
+            GenomicRegion::new(columns[0], parse_coord(columns[1])?, parse_coord(columns[2])?)
+                .with_context(|| format!("Invalid interval in BED line {}", i + 1))?;
+        let name = columns.get(3).map(|name| name.to_string());
+        targets.push(CoverageTarget { region, name });
+    }
+    Ok(targets)
+}
+
+/// A region in which the depth falls below a coverage rule's threshold.
+#[serde_as]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityAlert {
+    pub rule_name: String,
+    /// Name of the target which triggered the alert (if the rule has named targets).
+    pub target_name: Option<String>,
+    /// Part of the target (or pileup region, for rules without targets) which was checked.
+    pub region: GenomicRegion,
+    pub min_depth: u32,
+    /// Lowest depth observed in the region.
+    pub observed_depth: u32,
+    /// Number of positions in the region with depth below the threshold.
+    #[serde_as(as = "DisplayFromStr")]
+    pub low_depth_positions: u64,
+}
+
+/// Alert if the depth falls below a minimum anywhere in a set of targets.
+///
+/// Rules without targets apply to every position.
+#[derive(Clone, Debug)]
+pub struct CoverageRule {
+    pub name: String,
+    pub min_depth: u32,
+    targets: Option<Vec<CoverageTarget>>,
+}
+
+impl CoverageRule {
+    pub fn new<S: Into<String>>(name: S, min_depth: u32) -> Self {
+        Self { name: name.into(), min_depth, targets: None }
+    }
+
+    /// Restrict the rule to a set of targets.
+    pub fn with_targets(mut self, targets: Vec<CoverageTarget>) -> Self {
+        self.targets = Some(targets);
+        self
+    }
+
+    /// Restrict the rule to the targets in a BED file.
+    pub fn with_targets_from_bed<P: AsRef<Path>>(self, path: P) -> Result<Self> {
+        Ok(self.with_targets(read_bed_targets(path)?))
+    }
+
+    /// Parts of a region which are checked by the rule, along with the name of the target.
+    fn get_checked_regions(&self, region: &GenomicRegion) -> Vec<(GenomicRegion, Option<&str>)> {
+        let targets = match &self.targets {
+            Some(targets) => targets,
+            None => return vec![(region.clone(), None)],
+        };
+        targets
+            .iter()
+            .filter(|target| target.region.seq_name == region.seq_name)
+            .filter_map(|target| {
+                let start = target.region.start().max(region.start());
+                let end = target.region.end().min(region.end());
+                if start >= end {
+                    return None;
+                }
+                let overlap = GenomicRegion::new(&region.seq_name, start, end).ok()?;
+                Some((overlap, target.name.as_deref()))
+            })
+            .collect()
+    }
+
+    /// Check the depth of each targeted position in a pileup.
+    pub fn evaluate(&self, pileup: &Pileup) -> Vec<QualityAlert> {
+        let mut alerts = Vec::new();
+        for (region, target_name) in self.get_checked_regions(&pileup.region) {
+            let offset = (region.start() - pileup.region.start()) as usize;
+            let depths: Vec<u32> = pileup.columns[offset..offset + region.len() as usize]
+                .iter()
+                .map(|column| column.depth())
+                .collect();
+            let low_depth_positions =
+                depths.iter().filter(|depth| **depth < self.min_depth).count() as u64;
+            if low_depth_positions == 0 {
+                continue;
+            }
+            alerts.push(QualityAlert {
+                rule_name: self.name.clone(),
+                target_name: target_name.map(|name| name.to_owned()),
+                region,
+                min_depth: self.min_depth,
+                observed_depth: depths.into_iter().min().unwrap_or_default(),
+                low_depth_positions,
+            });
+        }
+        alerts
+    }
+}
+
+/// Evaluate a set of coverage rules against a pileup.
+pub fn evaluate_coverage_rules(rules: &[CoverageRule], pileup: &Pileup) -> Vec<QualityAlert> {
+    rules.iter().flat_map(|rule| rule.evaluate(pileup)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    fn pileup_with_depths(region: &GenomicRegion, depths: &[u32]) -> Pileup {
+        let mut pileup = Pileup::new(region);
+        for (column, depth) in pileup.columns.iter_mut().zip(depths) {
+            column.a = *depth;
+        }
+        pileup
+    }
+
+    #[test]
+    fn test_read_bed_targets() {
+        let targets = read_bed_targets(get_test_data_path("fake-genome.targets.bed")).unwrap();
+        assert_eq!(targets.len(), 3);
+        assert_eq!(targets[0].region, GenomicRegion::new("euk_genes", 100, 110).unwrap());
+        assert_eq!(targets[0].name.as_deref(), Some("exon1"));
+        assert_eq!(targets[2].region, GenomicRegion::new("mt", 0, 500).unwrap());
+        assert_eq!(targets[2].name, None);
+    }
+
+    #[test]
+    fn test_evaluate_without_targets() {
+        let region = GenomicRegion::new("mt", 10, 15).unwrap();
+        let pileup = pileup_with_depths(&region, &[30, 30, 5, 19, 40]);
+        let alerts = CoverageRule::new("low depth", 20).evaluate(&pileup);
+        assert_eq!(
+            alerts,
+            vec![QualityAlert {
+                rule_name: "low depth".to_owned(),
+                target_name: None,
+                region,
+                min_depth: 20,
+                observed_depth: 5,
+                low_depth_positions: 2,
+            }]
+        );
+        let pileup = pileup_with_depths(&pileup.region, &[20; 5]);
+        assert_eq!(CoverageRule::new("low depth", 20).evaluate(&pileup), vec![]);
+    }
+
+    #[test]
+    fn test_evaluate_with_targets() {
+        let targets = vec![
+            CoverageTarget {
+                region: GenomicRegion::new("mt", 8, 12).unwrap(),
+                name: Some("exon1".to_owned()),
+            },
+            CoverageTarget { region: GenomicRegion::new("mt", 13, 14).unwrap(), name: None },
+            CoverageTarget { region: GenomicRegion::new("mt", 20, 30).unwrap(), name: None },
+            CoverageTarget { region: GenomicRegion::new("chr1", 10, 15).unwrap(), name: None },
+        ];
+        let rule = CoverageRule::new("panel", 20).with_targets(targets);
+        let region = GenomicRegion::new("mt", 10, 15).unwrap();
+        let pileup = pileup_with_depths(&region, &[10, 30, 5, 30, 40]);
+        let alerts = evaluate_coverage_rules(&[rule], &pileup);
+        // Only the on-screen part of the first target is checked, the low depth position at 12
+        // isn't in any target
+        assert_eq!(
+            alerts,
+            vec![QualityAlert {
+                rule_name: "panel".to_owned(),
+                target_name: Some("exon1".to_owned()),
+                region: GenomicRegion::new("mt", 10, 12).unwrap(),
+                min_depth: 20,
+                observed_depth: 10,
+                low_depth_positions: 1,
+            }]
+        );
+    }
+}
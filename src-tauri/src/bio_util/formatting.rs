@@ -0,0 +1,81 @@
+//! Human-friendly formatting of genomic coordinates.
+//!
+//! The same rules are implemented in the frontend (src/lib/genomicCoordinates.ts) so that
+//! coordinates look the same wherever they're displayed.
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+
+/// Units used to abbreviate lengths, largest first.
+const LENGTH_UNITS: [(u64, &str); 3] = [(1_000_000_000, "Gb"), (1_000_000, "Mb"), (1_000, "kb")];
+
+/// Format a position with comma separated thousands (e.g 1,234,567).
+pub fn format_position(pos: u64) -> String {
+    let digits = pos.to_string();
+    // The leading group has 1-3 digits, every following group has exactly 3
+    let (head, tail) = digits.split_at((digits.len() - 1) % 3 + 1);
+    let mut formatted = head.to_owned();
+    for i in (0..tail.len()).step_by(3) {
+        formatted.push(',');
+        formatted.push_str(&tail[i..i + 3]);
+    }
+    formatted
+}
+
+/// Format a length with a unit suffix, rounded to one decimal place (e.g 850 bp, 1.2 Mb).
+pub fn format_length(len: u64) -> String {
+    if len >= 1_000 {
+        for (scale, unit) in LENGTH_UNITS {
+            // Round before choosing the unit so that e.g 999,999bp is shown as 1 Mb not 1000 kb
+            let tenths = (len as f64 / scale as f64 * 10.0).round();
+            if tenths >= 10.0 {
+                let value = format!("{:.1}", tenths / 10.0);
+                return format!("{} {}", value.trim_end_matches(".0"), unit);
+            }
+        }
+    }
+    format!("{} bp", len)
+}
+
+/// Format a region using 1-indexed coordinates (e.g chr1:1,001-2,000).
+pub fn format_region(region: &GenomicRegion) -> String {
+    let start = region.start() + 1;
+    if start == region.end() {
+        format!("{}:{}", region.seq_name, format_position(start))
+    } else {
+        format!("{}:{}-{}", region.seq_name, format_position(start), format_position(region.end()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_format_position() {
+        assert_eq!(format_position(0), "0");
+        assert_eq!(format_position(999), "999");
+        assert_eq!(format_position(1000), "1,000");
+        assert_eq!(format_position(1234567), "1,234,567");
+        assert_eq!(format_position(248956422), "248,956,422");
+    }
+
+    #[test]
+    fn test_format_length() {
+        assert_eq!(format_length(0), "0 bp");
+        assert_eq!(format_length(999), "999 bp");
+        assert_eq!(format_length(1000), "1 kb");
+        assert_eq!(format_length(16569), "16.6 kb");
+        assert_eq!(format_length(999_999), "1 Mb");
+        assert_eq!(format_length(1_234_567), "1.2 Mb");
+        assert_eq!(format_length(3_100_000_000), "3.1 Gb");
+    }
+
+    #[test]
+    fn test_format_region() {
+        let region = GenomicRegion::new("chr1", 1000, 2000000).unwrap();
+        assert_eq!(format_region(&region), "chr1:1,001-2,000,000");
+        let region = GenomicRegion::new("chr1", 1233, 1234).unwrap();
+        assert_eq!(format_region(&region), "chr1:1,234");
+    }
+}
@@ -0,0 +1,325 @@
+//! Reference genomes which can be downloaded on demand rather than being supplied by the user.
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use dashmap::DashSet;
+use flate2::read::MultiGzDecoder;
+use serde::Serialize;
+
+use crate::file_formats::fasta::index::{build_fasta_index, has_fasta_index};
+use crate::file_formats::remote_cache::hfile::RemoteReader;
+use crate::interface::events::{
+    EmitEvent, Event, GenomeDownloadProgressPayload, GenomeDownloadStatus,
+};
+
+/// Progress events are emitted each time this many more bytes have been downloaded.
+const PROGRESS_INTERVAL: u64 = 16 * 1024 * 1024;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A reference genome which can be downloaded.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryGenome {
+    /// Short identifier (e.g hg38), which is also used as the name of the downloaded FASTA.
+    pub id: String,
+    pub name: String,
+    pub species: String,
+    /// URL of the FASTA file, which may be gzipped.
+    pub fasta_url: String,
+}
+
+impl RegistryGenome {
+    fn new(id: &str, name: &str, species: &str, fasta_url: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            species: species.to_owned(),
+            fasta_url: fasta_url.to_owned(),
+        }
+    }
+}
+
+/// Genomes which are available to download, hosted by the UCSC genome browser.
+pub fn get_registry_genomes() -> Vec<RegistryGenome> {
+    let ucsc_url = |id: &str| {
+        format!("https://hgdownload.soe.ucsc.edu/goldenPath/{}/bigZips/{}.fa.gz", id, id)
+    };
+    [
+        ("hg38", "GRCh38/hg38", "Human"),
+        ("hg19", "GRCh37/hg19", "Human"),
+        ("hs1", "T2T-CHM13v2.0/hs1", "Human"),
+        ("mm39", "GRCm39/mm39", "Mouse"),
+        ("mm10", "GRCm38/mm10", "Mouse"),
+    ]
+    .into_iter()
+    .map(|(id, name, species)| RegistryGenome::new(id, name, species, &ucsc_url(id)))
+    .collect()
+}
+
+/// A registry genome and whether it has been downloaded.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryGenomeStatus {
+    #[serde(flatten)]
+    pub genome: RegistryGenome,
+    /// Path of the downloaded FASTA, if it has been downloaded and indexed.
+    pub path: Option<PathBuf>,
+    pub is_downloading: bool,
+}
+
+/// Copy a file, decompressing it if it's gzipped.
+///
+/// # Arguments
+///
+/// * `on_progress` - Called with the number of (compressed) bytes read so far.
+fn copy_decompressed<R: Read, W: Write, F: FnMut(u64) -> Result<()>>(
+    reader: R,
+    writer: &mut W,
+    mut on_progress: F,
+) -> Result<()> {
+    let mut reader = BufReader::new(ProgressReader::new(reader, &mut on_progress));
+    let is_gzipped = reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+    if is_gzipped {
+        io::copy(&mut MultiGzDecoder::new(&mut reader), writer)?;
+    } else {
+        io::copy(&mut reader, writer)?;
+    }
+    reader.into_inner().flush_progress()
+}
+
+/// Reports how many bytes have been read every PROGRESS_INTERVAL bytes.
+struct ProgressReader<R, F> {
+    inner: R,
+    on_progress: F,
+    num_read: u64,
+    last_reported: u64,
+}
+
+impl<R: Read, F: FnMut(u64) -> Result<()>> ProgressReader<R, F> {
+    fn new(inner: R, on_progress: F) -> Self {
+        Self { inner, on_progress, num_read: 0, last_reported: 0 }
+    }
+
+    fn flush_progress(&mut self) -> Result<()> {
+        self.last_reported = self.num_read;
+        (self.on_progress)(self.num_read)
+    }
+}
+
+impl<R: Read, F: FnMut(u64) -> Result<()>> Read for ProgressReader<R, F> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let num_read = self.inner.read(buffer)?;
+        self.num_read += num_read as u64;
+        if self.num_read - self.last_reported >= PROGRESS_INTERVAL {
+            self.flush_progress().map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        }
+        Ok(num_read)
+    }
+}
+
+/// Downloads registry genomes to a local directory.
+#[derive(Debug)]
+pub struct GenomeRegistry {
+    pub dir: PathBuf,
+    genomes: Vec<RegistryGenome>,
+    /// Ids of the genomes which are currently being downloaded.
+    downloading: DashSet<String>,
+}
+
+impl GenomeRegistry {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self::with_genomes(dir, get_registry_genomes())
+    }
+
+    pub fn with_genomes<P: Into<PathBuf>>(dir: P, genomes: Vec<RegistryGenome>) -> Self {
+        Self { dir: dir.into(), genomes, downloading: DashSet::new() }
+    }
+
+    pub fn get_genome(&self, genome_id: &str) -> Result<&RegistryGenome> {
+        self.genomes
+            .iter()
+            .find(|genome| genome.id == genome_id)
+            .with_context(|| format!("Unknown genome: {}", genome_id))
+    }
+
+    /// Path which a genome's FASTA is downloaded to.
+    pub fn get_fasta_path(&self, genome: &RegistryGenome) -> PathBuf {
+        self.dir.join(&genome.id).join(format!("{}.fa", genome.id))
+    }
+
+    /// A genome is only considered downloaded once it has also been indexed.
+    pub fn is_downloaded(&self, genome: &RegistryGenome) -> bool {
+        let fasta_path = self.get_fasta_path(genome);
+        fasta_path.is_file() && has_fasta_index(&fasta_path)
+    }
+
+    pub fn list_genomes(&self) -> Vec<RegistryGenomeStatus> {
+        self.genomes
+            .iter()
+            .map(|genome| RegistryGenomeStatus {
+                genome: genome.clone(),
+                path: Some(self.get_fasta_path(genome)).filter(|_| self.is_downloaded(genome)),
+                is_downloading: self.downloading.contains(&genome.id),
+            })
+            .collect()
+    }
+
+    /// Download and index a genome unless it has already been downloaded, returning the path of
+    /// its FASTA.
+    pub fn download<E: EmitEvent>(&self, event_emitter: &E, genome_id: &str) -> Result<PathBuf> {
+        let genome = self.get_genome(genome_id)?;
+        let fasta_path = self.get_fasta_path(genome);
+        if self.is_downloaded(genome) {
+            return Ok(fasta_path);
+        }
+        if !self.downloading.insert(genome.id.clone()) {
+            bail!("{} is already being downloaded", genome.name);
+        }
+        let emit_progress = |status, downloaded_bytes, total_bytes| {
+            event_emitter.emit(
+                Event::GenomeDownloadProgress,
+                GenomeDownloadProgressPayload {
+                    genome_id: &genome.id,
+                    status,
+                    downloaded_bytes,
+                    total_bytes,
+                },
+            )
+        };
+        let result = self.download_and_index(genome, &fasta_path, &emit_progress);
+        self.downloading.remove(&genome.id);
+        match result {
+            Ok(_) => {
+                emit_progress(GenomeDownloadStatus::Finished, 0, None)?;
+                Ok(fasta_path)
+            }
+            Err(err) => {
+                emit_progress(GenomeDownloadStatus::Failed, 0, None)?;
+                Err(err.context(format!("Failed to download {}", genome.name)))
+            }
+        }
+    }
+
+    fn download_and_index<F: Fn(GenomeDownloadStatus, u64, Option<u64>) -> Result<()>>(
+        &self,
+        genome: &RegistryGenome,
+        fasta_path: &Path,
+        emit_progress: F,
+    ) -> Result<()> {
+        log::info!("Downloading {} from {}", genome.name, genome.fasta_url);
+        let mut reader = RemoteReader::open(&genome.fasta_url)?;
+        let total_bytes = reader.size()?;
+        emit_progress(GenomeDownloadStatus::Started, 0, total_bytes)?;
+        let dir = fasta_path.parent().context("Genome path has no parent directory")?;
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        // Downloaded to a temporary file so that partial downloads are never mistaken for a genome
+        let mut part_path = fasta_path.as_os_str().to_owned();
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
+        let mut writer = BufWriter::new(File::create(&part_path)?);
+        let result = copy_decompressed(&mut reader, &mut writer, |downloaded_bytes| {
+            emit_progress(GenomeDownloadStatus::Downloading, downloaded_bytes, total_bytes)
+        })
+        .and_then(|_| Ok(writer.flush()?));
+        drop(writer);
+        if let Err(err) = result {
+            if let Err(remove_err) = fs::remove_file(&part_path) {
+                log::warn!("Failed to remove {}: {}", part_path.display(), remove_err);
+            }
+            return Err(err);
+        }
+        fs::rename(&part_path, fasta_path)?;
+        emit_progress(GenomeDownloadStatus::Indexing, 0, total_bytes)?;
+        build_fasta_index(fasta_path)?;
+        log::info!("Downloaded {} to {}", genome.name, fasta_path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::interface::events::StubEventEmitter;
+    use crate::paths::get_test_data_path;
+
+    fn init_test_registry() -> GenomeRegistry {
+        let fasta_url = get_test_data_path("fake-genome.fa.gz").to_string_lossy().to_string();
+        let genome = RegistryGenome::new("fake", "Fake genome", "None", &fasta_url);
+        GenomeRegistry::with_genomes(
+            std::env::temp_dir().join(Uuid::new_v4().to_string()),
+            vec![genome],
+        )
+    }
+
+    #[test]
+    fn test_registry_genome_ids_are_unique() {
+        let genomes = get_registry_genomes();
+        let ids: DashSet<String> = genomes.iter().map(|genome| genome.id.clone()).collect();
+        assert_eq!(ids.len(), genomes.len());
+    }
+
+    #[test]
+    fn test_copy_decompressed() {
+        let expected = fs::read(get_test_data_path("fake-genome.fa")).unwrap();
+        for filename in ["fake-genome.fa", "fake-genome.fa.gz"] {
+            let file = File::open(get_test_data_path(filename)).unwrap();
+            let mut contents = Vec::new();
+            let mut progress = Vec::new();
+            copy_decompressed(file, &mut contents, |num_read| {
+                progress.push(num_read);
+                Ok(())
+            })
+            .unwrap();
+            assert_eq!(contents, expected);
+            let file_size = fs::metadata(get_test_data_path(filename)).unwrap().len();
+            assert_eq!(progress, vec![file_size]);
+        }
+    }
+
+    #[test]
+    fn test_download_genome() {
+        let registry = init_test_registry();
+        let event_emitter = StubEventEmitter::new();
+        assert_eq!(registry.list_genomes()[0].path, None);
+        let fasta_path = registry.download(&event_emitter, "fake").unwrap();
+        assert_eq!(
+            fs::read(&fasta_path).unwrap(),
+            fs::read(get_test_data_path("fake-genome.fa")).unwrap()
+        );
+        assert!(has_fasta_index(&fasta_path));
+        assert_eq!(registry.list_genomes()[0].path, Some(fasta_path.clone()));
+        let payload = event_emitter.pop_event(&Event::GenomeDownloadProgress);
+        assert_eq!(payload["status"], "started");
+        let total_bytes = fs::metadata(get_test_data_path("fake-genome.fa.gz")).unwrap().len();
+        assert_eq!(payload["totalBytes"], total_bytes.to_string());
+        let payload = event_emitter.pop_until(&Event::GenomeDownloadProgress);
+        assert_eq!(payload["status"], "downloading");
+        assert_eq!(payload["downloadedBytes"], total_bytes.to_string());
+        assert_eq!(event_emitter.pop_until(&Event::GenomeDownloadProgress)["status"], "indexing");
+        assert_eq!(event_emitter.pop_until(&Event::GenomeDownloadProgress)["status"], "finished");
+        // Genomes are only downloaded once
+        registry.download(&event_emitter, "fake").unwrap();
+        assert!(event_emitter.calls.lock().is_empty());
+        assert!(registry.download(&event_emitter, "missing").is_err());
+        fs::remove_dir_all(&registry.dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_download() {
+        let mut registry = init_test_registry();
+        registry.genomes[0].fasta_url = "/nonexistent/genome.fa.gz".to_owned();
+        let event_emitter = StubEventEmitter::new();
+        assert!(registry.download(&event_emitter, "fake").is_err());
+        assert_eq!(event_emitter.pop_until(&Event::GenomeDownloadProgress)["status"], "failed");
+        let genome = registry.get_genome("fake").unwrap();
+        assert!(!registry.get_fasta_path(genome).exists());
+        assert!(!registry.list_genomes()[0].is_downloading);
+    }
+}
@@ -1,9 +1,54 @@
 use std::convert::From;
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use anyhow::{bail, Error, Result};
-use serde::{Deserialize, Serialize};
-use serde_with::{serde_as, DisplayFromStr};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::serde_as;
+
+/// Whether interval coordinates are serialized as JSON numbers rather than strings.
+static NUMERIC_COORDINATES: AtomicBool = AtomicBool::new(false);
+
+/// Set whether interval coordinates are serialized as JSON numbers. Coordinates are serialized as
+/// strings by default since JSON numbers can't represent every u64 exactly. Both forms are accepted
+/// when deserializing.
+///
+/// This is only set from the user config. Tests shouldn't change it since every test which
+/// serializes a coordinate would race with them; use write_coordinate instead.
+pub fn configure_numeric_coordinates(enabled: bool) {
+    NUMERIC_COORDINATES.store(enabled, Ordering::SeqCst);
+}
+
+fn serialize_coordinate<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    write_coordinate(*value, NUMERIC_COORDINATES.load(Ordering::SeqCst), serializer)
+}
+
+/// Serialize a coordinate as a number or as a string.
+fn write_coordinate<S: Serializer>(
+    value: u64,
+    numeric: bool,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    if numeric {
+        serializer.serialize_u64(value)
+    } else {
+        serializer.collect_str(&value)
+    }
+}
+
+fn deserialize_coordinate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Coordinate {
+        Number(u64),
+        String(String),
+    }
+
+    match Coordinate::deserialize(deserializer)? {
+        Coordinate::Number(value) => Ok(value),
+        Coordinate::String(value) => value.parse().map_err(serde::de::Error::custom),
+    }
+}
 
 /// A set of genomic coordinates.
 ///
@@ -63,13 +108,12 @@ impl fmt::Display for GenomicRegion {
 }
 
 // Simple interval with a start/end coordinate.
-#[serde_as]
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GenomicInterval {
-    #[serde_as(as = "DisplayFromStr")]
+    #[serde(serialize_with = "serialize_coordinate", deserialize_with = "deserialize_coordinate")]
     pub start: u64,
-    #[serde_as(as = "DisplayFromStr")]
+    #[serde(serialize_with = "serialize_coordinate", deserialize_with = "deserialize_coordinate")]
     pub end: u64,
 }
 
@@ -154,4 +198,27 @@ mod tests {
         let expected_interval = GenomicInterval::new(1, 10000).unwrap();
         assert_eq!(*region.interval(), expected_interval);
     }
+
+    #[test]
+    fn test_deserialize_string_or_numeric_coordinates() {
+        let expected_interval = GenomicInterval::new(1, 10000).unwrap();
+        let interval: GenomicInterval =
+            serde_json::from_str(r#"{"start": "1", "end": "10000"}"#).unwrap();
+        assert_eq!(interval, expected_interval);
+        let interval: GenomicInterval =
+            serde_json::from_str(r#"{"start": 1, "end": 10000}"#).unwrap();
+        assert_eq!(interval, expected_interval);
+    }
+
+    #[test]
+    fn test_write_coordinate() {
+        let value = u64::MAX;
+        let numeric = write_coordinate(value, true, serde_json::value::Serializer).unwrap();
+        assert_eq!(numeric, serde_json::json!(value));
+        let string = write_coordinate(value, false, serde_json::value::Serializer).unwrap();
+        assert_eq!(string, serde_json::json!(value.to_string()));
+        for json in [numeric, string] {
+            assert_eq!(deserialize_coordinate(json).unwrap(), value);
+        }
+    }
 }
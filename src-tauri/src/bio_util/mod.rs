@@ -1,3 +1,12 @@
+pub mod contaminants;
+pub mod contig_aliases;
+pub mod coverage_alerts;
+pub mod formatting;
+pub mod genome_registry;
 pub mod genomic_coordinates;
+pub mod pileup_comparison;
+pub mod reference_check;
 pub mod refseq;
 pub mod sequence;
+pub mod sequence_logo;
+pub mod view_summary;
@@ -0,0 +1,134 @@
+//! Comparison of allele content between the pileups of two (homologous) genomic regions.
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::sam_bam::pileup::{Pileup, PileupColumn};
+
+/// Maximum length of region for which pileups can be compared.
+pub const MAX_PILEUP_COMPARISON_WINDOW: u64 = 10000;
+
+/// Minimum frequency at which an allele must be observed to be considered present in a column.
+pub const MIN_ALLELE_FREQUENCY: f64 = 0.2;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Allele {
+    A,
+    C,
+    G,
+    T,
+    Del,
+}
+
+/// Get the alleles which are observed in a pileup column at >= MIN_ALLELE_FREQUENCY.
+pub fn present_alleles(column: &PileupColumn) -> Vec<Allele> {
+    let depth = column.depth();
+    if depth == 0 {
+        return Vec::new();
+    }
+    [
+        (Allele::A, column.a),
+        (Allele::C, column.c),
+        (Allele::G, column.g),
+        (Allele::T, column.t),
+        (Allele::Del, column.del),
+    ]
+    .into_iter()
+    .filter(|(_, count)| *count as f64 / depth as f64 >= MIN_ALLELE_FREQUENCY)
+    .map(|(allele, _)| allele)
+    .collect()
+}
+
+/// A pair of aligned pileup columns with different allele content.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PileupDifference {
+    #[serde_as(as = "DisplayFromStr")]
+    pub position_a: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub position_b: u64,
+    pub alleles_a: Vec<Allele>,
+    pub alleles_b: Vec<Allele>,
+    pub column_a: PileupColumn,
+    pub column_b: PileupColumn,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PileupComparison {
+    pub region_a: GenomicRegion,
+    pub region_b: GenomicRegion,
+    pub differences: Vec<PileupDifference>,
+}
+
+impl PileupComparison {
+    /// Compare the allele content of two pileups column by column.
+    ///
+    /// Columns are aligned by their offset from the start of each region. If the regions have
+    /// different lengths then only the overlapping offsets are compared. Columns without coverage
+    /// in either pileup are skipped.
+    pub fn new(pileup_a: &Pileup, pileup_b: &Pileup) -> Self {
+        let differences = pileup_a
+            .columns
+            .iter()
+            .zip(pileup_b.columns.iter())
+            .filter(|(column_a, column_b)| column_a.depth() > 0 && column_b.depth() > 0)
+            .filter_map(|(column_a, column_b)| {
+                let alleles_a = present_alleles(column_a);
+                let alleles_b = present_alleles(column_b);
+                if alleles_a == alleles_b {
+                    return None;
+                }
+                Some(PileupDifference {
+                    position_a: column_a.position,
+                    position_b: column_b.position,
+                    alleles_a,
+                    alleles_b,
+                    column_a: column_a.clone(),
+                    column_b: column_b.clone(),
+                })
+            })
+            .collect();
+        Self { region_a: pileup_a.region.clone(), region_b: pileup_b.region.clone(), differences }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn gen_pileup(start: u64, counts: &[[u32; 4]]) -> Pileup {
+        let region = GenomicRegion::new("X", start, start + counts.len() as u64).unwrap();
+        let mut pileup = Pileup::new(&region);
+        for (column, [a, c, g, t]) in pileup.columns.iter_mut().zip(counts.iter()) {
+            column.a = *a;
+            column.c = *c;
+            column.g = *g;
+            column.t = *t;
+        }
+        pileup
+    }
+
+    #[test]
+    fn test_present_alleles_ignores_low_frequency_alleles() {
+        let pileup = gen_pileup(0, &[[45, 0, 5, 50]]);
+        assert_eq!(present_alleles(&pileup.columns[0]), vec![Allele::A, Allele::T]);
+    }
+
+    #[test]
+    fn test_compare_pileups() {
+        let pileup_a = gen_pileup(100, &[[10, 0, 0, 0], [0, 10, 0, 0], [0, 0, 0, 0]]);
+        let pileup_b = gen_pileup(5000, &[[10, 0, 0, 1], [0, 5, 0, 5], [0, 0, 10, 0]]);
+        let comparison = PileupComparison::new(&pileup_a, &pileup_b);
+        assert_eq!(comparison.differences.len(), 1);
+        let difference = &comparison.differences[0];
+        assert_eq!(difference.position_a, 101);
+        assert_eq!(difference.position_b, 5001);
+        assert_eq!(difference.alleles_a, vec![Allele::C]);
+        assert_eq!(difference.alleles_b, vec![Allele::C, Allele::T]);
+    }
+}
@@ -0,0 +1,166 @@
+//! Checking that the sequences which a file was aligned to match the loaded reference sequence.
+//!
+//! Alignments against a different build of the reference (e.g hg19 reads on hg38) are otherwise
+//! shown with nonsensical diffs.
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::bio_util::contig_aliases::resolve_contig_name;
+use crate::bio_util::refseq::ReferenceSequence;
+use crate::file_formats::remote::is_remote;
+use crate::file_formats::sam_bam::header::{read_seq_dictionary, SeqDictEntry};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ContigMismatchKind {
+    /// The sequence isn't present on the reference.
+    Missing,
+    Length,
+    /// The sequence has the same length as the reference's but a different MD5 checksum.
+    Md5,
+}
+
+/// A sequence in a file's header which doesn't match the reference.
+#[serde_as]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContigMismatch {
+    pub seq_name: String,
+    pub kind: ContigMismatchKind,
+    #[serde_as(as = "DisplayFromStr")]
+    pub length: u64,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub reference_length: Option<u64>,
+}
+
+/// Find the sequence dictionary of a reference sequence file (e.g `genome.dict` for `genome.fa`)
+/// if one exists.
+pub fn find_reference_dictionary<P: AsRef<Path>>(reference_path: P) -> Option<PathBuf> {
+    let reference_path = reference_path.as_ref();
+    if is_remote(reference_path) || reference_path.is_dir() {
+        return None;
+    }
+    let uncompressed_path = match reference_path.extension() {
+        Some(extension) if extension == "gz" => reference_path.with_extension(""),
+        _ => reference_path.to_owned(),
+    };
+    let mut with_suffix: OsString = uncompressed_path.as_os_str().to_owned();
+    with_suffix.push(".dict");
+    [uncompressed_path.with_extension("dict"), PathBuf::from(with_suffix)]
+        .into_iter()
+        .find(|path| path.exists())
+}
+
+/// Get the MD5 checksums of a reference's sequences from its sequence dictionary (if it has one).
+fn get_reference_md5s(reference: &ReferenceSequence) -> Result<BTreeMap<String, String>> {
+    let dict_path = match find_reference_dictionary(&reference.path) {
+        Some(dict_path) => dict_path,
+        None => return Ok(BTreeMap::new()),
+    };
+    let md5s = read_seq_dictionary(dict_path)?
+        .into_iter()
+        .filter_map(|entry| Some((entry.name, entry.md5?)))
+        .collect();
+    Ok(md5s)
+}
+
+/// Compare the sequences in a file's header against the reference.
+///
+/// Sequence names are matched using contig aliases (e.g chr1 matches 1). MD5 checksums are only
+/// compared if they're present in both the header and the reference's sequence dictionary.
+pub fn check_reference_contigs(
+    reference: &ReferenceSequence,
+    dictionary: &[SeqDictEntry],
+) -> Result<Vec<ContigMismatch>> {
+    let reference_md5s = get_reference_md5s(reference)?;
+    let mut mismatches = Vec::new();
+    for entry in dictionary {
+        let seq_names = reference.seq_lengths.keys().map(String::as_str);
+        let mismatch = |kind, reference_length| ContigMismatch {
+            seq_name: entry.name.clone(),
+            kind,
+            length: entry.length,
+            reference_length,
+        };
+        let reference_seq_name = match resolve_contig_name(&entry.name, seq_names) {
+            Some(seq_name) => seq_name,
+            None => {
+                mismatches.push(mismatch(ContigMismatchKind::Missing, None));
+                continue;
+            }
+        };
+        let reference_length = reference.seq_lengths[reference_seq_name];
+        if entry.length != reference_length {
+            mismatches.push(mismatch(ContigMismatchKind::Length, Some(reference_length)));
+            continue;
+        }
+        if let (Some(md5), Some(reference_md5)) =
+            (&entry.md5, reference_md5s.get(reference_seq_name))
+        {
+            if !md5.eq_ignore_ascii_case(reference_md5) {
+                mismatches.push(mismatch(ContigMismatchKind::Md5, Some(reference_length)));
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::bio_util::refseq::get_default_reference;
+
+    fn entry(name: &str, length: u64, md5: Option<&str>) -> SeqDictEntry {
+        SeqDictEntry { name: name.to_owned(), length, md5: md5.map(str::to_owned) }
+    }
+
+    #[test]
+    fn test_check_reference_contigs() {
+        let reference = get_default_reference().unwrap();
+        let dictionary = [entry("euk_genes", 7185, None), entry("chrM", 16569, Some("abc"))];
+        assert_eq!(check_reference_contigs(&reference, &dictionary).unwrap(), vec![]);
+
+        let dictionary = [entry("euk_genes", 7000, None), entry("chr1", 1000, None)];
+        let expected = vec![
+            ContigMismatch {
+                seq_name: "euk_genes".to_owned(),
+                kind: ContigMismatchKind::Length,
+                length: 7000,
+                reference_length: Some(7185),
+            },
+            ContigMismatch {
+                seq_name: "chr1".to_owned(),
+                kind: ContigMismatchKind::Missing,
+                length: 1000,
+                reference_length: None,
+            },
+        ];
+        assert_eq!(check_reference_contigs(&reference, &dictionary).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_check_reference_md5s() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir(&dir).unwrap();
+        let fasta_path = dir.join("genome.fa");
+        std::fs::write(&fasta_path, b">chr1\nACGT\n").unwrap();
+        std::fs::write(dir.join("genome.fa.fai"), b"chr1\t4\t6\t4\t5\n").unwrap();
+        std::fs::write(dir.join("genome.dict"), b"@SQ\tSN:chr1\tLN:4\tM5:abc\n").unwrap();
+        assert_eq!(find_reference_dictionary(&fasta_path), Some(dir.join("genome.dict")));
+        let reference = ReferenceSequence::new("genome".to_owned(), &fasta_path).unwrap();
+        let mismatches =
+            check_reference_contigs(&reference, &[entry("1", 4, Some("def"))]).unwrap();
+        let matches = check_reference_contigs(&reference, &[entry("1", 4, Some("ABC"))]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].kind, ContigMismatchKind::Md5);
+        assert_eq!(matches.unwrap(), vec![]);
+    }
+}
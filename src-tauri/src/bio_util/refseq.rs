@@ -1,18 +1,33 @@
 use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::ops::Bound::{Excluded, Unbounded};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::{Context, Result};
-use serde::Serialize;
+use anyhow::{bail, Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 // use tauri::api::path::local_data_dir;
 
+/// References longer than this (in total) are never preloaded into memory.
+pub const MAX_PRELOAD_LENGTH: u64 = 50_000_000;
+
+use crate::bio_util::contig_aliases::resolve_contig_name;
 use crate::bio_util::genomic_coordinates::GenomicRegion;
 use crate::bio_util::sequence::SequenceView;
-use crate::file_formats::fasta::reader::FastaReader;
+use crate::file_formats::fasta::reader::{FastaReader, PreloadedFasta, SequenceReader};
+use crate::file_formats::fasta::remote::RemoteFastaReader;
+use crate::file_formats::remote::is_remote;
 
 /// Generate a map from sequence name to sequence length from an indexed fasta file.
 fn map_sequence_lengths<P: Into<PathBuf>>(path: P) -> Result<BTreeMap<String, u64>> {
-    let reader = FastaReader::new(path)?;
+    let pathbuf: PathBuf = path.into();
+    if is_remote(&pathbuf) {
+        return Ok(RemoteFastaReader::new(pathbuf)?.seq_lengths().clone());
+    }
+    let reader = FastaReader::new(pathbuf)?;
     let sizes = reader.sequences().iter().map(|seq| (seq.name.clone(), seq.len)).collect();
     Ok(sizes)
 }
@@ -27,6 +42,9 @@ pub struct ReferenceSequence {
     #[serde_as(as = "BTreeMap<_, DisplayFromStr>")]
     pub seq_lengths: BTreeMap<String, u64>,
     pub default_focused_region: GenomicRegion,
+    /// Entire reference sequence, if it has been loaded into memory.
+    #[serde(skip_serializing)]
+    preloaded: Option<Arc<PreloadedFasta>>,
 }
 
 impl ReferenceSequence {
@@ -36,11 +54,67 @@ impl ReferenceSequence {
         let (default_seq_name, default_seq_len) =
             seq_lengths.first_key_value().context("Reference sequence file is empty")?;
         let default_focused_region = GenomicRegion::new(default_seq_name, 0, *default_seq_len)?;
-        Ok(Self { name, path: pathbuf, seq_lengths, default_focused_region })
+        Ok(Self { name, path: pathbuf, seq_lengths, default_focused_region, preloaded: None })
+    }
+
+    /// Focus a region other than the start of the first sequence when the reference is opened.
+    pub fn with_default_focused_region(mut self, region: GenomicRegion) -> Result<Self> {
+        let seq_length = self.get_seq_length(&region.seq_name)?;
+        if region.end() > seq_length || region.is_empty() {
+            bail!(
+                "Region {} is outside of sequence {} (length {})",
+                region,
+                region.seq_name,
+                seq_length
+            );
+        }
+        self.default_focused_region = region;
+        Ok(self)
+    }
+
+    /// Load the entire reference sequence into memory so that readers don't need to access the
+    /// file.
+    pub fn preload(&mut self) -> Result<()> {
+        if is_remote(&self.path) {
+            bail!("Remote reference sequence {} can't be preloaded", self.name);
+        }
+        let total_length: u64 = self.seq_lengths.values().sum();
+        if total_length > MAX_PRELOAD_LENGTH {
+            bail!(
+                "Reference sequence {} is too large to preload ({}bp > {}bp)",
+                self.name,
+                total_length,
+                MAX_PRELOAD_LENGTH
+            );
+        }
+        self.preloaded = Some(Arc::new(PreloadedFasta::new(&self.path)?));
+        Ok(())
     }
 
-    pub fn get_reader(&self) -> Result<FastaReader> {
-        FastaReader::new(&self.path)
+    /// Preload the reference sequence if requested, falling back to reading from file if it can't
+    /// be preloaded.
+    pub fn with_preload(mut self, preload: bool) -> Self {
+        if preload {
+            if let Err(err) = self.preload() {
+                log::warn!("Reading reference sequence from file: {:#}", err);
+            }
+        }
+        self
+    }
+
+    pub fn is_preloaded(&self) -> bool {
+        self.preloaded.is_some()
+    }
+
+    pub fn get_reader(&self) -> Result<SequenceReader> {
+        let reader = match &self.preloaded {
+            Some(fasta) => SequenceReader::Preloaded(Arc::clone(fasta)),
+            None if is_remote(&self.path) => {
+                SequenceReader::Remote(Box::new(Mutex::new(RemoteFastaReader::new(&self.path)?)))
+            }
+            None => SequenceReader::Indexed(Box::new(Mutex::new(FastaReader::new(&self.path)?))),
+        };
+        Ok(reader)
     }
 
     pub fn get_seq_length(&self, seq_name: &str) -> Result<u64> {
@@ -52,6 +126,34 @@ impl ReferenceSequence {
         })
     }
 
+    /// Rename a region's sequence to the name used in the reference if the region was given with
+    /// an alias (e.g chr1 on a reference which names it 1). Unknown sequences are left unchanged.
+    pub fn resolve_region(&self, mut region: GenomicRegion) -> GenomicRegion {
+        let seq_names = self.seq_lengths.keys().map(String::as_str);
+        if let Some(seq_name) = resolve_contig_name(&region.seq_name, seq_names) {
+            if seq_name != region.seq_name {
+                region.seq_name = seq_name.to_owned();
+            }
+        }
+        region
+    }
+
+    /// Name of the sequence following `seq_name` in the reference (if any).
+    pub fn next_seq_name(&self, seq_name: &str) -> Option<&String> {
+        self.seq_lengths
+            .range::<str, _>((Excluded(seq_name), Unbounded))
+            .next()
+            .map(|(name, _)| name)
+    }
+
+    /// Name of the sequence preceding `seq_name` in the reference (if any).
+    pub fn prev_seq_name(&self, seq_name: &str) -> Option<&String> {
+        self.seq_lengths
+            .range::<str, _>((Unbounded, Excluded(seq_name)))
+            .next_back()
+            .map(|(name, _)| name)
+    }
+
     pub fn read_sequence(&self, region: &GenomicRegion) -> Result<SequenceView> {
         let sequence = self.get_reader()?.read(region)?;
         Ok(sequence)
@@ -64,9 +166,75 @@ fn dir_contains(dir: &Path, filename: &str) -> bool {
     path.exists()
 }
 
-/// Get the reference sequence which is loaded automatically on startup
+/// The most recently used reference sequence, which is restored when the app is next started.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LastReference {
+    pub name: String,
+    pub path: PathBuf,
+    pub focused_region: Option<GenomicRegion>,
+}
+
+impl LastReference {
+    /// Read the last reference from a file. Returns None if it has never been saved.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open {}", path.to_string_lossy()))?;
+        let last_reference = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Failed to parse {}", path.to_string_lossy()))?;
+        Ok(Some(last_reference))
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create {}", path.to_string_lossy()))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Open the reference sequence, focusing the saved region if it's still valid.
+    pub fn load(&self) -> Result<ReferenceSequence> {
+        let reference = ReferenceSequence::new(self.name.clone(), &self.path)?;
+        let focused_region = match &self.focused_region {
+            Some(region) => region.clone(),
+            None => return Ok(reference),
+        };
+        match reference.with_default_focused_region(focused_region) {
+            Ok(reference) => Ok(reference),
+            Err(err) => {
+                log::warn!("Not restoring last focused region: {:#}", err);
+                ReferenceSequence::new(self.name.clone(), &self.path)
+            }
+        }
+    }
+}
+
+/// Get the reference sequence to open on startup.
+///
+/// Restores the most recently used reference (saved at `last_reference_path`), falling back to the
+/// default reference if there isn't one or it can no longer be opened (e.g because it was moved).
+pub fn get_startup_reference<P: AsRef<Path>>(last_reference_path: P) -> Result<ReferenceSequence> {
+    let last_reference = LastReference::read(last_reference_path).and_then(|last_reference| {
+        last_reference.map(|last_reference| last_reference.load()).transpose()
+    });
+    match last_reference {
+        Ok(Some(reference)) => return Ok(reference),
+        Ok(None) => {}
+        Err(err) => log::warn!("Failed to restore the last used reference: {:#}", err),
+    }
+    get_default_reference()
+}
+
+/// Get the reference sequence which is loaded automatically if no other reference is available
 pub fn get_default_reference() -> Result<ReferenceSequence> {
-    // TODO cache path from previous session
     // TODO Try redownload if missing?
     // TODO Need to make 100 % sure we can load a reference here. May need multiple fallbacks.
 
@@ -99,6 +267,53 @@ mod tests {
         assert_eq!(path_end, vec!("fake-genome.fa", "test_data"));
     }
 
+    #[test]
+    pub fn test_restore_last_reference() {
+        let last_reference_path =
+            std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        // Falls back to the default reference if nothing has been saved
+        let reference = get_startup_reference(&last_reference_path).unwrap();
+        assert_eq!(reference.name, "HG19");
+
+        let focused_region = GenomicRegion::new("mt", 100, 200).unwrap();
+        let last_reference = LastReference {
+            name: "fake".to_owned(),
+            path: get_test_data_path("fake-genome.fa.gz"),
+            focused_region: Some(focused_region.clone()),
+        };
+        last_reference.write(&last_reference_path).unwrap();
+        assert_eq!(LastReference::read(&last_reference_path).unwrap(), Some(last_reference));
+        let reference = get_startup_reference(&last_reference_path).unwrap();
+        assert_eq!(reference.name, "fake");
+        assert_eq!(reference.default_focused_region, focused_region);
+        fs::remove_file(&last_reference_path).unwrap();
+    }
+
+    #[test]
+    pub fn test_restore_invalid_last_reference() {
+        let last_reference_path =
+            std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        let mut last_reference = LastReference {
+            name: "fake".to_owned(),
+            path: get_test_data_path("fake-genome.fa"),
+            focused_region: Some(GenomicRegion::new("mt", 100, 20000).unwrap()),
+        };
+        last_reference.write(&last_reference_path).unwrap();
+        // Regions which are no longer valid are dropped
+        let reference = get_startup_reference(&last_reference_path).unwrap();
+        assert_eq!(reference.name, "fake");
+        assert_eq!(
+            reference.default_focused_region,
+            GenomicRegion::new("euk_genes", 0, 7185).unwrap()
+        );
+        last_reference.path = get_test_data_path("missing.fa");
+        last_reference.write(&last_reference_path).unwrap();
+        assert_eq!(get_startup_reference(&last_reference_path).unwrap().name, "HG19");
+        fs::write(&last_reference_path, "{").unwrap();
+        assert_eq!(get_startup_reference(&last_reference_path).unwrap().name, "HG19");
+        fs::remove_file(&last_reference_path).unwrap();
+    }
+
     #[test]
     pub fn test_map_sequence_lengths() {
         let path = get_test_data_path("fake-genome.fa");
@@ -107,4 +322,33 @@ mod tests {
         let result = map_sequence_lengths(path).unwrap();
         assert_eq!(result, expected);
     }
+
+    #[test]
+    pub fn test_resolve_region() {
+        let reference = get_default_reference().unwrap();
+        let region = GenomicRegion::new("chrmt", 0, 10).unwrap();
+        assert_eq!(reference.resolve_region(region).seq_name, "mt");
+        let region = GenomicRegion::new("chr1", 0, 10).unwrap();
+        assert_eq!(reference.resolve_region(region.clone()), region);
+    }
+
+    #[test]
+    pub fn test_adjacent_seq_names() {
+        let refseq = get_default_reference().unwrap();
+        assert_eq!(refseq.next_seq_name("euk_genes").unwrap(), "mt");
+        assert_eq!(refseq.next_seq_name("mt"), None);
+        assert_eq!(refseq.prev_seq_name("mt").unwrap(), "euk_genes");
+        assert_eq!(refseq.prev_seq_name("euk_genes"), None);
+    }
+
+    #[test]
+    pub fn test_preload_reference_sequence() {
+        let mut refseq = get_default_reference().unwrap();
+        let region = GenomicRegion::new("mt", 0, 20).unwrap();
+        let expected = refseq.read_sequence(&region).unwrap().to_string().unwrap();
+        refseq.preload().unwrap();
+        assert!(refseq.is_preloaded());
+        assert!(matches!(refseq.get_reader().unwrap(), SequenceReader::Preloaded(_)));
+        assert_eq!(refseq.read_sequence(&region).unwrap().to_string().unwrap(), expected);
+    }
 }
@@ -18,10 +18,24 @@ impl SequenceView {
         SequenceView { sequence, offset }
     }
 
+    /// Start coordinate of the subsequence within the parent sequence.
+    pub fn start(&self) -> u64 {
+        self.offset
+    }
+
+    /// End coordinate (exclusive) of the subsequence within the parent sequence.
+    pub fn end(&self) -> u64 {
+        self.offset + self.sequence.len() as u64
+    }
+
     pub fn contains(&self, pos: u64) -> bool {
         pos >= self.offset && pos - self.offset < self.sequence.len() as u64
     }
 
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.sequence
+    }
+
     pub fn to_string(&self) -> Result<String> {
         Ok(String::from_utf8(self.sequence.to_owned())?)
     }
@@ -0,0 +1,123 @@
+//! Sequence logo data (per-position information content) computed from nucleotide counts.
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::sam_bam::pileup::Pileup;
+
+/// Maximum length of region for which a sequence logo can be computed.
+pub const MAX_SEQUENCE_LOGO_WINDOW: u64 = 200;
+
+const NUCLEOTIDES: [char; 4] = ['A', 'C', 'G', 'T'];
+
+/// The maximum possible information content (in bits) for a DNA alphabet.
+const MAX_INFORMATION_CONTENT: f64 = 2.0;
+
+/// A single letter in a sequence logo column.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogoLetter {
+    pub base: char,
+    pub frequency: f64,
+    /// Height of the letter in bits (frequency * information content).
+    pub height: f64,
+}
+
+/// A single position in a sequence logo.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SequenceLogoColumn {
+    #[serde_as(as = "DisplayFromStr")]
+    pub position: u64,
+    pub depth: u32,
+    pub information_content: f64,
+    /// Letters sorted by ascending height (i.e the order in which they should be stacked).
+    pub letters: Vec<LogoLetter>,
+}
+
+impl SequenceLogoColumn {
+    /// Compute information content from A/C/G/T counts at a single position.
+    ///
+    /// Uses the small-sample correction from Schneider et al. (1986) so that low coverage columns
+    /// are not reported as highly conserved.
+    pub fn from_counts(position: u64, counts: [u32; 4]) -> Self {
+        let depth: u32 = counts.iter().sum();
+        if depth == 0 {
+            return Self { position, depth, information_content: 0.0, letters: Vec::new() };
+        }
+        let frequencies = counts.map(|count| count as f64 / depth as f64);
+        let entropy: f64 =
+            frequencies.iter().filter(|freq| **freq > 0.0).map(|freq| -freq * freq.log2()).sum();
+        let small_sample_correction =
+            (NUCLEOTIDES.len() as f64 - 1.0) / (2.0 * std::f64::consts::LN_2 * depth as f64);
+        let information_content =
+            (MAX_INFORMATION_CONTENT - (entropy + small_sample_correction)).max(0.0);
+        let mut letters: Vec<LogoLetter> = NUCLEOTIDES
+            .iter()
+            .zip(frequencies.iter())
+            .filter(|(_, freq)| **freq > 0.0)
+            .map(|(base, freq)| LogoLetter {
+                base: *base,
+                frequency: *freq,
+                height: freq * information_content,
+            })
+            .collect();
+        letters.sort_by(|a, b| a.height.total_cmp(&b.height));
+        Self { position, depth, information_content, letters }
+    }
+}
+
+/// Sequence logo data for a small genomic region.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SequenceLogo {
+    pub region: GenomicRegion,
+    pub columns: Vec<SequenceLogoColumn>,
+}
+
+impl From<&Pileup> for SequenceLogo {
+    fn from(pileup: &Pileup) -> Self {
+        let columns = pileup
+            .columns
+            .iter()
+            .map(|column| SequenceLogoColumn::from_counts(column.position, column.base_counts()))
+            .collect();
+        Self { region: pileup.region.clone(), columns }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_empty_column_has_no_information() {
+        let column = SequenceLogoColumn::from_counts(10, [0, 0, 0, 0]);
+        assert_eq!(column.information_content, 0.0);
+        assert!(column.letters.is_empty());
+    }
+
+    #[test]
+    fn test_conserved_column_approaches_two_bits() {
+        let column = SequenceLogoColumn::from_counts(10, [0, 0, 1000, 0]);
+        assert!(column.information_content > 1.99);
+        assert_eq!(column.letters.len(), 1);
+        assert_eq!(column.letters[0].base, 'G');
+    }
+
+    #[test]
+    fn test_uniform_column_has_no_information() {
+        let column = SequenceLogoColumn::from_counts(10, [25, 25, 25, 25]);
+        assert_eq!(column.information_content, 0.0);
+    }
+
+    #[test]
+    fn test_letters_sorted_by_height() {
+        let column = SequenceLogoColumn::from_counts(10, [80, 0, 20, 0]);
+        let bases: Vec<char> = column.letters.iter().map(|letter| letter.base).collect();
+        assert_eq!(bases, vec!['G', 'A']);
+    }
+}
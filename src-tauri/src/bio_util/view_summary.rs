@@ -0,0 +1,410 @@
+//! Textual summaries of what is shown in a track (e.g for screen readers or for pasting into case
+//! notes).
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::bio_util::formatting::{format_length, format_region};
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::bio_util::pileup_comparison::{present_alleles, Allele};
+use crate::bio_util::sequence::SequenceView;
+use crate::file_formats::sam_bam::aligned_read::{AlignedPair, DiscordantRead};
+use crate::file_formats::sam_bam::pileup::{Pileup, PileupColumn};
+
+/// Minimum read depth at a position for a variant to be reported there.
+pub const MIN_VARIANT_DEPTH: u32 = 5;
+
+/// Maximum number of variants which are described in the summary text.
+pub const MAX_DESCRIBED_VARIANTS: usize = 10;
+
+/// Discordant reads which are further apart than this are put in separate clusters.
+pub const DISCORDANT_CLUSTER_DISTANCE: u64 = 1000;
+
+/// Minimum number of discordant reads with mates on the same contig to form a cluster.
+pub const MIN_DISCORDANT_CLUSTER_READS: usize = 2;
+
+/// A non-reference allele which is observed at a high frequency.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotableVariant {
+    /// Span of the variant on the reference. Adjacent deletion columns are merged into a single
+    /// variant.
+    pub region: GenomicRegion,
+    pub reference: String,
+    pub allele: Allele,
+    /// Number of reads with the allele, at the first position of the variant.
+    pub count: u32,
+    pub depth: u32,
+}
+
+/// A group of nearby discordant reads whose mates are aligned to the same contig.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscordantCluster {
+    /// Span of the reads in the cluster.
+    pub region: GenomicRegion,
+    /// Span of the mates of the reads in the cluster.
+    pub mate_region: GenomicRegion,
+    pub num_reads: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewSummary {
+    pub region: GenomicRegion,
+    pub min_depth: u32,
+    pub max_depth: u32,
+    pub mean_depth: f64,
+    pub variants: Vec<NotableVariant>,
+    pub discordant_clusters: Vec<DiscordantCluster>,
+    /// Plain text description of all of the above.
+    pub text: String,
+}
+
+fn allele_count(column: &PileupColumn, allele: Allele) -> u32 {
+    match allele {
+        Allele::A => column.a,
+        Allele::C => column.c,
+        Allele::G => column.g,
+        Allele::T => column.t,
+        Allele::Del => column.del,
+    }
+}
+
+fn reference_allele(base: u8) -> Option<Allele> {
+    match base.to_ascii_uppercase() {
+        b'A' => Some(Allele::A),
+        b'C' => Some(Allele::C),
+        b'G' => Some(Allele::G),
+        b'T' => Some(Allele::T),
+        _ => None,
+    }
+}
+
+/// Find the non-reference alleles which are observed at >= MIN_ALLELE_FREQUENCY in a pileup.
+///
+/// Positions with an ambiguous reference base (e.g N) or with a depth below MIN_VARIANT_DEPTH are
+/// skipped.
+///
+/// # Arguments
+///
+/// * `refseq` - Reference sequence which spans the entirety of the pileup's region.
+pub fn find_notable_variants(
+    pileup: &Pileup,
+    refseq: &SequenceView,
+) -> Result<Vec<NotableVariant>> {
+    let mut variants: Vec<NotableVariant> = Vec::new();
+    for column in pileup.columns.iter() {
+        let depth = column.depth();
+        if depth < MIN_VARIANT_DEPTH || !refseq.contains(column.position) {
+            continue;
+        }
+        let reference_base = refseq[column.position];
+        let reference = match reference_allele(reference_base) {
+            Some(reference) => reference,
+            None => continue,
+        };
+        for allele in present_alleles(column).into_iter().filter(|allele| *allele != reference) {
+            let extends_deletion = variants.last_mut().filter(|last| {
+                allele == Allele::Del
+                    && last.allele == Allele::Del
+                    && last.region.end() == column.position
+            });
+            if let Some(deletion) = extends_deletion {
+                deletion.region.interval.end = column.position + 1;
+                deletion.reference.push(reference_base.to_ascii_uppercase() as char);
+                continue;
+            }
+            variants.push(NotableVariant {
+                region: GenomicRegion::new(
+                    &pileup.region.seq_name,
+                    column.position,
+                    column.position + 1,
+                )?,
+                reference: (reference_base.to_ascii_uppercase() as char).to_string(),
+                allele,
+                count: allele_count(column, allele),
+                depth,
+            });
+        }
+    }
+    Ok(variants)
+}
+
+/// Group the discordant reads which overlap a region by the contig their mate is aligned to and
+/// by proximity.
+///
+/// Reads within DISCORDANT_CLUSTER_DISTANCE of each other are put in the same cluster. Clusters
+/// with fewer than MIN_DISCORDANT_CLUSTER_READS reads are dropped.
+pub fn cluster_discordant_reads<'a, I: IntoIterator<Item = &'a AlignedPair>>(
+    pairs: I,
+    region: &GenomicRegion,
+) -> Result<Vec<DiscordantCluster>> {
+    let mut reads_by_mate_seq: BTreeMap<&str, Vec<&DiscordantRead>> = BTreeMap::new();
+    for pair in pairs {
+        let discordant = match pair {
+            AlignedPair::DiscordantReadKind(discordant) => discordant,
+            AlignedPair::PairedReadsKind(_) | AlignedPair::UnpairedReadKind(_) => continue,
+        };
+        if discordant.read.region.seq_name != region.seq_name
+            || !discordant.interval.overlaps(&region.interval)
+        {
+            continue;
+        }
+        if let Some(mate_pos) = &discordant.read.mate_pos {
+            reads_by_mate_seq.entry(&mate_pos.seq_name).or_default().push(discordant);
+        }
+    }
+    let mut clusters = Vec::new();
+    for (mate_seq_name, mut reads) in reads_by_mate_seq {
+        reads.sort_by_key(|discordant| discordant.interval.start);
+        // End of the reads in each group, and the reads themselves
+        let mut groups: Vec<(u64, Vec<&DiscordantRead>)> = Vec::new();
+        for discordant in reads {
+            match groups.last_mut() {
+                Some((end, group))
+                    if discordant.interval.start <= *end + DISCORDANT_CLUSTER_DISTANCE =>
+                {
+                    *end = (*end).max(discordant.interval.end);
+                    group.push(discordant);
+                }
+                _ => groups.push((discordant.interval.end, vec![discordant])),
+            }
+        }
+        for (end, group) in groups {
+            if group.len() < MIN_DISCORDANT_CLUSTER_READS {
+                continue;
+            }
+            let mates = group.iter().filter_map(|discordant| discordant.read.mate_pos.as_ref());
+            let mate_start = mates.clone().map(GenomicRegion::start).min().unwrap_or(0);
+            let mate_end = mates.map(GenomicRegion::end).max().unwrap_or(0);
+            let start = group[0].interval.start;
+            clusters.push(DiscordantCluster {
+                region: GenomicRegion::new(&region.seq_name, start, end)?,
+                mate_region: GenomicRegion::new(mate_seq_name, mate_start, mate_end)?,
+                num_reads: group.len(),
+            });
+        }
+    }
+    clusters.sort_by_key(|cluster| cluster.region.start());
+    Ok(clusters)
+}
+
+fn pluralize(count: usize, singular: &str, plural: &str) -> String {
+    format!("{} {}", count, if count == 1 { singular } else { plural })
+}
+
+fn describe_variant(variant: &NotableVariant) -> String {
+    let change = match variant.allele {
+        Allele::Del => format!("{} deletion", format_length(variant.region.len())),
+        allele => format!("{}>{:?}", variant.reference, allele),
+    };
+    let percent = 100.0 * variant.count as f64 / variant.depth as f64;
+    format!(
+        "{} at {} ({} of {} reads, {:.0}%)",
+        change,
+        format_region(&variant.region),
+        variant.count,
+        variant.depth,
+        percent
+    )
+}
+
+fn describe_discordant_cluster(cluster: &DiscordantCluster) -> String {
+    format!(
+        "{} at {} with mates at {}",
+        pluralize(cluster.num_reads, "read", "reads"),
+        format_region(&cluster.region),
+        format_region(&cluster.mate_region)
+    )
+}
+
+impl ViewSummary {
+    /// Summarize the alignments of a track in a region.
+    ///
+    /// # Arguments
+    ///
+    /// * `pileup` - Pileup of the track across the region which is summarized.
+    /// * `refseq` - Reference sequence which spans the entirety of the pileup's region.
+    /// * `pairs` - Alignments of the track, which are searched for discordant read clusters.
+    pub fn new<'a, I: IntoIterator<Item = &'a AlignedPair>>(
+        pileup: &Pileup,
+        refseq: &SequenceView,
+        pairs: I,
+    ) -> Result<Self> {
+        let region = pileup.region.clone();
+        let depths = pileup.columns.iter().map(PileupColumn::depth);
+        let min_depth = depths.clone().min().unwrap_or(0);
+        let max_depth = depths.clone().max().unwrap_or(0);
+        let mean_depth = match pileup.columns.len() {
+            0 => 0.0,
+            len => depths.map(f64::from).sum::<f64>() / len as f64,
+        };
+        let variants = find_notable_variants(pileup, refseq)?;
+        let discordant_clusters = cluster_discordant_reads(pairs, &region)?;
+
+        let mut sentences = vec![
+            format!("{} ({})", format_region(&region), format_length(region.len())),
+            if min_depth == max_depth {
+                format!("Read depth {}", min_depth)
+            } else {
+                format!("Read depth {} to {}, mean {:.1}", min_depth, max_depth, mean_depth)
+            },
+        ];
+        sentences.push(match variants.len() {
+            0 => "No notable variants".to_owned(),
+            num_variants => {
+                let mut descriptions: Vec<String> =
+                    variants.iter().take(MAX_DESCRIBED_VARIANTS).map(describe_variant).collect();
+                if num_variants > MAX_DESCRIBED_VARIANTS {
+                    descriptions
+                        .push(format!("and {} more", num_variants - MAX_DESCRIBED_VARIANTS));
+                }
+                format!(
+                    "{}: {}",
+                    pluralize(num_variants, "notable variant", "notable variants"),
+                    descriptions.join("; ")
+                )
+            }
+        });
+        sentences.push(match discordant_clusters.len() {
+            0 => "No discordant pair clusters".to_owned(),
+            num_clusters => format!(
+                "{}: {}",
+                pluralize(num_clusters, "discordant pair cluster", "discordant pair clusters"),
+                discordant_clusters
+                    .iter()
+                    .map(describe_discordant_cluster)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+        });
+        let text =
+            sentences.into_iter().map(|sentence| sentence + ".").collect::<Vec<_>>().join(" ");
+        Ok(Self { region, min_depth, max_depth, mean_depth, variants, discordant_clusters, text })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::file_formats::sam_bam::aligned_read::{AlignedRead, UnpairedRead};
+    use crate::test_util::aligned_reads::gen_read;
+
+    fn gen_pileup(start: u64, counts: &[[u32; 5]]) -> Pileup {
+        let region = GenomicRegion::new("X", start, start + counts.len() as u64).unwrap();
+        let mut pileup = Pileup::new(&region);
+        for (column, [a, c, g, t, del]) in pileup.columns.iter_mut().zip(counts.iter()) {
+            column.a = *a;
+            column.c = *c;
+            column.g = *g;
+            column.t = *t;
+            column.del = *del;
+        }
+        pileup
+    }
+
+    fn gen_mate_read(start: u64, mate_pos: Option<GenomicRegion>) -> AlignedRead {
+        let id = format!("{}-{:?}", start, mate_pos);
+        AlignedRead { mate_pos, ..gen_read(&id, start, start + 100, Vec::new()) }
+    }
+
+    fn discordant(start: u64, mate_seq_name: &str, mate_start: u64) -> AlignedPair {
+        let mate_pos = GenomicRegion::new(mate_seq_name, mate_start, mate_start + 1).unwrap();
+        AlignedPair::DiscordantReadKind(DiscordantRead::new(gen_mate_read(start, Some(mate_pos))))
+    }
+
+    #[test]
+    fn test_find_notable_variants() {
+        let refseq = SequenceView::new(b"ACGTAN".to_vec(), 100);
+        let pileup = gen_pileup(
+            100,
+            &[
+                // Reference allele only
+                [10, 0, 0, 0, 0],
+                // Too shallow
+                [0, 2, 2, 0, 0],
+                // Heterozygous SNV
+                [0, 0, 5, 5, 0],
+                // Two column deletion
+                [0, 0, 0, 4, 6],
+                [0, 0, 0, 0, 10],
+                // Ambiguous reference
+                [0, 10, 0, 0, 0],
+            ],
+        );
+        let variants = find_notable_variants(&pileup, &refseq).unwrap();
+        let expected = vec![
+            NotableVariant {
+                region: GenomicRegion::new("X", 102, 103).unwrap(),
+                reference: "G".to_owned(),
+                allele: Allele::T,
+                count: 5,
+                depth: 10,
+            },
+            NotableVariant {
+                region: GenomicRegion::new("X", 103, 105).unwrap(),
+                reference: "TA".to_owned(),
+                allele: Allele::Del,
+                count: 6,
+                depth: 10,
+            },
+        ];
+        assert_eq!(variants, expected);
+    }
+
+    #[test]
+    fn test_cluster_discordant_reads() {
+        let region = GenomicRegion::new("X", 1000, 5000).unwrap();
+        let unpaired = UnpairedRead::new(gen_mate_read(1000, None));
+        let pairs = vec![
+            AlignedPair::UnpairedReadKind(unpaired),
+            discordant(1000, "Y", 500),
+            discordant(1200, "Y", 900),
+            discordant(1300, "Z", 100),
+            // Too far from the first cluster
+            discordant(4000, "Y", 700),
+            discordant(4100, "Y", 800),
+            // Outside of the region
+            discordant(8000, "Y", 700),
+        ];
+        let clusters = cluster_discordant_reads(&pairs, &region).unwrap();
+        let expected = vec![
+            DiscordantCluster {
+                region: GenomicRegion::new("X", 1000, 1300).unwrap(),
+                mate_region: GenomicRegion::new("Y", 500, 901).unwrap(),
+                num_reads: 2,
+            },
+            DiscordantCluster {
+                region: GenomicRegion::new("X", 4000, 4200).unwrap(),
+                mate_region: GenomicRegion::new("Y", 700, 801).unwrap(),
+                num_reads: 2,
+            },
+        ];
+        assert_eq!(clusters, expected);
+    }
+
+    #[test]
+    fn test_view_summary_text() {
+        let refseq = SequenceView::new(b"ACGT".to_vec(), 1000);
+        let pileup = gen_pileup(1000, &[[10, 0, 0, 0, 0], [0, 4, 0, 6, 0], [0, 0, 0, 0, 0]]);
+        let pairs = vec![discordant(920, "Y", 500), discordant(950, "Y", 600)];
+        let summary = ViewSummary::new(&pileup, &refseq, &pairs).unwrap();
+        assert_eq!((summary.min_depth, summary.max_depth), (0, 10));
+        assert_eq!(
+            summary.text,
+            "X:1,001-1,003 (3 bp). Read depth 0 to 10, mean 6.7. 1 notable variant: C>T at \
+             X:1,002 (6 of 10 reads, 60%). 1 discordant pair cluster: 2 reads at X:921-1,050 with \
+             mates at Y:501-601."
+        );
+
+        let summary = ViewSummary::new(&gen_pileup(1000, &[[3, 0, 0, 0, 0]]), &refseq, []).unwrap();
+        assert_eq!(
+            summary.text,
+            "X:1,001 (1 bp). Read depth 3. No notable variants. No discordant pair clusters."
+        );
+    }
+}
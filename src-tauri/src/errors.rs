@@ -2,6 +2,8 @@ use std::fmt::Display;
 
 use serde::{Serialize, Serializer};
 
+use crate::interface::split::{SplitBusyError, SplitId, SplitOperation};
+
 pub type CommandResult<T, E = CommandError> = anyhow::Result<T, E>;
 
 /// Errors which are returned to the frontend
@@ -9,6 +11,7 @@ pub enum CommandError {
     // This is necessary because anyhow errors to not implement the Serialize trait. Tauri docs
     // suggest implementing lots of different error types but that feels like overkill here.
     RuntimeError(anyhow::Error),
+    SplitBusyError(SplitBusyError),
     SerializationError(serde_json::Error),
     TauriError(tauri::Error),
     ValidationError(String),
@@ -18,6 +21,7 @@ impl Display for CommandError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             CommandError::RuntimeError(error) => write!(f, "{}", error),
+            CommandError::SplitBusyError(error) => write!(f, "{}", error),
             CommandError::ValidationError(error) => write!(f, "{}", error),
             CommandError::TauriError(error) => write!(f, "{}", error),
             CommandError::SerializationError(error) => write!(f, "{}", error),
@@ -25,20 +29,49 @@ impl Display for CommandError {
     }
 }
 
+/// Split busy errors are sent as an object so that the frontend can tell which split is busy
+/// without parsing the message.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SplitBusyErrorPayload<'a> {
+    kind: &'static str,
+    split_id: &'a SplitId,
+    operation: &'a SplitOperation,
+    message: String,
+}
+
 impl Serialize for CommandError {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_str(self.to_string().as_ref())
+        match self {
+            CommandError::SplitBusyError(error) => SplitBusyErrorPayload {
+                kind: "splitBusy",
+                split_id: &error.split_id,
+                operation: &error.operation,
+                message: error.to_string(),
+            }
+            .serialize(serializer),
+            _ => serializer.serialize_str(self.to_string().as_ref()),
+        }
     }
 }
 
 impl From<anyhow::Error> for CommandError {
     fn from(inner: anyhow::Error) -> Self {
-        // Make sure to log full traceback whenever anyhow errors are converted to CommandErrors
-        log::error!("{}", inner.backtrace());
-        CommandError::RuntimeError(inner)
+        match inner.downcast::<SplitBusyError>() {
+            Ok(busy_error) => {
+                log::warn!("{}", busy_error);
+                CommandError::SplitBusyError(busy_error)
+            }
+            Err(inner) => {
+                // Make sure to log full traceback whenever anyhow errors are converted to
+                // CommandErrors
+                log::error!("{}", inner.backtrace());
+                CommandError::RuntimeError(inner)
+            }
+        }
     }
 }
 
@@ -55,3 +88,33 @@ impl From<serde_json::Error> for CommandError {
         CommandError::SerializationError(inner)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_serialize_split_busy_error() {
+        let split_id = SplitId::new();
+        let operation = SplitOperation::LoadingTrack;
+        let error = CommandError::from(anyhow::Error::new(SplitBusyError { split_id, operation }));
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({
+                "kind": "splitBusy",
+                "splitId": split_id.to_string(),
+                "operation": "loadingTrack",
+                "message": format!("Split {} is busy (loading track)", split_id),
+            })
+        );
+    }
+
+    #[test]
+    fn test_serialize_runtime_error() {
+        let error = CommandError::from(anyhow::anyhow!("Failed to read file"));
+        assert_eq!(serde_json::to_value(&error).unwrap(), json!("Failed to read file"));
+    }
+}
@@ -1,61 +1,227 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use serde::Serialize;
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
-use crate::alignments::stack::{AlignmentStack, StackId};
+use crate::alignments::stack::{AlignmentStack, PackingStrategy, StackGrouping, StackId};
 use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::magic::{sniff_file_format, SniffedFormat};
+use crate::file_formats::paf::reader::PafReader;
+use crate::file_formats::paf::record::PafRecord;
+use crate::file_formats::remote::{is_remote, parse_remote_url};
 use crate::file_formats::sam_bam::aligned_read::AlignedPair;
 use crate::file_formats::sam_bam::reader::BamReader;
+use crate::file_formats::signal::reader::SignalReader;
+use crate::file_formats::tabix::reader::TabixReader;
+use crate::file_formats::tabix::record::IntervalRecord;
 
 pub enum FileKind {
     Bam,
+    BedGraph,
     Fasta,
+    Fastq,
+    Paf,
     Sam,
+    /// Any other bgzipped, tabix-indexed interval file
+    Tabix,
+    Wig,
+}
+
+impl fmt::Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileKind::Bam => write!(f, "BAM"),
+            FileKind::BedGraph => write!(f, "bedGraph"),
+            FileKind::Fasta => write!(f, "FASTA"),
+            FileKind::Fastq => write!(f, "FASTQ"),
+            FileKind::Paf => write!(f, "PAF"),
+            FileKind::Sam => write!(f, "SAM"),
+            FileKind::Tabix => write!(f, "tabix-indexed"),
+            FileKind::Wig => write!(f, "WIG"),
+        }
+    }
 }
 
 /// Parse the filetype from the file extension
-pub fn get_file_kind<P: Into<PathBuf>>(path: P) -> Result<FileKind> {
-    let pathbuf: PathBuf = path.into();
-    let extension = pathbuf
+fn get_file_kind_from_extension(pathbuf: &Path) -> Result<FileKind> {
+    let mut extension = pathbuf
         .extension()
         .with_context(|| format!("Unable to parse filename: {:?}", pathbuf.as_os_str()))?;
+    // Bgzipped files are identified by the extension preceding .gz
+    let is_bgzipped = extension == "gz";
+    if is_bgzipped {
+        if let Some(inner_extension) = pathbuf.file_stem().map(Path::new).and_then(Path::extension)
+        {
+            extension = inner_extension;
+        }
+    }
     match extension.to_str() {
         Some("bam") => Ok(FileKind::Bam),
+        Some("bedgraph") | Some("bedGraph") | Some("bdg") => Ok(FileKind::BedGraph),
+        Some("wig") => Ok(FileKind::Wig),
+        Some("paf") => Ok(FileKind::Paf),
         Some("sam") => Ok(FileKind::Sam),
         Some("fasta") | Some("fa") | Some("ffn") | Some("faa") | Some("frn") | Some("fna") => {
             Ok(FileKind::Fasta)
         }
+        Some("fastq") | Some("fq") => Ok(FileKind::Fastq),
+        Some(_) if is_bgzipped => Ok(FileKind::Tabix),
         Some(_) | None => {
             Err(anyhow!("Unrecognized file type: {}", pathbuf.to_string_lossy().to_string()))
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+/// Parse the filetype from the file's contents, falling back to the file extension.
+///
+/// Errors if the file's contents and extension indicate different filetypes.
+pub fn get_file_kind<P: Into<PathBuf>>(path: P) -> Result<FileKind> {
+    let pathbuf: PathBuf = path.into();
+    let from_extension = get_file_kind_from_extension(&pathbuf);
+    // Remote files aren't downloaded just to check their format. The extension is taken from the
+    // URL's path so that query strings (e.g presigned URL signatures) are ignored.
+    if is_remote(&pathbuf) {
+        return get_file_kind_from_extension(Path::new(parse_remote_url(&pathbuf)?.path()));
+    }
+    let sniffed_format = match sniff_file_format(&pathbuf) {
+        Some(sniffed_format) => sniffed_format,
+        None => return from_extension,
+    };
+    let file_kind = match (sniffed_format, from_extension) {
+        (SniffedFormat::Cram | SniffedFormat::Vcf, _) => {
+            bail!("{} files are not supported: {}", sniffed_format, pathbuf.to_string_lossy())
+        }
+        (SniffedFormat::Bam, Ok(FileKind::Bam) | Err(_)) => FileKind::Bam,
+        (SniffedFormat::Fasta, Ok(FileKind::Fasta) | Err(_)) => FileKind::Fasta,
+        (SniffedFormat::Fastq, Ok(FileKind::Fastq) | Err(_)) => FileKind::Fastq,
+        (SniffedFormat::Bgzip, Err(_)) => FileKind::Tabix,
+        (
+            SniffedFormat::Bgzip,
+            Ok(file_kind @ (FileKind::BedGraph | FileKind::Paf | FileKind::Tabix | FileKind::Wig)),
+        ) => file_kind,
+        (_, Ok(file_kind)) => bail!(
+            "{} looks like a {} file but has the extension of a {} file",
+            pathbuf.to_string_lossy(),
+            sniffed_format,
+            file_kind
+        ),
+    };
+    Ok(file_kind)
+}
+
+#[derive(Clone, Debug, Serialize)]
 #[serde(untagged)]
 pub enum AlignmentStackKind {
     AlignedPairKind(AlignmentStack<AlignedPair>),
+    PafRecordKind(AlignmentStack<PafRecord>),
+    IntervalRecordKind(AlignmentStack<IntervalRecord>),
 }
 impl AlignmentStackKind {
     pub fn id(&self) -> StackId {
         match *self {
-            Self::AlignedPairKind(AlignmentStack { id, .. }) => id,
+            Self::AlignedPairKind(AlignmentStack { id, .. })
+            | Self::PafRecordKind(AlignmentStack { id, .. })
+            | Self::IntervalRecordKind(AlignmentStack { id, .. }) => id,
         }
     }
+    /// Copy the stack, assigning the copy a new id.
+    pub fn duplicate(&self) -> Self {
+        let mut stack = self.clone();
+        match &mut stack {
+            Self::AlignedPairKind(AlignmentStack { id, .. })
+            | Self::PafRecordKind(AlignmentStack { id, .. })
+            | Self::IntervalRecordKind(AlignmentStack { id, .. }) => *id = StackId::new(),
+        }
+        stack
+    }
+
+    pub fn set_layout(
+        &mut self,
+        padding: u64,
+        packing: PackingStrategy,
+        grouping: StackGrouping,
+    ) -> Result<()> {
+        match self {
+            Self::AlignedPairKind(stack) => stack.set_layout(padding, packing, grouping),
+            Self::PafRecordKind(stack) => stack.set_layout(padding, packing, grouping),
+            Self::IntervalRecordKind(stack) => stack.set_layout(padding, packing, grouping),
+        }
+    }
+
     pub fn buffered_region(&self) -> &Option<GenomicRegion> {
         match self {
-            Self::AlignedPairKind(AlignmentStack { buffered_region, .. }) => buffered_region,
+            Self::AlignedPairKind(AlignmentStack { buffered_region, .. })
+            | Self::PafRecordKind(AlignmentStack { buffered_region, .. })
+            | Self::IntervalRecordKind(AlignmentStack { buffered_region, .. }) => buffered_region,
+        }
+    }
+
+    pub fn count_alignments(&self) -> usize {
+        match self {
+            Self::AlignedPairKind(stack) => stack.count_alignments(),
+            Self::PafRecordKind(stack) => stack.count_alignments(),
+            Self::IntervalRecordKind(stack) => stack.count_alignments(),
+        }
+    }
+
+    pub fn set_max_rows(&mut self, max_rows: Option<usize>) {
+        match self {
+            Self::AlignedPairKind(stack) => stack.set_max_rows(max_rows),
+            Self::PafRecordKind(stack) => stack.set_max_rows(max_rows),
+            Self::IntervalRecordKind(stack) => stack.set_max_rows(max_rows),
+        }
+    }
+
+    pub fn suppresses_diffs(&self) -> bool {
+        match self {
+            Self::AlignedPairKind(stack) => stack.suppresses_diffs(),
+            Self::PafRecordKind(stack) => stack.suppresses_diffs(),
+            Self::IntervalRecordKind(stack) => stack.suppresses_diffs(),
         }
     }
+
+    /// Serialize a range of the stack's rows (see AlignmentStack::rows_range).
+    pub fn serialize_rows(
+        &self,
+        row_start: usize,
+        row_end: Option<usize>,
+    ) -> Result<serde_json::Value> {
+        let json = match self {
+            Self::AlignedPairKind(stack) => {
+                serde_json::to_value(stack.rows_range(row_start, row_end))
+            }
+            Self::PafRecordKind(stack) => {
+                serde_json::to_value(stack.rows_range(row_start, row_end))
+            }
+            Self::IntervalRecordKind(stack) => {
+                serde_json::to_value(stack.rows_range(row_start, row_end))
+            }
+        };
+        Ok(json?)
+    }
+
+    /// Serialize the changes made by the stack's last update (see AlignmentStack::last_delta).
+    pub fn serialize_last_delta(&self) -> Result<Option<serde_json::Value>> {
+        let delta = match self {
+            Self::AlignedPairKind(stack) => stack.last_delta().map(serde_json::to_value),
+            Self::PafRecordKind(stack) => stack.last_delta().map(serde_json::to_value),
+            Self::IntervalRecordKind(stack) => stack.last_delta().map(serde_json::to_value),
+        };
+        Ok(delta.transpose()?)
+    }
 }
 
 #[derive(Debug)]
 pub enum AlignmentReaderKind {
     BamKind(BamReader),
+    PafKind(PafReader),
+    SignalKind(SignalReader),
+    TabixKind(TabixReader),
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::paths::get_test_data_path;
     use crate::util::same_enum_variant;
 
     use super::*;
@@ -89,6 +255,60 @@ mod tests {
         check_get_file_kind(&pathbuf, FileKind::Fasta);
     }
 
+    #[test]
+    pub fn test_get_file_kind_with_fastq() {
+        check_get_file_kind(&PathBuf::from("test.fq"), FileKind::Fastq);
+        check_get_file_kind(&PathBuf::from("test.fastq.gz"), FileKind::Fastq);
+        check_get_file_kind(&get_test_data_path("fake-genome.reads.fastq"), FileKind::Fastq);
+    }
+
+    #[test]
+    pub fn test_get_file_kind_with_bgzipped_bedgraph() {
+        check_get_file_kind(&PathBuf::from("test.bedgraph.gz"), FileKind::BedGraph);
+    }
+
+    #[test]
+    pub fn test_get_file_kind_with_wig() {
+        check_get_file_kind(&PathBuf::from("test.wig"), FileKind::Wig);
+    }
+
+    #[test]
+    pub fn test_get_file_kind_with_paf() {
+        check_get_file_kind(&PathBuf::from("test.paf"), FileKind::Paf);
+    }
+
+    #[test]
+    pub fn test_get_file_kind_with_tabix() {
+        check_get_file_kind(&PathBuf::from("annotations.tsv.gz"), FileKind::Tabix);
+    }
+
+    #[test]
+    pub fn test_get_file_kind_of_remote_file() {
+        check_get_file_kind(&PathBuf::from("s3://bucket/reads.bam"), FileKind::Bam);
+        check_get_file_kind(
+            &PathBuf::from("https://data.example.org/reads.bam?X-Amz-Signature=abc.def"),
+            FileKind::Bam,
+        );
+    }
+
+    #[test]
+    pub fn test_get_file_kind_from_contents() {
+        let path = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::copy(get_test_data_path("fake-genome.tiny.bam"), &path).unwrap();
+        check_get_file_kind(&path, FileKind::Bam);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    pub fn test_get_file_kind_with_mismatched_contents() {
+        let path = std::env::temp_dir().join(format!("{}.fa", uuid::Uuid::new_v4()));
+        std::fs::copy(get_test_data_path("fake-genome.tiny.bam"), &path).unwrap();
+        let result = get_file_kind(&path);
+        std::fs::remove_file(&path).unwrap();
+        let message = result.err().unwrap().to_string();
+        assert!(message.ends_with("looks like a BAM file but has the extension of a FASTA file"));
+    }
+
     #[test]
     pub fn test_get_file_kind_with_unsupported_filetype() {
         let mut pathbuf = PathBuf::new();
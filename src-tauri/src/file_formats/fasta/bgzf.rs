@@ -0,0 +1,272 @@
+//! Random access to bgzip-compressed files using a .gzi index.
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+
+/// Length of the fixed part of a BGZF block header (up to and including XLEN).
+const BLOCK_HEADER_LENGTH: usize = 12;
+
+/// Location of the start of a BGZF block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GziEntry {
+    pub compressed_offset: u64,
+    pub uncompressed_offset: u64,
+}
+
+/// Path at which the .gzi index for a bgzipped file is expected (i.e `<file>.gzi`).
+pub fn get_gzi_path<P: AsRef<Path>>(bgzf_path: P) -> PathBuf {
+    let mut gzi_path: OsString = bgzf_path.as_ref().as_os_str().to_owned();
+    gzi_path.push(".gzi");
+    PathBuf::from(gzi_path)
+}
+
+pub fn is_bgzipped<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().extension() == Some(OsStr::new("gz"))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Read a single BGZF block, returning None at the end of the file.
+fn read_block<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0; BLOCK_HEADER_LENGTH];
+    match reader.read_exact(&mut header) {
+        Ok(()) => (),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    // gzip magic number, deflate compression and the FEXTRA flag
+    if header[..4] != [31, 139, 8, 4] {
+        return Err(invalid_data("File is not in BGZF format"));
+    }
+    let extra_length = u16::from_le_bytes([header[10], header[11]]) as usize;
+    let mut extra = vec![0; extra_length];
+    reader.read_exact(&mut extra)?;
+    // The total block size is stored in the BC subfield
+    let mut block_size = None;
+    let mut i = 0;
+    while i + 4 <= extra.len() {
+        let subfield_length = u16::from_le_bytes([extra[i + 2], extra[i + 3]]) as usize;
+        if extra[i..i + 2] == *b"BC" && subfield_length == 2 && i + 6 <= extra.len() {
+            block_size = Some(u16::from_le_bytes([extra[i + 4], extra[i + 5]]) as usize + 1);
+        }
+        i += 4 + subfield_length;
+    }
+    let block_size = block_size.ok_or_else(|| invalid_data("BGZF block is missing its size"))?;
+    let header_length = BLOCK_HEADER_LENGTH + extra_length;
+    if block_size < header_length {
+        return Err(invalid_data("BGZF block size is invalid"));
+    }
+    let mut block = Vec::with_capacity(block_size);
+    block.extend_from_slice(&header);
+    block.extend_from_slice(&extra);
+    block.resize(block_size, 0);
+    reader.read_exact(&mut block[header_length..])?;
+    Ok(Some(block))
+}
+
+/// Size of a block's uncompressed data, stored in the last 4 bytes of the block.
+fn get_uncompressed_size(block: &[u8]) -> u64 {
+    let isize_bytes: [u8; 4] = block[block.len() - 4..].try_into().unwrap_or_default();
+    u32::from_le_bytes(isize_bytes) as u64
+}
+
+/// Generate the .gzi entries for a bgzipped file by scanning its block headers.
+fn index_bgzf<R: Read>(mut reader: R) -> io::Result<Vec<GziEntry>> {
+    let mut entries = Vec::new();
+    let mut compressed_offset = 0;
+    let mut uncompressed_offset = 0;
+    while let Some(block) = read_block(&mut reader)? {
+        let uncompressed_size = get_uncompressed_size(&block);
+        // Like htslib, exclude the first block (which is implicit) and empty blocks (i.e the EOF
+        // marker).
+        if compressed_offset > 0 && uncompressed_size > 0 {
+            entries.push(GziEntry { compressed_offset, uncompressed_offset });
+        }
+        compressed_offset += block.len() as u64;
+        uncompressed_offset += uncompressed_size;
+    }
+    Ok(entries)
+}
+
+/// Build a .gzi index for a bgzipped file. Returns the path to the index.
+pub fn build_gzi_index<P: AsRef<Path>>(bgzf_path: P) -> Result<PathBuf> {
+    let bgzf_path = bgzf_path.as_ref();
+    let file = File::open(bgzf_path)
+        .with_context(|| format!("Failed to open file: {}", bgzf_path.to_string_lossy()))?;
+    let entries = index_bgzf(BufReader::new(file))
+        .with_context(|| format!("Failed to index file: {}", bgzf_path.to_string_lossy()))?;
+    let gzi_path = get_gzi_path(bgzf_path);
+    let mut writer = BufWriter::new(
+        File::create(&gzi_path)
+            .with_context(|| format!("Failed to create index: {}", gzi_path.to_string_lossy()))?,
+    );
+    writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+    for entry in entries {
+        writer.write_all(&entry.compressed_offset.to_le_bytes())?;
+        writer.write_all(&entry.uncompressed_offset.to_le_bytes())?;
+    }
+    writer.flush()?;
+    Ok(gzi_path)
+}
+
+/// Parse a .gzi index. The returned entries include the (implicit) first block.
+pub fn read_gzi_index<R: Read>(mut reader: R) -> io::Result<Vec<GziEntry>> {
+    let mut read_u64 = || -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        reader.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    };
+    let num_entries = read_u64()?;
+    let mut entries = vec![GziEntry { compressed_offset: 0, uncompressed_offset: 0 }];
+    for _ in 0..num_entries {
+        let compressed_offset = read_u64()?;
+        let uncompressed_offset = read_u64()?;
+        entries.push(GziEntry { compressed_offset, uncompressed_offset });
+    }
+    Ok(entries)
+}
+
+/// A reader over the uncompressed contents of a bgzipped file which supports seeking to
+/// uncompressed offsets.
+#[derive(Debug)]
+pub struct BgzfReader<R: Read + Seek> {
+    inner: R,
+    index: Vec<GziEntry>,
+    /// Uncompressed data of the current block
+    block: Vec<u8>,
+    /// Uncompressed offset of the start of the current block
+    block_offset: u64,
+    /// Position within the current block
+    position: usize,
+}
+
+impl BgzfReader<File> {
+    /// Open a bgzipped file. The file's .gzi index must already exist.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let gzi_path = get_gzi_path(path);
+        let gzi = File::open(&gzi_path)
+            .with_context(|| format!("Failed to open index: {}", gzi_path.to_string_lossy()))?;
+        let index = read_gzi_index(BufReader::new(gzi))
+            .with_context(|| format!("Failed to read index: {}", gzi_path.to_string_lossy()))?;
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.to_string_lossy()))?;
+        Ok(Self::new(file, index))
+    }
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    pub fn new(inner: R, index: Vec<GziEntry>) -> Self {
+        Self { inner, index, block: Vec::new(), block_offset: 0, position: 0 }
+    }
+
+    /// Decompress the next block from the underlying reader. Returns false at the end of the file.
+    fn read_next_block(&mut self) -> io::Result<bool> {
+        self.block_offset += self.block.len() as u64;
+        self.block.clear();
+        self.position = 0;
+        match read_block(&mut self.inner)? {
+            Some(block) => {
+                GzDecoder::new(&block[..]).read_to_end(&mut self.block)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Skip over empty blocks (e.g the EOF marker)
+        while self.position >= self.block.len() {
+            if !self.read_next_block()? {
+                return Ok(0);
+            }
+        }
+        let num_bytes = buf.len().min(self.block.len() - self.position);
+        buf[..num_bytes].copy_from_slice(&self.block[self.position..self.position + num_bytes]);
+        self.position += num_bytes;
+        Ok(num_bytes)
+    }
+}
+
+impl<R: Read + Seek> Seek for BgzfReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let current_offset = self.block_offset + self.position as u64;
+        let offset = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(delta) => current_offset
+                .checked_add_signed(delta)
+                .ok_or_else(|| invalid_data("Invalid seek to a negative position"))?,
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Seeking from the end of a BGZF file is not supported",
+                ))
+            }
+        };
+        let within_block =
+            offset >= self.block_offset && offset < self.block_offset + self.block.len() as u64;
+        if !within_block {
+            let entry_index =
+                self.index.partition_point(|entry| entry.uncompressed_offset <= offset);
+            let entry = self.index[entry_index.saturating_sub(1)];
+            self.inner.seek(SeekFrom::Start(entry.compressed_offset))?;
+            self.block.clear();
+            self.block_offset = entry.uncompressed_offset;
+            // Blocks are read sequentially until the one containing the offset is reached
+            while offset >= self.block_offset + self.block.len() as u64 {
+                if !self.read_next_block()? {
+                    break;
+                }
+            }
+        }
+        self.position = (offset - self.block_offset) as usize;
+        Ok(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_build_gzi_index_matches_samtools() {
+        let bgzf_path = std::env::temp_dir().join(format!("{}.fa.gz", uuid::Uuid::new_v4()));
+        std::fs::copy(get_test_data_path("fake-genome.fa.gz"), &bgzf_path).unwrap();
+        let gzi_path = build_gzi_index(&bgzf_path).unwrap();
+        let gzi = std::fs::read(&gzi_path).unwrap();
+        std::fs::remove_file(&bgzf_path).unwrap();
+        std::fs::remove_file(&gzi_path).unwrap();
+        let expected = std::fs::read(get_test_data_path("fake-genome.fa.gz.gzi")).unwrap();
+        assert_eq!(gzi, expected);
+    }
+
+    #[test]
+    fn test_seek_and_read() {
+        let expected = std::fs::read(get_test_data_path("fake-genome.fa")).unwrap();
+        let mut reader = BgzfReader::from_path(get_test_data_path("fake-genome.fa.gz")).unwrap();
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, expected);
+        for offset in [20000, 5, 16000, expected.len() as u64 - 10] {
+            let mut buf = [0; 10];
+            assert_eq!(reader.seek(SeekFrom::Start(offset)).unwrap(), offset);
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, expected[offset as usize..offset as usize + 10]);
+        }
+        reader.seek(SeekFrom::Current(-20)).unwrap();
+        let mut buf = [0; 10];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, expected[expected.len() - 20..expected.len() - 10]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Building samtools-compatible (.fai) indexes for FASTA files.
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use flate2::read::MultiGzDecoder;
+
+use crate::file_formats::fasta::bgzf::{build_gzi_index, get_gzi_path, is_bgzipped};
+
+/// A single line of a .fai file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct FaiRecord {
+    name: String,
+    length: u64,
+    /// Byte offset of the first base of the sequence
+    offset: u64,
+    /// Number of bases on each line
+    line_bases: u64,
+    /// Number of bytes on each line (including the line terminator)
+    line_width: u64,
+}
+
+/// Path at which the index for a FASTA file is expected (i.e `<file>.fai`).
+pub fn get_fai_path<P: AsRef<Path>>(fasta_path: P) -> PathBuf {
+    let mut fai_path: OsString = fasta_path.as_ref().as_os_str().to_owned();
+    fai_path.push(".fai");
+    PathBuf::from(fai_path)
+}
+
+/// Build the .fai records for an uncompressed FASTA file.
+fn index_fasta<R: BufRead>(mut reader: R) -> Result<Vec<FaiRecord>> {
+    let mut records: Vec<FaiRecord> = Vec::new();
+    // Set once a line shorter than line_bases is seen. Any further sequence lines in the same
+    // record mean the line lengths are inconsistent.
+    let mut record_ended = false;
+    let mut offset = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let num_bytes = reader.read_until(b'\n', &mut line)? as u64;
+        if num_bytes == 0 {
+            break;
+        }
+        offset += num_bytes;
+        if line.starts_with(b">") {
+            let header = String::from_utf8_lossy(&line[1..]);
+            let name = header.split_whitespace().next().unwrap_or_default().to_owned();
+            if name.is_empty() {
+                bail!("FASTA record has an empty name (byte offset {})", offset - num_bytes);
+            }
+            records.push(FaiRecord { name, length: 0, offset, line_bases: 0, line_width: 0 });
+            record_ended = false;
+            continue;
+        }
+        let line_bases = line.iter().filter(|c| !c.is_ascii_whitespace()).count() as u64;
+        if line_bases == 0 {
+            continue;
+        }
+        let record = records.last_mut().context("FASTA file does not start with a header")?;
+        if record.line_bases == 0 {
+            record.line_bases = line_bases;
+            record.line_width = num_bytes;
+        } else if record_ended || line_bases > record.line_bases {
+            bail!("Sequence {} has inconsistent line lengths", record.name);
+        } else if line_bases < record.line_bases {
+            record_ended = true;
+        }
+        record.length += line_bases;
+    }
+    if records.is_empty() {
+        bail!("FASTA file does not contain any sequences");
+    }
+    Ok(records)
+}
+
+/// True if a FASTA file has all of the indexes required to read it.
+///
+/// Bgzipped files require a .gzi index in addition to the .fai index.
+pub fn has_fasta_index<P: AsRef<Path>>(fasta_path: P) -> bool {
+    let fasta_path = fasta_path.as_ref();
+    get_fai_path(fasta_path).exists()
+        && (!is_bgzipped(fasta_path) || get_gzi_path(fasta_path).exists())
+}
+
+/// Build an index for a FASTA file. Returns the path to the index.
+///
+/// Bgzipped files are also given a .gzi index. Offsets in the .fai index of a bgzipped file refer
+/// to the uncompressed data (as with samtools).
+pub fn build_fasta_index<P: AsRef<Path>>(fasta_path: P) -> Result<PathBuf> {
+    let fasta_path = fasta_path.as_ref();
+    log::info!("Building index for {}", fasta_path.to_string_lossy());
+    let file = File::open(fasta_path)
+        .with_context(|| format!("Failed to open FASTA file: {}", fasta_path.to_string_lossy()))?;
+    let records = if is_bgzipped(fasta_path) {
+        build_gzi_index(fasta_path)?;
+        index_fasta(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        index_fasta(BufReader::new(file))
+    }
+    .with_context(|| format!("Failed to index FASTA file: {}", fasta_path.to_string_lossy()))?;
+    let fai_path = get_fai_path(fasta_path);
+    let mut writer = BufWriter::new(File::create(&fai_path).with_context(|| {
+        format!("Failed to create FASTA index: {}", fai_path.to_string_lossy())
+    })?);
+    for record in records {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}",
+            record.name, record.length, record.offset, record.line_bases, record.line_width
+        )?;
+    }
+    writer.flush()?;
+    Ok(fai_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_index_fasta() {
+        let fasta = ">chr1 description\nACGT\nAC\n>chr2\r\nAAA\r\n";
+        let records = index_fasta(fasta.as_bytes()).unwrap();
+        assert_eq!(
+            records,
+            vec![
+                FaiRecord {
+                    name: "chr1".to_owned(),
+                    length: 6,
+                    offset: 18,
+                    line_bases: 4,
+                    line_width: 5
+                },
+                FaiRecord {
+                    name: "chr2".to_owned(),
+                    length: 3,
+                    offset: 33,
+                    line_bases: 3,
+                    line_width: 5
+                },
+            ]
+        );
+        assert!(index_fasta(">chr1\nAC\nACGT\n".as_bytes()).is_err());
+        assert!(index_fasta("ACGT\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_build_fasta_index_matches_samtools() {
+        let fasta_path = std::env::temp_dir().join(format!("{}.fa", uuid::Uuid::new_v4()));
+        std::fs::copy(get_test_data_path("fake-genome.fa"), &fasta_path).unwrap();
+        let fai_path = build_fasta_index(&fasta_path).unwrap();
+        let fai = std::fs::read_to_string(&fai_path).unwrap();
+        std::fs::remove_file(&fasta_path).unwrap();
+        std::fs::remove_file(&fai_path).unwrap();
+        let expected = std::fs::read_to_string(get_test_data_path("fake-genome.fa.fai")).unwrap();
+        assert_eq!(fai, expected);
+    }
+
+    #[test]
+    fn test_build_bgzipped_fasta_index_matches_samtools() {
+        let fasta_path = std::env::temp_dir().join(format!("{}.fa.gz", uuid::Uuid::new_v4()));
+        std::fs::copy(get_test_data_path("fake-genome.fa.gz"), &fasta_path).unwrap();
+        assert!(!has_fasta_index(&fasta_path));
+        let fai_path = build_fasta_index(&fasta_path).unwrap();
+        assert!(has_fasta_index(&fasta_path));
+        let fai = std::fs::read_to_string(&fai_path).unwrap();
+        std::fs::remove_file(&fasta_path).unwrap();
+        std::fs::remove_file(&fai_path).unwrap();
+        std::fs::remove_file(get_gzi_path(&fasta_path)).unwrap();
+        let expected_fai =
+            std::fs::read_to_string(get_test_data_path("fake-genome.fa.gz.fai")).unwrap();
+        assert_eq!(fai, expected_fai);
+    }
+}
@@ -1 +1,4 @@
+pub mod bgzf;
+pub mod index;
 pub mod reader;
+pub mod remote;
@@ -1,44 +1,222 @@
-use std::fs::File;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use bio::io::fasta::{self, Sequence};
+use parking_lot::Mutex;
 
+use crate::bio_util::contig_aliases::resolve_contig_name;
 use crate::bio_util::genomic_coordinates::GenomicRegion;
 use crate::bio_util::sequence::SequenceView;
+use crate::file_formats::enums::{get_file_kind, FileKind};
+use crate::file_formats::fasta::bgzf::{is_bgzipped, BgzfReader};
+use crate::file_formats::fasta::index::get_fai_path;
+use crate::file_formats::fasta::remote::RemoteFastaReader;
+use crate::file_formats::remote::is_remote;
 
-/// A reader for indexed .fasta files.
+/// List the FASTA files which make up a reference sequence.
+///
+/// A reference is either a single FASTA file or a directory of FASTA files (e.g one file per
+/// contig, as some assemblies are distributed).
+pub fn get_fasta_paths<P: AsRef<Path>>(reference_path: P) -> Result<Vec<PathBuf>> {
+    let reference_path = reference_path.as_ref();
+    if !reference_path.is_dir() {
+        return Ok(vec![reference_path.to_path_buf()]);
+    }
+    let mut paths = Vec::new();
+    let entries = fs::read_dir(reference_path).with_context(|| {
+        format!("Failed to read reference directory: {}", reference_path.display())
+    })?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() && matches!(get_file_kind(&path), Ok(FileKind::Fasta)) {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        bail!("No FASTA files found in reference directory: {}", reference_path.display());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Check that a path can be opened as a reference sequence before replacing the current reference.
+///
+/// Only the file format is checked. Whether the sequences can be read is checked when the
+/// reference is opened.
+pub fn validate_reference_path<P: AsRef<Path>>(reference_path: P) -> Result<()> {
+    let reference_path = reference_path.as_ref();
+    if !is_remote(reference_path) && !reference_path.exists() {
+        bail!("Reference sequence not found: {}", reference_path.display());
+    }
+    for fasta_path in get_fasta_paths(reference_path)? {
+        if !matches!(get_file_kind(&fasta_path)?, FileKind::Fasta) {
+            bail!("Reference sequence is not a FASTA file: {}", fasta_path.display());
+        }
+    }
+    Ok(())
+}
+
+/// An uncompressed or bgzipped fasta file.
+#[derive(Debug)]
+enum FastaFile {
+    Plain(File),
+    Bgzipped(BgzfReader<File>),
+}
+
+impl FastaFile {
+    fn open(path: &Path) -> Result<Self> {
+        let file = if is_bgzipped(path) {
+            FastaFile::Bgzipped(BgzfReader::from_path(path)?)
+        } else {
+            FastaFile::Plain(File::open(path)?)
+        };
+        Ok(file)
+    }
+}
+
+impl Read for FastaFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            FastaFile::Plain(file) => file.read(buf),
+            FastaFile::Bgzipped(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for FastaFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            FastaFile::Plain(file) => file.seek(pos),
+            FastaFile::Bgzipped(reader) => reader.seek(pos),
+        }
+    }
+}
+
+/// Open an indexed fasta file. Bgzipped files must also have a .gzi index.
+fn open_indexed_fasta(path: &Path) -> Result<fasta::IndexedReader<FastaFile>> {
+    let index = fasta::Index::from_file(&get_fai_path(path))?;
+    Ok(fasta::IndexedReader::with_index(FastaFile::open(path)?, index))
+}
+
+/// A reader for indexed .fasta files, or directories of indexed .fasta files.
+///
+/// Bgzipped .fasta files are supported if they have a .gzi index.
 #[derive(Debug)]
 pub struct FastaReader {
     pub reference_path: PathBuf,
-    reader: fasta::IndexedReader<File>,
+    readers: Vec<fasta::IndexedReader<FastaFile>>,
+    /// Maps each sequence name to the index of the reader for the file which contains it.
+    seq_readers: HashMap<String, usize>,
 }
 
 impl FastaReader {
     pub fn new<P: Into<PathBuf>>(reference_path: P) -> Result<FastaReader> {
         let pathbuf: PathBuf = reference_path.into();
-        let reader = fasta::IndexedReader::from_file(&pathbuf)
-            .with_context(|| format!("Failed to read reference file: {}", pathbuf.display()))?;
-        Ok(FastaReader { reference_path: pathbuf, reader })
+        let mut readers = Vec::new();
+        let mut seq_readers = HashMap::new();
+        for (i, path) in get_fasta_paths(&pathbuf)?.into_iter().enumerate() {
+            let reader = open_indexed_fasta(&path)
+                .with_context(|| format!("Failed to read reference file: {}", path.display()))?;
+            for sequence in reader.index.sequences() {
+                if seq_readers.insert(sequence.name.clone(), i).is_some() {
+                    bail!(
+                        "Sequence named {} is present in multiple files in {}",
+                        sequence.name,
+                        pathbuf.display()
+                    );
+                }
+            }
+            readers.push(reader);
+        }
+        Ok(FastaReader { reference_path: pathbuf, readers, seq_readers })
     }
 
     pub fn sequences(&self) -> Vec<Sequence> {
-        self.reader.index.sequences()
+        self.readers.iter().flat_map(|reader| reader.index.sequences()).collect()
     }
 
     /// Get sequence in fasta file for a given genomic region
+    /// Read a region of the reference. The region's sequence name may be an alias of the name
+    /// used in the file (e.g chr1 for 1).
     pub fn read(&mut self, region: &GenomicRegion) -> Result<SequenceView> {
-        self.reader.fetch(&region.seq_name, region.start(), region.end()).with_context(|| {
+        let seq_names = self.seq_readers.keys().map(String::as_str);
+        let (seq_name, reader) = match resolve_contig_name(&region.seq_name, seq_names) {
+            Some(seq_name) => (seq_name, &mut self.readers[self.seq_readers[seq_name]]),
+            None => bail!("Failed to fetch {} from {}", region, self.reference_path.display()),
+        };
+        reader.fetch(seq_name, region.start(), region.end()).with_context(|| {
             format!("Failed to fetch {} from {}", region, self.reference_path.display())
         })?;
         let mut sequence: Vec<u8> = vec![0; region.len() as usize];
-        self.reader.read(&mut sequence)?;
+        reader.read(&mut sequence)?;
         sequence.retain(|c| *c != b'\n');
         let view = SequenceView::new(sequence, region.start());
         Ok(view)
     }
 }
 
+/// A fasta file (or directory of fasta files) which has been read entirely into memory.
+#[derive(Debug)]
+pub struct PreloadedFasta {
+    pub reference_path: PathBuf,
+    sequences: HashMap<String, Vec<u8>>,
+}
+
+impl PreloadedFasta {
+    pub fn new<P: Into<PathBuf>>(reference_path: P) -> Result<PreloadedFasta> {
+        let pathbuf: PathBuf = reference_path.into();
+        let mut sequences = HashMap::new();
+        for path in get_fasta_paths(&pathbuf)? {
+            let file = FastaFile::open(&path)
+                .with_context(|| format!("Failed to read reference file: {}", path.display()))?;
+            for record in fasta::Reader::new(file).records() {
+                let record = record?;
+                sequences.insert(record.id().to_owned(), record.seq().to_owned());
+            }
+        }
+        Ok(PreloadedFasta { reference_path: pathbuf, sequences })
+    }
+
+    /// Get sequence in fasta file for a given genomic region
+    pub fn read(&self, region: &GenomicRegion) -> Result<SequenceView> {
+        let seq_names = self.sequences.keys().map(String::as_str);
+        let sequence = match resolve_contig_name(&region.seq_name, seq_names) {
+            Some(seq_name) => &self.sequences[seq_name],
+            None => bail!("Failed to fetch {} from {}", region, self.reference_path.display()),
+        };
+        if region.end() > sequence.len() as u64 {
+            bail!("Failed to fetch {} from {}", region, self.reference_path.display());
+        }
+        let sequence = sequence[region.start() as usize..region.end() as usize].to_vec();
+        Ok(SequenceView::new(sequence, region.start()))
+    }
+}
+
+/// Reads sequence either from an indexed fasta file (local or remote) or from a fasta file
+/// preloaded into memory.
+///
+/// Preloaded fastas are shared between all readers so no file handles are held.
+#[derive(Debug)]
+pub enum SequenceReader {
+    Indexed(Box<Mutex<FastaReader>>),
+    Remote(Box<Mutex<RemoteFastaReader>>),
+    Preloaded(Arc<PreloadedFasta>),
+}
+
+impl SequenceReader {
+    pub fn read(&self, region: &GenomicRegion) -> Result<SequenceView> {
+        match self {
+            SequenceReader::Indexed(reader) => reader.lock().read(region),
+            SequenceReader::Remote(reader) => reader.lock().read(region),
+            SequenceReader::Preloaded(fasta) => fasta.read(region),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -53,4 +231,86 @@ mod tests {
         let sequence_view = reader.read(&region).unwrap();
         assert_eq!(sequence_view.to_string().unwrap(), "GATCACAGGTCTATCACCCT".to_owned());
     }
+
+    #[test]
+    fn test_preloaded_reading_matches_indexed() {
+        let fasta_file = get_test_data_path("fake-genome.fa");
+        let mut reader = FastaReader::new(&fasta_file).unwrap();
+        let preloaded = PreloadedFasta::new(&fasta_file).unwrap();
+        let region = GenomicRegion::new("euk_genes", 55, 300).unwrap();
+        assert_eq!(
+            preloaded.read(&region).unwrap().to_string().unwrap(),
+            reader.read(&region).unwrap().to_string().unwrap()
+        );
+        let past_end = GenomicRegion::new("mt", 16560, 16600).unwrap();
+        assert!(preloaded.read(&past_end).is_err());
+        let missing_seq = GenomicRegion::new("chr1", 0, 10).unwrap();
+        assert!(preloaded.read(&missing_seq).is_err());
+    }
+
+    #[test]
+    fn test_reading_directory_of_fastas() {
+        let fasta_dir = get_test_data_path("fake-genome-contigs");
+        let paths = get_fasta_paths(&fasta_dir).unwrap();
+        let filenames: Vec<_> = paths.iter().map(|path| path.file_name().unwrap()).collect();
+        assert_eq!(filenames, vec!["euk_genes.fa", "mt.fa"]);
+        let mut reader = FastaReader::new(&fasta_dir).unwrap();
+        let mut single_file_reader =
+            FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap();
+        assert_eq!(reader.sequences(), single_file_reader.sequences());
+        for region in [
+            GenomicRegion::new("mt", 0, 20).unwrap(),
+            GenomicRegion::new("euk_genes", 60, 150).unwrap(),
+        ] {
+            assert_eq!(
+                reader.read(&region).unwrap().to_string().unwrap(),
+                single_file_reader.read(&region).unwrap().to_string().unwrap()
+            );
+        }
+        let preloaded = PreloadedFasta::new(&fasta_dir).unwrap();
+        let region = GenomicRegion::new("mt", 16500, 16569).unwrap();
+        assert_eq!(
+            preloaded.read(&region).unwrap().to_string().unwrap(),
+            single_file_reader.read(&region).unwrap().to_string().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_reading_bgzipped_fasta() {
+        let mut reader = FastaReader::new(get_test_data_path("fake-genome.fa.gz")).unwrap();
+        let mut uncompressed_reader =
+            FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap();
+        assert_eq!(reader.sequences(), uncompressed_reader.sequences());
+        // Regions spanning the boundaries between bgzf blocks
+        for region in [
+            GenomicRegion::new("euk_genes", 4000, 4200).unwrap(),
+            GenomicRegion::new("mt", 0, 16569).unwrap(),
+        ] {
+            assert_eq!(
+                reader.read(&region).unwrap().to_string().unwrap(),
+                uncompressed_reader.read(&region).unwrap().to_string().unwrap()
+            );
+        }
+        let preloaded = PreloadedFasta::new(get_test_data_path("fake-genome.fa.gz")).unwrap();
+        let region = GenomicRegion::new("mt", 100, 9000).unwrap();
+        assert_eq!(
+            preloaded.read(&region).unwrap().to_string().unwrap(),
+            uncompressed_reader.read(&region).unwrap().to_string().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_get_fasta_paths_for_file() {
+        let fasta_file = get_test_data_path("fake-genome.fa");
+        assert_eq!(get_fasta_paths(&fasta_file).unwrap(), vec![fasta_file]);
+    }
+
+    #[test]
+    fn test_validate_reference_path() {
+        assert!(validate_reference_path(get_test_data_path("fake-genome.fa")).is_ok());
+        assert!(validate_reference_path(get_test_data_path("fake-genome.fa.gz")).is_ok());
+        assert!(validate_reference_path(get_test_data_path("fake-genome-contigs")).is_ok());
+        assert!(validate_reference_path(get_test_data_path("fake-genome.reads.bam")).is_err());
+        assert!(validate_reference_path(get_test_data_path("missing.fa")).is_err());
+    }
 }
@@ -0,0 +1,148 @@
+//! Reading indexed fasta files through htslib, which supports remote files (e.g `s3://` URLs).
+use std::collections::BTreeMap;
+use std::ffi::{c_void, CStr, CString};
+use std::path::PathBuf;
+use std::ptr;
+
+use anyhow::{bail, Result};
+use rust_htslib::htslib;
+
+use crate::bio_util::contig_aliases::resolve_contig_name;
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::bio_util::sequence::SequenceView;
+use crate::file_formats::remote::get_htslib_path;
+
+/// A reader for an indexed (optionally bgzipped) fasta file which can't be read directly from disk.
+///
+/// htslib finds the .fai (and .gzi) index alongside the file, so the index must already exist.
+#[derive(Debug)]
+pub struct RemoteFastaReader {
+    pub reference_path: PathBuf,
+    seq_lengths: BTreeMap<String, u64>,
+    inner: *mut htslib::faidx_t,
+}
+
+// SAFETY: The faidx_t isn't tied to the thread which created it. The reader isn't Sync so it can
+// only be used from one thread at a time.
+unsafe impl Send for RemoteFastaReader {}
+
+impl RemoteFastaReader {
+    pub fn new<P: Into<PathBuf>>(reference_path: P) -> Result<RemoteFastaReader> {
+        let pathbuf: PathBuf = reference_path.into();
+        let c_path = CString::new(get_htslib_path(&pathbuf))?;
+        // SAFETY: c_path is a valid nul-terminated string. No flags are passed so that htslib
+        // doesn't attempt to build missing indexes.
+        let inner = unsafe { htslib::fai_load3(c_path.as_ptr(), ptr::null(), ptr::null(), 0) };
+        if inner.is_null() {
+            bail!("Failed to open indexed reference file: {}", pathbuf.to_string_lossy());
+        }
+        let mut reader =
+            RemoteFastaReader { reference_path: pathbuf, seq_lengths: BTreeMap::new(), inner };
+        // SAFETY: inner is non-null and the sequence names returned by faidx_iseq are owned by the
+        // index, which outlives this loop.
+        let num_seqs = unsafe { htslib::faidx_nseq(inner) };
+        for i in 0..num_seqs {
+            let (name, length) = unsafe {
+                let name_ptr = htslib::faidx_iseq(inner, i);
+                (
+                    CStr::from_ptr(name_ptr).to_string_lossy().to_string(),
+                    htslib::faidx_seq_len64(inner, name_ptr),
+                )
+            };
+            if length < 0 {
+                bail!("Failed to read length of {} from {}", name, reader.reference_path.display());
+            }
+            reader.seq_lengths.insert(name, length as u64);
+        }
+        Ok(reader)
+    }
+
+    /// Map from sequence name to sequence length.
+    pub fn seq_lengths(&self) -> &BTreeMap<String, u64> {
+        &self.seq_lengths
+    }
+
+    /// Get sequence in fasta file for a given genomic region
+    pub fn read(&mut self, region: &GenomicRegion) -> Result<SequenceView> {
+        // htslib truncates regions which extend past the end of a sequence, so these are checked
+        // up front to match FastaReader.
+        let seq_names = self.seq_lengths.keys().map(String::as_str);
+        let seq_name = match resolve_contig_name(&region.seq_name, seq_names) {
+            Some(seq_name) if region.end() <= self.seq_lengths[seq_name] => seq_name,
+            _ => bail!("Failed to fetch {} from {}", region, self.reference_path.display()),
+        };
+        let c_name = CString::new(seq_name.as_bytes())?;
+        let mut length: htslib::hts_pos_t = 0;
+        // SAFETY: inner is non-null and c_name is a valid nul-terminated string. The end
+        // coordinate is inclusive in htslib. The returned sequence is copied before being freed.
+        let sequence = unsafe {
+            let ptr = htslib::faidx_fetch_seq64(
+                self.inner,
+                c_name.as_ptr(),
+                region.start() as htslib::hts_pos_t,
+                region.end() as htslib::hts_pos_t - 1,
+                &mut length,
+            );
+            if ptr.is_null() {
+                None
+            } else {
+                let sequence = CStr::from_ptr(ptr).to_bytes().to_vec();
+                htslib::hts_free(ptr as *mut c_void);
+                Some(sequence)
+            }
+        };
+        match sequence {
+            Some(sequence) if length >= 0 && sequence.len() as u64 == region.len() => {
+                Ok(SequenceView::new(sequence, region.start()))
+            }
+            _ => bail!("Failed to fetch {} from {}", region, self.reference_path.display()),
+        }
+    }
+}
+
+impl Drop for RemoteFastaReader {
+    fn drop(&mut self) {
+        // SAFETY: inner is non-null and is only destroyed here.
+        unsafe { htslib::fai_destroy(self.inner) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::file_formats::fasta::reader::FastaReader;
+    use crate::paths::get_test_data_path;
+
+    // htslib reads local files in the same way as remote files, so the reader is tested against the
+    // bio crate's reader with local files.
+    #[test]
+    fn test_reading_matches_fasta_reader() {
+        for filename in ["fake-genome.fa", "fake-genome.fa.gz"] {
+            let path = get_test_data_path(filename);
+            let mut reader = RemoteFastaReader::new(&path).unwrap();
+            let mut fasta_reader = FastaReader::new(&path).unwrap();
+            let expected_lengths: BTreeMap<String, u64> =
+                fasta_reader.sequences().into_iter().map(|seq| (seq.name, seq.len)).collect();
+            assert_eq!(reader.seq_lengths(), &expected_lengths);
+            for region in [
+                GenomicRegion::new("mt", 0, 20).unwrap(),
+                GenomicRegion::new("euk_genes", 4000, 4200).unwrap(),
+            ] {
+                assert_eq!(
+                    reader.read(&region).unwrap().to_string().unwrap(),
+                    fasta_reader.read(&region).unwrap().to_string().unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_reads() {
+        let mut reader = RemoteFastaReader::new(get_test_data_path("fake-genome.fa")).unwrap();
+        assert!(reader.read(&GenomicRegion::new("mt", 16560, 16600).unwrap()).is_err());
+        assert!(reader.read(&GenomicRegion::new("chr1", 0, 10).unwrap()).is_err());
+        assert!(RemoteFastaReader::new(get_test_data_path("missing.fa")).is_err());
+    }
+}
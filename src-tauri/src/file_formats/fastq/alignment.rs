@@ -0,0 +1,101 @@
+//! On-the-fly alignment of unaligned reads to a short stretch of reference sequence.
+use bio::alignment::pairwise::Aligner;
+use bio::alphabets::dna::revcomp;
+use serde::Serialize;
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::bio_util::sequence::SequenceView;
+
+const MATCH_SCORE: i32 = 1;
+const MISMATCH_SCORE: i32 = -1;
+const GAP_OPEN_SCORE: i32 = -5;
+const GAP_EXTEND_SCORE: i32 = -1;
+
+/// Alignments scoring less than this fraction of a perfect match are discarded.
+const MIN_SCORE_FRACTION: f64 = 0.5;
+
+/// Placement of an unaligned read within a reference region.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FastqAlignment {
+    pub region: GenomicRegion,
+    /// True if the reverse complement of the read aligned.
+    pub is_reverse: bool,
+    pub score: i32,
+    pub cigar_string: String,
+}
+
+/// Align a read to a reference sequence, trying both orientations.
+///
+/// The entire read is aligned (semi-global alignment) and None is returned if the best alignment
+/// scores poorly.
+pub fn align_read(
+    sequence: &[u8],
+    seq_name: &str,
+    refseq: &SequenceView,
+) -> Option<FastqAlignment> {
+    if sequence.is_empty() {
+        return None;
+    }
+    let reference = refseq.as_bytes().to_ascii_uppercase();
+    let score_fn = |a: u8, b: u8| if a == b { MATCH_SCORE } else { MISMATCH_SCORE };
+    let mut aligner = Aligner::with_capacity(
+        sequence.len(),
+        reference.len(),
+        GAP_OPEN_SCORE,
+        GAP_EXTEND_SCORE,
+        score_fn,
+    );
+    let forward = sequence.to_ascii_uppercase();
+    let reverse = revcomp(&forward[..]);
+    let (alignment, is_reverse) = [(forward, false), (reverse, true)]
+        .into_iter()
+        .map(|(query, is_reverse)| (aligner.semiglobal(&query, &reference), is_reverse))
+        .max_by_key(|(alignment, _)| alignment.score)?;
+    let min_score = (sequence.len() as f64 * MATCH_SCORE as f64 * MIN_SCORE_FRACTION).ceil();
+    if (alignment.score as f64) < min_score {
+        return None;
+    }
+    let start = refseq.start() + alignment.ystart as u64;
+    let end = refseq.start() + alignment.yend as u64;
+    let region = GenomicRegion::new(seq_name, start, end).ok()?;
+    Some(FastqAlignment {
+        region,
+        is_reverse,
+        score: alignment.score,
+        cigar_string: alignment.cigar(false),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_align_read() {
+        let refseq = SequenceView::new(b"ttttGATCACAGGTCTATCACCCTttttt".to_vec(), 1000);
+        let alignment = align_read(b"GATCACAGGTATATCACCCT", "mt", &refseq).unwrap();
+        assert_eq!(alignment.region, GenomicRegion::new("mt", 1004, 1024).unwrap());
+        assert!(!alignment.is_reverse);
+        assert_eq!(alignment.score, 18);
+        assert_eq!(alignment.cigar_string, "10=1X9=");
+    }
+
+    #[test]
+    fn test_align_reverse_complemented_read() {
+        let refseq = SequenceView::new(b"ttttGATCACAGGTCTATCACCCTttttt".to_vec(), 1000);
+        let alignment = align_read(b"AGGGTGATAGACCTGTGATC", "mt", &refseq).unwrap();
+        assert_eq!(alignment.region, GenomicRegion::new("mt", 1004, 1024).unwrap());
+        assert!(alignment.is_reverse);
+        assert_eq!(alignment.score, 20);
+    }
+
+    #[test]
+    fn test_unaligned_read() {
+        let refseq = SequenceView::new(b"GATCACAGGTCTATCACCCT".to_vec(), 0);
+        assert_eq!(align_read(b"CCGCCGCGCGCGCGGCGCGC", "mt", &refseq), None);
+        assert_eq!(align_read(b"", "mt", &refseq), None);
+    }
+}
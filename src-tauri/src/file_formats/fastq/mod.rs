@@ -0,0 +1,2 @@
+pub mod alignment;
+pub mod reader;
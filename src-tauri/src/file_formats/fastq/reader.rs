@@ -0,0 +1,139 @@
+//! Reading small FASTQ files for display as an unanchored list of reads.
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use bio::io::fastq;
+use flate2::read::MultiGzDecoder;
+use serde::Serialize;
+
+use crate::file_formats::fasta::bgzf::is_bgzipped;
+use crate::file_formats::fastq::alignment::FastqAlignment;
+
+/// Maximum number of reads which are read from a FASTQ file. Larger files are truncated.
+pub const MAX_FASTQ_READS: usize = 100_000;
+
+/// Maximum number of reads which can be fetched at once.
+pub const MAX_FASTQ_PAGE_SIZE: usize = 500;
+
+/// Offset of Phred quality scores in FASTQ quality strings (Sanger/Illumina 1.8+ encoding).
+const PHRED_OFFSET: u8 = 33;
+
+/// A single unaligned read from a FASTQ file.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FastqRead {
+    pub id: String,
+    pub description: Option<String>,
+    pub sequence: String,
+    pub qualities: String,
+    pub mean_quality: f64,
+    /// Alignment of the read to the focused region of a split (if requested and the read aligns).
+    pub alignment: Option<FastqAlignment>,
+}
+
+impl FastqRead {
+    fn from_record(record: &fastq::Record) -> Self {
+        let qualities = record.qual();
+        let mean_quality = if qualities.is_empty() {
+            0.0
+        } else {
+            let total: u64 = qualities.iter().map(|q| q.saturating_sub(PHRED_OFFSET) as u64).sum();
+            total as f64 / qualities.len() as f64
+        };
+        Self {
+            id: record.id().to_owned(),
+            description: record.desc().map(|desc| desc.to_owned()),
+            sequence: String::from_utf8_lossy(record.seq()).into_owned(),
+            qualities: String::from_utf8_lossy(qualities).into_owned(),
+            mean_quality,
+            alignment: None,
+        }
+    }
+}
+
+/// The reads from a FASTQ file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FastqReads {
+    pub reads: Vec<FastqRead>,
+    /// True if the file contains more than `MAX_FASTQ_READS` reads.
+    pub truncated: bool,
+}
+
+impl FastqReads {
+    /// Get a subset of the reads for display.
+    pub fn page(self, offset: usize, limit: usize) -> FastqPage {
+        let total_reads = self.reads.len();
+        let reads = self.reads.into_iter().skip(offset).take(limit).collect();
+        FastqPage { reads, offset, total_reads, truncated: self.truncated }
+    }
+}
+
+/// A subset of the reads from a FASTQ file.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FastqPage {
+    pub reads: Vec<FastqRead>,
+    /// Index of the first read in the page.
+    pub offset: usize,
+    pub total_reads: usize,
+    pub truncated: bool,
+}
+
+/// Read up to `MAX_FASTQ_READS` reads from a (optionally gzipped) FASTQ file.
+pub fn read_fastq<P: AsRef<Path>>(path: P) -> Result<FastqReads> {
+    let path = path.as_ref();
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open FASTQ file: {}", path.to_string_lossy()))?;
+    let reader: Box<dyn Read> = if is_bgzipped(path) {
+        Box::new(MultiGzDecoder::new(BufReader::new(file)))
+    } else {
+        Box::new(file)
+    };
+    let mut reads = Vec::new();
+    let mut truncated = false;
+    for record in fastq::Reader::new(reader).records() {
+        if reads.len() == MAX_FASTQ_READS {
+            truncated = true;
+            break;
+        }
+        let record = record
+            .with_context(|| format!("Failed to parse FASTQ file: {}", path.to_string_lossy()))?;
+        reads.push(FastqRead::from_record(&record));
+    }
+    Ok(FastqReads { reads, truncated })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_read_fastq() {
+        let fastq = read_fastq(get_test_data_path("fake-genome.reads.fastq")).unwrap();
+        assert!(!fastq.truncated);
+        assert_eq!(fastq.reads.len(), 3);
+        let read = &fastq.reads[0];
+        assert_eq!(read.id, "mt_read1");
+        assert_eq!(read.description.as_deref(), Some("forward"));
+        assert_eq!(read.sequence, "GATCACAGGTCTATCACCCTATTAACCACTCACGGGAGCTCTCC");
+        assert_eq!(read.mean_quality, 40.0);
+        assert_eq!(read.alignment, None);
+        assert_eq!(fastq.reads[2].description, None);
+        let page = fastq.page(1, 5);
+        assert_eq!(page.reads.len(), 2);
+        assert_eq!(page.reads[0].id, "mt_read2");
+        assert_eq!(page.total_reads, 3);
+    }
+
+    #[test]
+    fn test_read_gzipped_fastq() {
+        let fastq = read_fastq(get_test_data_path("fake-genome.reads.fastq")).unwrap();
+        let gzipped_fastq = read_fastq(get_test_data_path("fake-genome.reads.fastq.gz")).unwrap();
+        assert_eq!(gzipped_fastq, fastq);
+    }
+}
@@ -0,0 +1,153 @@
+//! Detecting file formats from the first few bytes of a file.
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+
+/// Number of (uncompressed) bytes used to detect the format of a file.
+const SNIFF_LENGTH: usize = 64;
+
+/// Number of bytes read from the start of compressed files (the maximum size of a BGZF block).
+const COMPRESSED_SNIFF_LENGTH: usize = 65536;
+
+const GZIP_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+
+/// Formats which can be identified by their contents.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SniffedFormat {
+    Bam,
+    Cram,
+    Fasta,
+    Fastq,
+    Vcf,
+    /// Any other bgzip/gzip compressed file
+    Bgzip,
+}
+
+impl fmt::Display for SniffedFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SniffedFormat::Bam => write!(f, "BAM"),
+            SniffedFormat::Cram => write!(f, "CRAM"),
+            SniffedFormat::Fasta => write!(f, "FASTA"),
+            SniffedFormat::Fastq => write!(f, "FASTQ"),
+            SniffedFormat::Vcf => write!(f, "VCF"),
+            SniffedFormat::Bgzip => write!(f, "bgzip"),
+        }
+    }
+}
+
+/// Read up to `buf.len()` bytes, stopping early only at the end of the input.
+fn read_prefix<R: Read>(mut reader: R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut num_bytes = 0;
+    while num_bytes < buf.len() {
+        match reader.read(&mut buf[num_bytes..]) {
+            Ok(0) => break,
+            Ok(n) => num_bytes += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(num_bytes)
+}
+
+/// True if the data starts with a SAM header line (e.g `@HD\tVN:1.6`).
+///
+/// SAM headers and FASTQ records both start with `@` but SAM header records are always a two
+/// letter tag followed by a tab.
+fn starts_with_sam_header(prefix: &[u8]) -> bool {
+    prefix.len() >= 4
+        && prefix[0] == b'@'
+        && prefix[1..3].iter().all(u8::is_ascii_uppercase)
+        && prefix[3] == b'\t'
+}
+
+/// Identify the format of uncompressed data.
+fn sniff_uncompressed(prefix: &[u8]) -> Option<SniffedFormat> {
+    if prefix.starts_with(b"BAM\x01") {
+        Some(SniffedFormat::Bam)
+    } else if prefix.starts_with(b"CRAM") {
+        Some(SniffedFormat::Cram)
+    } else if prefix.starts_with(b"##fileformat=VCF") {
+        Some(SniffedFormat::Vcf)
+    } else if prefix.starts_with(b">") {
+        Some(SniffedFormat::Fasta)
+    } else if prefix.starts_with(b"@") && !starts_with_sam_header(prefix) {
+        Some(SniffedFormat::Fastq)
+    } else {
+        None
+    }
+}
+
+/// Decompress as much of the start of some gzipped data as possible.
+fn decompress_prefix(compressed: &[u8], buf: &mut [u8]) -> usize {
+    let mut decoder = GzDecoder::new(compressed);
+    let mut num_bytes = 0;
+    // The compressed data may be truncated, in which case decoding stops with an error
+    while num_bytes < buf.len() {
+        match decoder.read(&mut buf[num_bytes..]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => num_bytes += n,
+        }
+    }
+    num_bytes
+}
+
+/// Detect the format of some data from its first few bytes.
+///
+/// Compressed data is identified by its decompressed contents where possible. Returns None if the
+/// format isn't recognized.
+pub fn sniff_format<R: Read>(reader: R) -> io::Result<Option<SniffedFormat>> {
+    let mut prefix = vec![0; COMPRESSED_SNIFF_LENGTH];
+    let num_bytes = read_prefix(reader, &mut prefix)?;
+    let prefix = &prefix[..num_bytes];
+    if !prefix.starts_with(&GZIP_MAGIC) {
+        return Ok(sniff_uncompressed(prefix));
+    }
+    let mut decompressed = [0; SNIFF_LENGTH];
+    let num_bytes = decompress_prefix(prefix, &mut decompressed);
+    let format = sniff_uncompressed(&decompressed[..num_bytes]).unwrap_or(SniffedFormat::Bgzip);
+    Ok(Some(format))
+}
+
+/// Detect the format of a file from its first few bytes.
+///
+/// Returns None if the file can't be read or its format isn't recognized.
+pub fn sniff_file_format<P: AsRef<Path>>(path: P) -> Option<SniffedFormat> {
+    let file = File::open(path).ok()?;
+    sniff_format(file).ok().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_sniff_file_format() {
+        let check = |filename: &str, expected: Option<SniffedFormat>| {
+            assert_eq!(sniff_file_format(get_test_data_path(filename)), expected);
+        };
+        check("fake-genome.reads.bam", Some(SniffedFormat::Bam));
+        check("fake-genome.fa", Some(SniffedFormat::Fasta));
+        check("fake-genome.fa.gz", Some(SniffedFormat::Fasta));
+        check("fake-genome.reads.fastq", Some(SniffedFormat::Fastq));
+        check("fake-genome.reads.fastq.gz", Some(SniffedFormat::Fastq));
+        check("fake-genome.reads.sam", None);
+        check("fake-genome.bedgraph.gz", Some(SniffedFormat::Bgzip));
+        check("fake-genome.paf", None);
+        check("missing-file.bam", None);
+    }
+
+    #[test]
+    fn test_sniff_uncompressed_formats() {
+        assert_eq!(sniff_format(&b"CRAM\x03\x00"[..]).unwrap(), Some(SniffedFormat::Cram));
+        let vcf = b"##fileformat=VCFv4.2\n#CHROM\tPOS\n";
+        assert_eq!(sniff_format(&vcf[..]).unwrap(), Some(SniffedFormat::Vcf));
+        assert_eq!(sniff_format(&b""[..]).unwrap(), None);
+    }
+}
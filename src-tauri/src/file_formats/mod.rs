@@ -1,3 +1,11 @@
 pub mod enums;
 pub mod fasta;
+pub mod fastq;
+pub mod magic;
+pub mod nanopore;
+pub mod paf;
+pub mod remote;
+pub mod remote_cache;
 pub mod sam_bam;
+pub mod signal;
+pub mod tabix;
@@ -0,0 +1,252 @@
+//! Per-read run metadata for nanopore reads, from sequencing summaries or basecaller BAM tags.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use flate2::read::MultiGzDecoder;
+use rust_htslib::bam::record::{Aux, Record};
+use serde::Serialize;
+
+use crate::file_formats::fasta::bgzf::is_bgzipped;
+use crate::file_formats::sam_bam::aligned_read::AlignedRead;
+
+/// Run metadata for a single read.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadRunMetadata {
+    /// Flow cell channel which the read was sequenced on.
+    pub channel: Option<u32>,
+    /// Mean basecall quality score.
+    pub mean_qscore: Option<f32>,
+    /// Duration of the read in seconds.
+    pub duration: Option<f32>,
+    pub run_id: Option<String>,
+}
+
+// Non-finite values are never parsed, so the float fields can be compared for equality.
+impl Eq for ReadRunMetadata {}
+
+/// Convert a numeric aux field to a float, skipping non-finite values.
+fn aux_to_f32(aux: Aux) -> Option<f32> {
+    let value = match aux {
+        Aux::Float(value) => value,
+        Aux::Double(value) => value as f32,
+        aux => aux_to_u32(aux)? as f32,
+    };
+    Some(value).filter(|value| value.is_finite())
+}
+
+/// Convert an integer aux field to a u32, skipping negative values.
+pub fn aux_to_u32(aux: Aux) -> Option<u32> {
+    match aux {
+        Aux::U8(value) => Some(value as u32),
+        Aux::U16(value) => Some(value as u32),
+        Aux::U32(value) => Some(value),
+        Aux::I8(value) => u32::try_from(value).ok(),
+        Aux::I16(value) => u32::try_from(value).ok(),
+        Aux::I32(value) => u32::try_from(value).ok(),
+        _ => None,
+    }
+}
+
+fn parse_finite_f32(value: &str) -> Option<f32> {
+    value.parse::<f32>().ok().filter(|value| value.is_finite())
+}
+
+impl ReadRunMetadata {
+    /// Get run metadata from the tags which nanopore basecallers (Guppy/Dorado) add to reads.
+    ///
+    /// Uses the `qs` (mean qscore), `du` (duration) and `ch` (channel) tags. Returns None if none of
+    /// the tags are present.
+    pub fn from_record(record: &Record) -> Option<Self> {
+        let metadata = Self {
+            channel: record.aux(b"ch").ok().and_then(aux_to_u32),
+            mean_qscore: record.aux(b"qs").ok().and_then(aux_to_f32),
+            duration: record.aux(b"du").ok().and_then(aux_to_f32),
+            run_id: None,
+        };
+        if metadata == Self::default() {
+            return None;
+        }
+        Some(metadata)
+    }
+
+    /// Fill in the fields which are missing from this metadata with the values from another.
+    fn merge(&mut self, other: &ReadRunMetadata) {
+        self.channel = self.channel.or(other.channel);
+        self.mean_qscore = self.mean_qscore.or(other.mean_qscore);
+        self.duration = self.duration.or(other.duration);
+        if self.run_id.is_none() {
+            self.run_id = other.run_id.clone();
+        }
+    }
+}
+
+/// Run metadata for each read in a nanopore sequencing summary (sequencing_summary.txt).
+#[derive(Debug, Default)]
+pub struct SequencingSummary {
+    reads: HashMap<String, ReadRunMetadata>,
+}
+
+impl SequencingSummary {
+    /// Read a (optionally gzipped) tab-separated sequencing summary.
+    ///
+    /// Only the `read_id` column is required. Channel, duration, mean qscore and run id are read
+    /// from the `channel`, `duration`, `mean_qscore_template` and `run_id` columns if present.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| {
+            format!("Failed to open sequencing summary: {}", path.to_string_lossy())
+        })?;
+        let reader: Box<dyn Read> =
+            if is_bgzipped(path) { Box::new(MultiGzDecoder::new(file)) } else { Box::new(file) };
+        let mut lines = BufReader::new(reader).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let columns: Vec<&str> = header.split('\t').collect();
+        let find_column = |name: &str| columns.iter().position(|column| *column == name);
+        let read_id_column = match find_column("read_id") {
+            Some(column) => column,
+            None => {
+                bail!("Sequencing summary is missing a read_id column: {}", path.to_string_lossy())
+            }
+        };
+        let channel_column = find_column("channel");
+        let qscore_column = find_column("mean_qscore_template");
+        let duration_column = find_column("duration");
+        let run_id_column = find_column("run_id");
+
+        let mut reads = HashMap::new();
+        for line in lines {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let field =
+                |column: Option<usize>| column.and_then(|column| fields.get(column).copied());
+            let read_id = match field(Some(read_id_column)) {
+                Some(read_id) => read_id.to_owned(),
+                None => continue,
+            };
+            let metadata = ReadRunMetadata {
+                channel: field(channel_column).and_then(|value| value.parse().ok()),
+                mean_qscore: field(qscore_column).and_then(parse_finite_f32),
+                duration: field(duration_column).and_then(parse_finite_f32),
+                run_id: field(run_id_column).map(|value| value.to_owned()),
+            };
+            reads.insert(read_id, metadata);
+        }
+        Ok(Self { reads })
+    }
+
+    pub fn len(&self) -> usize {
+        self.reads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reads.is_empty()
+    }
+
+    pub fn get(&self, read_id: &str) -> Option<&ReadRunMetadata> {
+        self.reads.get(read_id)
+    }
+
+    /// Join the summary's metadata onto a read by name.
+    ///
+    /// Values from the summary take precedence over values from the read's tags.
+    pub fn annotate(&self, read: &mut AlignedRead) {
+        let summary_metadata = match self.reads.get(&read.qname) {
+            Some(metadata) => metadata,
+            None => return,
+        };
+        let mut metadata = summary_metadata.clone();
+        if let Some(tag_metadata) = &read.run_metadata {
+            metadata.merge(tag_metadata);
+        }
+        read.run_metadata = Some(metadata);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::paths::get_test_data_path;
+    use crate::test_util::htslib_records::RecordBuilder;
+
+    pub const SUMMARY_READ_ID: &str =
+        "SYN_1223_162_308_379_-_8162_1_._euk_genes&1223_-67_82_379_+_7933_1_._euk_genes 1:";
+
+    pub fn read_test_summary() -> SequencingSummary {
+        SequencingSummary::read(get_test_data_path("fake-genome.sequencing_summary.txt")).unwrap()
+    }
+
+    #[test]
+    fn test_read_sequencing_summary() {
+        let summary = read_test_summary();
+        assert_eq!(summary.len(), 3);
+        assert_eq!(
+            summary.get(SUMMARY_READ_ID),
+            Some(&ReadRunMetadata {
+                channel: Some(112),
+                mean_qscore: Some(14.5),
+                duration: Some(2.25),
+                run_id: Some("run1".to_owned()),
+            })
+        );
+        // Non-finite values are skipped
+        let metadata = summary
+            .get(
+                "SYN_1229_208_409_434_-_8208_1_._euk_genes&1229_-75_73_434_+_7925_1_._euk_genes 1:",
+            )
+            .unwrap();
+        assert_eq!(metadata.mean_qscore, None);
+        assert_eq!(metadata.channel, Some(7));
+        assert_eq!(summary.get("missing_read"), None);
+    }
+
+    #[test]
+    fn test_read_metadata_from_record() {
+        assert_eq!(ReadRunMetadata::from_record(&RecordBuilder::default().record), None);
+        let record = RecordBuilder::default()
+            .aux(b"qs", Aux::Float(12.5))
+            .aux(b"ch", Aux::U16(350))
+            .aux(b"du", Aux::Float(1.5))
+            .record;
+        assert_eq!(
+            ReadRunMetadata::from_record(&record),
+            Some(ReadRunMetadata {
+                channel: Some(350),
+                mean_qscore: Some(12.5),
+                duration: Some(1.5),
+                run_id: None,
+            })
+        );
+        // Older basecallers write integer qscores
+        let record = RecordBuilder::default().aux(b"qs", Aux::I8(9)).record;
+        assert_eq!(ReadRunMetadata::from_record(&record).unwrap().mean_qscore, Some(9.0));
+    }
+
+    #[test]
+    fn test_merge_metadata() {
+        let mut metadata = ReadRunMetadata { channel: Some(1), ..Default::default() };
+        metadata.merge(&ReadRunMetadata {
+            channel: Some(2),
+            mean_qscore: Some(10.0),
+            duration: None,
+            run_id: Some("run".to_owned()),
+        });
+        assert_eq!(
+            metadata,
+            ReadRunMetadata {
+                channel: Some(1),
+                mean_qscore: Some(10.0),
+                duration: None,
+                run_id: Some("run".to_owned()),
+            }
+        );
+    }
+}
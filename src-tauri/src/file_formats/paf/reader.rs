@@ -0,0 +1,84 @@
+//! Reader for minimap2 PAF files.
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::alignments::alignment::Alignment;
+use crate::alignments::alignment_reader::{AlignmentReader, SkippedReads};
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::bio_util::sequence::SequenceView;
+use crate::file_formats::paf::record::{parse_paf_line, PafRecord};
+
+/// Reader for query alignments from a PAF file.
+///
+/// PAF files aren't indexed so the file is parsed in full on initialization.
+#[derive(Debug)]
+pub struct PafReader {
+    pub path: PathBuf,
+    records: Vec<PafRecord>,
+}
+
+impl PafReader {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let file = File::open(&path)
+            .with_context(|| format!("Failed to open PAF file: {}", path.to_string_lossy()))?;
+        let mut records = Vec::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = parse_paf_line(&line, i).with_context(|| {
+                format!("Failed to parse PAF file: {} (line {})", path.to_string_lossy(), i + 1)
+            })?;
+            records.push(record);
+        }
+        Ok(Self { path, records })
+    }
+}
+
+impl AlignmentReader for PafReader {
+    type Item = PafRecord;
+
+    fn read(
+        &mut self,
+        region: &GenomicRegion,
+        _refseq: &SequenceView,
+    ) -> Result<(Vec<Self::Item>, SkippedReads)> {
+        let records = self
+            .records
+            .iter()
+            .filter(|record| {
+                record.target_name == region.seq_name
+                    && record.start() < region.end()
+                    && record.end() > region.start()
+            })
+            .cloned()
+            .collect();
+        Ok((records, SkippedReads::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::file_formats::fasta::reader::FastaReader;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_read_paf() {
+        let mut reader = PafReader::new(get_test_data_path("fake-genome.paf")).unwrap();
+        let mut fasta_reader = FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap();
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let sequence_view = fasta_reader.read(&region).unwrap();
+        let (records, skipped_reads) = reader.read(&region, &sequence_view).unwrap();
+        let ids: Vec<&str> = records.iter().map(|record| record.id()).collect();
+        assert_eq!(ids, vec!["contig1/0", "contig2/1"]);
+        assert_eq!(skipped_reads.count, 0);
+    }
+}
@@ -0,0 +1,132 @@
+//! Records from minimap2 PAF (Pairwise mApping Format) files.
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::alignments::alignment::Alignment;
+use crate::bio_util::genomic_coordinates::GenomicInterval;
+use crate::impl_alignment;
+
+/// Number of mandatory columns in a PAF line.
+const NUM_PAF_COLUMNS: usize = 12;
+
+/// A single query alignment from a PAF file.
+///
+/// The interval is the aligned region of the target (i.e reference) sequence.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename = "pafRecordKind", rename_all = "camelCase", tag = "type")]
+pub struct PafRecord {
+    pub id: String,
+    pub query_name: String,
+    #[serde_as(as = "DisplayFromStr")]
+    pub query_length: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub query_start: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub query_end: u64,
+    pub target_name: String,
+    pub interval: GenomicInterval,
+
+    /// True if the query is aligned to the reverse strand of the target
+    pub is_reverse: bool,
+
+    /// Number of matching bases in the alignment
+    #[serde_as(as = "DisplayFromStr")]
+    pub num_matches: u64,
+
+    /// Total number of bases (including gaps) in the alignment
+    #[serde_as(as = "DisplayFromStr")]
+    pub block_length: u64,
+
+    /// Mapping quality (255 if missing)
+    pub mapq: u8,
+
+    /// CIGAR string from the cg:Z tag (if present)
+    pub cigar_string: Option<String>,
+}
+
+impl_alignment!(PafRecord);
+
+impl PafRecord {
+    /// Fraction of the alignment block which consists of matching bases.
+    pub fn identity(&self) -> f64 {
+        if self.block_length == 0 {
+            return 0.0;
+        }
+        self.num_matches as f64 / self.block_length as f64
+    }
+}
+
+fn parse_column<T: std::str::FromStr>(columns: &[&str], index: usize, name: &str) -> Result<T> {
+    columns[index].parse().map_err(|_| anyhow!("Invalid {} in PAF line: {}", name, columns[index]))
+}
+
+/// Parse a single line of a PAF file.
+///
+/// # Arguments
+///
+/// * `line_number` - Used to give the record a unique id since a query may have multiple
+///   alignments to the same target.
+pub fn parse_paf_line(line: &str, line_number: usize) -> Result<PafRecord> {
+    let columns: Vec<&str> = line.split('\t').collect();
+    if columns.len() < NUM_PAF_COLUMNS {
+        bail!("Expected at least {} columns in PAF line: {}", NUM_PAF_COLUMNS, line);
+    }
+    let is_reverse = match columns[4] {
+        "+" => false,
+        "-" => true,
+        strand => bail!("Invalid strand in PAF line: {}", strand),
+    };
+    let target_start = parse_column(&columns, 7, "target start")?;
+    let target_end = parse_column(&columns, 8, "target end")?;
+    let interval = GenomicInterval::new(target_start, target_end)
+        .with_context(|| format!("Invalid target interval in PAF line {}", line_number))?;
+    let cigar_string = columns[NUM_PAF_COLUMNS..]
+        .iter()
+        .find_map(|tag| tag.strip_prefix("cg:Z:"))
+        .map(String::from);
+    let query_name = columns[0].to_owned();
+    Ok(PafRecord {
+        id: format!("{}/{}", query_name, line_number),
+        query_name,
+        query_length: parse_column(&columns, 1, "query length")?,
+        query_start: parse_column(&columns, 2, "query start")?,
+        query_end: parse_column(&columns, 3, "query end")?,
+        target_name: columns[5].to_owned(),
+        interval,
+        is_reverse,
+        num_matches: parse_column(&columns, 9, "number of matches")?,
+        block_length: parse_column(&columns, 10, "alignment block length")?,
+        mapq: parse_column(&columns, 11, "mapping quality")?,
+        cigar_string,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_paf_line() {
+        let line = "contig1\t5000\t100\t1100\t-\tmt\t16569\t2000\t3010\t950\t1010\t60\ttp:A:P\tcg:Z:1000M10D";
+        let record = parse_paf_line(line, 3).unwrap();
+        assert_eq!(record.id, "contig1/3");
+        assert_eq!(record.target_name, "mt");
+        assert_eq!(record.interval, GenomicInterval::new(2000, 3010).unwrap());
+        assert_eq!((record.query_start, record.query_end), (100, 1100));
+        assert!(record.is_reverse);
+        assert_eq!(record.mapq, 60);
+        assert_eq!(record.cigar_string.as_deref(), Some("1000M10D"));
+        assert_eq!(record.identity(), 950.0 / 1010.0);
+    }
+
+    #[test]
+    fn test_parse_invalid_paf_line() {
+        assert!(parse_paf_line("contig1\t5000\t100", 0).is_err());
+        let line = "contig1\t5000\t100\t1100\t?\tmt\t16569\t2000\t3010\t950\t1010\t60";
+        assert!(parse_paf_line(line, 0).is_err());
+    }
+}
@@ -0,0 +1,579 @@
+//! Files stored remotely in cloud object stores (e.g S3 or GCS) or on web servers, which are read
+//! using htslib's plugins.
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use parking_lot::Mutex;
+use serde_json::json;
+use url::Url;
+
+use crate::file_formats::remote_cache::block_cache::BlockCache;
+use crate::file_formats::remote_cache::hfile::{
+    get_block_cache, get_url_headers, is_scheme_registered, set_block_cache, set_host_headers,
+    REMOTE_PREFIX,
+};
+use crate::interface::user_config::{
+    GcsConfig, HostAuthConfig, RemoteCacheConfig, S3Config, UserConfig,
+};
+use crate::paths::get_remote_cache_dir;
+
+/// Static GCS access token which htslib uses in place of the token file (if set).
+const GCS_OAUTH_TOKEN_VAR: &str = "GCS_OAUTH_TOKEN";
+
+/// File from which htslib reads the access token for https (and gs://) requests. htslib re-reads
+/// the file when the token it contains expires.
+const HTS_AUTH_LOCATION_VAR: &str = "HTS_AUTH_LOCATION";
+
+/// How long access tokens last. Tokens printed by `gcloud auth print-access-token` last an hour.
+const GCS_TOKEN_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Access tokens are refreshed when they're this close to expiring.
+const GCS_TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(10 * 60);
+
+/// How often the background thread checks whether the access token needs refreshing.
+const GCS_TOKEN_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// URL scheme of a supported remote file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RemoteScheme {
+    S3,
+    Gcs,
+    Http,
+    Https,
+}
+
+impl RemoteScheme {
+    fn prefix(&self) -> &'static str {
+        match self {
+            RemoteScheme::S3 => "s3://",
+            RemoteScheme::Gcs => "gs://",
+            RemoteScheme::Http => "http://",
+            RemoteScheme::Https => "https://",
+        }
+    }
+
+    /// What the host part of the URL refers to, for error messages.
+    fn host_description(&self) -> &'static str {
+        match self {
+            RemoteScheme::S3 | RemoteScheme::Gcs => "bucket name",
+            RemoteScheme::Http | RemoteScheme::Https => "host",
+        }
+    }
+}
+
+/// Get the scheme of a remote path, or None if the path is a local file.
+pub fn get_remote_scheme<P: AsRef<Path>>(path: P) -> Option<RemoteScheme> {
+    let path = path.as_ref().to_string_lossy();
+    [RemoteScheme::S3, RemoteScheme::Gcs, RemoteScheme::Http, RemoteScheme::Https]
+        .into_iter()
+        .find(|scheme| path.starts_with(scheme.prefix()))
+}
+
+pub fn is_remote<P: AsRef<Path>>(path: P) -> bool {
+    get_remote_scheme(path).is_some()
+}
+
+/// Parse a remote path (e.g `s3://bucket/key.bam`) into a URL which can be passed to htslib.
+pub fn parse_remote_url<P: AsRef<Path>>(path: P) -> Result<Url> {
+    let scheme = match get_remote_scheme(&path) {
+        Some(scheme) => scheme,
+        None => bail!(
+            "Unsupported URL (expected s3://, gs://, http:// or https://): {}",
+            path.as_ref().to_string_lossy()
+        ),
+    };
+    let path = path.as_ref().to_string_lossy();
+    let url = Url::parse(&path).with_context(|| format!("Invalid URL: {}", path))?;
+    if url.host_str().unwrap_or_default().is_empty() {
+        bail!("URL is missing a {}: {}", scheme.host_description(), path);
+    }
+    if url.path().trim_start_matches('/').is_empty() {
+        bail!("URL is missing a file path: {}", path);
+    }
+    Ok(url)
+}
+
+/// Whether a remote file has to be read through gensketch's own htslib backend, which caches
+/// blocks and sends the headers configured for the file's host.
+fn needs_remote_backend(url: &str) -> bool {
+    is_scheme_registered() && (get_block_cache().is_some() || get_url_headers(url).is_some())
+}
+
+/// Get the path which htslib should open a file with.
+///
+/// Remote files are read through the on-disk block cache if it's enabled, and with any headers
+/// configured for their host. Local paths are returned unchanged.
+pub fn get_htslib_path<P: AsRef<Path>>(path: P) -> String {
+    let path = path.as_ref().to_string_lossy();
+    if is_remote(path.as_ref()) && needs_remote_backend(&path) {
+        format!("{}{}", REMOTE_PREFIX, path)
+    } else {
+        path.to_string()
+    }
+}
+
+/// Parse a remote path into the URL which htslib should open it with (see `get_htslib_path`).
+pub fn get_htslib_url<P: AsRef<Path>>(path: P) -> Result<Url> {
+    let url = parse_remote_url(&path)?;
+    if !needs_remote_backend(url.as_str()) {
+        return Ok(url);
+    }
+    let backend_url = format!("{}{}", REMOTE_PREFIX, url);
+    Url::parse(&backend_url).with_context(|| format!("Invalid URL: {}", backend_url))
+}
+
+/// Where AWS credentials were found.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AwsCredentialSource {
+    Environment,
+    SharedCredentialsFile(PathBuf),
+}
+
+impl fmt::Display for AwsCredentialSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AwsCredentialSource::Environment => write!(f, "environment variables"),
+            AwsCredentialSource::SharedCredentialsFile(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Find AWS credentials in the same locations as htslib's S3 plugin.
+///
+/// # Arguments
+///
+/// * `get_var` - Looks up an environment variable.
+fn find_aws_credentials_with<F: Fn(&str) -> Option<String>>(
+    get_var: F,
+) -> Option<AwsCredentialSource> {
+    if get_var("AWS_ACCESS_KEY_ID").is_some() {
+        return Some(AwsCredentialSource::Environment);
+    }
+    let credentials_path = match get_var("AWS_SHARED_CREDENTIALS_FILE") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(get_var("HOME")?).join(".aws").join("credentials"),
+    };
+    if credentials_path.is_file() {
+        return Some(AwsCredentialSource::SharedCredentialsFile(credentials_path));
+    }
+    None
+}
+
+/// Find AWS credentials in the environment or the shared credentials file (~/.aws/credentials).
+pub fn find_aws_credentials() -> Option<AwsCredentialSource> {
+    find_aws_credentials_with(|name| env::var(name).ok())
+}
+
+/// Environment variables which configure htslib's S3 plugin.
+fn get_s3_env_vars(config: &S3Config) -> Vec<(&'static str, String)> {
+    [
+        ("AWS_PROFILE", &config.profile),
+        ("AWS_DEFAULT_REGION", &config.region),
+        ("HTS_S3_HOST", &config.endpoint),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name, value.clone()?)))
+    .collect()
+}
+
+/// Get the file which GCS access tokens are written to for htslib (see `GcsTokenManager`).
+pub fn get_gcs_token_path() -> PathBuf {
+    env::temp_dir().join(format!("gensketch-gcs-token-{}.json", process::id()))
+}
+
+/// Set the environment variables which configure access to S3 and GCS through htslib.
+///
+/// htslib and libcurl read the environment on reader threads, and setting a variable while
+/// another thread reads the environment is a data race, so this must be called at startup before
+/// any threads are spawned. htslib's plugins also only read most variables when they're first
+/// used, so changes to these settings take effect when the app is restarted.
+///
+/// S3 settings which are omitted from the config are left to the standard AWS environment
+/// variables and config files.
+pub fn set_remote_env_vars(user_config: &UserConfig) {
+    for (name, value) in get_s3_env_vars(&user_config.s3) {
+        env::set_var(name, value);
+    }
+    if let Some(project) = &user_config.gcs.requester_pays_project {
+        env::set_var("GCS_REQUESTER_PAYS_PROJECT", project);
+    }
+    // The token file doesn't exist until a gs:// file is opened, which htslib allows for
+    if env::var_os(GCS_OAUTH_TOKEN_VAR).is_none() {
+        env::set_var(HTS_AUTH_LOCATION_VAR, get_gcs_token_path());
+    }
+    match find_aws_credentials() {
+        Some(source) => log::info!("Using AWS credentials from {}", source),
+        None => log::warn!("No AWS credentials found, only public S3 buckets can be opened"),
+    }
+}
+
+/// Configure the on-disk cache which remote files are read through.
+///
+/// Remote files aren't cached if the cache directory can't be opened.
+pub fn configure_remote_cache(config: &RemoteCacheConfig) {
+    if !config.enabled {
+        set_block_cache(None);
+        return;
+    }
+    let dir = config.dir.clone().unwrap_or_else(get_remote_cache_dir);
+    match BlockCache::new(&dir, config.max_size) {
+        Ok(cache) => {
+            log::info!("Caching remote files in {} ({} bytes used)", dir.display(), cache.size());
+            set_block_cache(Some(Arc::new(cache)));
+        }
+        Err(err) => {
+            log::error!(
+                "Failed to open remote file cache, remote files won't be cached: {:#}",
+                err
+            );
+            set_block_cache(None);
+        }
+    }
+}
+
+/// Normalize a host (optionally with a port) to the form which headers are looked up by.
+fn normalize_host(host: &str) -> Result<String> {
+    let url = Url::parse(&format!("https://{}/", host.trim())).ok().filter(|url| {
+        url.path() == "/"
+            && url.username().is_empty()
+            && url.query().is_none()
+            && url.fragment().is_none()
+    });
+    let host_str = url.as_ref().and_then(|url| url.host_str()).unwrap_or_default();
+    if host_str.is_empty() {
+        bail!("Invalid host (expected e.g data.example.org or data.example.org:8443): {}", host);
+    }
+    // Default ports are omitted since they're omitted from parsed URLs
+    match url.as_ref().and_then(|url| url.port()) {
+        Some(port) => Ok(format!("{}:{}", host_str, port)),
+        None => Ok(host_str.to_owned()),
+    }
+}
+
+/// Whether a header name only contains the characters allowed in HTTP tokens.
+fn is_valid_header_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|char| char.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(char))
+}
+
+fn format_header(host: &str, name: &str, value: &str) -> Result<String> {
+    if !is_valid_header_name(name) {
+        bail!("Invalid header name for {}: {:?}", host, name);
+    }
+    let value = value.trim();
+    if value.contains(['\r', '\n', '\0']) {
+        bail!("Header {} for {} contains a line break or null character", name, host);
+    }
+    Ok(format!("{}: {}", name, value))
+}
+
+/// Build the header lines which are sent with requests to a host.
+///
+/// Errors if a header couldn't be sent as is (e.g because it contains a line break).
+fn get_host_auth_headers(config: &HostAuthConfig) -> Result<Vec<String>> {
+    let mut headers = Vec::new();
+    if let Some(token) = &config.bearer_token {
+        if token.trim().is_empty() {
+            bail!("Bearer token for {} is empty", config.host);
+        }
+        if config.headers.keys().any(|name| name.eq_ignore_ascii_case("Authorization")) {
+            bail!("{} has both a bearer token and an Authorization header", config.host);
+        }
+        let value = format!("Bearer {}", token.trim());
+        headers.push(format_header(&config.host, "Authorization", &value)?);
+    }
+    for (name, value) in config.headers.iter() {
+        headers.push(format_header(&config.host, name, value)?);
+    }
+    Ok(headers)
+}
+
+/// Check that a host's auth config can be used to open remote files.
+pub fn validate_host_auth(config: &HostAuthConfig) -> Result<()> {
+    normalize_host(&config.host)?;
+    get_host_auth_headers(config)?;
+    Ok(())
+}
+
+/// Configure the headers (e.g bearer tokens) which are sent with requests for http(s) files on
+/// each host. Replaces any previously configured headers.
+pub fn configure_host_auth(configs: &[HostAuthConfig]) -> Result<()> {
+    let mut host_headers = BTreeMap::new();
+    for config in configs.iter() {
+        let host = normalize_host(&config.host)?;
+        if host_headers.insert(host, get_host_auth_headers(config)?).is_some() {
+            bail!("Auth is configured more than once for {}", config.host);
+        }
+    }
+    set_host_headers(host_headers);
+    Ok(())
+}
+
+/// An OAuth access token for GCS.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct GcsToken {
+    token: String,
+    expiry: SystemTime,
+}
+
+impl GcsToken {
+    fn needs_refresh(&self, now: SystemTime) -> bool {
+        now + GCS_TOKEN_REFRESH_MARGIN >= self.expiry
+    }
+
+    /// Write the token to a file in the JSON format which htslib reads from HTS_AUTH_LOCATION.
+    ///
+    /// The file is replaced atomically so that htslib never reads a partially written token.
+    fn write_auth_file(&self, path: &Path) -> Result<()> {
+        let expiry = self.expiry.duration_since(UNIX_EPOCH)?.as_secs();
+        let contents = json!({ "token": self.token, "token_type": "Bearer", "expiry": expiry });
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // The token grants access to the user's buckets so it's only readable by the user
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(contents.to_string().as_bytes())?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// Keeps a GCS access token up to date in a file which htslib reads.
+///
+/// Tokens are only fetched once a gs:// file is opened, after which they're refreshed in the
+/// background for the rest of the session.
+#[derive(Debug)]
+pub struct GcsTokenManager {
+    /// Command which prints an access token.
+    token_command: Vec<String>,
+    token_path: PathBuf,
+    token: Mutex<Option<GcsToken>>,
+    is_refreshing: AtomicBool,
+}
+
+impl GcsTokenManager {
+    pub fn new<P: Into<PathBuf>>(config: &GcsConfig, token_path: P) -> Self {
+        Self {
+            token_command: config.token_command.clone(),
+            token_path: token_path.into(),
+            token: Mutex::new(None),
+            is_refreshing: AtomicBool::new(false),
+        }
+    }
+
+    fn fetch_token(&self) -> Result<GcsToken> {
+        let (program, args) =
+            self.token_command.split_first().context("GCS token command is empty")?;
+        let command_string = self.token_command.join(" ");
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .with_context(|| format!("Failed to run {}", command_string))?;
+        if !output.status.success() {
+            bail!("{} failed: {}", command_string, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        let token = String::from_utf8(output.stdout)?.trim().to_owned();
+        if token.is_empty() {
+            bail!("{} didn't print an access token", command_string);
+        }
+        Ok(GcsToken { token, expiry: SystemTime::now() + GCS_TOKEN_LIFETIME })
+    }
+
+    /// Fetch a new token if there's no token yet or the current token is about to expire.
+    pub fn refresh_if_needed(&self) -> Result<()> {
+        let mut token = self.token.lock();
+        if let Some(token) = &*token {
+            if !token.needs_refresh(SystemTime::now()) {
+                return Ok(());
+            }
+        }
+        log::info!("Fetching GCS access token");
+        let new_token = self.fetch_token()?;
+        new_token.write_auth_file(&self.token_path)?;
+        *token = Some(new_token);
+        Ok(())
+    }
+
+    /// Make sure that a valid token is available to htslib and keep it refreshed.
+    ///
+    /// Does nothing if the user has provided a static token via GCS_OAUTH_TOKEN.
+    pub fn start(self: &Arc<Self>) -> Result<()> {
+        if env::var_os(GCS_OAUTH_TOKEN_VAR).is_some() {
+            return Ok(());
+        }
+        self.refresh_if_needed().context("Failed to get a GCS access token")?;
+        if !self.is_refreshing.swap(true, Ordering::SeqCst) {
+            let manager = Arc::clone(self);
+            thread::spawn(move || loop {
+                thread::sleep(GCS_TOKEN_CHECK_INTERVAL);
+                if let Err(err) = manager.refresh_if_needed() {
+                    log::error!("Failed to refresh GCS access token: {:#}", err);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_get_remote_scheme() {
+        assert_eq!(get_remote_scheme("s3://bucket/sample.bam"), Some(RemoteScheme::S3));
+        assert_eq!(get_remote_scheme(PathBuf::from("s3://bucket/ref.fa")), Some(RemoteScheme::S3));
+        assert_eq!(get_remote_scheme("gs://bucket/sample.bam"), Some(RemoteScheme::Gcs));
+        assert_eq!(get_remote_scheme("https://host/sample.bam"), Some(RemoteScheme::Https));
+        assert_eq!(get_remote_scheme("/data/s3://sample.bam"), None);
+        assert!(!is_remote(get_test_data_path("fake-genome.tiny.bam")));
+    }
+
+    #[test]
+    fn test_parse_remote_url() {
+        let url = parse_remote_url("s3://bucket/dir/sample.bam").unwrap();
+        assert_eq!(url.host_str(), Some("bucket"));
+        assert_eq!(url.path(), "/dir/sample.bam");
+        assert!(parse_remote_url("s3://bucket").is_err());
+        assert!(parse_remote_url("s3://bucket/").is_err());
+        assert!(parse_remote_url("s3:///sample.bam").is_err());
+        assert!(parse_remote_url("gs://bucket/sample.bam").is_ok());
+        assert!(parse_remote_url("https://data.example.org/sample.bam?X-Amz-Signature=abc").is_ok());
+        assert!(parse_remote_url("https://data.example.org/").is_err());
+        assert!(parse_remote_url("ftp://data.example.org/sample.bam").is_err());
+    }
+
+    #[test]
+    fn test_get_htslib_path_of_local_file() {
+        let path = get_test_data_path("fake-genome.fa");
+        assert_eq!(get_htslib_path(&path), path.to_string_lossy());
+    }
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(normalize_host("Data.Example.org").unwrap(), "data.example.org");
+        assert_eq!(normalize_host("data.example.org:8443").unwrap(), "data.example.org:8443");
+        assert_eq!(normalize_host("data.example.org:443").unwrap(), "data.example.org");
+        for host in ["", "data.example.org/path", "user@data.example.org", "https://host"] {
+            assert!(normalize_host(host).is_err(), "{}", host);
+        }
+    }
+
+    #[test]
+    fn test_get_host_auth_headers() {
+        let mut config = HostAuthConfig {
+            host: "data.example.org".to_owned(),
+            bearer_token: Some(" token \n".to_owned()),
+            headers: BTreeMap::from([("X-Api-Key".to_owned(), "key".to_owned())]),
+        };
+        assert_eq!(
+            get_host_auth_headers(&config).unwrap(),
+            vec!["Authorization: Bearer token".to_owned(), "X-Api-Key: key".to_owned()]
+        );
+        assert!(validate_host_auth(&config).is_ok());
+        config.headers.insert("authorization".to_owned(), "Basic abc".to_owned());
+        assert!(get_host_auth_headers(&config).is_err());
+        config.bearer_token = None;
+        assert_eq!(get_host_auth_headers(&config).unwrap().len(), 2);
+        config.headers.insert("X-Injected".to_owned(), "a\r\nHost: evil".to_owned());
+        assert!(get_host_auth_headers(&config).is_err());
+        config.headers = BTreeMap::from([("Bad Name".to_owned(), "value".to_owned())]);
+        assert!(get_host_auth_headers(&config).is_err());
+        config.headers.clear();
+        config.bearer_token = Some("  ".to_owned());
+        assert!(get_host_auth_headers(&config).is_err());
+    }
+
+    #[test]
+    fn test_find_aws_credentials() {
+        let vars = HashMap::from([("AWS_ACCESS_KEY_ID", "key".to_owned())]);
+        let find = |vars: &HashMap<&str, String>| {
+            find_aws_credentials_with(|name| vars.get(name).cloned())
+        };
+        assert_eq!(find(&vars), Some(AwsCredentialSource::Environment));
+        let credentials_path = get_test_data_path("fake-genome.fa");
+        let vars = HashMap::from([(
+            "AWS_SHARED_CREDENTIALS_FILE",
+            credentials_path.to_string_lossy().to_string(),
+        )]);
+        assert_eq!(find(&vars), Some(AwsCredentialSource::SharedCredentialsFile(credentials_path)));
+        let vars = HashMap::from([("HOME", "/nonexistent".to_owned())]);
+        assert_eq!(find(&vars), None);
+        assert_eq!(find(&HashMap::new()), None);
+    }
+
+    #[test]
+    fn test_get_s3_env_vars() {
+        let config = S3Config {
+            profile: Some("clinical".to_owned()),
+            region: None,
+            endpoint: Some("minio.local:9000".to_owned()),
+        };
+        assert_eq!(
+            get_s3_env_vars(&config),
+            vec![
+                ("AWS_PROFILE", "clinical".to_owned()),
+                ("HTS_S3_HOST", "minio.local:9000".to_owned())
+            ]
+        );
+        assert_eq!(get_s3_env_vars(&S3Config::default()), vec![]);
+    }
+
+    #[test]
+    fn test_gcs_token_needs_refresh() {
+        let now = SystemTime::now();
+        let token = GcsToken { token: "token".to_owned(), expiry: now + GCS_TOKEN_LIFETIME };
+        assert!(!token.needs_refresh(now));
+        assert!(token.needs_refresh(now + GCS_TOKEN_LIFETIME - GCS_TOKEN_REFRESH_MARGIN));
+    }
+
+    #[test]
+    fn test_refresh_gcs_token() {
+        let token_path = env::temp_dir().join("gensketch-test-gcs-token.json");
+        let config = GcsConfig {
+            token_command: vec!["echo".to_owned(), "ya29.token".to_owned()],
+            requester_pays_project: None,
+        };
+        let manager = GcsTokenManager::new(&config, &token_path);
+        manager.refresh_if_needed().unwrap();
+        let token = manager.token.lock().clone().unwrap();
+        assert_eq!(token.token, "ya29.token");
+        let contents: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&token_path).unwrap()).unwrap();
+        assert_eq!(contents["token"], "ya29.token");
+        assert_eq!(contents["expiry"], token.expiry.duration_since(UNIX_EPOCH).unwrap().as_secs());
+        // The token is still valid so it isn't fetched again
+        manager.refresh_if_needed().unwrap();
+        assert_eq!(manager.token.lock().clone().unwrap(), token);
+        fs::remove_file(token_path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_gcs_token_command() {
+        let token_path = env::temp_dir().join("gensketch-test-failed-gcs-token.json");
+        for token_command in [vec![], vec!["false".to_owned()], vec!["true".to_owned()]] {
+            let config = GcsConfig { token_command, requester_pays_project: None };
+            let manager = GcsTokenManager::new(&config, &token_path);
+            assert!(manager.refresh_if_needed().is_err());
+        }
+        assert!(!token_path.exists());
+    }
+}
@@ -0,0 +1,277 @@
+//! Storing fixed-size blocks of remote files on disk, with least recently used blocks evicted
+//! once the cache exceeds a size limit.
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use uuid::Uuid;
+
+/// Size of the blocks which remote files are fetched and cached in. Larger than a BGZF block
+/// (64 KiB max compressed) so that most BGZF blocks only span one or two cache blocks.
+pub const BLOCK_SIZE: u64 = 256 * 1024;
+
+const BLOCK_EXTENSION: &str = "block";
+
+/// 64-bit FNV-1a hash. Used rather than std's hasher, whose output may change between Rust
+/// releases, so that data cached by previous versions is still found.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Identifies a block by the URL of the file and the block's offset within the file.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct BlockKey {
+    url_hash: u64,
+    offset: u64,
+}
+
+impl BlockKey {
+    pub fn new(url: &str, offset: u64) -> Self {
+        Self { url_hash: fnv1a(url.as_bytes()), offset }
+    }
+
+    fn filename(&self) -> String {
+        format!("{:016x}-{:x}.{}", self.url_hash, self.offset, BLOCK_EXTENSION)
+    }
+
+    fn from_filename(filename: &str) -> Option<Self> {
+        let stem = filename.strip_suffix(BLOCK_EXTENSION)?.strip_suffix('.')?;
+        let (url_hash, offset) = stem.split_once('-')?;
+        Some(Self {
+            url_hash: u64::from_str_radix(url_hash, 16).ok()?,
+            offset: u64::from_str_radix(offset, 16).ok()?,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct CachedBlock {
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct LruState {
+    blocks: HashMap<BlockKey, CachedBlock>,
+    /// Blocks ordered from least to most recently used.
+    lru: BTreeMap<u64, BlockKey>,
+    /// Total size of the cached blocks in bytes.
+    size: u64,
+    /// Incremented whenever a block is used.
+    clock: u64,
+}
+
+impl LruState {
+    fn touch(&mut self, key: &BlockKey) -> bool {
+        let block = match self.blocks.get_mut(key) {
+            Some(block) => block,
+            None => return false,
+        };
+        self.lru.remove(&block.last_used);
+        self.clock += 1;
+        block.last_used = self.clock;
+        self.lru.insert(self.clock, *key);
+        true
+    }
+
+    fn insert(&mut self, key: BlockKey, size: u64) {
+        self.remove(&key);
+        self.clock += 1;
+        self.blocks.insert(key, CachedBlock { size, last_used: self.clock });
+        self.lru.insert(self.clock, key);
+        self.size += size;
+    }
+
+    fn remove(&mut self, key: &BlockKey) {
+        if let Some(block) = self.blocks.remove(key) {
+            self.lru.remove(&block.last_used);
+            self.size -= block.size;
+        }
+    }
+
+    fn pop_least_recently_used(&mut self) -> Option<BlockKey> {
+        let key = *self.lru.values().next()?;
+        self.remove(&key);
+        Some(key)
+    }
+}
+
+/// An on-disk cache of blocks of remote files.
+///
+/// Blocks are looked up by a key for the version of the file (see hfile), so the blocks of a file
+/// which has been replaced are never read, and are eventually evicted. Blocks which were cached in
+/// previous sessions are reused.
+#[derive(Debug)]
+pub struct BlockCache {
+    pub dir: PathBuf,
+    /// Maximum total size of the cached blocks in bytes.
+    pub max_size: u64,
+    state: Mutex<LruState>,
+}
+
+impl BlockCache {
+    /// Open a cache directory, creating it if it doesn't exist.
+    pub fn new<P: Into<PathBuf>>(dir: P, max_size: u64) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        let mut existing_blocks = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let key = match BlockKey::from_filename(&entry.file_name().to_string_lossy()) {
+                Some(key) => key,
+                None => continue,
+            };
+            let metadata = entry.metadata()?;
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            existing_blocks.push((modified, key, metadata.len()));
+        }
+        // Blocks from previous sessions are ordered by when they were cached since access times
+        // aren't tracked on disk.
+        existing_blocks.sort();
+        let mut state = LruState::default();
+        for (_, key, size) in existing_blocks {
+            state.insert(key, size);
+        }
+        let cache = Self { dir, max_size, state: Mutex::new(state) };
+        cache.evict(&mut cache.state.lock())?;
+        Ok(cache)
+    }
+
+    fn block_path(&self, key: &BlockKey) -> PathBuf {
+        self.dir.join(key.filename())
+    }
+
+    /// Get the block of a file which starts at an offset, if it's cached.
+    pub fn get(&self, url: &str, offset: u64) -> Option<Vec<u8>> {
+        let key = BlockKey::new(url, offset);
+        if !self.state.lock().touch(&key) {
+            return None;
+        }
+        match fs::read(self.block_path(&key)) {
+            Ok(block) => Some(block),
+            Err(err) => {
+                // The block may have been evicted by another reader in the meantime
+                log::debug!("Failed to read cached block {}: {}", key.filename(), err);
+                self.state.lock().remove(&key);
+                None
+            }
+        }
+    }
+
+    /// Cache the block of a file which starts at an offset.
+    pub fn insert(&self, url: &str, offset: u64, block: &[u8]) -> Result<()> {
+        let key = BlockKey::new(url, offset);
+        let path = self.block_path(&key);
+        // Written to a temporary file first so that readers never see a partially written block
+        let tmp_path = self.dir.join(format!("{}.tmp", Uuid::new_v4()));
+        fs::write(&tmp_path, block)
+            .with_context(|| format!("Failed to write cached block {}", tmp_path.display()))?;
+        let mut state = self.state.lock();
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Failed to write cached block {}", path.display()))?;
+        state.insert(key, block.len() as u64);
+        self.evict(&mut state)
+    }
+
+    /// Remove the least recently used blocks until the cache is within its size limit.
+    fn evict(&self, state: &mut LruState) -> Result<()> {
+        while state.size > self.max_size {
+            let key = match state.pop_least_recently_used() {
+                Some(key) => key,
+                None => break,
+            };
+            let path = self.block_path(&key);
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to evict cached block {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Number of cached blocks.
+    pub fn len(&self) -> usize {
+        self.state.lock().blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total size of the cached blocks in bytes.
+    pub fn size(&self) -> u64 {
+        self.state.lock().size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const URL: &str = "s3://bucket/reads.bam";
+
+    fn temp_cache_dir() -> PathBuf {
+        std::env::temp_dir().join(Uuid::new_v4().to_string())
+    }
+
+    #[test]
+    fn test_block_key_filename() {
+        let key = BlockKey::new(URL, 3 * BLOCK_SIZE);
+        assert_eq!(BlockKey::from_filename(&key.filename()), Some(key));
+        assert_ne!(BlockKey::new("s3://bucket/other.bam", 3 * BLOCK_SIZE), key);
+        assert_eq!(BlockKey::from_filename("readme.txt"), None);
+        assert_eq!(BlockKey::from_filename("abc.tmp"), None);
+    }
+
+    #[test]
+    fn test_get_and_insert() {
+        let cache = BlockCache::new(temp_cache_dir(), 1000).unwrap();
+        assert_eq!(cache.get(URL, 0), None);
+        cache.insert(URL, 0, b"ACGT").unwrap();
+        cache.insert(URL, BLOCK_SIZE, b"TT").unwrap();
+        assert_eq!(cache.get(URL, 0), Some(b"ACGT".to_vec()));
+        assert_eq!(cache.get(URL, BLOCK_SIZE), Some(b"TT".to_vec()));
+        assert_eq!(cache.get("gs://bucket/reads.bam", 0), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.size(), 6);
+        // Replacing a block doesn't double count its size
+        cache.insert(URL, 0, b"ACG").unwrap();
+        assert_eq!(cache.size(), 5);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = BlockCache::new(temp_cache_dir(), 10).unwrap();
+        cache.insert(URL, 0, b"AAAA").unwrap();
+        cache.insert(URL, BLOCK_SIZE, b"CCCC").unwrap();
+        cache.get(URL, 0).unwrap();
+        cache.insert(URL, 2 * BLOCK_SIZE, b"GGGG").unwrap();
+        assert_eq!(cache.get(URL, BLOCK_SIZE), None);
+        assert_eq!(cache.get(URL, 0), Some(b"AAAA".to_vec()));
+        assert_eq!(cache.get(URL, 2 * BLOCK_SIZE), Some(b"GGGG".to_vec()));
+        assert_eq!(cache.size(), 8);
+        assert!(!cache.block_path(&BlockKey::new(URL, BLOCK_SIZE)).exists());
+    }
+
+    #[test]
+    fn test_reopen_cache() {
+        let dir = temp_cache_dir();
+        let cache = BlockCache::new(&dir, 100).unwrap();
+        cache.insert(URL, 0, b"ACGT").unwrap();
+        cache.insert(URL, BLOCK_SIZE, b"ACGT").unwrap();
+        fs::write(dir.join("unrelated.txt"), "").unwrap();
+        drop(cache);
+        let cache = BlockCache::new(&dir, 100).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(URL, 0), Some(b"ACGT".to_vec()));
+        // Reopening with a smaller limit evicts blocks
+        let cache = BlockCache::new(&dir, 4).unwrap();
+        assert_eq!(cache.len(), 1);
+    }
+}
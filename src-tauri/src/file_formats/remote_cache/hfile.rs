@@ -0,0 +1,612 @@
+//! An htslib file backend which reads remote files through the block cache.
+//!
+//! htslib opens any path with the `gensketch:` scheme (e.g `gensketch:s3://bucket/reads.bam`)
+//! using this backend, which opens the rest of the path with htslib's own backends (sending any
+//! headers configured for the host) and caches every block that it reads. Index files are found
+//! by appending to the path, so they're also read through the backend.
+//!
+//! The backend is written against htslib's plugin API (hfile_internal.h), which isn't part of its
+//! stable API, so it's only registered if the linked htslib is the release which it was written
+//! against.
+use std::collections::BTreeMap;
+use std::ffi::{c_void, CStr, CString};
+use std::io::{self, Read};
+use std::iter;
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Once};
+
+use anyhow::{bail, Result};
+use parking_lot::{const_rwlock, RwLock};
+use rust_htslib::htslib;
+use url::Url;
+
+use crate::file_formats::remote_cache::block_cache::{BlockCache, BLOCK_SIZE};
+
+/// Prefix of paths which are read through this backend.
+pub const REMOTE_PREFIX: &str = "gensketch:";
+
+const SEEK_SET: c_int = 0;
+const SEEK_CUR: c_int = 1;
+const SEEK_END: c_int = 2;
+
+/// htslib release which the plugin API structs below were copied from (vendored by hts-sys, which
+/// is pinned in Cargo.toml).
+const SUPPORTED_HTSLIB_VERSION: &str = "1.16";
+
+// These are part of htslib's plugin API (hfile_internal.h) so they aren't in hts-sys' bindings.
+extern "C" {
+    fn hfile_init(struct_size: usize, mode: *const c_char, capacity: usize) -> *mut htslib::hFILE;
+    fn hfile_add_scheme_handler(scheme: *const c_char, handler: *const SchemeHandler);
+    fn hfile_always_remote(filename: *const c_char) -> c_int;
+    fn hread2(fp: *mut htslib::hFILE, buffer: *mut c_void, nbytes: usize, nread: usize) -> isize;
+}
+
+/// Matches `struct hFILE_backend` in hfile_internal.h.
+#[repr(C)]
+struct Backend {
+    read: unsafe extern "C" fn(*mut htslib::hFILE, *mut c_void, usize) -> isize,
+    write: unsafe extern "C" fn(*mut htslib::hFILE, *const c_void, usize) -> isize,
+    seek: unsafe extern "C" fn(*mut htslib::hFILE, htslib::off_t, c_int) -> htslib::off_t,
+    flush: Option<unsafe extern "C" fn(*mut htslib::hFILE) -> c_int>,
+    close: unsafe extern "C" fn(*mut htslib::hFILE) -> c_int,
+}
+
+/// Matches `struct hFILE_scheme_handler` in hfile_internal.h.
+#[repr(C)]
+struct SchemeHandler {
+    open: unsafe extern "C" fn(*const c_char, *const c_char) -> *mut htslib::hFILE,
+    isremote: unsafe extern "C" fn(*const c_char) -> c_int,
+    provider: *const c_char,
+    priority: c_int,
+}
+
+// SAFETY: The provider string is a static which is never mutated.
+unsafe impl Sync for SchemeHandler {}
+
+static BACKEND: Backend = Backend {
+    read: remote_read,
+    write: remote_write,
+    seek: remote_seek,
+    flush: None,
+    close: remote_close,
+};
+
+static SCHEME_HANDLER: SchemeHandler = SchemeHandler {
+    open: remote_open,
+    isremote: hfile_always_remote,
+    provider: b"gensketch\0".as_ptr() as *const c_char,
+    priority: 50,
+};
+
+static SCHEME: &[u8] = b"gensketch\0";
+
+static REGISTER_SCHEME: Once = Once::new();
+
+/// Whether the scheme was registered, i.e the linked htslib is supported.
+static SCHEME_REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// Cache which newly opened files are read through, or None if caching is disabled.
+static BLOCK_CACHE: RwLock<Option<Arc<BlockCache>>> = const_rwlock(None);
+
+/// Header lines (e.g `Authorization: Bearer ...`) which are sent with HTTP requests, keyed by
+/// lowercase host or `host:port`.
+static HOST_HEADERS: RwLock<BTreeMap<String, Vec<String>>> = const_rwlock(BTreeMap::new());
+
+/// Whether an htslib version string (e.g `1.16`, `1.16.1` or `1.16-12-gabcdef` for a git build)
+/// is a build of the supported release.
+fn is_supported_htslib_version(version: &str) -> bool {
+    let release = version.split('-').next().unwrap_or_default();
+    release == SUPPORTED_HTSLIB_VERSION
+        || release
+            .strip_prefix(SUPPORTED_HTSLIB_VERSION)
+            .map_or(false, |rest| rest.starts_with('.'))
+}
+
+fn check_htslib_version() -> Result<()> {
+    // SAFETY: hts_version returns a pointer to a static string.
+    let version = unsafe { CStr::from_ptr(htslib::hts_version()) }.to_string_lossy();
+    if !is_supported_htslib_version(&version) {
+        bail!(
+            "htslib {} is linked but the remote file backend requires htslib {}",
+            version,
+            SUPPORTED_HTSLIB_VERSION
+        );
+    }
+    Ok(())
+}
+
+fn register_scheme() {
+    REGISTER_SCHEME.call_once(|| {
+        if let Err(err) = check_htslib_version() {
+            log::error!("Remote files won't be cached or sent with custom headers: {:#}", err);
+            return;
+        }
+        // SAFETY: The scheme and handler are statics so they outlive htslib's scheme table, which
+        // stores pointers to them. Scheme handlers can only be added once htslib has initialized
+        // its table, which happens the first time that its plugins are queried.
+        unsafe {
+            htslib::hfile_has_plugin(SCHEME_HANDLER.provider);
+            hfile_add_scheme_handler(SCHEME.as_ptr() as *const c_char, &SCHEME_HANDLER);
+        }
+        SCHEME_REGISTERED.store(true, Ordering::SeqCst);
+    });
+}
+
+/// Whether `gensketch:` paths can be opened. False if the linked htslib isn't supported, or if
+/// neither a cache nor host headers have been set yet.
+pub fn is_scheme_registered() -> bool {
+    SCHEME_REGISTERED.load(Ordering::SeqCst)
+}
+
+/// Set the cache which `gensketch:` paths are read through, registering the scheme with htslib if
+/// necessary. Files which are already open keep using the previous cache.
+pub fn set_block_cache(cache: Option<Arc<BlockCache>>) {
+    register_scheme();
+    *BLOCK_CACHE.write() = cache;
+}
+
+pub fn get_block_cache() -> Option<Arc<BlockCache>> {
+    BLOCK_CACHE.read().clone()
+}
+
+/// Set the headers which are sent with requests to each host when opening `gensketch:` paths.
+///
+/// Header lines must already be validated since they're passed to libcurl as is.
+pub fn set_host_headers(host_headers: BTreeMap<String, Vec<String>>) {
+    register_scheme();
+    *HOST_HEADERS.write() = host_headers;
+}
+
+fn find_url_headers(
+    host_headers: &BTreeMap<String, Vec<String>>,
+    url: &str,
+) -> Option<Vec<String>> {
+    let url = Url::parse(url).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    let host = url.host_str()?.to_lowercase();
+    url.port()
+        .and_then(|port| host_headers.get(&format!("{}:{}", host, port)))
+        .or_else(|| host_headers.get(&host))
+        .cloned()
+}
+
+/// Get the headers which are sent with requests for an http(s) URL, or None if none are
+/// configured for its host. A `host:port` entry takes precedence over an entry for the host.
+pub fn get_url_headers(url: &str) -> Option<Vec<String>> {
+    find_url_headers(&HOST_HEADERS.read(), url)
+}
+
+/// Open a URL with htslib's own backends, sending any headers configured for its host.
+///
+/// # Safety
+///
+/// `mode` must be a valid hopen mode.
+unsafe fn hopen_with_headers(url: &str, mode: &CStr) -> *mut htslib::hFILE {
+    let c_url = match CString::new(url) {
+        Ok(c_url) => c_url,
+        Err(_) => return ptr::null_mut(),
+    };
+    let headers = match get_url_headers(url) {
+        Some(headers) => headers,
+        None => return htslib::hopen(c_url.as_ptr(), mode.as_ptr()),
+    };
+    let headers = match headers.into_iter().map(CString::new).collect::<Result<Vec<_>, _>>() {
+        Ok(headers) => headers,
+        Err(_) => return ptr::null_mut(),
+    };
+    let header_ptrs: Vec<*const c_char> =
+        headers.iter().map(|header| header.as_ptr()).chain(iter::once(ptr::null())).collect();
+    // hopen only passes on its extra arguments if the mode contains a ':'
+    let mut mode = mode.to_bytes().to_vec();
+    mode.push(b':');
+    let mode = CString::new(mode).unwrap();
+    // Disabling auth tokens stops libcurl from replacing a configured Authorization header with
+    // the token from HTS_AUTH_LOCATION. libcurl copies the headers so they can be freed after.
+    htslib::hopen(
+        c_url.as_ptr(),
+        mode.as_ptr(),
+        b"httphdr:v\0".as_ptr() as *const c_char,
+        header_ptrs.as_ptr(),
+        b"auth_token_enabled\0".as_ptr() as *const c_char,
+        b"false\0".as_ptr() as *const c_char,
+        ptr::null::<c_char>(),
+    )
+}
+
+/// Read from an hFILE until `buffer` is full or the end of the file is reached.
+///
+/// Equivalent to htslib's `hread`, which is inline so it can't be called directly.
+///
+/// # Safety
+///
+/// `fp` must be a valid hFILE opened for reading.
+unsafe fn hread(fp: *mut htslib::hFILE, buffer: &mut [u8]) -> isize {
+    let available = (*fp).end.offset_from((*fp).begin) as usize;
+    let n = available.min(buffer.len());
+    ptr::copy_nonoverlapping((*fp).begin as *const u8, buffer.as_mut_ptr(), n);
+    (*fp).begin = (*fp).begin.add(n);
+    if n == buffer.len() || (*fp).mobile() == 0 {
+        n as isize
+    } else {
+        hread2(fp, buffer.as_mut_ptr() as *mut c_void, buffer.len(), n)
+    }
+}
+
+/// Reads a remote file from start to end (e.g to download it), sending any headers configured for
+/// its host. Bypasses the block cache since the file is only read once.
+pub struct RemoteReader {
+    url: String,
+    fp: *mut htslib::hFILE,
+}
+
+// SAFETY: The hFILE is owned by the reader and only accessed through &mut self.
+unsafe impl Send for RemoteReader {}
+
+impl RemoteReader {
+    pub fn open(url: &str) -> Result<Self> {
+        // SAFETY: "r" is a valid mode.
+        let fp = unsafe { hopen_with_headers(url, CStr::from_bytes_with_nul(b"r\0").unwrap()) };
+        if fp.is_null() {
+            bail!("Failed to open {}: {}", url, io::Error::last_os_error());
+        }
+        Ok(Self { url: url.to_owned(), fp })
+    }
+
+    /// Get the size of the file in bytes, or None if the server doesn't report it. Must be called
+    /// before the file is read.
+    pub fn size(&mut self) -> Result<Option<u64>> {
+        // SAFETY: fp is a valid hFILE until the reader is dropped.
+        let size = unsafe { htslib::hseek(self.fp, 0, SEEK_END) };
+        if size < 0 {
+            return Ok(None);
+        }
+        // SAFETY: fp is a valid hFILE until the reader is dropped.
+        if unsafe { htslib::hseek(self.fp, 0, SEEK_SET) } != 0 {
+            bail!("Failed to seek to the start of {}", self.url);
+        }
+        Ok(Some(size as u64))
+    }
+}
+
+impl Read for RemoteReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        // SAFETY: fp is a valid hFILE until the reader is dropped.
+        let num_read = unsafe { hread(self.fp, buffer) };
+        if num_read < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(num_read as usize)
+    }
+}
+
+impl Drop for RemoteReader {
+    fn drop(&mut self) {
+        // SAFETY: fp is valid and isn't used after being closed.
+        if unsafe { htslib::hclose(self.fp) } != 0 {
+            log::warn!("Failed to close {}", self.url);
+        }
+    }
+}
+
+/// Identifies a version of a remote file in the cache. The size is included so that the blocks of
+/// a file which has been replaced aren't mixed with its new contents (replacements of the same
+/// size aren't detected).
+fn get_cache_key(url: &str, size: u64) -> String {
+    format!("{}#{}", url, size)
+}
+
+/// A remote file which is read in blocks through the cache (if caching is enabled).
+struct RemoteFile {
+    url: String,
+    /// The file opened by htslib's own backend for the URL, which is only read from if a block
+    /// isn't cached.
+    inner: *mut htslib::hFILE,
+    inner_offset: u64,
+    offset: u64,
+    /// Size of the file when it was opened, or None if the server doesn't report it.
+    size: Option<u64>,
+    /// Blocks are only cached if the file's size is known, since the cache key includes it.
+    cache: Option<(Arc<BlockCache>, String)>,
+    /// Block containing the last read, so that small sequential reads aren't each read from disk.
+    current_block: Option<(u64, Vec<u8>)>,
+}
+
+impl RemoteFile {
+    /// Open a URL with htslib's own backends, reading it through a cache if one is given.
+    ///
+    /// # Safety
+    ///
+    /// `mode` must be a valid hopen mode.
+    unsafe fn open(url: String, mode: &CStr, cache: Option<Arc<BlockCache>>) -> Result<Self> {
+        let inner = hopen_with_headers(&url, mode);
+        if inner.is_null() {
+            bail!("Failed to open {}: {}", url, io::Error::last_os_error());
+        }
+        // The size is known once a remote file is opened, so this doesn't send another request
+        let end_offset = htslib::hseek(inner, 0, SEEK_END);
+        let (size, inner_offset) = match u64::try_from(end_offset) {
+            Ok(size) => (Some(size), size),
+            // The position of the inner file is unknown, so it's seeked before it's read
+            Err(_) => (None, u64::MAX),
+        };
+        let cache = cache.zip(size).map(|(cache, size)| (cache, get_cache_key(&url, size)));
+        Ok(Self { url, inner, inner_offset, offset: 0, size, cache, current_block: None })
+    }
+
+    /// Close the inner file, returning htslib's status.
+    fn close(self) -> c_int {
+        // SAFETY: inner is a valid hFILE and isn't used after being closed.
+        unsafe { htslib::hclose(self.inner) }
+    }
+
+    fn fetch_block(&mut self, block_offset: u64) -> Result<Vec<u8>> {
+        if let Some(block) =
+            self.cache.as_ref().and_then(|(cache, key)| cache.get(key, block_offset))
+        {
+            return Ok(block);
+        }
+        if self.inner_offset != block_offset {
+            // SAFETY: inner is a valid hFILE until this file is closed.
+            let offset =
+                unsafe { htslib::hseek(self.inner, block_offset as htslib::off_t, SEEK_SET) };
+            if offset < 0 {
+                bail!("Failed to seek to offset {} in {}", block_offset, self.url);
+            }
+            self.inner_offset = block_offset;
+        }
+        let mut block = vec![0; BLOCK_SIZE as usize];
+        // SAFETY: inner is a valid hFILE until this file is closed.
+        let num_read = unsafe { hread(self.inner, &mut block) };
+        if num_read < 0 {
+            bail!("Failed to read offset {} of {}", block_offset, self.url);
+        }
+        block.truncate(num_read as usize);
+        self.inner_offset += num_read as u64;
+        if let Some((cache, key)) = &self.cache {
+            if let Err(err) = cache.insert(key, block_offset, &block) {
+                log::warn!("Failed to cache block of {}: {:#}", self.url, err);
+            }
+        }
+        Ok(block)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        let block_offset = self.offset - self.offset % BLOCK_SIZE;
+        let block = match self.current_block.take() {
+            Some((offset, block)) if offset == block_offset => block,
+            _ => self.fetch_block(block_offset)?,
+        };
+        let start = (self.offset - block_offset) as usize;
+        let num_read = block.len().saturating_sub(start).min(buffer.len());
+        buffer[..num_read].copy_from_slice(&block[start..start + num_read]);
+        self.offset += num_read as u64;
+        self.current_block = Some((block_offset, block));
+        Ok(num_read)
+    }
+
+    fn seek(&mut self, offset: i64, whence: c_int) -> Result<u64> {
+        let new_offset = match whence {
+            SEEK_SET => offset,
+            SEEK_CUR => self.offset as i64 + offset,
+            SEEK_END => match self.size {
+                Some(size) => size as i64 + offset,
+                None => {
+                    // SAFETY: inner is a valid hFILE until this file is closed.
+                    let end_offset =
+                        unsafe { htslib::hseek(self.inner, offset as htslib::off_t, SEEK_END) };
+                    if end_offset < 0 {
+                        bail!("Failed to seek to the end of {}", self.url);
+                    }
+                    self.inner_offset = end_offset as u64;
+                    end_offset
+                }
+            },
+            _ => bail!("Invalid seek mode: {}", whence),
+        };
+        if new_offset < 0 {
+            bail!("Attempted to seek before the start of {}", self.url);
+        }
+        self.offset = new_offset as u64;
+        Ok(self.offset)
+    }
+}
+
+/// The hFILE struct which htslib allocates for a remote file.
+#[repr(C)]
+struct RemoteHFile {
+    base: htslib::hFILE,
+    file: *mut RemoteFile,
+}
+
+/// # Safety
+///
+/// `fp` must have been opened by `remote_open` and not yet closed.
+unsafe fn get_remote_file<'a>(fp: *mut htslib::hFILE) -> &'a mut RemoteFile {
+    &mut *(*(fp as *mut RemoteHFile)).file
+}
+
+unsafe extern "C" fn remote_open(
+    filename: *const c_char,
+    mode: *const c_char,
+) -> *mut htslib::hFILE {
+    let filename = CStr::from_ptr(filename).to_string_lossy();
+    let url = filename.strip_prefix(REMOTE_PREFIX).unwrap_or(&filename).to_owned();
+    let mode_str = CStr::from_ptr(mode);
+    if mode_str.to_bytes().iter().any(|char| *char == b'w' || *char == b'a') {
+        log::error!("Failed to open {}: remote files are read-only", filename);
+        return ptr::null_mut();
+    }
+    let file = match RemoteFile::open(url, mode_str, get_block_cache()) {
+        Ok(file) => file,
+        Err(err) => {
+            log::error!("{:#}", err);
+            return ptr::null_mut();
+        }
+    };
+    let fp = hfile_init(mem::size_of::<RemoteHFile>(), mode, 0);
+    if fp.is_null() {
+        file.close();
+        return ptr::null_mut();
+    }
+    (*(fp as *mut RemoteHFile)).file = Box::into_raw(Box::new(file));
+    (*fp).backend = &BACKEND as *const Backend as *const htslib::hFILE_backend;
+    fp
+}
+
+unsafe extern "C" fn remote_read(
+    fp: *mut htslib::hFILE,
+    buffer: *mut c_void,
+    nbytes: usize,
+) -> isize {
+    let buffer = slice::from_raw_parts_mut(buffer as *mut u8, nbytes);
+    let file = get_remote_file(fp);
+    match file.read(buffer) {
+        Ok(num_read) => num_read as isize,
+        Err(err) => {
+            log::error!("{:#}", err);
+            -1
+        }
+    }
+}
+
+unsafe extern "C" fn remote_write(
+    _fp: *mut htslib::hFILE,
+    _buffer: *const c_void,
+    _nbytes: usize,
+) -> isize {
+    -1
+}
+
+unsafe extern "C" fn remote_seek(
+    fp: *mut htslib::hFILE,
+    offset: htslib::off_t,
+    whence: c_int,
+) -> htslib::off_t {
+    let file = get_remote_file(fp);
+    match file.seek(offset, whence) {
+        Ok(offset) => offset as htslib::off_t,
+        Err(err) => {
+            log::error!("{:#}", err);
+            -1
+        }
+    }
+}
+
+unsafe extern "C" fn remote_close(fp: *mut htslib::hFILE) -> c_int {
+    let remote_fp = fp as *mut RemoteHFile;
+    let file = Box::from_raw((*remote_fp).file);
+    (*remote_fp).file = ptr::null_mut();
+    file.close()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use std::io::Read as _;
+
+    use rust_htslib::bam::{self, Read};
+    use url::Url;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    fn count_records(url: &str) -> usize {
+        let mut reader = bam::Reader::from_url(&Url::parse(url).unwrap()).unwrap();
+        reader.records().collect::<Result<Vec<_>, _>>().unwrap().len()
+    }
+
+    // htslib reads local files in the same way as remote files, so the backend is tested with a
+    // local file.
+    #[test]
+    fn test_read_through_cache() {
+        let cache_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let cache = Arc::new(BlockCache::new(cache_dir, 100 * BLOCK_SIZE).unwrap());
+        set_block_cache(Some(Arc::clone(&cache)));
+        let bam_path = get_test_data_path("fake-genome.reads.bam");
+        let path = bam_path.to_string_lossy();
+        let expected_num_records = count_records(&format!("file://{}", path));
+        assert!(expected_num_records > 0);
+
+        assert!(is_scheme_registered());
+        let cached_url = format!("{}{}", REMOTE_PREFIX, path);
+        assert_eq!(count_records(&cached_url), expected_num_records);
+        let num_blocks = cache.len();
+        assert_eq!(num_blocks as u64, std::fs::metadata(&bam_path).unwrap().len() / BLOCK_SIZE + 1);
+        // The second read is served from the cache
+        assert_eq!(count_records(&cached_url), expected_num_records);
+        assert_eq!(cache.len(), num_blocks);
+        let bam = std::fs::read(&bam_path).unwrap();
+        let cache_key = get_cache_key(&path, bam.len() as u64);
+        assert_eq!(cache.get(&cache_key, 0).unwrap()[..4], bam[..4]);
+    }
+
+    #[test]
+    fn test_replaced_file_isnt_read_from_cache() {
+        let cache_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let cache = Arc::new(BlockCache::new(cache_dir, 100 * BLOCK_SIZE).unwrap());
+        let path = std::env::temp_dir().join(format!("{}.txt", Uuid::new_v4()));
+        let read_file = || {
+            let url = path.to_string_lossy().to_string();
+            let mode = CStr::from_bytes_with_nul(b"r\0").unwrap();
+            // SAFETY: "r" is a valid mode.
+            let mut file =
+                unsafe { RemoteFile::open(url, mode, Some(Arc::clone(&cache))) }.unwrap();
+            let mut contents = vec![0; 100];
+            let num_read = file.read(&mut contents).unwrap();
+            contents.truncate(num_read);
+            assert_eq!(file.close(), 0);
+            contents
+        };
+        std::fs::write(&path, b"ACGT").unwrap();
+        assert_eq!(read_file(), b"ACGT");
+        std::fs::write(&path, b"TTTTTT").unwrap();
+        assert_eq!(read_file(), b"TTTTTT");
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_supported_htslib_version() {
+        // The vendored htslib must match the release which the backend was written against
+        check_htslib_version().unwrap();
+        assert!(is_supported_htslib_version("1.16"));
+        assert!(is_supported_htslib_version("1.16.1"));
+        assert!(is_supported_htslib_version("1.16-12-gabcdef"));
+        assert!(!is_supported_htslib_version("1.17"));
+        assert!(!is_supported_htslib_version("1.160"));
+    }
+
+    #[test]
+    fn test_remote_reader() {
+        let path = get_test_data_path("fake-genome.fa");
+        let mut reader = RemoteReader::open(&path.to_string_lossy()).unwrap();
+        let expected = std::fs::read(&path).unwrap();
+        assert_eq!(reader.size().unwrap(), Some(expected.len() as u64));
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, expected);
+        assert!(RemoteReader::open("/nonexistent/genome.fa").is_err());
+    }
+
+    #[test]
+    fn test_find_url_headers() {
+        let header = |value: &str| vec![format!("X-Api-Key: {}", value)];
+        let host_headers = BTreeMap::from([
+            ("data.example.org".to_owned(), header("host")),
+            ("data.example.org:8443".to_owned(), header("port")),
+        ]);
+        let find = |url: &str| find_url_headers(&host_headers, url);
+        assert_eq!(find("https://data.example.org/reads.bam"), Some(header("host")));
+        assert_eq!(find("https://DATA.example.org:443/reads.bam"), Some(header("host")));
+        assert_eq!(find("https://data.example.org:8443/reads.bam"), Some(header("port")));
+        assert_eq!(find("http://data.example.org:8080/reads.bam"), Some(header("host")));
+        assert_eq!(find("https://other.example.org/reads.bam"), None);
+        assert_eq!(find("s3://data.example.org/reads.bam"), None);
+    }
+}
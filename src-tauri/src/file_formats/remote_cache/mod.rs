@@ -0,0 +1,2 @@
+pub mod block_cache;
+pub mod hfile;
@@ -4,11 +4,20 @@ use std::collections::{BTreeMap, VecDeque};
 use anyhow::{Context, Result};
 use rust_htslib::bam::record::Record;
 use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
 
 use crate::alignments::alignment::Alignment;
+use crate::alignments::field_mask;
+use crate::alignments::stack::{StackGrouping, FORWARD_STRAND_KEY, REVERSE_STRAND_KEY};
 use crate::bio_util::genomic_coordinates::{GenomicInterval, GenomicRegion};
 use crate::bio_util::sequence::SequenceView;
+use crate::file_formats::nanopore::summary::{aux_to_u32, ReadRunMetadata};
+use crate::file_formats::sam_bam::base_modifications::{
+    parse_base_modifications, BaseModification,
+};
 use crate::file_formats::sam_bam::diff::{iter_sequence_diffs, SequenceDiff};
+use crate::file_formats::sam_bam::insert_size::InsertSizeClass;
+use crate::file_formats::sam_bam::supplementary::{get_alignment_chain, AlignmentChain};
 use crate::file_formats::sam_bam::tid::TidMap;
 use crate::impl_alignment;
 
@@ -30,27 +39,196 @@ fn get_mate_region(record: &Record, tid_map: &TidMap) -> Result<Option<GenomicRe
         .transpose()
 }
 
+/// Relative orientation of the reads in a pair, named from the leftmost read to the rightmost read
+/// (e.g FR is a forward read followed by a reverse read).
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PairOrientation {
+    /// Reads point towards each other (expected for standard paired-end libraries).
+    Fr,
+    /// Reads point away from each other (e.g tandem duplications or mate-pair libraries).
+    Rf,
+    /// Both reads are on the same strand (e.g inversions).
+    Tandem,
+}
+
+/// Get the orientation of a read's pair from a rust htslib bam record.
+///
+/// Returns None if the read is unpaired or if its mate is unmapped or on a different contig.
+fn get_pair_orientation(record: &Record) -> Option<PairOrientation> {
+    if !record.is_paired() || record.is_mate_unmapped() || record.tid() != record.mtid() {
+        return None;
+    }
+    let is_leftmost = record.pos() < record.mpos()
+        || (record.pos() == record.mpos() && record.is_first_in_template());
+    let (left_reverse, right_reverse) = if is_leftmost {
+        (record.is_reverse(), record.is_mate_reverse())
+    } else {
+        (record.is_mate_reverse(), record.is_reverse())
+    };
+    match (left_reverse, right_reverse) {
+        (false, true) => Some(PairOrientation::Fr),
+        (true, false) => Some(PairOrientation::Rf),
+        _ => Some(PairOrientation::Tandem),
+    }
+}
+
+/// A run of consecutive bases of a read with the same base quality.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityRun {
+    pub length: u32,
+    pub quality: u8,
+}
+
+/// Run-length encode the base qualities of a read, or None if the read has no qualities.
+///
+/// Runs are in the order of the read's sequence in the file (i.e left to right along the
+/// reference, including soft clipped bases).
+pub fn encode_base_qualities(qual: &[u8]) -> Option<Vec<QualityRun>> {
+    // Missing qualities are stored as 0xff
+    if qual.first().map_or(true, |quality| *quality == 0xff) {
+        return None;
+    }
+    let mut runs: Vec<QualityRun> = Vec::new();
+    for &quality in qual {
+        match runs.last_mut() {
+            Some(run) if run.quality == quality => run.length += 1,
+            _ => runs.push(QualityRun { length: 1, quality }),
+        }
+    }
+    Some(runs)
+}
+
 /// A single aligned read from a SAM/BAM file.
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AlignedRead {
     pub id: String,
+    #[serde(skip_serializing_if = "field_mask::skip_qname")]
     pub qname: String,
     pub region: GenomicRegion,
 
     /// Start position of paired read (None if read is unpaired)
+    #[serde(skip_serializing_if = "field_mask::skip_mate_pos")]
     pub mate_pos: Option<GenomicRegion>,
 
+    #[serde(skip_serializing_if = "field_mask::skip_cigar_string")]
     pub cigar_string: String,
 
     /// Differences in this read compared to the reference sequence (i.e SNVs/indels/clipping)
+    #[serde(skip_serializing_if = "field_mask::skip_diffs")]
     pub diffs: Vec<SequenceDiff>,
 
     /// True if the alignment is in the reverse orientation
     pub is_reverse: bool,
+
+    /// True if the read is the second read of its pair (SAM flag 0x80).
+    pub is_second_of_pair: bool,
+
+    /// Set if the read's diffs could not be parsed (e.g CIGAR string doesn't match the sequence).
+    ///
+    /// The read is still displayed but its diffs will be empty.
+    pub parse_error: Option<String>,
+
+    /// True if the read starts before the reference sequence which was used to compute its diffs.
+    pub clipped_start: bool,
+
+    /// True if the read ends after the reference sequence which was used to compute its diffs.
+    pub clipped_end: bool,
+
+    /// Number of diffs which weren't computed because they fall outside of the reference sequence.
+    pub num_hidden_diffs: u32,
+
+    /// Nanopore run metadata (e.g channel and qscore), from the read's tags or a sequencing
+    /// summary.
+    pub run_metadata: Option<ReadRunMetadata>,
+
+    /// Haplotype which the read is phased to (HP tag), if any.
+    pub haplotype: Option<u32>,
+
+    pub mapq: u8,
+
+    /// Observed template length (TLEN), which is negative for the rightmost read of a pair.
+    pub template_length: i64,
+
+    /// Orientation of the read's pair (None if the mate is unmapped or on another contig).
+    pub pair_orientation: Option<PairOrientation>,
+
+    /// Run-length encoded base qualities, only set if the track shows base qualities.
+    #[serde(skip_serializing_if = "field_mask::skip_base_qualities")]
+    pub base_qualities: Option<Vec<QualityRun>>,
+
+    /// Base modification calls (e.g methylation) from the MM/ML tags, ordered by position.
+    #[serde(skip_serializing_if = "field_mask::skip_base_modifications")]
+    pub base_modifications: Vec<BaseModification>,
+
+    /// True if this is a supplementary alignment of the read (SAM flag 0x800).
+    pub is_supplementary: bool,
+
+    /// All alignments of the read if it's split into primary and supplementary alignments (SA
+    /// tag), e.g a long read which spans a structural variant.
+    pub chain: Option<AlignmentChain>,
 }
 
 impl AlignedRead {
+    /// Rename the contig which the read (or its mate) is aligned to.
+    pub fn rename_seq(&mut self, seq_name: &str, new_seq_name: &str) {
+        if seq_name == new_seq_name {
+            return;
+        }
+        let regions = std::iter::once(&mut self.region).chain(self.mate_pos.as_mut());
+        for region in regions.filter(|region| region.seq_name == seq_name) {
+            region.seq_name = new_seq_name.to_owned();
+        }
+        if let Some(chain) = self.chain.as_mut() {
+            chain.rename_seq(seq_name, new_seq_name);
+        }
+    }
+
+    /// Drop the diffs which fall outside of a reference sequence, as if they'd been computed
+    /// against it rather than the (larger) sequence which the read was read with.
+    ///
+    /// Like when diffs are computed, diffs which start before the sequence are clipped to its start
+    /// and those which lie entirely outside of it are counted as hidden (except for mismatches).
+    pub fn clip_diffs(&mut self, refseq: &SequenceView) {
+        let mut hidden_softclip_len = 0;
+        for mut diff in std::mem::take(&mut self.diffs) {
+            let is_visible = match &mut diff {
+                SequenceDiff::Mismatch { interval, .. } | SequenceDiff::Ins { interval, .. } => {
+                    refseq.contains(interval.start)
+                }
+                SequenceDiff::Del { interval } | SequenceDiff::RefSkip { interval } => {
+                    clip_diff_start(interval, refseq).is_some()
+                }
+                SequenceDiff::SoftClip { interval, sequence } => {
+                    let len = interval.len();
+                    match clip_diff_start(interval, refseq) {
+                        Some(clipped) => {
+                            // Softclips have a base at each position
+                            sequence.drain(..cmp::min(clipped as usize, sequence.len()));
+                            hidden_softclip_len += clipped;
+                            true
+                        }
+                        None => {
+                            hidden_softclip_len += len;
+                            false
+                        }
+                    }
+                }
+            };
+            if is_visible {
+                self.diffs.push(diff);
+            } else if !matches!(diff, SequenceDiff::Mismatch { .. }) {
+                self.num_hidden_diffs += 1;
+            }
+        }
+        // Reads are only extended by the softclips which are visible
+        self.region.interval.end -= hidden_softclip_len;
+        self.clipped_start = self.region.start() < refseq.start();
+        self.clipped_end = self.region.end() > refseq.end();
+    }
+
     /// Initialize an AlignedRead from a rust-htslib Record object (+ extra required metadata)
     ///
     /// # Arguments
@@ -68,7 +246,16 @@ impl AlignedRead {
             format!("Read {} has invalid end position ({})", qname, cigar.end_pos())
         })?;
         let mut genomic_region = GenomicRegion::new(seq_name, start, end)?;
-        let diffs = iter_sequence_diffs(record, refseq).collect::<Result<Vec<SequenceDiff>>>()?;
+        // A single malformed read shouldn't prevent the rest of the region from being loaded so
+        // diff errors are recorded on the read rather than propagated.
+        let mut diff_iter = iter_sequence_diffs(record, refseq);
+        let (diffs, parse_error) = match diff_iter.by_ref().collect::<Result<Vec<SequenceDiff>>>() {
+            Ok(diffs) => (diffs, None),
+            Err(err) => {
+                log::warn!("Failed to parse sequence diffs for read {}: {:#}", qname, err);
+                (Vec::new(), Some(format!("{:#}", err)))
+            }
+        };
         for diff in &diffs {
             // Accounting for the fact that softclips don't increment the read position per the SAM
             // spec.
@@ -76,22 +263,50 @@ impl AlignedRead {
                 genomic_region.interval.end += interval.len();
             }
         }
+        let clipped_start = genomic_region.start() < refseq.start();
+        let clipped_end = genomic_region.end() > refseq.end();
         let is_reverse = record.is_reverse();
         let mate_pos = get_mate_region(record, tid_map)?;
+        let base_modifications = parse_base_modifications(record).unwrap_or_else(|err| {
+            log::warn!("Failed to parse base modifications for read {}: {:#}", qname, err);
+            Vec::new()
+        });
         let mut id = qname.clone();
         if record.is_first_in_template() {
             id.push_str("/1")
         } else {
             id.push_str("/2")
         }
+        let chain = get_alignment_chain(record, seq_name, &id).unwrap_or_else(|err| {
+            log::warn!("Failed to parse supplementary alignments for read {}: {:#}", qname, err);
+            None
+        });
+        if let Some(chain) = chain.as_ref().filter(|_| record.is_supplementary()) {
+            // Distinguishes the alignment from the read's primary alignment
+            id = format!("{}/{}", chain.id, chain.index);
+        }
         Ok(AlignedRead {
             id,
             qname,
             region: genomic_region,
             diffs,
             is_reverse,
+            is_second_of_pair: record.is_last_in_template(),
             mate_pos,
             cigar_string: cigar.to_string(),
+            parse_error,
+            clipped_start,
+            clipped_end,
+            num_hidden_diffs: diff_iter.num_hidden_diffs(),
+            run_metadata: ReadRunMetadata::from_record(record),
+            haplotype: record.aux(b"HP").ok().and_then(aux_to_u32),
+            mapq: record.mapq(),
+            template_length: record.insert_size(),
+            pair_orientation: get_pair_orientation(record),
+            base_qualities: None,
+            base_modifications,
+            is_supplementary: record.is_supplementary(),
+            chain,
         })
     }
 }
@@ -107,9 +322,21 @@ impl Alignment for AlignedRead {
     fn end(&self) -> u64 {
         self.region.end()
     }
+
+    fn group_key(&self, grouping: StackGrouping) -> Option<String> {
+        match grouping {
+            StackGrouping::Ungrouped => None,
+            StackGrouping::Haplotype => self.haplotype.map(|haplotype| haplotype.to_string()),
+            StackGrouping::Strand => {
+                let key = if self.is_reverse { REVERSE_STRAND_KEY } else { FORWARD_STRAND_KEY };
+                Some(key.to_owned())
+            }
+        }
+    }
 }
 
 /// A paired set of reads in which both reads align to the same chromosome/contig
+#[serde_as]
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PairedReads {
@@ -118,26 +345,55 @@ pub struct PairedReads {
     /// read2 is None when the other read in the pair is outside of the current window
     pub read2: Option<AlignedRead>,
     pub interval: GenomicInterval,
+    /// End of the leftmost read in the pair (i.e where the line connecting the reads starts).
+    ///
+    /// If read2 is None and the mate is to the left of read1, only the mate's start position is
+    /// known so the mate is treated as being 1bp long.
+    #[serde_as(as = "DisplayFromStr")]
+    pub leftmost_end: u64,
+    /// Start of the rightmost read in the pair (i.e where the line connecting the reads ends).
+    #[serde_as(as = "DisplayFromStr")]
+    pub rightmost_start: u64,
+    /// Number of bases between the two reads (zero if the reads overlap).
+    #[serde_as(as = "DisplayFromStr")]
+    pub gap_length: u64,
+    /// Relative orientation of the reads, which is known even when read2 is outside the window.
+    pub orientation: Option<PairOrientation>,
+    /// Set once the insert sizes of the pairs in a region have been sampled.
+    pub insert_size_class: Option<InsertSizeClass>,
 }
 
 impl PairedReads {
     pub fn new(read1: AlignedRead, read2: Option<AlignedRead>) -> Result<Self> {
-        let interval: GenomicInterval = match &read2 {
-            Some(inner_read2) => {
-                let start = cmp::min(read1.region.start(), inner_read2.region.start());
-                let end = cmp::max(read1.region.end(), inner_read2.region.end());
-                (start, end).try_into()?
-            }
-            None => {
-                // mate_pos should always be defined because otherwise we would have used
-                // UnpairedRead instead
-                let mate_pos = read1.mate_pos.as_ref().unwrap();
-                let start = cmp::min(read1.region.start(), mate_pos.start());
-                let end = cmp::max(read1.region.end(), mate_pos.end());
-                (start, end).try_into()?
-            }
+        let mate_region = match &read2 {
+            Some(inner_read2) => &inner_read2.region,
+            // mate_pos should always be defined because otherwise we would have used
+            // UnpairedRead instead
+            None => read1.mate_pos.as_ref().unwrap(),
         };
-        Ok(Self { id: read1.qname.clone(), read1, read2, interval })
+        let (left, right) = if read1.region.start() <= mate_region.start() {
+            (&read1.region, mate_region)
+        } else {
+            (mate_region, &read1.region)
+        };
+        let start = cmp::min(left.start(), right.start());
+        let end = cmp::max(left.end(), right.end());
+        let interval = (start, end).try_into()?;
+        let leftmost_end = left.end();
+        let rightmost_start = right.start();
+        let gap_length = rightmost_start.saturating_sub(leftmost_end);
+        let orientation = read1.pair_orientation;
+        Ok(Self {
+            id: read1.qname.clone(),
+            read1,
+            read2,
+            interval,
+            leftmost_end,
+            rightmost_start,
+            gap_length,
+            orientation,
+            insert_size_class: None,
+        })
     }
 }
 
@@ -176,6 +432,8 @@ impl DiscordantRead {
     }
 }
 
+// Most alignments are paired reads so boxing the largest variant wouldn't save any memory.
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase", tag = "type")]
 pub enum AlignedPair {
@@ -184,6 +442,30 @@ pub enum AlignedPair {
     DiscordantReadKind(DiscordantRead),
 }
 
+impl AlignedPair {
+    /// Iterate mutably over the individual reads in the pair.
+    pub fn reads_mut(&mut self) -> impl Iterator<Item = &mut AlignedRead> {
+        use AlignedPair::*;
+        let (first, second) = match self {
+            PairedReadsKind(PairedReads { read1, read2, .. }) => (read1, read2.as_mut()),
+            UnpairedReadKind(UnpairedRead { read, .. })
+            | DiscordantReadKind(DiscordantRead { read, .. }) => (read, None),
+        };
+        std::iter::once(first).chain(second)
+    }
+
+    /// Iterate over the individual reads in the pair.
+    pub fn reads(&self) -> impl Iterator<Item = &AlignedRead> {
+        use AlignedPair::*;
+        let (first, second) = match self {
+            PairedReadsKind(PairedReads { read1, read2, .. }) => (read1, read2.as_ref()),
+            UnpairedReadKind(UnpairedRead { read, .. })
+            | DiscordantReadKind(DiscordantRead { read, .. }) => (read, None),
+        };
+        std::iter::once(first).chain(second)
+    }
+}
+
 impl Alignment for AlignedPair {
     fn id(&self) -> &str {
         use AlignedPair::*;
@@ -211,17 +493,60 @@ impl Alignment for AlignedPair {
             | DiscordantReadKind(DiscordantRead { interval, .. }) => interval.end,
         }
     }
+    /// Pairs are grouped by the first read which has a value for the grouped field, except when
+    /// grouped by strand where the strand of the first read of the pair is used (if it's loaded).
+    fn group_key(&self, grouping: StackGrouping) -> Option<String> {
+        match grouping {
+            StackGrouping::Strand => self
+                .reads()
+                .find(|read| !read.is_second_of_pair)
+                .or_else(|| self.reads().next())
+                .and_then(|read| read.group_key(grouping)),
+            StackGrouping::Ungrouped | StackGrouping::Haplotype => {
+                self.reads().find_map(|read| read.group_key(grouping))
+            }
+        }
+    }
+
+    fn num_reads(&self) -> usize {
+        self.reads().count()
+    }
 }
 
 impl_alignment![DiscordantRead, PairedReads, UnpairedRead];
 
+/// Clip the start of a diff which spans multiple positions to the start of a reference sequence,
+/// returning the number of positions which were clipped, or None if the diff doesn't overlap it.
+fn clip_diff_start(interval: &mut GenomicInterval, refseq: &SequenceView) -> Option<u64> {
+    if refseq.contains(interval.start) {
+        return Some(0);
+    }
+    let start = refseq.start();
+    if interval.start < start && interval.end > start && refseq.contains(start) {
+        let clipped = start - interval.start;
+        interval.start = start;
+        return Some(clipped);
+    }
+    None
+}
+
 /// Match aligned reads to their mate pairs
 ///
-/// Output order is determined by the read name of the first read in the pair.
+/// Output order is determined by the read name of the first read in the pair. Supplementary
+/// alignments aren't paired and are output last, in their input order.
 pub fn pair_reads(reads: Vec<AlignedRead>) -> Result<Vec<AlignedPair>> {
     let mut reads_by_name: BTreeMap<String, VecDeque<AlignedRead>> = BTreeMap::new();
     let mut existing_reads;
+    let mut supplementary = Vec::new();
     for read in reads.into_iter() {
+        if read.is_supplementary {
+            // Drawn separately from the read's pair, and linked to its other alignments by its
+            // chain
+            let mut pair = UnpairedRead::new(read);
+            pair.id = pair.read.id.clone();
+            supplementary.push(AlignedPair::UnpairedReadKind(pair));
+            continue;
+        }
         existing_reads =
             reads_by_name.entry(read.qname.clone()).or_insert(VecDeque::with_capacity(2));
         existing_reads.push_back(read);
@@ -246,6 +571,7 @@ pub fn pair_reads(reads: Vec<AlignedRead>) -> Result<Vec<AlignedPair>> {
             }
         }
     }
+    pairs.extend(supplementary);
     Ok(pairs)
 }
 
@@ -255,10 +581,12 @@ mod tests {
 
     use itertools::Itertools;
     use pretty_assertions::assert_eq;
+    use rust_htslib::bam::record::{Aux, CigarString};
 
     use crate::bio_util::genomic_coordinates::{GenomicInterval, GenomicRegion};
     use crate::bio_util::sequence::SequenceView;
     use crate::file_formats::sam_bam::tid::TidMap;
+    use crate::test_util::aligned_reads::gen_read;
     use crate::test_util::htslib_records::RecordBuilder;
 
     use super::*;
@@ -267,20 +595,15 @@ mod tests {
         let paired_read1 = AlignedRead {
             id: "paired_read/1".to_owned(),
             qname: "paired_read".to_owned(),
-            cigar_string: "100M".to_owned(),
-            region: GenomicRegion::new("X", 0, 100).unwrap(),
             mate_pos: Some(GenomicRegion::new("X", 200, 201).unwrap()),
-            diffs: Vec::new(),
-            is_reverse: false,
+            ..gen_read("paired_read", 0, 100, Vec::new())
         };
         let paired_read2 = AlignedRead {
             id: "paired_read/2".to_owned(),
             qname: "paired_read".to_owned(),
-            cigar_string: "100M".to_owned(),
-            region: GenomicRegion::new("X", 200, 301).unwrap(),
             mate_pos: Some(GenomicRegion::new("X", 0, 1).unwrap()),
-            diffs: Vec::new(),
             is_reverse: true,
+            ..gen_read("paired_read", 200, 301, Vec::new())
         };
         (paired_read1, paired_read2)
     }
@@ -289,11 +612,7 @@ mod tests {
         AlignedRead {
             id: "unpaired_read/1".to_owned(),
             qname: "unpaired_read".to_owned(),
-            region: GenomicRegion::new("X", 0, 100).unwrap(),
-            mate_pos: None,
-            cigar_string: "100M".to_owned(),
-            diffs: Vec::new(),
-            is_reverse: false,
+            ..gen_read("unpaired_read", 0, 100, Vec::new())
         }
     }
 
@@ -301,11 +620,8 @@ mod tests {
         AlignedRead {
             id: "missing_pair_read/1".to_owned(),
             qname: "missing_pair_read".to_owned(),
-            region: GenomicRegion::new("X", 0, 100).unwrap(),
             mate_pos: Some(GenomicRegion::new("X", 6000, 6001).unwrap()),
-            cigar_string: "100M".to_owned(),
-            diffs: Vec::new(),
-            is_reverse: false,
+            ..gen_read("missing_pair_read", 0, 100, Vec::new())
         }
     }
 
@@ -313,11 +629,8 @@ mod tests {
         AlignedRead {
             id: "discordant_read/1".to_owned(),
             qname: "discordant_read".to_owned(),
-            region: GenomicRegion::new("X", 0, 100).unwrap(),
             mate_pos: Some(GenomicRegion::new("1", 6000, 6001).unwrap()),
-            cigar_string: "100M".to_owned(),
-            diffs: Vec::new(),
-            is_reverse: false,
+            ..gen_read("discordant_read", 0, 100, Vec::new())
         }
     }
 
@@ -355,11 +668,128 @@ mod tests {
         assert!(result.is_err())
     }
 
+    #[test]
+    pub fn test_init_aligned_read_with_malformed_cigar() {
+        let seqview = SequenceView::new("TTTAGCTAAA".as_bytes().to_vec(), 1000);
+        let cigar = CigarString::try_from("2M2I").unwrap();
+        let record = RecordBuilder::new(b"test", b"AGC", Some(&cigar), b"BBB").pos(1003).tid(0);
+        let tid_map: TidMap =
+            [(0, "X".to_owned())].into_iter().collect::<BTreeMap<u32, String>>().into();
+        let aligned_read = AlignedRead::from_record(&record.record, &seqview, &tid_map).unwrap();
+        assert!(aligned_read.diffs.is_empty());
+        assert!(aligned_read.parse_error.is_some());
+    }
+
+    #[test]
+    pub fn test_init_aligned_read_clipped_by_refseq() {
+        let seqview = SequenceView::new("CTAAA".as_bytes().to_vec(), 1005);
+        let record = RecordBuilder::default().record;
+        let tid_map: TidMap =
+            [(0, "X".to_owned())].into_iter().collect::<BTreeMap<u32, String>>().into();
+        let aligned_read = AlignedRead::from_record(&record, &seqview, &tid_map).unwrap();
+        assert!(aligned_read.clipped_start);
+        assert!(!aligned_read.clipped_end);
+    }
+
+    #[test]
+    pub fn test_clip_diffs() {
+        let seqview = SequenceView::new("TTTAGCTAAA".as_bytes().to_vec(), 1000);
+        let cigar = CigarString::try_from("2M1I1M2D2M2S").unwrap();
+        let record =
+            RecordBuilder::new(b"test", b"GTCATAGG", Some(&cigar), b"BBBBBBBB").pos(1001).record;
+        let tid_map: TidMap =
+            [(0, "X".to_owned())].into_iter().collect::<BTreeMap<u32, String>>().into();
+        let mut aligned_read = AlignedRead::from_record(&record, &seqview, &tid_map).unwrap();
+        assert_eq!(aligned_read.diffs.len(), 4);
+        // Same as if the diffs were computed against the smaller sequence
+        let clipped_seqview = SequenceView::new("CTAAA".as_bytes().to_vec(), 1005);
+        aligned_read.clip_diffs(&clipped_seqview);
+        let expected = AlignedRead::from_record(&record, &clipped_seqview, &tid_map).unwrap();
+        assert_eq!(aligned_read, expected);
+        assert_eq!(aligned_read.num_hidden_diffs, 1);
+        assert!(aligned_read.clipped_start);
+    }
+
+    #[test]
+    pub fn test_init_supplementary_aligned_read() {
+        let seqview = SequenceView::new("TTTAGCTAAA".as_bytes().to_vec(), 1000);
+        let cigar = CigarString::try_from("2S4M").unwrap();
+        let record = RecordBuilder::new(b"test", b"TTAGCT", Some(&cigar), b"BBBBBB")
+            .flags(0x800)
+            .aux(b"SA", Aux::String("X,101,+,2M4S,60,0;"))
+            .record;
+        let tid_map: TidMap =
+            [(0, "X".to_owned())].into_iter().collect::<BTreeMap<u32, String>>().into();
+        let aligned_read = AlignedRead::from_record(&record, &seqview, &tid_map).unwrap();
+        assert!(aligned_read.is_supplementary);
+        let chain = aligned_read.chain.unwrap();
+        assert_eq!(chain.id, "test/2");
+        assert_eq!(chain.segments.len(), 2);
+        assert_eq!(chain.index, 1);
+        assert_eq!(aligned_read.id, "test/2/1");
+    }
+
+    #[test]
+    pub fn test_encode_base_qualities() {
+        assert_eq!(
+            encode_base_qualities(&[30, 30, 30, 10, 30, 30]).unwrap(),
+            vec![
+                QualityRun { length: 3, quality: 30 },
+                QualityRun { length: 1, quality: 10 },
+                QualityRun { length: 2, quality: 30 },
+            ]
+        );
+        assert_eq!(encode_base_qualities(&[0xff, 0xff]), None);
+        assert_eq!(encode_base_qualities(&[]), None);
+    }
+
+    #[test]
+    pub fn test_get_pair_orientation() {
+        let (paired, mate_unmapped, reverse, mate_reverse, first) = (0x1, 0x8, 0x10, 0x20, 0x40);
+        let gen_record =
+            |flags: u16, mpos: i64| RecordBuilder::default().flags(flags).mtid(0).mpos(mpos);
+        let orientation = |record: RecordBuilder| get_pair_orientation(&record.record);
+        assert_eq!(
+            orientation(gen_record(paired | mate_reverse | first, 1200)),
+            Some(PairOrientation::Fr)
+        );
+        // The rightmost read of an FR pair is reversed
+        assert_eq!(orientation(gen_record(paired | reverse, 900)), Some(PairOrientation::Fr));
+        assert_eq!(orientation(gen_record(paired | reverse, 1200)), Some(PairOrientation::Rf));
+        assert_eq!(
+            orientation(gen_record(paired | reverse | mate_reverse, 1200)),
+            Some(PairOrientation::Tandem)
+        );
+        assert_eq!(orientation(gen_record(paired, 900)), Some(PairOrientation::Tandem));
+        assert_eq!(orientation(gen_record(0, 1200)), None);
+        assert_eq!(orientation(gen_record(paired | mate_unmapped, 1200)), None);
+        assert_eq!(orientation(gen_record(paired | mate_reverse, 1200).mtid(1)), None);
+    }
+
     #[test]
     pub fn test_init_paired_reads_with_pair() {
         let (read1, read2) = gen_aligned_read_pair();
         let paired_reads = PairedReads::new(read1, Some(read2)).unwrap();
         assert_eq!(paired_reads.interval, GenomicInterval::new(0, 301).unwrap());
+        assert_eq!(paired_reads.leftmost_end, 100);
+        assert_eq!(paired_reads.rightmost_start, 200);
+        assert_eq!(paired_reads.gap_length, 100);
+        // Order of the reads doesn't matter
+        let (read1, read2) = gen_aligned_read_pair();
+        let swapped_reads = PairedReads::new(read2, Some(read1)).unwrap();
+        assert_eq!(swapped_reads.leftmost_end, 100);
+        assert_eq!(swapped_reads.rightmost_start, 200);
+    }
+
+    #[test]
+    pub fn test_init_paired_reads_with_overlapping_reads() {
+        let (read1, mut read2) = gen_aligned_read_pair();
+        read2.region = GenomicRegion::new("X", 50, 150).unwrap();
+        let paired_reads = PairedReads::new(read1, Some(read2)).unwrap();
+        assert_eq!(paired_reads.interval, GenomicInterval::new(0, 150).unwrap());
+        assert_eq!(paired_reads.leftmost_end, 100);
+        assert_eq!(paired_reads.rightmost_start, 50);
+        assert_eq!(paired_reads.gap_length, 0);
     }
 
     #[test]
@@ -367,6 +797,7 @@ mod tests {
         let read = gen_missing_pair_read();
         let paired_reads = PairedReads::new(read, None).unwrap();
         assert_eq!(paired_reads.interval, GenomicInterval::new(0, 6001).unwrap());
+        assert_eq!(paired_reads.gap_length, 5900);
     }
 
     #[test]
@@ -395,4 +826,16 @@ mod tests {
         ];
         assert_eq!(result, expected_result);
     }
+
+    #[test]
+    pub fn test_pair_reads_with_supplementary_alignment() {
+        let (paired_read1, paired_read2) = gen_aligned_read_pair();
+        let mut supplementary = paired_read1.clone();
+        supplementary.id.push_str("/1");
+        supplementary.is_supplementary = true;
+        let result = pair_reads(vec![supplementary.clone(), paired_read1, paired_read2]).unwrap();
+        assert_eq!(result.len(), 2);
+        assert!(matches!(result[0], AlignedPair::PairedReadsKind(_)));
+        assert_eq!(result[1].id(), "paired_read/1/1");
+    }
 }
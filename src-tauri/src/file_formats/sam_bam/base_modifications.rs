@@ -0,0 +1,218 @@
+//! Parsing base modification calls (e.g 5mC/6mA methylation) from the MM and ML tags of a read.
+//!
+//! See section 1.7 of the SAM optional fields spec
+//! (https://samtools.github.io/hts-specs/SAMtags.pdf) for the format.
+use anyhow::{bail, Context, Result};
+use bio::alphabets::dna::revcomp;
+use rust_htslib::bam::record::{Aux, Cigar, Record};
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::file_formats::sam_bam::diff::iter_aligned_pairs_cigar;
+
+/// A modification call for a single base of a read.
+#[serde_as]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BaseModification {
+    /// Reference position of the modified base.
+    #[serde_as(as = "DisplayFromStr")]
+    pub position: u64,
+    /// Modification code, e.g m (5mC), h (5hmC), a (6mA) or a ChEBI number.
+    pub code: String,
+    /// Probability that the base is modified, scaled to 0-255 (as in the ML tag).
+    pub probability: u8,
+}
+
+/// A modification call on the original (unreversed) read sequence.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ModCall {
+    read_pos: usize,
+    code: String,
+    probability: u8,
+}
+
+/// Get the value of a tag, or of its lowercase equivalent (used before the tags were finalized).
+fn get_tag<'a>(record: &'a Record, tag: &[u8; 2]) -> Option<Aux<'a>> {
+    let draft_tag = [tag[0], tag[1].to_ascii_lowercase()];
+    record.aux(tag).or_else(|_| record.aux(&draft_tag)).ok()
+}
+
+/// Split the modification codes of an MM entry (e.g "mh" or a ChEBI number like "27551").
+fn split_codes(codes: &str) -> Vec<String> {
+    if codes.chars().all(|c| c.is_ascii_digit()) {
+        vec![codes.to_owned()]
+    } else {
+        codes.chars().map(|c| c.to_string()).collect()
+    }
+}
+
+/// Parse an MM tag into modification calls on the original read sequence.
+///
+/// Calls on the opposite strand (e.g from duplex reads) are skipped.
+///
+/// # Arguments
+///
+/// * `sequence` - Read sequence in its original orientation (i.e reverse complemented if the read
+///   is aligned to the reverse strand).
+/// * `probabilities` - Values of the ML tag, or None if it's missing (all calls are certain).
+fn parse_mm_tag(mm: &str, sequence: &[u8], probabilities: Option<&[u8]>) -> Result<Vec<ModCall>> {
+    let mut calls = Vec::new();
+    let mut probability_index = 0;
+    for entry in mm.split(';').filter(|entry| !entry.is_empty()) {
+        let mut fields = entry.split(',');
+        let header = fields.next().unwrap_or_default();
+        let mut header_chars = header.chars();
+        let (base, strand) = match (header_chars.next(), header_chars.next()) {
+            (Some(base), Some(strand @ ('+' | '-'))) => (base.to_ascii_uppercase() as u8, strand),
+            _ => bail!("Invalid MM tag entry: {}", entry),
+        };
+        let codes = header_chars.as_str().trim_end_matches(['.', '?']);
+        if codes.is_empty() {
+            bail!("MM tag entry has no modification codes: {}", entry);
+        }
+        let codes = split_codes(codes);
+        let mut base_positions = sequence
+            .iter()
+            .enumerate()
+            .filter(|(_, read_base)| base == b'N' || read_base.to_ascii_uppercase() == base)
+            .map(|(read_pos, _)| read_pos);
+        for skip in fields {
+            let skip: usize = skip
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid MM tag skip count: {}", skip))?;
+            let read_pos = base_positions.nth(skip).with_context(|| {
+                format!("MM tag entry {} has more calls than there are {} bases", header, base)
+            })?;
+            for code in codes.iter() {
+                let probability = match probabilities {
+                    Some(probabilities) => {
+                        *probabilities.get(probability_index).with_context(|| {
+                            "ML tag has fewer values than there are calls in the MM tag"
+                        })?
+                    }
+                    None => u8::MAX,
+                };
+                probability_index += 1;
+                if strand == '+' {
+                    calls.push(ModCall { read_pos, code: code.clone(), probability });
+                }
+            }
+        }
+    }
+    Ok(calls)
+}
+
+/// Parse the base modification calls of a read from its MM and ML tags.
+///
+/// Calls on bases which aren't aligned to the reference (e.g insertions or soft clips) are
+/// skipped. Reads without an MM tag have no calls.
+pub fn parse_base_modifications(record: &Record) -> Result<Vec<BaseModification>> {
+    let mm = match get_tag(record, b"MM") {
+        Some(Aux::String(mm)) => mm,
+        Some(_) => bail!("MM tag must be a string"),
+        None => return Ok(Vec::new()),
+    };
+    let probabilities: Option<Vec<u8>> = match get_tag(record, b"ML") {
+        Some(Aux::ArrayU8(ml)) => Some(ml.iter().collect()),
+        Some(_) => bail!("ML tag must be an array of 8-bit unsigned integers"),
+        None => None,
+    };
+    let mut sequence = record.seq().as_bytes();
+    if record.is_reverse() {
+        sequence = revcomp(sequence);
+    }
+    let calls = parse_mm_tag(mm, &sequence, probabilities.as_deref())?;
+    if calls.is_empty() {
+        return Ok(Vec::new());
+    }
+    // Reference position of each base of the stored (forward strand) read sequence
+    let mut ref_positions = vec![None; sequence.len()];
+    for (cigar, read_pos, ref_pos) in iter_aligned_pairs_cigar(record) {
+        if let (Cigar::Match(_) | Cigar::Equal(_) | Cigar::Diff(_), Some(read_pos), Some(ref_pos)) =
+            (cigar, read_pos, ref_pos)
+        {
+            if let Some(position) = ref_positions.get_mut(read_pos) {
+                *position = Some(ref_pos);
+            }
+        }
+    }
+    let mut modifications: Vec<BaseModification> = calls
+        .into_iter()
+        .filter_map(|call| {
+            let read_pos = match record.is_reverse() {
+                true => sequence.len() - 1 - call.read_pos,
+                false => call.read_pos,
+            };
+            let position = ref_positions[read_pos]?;
+            Some(BaseModification { position, code: call.code, probability: call.probability })
+        })
+        .collect();
+    modifications.sort_by_key(|modification| modification.position);
+    Ok(modifications)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rust_htslib::bam::record::CigarString;
+
+    use super::*;
+    use crate::test_util::htslib_records::RecordBuilder;
+
+    fn gen_record(cigar: &str, seq: &[u8], mm: &str, ml: &[u8]) -> RecordBuilder {
+        let cigar = CigarString::try_from(cigar).unwrap();
+        RecordBuilder::new(b"read", seq, Some(&cigar), &vec![30; seq.len()])
+            .pos(100)
+            .aux(b"MM", Aux::String(mm))
+            .aux(b"ML", Aux::ArrayU8(ml.into()))
+    }
+
+    fn modification(position: u64, code: &str, probability: u8) -> BaseModification {
+        BaseModification { position, code: code.to_owned(), probability }
+    }
+
+    #[test]
+    fn test_parse_mm_tag() {
+        // Skip counts are relative to the previous call
+        let calls = parse_mm_tag("C+m?,1,0;", b"CACCTC", Some(&[200, 10])).unwrap();
+        let positions: Vec<usize> = calls.iter().map(|call| call.read_pos).collect();
+        assert_eq!(positions, vec![2, 3]);
+        assert_eq!(calls[1].probability, 10);
+        // Multiple codes have one probability each per call
+        let calls = parse_mm_tag("C+mh,0;A-a,0;N+27551,1", b"ACGT", Some(&[1, 2, 3, 4])).unwrap();
+        let calls: Vec<(usize, &str, u8)> = calls
+            .iter()
+            .map(|call| (call.read_pos, call.code.as_str(), call.probability))
+            .collect();
+        assert_eq!(calls, vec![(1, "m", 1), (1, "h", 2), (1, "27551", 4)]);
+        assert!(parse_mm_tag("C+m,3", b"CAC", None).is_err());
+        assert!(parse_mm_tag("C+m,0", b"CAC", Some(&[])).is_err());
+        assert!(parse_mm_tag("Cm,0", b"CAC", None).is_err());
+    }
+
+    #[test]
+    fn test_parse_base_modifications() {
+        // The inserted C isn't aligned to the reference so it's skipped
+        let record = gen_record("2M1I2M", b"CACCG", "C+m,0,0,0;", &[10, 20, 30]).record;
+        let modifications = parse_base_modifications(&record).unwrap();
+        assert_eq!(modifications, vec![modification(100, "m", 10), modification(102, "m", 30)]);
+    }
+
+    #[test]
+    fn test_parse_base_modifications_on_reverse_strand() {
+        // The original read sequence is CGATG, so the first C is the last base of the record
+        let mut record = gen_record("5M", b"CATCG", "C+m,0;", &[255]).record;
+        record.set_reverse();
+        let modifications = parse_base_modifications(&record).unwrap();
+        assert_eq!(modifications, vec![modification(104, "m", 255)]);
+    }
+
+    #[test]
+    fn test_parse_read_without_modifications() {
+        let cigar = CigarString::try_from("4M").unwrap();
+        let record = RecordBuilder::new(b"read", b"ACGT", Some(&cigar), b"BBBB").record;
+        assert!(parse_base_modifications(&record).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,488 @@
+//! Binned read depth summaries of whole SAM/BAM files (similar to IGV's .tdf files), so that
+//! coverage can be shown for regions which are too large to read alignments for.
+//!
+//! Summaries are computed in a single pass over the file the first time it's opened and cached on
+//! disk. Only the finest level of bins is stored, coarser levels are computed when it's loaded.
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read as IoRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{bail, Context, Result};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use rust_htslib::bam;
+use rust_htslib::bam::record::{Cigar, Record};
+use rust_htslib::bam::Read;
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use uuid::Uuid;
+
+use crate::bio_util::contig_aliases::resolve_contig_name;
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::remote_cache::block_cache::fnv1a;
+use crate::file_formats::sam_bam::reader::open_bam;
+
+/// Number of bases in each bin of the finest level of the pyramid.
+pub const FINEST_BIN_SIZE: u64 = 256;
+
+/// Each level's bins span this many bins of the level below it.
+const LEVEL_FACTOR: u64 = 4;
+
+/// Written at the start of cached pyramids. Bumped whenever the file layout changes.
+const CACHE_MAGIC: &[u8; 8] = b"GSCOV\x00\x00\x01";
+
+const CACHE_EXTENSION: &str = "cov";
+
+/// Number of bins which coverage is sampled in when a region is too large to read alignments for.
+pub const SAMPLED_COVERAGE_BINS: usize = 200;
+
+/// Length of the window at the center of each bin which is read when sampling coverage.
+const COVERAGE_SAMPLE_LENGTH: u64 = 128;
+
+/// Mean read depth in fixed-size bins across a region.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinnedCoverage {
+    /// Region spanned by the bins. The last bin is shorter than `bin_size` if the region's length
+    /// isn't a multiple of it (e.g at the end of the sequence).
+    pub region: GenomicRegion,
+    #[serde_as(as = "DisplayFromStr")]
+    pub bin_size: u64,
+    pub depths: Vec<f32>,
+}
+
+/// Binned depths of a single sequence at each level, finest first.
+#[derive(Clone, Debug, PartialEq)]
+struct SeqCoverage {
+    length: u64,
+    levels: Vec<Vec<f32>>,
+}
+
+fn level_bin_size(level: usize) -> u64 {
+    FINEST_BIN_SIZE * LEVEL_FACTOR.pow(level as u32)
+}
+
+fn num_bins(length: u64, bin_size: u64) -> usize {
+    ((length + bin_size - 1) / bin_size) as usize
+}
+
+impl SeqCoverage {
+    /// Build the coarser levels from the finest, until a single bin spans the whole sequence.
+    fn from_finest(length: u64, finest: Vec<f32>) -> Self {
+        let mut levels = vec![finest];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels.len();
+            let finer_bin_size = level_bin_size(level - 1);
+            let bin_size = level_bin_size(level);
+            let coarser = (0..num_bins(length, bin_size))
+                .map(|bin| {
+                    let start = bin as u64 * bin_size;
+                    let end = (start + bin_size).min(length);
+                    let bases: f64 = (start..end)
+                        .step_by(finer_bin_size as usize)
+                        .map(|finer_start| {
+                            let finer_end = (finer_start + finer_bin_size).min(length);
+                            let depth = levels[level - 1][(finer_start / finer_bin_size) as usize];
+                            depth as f64 * (finer_end - finer_start) as f64
+                        })
+                        .sum();
+                    (bases / (end - start) as f64) as f32
+                })
+                .collect();
+            levels.push(coarser);
+        }
+        Self { length, levels }
+    }
+}
+
+/// Read depth of every sequence in a SAM/BAM file at several resolutions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoveragePyramid {
+    seqs: BTreeMap<String, SeqCoverage>,
+}
+
+impl CoveragePyramid {
+    /// Compute the pyramid for a SAM/BAM file by reading every alignment in it.
+    ///
+    /// Depth is counted the same way as pileups, so deletions are included but reference skips
+    /// and unmapped reads aren't.
+    pub fn build<P: Into<PathBuf>>(bam_path: P) -> Result<Self> {
+        let bam_path = bam_path.into();
+        let mut reader = open_bam(&bam_path)?;
+        let header = reader.header().clone();
+        let mut bases: Vec<Vec<u64>> = (0..header.target_count())
+            .map(|tid| {
+                let length = header.target_len(tid).unwrap_or(0);
+                vec![0; num_bins(length, FINEST_BIN_SIZE)]
+            })
+            .collect();
+        let mut record = Record::new();
+        while let Some(result) = reader.read(&mut record) {
+            result.with_context(|| format!("Failed to read {}", bam_path.display()))?;
+            if record.is_unmapped() || record.tid() < 0 {
+                continue;
+            }
+            let seq_bases = match bases.get_mut(record.tid() as usize) {
+                Some(seq_bases) => seq_bases,
+                None => continue,
+            };
+            for (start, end) in aligned_blocks(&record) {
+                add_bases(seq_bases, start, end);
+            }
+        }
+        let seqs = bases
+            .into_iter()
+            .enumerate()
+            .map(|(tid, seq_bases)| {
+                let tid = tid as u32;
+                let name = String::from_utf8_lossy(header.tid2name(tid)).to_string();
+                let length = header.target_len(tid).unwrap_or(0);
+                let finest = seq_bases
+                    .iter()
+                    .enumerate()
+                    .map(|(bin, bin_bases)| {
+                        let start = bin as u64 * FINEST_BIN_SIZE;
+                        let end = (start + FINEST_BIN_SIZE).min(length);
+                        (*bin_bases as f64 / (end - start) as f64) as f32
+                    })
+                    .collect();
+                (name, SeqCoverage::from_finest(length, finest))
+            })
+            .collect();
+        Ok(Self { seqs })
+    }
+
+    /// Get the binned depth across a region using the finest level which has at most `max_bins`
+    /// bins in the region (or the coarsest level if none do).
+    ///
+    /// The region's sequence name may be an alias of the name used in the file.
+    pub fn get_binned_coverage(
+        &self,
+        region: &GenomicRegion,
+        max_bins: usize,
+    ) -> Result<BinnedCoverage> {
+        let seq_name = resolve_contig_name(&region.seq_name, self.seqs.keys().map(String::as_str))
+            .with_context(|| format!("Invalid contig/chromosome name: {}", region.seq_name))?;
+        let seq = &self.seqs[seq_name];
+        let start = region.start().min(seq.length);
+        let end = region.end().min(seq.length);
+        let level = (0..seq.levels.len())
+            .find(|level| {
+                let bin_size = level_bin_size(*level);
+                num_bins(end, bin_size) - (start / bin_size) as usize <= max_bins
+            })
+            .unwrap_or(seq.levels.len() - 1);
+        let bin_size = level_bin_size(level);
+        let first_bin = (start / bin_size) as usize;
+        let last_bin = num_bins(end, bin_size).max(first_bin);
+        let region = GenomicRegion::new(
+            &region.seq_name,
+            first_bin as u64 * bin_size,
+            (last_bin as u64 * bin_size).min(seq.length),
+        )?;
+        let depths = seq.levels[level][first_bin..last_bin].to_vec();
+        Ok(BinnedCoverage { region, bin_size, depths })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(CACHE_MAGIC)?;
+        writer.write_all(&(self.seqs.len() as u64).to_le_bytes())?;
+        for (name, seq) in self.seqs.iter() {
+            writer.write_all(&(name.len() as u64).to_le_bytes())?;
+            writer.write_all(name.as_bytes())?;
+            writer.write_all(&seq.length.to_le_bytes())?;
+            for depth in seq.levels[0].iter() {
+                writer.write_all(&depth.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read<R: IoRead>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != CACHE_MAGIC {
+            bail!("Unrecognized coverage cache format");
+        }
+        let read_u64 = |reader: &mut R| -> Result<u64> {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let mut seqs = BTreeMap::new();
+        for _ in 0..read_u64(reader)? {
+            let mut name = vec![0; read_u64(reader)? as usize];
+            reader.read_exact(&mut name)?;
+            let length = read_u64(reader)?;
+            let mut finest = Vec::with_capacity(num_bins(length, FINEST_BIN_SIZE));
+            for _ in 0..num_bins(length, FINEST_BIN_SIZE) {
+                let mut bytes = [0; 4];
+                reader.read_exact(&mut bytes)?;
+                finest.push(f32::from_le_bytes(bytes));
+            }
+            let name = String::from_utf8(name).context("Invalid sequence name")?;
+            seqs.insert(name, SeqCoverage::from_finest(length, finest));
+        }
+        Ok(Self { seqs })
+    }
+}
+
+/// Get the reference intervals [start, end) covered by a record's aligned bases and deletions.
+fn aligned_blocks(record: &Record) -> Vec<(u64, u64)> {
+    let mut blocks = Vec::new();
+    let mut position = record.pos().max(0) as u64;
+    for op in record.cigar().iter() {
+        match op {
+            Cigar::Match(len) | Cigar::Equal(len) | Cigar::Diff(len) | Cigar::Del(len) => {
+                blocks.push((position, position + *len as u64));
+                position += *len as u64;
+            }
+            Cigar::RefSkip(len) => position += *len as u64,
+            Cigar::Ins(_) | Cigar::SoftClip(_) | Cigar::HardClip(_) | Cigar::Pad(_) => (),
+        }
+    }
+    blocks
+}
+
+/// Estimate the binned depth across a region of an indexed SAM/BAM file from a short window at
+/// the center of each bin, so that only a small fraction of the region's records are read.
+///
+/// # Arguments
+///
+/// * `seq_name` - Name of the region's sequence in the file (which may be an alias of the
+///   region's sequence name).
+/// * `num_bins` - Maximum number of bins. Fewer are used if the region is shorter than this.
+pub fn sample_binned_coverage(
+    reader: &mut bam::IndexedReader,
+    seq_name: &str,
+    region: &GenomicRegion,
+    num_bins: usize,
+) -> Result<BinnedCoverage> {
+    let num_bins = num_bins.max(1) as u64;
+    let bin_size = ((region.len() + num_bins - 1) / num_bins).max(1);
+    let mut depths = Vec::new();
+    let mut record = Record::new();
+    for bin_start in (region.start()..region.end()).step_by(bin_size as usize) {
+        let bin_end = (bin_start + bin_size).min(region.end());
+        let sample_length = COVERAGE_SAMPLE_LENGTH.min(bin_end - bin_start);
+        let sample_start = bin_start + (bin_end - bin_start - sample_length) / 2;
+        let sample_end = sample_start + sample_length;
+        reader.fetch((seq_name, sample_start, sample_end))?;
+        let mut bases = 0;
+        while let Some(result) = reader.read(&mut record) {
+            result?;
+            if record.is_unmapped() {
+                continue;
+            }
+            for (start, end) in aligned_blocks(&record) {
+                bases += end.min(sample_end).saturating_sub(start.max(sample_start));
+            }
+        }
+        depths.push((bases as f64 / sample_length as f64) as f32);
+    }
+    Ok(BinnedCoverage { region: region.clone(), bin_size, depths })
+}
+
+/// Add the bases of an aligned block [start, end) to the bins which it overlaps.
+fn add_bases(bins: &mut [u64], start: u64, end: u64) {
+    let mut position = start;
+    while position < end {
+        let bin = (position / FINEST_BIN_SIZE) as usize;
+        let bin_end = ((bin as u64 + 1) * FINEST_BIN_SIZE).min(end);
+        match bins.get_mut(bin) {
+            Some(bin_bases) => *bin_bases += bin_end - position,
+            // The alignment runs off the end of the sequence
+            None => return,
+        }
+        position = bin_end;
+    }
+}
+
+/// Computes coverage pyramids and caches them in memory and on disk.
+///
+/// Cached pyramids are keyed by the file's path, size and modification time so that they're
+/// rebuilt if the file changes.
+#[derive(Debug)]
+pub struct CoveragePyramidCache {
+    dir: PathBuf,
+    /// Locked while the pyramid for a file is built so that it's only built once.
+    pyramids: DashMap<PathBuf, Arc<Mutex<Option<Arc<CoveragePyramid>>>>>,
+}
+
+impl CoveragePyramidCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into(), pyramids: DashMap::new() }
+    }
+
+    fn cache_path(&self, bam_path: &Path) -> Result<PathBuf> {
+        let metadata = fs::metadata(bam_path)
+            .with_context(|| format!("Failed to read metadata of {}", bam_path.display()))?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos();
+        let key = format!("{}\t{}\t{}", bam_path.display(), metadata.len(), modified);
+        Ok(self.dir.join(format!("{:016x}.{}", fnv1a(key.as_bytes()), CACHE_EXTENSION)))
+    }
+
+    fn load(&self, cache_path: &Path) -> Result<Option<CoveragePyramid>> {
+        if !cache_path.exists() {
+            return Ok(None);
+        }
+        let mut reader = BufReader::new(File::open(cache_path)?);
+        Ok(Some(CoveragePyramid::read(&mut reader)?))
+    }
+
+    fn save(&self, cache_path: &Path, pyramid: &CoveragePyramid) -> Result<()> {
+        fs::create_dir_all(&self.dir).with_context(|| {
+            format!("Failed to create coverage cache directory {}", self.dir.display())
+        })?;
+        // Written to a temporary file first so that readers never see a partially written pyramid
+        let tmp_path = self.dir.join(format!("{}.tmp", Uuid::new_v4()));
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        pyramid.write(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&tmp_path, cache_path)
+            .with_context(|| format!("Failed to write coverage cache {}", cache_path.display()))
+    }
+
+    /// Get the pyramid of a local SAM/BAM file, loading it from the disk cache or building it if
+    /// it hasn't been cached.
+    pub fn get<P: Into<PathBuf>>(&self, bam_path: P) -> Result<Arc<CoveragePyramid>> {
+        let bam_path: PathBuf = bam_path.into();
+        let entry = self.pyramids.entry(bam_path.clone()).or_default().clone();
+        let mut entry = entry.lock();
+        if let Some(pyramid) = entry.as_ref() {
+            return Ok(pyramid.clone());
+        }
+        let cache_path = self.cache_path(&bam_path)?;
+        let cached = self.load(&cache_path).unwrap_or_else(|err| {
+            log::warn!("Ignoring invalid coverage cache {}: {:#}", cache_path.display(), err);
+            None
+        });
+        let pyramid = match cached {
+            Some(pyramid) => pyramid,
+            None => {
+                log::info!("Computing coverage summary of {}", bam_path.display());
+                let pyramid = CoveragePyramid::build(&bam_path)?;
+                if let Err(err) = self.save(&cache_path, &pyramid) {
+                    log::error!("Failed to cache coverage of {}: {:#}", bam_path.display(), err);
+                }
+                pyramid
+            }
+        };
+        let pyramid = Arc::new(pyramid);
+        *entry = Some(pyramid.clone());
+        Ok(pyramid)
+    }
+
+    /// Whether the pyramid of a file is already cached in memory or on disk.
+    pub fn contains<P: AsRef<Path>>(&self, bam_path: P) -> bool {
+        let bam_path = bam_path.as_ref();
+        let in_memory = self.pyramids.get(bam_path).map_or(false, |entry| entry.lock().is_some());
+        in_memory || self.cache_path(bam_path).map_or(false, |path| path.exists())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::alignments::alignment_reader::AlignmentReader;
+    use crate::file_formats::fasta::reader::FastaReader;
+    use crate::file_formats::sam_bam::pileup::Pileup;
+    use crate::file_formats::sam_bam::reader::BamReader;
+    use crate::paths::get_test_data_path;
+
+    fn temp_cache_dir() -> PathBuf {
+        std::env::temp_dir().join(Uuid::new_v4().to_string())
+    }
+
+    #[test]
+    fn test_add_bases() {
+        let mut bins = vec![0; 3];
+        add_bases(&mut bins, 200, 600);
+        add_bases(&mut bins, 700, 900);
+        assert_eq!(bins, vec![56, 256, 156]);
+    }
+
+    #[test]
+    fn test_from_finest() {
+        let seq = SeqCoverage::from_finest(1280, vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        // The last bin of each level is shorter so has less weight in the level above
+        assert_eq!(seq.levels, vec![vec![1.0, 2.0, 3.0, 4.0, 5.0], vec![2.5, 5.0], vec![3.0]]);
+    }
+
+    #[test]
+    fn test_build_matches_pileup() {
+        let bam_path = get_test_data_path("fake-genome.reads.bam");
+        let pyramid = CoveragePyramid::build(&bam_path).unwrap();
+        let region = GenomicRegion::new("mt", 1024, 1024 + FINEST_BIN_SIZE).unwrap();
+        let binned = pyramid.get_binned_coverage(&region, 1).unwrap();
+        assert_eq!(binned.region, region);
+        assert_eq!(binned.bin_size, FINEST_BIN_SIZE);
+
+        let sequence_view =
+            FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap().read(&region).unwrap();
+        let (reads, _) = BamReader::new(bam_path).unwrap().read(&region, &sequence_view).unwrap();
+        let pileup = Pileup::from_reads(&region, &reads, &sequence_view).unwrap();
+        let total: u32 = pileup.columns.iter().map(|column| column.depth()).sum();
+        let expected = total as f32 / FINEST_BIN_SIZE as f32;
+        assert!((binned.depths[0] - expected).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_get_binned_coverage_picks_level() {
+        let pyramid = CoveragePyramid::build(get_test_data_path("fake-genome.reads.bam")).unwrap();
+        let region = GenomicRegion::new("chrM", 100, 10000).unwrap();
+        let binned = pyramid.get_binned_coverage(&region, 20).unwrap();
+        assert_eq!(binned.bin_size, 1024);
+        assert_eq!(binned.region, GenomicRegion::new("chrM", 0, 10240).unwrap());
+        assert_eq!(binned.depths.len(), 10);
+        // Regions past the end of the sequence are clamped to it
+        let region = GenomicRegion::new("mt", 0, 1_000_000).unwrap();
+        let binned = pyramid.get_binned_coverage(&region, 1).unwrap();
+        assert_eq!(binned.region, GenomicRegion::new("mt", 0, 16569).unwrap());
+        assert_eq!(binned.depths.len(), 1);
+        let region = GenomicRegion::new("chr1", 0, 100).unwrap();
+        assert!(pyramid.get_binned_coverage(&region, 1).is_err());
+    }
+
+    #[test]
+    fn test_sample_binned_coverage() {
+        let bam_path = get_test_data_path("fake-genome.reads.bam");
+        let pyramid = CoveragePyramid::build(&bam_path).unwrap();
+        let mut reader = bam::IndexedReader::from_path(&bam_path).unwrap();
+        // Bins which are no longer than the sample length are read in full
+        let region = GenomicRegion::new("mt", 1024, 1024 + FINEST_BIN_SIZE).unwrap();
+        let sampled = sample_binned_coverage(&mut reader, "mt", &region, 2).unwrap();
+        assert_eq!(sampled.bin_size, 128);
+        let expected = pyramid.get_binned_coverage(&region, 1).unwrap().depths[0];
+        assert!(((sampled.depths[0] + sampled.depths[1]) / 2.0 - expected).abs() < 1e-3);
+
+        let region = GenomicRegion::new("mt", 0, 16569).unwrap();
+        let sampled = sample_binned_coverage(&mut reader, "mt", &region, 10).unwrap();
+        assert_eq!(sampled.bin_size, 1657);
+        assert_eq!(sampled.region, region);
+        assert_eq!(sampled.depths.len(), 10);
+        assert!(sampled.depths.iter().any(|depth| *depth > 0.0));
+    }
+
+    #[test]
+    fn test_cache_pyramid_on_disk() {
+        let dir = temp_cache_dir();
+        let bam_path = get_test_data_path("fake-genome.reads.bam");
+        let cache = CoveragePyramidCache::new(&dir);
+        assert!(!cache.contains(&bam_path));
+        let pyramid = cache.get(&bam_path).unwrap();
+        assert!(cache.contains(&bam_path));
+        let reopened = CoveragePyramidCache::new(&dir);
+        assert!(reopened.contains(&bam_path));
+        let cached = reopened.get(&bam_path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(cached, pyramid);
+    }
+}
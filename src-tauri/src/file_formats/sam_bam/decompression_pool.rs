@@ -0,0 +1,140 @@
+//! An htslib thread pool which is shared by every BAM reader, so that BGZF decompression of large
+//! regions uses multiple cores without each reader starting its own threads.
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use parking_lot::{const_rwlock, RwLock};
+use rust_htslib::bam;
+use rust_htslib::bam::Read;
+use rust_htslib::htslib;
+
+/// Number of threads in the shared decompression pool, unless configured otherwise.
+pub const DEFAULT_DECOMPRESSION_THREADS: usize = 4;
+
+/// Pool which newly opened readers decompress with, or None to decompress on the reading thread.
+static DECOMPRESSION_POOL: RwLock<Option<Arc<DecompressionPool>>> = const_rwlock(None);
+
+/// Start a pool of `threads` threads which newly opened readers share for decompression, replacing
+/// the current pool. Readers which are already open keep using the pool they were opened with.
+/// Zero disables multi-threaded decompression.
+pub fn configure_decompression_threads(threads: usize) -> Result<()> {
+    let pool = match threads {
+        0 => None,
+        threads => Some(Arc::new(DecompressionPool::new(threads)?)),
+    };
+    *DECOMPRESSION_POOL.write() = pool;
+    Ok(())
+}
+
+/// Get the pool which newly opened readers should decompress with.
+pub fn get_decompression_pool() -> Option<Arc<DecompressionPool>> {
+    DECOMPRESSION_POOL.read().clone()
+}
+
+/// An htslib thread pool, which is destroyed once every reader using it has been closed.
+#[derive(Debug)]
+pub struct DecompressionPool {
+    pool: *mut htslib::hts_tpool,
+    threads: usize,
+}
+
+// htslib's thread pools are thread safe and are designed to be shared by files which are read on
+// different threads. The pool is only destroyed once it's dropped, after the last reader holding a
+// reference to it.
+unsafe impl Send for DecompressionPool {}
+unsafe impl Sync for DecompressionPool {}
+
+impl DecompressionPool {
+    pub fn new(threads: usize) -> Result<Self> {
+        let num_threads = i32::try_from(threads).context("Too many decompression threads")?;
+        let pool = unsafe { htslib::hts_tpool_init(num_threads) };
+        if pool.is_null() {
+            bail!("Failed to start a pool of {} decompression threads", threads);
+        }
+        Ok(Self { pool, threads })
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+}
+
+impl Drop for DecompressionPool {
+    fn drop(&mut self) {
+        unsafe { htslib::hts_tpool_destroy(self.pool) };
+    }
+}
+
+/// A BAM reader which decompresses with a shared pool (if any), and keeps the pool alive while
+/// it's open.
+#[derive(Debug)]
+pub struct PooledReader {
+    // Fields are dropped in order, so the reader is closed before its reference to the pool is
+    // released
+    reader: bam::IndexedReader,
+    pool: Option<Arc<DecompressionPool>>,
+}
+
+impl PooledReader {
+    pub fn new(
+        mut reader: bam::IndexedReader,
+        pool: Option<Arc<DecompressionPool>>,
+    ) -> Result<Self> {
+        if let Some(pool) = &pool {
+            // htslib copies the pool pointer and queue size into the file
+            let mut thread_pool = htslib::htsThreadPool { pool: pool.pool, qsize: 0 };
+            let status = unsafe { htslib::hts_set_thread_pool(reader.htsfile(), &mut thread_pool) };
+            if status != 0 {
+                bail!("Failed to attach decompression thread pool (status {})", status);
+            }
+        }
+        Ok(Self { reader, pool })
+    }
+
+    /// Number of threads which the reader decompresses with (zero if it decompresses on the
+    /// reading thread).
+    pub fn decompression_threads(&self) -> usize {
+        self.pool.as_ref().map_or(0, |pool| pool.threads())
+    }
+}
+
+impl Deref for PooledReader {
+    type Target = bam::IndexedReader;
+
+    fn deref(&self) -> &bam::IndexedReader {
+        &self.reader
+    }
+}
+
+impl DerefMut for PooledReader {
+    fn deref_mut(&mut self) -> &mut bam::IndexedReader {
+        &mut self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_readers_share_pool() {
+        let bam_path = get_test_data_path("fake-genome.reads.bam");
+        let pool = Arc::new(DecompressionPool::new(2).unwrap());
+        let mut readers: Vec<_> = (0..2)
+            .map(|_| {
+                let reader = bam::IndexedReader::from_path(&bam_path).unwrap();
+                PooledReader::new(reader, Some(Arc::clone(&pool))).unwrap()
+            })
+            .collect();
+        assert_eq!(Arc::strong_count(&pool), 3);
+        // The pool stays alive until the last reader is closed
+        drop(pool);
+        for reader in readers.iter_mut() {
+            assert_eq!(reader.decompression_threads(), 2);
+            reader.fetch(("mt", 1000, 1500)).unwrap();
+            assert!(reader.records().count() > 0);
+        }
+    }
+}
@@ -1,14 +1,42 @@
-use anyhow::Result;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use anyhow::{bail, Result};
 use rust_htslib::bam::record::{Cigar, Record, Seq};
+use serde::ser::{SerializeTuple, Serializer};
 use serde::Serialize;
 
 use crate::bio_util::genomic_coordinates::GenomicInterval;
 use crate::bio_util::sequence::SequenceView;
 use crate::util::same_enum_variant;
 
+/// Version of the compact serialization format of SequenceDiff.
+///
+/// Must be incremented whenever the format changes so that the frontend can detect a mismatch.
+pub const DIFF_SCHEMA_VERSION: u32 = 2;
+
+/// Insertion sequences longer than this are truncated (long read insertions can be kilobases).
+///
+/// Full sequences can be fetched on demand with BamReader::fetch_insertion_sequence.
+pub const MAX_INSERTION_SEQUENCE_LENGTH: usize = 100;
+
+/// Minimum base quality of mismatches, unless configured otherwise (zero shows every mismatch).
+pub const DEFAULT_MIN_MISMATCH_BASE_QUALITY: u8 = 0;
+
+static MIN_MISMATCH_BASE_QUALITY: AtomicU8 = AtomicU8::new(DEFAULT_MIN_MISMATCH_BASE_QUALITY);
+
+/// Set the base quality below which mismatches are treated as matches (e.g to hide the noisy
+/// mismatches of nanopore reads).
+pub fn configure_min_mismatch_base_quality(min_base_quality: u8) {
+    MIN_MISMATCH_BASE_QUALITY.store(min_base_quality, Ordering::SeqCst);
+}
+
 /// A sequence difference between an aligned read and the reference.
-#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
-#[serde(rename_all = "camelCase", tag = "type")]
+///
+/// Reads at high depth can contain thousands of diffs so these are serialized as compact tuples
+/// rather than tagged objects: `[kind, start, end]` or `[kind, start, end, sequence]` where kind is
+/// a DiffKind code. Truncated insertions are serialized as `[kind, start, end, sequence, length]`.
+/// Positions are numbers whether or not numeric coordinates are enabled.
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum SequenceDiff {
     /// A single base substitution.
     // Cigar=M or X. M in cigar string can mean either a match or a mismatch.
@@ -22,6 +50,8 @@ pub enum SequenceDiff {
     Ins {
         interval: GenomicInterval,
         sequence: String,
+        /// Length of the full insertion if `sequence` was truncated.
+        full_length: Option<u32>,
     },
 
     /// A deletion of one or more bases which are present in the reference.
@@ -43,12 +73,83 @@ pub enum SequenceDiff {
     },
 }
 
+/// Integer codes which identify the variant of a serialized SequenceDiff.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum DiffKind {
+    Mismatch = 0,
+    Ins = 1,
+    Del = 2,
+    SoftClip = 3,
+    RefSkip = 4,
+}
+
+impl SequenceDiff {
+    pub fn kind(&self) -> DiffKind {
+        match self {
+            Self::Mismatch { .. } => DiffKind::Mismatch,
+            Self::Ins { .. } => DiffKind::Ins,
+            Self::Del { .. } => DiffKind::Del,
+            Self::SoftClip { .. } => DiffKind::SoftClip,
+            Self::RefSkip { .. } => DiffKind::RefSkip,
+        }
+    }
+
+    pub fn interval(&self) -> &GenomicInterval {
+        match self {
+            Self::Mismatch { interval, .. }
+            | Self::Ins { interval, .. }
+            | Self::Del { interval }
+            | Self::SoftClip { interval, .. }
+            | Self::RefSkip { interval } => interval,
+        }
+    }
+
+    pub fn sequence(&self) -> Option<&str> {
+        match self {
+            Self::Mismatch { sequence, .. }
+            | Self::Ins { sequence, .. }
+            | Self::SoftClip { sequence, .. } => Some(sequence),
+            Self::Del { .. } | Self::RefSkip { .. } => None,
+        }
+    }
+}
+
+impl Serialize for SequenceDiff {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let sequence = self.sequence();
+        let full_length = match self {
+            Self::Ins { full_length, .. } => *full_length,
+            _ => None,
+        };
+        let len = match (sequence, full_length) {
+            (Some(_), Some(_)) => 5,
+            (Some(_), None) => 4,
+            (None, _) => 3,
+        };
+        let mut tuple = serializer.serialize_tuple(len)?;
+        tuple.serialize_element(&(self.kind() as u8))?;
+        // Positions are always numbers in the compact format (unlike GenomicInterval coordinates,
+        // which are strings unless numeric coordinates are enabled) to keep payloads small
+        tuple.serialize_element(&self.interval().start)?;
+        tuple.serialize_element(&self.interval().end)?;
+        if let Some(sequence) = sequence {
+            tuple.serialize_element(sequence)?;
+        }
+        if let Some(full_length) = full_length {
+            tuple.serialize_element(&full_length)?;
+        }
+        tuple.end()
+    }
+}
+
 // This code is mostly stolen from rust-htslib's iterator of the same name with a few updates for
 // our usecase.
 // 1. i64s are cast to u64. This should be safe because we don't expect negative positions for
 // aligned reads.
 // 2. The current cigar operation is returned for each base so that we can distinguish between
 // insertions vs. softclipping.
+// 3. Padding and zero-length cigar operations are skipped rather than causing a panic.
 pub struct IterAlignedPairsCigar {
     genome_pos: i64,
     read_pos: i64,
@@ -78,6 +179,16 @@ impl Iterator for IterAlignedPairsCigar {
         while self.cigar_index < self.cigar.len() {
             let entry = self.cigar[self.cigar_index];
             match entry {
+                // Some tools emit zero-length operations (e.g 0I) which don't consume any bases
+                Cigar::Match(0)
+                | Cigar::Equal(0)
+                | Cigar::Diff(0)
+                | Cigar::Ins(0)
+                | Cigar::SoftClip(0)
+                | Cigar::Del(0)
+                | Cigar::RefSkip(0) => {
+                    // no advance
+                }
                 Cigar::Match(len) | Cigar::Equal(len) | Cigar::Diff(len) => {
                     self.genome_pos += 1;
                     self.read_pos += 1;
@@ -120,11 +231,11 @@ impl Iterator for IterAlignedPairsCigar {
                     }
                     return Some((entry, None, Some(self.genome_pos as u64 - 1)));
                 }
-                Cigar::HardClip(_) => {
+                // Padding is only used for multiple sequence alignment. It consumes neither read nor
+                // reference bases so, like hardclips, it doesn't affect the unpadded alignment.
+                Cigar::HardClip(_) | Cigar::Pad(_) => {
                     // no advance
                 }
-                // padding is only used for multiple sequence alignment
-                Cigar::Pad(_) => panic!("Padding (Cigar::Pad) is not supported."),
             }
             self.cigar_index += 1;
         }
@@ -132,7 +243,7 @@ impl Iterator for IterAlignedPairsCigar {
     }
 }
 
-fn iter_aligned_pairs_cigar(record: &Record) -> IterAlignedPairsCigar {
+pub fn iter_aligned_pairs_cigar(record: &Record) -> IterAlignedPairsCigar {
     IterAlignedPairsCigar::new(record.pos(), record.cigar().take().0)
 }
 
@@ -147,22 +258,103 @@ pub struct DiffAlignments<'a> {
     /// The read sequence
     record_sequence: Seq<'a>,
 
+    /// Base qualities of the read sequence (0xff if they're missing)
+    record_qualities: &'a [u8],
+
+    /// Mismatches with a lower base quality than this are skipped
+    min_base_quality: u8,
+
+    /// Insertion sequences longer than this are truncated (None to keep the full sequences)
+    max_insertion_length: Option<usize>,
+
     /// The current position which is being iterated over from the aligned read
     aligned_pair_index: usize,
 
     /// A vector of tuples of the form (current Cigar operation, current read position, current reference
     /// position).
     aligned_pairs: Vec<(Cigar, Option<usize>, Option<u64>)>,
+
+    /// True if the last reference position which was iterated over is outside of refseq.
+    outside_refseq: bool,
+
+    /// Number of diffs which were skipped because they fall outside of refseq.
+    num_hidden_diffs: u32,
 }
 
 impl<'a> DiffAlignments<'a> {
     pub fn new(record: &'a Record, refseq: &'a SequenceView) -> Self {
+        Self::with_min_base_quality(
+            record,
+            refseq,
+            MIN_MISMATCH_BASE_QUALITY.load(Ordering::SeqCst),
+        )
+    }
+
+    /// Iterate across the diffs of a read, skipping mismatches with a base quality below
+    /// `min_base_quality`. Reads without base qualities keep all of their mismatches.
+    pub fn with_min_base_quality(
+        record: &'a Record,
+        refseq: &'a SequenceView,
+        min_base_quality: u8,
+    ) -> Self {
         DiffAlignments {
             refseq,
             current_diff_ref_start: record.pos() as u64,
             record_sequence: record.seq(),
+            record_qualities: record.qual(),
+            min_base_quality,
+            max_insertion_length: Some(MAX_INSERTION_SEQUENCE_LENGTH),
             aligned_pair_index: 0,
             aligned_pairs: iter_aligned_pairs_cigar(record).collect(),
+            outside_refseq: !refseq.contains(record.pos() as u64),
+            num_hidden_diffs: 0,
+        }
+    }
+
+    /// Keep the full sequence of insertions rather than truncating them to
+    /// MAX_INSERTION_SEQUENCE_LENGTH.
+    pub fn with_full_insertions(mut self) -> Self {
+        self.max_insertion_length = None;
+        self
+    }
+
+    /// Number of diffs skipped so far because they fall outside of the reference sequence.
+    ///
+    /// Mismatches can't be detected without the reference sequence so they aren't included.
+    pub fn num_hidden_diffs(&self) -> u32 {
+        self.num_hidden_diffs
+    }
+
+    /// True if the current aligned pair is the last in a run of the same cigar operation.
+    fn is_end_of_run(&self) -> bool {
+        let next_index = self.aligned_pair_index + 1;
+        next_index >= self.aligned_pairs.len()
+            || !same_enum_variant(
+                &self.aligned_pairs[next_index].0,
+                &self.aligned_pairs[self.aligned_pair_index].0,
+            )
+    }
+
+    /// Get the base at a position in the read sequence.
+    ///
+    /// Fails if the CIGAR string implies a longer read than the stored sequence (e.g if SEQ is
+    /// omitted from the record).
+    fn read_base(&self, read_pos: usize) -> Result<u8> {
+        if read_pos >= self.record_sequence.len() {
+            bail!(
+                "CIGAR string extends past the end of the read sequence (length={}, position={})",
+                self.record_sequence.len(),
+                read_pos
+            );
+        }
+        Ok(self.record_sequence[read_pos])
+    }
+
+    /// True if the base at a position in the read sequence is below the minimum base quality.
+    fn is_low_quality(&self, read_pos: usize) -> bool {
+        match self.record_qualities.get(read_pos) {
+            Some(0xff) | None => false,
+            Some(quality) => *quality < self.min_base_quality,
         }
     }
 
@@ -177,13 +369,13 @@ impl<'a> DiffAlignments<'a> {
         loop {
             match aligned_pair {
                 (Cigar::Ins(_), Some(read_pos), None) => {
-                    sequence.push(self.record_sequence[read_pos]);
+                    sequence.push(self.read_base(read_pos)?);
                 }
                 (Cigar::Del(_) | Cigar::RefSkip(_), _, Some(ref_pos)) => {
                     current_ref_pos = ref_pos;
                 }
                 (Cigar::SoftClip(_), Some(read_pos), Some(ref_pos)) => {
-                    sequence.push(self.record_sequence[read_pos]);
+                    sequence.push(self.read_base(read_pos)?);
                     current_ref_pos = ref_pos;
                 }
                 _ => break,
@@ -200,11 +392,21 @@ impl<'a> DiffAlignments<'a> {
 
             self.aligned_pair_index += 1;
         }
+        let is_insertion = matches!(initial_aligned_pair.0, Cigar::Ins(_));
+        let full_length = match self.max_insertion_length {
+            Some(max_length) if is_insertion && sequence.len() > max_length => {
+                let full_length = sequence.len() as u32;
+                sequence.truncate(max_length);
+                Some(full_length)
+            }
+            _ => None,
+        };
         let sequence = String::from_utf8_lossy(&sequence).into();
         let diff = match initial_aligned_pair {
             (Cigar::Ins(_), _, _) => SequenceDiff::Ins {
                 interval: (self.current_diff_ref_start, current_ref_pos).try_into()?,
                 sequence,
+                full_length,
             },
             (Cigar::SoftClip(_), _, _) => SequenceDiff::SoftClip {
                 interval: (self.current_diff_ref_start, current_ref_pos + 1).try_into()?,
@@ -216,8 +418,14 @@ impl<'a> DiffAlignments<'a> {
             (Cigar::RefSkip(_), _, _) => SequenceDiff::RefSkip {
                 interval: (self.current_diff_ref_start, current_ref_pos + 1).try_into()?,
             },
-            _ => {
-                panic!("extend_diff_group called with invalid input");
+            (cigar, read_pos, ref_pos) => {
+                bail!(
+                    "Unexpected aligned pair while collapsing diff (cigar={:?}, read_pos={:?}, \
+                     ref_pos={:?})",
+                    cigar,
+                    read_pos,
+                    ref_pos
+                );
             }
         };
         Ok(diff)
@@ -229,14 +437,16 @@ impl<'a> DiffAlignments<'a> {
     /// mismatch. We handle this by directly comparing the read sequence to the reference sequence
     /// at these positions. An alternative would be to parse the MD tag but since reads from
     /// certain sequencers/aligners do not populate this tag its safer to just use the CIGAR.
+    ///
+    /// Mismatches with a base quality below the minimum are treated as matches.
     fn handle_possible_mismatch(
         &mut self,
         read_pos: usize,
         ref_pos: u64,
     ) -> Result<Option<SequenceDiff>> {
-        let read_base = self.record_sequence[read_pos];
+        let read_base = self.read_base(read_pos)?;
         let ref_base = self.refseq[ref_pos];
-        if read_base != ref_base {
+        if read_base != ref_base && !self.is_low_quality(read_pos) {
             let interval = (ref_pos, ref_pos + 1).try_into()?;
             return Ok(Some(SequenceDiff::Mismatch {
                 interval,
@@ -253,15 +463,32 @@ impl<'a> Iterator for DiffAlignments<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         while self.aligned_pair_index < self.aligned_pairs.len() {
             let aligned_pair = self.aligned_pairs[self.aligned_pair_index];
-            if let (_, _, Some(ref_pos)) = aligned_pair {
-                if !self.refseq.contains(ref_pos) {
-                    // If the position is outside of the viewed region then we skip computing diff
+            if let (cigar, _, Some(ref_pos)) = aligned_pair {
+                self.outside_refseq = !self.refseq.contains(ref_pos);
+                if self.outside_refseq {
+                    // If the position is outside of the viewed region then we skip computing diff.
+                    // Diffs which lie entirely outside of the region are counted so that the
+                    // frontend can indicate that they're hidden.
+                    let is_diff =
+                        matches!(cigar, Cigar::SoftClip(_) | Cigar::Del(_) | Cigar::RefSkip(_));
+                    if is_diff && self.is_end_of_run() {
+                        self.num_hidden_diffs += 1;
+                    }
                     self.aligned_pair_index += 1;
                     continue;
                 }
                 self.current_diff_ref_start = ref_pos;
             }
             let maybe_diff = match aligned_pair {
+                // Insertions don't have a reference position so they are hidden if the preceding
+                // reference position is outside of the viewed region.
+                (Cigar::Ins(_), _, _) if self.outside_refseq => match self.collapse_diff() {
+                    Ok(_) => {
+                        self.num_hidden_diffs += 1;
+                        None
+                    }
+                    Err(err) => Some(Err(err)),
+                },
                 (Cigar::Ins(_) | Cigar::SoftClip(_) | Cigar::Del(_) | Cigar::RefSkip(_), _, _) => {
                     Some(self.collapse_diff())
                 }
@@ -340,6 +567,27 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn test_diff_hides_low_quality_mismatches() {
+        let seqview = SequenceView::new("TTTAGCTAAA".as_bytes().to_vec(), 1000);
+        let cigar = CigarString::try_from("4M").unwrap();
+        let record = RecordBuilder::new(b"read", b"TGGT", Some(&cigar), &[30, 30, 5, 30]).record;
+        let diffs: Vec<SequenceDiff> = DiffAlignments::with_min_base_quality(&record, &seqview, 10)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            diffs,
+            vec!(SequenceDiff::Mismatch {
+                interval: (1003, 1004).try_into().unwrap(),
+                sequence: "T".to_owned()
+            })
+        );
+        // Reads without base qualities keep their mismatches
+        let record = RecordBuilder::new(b"read", b"TGGT", Some(&cigar), &[0xff; 4]).record;
+        let diffs = DiffAlignments::with_min_base_quality(&record, &seqview, 10);
+        assert_eq!(diffs.count(), 2);
+    }
+
     #[test]
     pub fn test_diff_with_deletion() {
         let diffs = run_diff("3M1D", b"AGC", b"BBB").unwrap();
@@ -353,7 +601,8 @@ mod tests {
             diffs,
             vec!(SequenceDiff::Ins {
                 interval: (1004, 1004).try_into().unwrap(),
-                sequence: "T".to_owned()
+                sequence: "T".to_owned(),
+                full_length: None,
             })
         );
     }
@@ -397,6 +646,50 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn test_diffs_outside_of_refseq_are_hidden() {
+        let seqview = SequenceView::new("TTTAGCTAAA".as_bytes().to_vec(), 1000);
+        let cigar = CigarString::try_from("7M1D1M2I").unwrap();
+        let record = RecordBuilder::new(b"read", b"AGCTAAAATT", Some(&cigar), b"BBBBBBBBBB").record;
+        let mut diff_iter = iter_sequence_diffs(&record, &seqview);
+        let diffs = diff_iter.by_ref().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(diffs, Vec::new());
+        assert_eq!(diff_iter.num_hidden_diffs(), 2);
+    }
+
+    #[test]
+    pub fn test_diff_crossing_refseq_boundary_is_not_hidden() {
+        let seqview = SequenceView::new("TTTAGCTAAA".as_bytes().to_vec(), 1000);
+        let cigar = CigarString::try_from("6M3D1M").unwrap();
+        let record = RecordBuilder::new(b"read", b"AGCTAAA", Some(&cigar), b"BBBBBBB").record;
+        let mut diff_iter = iter_sequence_diffs(&record, &seqview);
+        let diffs = diff_iter.by_ref().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(diffs, vec!(SequenceDiff::Del { interval: (1009, 1012).try_into().unwrap() }));
+        assert_eq!(diff_iter.num_hidden_diffs(), 0);
+    }
+
+    #[test]
+    pub fn test_long_insertion_is_truncated() {
+        let seqview = SequenceView::new("TTTAGCTAAA".as_bytes().to_vec(), 1000);
+        let insertion = "T".repeat(MAX_INSERTION_SEQUENCE_LENGTH + 10);
+        let cigar = CigarString::try_from(format!("2M{}I2M", insertion.len()).as_str()).unwrap();
+        let seq = format!("AG{}CT", insertion);
+        let qual = vec![b'B'; seq.len()];
+        let record = RecordBuilder::new(b"read", seq.as_bytes(), Some(&cigar), &qual).record;
+        let diffs = iter_sequence_diffs(&record, &seqview).collect::<Result<Vec<_>>>().unwrap();
+        let expected = SequenceDiff::Ins {
+            interval: (1004, 1004).try_into().unwrap(),
+            sequence: insertion[..MAX_INSERTION_SEQUENCE_LENGTH].to_owned(),
+            full_length: Some(insertion.len() as u32),
+        };
+        assert_eq!(diffs, vec![expected]);
+        let diffs = DiffAlignments::new(&record, &seqview)
+            .with_full_insertions()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(diffs[0].sequence(), Some(insertion.as_str()));
+    }
+
     #[test]
     pub fn test_complex_diff() {
         let diffs = run_diff("2M3D1M4I1M", b"AGATTTTA", b"BBBBBBBB").unwrap();
@@ -405,8 +698,78 @@ mod tests {
             SequenceDiff::Ins {
                 interval: (1008, 1008).try_into().unwrap(),
                 sequence: "TTTT".to_owned(),
+                full_length: None,
             },
         ];
         assert_eq!(diffs, expected_diffs);
     }
+
+    #[test]
+    pub fn test_padding_doesnt_produce_diffs() {
+        let diffs = run_diff("2M1P2M", b"AGCT", b"BBBB").unwrap();
+        assert_eq!(diffs, Vec::new());
+    }
+
+    #[test]
+    pub fn test_diff_with_padded_insertion() {
+        let diffs = run_diff("2M1I1P1I2M", b"AGTTCT", b"BBBBBB").unwrap();
+        assert_eq!(
+            diffs,
+            vec!(SequenceDiff::Ins {
+                interval: (1004, 1004).try_into().unwrap(),
+                sequence: "TT".to_owned(),
+                full_length: None,
+            })
+        );
+    }
+
+    #[test]
+    pub fn test_zero_length_operations_dont_produce_diffs() {
+        let diffs = run_diff("2M0I2M", b"AGCT", b"BBBB").unwrap();
+        assert_eq!(diffs, Vec::new());
+    }
+
+    #[test]
+    pub fn test_diff_with_hardclip_adjacent_softclips() {
+        let diffs = run_diff("2H1S2M1S3H", b"TGCA", b"BBBB").unwrap();
+        assert_eq!(
+            diffs,
+            vec!(
+                SequenceDiff::SoftClip {
+                    interval: (1003, 1004).try_into().unwrap(),
+                    sequence: "T".to_owned()
+                },
+                SequenceDiff::SoftClip {
+                    interval: (1006, 1007).try_into().unwrap(),
+                    sequence: "A".to_owned()
+                }
+            )
+        );
+    }
+
+    #[test]
+    pub fn test_diff_with_cigar_longer_than_sequence_returns_error() {
+        let result = run_diff("2M2I", b"AGC", b"BBB");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_serialize_compact_diffs() {
+        let diffs = vec![
+            SequenceDiff::Mismatch {
+                interval: (1003, 1004).try_into().unwrap(),
+                sequence: "A".to_owned(),
+            },
+            SequenceDiff::Del { interval: (1005, 1007).try_into().unwrap() },
+            SequenceDiff::Ins {
+                interval: (1008, 1008).try_into().unwrap(),
+                sequence: "AC".to_owned(),
+                full_length: Some(5000),
+            },
+        ];
+        assert_eq!(
+            serde_json::to_value(&diffs).unwrap(),
+            serde_json::json!([[0, 1003, 1004, "A"], [2, 1005, 1007], [1, 1008, 1008, "AC", 5000]])
+        );
+    }
 }
@@ -0,0 +1,531 @@
+//! Expressions which select reads by their fields, in the style of `samtools view -e`.
+//!
+//! E.g `mapq >= 30 && flag.proper_pair && [NM] <= 2`. Expressions are made up of:
+//!
+//! * Fields: `mapq`, `flag`, `pos`, `endpos`, `mpos`, `tlen`, `qlen`, `rlen` and `qname`.
+//!   Positions are 1-based as in SAM files.
+//! * Flags: `flag.paired`, `flag.proper_pair`, `flag.unmap`, `flag.munmap`, `flag.reverse`,
+//!   `flag.mreverse`, `flag.read1`, `flag.read2`, `flag.secondary`, `flag.qcfail`, `flag.dup` and
+//!   `flag.supplementary`.
+//! * Tags (e.g `[NM]`), which are true on their own if the read has the tag.
+//! * Number and string literals (e.g `30` or `"rg1"`).
+//! * The operators `||`, `&&`, `==`, `!=`, `<`, `<=`, `>`, `>=`, `+`, `-`, `*`, `/` and `!`, and
+//!   parentheses. Arithmetic binds more tightly than comparisons, which bind more tightly than
+//!   `&&` and then `||`. Comparisons can't be chained (e.g `1 < mapq < 10`).
+//!
+//! Comparisons involving a tag which the read doesn't have are false, as are comparisons between
+//! a number and a string.
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use rust_htslib::bam::record::{Aux, Record};
+
+const FLAG_NAMES: [(&str, u16); 12] = [
+    ("paired", 0x1),
+    ("proper_pair", 0x2),
+    ("unmap", 0x4),
+    ("munmap", 0x8),
+    ("reverse", 0x10),
+    ("mreverse", 0x20),
+    ("read1", 0x40),
+    ("read2", 0x80),
+    ("secondary", 0x100),
+    ("qcfail", 0x200),
+    ("dup", 0x400),
+    ("supplementary", 0x800),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Identifier(String),
+    Tag([u8; 2]),
+    Operator(&'static str),
+}
+
+/// Operators ordered so that longer operators are matched before their prefixes.
+const OPERATORS: [&str; 16] =
+    ["&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "+", "-", "*", "/", "(", ")", "="];
+
+/// Split an expression into tokens, each paired with its offset in the expression.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let (offset, c) = chars[i];
+        let rest = &source[offset..];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit()
+            || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let length =
+                rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '.').unwrap_or(rest.len());
+            let text = &rest[..length];
+            let number = match text.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16).map(|number| number as f64).ok(),
+                None => text.parse::<f64>().ok(),
+            };
+            let number =
+                number.ok_or_else(|| anyhow!("Invalid number at position {}: {}", offset, text))?;
+            tokens.push((offset, Token::Number(number)));
+            i += text.chars().count();
+        } else if c == '"' || c == '\'' {
+            let length = rest[1..]
+                .find(c)
+                .ok_or_else(|| anyhow!("Unterminated string at position {}", offset))?;
+            let text = &rest[1..length + 1];
+            tokens.push((offset, Token::String(text.to_owned())));
+            i += text.chars().count() + 2;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '.')
+                .unwrap_or(rest.len());
+            tokens.push((offset, Token::Identifier(rest[..length].to_owned())));
+            i += length;
+        } else if c == '[' {
+            let tag = rest.get(1..3).filter(|tag| {
+                tag.chars().all(|c| c.is_ascii_alphanumeric()) && rest[3..].starts_with(']')
+            });
+            let tag = tag.ok_or_else(|| anyhow!("Invalid tag at position {}", offset))?.as_bytes();
+            tokens.push((offset, Token::Tag([tag[0], tag[1]])));
+            i += 4;
+        } else {
+            let operator = OPERATORS
+                .iter()
+                .find(|operator| rest.starts_with(*operator))
+                .ok_or_else(|| anyhow!("Unexpected character at position {}: {}", offset, c))?;
+            if *operator == "=" {
+                bail!("Unexpected character at position {}: = (use == for equality)", offset);
+            }
+            tokens.push((offset, Token::Operator(operator)));
+            i += operator.len();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Mapq,
+    Flag,
+    Pos,
+    Endpos,
+    Mpos,
+    Tlen,
+    Qlen,
+    Rlen,
+    Qname,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        let field = match name {
+            "mapq" => Self::Mapq,
+            "flag" => Self::Flag,
+            "pos" => Self::Pos,
+            "endpos" => Self::Endpos,
+            "mpos" => Self::Mpos,
+            "tlen" => Self::Tlen,
+            "qlen" => Self::Qlen,
+            "rlen" => Self::Rlen,
+            "qname" => Self::Qname,
+            _ => return None,
+        };
+        Some(field)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(f64),
+    String(String),
+    Field(Field),
+    Flag(u16),
+    Tag([u8; 2]),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+/// Binary operators grouped by precedence, from lowest to highest.
+const PRECEDENCE: [&[(&str, BinaryOp)]; 5] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[
+        ("==", BinaryOp::Eq),
+        ("!=", BinaryOp::Ne),
+        ("<", BinaryOp::Lt),
+        ("<=", BinaryOp::Le),
+        (">", BinaryOp::Gt),
+        (">=", BinaryOp::Ge),
+    ],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[("*", BinaryOp::Mul), ("/", BinaryOp::Div)],
+];
+
+/// Recursive descent parser over the tokens of an expression.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    source_len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.position).map_or(self.source_len, |(offset, _)| *offset)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let offset = self.offset();
+        let token = self.peek().cloned();
+        self.position += 1;
+        token.ok_or_else(|| anyhow!("Unexpected end of expression at position {}", offset))
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr> {
+        if level == PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        let mut expr = self.parse_binary(level + 1)?;
+        let is_comparison = level == 2;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Operator(operator)) => PRECEDENCE[level]
+                    .iter()
+                    .find(|(symbol, _)| symbol == operator)
+                    .map(|(_, op)| *op),
+                _ => None,
+            };
+            let op = match op {
+                Some(op) => op,
+                None => return Ok(expr),
+            };
+            self.position += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+            if is_comparison {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        let offset = self.offset();
+        match self.next()? {
+            Token::Operator("!") => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Token::Operator("-") => Ok(Expr::Neg(Box::new(self.parse_unary()?))),
+            Token::Operator("(") => {
+                let expr = self.parse_binary(0)?;
+                match self.next()? {
+                    Token::Operator(")") => Ok(expr),
+                    _ => bail!("Expected ) at position {}", self.tokens[self.position - 1].0),
+                }
+            }
+            Token::Number(number) => Ok(Expr::Number(number)),
+            Token::String(string) => Ok(Expr::String(string)),
+            Token::Tag(tag) => Ok(Expr::Tag(tag)),
+            Token::Identifier(name) => {
+                if let Some(flag_name) = name.strip_prefix("flag.") {
+                    return FLAG_NAMES
+                        .iter()
+                        .find(|(name, _)| *name == flag_name)
+                        .map(|(_, bit)| Expr::Flag(*bit))
+                        .ok_or_else(|| anyhow!("Unknown flag at position {}: {}", offset, name));
+                }
+                Field::from_name(&name)
+                    .map(Expr::Field)
+                    .ok_or_else(|| anyhow!("Unknown field at position {}: {}", offset, name))
+            }
+            Token::Operator(operator) => {
+                bail!("Unexpected operator at position {}: {}", offset, operator)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(f64),
+    String(String),
+    /// A tag which the read doesn't have.
+    Missing,
+}
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        match self {
+            Self::Number(number) => *number != 0.0,
+            Self::String(string) => !string.is_empty(),
+            Self::Missing => false,
+        }
+    }
+}
+
+fn get_tag_value(record: &Record, tag: &[u8; 2]) -> Value {
+    match record.aux(tag) {
+        Ok(Aux::Char(c)) => Value::String((c as char).to_string()),
+        Ok(Aux::I8(n)) => Value::Number(n.into()),
+        Ok(Aux::U8(n)) => Value::Number(n.into()),
+        Ok(Aux::I16(n)) => Value::Number(n.into()),
+        Ok(Aux::U16(n)) => Value::Number(n.into()),
+        Ok(Aux::I32(n)) => Value::Number(n.into()),
+        Ok(Aux::U32(n)) => Value::Number(n.into()),
+        Ok(Aux::Float(n)) => Value::Number(n.into()),
+        Ok(Aux::Double(n)) => Value::Number(n),
+        Ok(Aux::String(string)) | Ok(Aux::HexByteArray(string)) => Value::String(string.to_owned()),
+        // Array tags can't be compared, but are still considered present
+        Ok(_) => Value::Number(1.0),
+        Err(_) => Value::Missing,
+    }
+}
+
+fn get_field_value(record: &Record, field: Field) -> Value {
+    let number = match field {
+        Field::Mapq => record.mapq().into(),
+        Field::Flag => record.flags().into(),
+        Field::Pos => (record.pos() + 1) as f64,
+        Field::Endpos => record.cigar().end_pos() as f64,
+        Field::Mpos => (record.mpos() + 1) as f64,
+        Field::Tlen => record.insert_size() as f64,
+        Field::Qlen => record.seq_len() as f64,
+        Field::Rlen => (record.cigar().end_pos() - record.pos()) as f64,
+        Field::Qname => return Value::String(String::from_utf8_lossy(record.qname()).into()),
+    };
+    Value::Number(number)
+}
+
+fn compare(op: BinaryOp, lhs: &Value, rhs: &Value) -> bool {
+    let ordering = match (lhs, rhs) {
+        (Value::Number(lhs), Value::Number(rhs)) => lhs.partial_cmp(rhs),
+        (Value::String(lhs), Value::String(rhs)) => Some(lhs.cmp(rhs)),
+        _ => None,
+    };
+    let ordering = match ordering {
+        Some(ordering) => ordering,
+        None => return false,
+    };
+    match op {
+        BinaryOp::Eq => ordering.is_eq(),
+        BinaryOp::Ne => ordering.is_ne(),
+        BinaryOp::Lt => ordering.is_lt(),
+        BinaryOp::Le => ordering.is_le(),
+        BinaryOp::Gt => ordering.is_gt(),
+        BinaryOp::Ge => ordering.is_ge(),
+        _ => false,
+    }
+}
+
+fn arithmetic(op: BinaryOp, lhs: &Value, rhs: &Value) -> Value {
+    let (lhs, rhs) = match (lhs, rhs) {
+        (Value::Number(lhs), Value::Number(rhs)) => (lhs, rhs),
+        _ => return Value::Missing,
+    };
+    match op {
+        BinaryOp::Add => Value::Number(lhs + rhs),
+        BinaryOp::Sub => Value::Number(lhs - rhs),
+        BinaryOp::Mul => Value::Number(lhs * rhs),
+        BinaryOp::Div => Value::Number(lhs / rhs),
+        _ => Value::Missing,
+    }
+}
+
+fn boolean(value: bool) -> Value {
+    Value::Number(if value { 1.0 } else { 0.0 })
+}
+
+fn evaluate(expr: &Expr, record: &Record) -> Value {
+    match expr {
+        Expr::Number(number) => Value::Number(*number),
+        Expr::String(string) => Value::String(string.clone()),
+        Expr::Field(field) => get_field_value(record, *field),
+        Expr::Flag(bit) => boolean(record.flags() & bit != 0),
+        Expr::Tag(tag) => get_tag_value(record, tag),
+        Expr::Not(expr) => boolean(!is_true(expr, record)),
+        Expr::Neg(expr) => match evaluate(expr, record) {
+            Value::Number(number) => Value::Number(-number),
+            _ => Value::Missing,
+        },
+        Expr::Binary(BinaryOp::Or, lhs, rhs) => {
+            boolean(is_true(lhs, record) || is_true(rhs, record))
+        }
+        Expr::Binary(BinaryOp::And, lhs, rhs) => {
+            boolean(is_true(lhs, record) && is_true(rhs, record))
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (evaluate(lhs, record), evaluate(rhs, record));
+            match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
+                    arithmetic(*op, &lhs, &rhs)
+                }
+                _ => boolean(compare(*op, &lhs, &rhs)),
+            }
+        }
+    }
+}
+
+/// Whether an expression is true for a record. Tags on their own are true if the read has them,
+/// even if their value is zero.
+fn is_true(expr: &Expr, record: &Record) -> bool {
+    match expr {
+        Expr::Tag(tag) => record.aux(tag).is_ok(),
+        _ => evaluate(expr, record).is_truthy(),
+    }
+}
+
+/// A parsed read filter expression.
+#[derive(Clone, Debug)]
+pub struct FilterExpression {
+    source: String,
+    root: Expr,
+}
+
+impl FilterExpression {
+    pub fn parse(source: &str) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        if tokens.is_empty() {
+            bail!("Filter expression is empty");
+        }
+        let mut parser = Parser { tokens, position: 0, source_len: source.len() };
+        let root = parser.parse_binary(0)?;
+        if parser.position < parser.tokens.len() {
+            bail!("Unexpected token at position {}", parser.offset());
+        }
+        Ok(Self { source: source.to_owned(), root })
+    }
+
+    pub fn matches(&self, record: &Record) -> bool {
+        is_true(&self.root, record)
+    }
+}
+
+impl PartialEq for FilterExpression {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for FilterExpression {}
+
+impl FromStr for FilterExpression {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        Self::parse(source)
+            .map_err(|err| anyhow!("Invalid filter expression \"{}\": {:#}", source, err))
+    }
+}
+
+impl fmt::Display for FilterExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rust_htslib::bam::record::{Cigar, CigarString};
+
+    use super::*;
+
+    fn gen_record() -> Record {
+        let mut record = Record::new();
+        let cigar = CigarString(vec![Cigar::Match(8), Cigar::Del(2)]);
+        record.set(b"read1", Some(&cigar), b"ACGTACGT", &[30; 8]);
+        record.set_pos(99);
+        record.set_mapq(40);
+        record.set_flags(0x1 | 0x2 | 0x40);
+        record.push_aux(b"NM", Aux::U8(1)).unwrap();
+        record.push_aux(b"RG", Aux::String("rg1")).unwrap();
+        record.push_aux(b"XS", Aux::I32(0)).unwrap();
+        record
+    }
+
+    fn matches(source: &str) -> bool {
+        FilterExpression::parse(source).unwrap().matches(&gen_record())
+    }
+
+    #[test]
+    fn test_filter_expression_fields() {
+        assert!(matches("mapq>=30 && flag.proper_pair && [NM]<=2"));
+        assert!(!matches("mapq > 40"));
+        assert!(matches("pos == 100 && endpos == 109 && rlen == 10 && qlen == 8"));
+        assert!(matches("flag == 0x43"));
+        assert!(matches("flag.read1 && !flag.read2 && !flag.dup"));
+        assert!(matches("qname == \"read1\" && [RG] == 'rg1'"));
+        assert!(!matches("[RG] != \"rg1\""));
+    }
+
+    #[test]
+    fn test_filter_expression_tags() {
+        // Tags with a value of zero are still present
+        assert!(matches("[XS]"));
+        assert!(!matches("[XS] > 0"));
+        assert!(!matches("[AS]"));
+        assert!(matches("![AS]"));
+        // Comparisons against missing tags and mismatched types are false
+        assert!(!matches("[AS] < 10"));
+        assert!(!matches("[AS] != 10"));
+        assert!(!matches("[RG] == 1"));
+    }
+
+    #[test]
+    fn test_filter_expression_precedence() {
+        assert!(matches("mapq > 50 || flag.paired && [NM] == 1"));
+        assert!(!matches("(mapq > 50 || flag.paired) && [NM] == 2"));
+        assert!(matches("mapq - 10 * 2 == 20"));
+        assert!(matches("-[NM] + 2 == 1"));
+        assert!(matches("mapq / 4 == 10.0"));
+    }
+
+    #[test]
+    fn test_filter_expression_errors() {
+        let invalid = [
+            "",
+            "mapq >=",
+            "mapq = 30",
+            "mapq >= 30)",
+            "(mapq >= 30",
+            "unknown > 1",
+            "flag.unknown",
+            "[N]",
+            "qname == \"read1",
+            "mapq >= 30 && $",
+            "1 < 2 < 3",
+        ];
+        for source in invalid {
+            assert!(FilterExpression::parse(source).is_err(), "{} should be invalid", source);
+        }
+    }
+
+    #[test]
+    fn test_filter_expression_roundtrip() {
+        let expression: FilterExpression = "mapq >= 30".parse().unwrap();
+        assert_eq!(expression.to_string(), "mapq >= 30");
+        assert_eq!(expression, FilterExpression::parse("mapq >= 30").unwrap());
+    }
+}
@@ -0,0 +1,79 @@
+//! Sequence dictionaries (@SQ header lines) of SAM/BAM files and of the `.dict` files which are
+//! written alongside reference sequences (e.g by Picard CreateSequenceDictionary).
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use rust_htslib::bam::Read;
+
+use crate::file_formats::sam_bam::reader::open_bam;
+
+/// A sequence listed in a sequence dictionary.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SeqDictEntry {
+    pub name: String,
+    pub length: u64,
+    /// MD5 checksum of the sequence (M5 tag), if present.
+    pub md5: Option<String>,
+}
+
+/// Get the sequences listed in the header of a SAM/BAM file.
+pub fn get_seq_dictionary<P: Into<PathBuf>>(bam_path: P) -> Result<Vec<SeqDictEntry>> {
+    let reader = open_bam(bam_path.into())?;
+    Ok(parse_seq_dictionary(reader.header().as_bytes()))
+}
+
+/// Read a `.dict` sequence dictionary file.
+pub fn read_seq_dictionary<P: AsRef<Path>>(dict_path: P) -> Result<Vec<SeqDictEntry>> {
+    let dict_path = dict_path.as_ref();
+    let contents = fs::read(dict_path).with_context(|| {
+        format!("Failed to read sequence dictionary {}", dict_path.to_string_lossy())
+    })?;
+    Ok(parse_seq_dictionary(&contents))
+}
+
+/// Parse the @SQ lines of a SAM header. Lines without a name or a valid length are skipped.
+fn parse_seq_dictionary(header: &[u8]) -> Vec<SeqDictEntry> {
+    let mut entries = Vec::new();
+    for line in String::from_utf8_lossy(header).lines() {
+        if !line.starts_with("@SQ\t") {
+            continue;
+        }
+        let get_tag = |tag: &str| line.split('\t').find_map(|field| field.strip_prefix(tag));
+        let name = get_tag("SN:");
+        let length = get_tag("LN:").and_then(|length| length.parse::<u64>().ok());
+        if let (Some(name), Some(length)) = (name, length) {
+            let md5 = get_tag("M5:").map(str::to_owned);
+            entries.push(SeqDictEntry { name: name.to_owned(), length, md5 });
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::paths::get_test_data_path;
+
+    use super::*;
+
+    #[test]
+    pub fn test_parse_seq_dictionary() {
+        let header = b"@HD\tVN:1.6\n@SQ\tSN:chr1\tLN:1000\tM5:ABC123\n@SQ\tSN:chr2\tLN:500\n\
+            @SQ\tSN:broken\tLN:x\n@RG\tID:rg1\tSN:notaseq\tLN:5\n";
+        let expected = vec![
+            SeqDictEntry { name: "chr1".to_owned(), length: 1000, md5: Some("ABC123".to_owned()) },
+            SeqDictEntry { name: "chr2".to_owned(), length: 500, md5: None },
+        ];
+        assert_eq!(parse_seq_dictionary(header), expected);
+    }
+
+    #[test]
+    pub fn test_get_seq_dictionary() {
+        let dictionary = get_seq_dictionary(get_test_data_path("fake-genome.reads.bam")).unwrap();
+        let names: Vec<_> = dictionary.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["euk_genes", "mt"]);
+        assert_eq!(dictionary[1].length, 16569);
+    }
+}
@@ -0,0 +1,221 @@
+//! Locating and building BAM indexes.
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use rust_htslib::bam::index::{self, Type};
+use rust_htslib::bam::{self, Read};
+use thiserror::Error;
+
+/// Number of threads used by htslib when building an index.
+const INDEX_BUILD_THREADS: u32 = 4;
+
+/// BAI indexes can't be used for sequences longer than this (2^29 - 1).
+const MAX_BAI_SEQ_LENGTH: u64 = (1 << 29) - 1;
+
+/// Minimum interval size (as a power of 2) of CSI indexes. This is the same default as samtools.
+const CSI_MIN_SHIFT: u32 = 14;
+
+/// Returned when a BAM file can't be read because of its index.
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum BamIndexError {
+    #[error("No index found for {} (a .bai or .csi index is required)", .0.display())]
+    Missing(PathBuf),
+    #[error("The index {} can't be used: {}", .index_path.display(), .reason)]
+    Unsupported { index_path: PathBuf, reason: String },
+}
+
+/// Find the index of a BAM file if one exists.
+///
+/// Checks the same locations as htslib (in the same order): `<file>.bam.csi`, `<file>.bam.bai` and
+/// `<file>.bai`.
+pub fn find_bam_index<P: AsRef<Path>>(bam_path: P) -> Option<PathBuf> {
+    let bam_path = bam_path.as_ref();
+    let with_suffix = |suffix: &str| {
+        let mut path: OsString = bam_path.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    };
+    [with_suffix(".csi"), with_suffix(".bai"), bam_path.with_extension("bai")]
+        .into_iter()
+        .find(|path| path.exists())
+}
+
+/// True if any of the sequences in a BAM file are too long to be indexed with a BAI index.
+fn requires_csi_index(bam_path: &Path) -> Result<bool> {
+    let reader = bam::Reader::from_path(bam_path)?;
+    let header = reader.header();
+    let requires_csi = (0..header.target_count())
+        .filter_map(|tid| header.target_len(tid))
+        .any(|seq_length| seq_length > MAX_BAI_SEQ_LENGTH);
+    Ok(requires_csi)
+}
+
+/// Find the index of a BAM file and check that it can be used to read the file.
+///
+/// BAI indexes can't address positions beyond 512Mb, so BAM files with longer sequences (e.g wheat
+/// or barley genomes) must have a CSI index.
+pub fn check_bam_index<P: AsRef<Path>>(bam_path: P) -> Result<PathBuf> {
+    let bam_path = bam_path.as_ref();
+    let index_path =
+        find_bam_index(bam_path).ok_or_else(|| BamIndexError::Missing(bam_path.to_owned()))?;
+    let is_csi = index_path.extension().map_or(false, |extension| extension == "csi");
+    if !is_csi && requires_csi_index(bam_path)? {
+        return Err(BamIndexError::Unsupported {
+            index_path,
+            reason: "BAI indexes don't support sequences longer than 512Mb, the file must be \
+                indexed with a CSI index instead (e.g samtools index -c)"
+                .to_owned(),
+        }
+        .into());
+    }
+    Ok(index_path)
+}
+
+/// Open an indexed BAM file from disk, distinguishing errors caused by the index from errors
+/// caused by the file itself.
+pub fn open_indexed_bam<P: AsRef<Path>>(bam_path: P) -> Result<bam::IndexedReader> {
+    let bam_path = bam_path.as_ref();
+    let index_path = check_bam_index(bam_path)?;
+    match bam::IndexedReader::from_path_and_index(bam_path, &index_path) {
+        Ok(reader) => Ok(reader),
+        Err(err) => {
+            bam::Reader::from_path(bam_path)
+                .with_context(|| format!("Failed to open {}", bam_path.to_string_lossy()))?;
+            Err(BamIndexError::Unsupported {
+                index_path,
+                reason: format!("{} (it may be corrupt or in an unknown format)", err),
+            }
+            .into())
+        }
+    }
+}
+
+/// Build an index for a BAM file.
+///
+/// A .bai index is built unless the BAM contains sequences longer than 512Mb, in which case a .csi
+/// index is built. The index is written alongside the BAM file. Returns the path to the index.
+pub fn build_bam_index<P: AsRef<Path>>(bam_path: P) -> Result<PathBuf> {
+    let bam_path = bam_path.as_ref();
+    log::info!("Building index for {}", bam_path.to_string_lossy());
+    let (index_type, suffix) = if requires_csi_index(bam_path)? {
+        (Type::Csi(CSI_MIN_SHIFT), ".csi")
+    } else {
+        (Type::Bai, ".bai")
+    };
+    let mut index_path: OsString = bam_path.as_os_str().to_owned();
+    index_path.push(suffix);
+    let index_path = PathBuf::from(index_path);
+    index::build(bam_path, Some(index_path.as_path()), index_type, INDEX_BUILD_THREADS)
+        .with_context(|| format!("Failed to build index for {}", bam_path.to_string_lossy()))?;
+    Ok(index_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::alignments::alignment::Alignment;
+    use crate::alignments::alignment_reader::AlignmentReader;
+    use crate::alignments::stack_reader::StackReader;
+    use crate::bio_util::genomic_coordinates::GenomicRegion;
+    use crate::bio_util::sequence::SequenceView;
+    use crate::file_formats::fasta::reader::FastaReader;
+    use crate::file_formats::sam_bam::reader::BamReader;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_build_missing_bam_index() {
+        let bam_path = std::env::temp_dir().join(format!("{}.bam", uuid::Uuid::new_v4()));
+        std::fs::copy(get_test_data_path("fake-genome.tiny.bam"), &bam_path).unwrap();
+        assert_eq!(find_bam_index(&bam_path), None);
+        let index_path = build_bam_index(&bam_path).unwrap();
+        assert_eq!(find_bam_index(&bam_path), Some(index_path.clone()));
+        assert!(BamReader::new(&bam_path).is_ok());
+        std::fs::remove_file(&bam_path).unwrap();
+        std::fs::remove_file(&index_path).unwrap();
+    }
+
+    /// Copy a test BAM file to a temporary path so that indexes can be written alongside it.
+    fn copy_test_bam(filename: &str) -> PathBuf {
+        let bam_path = std::env::temp_dir().join(format!("{}.bam", uuid::Uuid::new_v4()));
+        std::fs::copy(get_test_data_path(filename), &bam_path).unwrap();
+        bam_path
+    }
+
+    #[test]
+    fn test_missing_bam_index() {
+        let bam_path = copy_test_bam("fake-genome.tiny.bam");
+        let err = BamReader::new(&bam_path).unwrap_err();
+        std::fs::remove_file(&bam_path).unwrap();
+        assert_eq!(err.downcast_ref::<BamIndexError>(), Some(&BamIndexError::Missing(bam_path)));
+    }
+
+    #[test]
+    fn test_unsupported_bam_index() {
+        // A BAI index can't be used for sequences longer than 512Mb
+        let bam_path = copy_test_bam("long-contig.bam");
+        let bai_path = bam_path.with_extension("bai");
+        std::fs::copy(get_test_data_path("fake-genome.tiny.bam.bai"), &bai_path).unwrap();
+        let err = BamReader::new(&bam_path).unwrap_err();
+        std::fs::remove_file(&bam_path).unwrap();
+        std::fs::remove_file(&bai_path).unwrap();
+        let err = err.downcast::<BamIndexError>().unwrap();
+        assert!(
+            matches!(err, BamIndexError::Unsupported { index_path, .. } if index_path == bai_path)
+        );
+
+        // Nor can an index which htslib fails to load
+        let bam_path = copy_test_bam("fake-genome.tiny.bam");
+        let bai_path = bam_path.with_extension("bai");
+        std::fs::write(&bai_path, b"not an index").unwrap();
+        let err = BamReader::new(&bam_path).unwrap_err();
+        std::fs::remove_file(&bam_path).unwrap();
+        std::fs::remove_file(&bai_path).unwrap();
+        let err = err.downcast::<BamIndexError>().unwrap();
+        assert!(
+            matches!(err, BamIndexError::Unsupported { index_path, .. } if index_path == bai_path)
+        );
+    }
+
+    #[test]
+    fn test_read_bam_with_csi_index() {
+        let bam_path = copy_test_bam("fake-genome.reads.bam");
+        let mut index_path: OsString = bam_path.as_os_str().to_owned();
+        index_path.push(".csi");
+        let index_path = PathBuf::from(index_path);
+        index::build(bam_path.as_path(), Some(index_path.as_path()), Type::Csi(CSI_MIN_SHIFT), 1)
+            .unwrap();
+        assert_eq!(check_bam_index(&bam_path).unwrap(), index_path);
+        let stack_reader = StackReader::new(&bam_path);
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let sequence_view =
+            FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap().read(&region).unwrap();
+        let (alignments, _) =
+            BamReader::new(&bam_path).unwrap().read(&region, &sequence_view).unwrap();
+        std::fs::remove_file(&bam_path).unwrap();
+        std::fs::remove_file(&index_path).unwrap();
+        assert!(stack_reader.is_ok());
+        assert_eq!(alignments.len(), 575);
+    }
+
+    #[test]
+    fn test_build_csi_index_for_long_sequences() {
+        let bam_path = std::env::temp_dir().join(format!("{}.bam", uuid::Uuid::new_v4()));
+        std::fs::copy(get_test_data_path("long-contig.bam"), &bam_path).unwrap();
+        let index_path = build_bam_index(&bam_path).unwrap();
+        assert!(index_path.to_string_lossy().ends_with(".bam.csi"));
+        assert_eq!(find_bam_index(&bam_path), Some(index_path.clone()));
+
+        // Reads beyond the BAI limit can be fetched using the CSI index
+        let mut reader = BamReader::new(&bam_path).unwrap();
+        let region = GenomicRegion::new("long_contig", 549_999_990, 550_000_020).unwrap();
+        let refseq = SequenceView::new(vec![b'A'; region.len() as usize], region.start());
+        let (alignments, _) = reader.read(&region, &refseq).unwrap();
+        std::fs::remove_file(&bam_path).unwrap();
+        std::fs::remove_file(&index_path).unwrap();
+        let starts: Vec<_> = alignments.iter().map(|alignment| alignment.start()).collect();
+        assert_eq!(starts, vec![550_000_000, 550_000_005]);
+    }
+}
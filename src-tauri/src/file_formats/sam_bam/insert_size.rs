@@ -0,0 +1,157 @@
+//! Flagging read pairs with unusually small or large insert sizes (e.g evidence for deletions or
+//! insertions), similar to IGV's insert size coloring.
+//!
+//! The expected range of insert sizes is sampled from the FR pairs in the loaded region rather than
+//! being configured, so it adapts to each library.
+use serde::Serialize;
+
+use crate::file_formats::sam_bam::aligned_read::{AlignedPair, PairOrientation, PairedReads};
+
+/// Percentile below which insert sizes are flagged as small.
+pub const LOWER_INSERT_SIZE_PERCENTILE: f64 = 0.5;
+
+/// Percentile above which insert sizes are flagged as large.
+pub const UPPER_INSERT_SIZE_PERCENTILE: f64 = 99.5;
+
+/// Minimum number of pairs needed to estimate the expected insert size range.
+pub const MIN_INSERT_SIZE_SAMPLES: usize = 100;
+
+/// Whether a pair's insert size is within the expected range.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InsertSizeClass {
+    Small,
+    Normal,
+    Large,
+}
+
+/// Expected range of insert sizes (inclusive).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InsertSizeRange {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl InsertSizeRange {
+    /// Estimate the expected range from the percentiles of a sample of insert sizes.
+    ///
+    /// Returns None if there are too few insert sizes for the percentiles to be meaningful.
+    pub fn sample(insert_sizes: impl Iterator<Item = u64>) -> Option<Self> {
+        let mut insert_sizes: Vec<u64> = insert_sizes.collect();
+        if insert_sizes.len() < MIN_INSERT_SIZE_SAMPLES {
+            return None;
+        }
+        insert_sizes.sort_unstable();
+        let percentile = |percentile: f64| {
+            let index = (percentile / 100.0 * (insert_sizes.len() - 1) as f64).round() as usize;
+            insert_sizes[index]
+        };
+        Some(Self {
+            min: percentile(LOWER_INSERT_SIZE_PERCENTILE),
+            max: percentile(UPPER_INSERT_SIZE_PERCENTILE),
+        })
+    }
+
+    pub fn classify(&self, insert_size: u64) -> InsertSizeClass {
+        if insert_size < self.min {
+            InsertSizeClass::Small
+        } else if insert_size > self.max {
+            InsertSizeClass::Large
+        } else {
+            InsertSizeClass::Normal
+        }
+    }
+}
+
+/// Absolute insert size of a pair, or None if it's not set (TLEN=0).
+fn get_insert_size(pair: &PairedReads) -> Option<u64> {
+    let insert_size = pair.read1.template_length.unsigned_abs();
+    (insert_size > 0).then_some(insert_size)
+}
+
+fn iter_paired_reads(alignments: &mut [AlignedPair]) -> impl Iterator<Item = &mut PairedReads> {
+    alignments.iter_mut().filter_map(|pair| match pair {
+        AlignedPair::PairedReadsKind(paired_reads) => Some(paired_reads),
+        _ => None,
+    })
+}
+
+/// Classify the insert sizes of the paired reads in a set of alignments.
+///
+/// Pairs are left unclassified if there are too few FR pairs to estimate the expected range.
+pub fn classify_insert_sizes(alignments: &mut [AlignedPair]) {
+    let insert_sizes = iter_paired_reads(alignments)
+        .filter(|pair| pair.orientation == Some(PairOrientation::Fr))
+        .filter_map(|pair| get_insert_size(pair))
+        .collect::<Vec<u64>>();
+    let Some(range) = InsertSizeRange::sample(insert_sizes.into_iter()) else {
+        return;
+    };
+    for pair in iter_paired_reads(alignments) {
+        pair.insert_size_class =
+            get_insert_size(pair).map(|insert_size| range.classify(insert_size));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::bio_util::genomic_coordinates::GenomicRegion;
+    use crate::file_formats::sam_bam::aligned_read::AlignedRead;
+    use crate::test_util::aligned_reads::gen_read;
+
+    fn gen_pair(id: &str, template_length: i64) -> AlignedPair {
+        let start = id.len() as u64;
+        let read = AlignedRead {
+            id: format!("{}/1", id),
+            qname: id.to_owned(),
+            mate_pos: Some(GenomicRegion::new("X", start + 200, start + 201).unwrap()),
+            template_length,
+            pair_orientation: Some(PairOrientation::Fr),
+            ..gen_read(id, start, start + 100, Vec::new())
+        };
+        AlignedPair::PairedReadsKind(PairedReads::new(read, None).unwrap())
+    }
+
+    fn get_class(pair: &AlignedPair) -> Option<InsertSizeClass> {
+        match pair {
+            AlignedPair::PairedReadsKind(pair) => pair.insert_size_class,
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_sample_insert_size_range() {
+        let range = InsertSizeRange::sample(0..1001).unwrap();
+        assert_eq!(range, InsertSizeRange { min: 5, max: 995 });
+        assert_eq!(range.classify(4), InsertSizeClass::Small);
+        assert_eq!(range.classify(5), InsertSizeClass::Normal);
+        assert_eq!(range.classify(996), InsertSizeClass::Large);
+        assert_eq!(InsertSizeRange::sample(0..10), None);
+    }
+
+    #[test]
+    fn test_classify_insert_sizes() {
+        let mut alignments: Vec<AlignedPair> =
+            (0..200).map(|i| gen_pair(&format!("read{}", i), 300 + i % 10)).collect();
+        alignments.push(gen_pair("large", -5000));
+        alignments.push(gen_pair("small", 10));
+        alignments.push(gen_pair("unset", 0));
+        classify_insert_sizes(&mut alignments);
+        let classes: Vec<Option<InsertSizeClass>> = alignments.iter().map(get_class).collect();
+        assert_eq!(classes[0], Some(InsertSizeClass::Normal));
+        assert_eq!(
+            classes[200..],
+            [Some(InsertSizeClass::Large), Some(InsertSizeClass::Small), None]
+        );
+    }
+
+    #[test]
+    fn test_classify_insert_sizes_with_few_pairs() {
+        let mut alignments = vec![gen_pair("a", 300), gen_pair("b", 5000)];
+        classify_insert_sizes(&mut alignments);
+        assert!(alignments.iter().all(|pair| get_class(pair).is_none()));
+    }
+}
@@ -1,4 +1,18 @@
 pub mod aligned_read;
+pub mod base_modifications;
+pub mod coverage_pyramid;
+pub mod decompression_pool;
 pub mod diff;
+pub mod filter_expression;
+pub mod header;
+pub mod index;
+pub mod insert_size;
+pub mod pileup;
+pub mod read_details;
+pub mod read_lengths;
 pub mod reader;
+pub mod samples;
+pub mod softclip;
+pub mod splice_junctions;
+pub mod supplementary;
 pub mod tid;
@@ -0,0 +1,469 @@
+//! Per-position nucleotide counts computed from stacked reads.
+use anyhow::{bail, Result};
+use parking_lot::{const_rwlock, RwLock};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::bio_util::sequence::SequenceView;
+use crate::file_formats::sam_bam::aligned_read::AlignedRead;
+use crate::file_formats::sam_bam::diff::SequenceDiff;
+
+/// Maximum length of region for which a pileup can be requested by the frontend.
+pub const MAX_PILEUP_WINDOW: u64 = 10000;
+
+/// Fraction of reads with a mismatched base above which a position is highlighted, unless
+/// configured otherwise (matches IGV's default).
+pub const DEFAULT_MISMATCH_HIGHLIGHT_THRESHOLD: f64 = 0.2;
+
+static MISMATCH_HIGHLIGHT_THRESHOLD: RwLock<f64> =
+    const_rwlock(DEFAULT_MISMATCH_HIGHLIGHT_THRESHOLD);
+
+/// Set the fraction of reads with a mismatched base above which a position is highlighted.
+pub fn configure_mismatch_highlight_threshold(threshold: f64) {
+    *MISMATCH_HIGHLIGHT_THRESHOLD.write() = threshold.clamp(0.0, 1.0);
+}
+
+pub fn get_mismatch_highlight_threshold() -> f64 {
+    *MISMATCH_HIGHLIGHT_THRESHOLD.read()
+}
+
+/// The base observed in a single read at a single reference position.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ReadBase {
+    Base(u8),
+    Del,
+    /// Position is spanned by the read but not aligned (softclip or reference skip).
+    Skip,
+}
+
+/// Nucleotide counts at a single reference position.
+#[serde_as]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PileupColumn {
+    #[serde_as(as = "DisplayFromStr")]
+    pub position: u64,
+    pub a: u32,
+    pub c: u32,
+    pub g: u32,
+    pub t: u32,
+    pub n: u32,
+    pub del: u32,
+    pub ins: u32,
+}
+
+impl PileupColumn {
+    pub fn new(position: u64) -> Self {
+        Self { position, ..Default::default() }
+    }
+
+    /// Number of reads with an aligned base (or deletion) at this position.
+    pub fn depth(&self) -> u32 {
+        self.a + self.c + self.g + self.t + self.n + self.del
+    }
+
+    /// Fraction of the aligned bases at this position which don't match the reference base.
+    ///
+    /// Deletions aren't counted. None if there are no aligned bases or the reference base is
+    /// ambiguous.
+    pub fn mismatch_fraction(&self, reference_base: u8) -> Option<f64> {
+        let reference_count = match reference_base.to_ascii_uppercase() {
+            b'A' => self.a,
+            b'C' => self.c,
+            b'G' => self.g,
+            b'T' => self.t,
+            _ => return None,
+        };
+        let num_bases = self.a + self.c + self.g + self.t + self.n;
+        if num_bases == 0 {
+            return None;
+        }
+        Some((num_bases - reference_count) as f64 / num_bases as f64)
+    }
+
+    /// Counts of the four primary nucleotides in A/C/G/T order.
+    pub fn base_counts(&self) -> [u32; 4] {
+        [self.a, self.c, self.g, self.t]
+    }
+
+    fn add_base(&mut self, base: ReadBase) {
+        match base {
+            ReadBase::Base(b'A' | b'a') => self.a += 1,
+            ReadBase::Base(b'C' | b'c') => self.c += 1,
+            ReadBase::Base(b'G' | b'g') => self.g += 1,
+            ReadBase::Base(b'T' | b't') => self.t += 1,
+            ReadBase::Base(_) => self.n += 1,
+            ReadBase::Del => self.del += 1,
+            ReadBase::Skip => (),
+        }
+    }
+}
+
+/// A position at which enough reads have a mismatched base that it's likely a SNV.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MismatchHighlight {
+    #[serde(flatten)]
+    pub column: PileupColumn,
+    pub mismatch_fraction: f64,
+}
+
+/// How the read depth of a track is split by strand (e.g to show the strandedness of RNA-seq
+/// data).
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CoverageStrand {
+    /// Reads on both strands are counted together.
+    #[default]
+    Combined,
+    /// Reads are counted by the strand which they're aligned to.
+    Read,
+    /// Reads are counted by the strand of the first read of their pair (i.e the strand of the
+    /// second read is flipped), for stranded protocols where the first read is sense to the
+    /// transcript.
+    FirstOfPair,
+    /// Reads are counted by the strand of the second read of their pair, for stranded protocols
+    /// where the second read is sense to the transcript (e.g dUTP).
+    SecondOfPair,
+}
+
+impl CoverageStrand {
+    /// Get whether a read is counted towards the forward strand, or None if strands are combined.
+    ///
+    /// Unpaired reads are treated as the first read of a pair.
+    pub fn is_forward(&self, read: &AlignedRead) -> Option<bool> {
+        match self {
+            Self::Combined => None,
+            Self::Read => Some(!read.is_reverse),
+            Self::FirstOfPair => Some(read.is_reverse == read.is_second_of_pair),
+            Self::SecondOfPair => Some(read.is_reverse != read.is_second_of_pair),
+        }
+    }
+}
+
+/// Read depth of the forward and reverse strands at each position.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StrandedDepths {
+    pub forward: Vec<u32>,
+    pub reverse: Vec<u32>,
+}
+
+/// Read depth at each position in a genomic region (e.g for drawing a coverage histogram above a
+/// track's reads). Deletions are counted but skipped (e.g spliced) positions aren't.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Coverage {
+    pub region: GenomicRegion,
+    pub depths: Vec<u32>,
+    /// Depths split by strand, unless the track's coverage strands are combined.
+    pub stranded_depths: Option<StrandedDepths>,
+}
+
+impl From<&Pileup> for Coverage {
+    fn from(pileup: &Pileup) -> Self {
+        Self { region: pileup.region.clone(), depths: pileup.depths(), stranded_depths: None }
+    }
+}
+
+impl Coverage {
+    /// Compute the read depth of a set of reads, split by strand unless `strand` is Combined.
+    ///
+    /// # Arguments
+    ///
+    /// * `refseq` - Reference sequence which spans the entirety of `region`.
+    pub fn from_reads<'a, I: IntoIterator<Item = &'a AlignedRead>>(
+        region: &GenomicRegion,
+        reads: I,
+        refseq: &SequenceView,
+        strand: CoverageStrand,
+    ) -> Result<Self> {
+        let reads: Vec<&AlignedRead> = reads.into_iter().collect();
+        let mut coverage = Self::from(&Pileup::from_reads(region, reads.iter().copied(), refseq)?);
+        if strand != CoverageStrand::Combined {
+            let strand_depths = |forward: bool| -> Result<Vec<u32>> {
+                let reads =
+                    reads.iter().copied().filter(|read| strand.is_forward(read) == Some(forward));
+                Ok(Pileup::from_reads(region, reads, refseq)?.depths())
+            };
+            coverage.stranded_depths = Some(StrandedDepths {
+                forward: strand_depths(true)?,
+                reverse: strand_depths(false)?,
+            });
+        }
+        Ok(coverage)
+    }
+}
+
+/// Nucleotide counts for each position in a genomic region.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pileup {
+    pub region: GenomicRegion,
+    pub columns: Vec<PileupColumn>,
+}
+
+impl Pileup {
+    pub fn new(region: &GenomicRegion) -> Self {
+        let columns = (region.start()..region.end()).map(PileupColumn::new).collect();
+        Self { region: region.clone(), columns }
+    }
+
+    /// Compute a pileup from a set of reads.
+    ///
+    /// # Arguments
+    ///
+    /// * `refseq` - Reference sequence which spans the entirety of `region`.
+    pub fn from_reads<'a, I: IntoIterator<Item = &'a AlignedRead>>(
+        region: &GenomicRegion,
+        reads: I,
+        refseq: &SequenceView,
+    ) -> Result<Self> {
+        if !refseq.contains(region.start())
+            || (!region.is_empty() && !refseq.contains(region.end() - 1))
+        {
+            bail!("Reference sequence does not span pileup region {}", region);
+        }
+        let mut pileup = Self::new(region);
+        for read in reads {
+            pileup.add_read(read, refseq);
+        }
+        Ok(pileup)
+    }
+
+    /// Find the positions at which the fraction of reads with a mismatched base exceeds a
+    /// threshold.
+    ///
+    /// Positions outside of `refseq` are skipped.
+    pub fn find_mismatch_highlights(
+        &self,
+        refseq: &SequenceView,
+        threshold: f64,
+    ) -> Vec<MismatchHighlight> {
+        self.columns
+            .iter()
+            .filter(|column| refseq.contains(column.position))
+            .filter_map(|column| {
+                let mismatch_fraction = column.mismatch_fraction(refseq[column.position])?;
+                (mismatch_fraction > threshold)
+                    .then(|| MismatchHighlight { column: column.clone(), mismatch_fraction })
+            })
+            .collect()
+    }
+
+    /// Read depth at each position.
+    pub fn depths(&self) -> Vec<u32> {
+        self.columns.iter().map(PileupColumn::depth).collect()
+    }
+
+    /// Add the bases from a single read to the pileup.
+    fn add_read(&mut self, read: &AlignedRead, refseq: &SequenceView) {
+        if read.region.seq_name != self.region.seq_name
+            || !read.region.interval.overlaps(&self.region.interval)
+        {
+            return;
+        }
+        let read_start = read.region.start();
+        let mut bases: Vec<Option<ReadBase>> = vec![None; read.region.len() as usize];
+        for diff in read.diffs.iter() {
+            match diff {
+                SequenceDiff::Mismatch { interval, sequence } => {
+                    if let Some(base) = sequence.bytes().next() {
+                        set_read_bases(
+                            &mut bases,
+                            read_start,
+                            interval.start,
+                            interval.end,
+                            ReadBase::Base(base),
+                        );
+                    }
+                }
+                SequenceDiff::Del { interval } => {
+                    set_read_bases(
+                        &mut bases,
+                        read_start,
+                        interval.start,
+                        interval.end,
+                        ReadBase::Del,
+                    );
+                }
+                SequenceDiff::SoftClip { interval, .. } | SequenceDiff::RefSkip { interval } => {
+                    set_read_bases(
+                        &mut bases,
+                        read_start,
+                        interval.start,
+                        interval.end,
+                        ReadBase::Skip,
+                    );
+                }
+                SequenceDiff::Ins { interval, .. } => {
+                    if let Some(column) = self.get_column_mut(interval.start) {
+                        column.ins += 1;
+                    }
+                }
+            }
+        }
+        let start = read_start.max(self.region.start());
+        let end = read.region.end().min(self.region.end());
+        for pos in start..end {
+            let base = bases[(pos - read_start) as usize];
+            let base = match base {
+                Some(base) => base,
+                None if refseq.contains(pos) => ReadBase::Base(refseq[pos]),
+                None => continue,
+            };
+            if let Some(column) = self.get_column_mut(pos) {
+                column.add_base(base);
+            }
+        }
+    }
+
+    fn get_column_mut(&mut self, pos: u64) -> Option<&mut PileupColumn> {
+        if pos < self.region.start() || pos >= self.region.end() {
+            return None;
+        }
+        self.columns.get_mut((pos - self.region.start()) as usize)
+    }
+}
+
+/// Overwrite the bases of a read between `start` and `end` (reference coordinates).
+fn set_read_bases(
+    bases: &mut [Option<ReadBase>],
+    read_start: u64,
+    start: u64,
+    end: u64,
+    base: ReadBase,
+) {
+    for pos in start.max(read_start)..end {
+        if let Some(item) = bases.get_mut((pos - read_start) as usize) {
+            *item = Some(base);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::test_util::aligned_reads::gen_read;
+
+    #[test]
+    pub fn test_stranded_coverage() {
+        let refseq = SequenceView::new(b"AGCTAGCT".to_vec(), 100);
+        let mut reads = vec![
+            gen_read("0", 100, 104, Vec::new()),
+            gen_read("1", 102, 106, Vec::new()),
+            gen_read("2", 104, 108, Vec::new()),
+        ];
+        reads[1].is_reverse = true;
+        reads[2].is_reverse = true;
+        reads[2].is_second_of_pair = true;
+        let region = GenomicRegion::new("X", 100, 108).unwrap();
+        let coverage =
+            Coverage::from_reads(&region, &reads, &refseq, CoverageStrand::Combined).unwrap();
+        assert_eq!(coverage.depths, vec![1, 1, 2, 2, 2, 2, 1, 1]);
+        assert_eq!(coverage.stranded_depths, None);
+        let coverage =
+            Coverage::from_reads(&region, &reads, &refseq, CoverageStrand::Read).unwrap();
+        let stranded_depths = coverage.stranded_depths.unwrap();
+        assert_eq!(stranded_depths.forward, vec![1, 1, 1, 1, 0, 0, 0, 0]);
+        assert_eq!(stranded_depths.reverse, vec![0, 0, 1, 1, 2, 2, 1, 1]);
+        // The second read of a pair is counted on the opposite strand
+        let coverage =
+            Coverage::from_reads(&region, &reads, &refseq, CoverageStrand::FirstOfPair).unwrap();
+        let stranded_depths = coverage.stranded_depths.unwrap();
+        assert_eq!(stranded_depths.forward, vec![1, 1, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(stranded_depths.reverse, vec![0, 0, 1, 1, 1, 1, 0, 0]);
+        let coverage =
+            Coverage::from_reads(&region, &reads, &refseq, CoverageStrand::SecondOfPair).unwrap();
+        assert_eq!(coverage.stranded_depths.unwrap().forward, vec![0, 0, 1, 1, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    pub fn test_pileup_counts_reference_and_mismatch_bases() {
+        let refseq = SequenceView::new(b"AGCTAGCT".to_vec(), 100);
+        let reads = vec![
+            gen_read("0", 100, 104, Vec::new()),
+            gen_read(
+                "1",
+                102,
+                106,
+                vec![SequenceDiff::Mismatch {
+                    interval: (103, 104).try_into().unwrap(),
+                    sequence: "G".to_owned(),
+                }],
+            ),
+        ];
+        let region = GenomicRegion::new("X", 102, 105).unwrap();
+        let pileup = Pileup::from_reads(&region, &reads, &refseq).unwrap();
+        assert_eq!(pileup.columns.len(), 3);
+        assert_eq!(pileup.columns[0].c, 2);
+        assert_eq!(pileup.columns[1].t, 1);
+        assert_eq!(pileup.columns[1].g, 1);
+        assert_eq!(pileup.columns[2].a, 1);
+        assert_eq!(pileup.columns[2].depth(), 1);
+    }
+
+    #[test]
+    pub fn test_find_mismatch_highlights() {
+        let refseq = SequenceView::new(b"ACGN".to_vec(), 100);
+        let region = GenomicRegion::new("X", 100, 105).unwrap();
+        let mut pileup = Pileup::new(&region);
+        // Reference only, 50% mismatches, 10% mismatches, ambiguous reference, outside refseq
+        pileup.columns[0].a = 10;
+        pileup.columns[1].c = 5;
+        pileup.columns[1].t = 5;
+        pileup.columns[2].g = 9;
+        pileup.columns[2].a = 1;
+        pileup.columns[2].del = 10;
+        pileup.columns[3].a = 10;
+        pileup.columns[4].a = 10;
+        let highlights = pileup.find_mismatch_highlights(&refseq, 0.2);
+        assert_eq!(highlights.len(), 1);
+        assert_eq!(highlights[0].column.position, 101);
+        assert_eq!(highlights[0].mismatch_fraction, 0.5);
+        // Deletions aren't mismatches
+        assert_eq!(pileup.columns[2].mismatch_fraction(b'g'), Some(0.1));
+        assert_eq!(pileup.find_mismatch_highlights(&refseq, 0.05).len(), 2);
+        assert_eq!(pileup.find_mismatch_highlights(&refseq, 0.5).len(), 0);
+    }
+
+    #[test]
+    pub fn test_pileup_counts_indels_and_ignores_softclips() {
+        let refseq = SequenceView::new(b"AGCTAGCT".to_vec(), 100);
+        let reads = vec![gen_read(
+            "0",
+            100,
+            106,
+            vec![
+                SequenceDiff::SoftClip {
+                    interval: (100, 101).try_into().unwrap(),
+                    sequence: "T".to_owned(),
+                },
+                SequenceDiff::Ins {
+                    interval: (102, 102).try_into().unwrap(),
+                    sequence: "AA".to_owned(),
+                    full_length: None,
+                },
+                SequenceDiff::Del { interval: (103, 105).try_into().unwrap() },
+            ],
+        )];
+        let region = GenomicRegion::new("X", 100, 106).unwrap();
+        let pileup = Pileup::from_reads(&region, &reads, &refseq).unwrap();
+        let depths: Vec<u32> = pileup.columns.iter().map(|col| col.depth()).collect();
+        assert_eq!(depths, vec![0, 1, 1, 1, 1, 1]);
+        assert_eq!(pileup.columns[2].ins, 1);
+        assert_eq!(pileup.columns[3].del, 1);
+        assert_eq!(pileup.columns[4].del, 1);
+    }
+
+    #[test]
+    pub fn test_pileup_errors_if_reference_does_not_span_region() {
+        let refseq = SequenceView::new(b"AGCT".to_vec(), 100);
+        let region = GenomicRegion::new("X", 98, 102).unwrap();
+        let reads: Vec<AlignedRead> = Vec::new();
+        assert!(Pileup::from_reads(&region, &reads, &refseq).is_err());
+    }
+}
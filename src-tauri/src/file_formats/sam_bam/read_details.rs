@@ -0,0 +1,157 @@
+//! Full details of a single read (e.g for a read detail popup), which are too large to include
+//! with every read in a stack.
+use std::fmt::Display;
+
+use anyhow::{Context, Result};
+use rust_htslib::bam::record::{Aux, AuxArray, AuxArrayElement, Record};
+use serde::Serialize;
+
+use crate::file_formats::sam_bam::aligned_read::AlignedRead;
+
+/// Names of the bits of the SAM FLAG field, in bit order.
+const FLAG_NAMES: [&str; 12] = [
+    "PAIRED",
+    "PROPER_PAIR",
+    "UNMAP",
+    "MUNMAP",
+    "REVERSE",
+    "MREVERSE",
+    "READ1",
+    "READ2",
+    "SECONDARY",
+    "QCFAIL",
+    "DUP",
+    "SUPPLEMENTARY",
+];
+
+/// An optional field of a read, formatted as in a SAM file (e.g tag=NM, type=i, value=3).
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SamTag {
+    pub tag: String,
+    pub type_code: char,
+    /// Arrays are formatted as their element type followed by their values (e.g "c,1,2,3").
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadDetails {
+    /// Fields of the read which are also included in stacks.
+    pub read: AlignedRead,
+    pub flags: u16,
+    /// Names of the flag bits which are set (e.g PAIRED, READ1), as used by samtools.
+    pub flag_names: Vec<&'static str>,
+    pub sequence: String,
+    /// Phred scaled base qualities, None if the read doesn't have qualities.
+    pub base_qualities: Option<Vec<u8>>,
+    pub is_mate_reverse: bool,
+    pub is_mate_unmapped: bool,
+    pub tags: Vec<SamTag>,
+}
+
+fn format_array<T: AuxArrayElement + Display>(subtype: char, array: AuxArray<T>) -> String {
+    std::iter::once(subtype.to_string())
+        .chain(array.iter().map(|value| value.to_string()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Format the value of an optional field as in a SAM file, returning its type code and value.
+fn format_aux(aux: Aux) -> (char, String) {
+    match aux {
+        Aux::Char(value) => ('A', (value as char).to_string()),
+        Aux::I8(value) => ('i', value.to_string()),
+        Aux::U8(value) => ('i', value.to_string()),
+        Aux::I16(value) => ('i', value.to_string()),
+        Aux::U16(value) => ('i', value.to_string()),
+        Aux::I32(value) => ('i', value.to_string()),
+        Aux::U32(value) => ('i', value.to_string()),
+        Aux::Float(value) => ('f', value.to_string()),
+        Aux::Double(value) => ('d', value.to_string()),
+        Aux::String(value) => ('Z', value.to_owned()),
+        Aux::HexByteArray(value) => ('H', value.to_owned()),
+        Aux::ArrayI8(array) => ('B', format_array('c', array)),
+        Aux::ArrayU8(array) => ('B', format_array('C', array)),
+        Aux::ArrayI16(array) => ('B', format_array('s', array)),
+        Aux::ArrayU16(array) => ('B', format_array('S', array)),
+        Aux::ArrayI32(array) => ('B', format_array('i', array)),
+        Aux::ArrayU32(array) => ('B', format_array('I', array)),
+        Aux::ArrayFloat(array) => ('B', format_array('f', array)),
+    }
+}
+
+/// Get the optional fields of a read in the order which they're stored.
+fn get_tags(record: &Record) -> Result<Vec<SamTag>> {
+    let mut tags = Vec::new();
+    for result in record.aux_iter() {
+        let (tag, aux) = result.context("Failed to parse optional fields")?;
+        let (type_code, value) = format_aux(aux);
+        tags.push(SamTag { tag: String::from_utf8_lossy(tag).into(), type_code, value });
+    }
+    Ok(tags)
+}
+
+impl ReadDetails {
+    /// Collect the details of a read from its record.
+    ///
+    /// # Arguments
+    ///
+    /// * `read` - The read which was initialized from the record.
+    pub fn from_record(record: &Record, read: AlignedRead) -> Result<Self> {
+        let flags = record.flags();
+        let flag_names = FLAG_NAMES
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| flags & (1 << bit) != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        let qual = record.qual();
+        // Missing qualities are stored as 0xff
+        let base_qualities = match qual.first() {
+            Some(0xff) | None => None,
+            Some(_) => Some(qual.to_vec()),
+        };
+        Ok(Self {
+            read,
+            flags,
+            flag_names,
+            sequence: String::from_utf8_lossy(&record.seq().as_bytes()).into(),
+            base_qualities,
+            is_mate_reverse: record.is_mate_reverse(),
+            is_mate_unmapped: record.is_mate_unmapped(),
+            tags: get_tags(record)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::test_util::htslib_records::RecordBuilder;
+
+    #[test]
+    fn test_get_tags() {
+        let ml: &[u8] = &[1, 255];
+        let record = RecordBuilder::default()
+            .aux(b"NM", Aux::I32(3))
+            .aux(b"RG", Aux::String("sample1"))
+            .aux(b"XA", Aux::Char(b'T'))
+            .aux(b"ML", Aux::ArrayU8(ml.into()))
+            .record;
+        let tags: Vec<(String, char, String)> = get_tags(&record)
+            .unwrap()
+            .into_iter()
+            .map(|tag| (tag.tag, tag.type_code, tag.value))
+            .collect();
+        let expected = vec![
+            ("NM".to_owned(), 'i', "3".to_owned()),
+            ("RG".to_owned(), 'Z', "sample1".to_owned()),
+            ("XA".to_owned(), 'A', "T".to_owned()),
+            ("ML".to_owned(), 'B', "C,1,255".to_owned()),
+        ];
+        assert_eq!(tags, expected);
+    }
+}
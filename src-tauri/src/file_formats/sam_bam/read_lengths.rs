@@ -0,0 +1,173 @@
+//! Read length distributions (e.g to check the length profile of a long-read library).
+use anyhow::Result;
+use rust_htslib::bam::{Read, Record};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::sam_bam::reader::ReadFilter;
+
+/// Maximum number of reads which are read to compute a read length distribution. Distributions
+/// of regions (or files) with more reads than this are estimated from the first reads.
+pub const MAX_READ_LENGTH_SAMPLES: usize = 100_000;
+
+/// Which reads the read length distribution of a track is computed from.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReadLengthScope {
+    /// Reads which overlap the focused region of a split.
+    FocusedRegion,
+    /// Reads at the start of the file.
+    File,
+}
+
+/// Number of reads with lengths in [start, end).
+#[serde_as]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadLengthBin {
+    #[serde_as(as = "DisplayFromStr")]
+    pub start: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub end: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub count: u64,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadLengthHistogram {
+    /// Region which the reads overlap (None if the reads were taken from the start of the file).
+    pub region: Option<GenomicRegion>,
+    #[serde_as(as = "DisplayFromStr")]
+    pub num_reads: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub min_length: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub max_length: u64,
+    pub mean_length: f64,
+    /// Length such that reads at least this long contain half of the sequenced bases.
+    #[serde_as(as = "DisplayFromStr")]
+    pub n50: u64,
+    /// Equal width bins spanning min_length to max_length.
+    pub bins: Vec<ReadLengthBin>,
+    /// True if only the first MAX_READ_LENGTH_SAMPLES reads were used.
+    pub is_estimate: bool,
+}
+
+/// Compute the N50 of a set of read lengths (zero if there are no reads).
+pub fn compute_n50(lengths: &[u64]) -> u64 {
+    let mut lengths = lengths.to_vec();
+    lengths.sort_unstable_by(|a, b| b.cmp(a));
+    let total: u64 = lengths.iter().sum();
+    let mut cumulative = 0;
+    for length in lengths {
+        cumulative += length;
+        if cumulative * 2 >= total {
+            return length;
+        }
+    }
+    0
+}
+
+impl ReadLengthHistogram {
+    pub fn new(
+        lengths: &[u64],
+        num_bins: usize,
+        region: Option<GenomicRegion>,
+        is_estimate: bool,
+    ) -> Self {
+        let min_length = lengths.iter().copied().min().unwrap_or(0);
+        let max_length = lengths.iter().copied().max().unwrap_or(0);
+        let mut bins = Vec::new();
+        if !lengths.is_empty() {
+            let num_bins = num_bins.max(1) as u64;
+            let span = max_length - min_length + 1;
+            let bin_width = ((span + num_bins - 1) / num_bins).max(1);
+            bins = (0..(span + bin_width - 1) / bin_width)
+                .map(|i| {
+                    let start = min_length + i * bin_width;
+                    ReadLengthBin { start, end: start + bin_width, count: 0 }
+                })
+                .collect();
+            for length in lengths {
+                bins[((length - min_length) / bin_width) as usize].count += 1;
+            }
+        }
+        let mean_length = if lengths.is_empty() {
+            0.0
+        } else {
+            lengths.iter().sum::<u64>() as f64 / lengths.len() as f64
+        };
+        Self {
+            region,
+            num_reads: lengths.len() as u64,
+            min_length,
+            max_length,
+            mean_length,
+            n50: compute_n50(lengths),
+            bins,
+            is_estimate,
+        }
+    }
+}
+
+/// Read the lengths of the reads from the current position of a reader.
+///
+/// Secondary and supplementary alignments are skipped so that each read is only counted once.
+/// Returns the lengths and whether reading stopped early at `max_reads`.
+pub fn read_lengths<R: Read>(
+    reader: &mut R,
+    filter: &ReadFilter,
+    max_reads: usize,
+) -> Result<(Vec<u64>, bool)> {
+    let mut lengths = Vec::new();
+    let mut record = Record::new();
+    while let Some(result) = reader.read(&mut record) {
+        result?;
+        if record.is_secondary() || record.is_supplementary() || !filter.includes(&record) {
+            continue;
+        }
+        if lengths.len() == max_reads {
+            return Ok((lengths, true));
+        }
+        lengths.push(record.seq_len() as u64);
+    }
+    Ok((lengths, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_compute_n50() {
+        assert_eq!(compute_n50(&[2, 3, 4, 5, 6, 7, 8, 9, 10]), 8);
+        assert_eq!(compute_n50(&[100]), 100);
+        assert_eq!(compute_n50(&[]), 0);
+    }
+
+    #[test]
+    fn test_read_length_histogram() {
+        let histogram = ReadLengthHistogram::new(&[100, 150, 199, 200, 1000], 9, None, false);
+        assert_eq!(histogram.num_reads, 5);
+        assert_eq!((histogram.min_length, histogram.max_length), (100, 1000));
+        assert_eq!(histogram.mean_length, 329.8);
+        assert_eq!(histogram.n50, 1000);
+        // 901 lengths don't divide evenly into 9 bins, so the bins are rounded up to 101bp
+        assert_eq!(histogram.bins.len(), 9);
+        assert_eq!(histogram.bins[0], ReadLengthBin { start: 100, end: 201, count: 4 });
+        assert_eq!(histogram.bins.iter().map(|bin| bin.count).sum::<u64>(), 5);
+        assert_eq!(histogram.bins[8], ReadLengthBin { start: 908, end: 1009, count: 1 });
+    }
+
+    #[test]
+    fn test_empty_read_length_histogram() {
+        let histogram = ReadLengthHistogram::new(&[], 10, None, false);
+        assert_eq!(histogram.num_reads, 0);
+        assert!(histogram.bins.is_empty());
+    }
+}
@@ -1,53 +1,519 @@
+use std::collections::BTreeSet;
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use parking_lot::Mutex;
 use rayon::prelude::*;
 use rust_htslib::bam;
+use rust_htslib::bam::record::{Aux, Record};
 use rust_htslib::bam::Read;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
 
-use crate::alignments::alignment_reader::AlignmentReader;
+use crate::alignments::alignment_reader::{AlignmentReader, SkippedRead, SkippedReads};
+use crate::alignments::cancellation::CancellationToken;
 use crate::bio_util::genomic_coordinates::GenomicRegion;
 use crate::bio_util::sequence::SequenceView;
-use crate::file_formats::sam_bam::aligned_read::AlignedRead;
+use crate::file_formats::nanopore::summary::aux_to_u32;
+use crate::file_formats::remote::{get_htslib_url, is_remote};
+use crate::file_formats::sam_bam::aligned_read::{encode_base_qualities, AlignedRead};
+use crate::file_formats::sam_bam::coverage_pyramid::{sample_binned_coverage, BinnedCoverage};
+use crate::file_formats::sam_bam::decompression_pool::{
+    get_decompression_pool, DecompressionPool, PooledReader,
+};
+use crate::file_formats::sam_bam::diff::{DiffAlignments, SequenceDiff};
+use crate::file_formats::sam_bam::filter_expression::FilterExpression;
+use crate::file_formats::sam_bam::index::open_indexed_bam;
+use crate::file_formats::sam_bam::read_details::ReadDetails;
+use crate::file_formats::sam_bam::read_lengths::read_lengths;
 use crate::file_formats::sam_bam::tid::TidMap;
 
+/// Number of records which are read between checks of whether a read has been cancelled.
+const CANCELLATION_CHECK_INTERVAL: usize = 1000;
+
+/// Base qualities are only read for regions up to this size, since they're roughly as large as the
+/// rest of a read's payload combined.
+pub const MAX_BASE_QUALITY_WINDOW: u64 = 30_000;
+
+/// Maximum number of unplaced records which are searched for an unmapped mate. Unplaced reads can
+/// make up a large part of a file, and the reader is locked while they're searched.
+const MAX_UNPLACED_MATE_SEARCH: usize = 100_000;
+
+/// A read which isn't aligned to the reference.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnmappedRead {
+    pub qname: String,
+    pub sequence: String,
+    pub is_reverse: bool,
+}
+
+impl UnmappedRead {
+    fn from_record(record: &Record) -> Self {
+        Self {
+            qname: String::from_utf8_lossy(record.qname()).into(),
+            sequence: String::from_utf8_lossy(&record.seq().as_bytes()).into(),
+            is_reverse: record.is_reverse(),
+        }
+    }
+}
+
+/// Criteria which reads must meet to be read from a SAM/BAM file.
+#[serde_as]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadFilter {
+    /// Reads with a lower mapping quality are excluded.
+    #[serde(default)]
+    pub min_mapq: u8,
+
+    /// Whether reads which are flagged as PCR or optical duplicates are excluded.
+    #[serde(default)]
+    pub hide_duplicates: bool,
+
+    /// Whether secondary alignments (flag 0x100) are excluded.
+    #[serde(default)]
+    pub hide_secondary: bool,
+
+    /// Whether supplementary alignments (flag 0x800) are excluded.
+    #[serde(default)]
+    pub hide_supplementary: bool,
+
+    /// Reads for which the expression is false are excluded.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub expression: Option<FilterExpression>,
+
+    /// Only reads from these read groups (RG tag) are included, if set. Reads without a read group
+    /// are then excluded.
+    #[serde(default)]
+    pub read_groups: Option<Vec<String>>,
+
+    /// Only reads with one of these values of a tag are included, if set.
+    #[serde(default)]
+    pub tag_values: Option<TagValues>,
+}
+
+impl ReadFilter {
+    pub fn includes(&self, record: &Record) -> bool {
+        record.mapq() >= self.min_mapq
+            && !(self.hide_duplicates && record.is_duplicate())
+            && !(self.hide_secondary && record.is_secondary())
+            && !(self.hide_supplementary && record.is_supplementary())
+            && self.expression.as_ref().map_or(true, |expression| expression.matches(record))
+            && self.read_groups.as_ref().map_or(true, |read_groups| match record.aux(b"RG") {
+                Ok(Aux::String(read_group)) => read_groups.iter().any(|id| id == read_group),
+                _ => false,
+            })
+            && self.tag_values.as_ref().map_or(true, |tag_values| tag_values.includes(record))
+    }
+}
+
+/// Values of a tag, e.g the cell barcodes (CB tag) of a cluster of cells in single cell data.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagValues {
+    pub tag: String,
+    pub values: BTreeSet<String>,
+}
+
+impl TagValues {
+    pub fn validate(&self) -> Result<()> {
+        let is_tag = self.tag.len() == 2
+            && self.tag.starts_with(|c: char| c.is_ascii_alphabetic())
+            && self.tag.chars().all(|c| c.is_ascii_alphanumeric());
+        if !is_tag {
+            bail!("Invalid tag: {} (expected two characters e.g CB)", self.tag);
+        }
+        if self.values.is_empty() {
+            bail!("No values were given for the {} tag", self.tag);
+        }
+        Ok(())
+    }
+
+    /// Whether the record's tag has one of the values. Integer tags are compared as strings.
+    pub fn includes(&self, record: &Record) -> bool {
+        match record.aux(self.tag.as_bytes()) {
+            Ok(Aux::String(value)) => self.values.contains(value),
+            Ok(Aux::Char(value)) => self.values.contains(&char::from(value).to_string()),
+            Ok(aux) => {
+                aux_to_u32(aux).map_or(false, |value| self.values.contains(&value.to_string()))
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// A named group of values of a tag, which are shown together in their own track.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagGroup {
+    pub name: String,
+    pub values: BTreeSet<String>,
+}
+
+/// Open a SAM/BAM file for sequential reading. Remote files (e.g `s3://` URLs) are supported.
+pub fn open_bam<P: Into<PathBuf>>(bam_path: P) -> Result<bam::Reader> {
+    let bam_path: PathBuf = bam_path.into();
+    let reader = if is_remote(&bam_path) {
+        bam::Reader::from_url(&get_htslib_url(&bam_path)?)
+    } else {
+        bam::Reader::from_path(&bam_path)
+    };
+    reader.with_context(|| format!("Failed to open {}", bam_path.to_string_lossy()))
+}
+
+fn find_unmapped_read<
+    I: Iterator<Item = std::result::Result<Record, rust_htslib::errors::Error>>,
+>(
+    records: I,
+    qname: &str,
+    max_records: usize,
+) -> Result<Option<UnmappedRead>> {
+    for record in records.take(max_records) {
+        let record = record?;
+        if record.is_unmapped() && record.qname() == qname.as_bytes() {
+            return Ok(Some(UnmappedRead::from_record(&record)));
+        }
+    }
+    Ok(None)
+}
+
+/// Get the span of the reference which a record was placed on. Unmapped records (which are placed
+/// next to their mate) span a single base.
+fn get_record_span(record: &Record) -> (u64, u64) {
+    let start = record.pos().max(0) as u64;
+    let end = record.cigar().end_pos().max(0) as u64;
+    (start, end.max(start + 1))
+}
+
 #[derive(Debug)]
 pub struct BamReader {
     pub bam_path: PathBuf,
     tid_map: TidMap,
-    reader: Mutex<bam::IndexedReader>,
+    reader: Mutex<PooledReader>,
+    filter: ReadFilter,
+    base_qualities: bool,
 }
 
 impl BamReader {
+    /// Open a BAM file, decompressing it with the shared decompression pool if one is configured
+    /// (see configure_decompression_threads).
     pub fn new<P: Into<PathBuf>>(bam_path: P) -> Result<BamReader> {
+        Self::with_pool(bam_path, get_decompression_pool())
+    }
+
+    /// Open a BAM file which is decompressed using a thread pool. Wide regions of high depth data
+    /// are otherwise bottlenecked on decompression. None decompresses on the reading thread.
+    pub fn with_pool<P: Into<PathBuf>>(
+        bam_path: P,
+        pool: Option<Arc<DecompressionPool>>,
+    ) -> Result<BamReader> {
         let pathbuf: PathBuf = bam_path.into();
-        let reader = Mutex::new(bam::IndexedReader::from_path(&pathbuf)?);
+        // htslib finds the index itself, but is given the index explicitly so that the index
+        // which is used always matches the one found by find_bam_index. The indexes of remote
+        // files are always found by htslib.
+        let reader = if is_remote(&pathbuf) {
+            bam::IndexedReader::from_url(&get_htslib_url(&pathbuf)?).with_context(|| {
+                format!("Failed to open {} (an index is required)", pathbuf.to_string_lossy())
+            })?
+        } else {
+            open_indexed_bam(&pathbuf)?
+        };
+        let reader = PooledReader::new(reader, pool).with_context(|| {
+            format!("Failed to start multi-threaded decompression of {}", pathbuf.to_string_lossy())
+        })?;
+        let reader = Mutex::new(reader);
         let tid_map = TidMap::new(&pathbuf)?;
-        Ok(BamReader { bam_path: pathbuf, reader, tid_map })
+        Ok(BamReader {
+            bam_path: pathbuf,
+            reader,
+            tid_map,
+            filter: ReadFilter::default(),
+            base_qualities: false,
+        })
     }
-}
 
-impl AlignmentReader for BamReader {
-    type Item = AlignedRead;
+    pub fn filter(&self) -> ReadFilter {
+        self.filter.clone()
+    }
 
-    fn read(&mut self, region: &GenomicRegion, refseq: &SequenceView) -> Result<Vec<Self::Item>> {
-        if self.tid_map.get_tid(&region.seq_name).is_none() {
-            bail!("Invalid contig/chromosome name: {}", region.seq_name);
+    /// Set which reads are excluded by subsequent reads.
+    pub fn set_filter(&mut self, filter: ReadFilter) {
+        self.filter = filter;
+    }
+
+    pub fn base_qualities(&self) -> bool {
+        self.base_qualities
+    }
+
+    /// Set whether subsequent reads include base qualities (for regions up to
+    /// MAX_BASE_QUALITY_WINDOW).
+    pub fn set_base_qualities(&mut self, base_qualities: bool) {
+        self.base_qualities = base_qualities;
+    }
+
+    /// Fetch the unmapped mate of a read.
+    ///
+    /// Per the SAM spec, unmapped mates should be placed at the position of their mapped mate. If
+    /// the mate isn't found there then the first MAX_UNPLACED_MATE_SEARCH unplaced reads at the
+    /// end of the file are searched, and the mate is treated as missing if it isn't among them.
+    ///
+    /// # Arguments
+    ///
+    /// * `qname` - Name of the read.
+    /// * `region` - Genomic region of the mapped mate.
+    pub fn fetch_unmapped_mate(
+        &self,
+        qname: &str,
+        region: &GenomicRegion,
+    ) -> Result<Option<UnmappedRead>> {
+        let seq_name = self.tid_map.resolve_seq_name(&region.seq_name).unwrap_or(&region.seq_name);
+        let mut reader = self.reader.lock();
+        reader.fetch((seq_name, region.start(), region.start() + 1))?;
+        if let Some(mate) = find_unmapped_read(reader.records(), qname, usize::MAX)? {
+            return Ok(Some(mate));
         }
+        reader.fetch(bam::FetchDefinition::Unmapped)?;
+        find_unmapped_read(reader.records(), qname, MAX_UNPLACED_MATE_SEARCH)
+    }
+
+    /// Fetch the record of a read which was previously read from the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `refseq` - Reference sequence which spans the start of the read.
+    fn fetch_record(&self, read: &AlignedRead, refseq: &SequenceView) -> Result<Record> {
+        let seq_name = match self.tid_map.resolve_seq_name(&read.region.seq_name) {
+            Some(seq_name) => seq_name,
+            None => bail!("Invalid contig/chromosome name: {}", read.region.seq_name),
+        };
         let mut reader = self.reader.lock();
-        reader.fetch((region.seq_name.as_str(), region.start(), region.end()))?;
-        let alignments = reader
-            .records()
-            .collect::<std::result::Result<Vec<_>, _>>()?
+        reader.fetch((seq_name, read.region.start(), read.region.start() + 1))?;
+        for record in reader.records() {
+            let record = record?;
+            // Reads are identified by their id rather than their name since each alignment of a
+            // pair/chain has the same name
+            if record.qname() == read.qname.as_bytes()
+                && AlignedRead::from_record(&record, refseq, &self.tid_map)?.id == read.id
+            {
+                return Ok(record);
+            }
+        }
+        bail!("Failed to find read {} in {}", read.id, self.bam_path.to_string_lossy())
+    }
+
+    /// Fetch the aligned region of a read's mate.
+    ///
+    /// Falls back to the mate position of the read if the mate's record isn't found (e.g if the
+    /// file is truncated). None if the read is unpaired or its mate is unmapped.
+    pub fn fetch_mate_region(&self, read: &AlignedRead) -> Result<Option<GenomicRegion>> {
+        let mate_pos = match &read.mate_pos {
+            Some(mate_pos) => mate_pos,
+            None => return Ok(None),
+        };
+        let seq_name = match self.tid_map.resolve_seq_name(&mate_pos.seq_name) {
+            Some(seq_name) => seq_name,
+            None => bail!("Invalid contig/chromosome name: {}", mate_pos.seq_name),
+        };
+        let mut reader = self.reader.lock();
+        reader.fetch((seq_name, mate_pos.start(), mate_pos.start() + 1))?;
+        for record in reader.records() {
+            let record = record?;
+            let is_mate = record.qname() == read.qname.as_bytes()
+                && record.pos() as u64 == mate_pos.start()
+                && record.is_last_in_template() != read.is_second_of_pair
+                && !(record.is_unmapped() || record.is_secondary() || record.is_supplementary());
+            if is_mate {
+                let end = u64::try_from(record.cigar().end_pos())?;
+                return Ok(Some(GenomicRegion::new(&mate_pos.seq_name, mate_pos.start(), end)?));
+            }
+        }
+        Ok(Some(mate_pos.clone()))
+    }
+
+    /// Fetch the full sequence of an insertion in a read (e.g one which was truncated to
+    /// MAX_INSERTION_SEQUENCE_LENGTH in the read's diffs).
+    ///
+    /// # Arguments
+    ///
+    /// * `read` - Read which was previously read from the file.
+    /// * `position` - Start of the insertion's interval.
+    /// * `refseq` - Reference sequence which spans the read.
+    pub fn fetch_insertion_sequence(
+        &self,
+        read: &AlignedRead,
+        position: u64,
+        refseq: &SequenceView,
+    ) -> Result<String> {
+        let record = self.fetch_record(read, refseq)?;
+        for diff in DiffAlignments::new(&record, refseq).with_full_insertions() {
+            if let SequenceDiff::Ins { interval, sequence, .. } = diff? {
+                if interval.start == position {
+                    return Ok(sequence);
+                }
+            }
+        }
+        bail!("Read {} has no insertion at {}", read.id, position)
+    }
+
+    /// Fetch the full details of a read (e.g all of its tags) which was previously read from the
+    /// file.
+    ///
+    /// # Arguments
+    ///
+    /// * `refseq` - Reference sequence which spans the start of the read.
+    pub fn fetch_read_details(
+        &self,
+        read: &AlignedRead,
+        refseq: &SequenceView,
+    ) -> Result<ReadDetails> {
+        let record = self.fetch_record(read, refseq)?;
+        ReadDetails::from_record(&record, read.clone())
+    }
+
+    /// Estimate the binned read depth across a region which is too large to read every alignment
+    /// in.
+    pub fn sample_coverage(
+        &self,
+        region: &GenomicRegion,
+        num_bins: usize,
+    ) -> Result<BinnedCoverage> {
+        let seq_name = match self.tid_map.resolve_seq_name(&region.seq_name) {
+            Some(seq_name) => seq_name,
+            None => bail!("Invalid contig/chromosome name: {}", region.seq_name),
+        };
+        sample_binned_coverage(&mut self.reader.lock(), seq_name, region, num_bins)
+    }
+
+    /// Read the lengths of the reads which overlap a region, or of the reads at the start of the
+    /// file if no region is given. At most `max_reads` reads are read.
+    ///
+    /// Returns the lengths and whether there were more reads than `max_reads`.
+    pub fn sample_read_lengths(
+        &self,
+        region: Option<&GenomicRegion>,
+        max_reads: usize,
+    ) -> Result<(Vec<u64>, bool)> {
+        let mut reader = self.reader.lock();
+        match region {
+            Some(region) => {
+                let seq_name = match self.tid_map.resolve_seq_name(&region.seq_name) {
+                    Some(seq_name) => seq_name,
+                    None => bail!("Invalid contig/chromosome name: {}", region.seq_name),
+                };
+                reader.fetch((seq_name, region.start(), region.end()))?
+            }
+            None => reader.fetch(bam::FetchDefinition::All)?,
+        };
+        read_lengths(&mut **reader, &self.filter, max_reads)
+    }
+
+    /// Convert the records which were fetched from a region into alignments.
+    ///
+    /// Unmapped records, records excluded by the read filter and records which can't be converted
+    /// (e.g with an invalid tid or position) are skipped and counted rather than failing the read.
+    ///
+    /// # Arguments
+    ///
+    /// * `seq_name` - Name which the bam uses for the region's contig.
+    fn convert_records(
+        &self,
+        mut records: Vec<Record>,
+        region: &GenomicRegion,
+        seq_name: &str,
+        refseq: &SequenceView,
+    ) -> (Vec<AlignedRead>, SkippedReads) {
+        // Unmapped reads can be placed next to their mapped mate but can't be displayed.
+        let mut skipped_reads = SkippedReads::default();
+        records.retain(|record| {
+            let (start, end) = get_record_span(record);
+            if record.is_unmapped() {
+                skipped_reads.push_unmapped(start, end);
+                false
+            } else if !self.filter.includes(record) {
+                skipped_reads.push_filtered(start, end);
+                false
+            } else {
+                true
+            }
+        });
+        let include_base_qualities = self.base_qualities && region.len() <= MAX_BASE_QUALITY_WINDOW;
+        let results: Vec<_> = records
             .par_iter()
             .map(|record| {
-                let alignment = AlignedRead::from_record(record, refseq, &self.tid_map)?;
-                Ok(alignment)
+                let mut read =
+                    AlignedRead::from_record(record, refseq, &self.tid_map).map_err(|err| {
+                        let skipped_read = SkippedRead {
+                            qname: String::from_utf8_lossy(record.qname()).into(),
+                            reason: format!("{:#}", err),
+                        };
+                        (skipped_read, get_record_span(record))
+                    })?;
+                // Reads use the reference's name for the contig if the bam uses an alias of it
+                read.rename_seq(seq_name, &region.seq_name);
+                if include_base_qualities {
+                    read.base_qualities = encode_base_qualities(record.qual());
+                }
+                Ok(read)
             })
-            .collect::<Result<_>>()?;
+            .collect();
 
-        Ok(alignments)
+        let mut alignments = Vec::with_capacity(results.len());
+        for result in results {
+            match result {
+                Ok(alignment) => alignments.push(alignment),
+                Err((skipped_read, (start, end))) => {
+                    skipped_reads.push_at(skipped_read, start, end)
+                }
+            }
+        }
+        (alignments, skipped_reads)
+    }
+}
+
+impl AlignmentReader for BamReader {
+    type Item = AlignedRead;
+
+    fn read(
+        &mut self,
+        region: &GenomicRegion,
+        refseq: &SequenceView,
+    ) -> Result<(Vec<Self::Item>, SkippedReads)> {
+        self.read_cancellable(region, refseq, &CancellationToken::new())
+    }
+
+    fn read_cancellable(
+        &mut self,
+        region: &GenomicRegion,
+        refseq: &SequenceView,
+        cancellation: &CancellationToken,
+    ) -> Result<(Vec<Self::Item>, SkippedReads)> {
+        let seq_name = match self.tid_map.resolve_seq_name(&region.seq_name) {
+            Some(seq_name) => seq_name,
+            None => bail!("Invalid contig/chromosome name: {}", region.seq_name),
+        };
+        let mut reader = self.reader.lock();
+        reader.fetch((seq_name, region.start(), region.end()))?;
+        let mut records = Vec::new();
+        for (i, record) in reader.records().enumerate() {
+            if i % CANCELLATION_CHECK_INTERVAL == 0 {
+                cancellation.check()?;
+            }
+            records.push(record?);
+        }
+        cancellation.check()?;
+        let (alignments, skipped_reads) = self.convert_records(records, region, seq_name, refseq);
+        if skipped_reads.count > 0 {
+            log::warn!(
+                "Skipped {} invalid reads in {} ({})",
+                skipped_reads.count,
+                self.bam_path.to_string_lossy(),
+                region
+            );
+        }
+        Ok((alignments, skipped_reads))
     }
 }
 
@@ -73,7 +539,7 @@ mod tests {
         let mut fasta_reader = FastaReader::new(fasta_path).unwrap();
         let sequence_view = fasta_reader.read(region).unwrap();
         let mut bam_reader = BamReader::new(bam_path).unwrap();
-        let alignments = bam_reader.read(region, &sequence_view).unwrap();
+        let (alignments, _) = bam_reader.read(region, &sequence_view).unwrap();
         assert_eq!(alignments.len(), expected_num_reads);
     }
 
@@ -83,6 +549,231 @@ mod tests {
         check_read_bam("fake-genome.reads.bam", "fake-genome.fa", &region, 575)
     }
 
+    #[test]
+    pub fn test_read_bam_with_contig_alias() {
+        let region = GenomicRegion::new("chrM", 1000, 1500).unwrap();
+        check_read_bam("fake-genome.reads.bam", "fake-genome.fa", &region, 575);
+        let bam_path = get_test_data_path("fake-genome.reads.bam");
+        let sequence_view =
+            FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap().read(&region).unwrap();
+        let (alignments, _) =
+            BamReader::new(bam_path).unwrap().read(&region, &sequence_view).unwrap();
+        assert!(alignments.iter().all(|read| read.region.seq_name == "chrM"));
+    }
+
+    #[test]
+    pub fn test_skip_invalid_records() {
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let sequence_view =
+            FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap().read(&region).unwrap();
+        let bam_reader = BamReader::new(get_test_data_path("fake-genome.reads.bam")).unwrap();
+        let mut records: Vec<Record> = {
+            let mut reader = bam_reader.reader.lock();
+            reader.fetch(("mt", region.start(), region.end())).unwrap();
+            reader.records().map(|record| record.unwrap()).collect()
+        };
+        let mut invalid_tid = records[0].clone();
+        invalid_tid.set_qname(b"invalid_tid");
+        invalid_tid.set_tid(99);
+        let mut invalid_pos = records[1].clone();
+        invalid_pos.set_qname(b"invalid_pos");
+        invalid_pos.set_pos(-5);
+        records.extend([invalid_tid, invalid_pos]);
+
+        let (alignments, skipped_reads) =
+            bam_reader.convert_records(records, &region, "mt", &sequence_view);
+        assert_eq!(alignments.len(), 575);
+        assert_eq!(skipped_reads.count, 2);
+        let qnames: Vec<&str> =
+            skipped_reads.reads.iter().map(|read| read.qname.as_str()).collect();
+        assert_eq!(qnames, vec!["invalid_tid", "invalid_pos"]);
+        assert!(skipped_reads.reads[1].reason.contains("invalid position"));
+    }
+
+    #[test]
+    pub fn test_read_bam_with_pool() {
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let sequence_view =
+            FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap().read(&region).unwrap();
+        let bam_path = get_test_data_path("fake-genome.reads.bam");
+        let pool = Arc::new(DecompressionPool::new(4).unwrap());
+        for pool in [None, Some(pool)] {
+            let mut bam_reader = BamReader::with_pool(&bam_path, pool).unwrap();
+            let (alignments, _) = bam_reader.read(&region, &sequence_view).unwrap();
+            assert_eq!(alignments.len(), 575);
+        }
+    }
+
+    #[test]
+    pub fn test_read_bam_with_filter() {
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let sequence_view =
+            FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap().read(&region).unwrap();
+        let mut bam_reader = BamReader::new(get_test_data_path("fake-genome.reads.bam")).unwrap();
+        bam_reader.set_filter(ReadFilter { min_mapq: 40, ..Default::default() });
+        let (alignments, skipped_reads) = bam_reader.read(&region, &sequence_view).unwrap();
+        assert!(!alignments.is_empty() && alignments.len() < 575);
+        assert_eq!(skipped_reads.filtered_count, 575 - alignments.len());
+        bam_reader.set_filter(ReadFilter::default());
+        let (alignments, skipped_reads) = bam_reader.read(&region, &sequence_view).unwrap();
+        assert_eq!(alignments.len(), 575);
+        assert_eq!(skipped_reads.filtered_count, 0);
+    }
+
+    #[test]
+    pub fn test_read_filter_hides_duplicates() {
+        let mut record = Record::new();
+        record.set_mapq(30);
+        record.set_flags(0x400);
+        let mut filter = ReadFilter::default();
+        assert!(filter.includes(&record));
+        filter.hide_duplicates = true;
+        assert!(!filter.includes(&record));
+        record.unset_duplicate();
+        assert!(filter.includes(&record));
+        filter.min_mapq = 31;
+        assert!(!filter.includes(&record));
+    }
+
+    #[test]
+    pub fn test_read_filter_hides_secondary_and_supplementary() {
+        let mut secondary = Record::new();
+        secondary.set_flags(0x100);
+        let mut supplementary = Record::new();
+        supplementary.set_flags(0x800);
+        let mut filter = ReadFilter::default();
+        assert!(filter.includes(&secondary) && filter.includes(&supplementary));
+        filter.hide_secondary = true;
+        assert!(!filter.includes(&secondary) && filter.includes(&supplementary));
+        filter.hide_supplementary = true;
+        assert!(!filter.includes(&supplementary));
+    }
+
+    #[test]
+    pub fn test_read_filter_by_read_group() {
+        let mut record = Record::new();
+        let mut filter = ReadFilter::default();
+        assert!(filter.includes(&record));
+        filter.read_groups = Some(vec!["rg1".to_owned(), "rg2".to_owned()]);
+        assert!(!filter.includes(&record));
+        record.push_aux(b"RG", Aux::String("rg2")).unwrap();
+        assert!(filter.includes(&record));
+        filter.read_groups = Some(vec!["rg1".to_owned()]);
+        assert!(!filter.includes(&record));
+    }
+
+    #[test]
+    pub fn test_read_filter_by_tag_values() {
+        let values = ["AAAC-1".to_owned(), "7".to_owned()].into();
+        let tag_values = TagValues { tag: "CB".to_owned(), values };
+        assert!(tag_values.validate().is_ok());
+        let filter = ReadFilter { tag_values: Some(tag_values.clone()), ..Default::default() };
+        let mut record = Record::new();
+        assert!(!filter.includes(&record));
+        record.push_aux(b"CB", Aux::String("AAAC-1")).unwrap();
+        assert!(filter.includes(&record));
+        record.remove_aux(b"CB").unwrap();
+        record.push_aux(b"CB", Aux::String("GGGT-1")).unwrap();
+        assert!(!filter.includes(&record));
+        record.remove_aux(b"CB").unwrap();
+        record.push_aux(b"CB", Aux::U8(7)).unwrap();
+        assert!(filter.includes(&record));
+
+        let invalid_tag = TagValues { tag: "C".to_owned(), ..tag_values.clone() };
+        assert!(invalid_tag.validate().is_err());
+        let no_values = TagValues { values: BTreeSet::new(), ..tag_values };
+        assert!(no_values.validate().is_err());
+    }
+
+    #[test]
+    pub fn test_sample_read_lengths() {
+        let bam_reader = BamReader::new(get_test_data_path("fake-genome.reads.bam")).unwrap();
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let (region_lengths, truncated) =
+            bam_reader.sample_read_lengths(Some(&region), usize::MAX).unwrap();
+        assert!(!truncated);
+        assert!(!region_lengths.is_empty());
+        let (file_lengths, truncated) = bam_reader.sample_read_lengths(None, 10).unwrap();
+        assert!(truncated);
+        assert_eq!(file_lengths.len(), 10);
+    }
+
+    #[test]
+    pub fn test_read_bam_with_base_qualities() {
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let fasta_path = get_test_data_path("fake-genome.fa");
+        let sequence_view = FastaReader::new(fasta_path).unwrap().read(&region).unwrap();
+        let mut bam_reader = BamReader::new(get_test_data_path("fake-genome.reads.bam")).unwrap();
+        let (alignments, _) = bam_reader.read(&region, &sequence_view).unwrap();
+        assert!(alignments.iter().all(|read| read.base_qualities.is_none()));
+        bam_reader.set_base_qualities(true);
+        let (alignments, _) = bam_reader.read(&region, &sequence_view).unwrap();
+        assert!(alignments.iter().all(|read| read.base_qualities.is_some()));
+        // Large regions are read without base qualities
+        let region = GenomicRegion::new("mt", 0, MAX_BASE_QUALITY_WINDOW + 1).unwrap();
+        let (alignments, _) = bam_reader.read(&region, &sequence_view).unwrap();
+        assert!(alignments.iter().all(|read| read.base_qualities.is_none()));
+    }
+
+    #[test]
+    pub fn test_fetch_insertion_sequence() {
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let fasta_path = get_test_data_path("fake-genome.fa");
+        let sequence_view = FastaReader::new(fasta_path).unwrap().read(&region).unwrap();
+        let mut bam_reader = BamReader::new(get_test_data_path("fake-genome.reads.bam")).unwrap();
+        let (alignments, _) = bam_reader.read(&region, &sequence_view).unwrap();
+        let (read, interval, sequence) = alignments
+            .iter()
+            .find_map(|read| {
+                read.diffs.iter().find_map(|diff| match diff {
+                    SequenceDiff::Ins { interval, sequence, .. } => {
+                        Some((read, interval, sequence))
+                    }
+                    _ => None,
+                })
+            })
+            .unwrap();
+        let fetched =
+            bam_reader.fetch_insertion_sequence(read, interval.start, &sequence_view).unwrap();
+        assert_eq!(&fetched, sequence);
+        assert!(bam_reader.fetch_insertion_sequence(read, 1499, &sequence_view).is_err());
+    }
+
+    #[test]
+    pub fn test_fetch_mate_region() {
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let fasta_path = get_test_data_path("fake-genome.fa");
+        let sequence_view = FastaReader::new(fasta_path).unwrap().read(&region).unwrap();
+        let mut bam_reader = BamReader::new(get_test_data_path("fake-genome.reads.bam")).unwrap();
+        let (alignments, _) = bam_reader.read(&region, &sequence_view).unwrap();
+        let read = alignments.iter().find(|read| read.mate_pos.is_some()).unwrap();
+        let mate_region = bam_reader.fetch_mate_region(read).unwrap().unwrap();
+        let mate_pos = read.mate_pos.as_ref().unwrap();
+        assert_eq!(mate_region.seq_name, mate_pos.seq_name);
+        assert_eq!(mate_region.start(), mate_pos.start());
+        assert!(mate_region.len() > 1);
+        let mut unpaired_read = read.clone();
+        unpaired_read.mate_pos = None;
+        assert_eq!(bam_reader.fetch_mate_region(&unpaired_read).unwrap(), None);
+    }
+
+    #[test]
+    pub fn test_fetch_read_details() {
+        let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
+        let fasta_path = get_test_data_path("fake-genome.fa");
+        let sequence_view = FastaReader::new(fasta_path).unwrap().read(&region).unwrap();
+        let mut bam_reader = BamReader::new(get_test_data_path("fake-genome.reads.bam")).unwrap();
+        let (alignments, _) = bam_reader.read(&region, &sequence_view).unwrap();
+        let read = alignments.iter().find(|read| read.region.start() >= 1000).unwrap();
+        let details = bam_reader.fetch_read_details(read, &sequence_view).unwrap();
+        assert_eq!(details.read.id, read.id);
+        assert_eq!(details.flag_names.contains(&"REVERSE"), read.is_reverse);
+        assert!(!details.sequence.is_empty());
+        let mut missing_read = read.clone();
+        missing_read.id = "missing".to_owned();
+        assert!(bam_reader.fetch_read_details(&missing_read, &sequence_view).is_err());
+    }
+
     #[test]
     pub fn test_read_empty_bam() {
         let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
@@ -94,4 +785,43 @@ mod tests {
         let region = GenomicRegion::new("mt", 1000, 1500).unwrap();
         check_read_bam("fake-genome.unmapped.bam", "fake-genome.fa", &region, 0)
     }
+
+    #[test]
+    pub fn test_fetch_unmapped_mate() {
+        let bam_path = get_test_data_path("fake-genome.unmapped.bam");
+        let bam_reader = BamReader::new(bam_path).unwrap();
+        let qname =
+            "SYN_7556_62_211_360_-_8062_1_._euk_genes&7556_-148_1_360_+_7852_1_._euk_genes \
+                     1:";
+        let region = GenomicRegion::new("euk_genes", 62, 212).unwrap();
+        let mate = bam_reader.fetch_unmapped_mate(qname, &region).unwrap().unwrap();
+        assert_eq!(mate.qname, qname);
+        assert!(!mate.sequence.is_empty());
+        let missing_mate = bam_reader.fetch_unmapped_mate("missing", &region).unwrap();
+        assert!(missing_mate.is_none());
+    }
+
+    #[test]
+    pub fn test_find_unmapped_read_stops_at_max_records() {
+        let bam_path = get_test_data_path("fake-genome.unmapped.bam");
+        let qname =
+            "SYN_7556_62_211_360_-_8062_1_._euk_genes&7556_-148_1_360_+_7852_1_._euk_genes \
+                     1:";
+        let mut reader = bam::Reader::from_path(&bam_path).unwrap();
+        assert!(find_unmapped_read(reader.records(), qname, usize::MAX).unwrap().is_some());
+        let mut reader = bam::Reader::from_path(&bam_path).unwrap();
+        assert!(find_unmapped_read(reader.records(), qname, 0).unwrap().is_none());
+    }
+
+    #[test]
+    pub fn test_read_counts_unmapped_reads() {
+        let region = GenomicRegion::new("euk_genes", 62, 212).unwrap();
+        let sequence_view =
+            FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap().read(&region).unwrap();
+        let mut bam_reader =
+            BamReader::new(get_test_data_path("fake-genome.unmapped.bam")).unwrap();
+        let (alignments, skipped_reads) = bam_reader.read(&region, &sequence_view).unwrap();
+        assert!(alignments.iter().all(|read| read.region.seq_name == "euk_genes"));
+        assert!(skipped_reads.unmapped_count > 0);
+    }
 }
@@ -0,0 +1,141 @@
+//! Sample names from the read groups (@RG header lines) of a SAM/BAM file.
+use std::path::PathBuf;
+
+use anyhow::Result;
+use rust_htslib::bam::Read;
+use serde::{Deserialize, Serialize};
+
+use crate::file_formats::sam_bam::reader::open_bam;
+
+/// Get the unique sample names (SM tags) from the read groups of a SAM/BAM file.
+///
+/// Sample names are returned in the order in which they first appear in the header.
+pub fn get_sample_names<P: Into<PathBuf>>(bam_path: P) -> Result<Vec<String>> {
+    let reader = open_bam(bam_path.into())?;
+    Ok(parse_sample_names(reader.header().as_bytes()))
+}
+
+fn parse_sample_names(header: &[u8]) -> Vec<String> {
+    let mut sample_names: Vec<String> = Vec::new();
+    for read_group in parse_read_groups(header) {
+        if let Some(sample_name) = read_group.sample {
+            if !sample_names.contains(&sample_name) {
+                sample_names.push(sample_name);
+            }
+        }
+    }
+    sample_names
+}
+
+/// Header tag of a read group which reads can be grouped by.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReadGroupField {
+    Sample,
+    Library,
+}
+
+/// A read group (@RG header line) of a SAM/BAM file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadGroup {
+    pub id: String,
+    /// SM tag.
+    pub sample: Option<String>,
+    /// LB tag.
+    pub library: Option<String>,
+}
+
+impl ReadGroup {
+    pub fn get(&self, field: ReadGroupField) -> Option<&str> {
+        match field {
+            ReadGroupField::Sample => self.sample.as_deref(),
+            ReadGroupField::Library => self.library.as_deref(),
+        }
+    }
+}
+
+/// Get the read groups of a SAM/BAM file in the order in which they appear in the header.
+pub fn get_read_groups<P: Into<PathBuf>>(bam_path: P) -> Result<Vec<ReadGroup>> {
+    let reader = open_bam(bam_path.into())?;
+    Ok(parse_read_groups(reader.header().as_bytes()))
+}
+
+fn parse_read_groups(header: &[u8]) -> Vec<ReadGroup> {
+    String::from_utf8_lossy(header)
+        .lines()
+        .filter_map(|line| line.strip_prefix("@RG\t"))
+        .filter_map(|line| {
+            let get_tag = |tag: &str| {
+                line.split('\t').find_map(|field| field.strip_prefix(tag)).map(str::to_owned)
+            };
+            // Read groups must have an ID, so any without one are malformed and can't be matched
+            // to reads anyway
+            Some(ReadGroup { id: get_tag("ID:")?, sample: get_tag("SM:"), library: get_tag("LB:") })
+        })
+        .collect()
+}
+
+/// Group read group ids by a header tag (e.g sample), in the order in which each value first
+/// appears.
+///
+/// Read groups which don't have the tag are grouped by their id instead.
+pub fn group_read_groups(
+    read_groups: &[ReadGroup],
+    field: ReadGroupField,
+) -> Vec<(String, Vec<String>)> {
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+    for read_group in read_groups {
+        let name = read_group.get(field).unwrap_or(&read_group.id);
+        match groups.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, ids)) => ids.push(read_group.id.clone()),
+            None => groups.push((name.to_owned(), vec![read_group.id.clone()])),
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::paths::get_test_data_path;
+
+    use super::*;
+
+    #[test]
+    pub fn test_parse_sample_names() {
+        let header = b"@HD\tVN:1.4\n@RG\tID:rg1\tSM:tumor\n@RG\tID:rg2\tSM:normal\n\
+            @RG\tID:rg3\tSM:tumor\n@RG\tID:rg4\n@PG\tID:bwa\tSM:notasample\n";
+        assert_eq!(parse_sample_names(header), vec!["tumor".to_owned(), "normal".to_owned()]);
+    }
+
+    #[test]
+    pub fn test_group_read_groups() {
+        let header = b"@RG\tID:rg1\tSM:tumor\tLB:lib1\n@RG\tID:rg2\tSM:normal\tLB:lib1\n\
+            @RG\tID:rg3\tSM:tumor\tLB:lib2\n@RG\tID:rg4\n@RG\tSM:noid\n";
+        let read_groups = parse_read_groups(header);
+        assert_eq!(read_groups.len(), 4);
+        assert_eq!(
+            group_read_groups(&read_groups, ReadGroupField::Sample),
+            vec![
+                ("tumor".to_owned(), vec!["rg1".to_owned(), "rg3".to_owned()]),
+                ("normal".to_owned(), vec!["rg2".to_owned()]),
+                ("rg4".to_owned(), vec!["rg4".to_owned()]),
+            ]
+        );
+        assert_eq!(
+            group_read_groups(&read_groups, ReadGroupField::Library),
+            vec![
+                ("lib1".to_owned(), vec!["rg1".to_owned(), "rg2".to_owned()]),
+                ("lib2".to_owned(), vec!["rg3".to_owned()]),
+                ("rg4".to_owned(), vec!["rg4".to_owned()]),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_get_sample_names_without_read_groups() {
+        let bam_path = get_test_data_path("fake-genome.reads.bam");
+        assert!(get_sample_names(bam_path).unwrap().is_empty());
+    }
+}
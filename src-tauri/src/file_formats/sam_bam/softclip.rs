@@ -0,0 +1,167 @@
+//! Clustering of softclipped sequences which share a breakpoint.
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::sam_bam::aligned_read::AlignedRead;
+use crate::file_formats::sam_bam::diff::SequenceDiff;
+
+/// The side of the aligned portion of a read on which a softclip occurs.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClipSide {
+    Left,
+    Right,
+}
+
+/// A group of softclips which start at the same breakpoint.
+#[serde_as]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SoftClipCluster {
+    /// Reference position of the boundary between the aligned and clipped portions of the reads.
+    #[serde_as(as = "DisplayFromStr")]
+    pub position: u64,
+    pub side: ClipSide,
+    pub num_reads: usize,
+    /// Majority-vote consensus of the clipped sequences.
+    pub consensus: String,
+}
+
+/// Compute the consensus of a set of sequences which are anchored at their first base.
+///
+/// Only positions which are covered by at least two sequences (or one if there is only a single
+/// sequence) are included.
+fn left_anchored_consensus(sequences: &[&[u8]]) -> Vec<u8> {
+    let min_coverage = sequences.len().clamp(1, 2);
+    let mut consensus = Vec::new();
+    for i in 0.. {
+        let mut counts: BTreeMap<u8, usize> = BTreeMap::new();
+        for base in sequences.iter().filter_map(|seq| seq.get(i)) {
+            *counts.entry(base.to_ascii_uppercase()).or_default() += 1;
+        }
+        if counts.values().sum::<usize>() < min_coverage {
+            break;
+        }
+        // Ties are broken by base order so that the result is deterministic
+        let (base, _) =
+            counts.into_iter().max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0))).unwrap();
+        consensus.push(base);
+    }
+    consensus
+}
+
+/// Group the softclips from a set of reads by breakpoint.
+///
+/// # Arguments
+///
+/// * `region` - Only breakpoints within this region are included.
+/// * `min_reads` - Minimum number of reads which must share a breakpoint to form a cluster.
+pub fn cluster_softclips<'a, I: IntoIterator<Item = &'a AlignedRead>>(
+    reads: I,
+    region: &GenomicRegion,
+    min_reads: usize,
+) -> Vec<SoftClipCluster> {
+    let mut clips: BTreeMap<(u64, ClipSide), Vec<&[u8]>> = BTreeMap::new();
+    for read in reads {
+        if read.region.seq_name != region.seq_name {
+            continue;
+        }
+        // Softclips are drawn on the reference as if they were aligned bases so a clip at the
+        // start of a read shifts the rest of the read to the right. This offset is removed to
+        // get the true breakpoint of a clip at the end of the read.
+        let mut leading_clip_len = 0;
+        for diff in read.diffs.iter() {
+            if let SequenceDiff::SoftClip { interval, sequence } = diff {
+                let key = if interval.start == read.region.start() {
+                    leading_clip_len = interval.len();
+                    (interval.start, ClipSide::Left)
+                } else {
+                    (interval.start - leading_clip_len, ClipSide::Right)
+                };
+                if key.0 >= region.start() && key.0 < region.end() {
+                    clips.entry(key).or_default().push(sequence.as_bytes());
+                }
+            }
+        }
+    }
+    clips
+        .into_iter()
+        .filter(|(_, sequences)| sequences.len() >= min_reads.max(1))
+        .map(|((position, side), sequences)| {
+            let consensus = match side {
+                ClipSide::Right => left_anchored_consensus(&sequences),
+                ClipSide::Left => {
+                    let reversed: Vec<Vec<u8>> =
+                        sequences.iter().map(|seq| seq.iter().rev().copied().collect()).collect();
+                    let reversed_refs: Vec<&[u8]> = reversed.iter().map(|seq| &seq[..]).collect();
+                    let mut consensus = left_anchored_consensus(&reversed_refs);
+                    consensus.reverse();
+                    consensus
+                }
+            };
+            SoftClipCluster {
+                position,
+                side,
+                num_reads: sequences.len(),
+                consensus: String::from_utf8_lossy(&consensus).into(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::test_util::aligned_reads::gen_read;
+
+    fn gen_clipped_read(id: &str, start: u64, end: u64, clip: SequenceDiff) -> AlignedRead {
+        gen_read(id, start, end, vec![clip])
+    }
+
+    fn gen_clip(start: u64, sequence: &str) -> SequenceDiff {
+        SequenceDiff::SoftClip {
+            interval: (start, start + sequence.len() as u64).try_into().unwrap(),
+            sequence: sequence.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_cluster_right_softclips() {
+        let reads = vec![
+            gen_clipped_read("0", 100, 154, gen_clip(150, "ACGT")),
+            gen_clipped_read("1", 110, 156, gen_clip(150, "ACCTGG")),
+            gen_clipped_read("2", 120, 155, gen_clip(150, "ACGTG")),
+            gen_clipped_read("3", 120, 155, gen_clip(152, "ACG")),
+        ];
+        let region = GenomicRegion::new("X", 0, 1000).unwrap();
+        let clusters = cluster_softclips(&reads, &region, 2);
+        assert_eq!(
+            clusters,
+            vec![SoftClipCluster {
+                position: 150,
+                side: ClipSide::Right,
+                num_reads: 3,
+                consensus: "ACGTG".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_cluster_left_softclips() {
+        let reads = vec![
+            gen_clipped_read("0", 100, 154, gen_clip(100, "TACG")),
+            gen_clipped_read("1", 100, 152, gen_clip(100, "CG")),
+        ];
+        let region = GenomicRegion::new("X", 0, 1000).unwrap();
+        let clusters = cluster_softclips(&reads, &region, 2);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].position, 100);
+        assert_eq!(clusters[0].side, ClipSide::Left);
+        assert_eq!(clusters[0].consensus, "CG");
+    }
+}
@@ -0,0 +1,81 @@
+//! Counting spliced reads across each splice junction (e.g for drawing sashimi plots of RNA-seq
+//! data).
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::sam_bam::aligned_read::AlignedRead;
+use crate::file_formats::sam_bam::diff::SequenceDiff;
+
+/// Reads which skip the same stretch of the reference (i.e an intron).
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpliceJunction {
+    /// The skipped region, from the end of the upstream exon to the start of the downstream exon.
+    pub region: GenomicRegion,
+    pub num_reads: u32,
+}
+
+/// Aggregate the reference skips (CIGAR N) of a set of reads into splice junctions, ordered by
+/// position.
+///
+/// Each read is counted separately, so a pair whose reads both span a junction counts twice.
+pub fn count_splice_junctions<'a, I: IntoIterator<Item = &'a AlignedRead>>(
+    reads: I,
+) -> Result<Vec<SpliceJunction>> {
+    let mut counts: BTreeMap<(&str, u64, u64), u32> = BTreeMap::new();
+    for read in reads {
+        for diff in read.diffs.iter() {
+            if let SequenceDiff::RefSkip { interval } = diff {
+                let key = (read.region.seq_name.as_str(), interval.start, interval.end);
+                *counts.entry(key).or_default() += 1;
+            }
+        }
+    }
+    counts
+        .into_iter()
+        .map(|((seq_name, start, end), num_reads)| {
+            Ok(SpliceJunction { region: GenomicRegion::new(seq_name, start, end)?, num_reads })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::bio_util::genomic_coordinates::GenomicInterval;
+    use crate::test_util::aligned_reads::gen_read;
+
+    fn gen_spliced_read(id: &str, introns: &[(u64, u64)]) -> AlignedRead {
+        let diffs = introns
+            .iter()
+            .map(|(start, end)| SequenceDiff::RefSkip {
+                interval: GenomicInterval::new(*start, *end).unwrap(),
+            })
+            .collect();
+        gen_read(id, 0, 1000, diffs)
+    }
+
+    #[test]
+    fn test_count_splice_junctions() {
+        let reads = vec![
+            gen_spliced_read("a", &[(100, 200), (300, 400)]),
+            gen_spliced_read("b", &[(100, 200)]),
+            gen_spliced_read("c", &[(100, 250)]),
+            gen_spliced_read("d", &[]),
+        ];
+        let junctions = count_splice_junctions(&reads).unwrap();
+        let junction = |start: u64, end: u64, num_reads: u32| SpliceJunction {
+            region: GenomicRegion::new("X", start, end).unwrap(),
+            num_reads,
+        };
+        assert_eq!(
+            junctions,
+            vec![junction(100, 200, 2), junction(100, 250, 1), junction(300, 400, 1)]
+        );
+    }
+}
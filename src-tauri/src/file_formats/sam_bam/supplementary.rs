@@ -0,0 +1,228 @@
+//! Chaining the primary and supplementary alignments of a read (e.g a long read which is split
+//! across a structural variant) from its SA tag.
+//!
+//! See section 1.2 of the SAM optional fields spec
+//! (https://samtools.github.io/hts-specs/SAMtags.pdf) for the format.
+use anyhow::{anyhow, bail, Context, Result};
+use rust_htslib::bam::record::{Aux, Cigar, CigarString, Record};
+use serde::Serialize;
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+
+/// A single alignment of a chained read.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainSegment {
+    /// Span of the alignment on the reference, excluding clipped bases.
+    pub region: GenomicRegion,
+    pub is_reverse: bool,
+    /// Span of the alignment in the original (unreversed) read sequence.
+    pub query_start: u32,
+    pub query_end: u32,
+    pub mapq: u8,
+}
+
+/// The alignments of a read which is split into a primary alignment and supplementary alignments.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlignmentChain {
+    /// Shared by every alignment in the chain.
+    pub id: String,
+    /// Alignments ordered by their position in the read.
+    pub segments: Vec<ChainSegment>,
+    /// Index of the segment which this alignment of the read is.
+    pub index: usize,
+}
+
+impl AlignmentChain {
+    /// Rename the contig which segments of the chain are aligned to.
+    pub fn rename_seq(&mut self, seq_name: &str, new_seq_name: &str) {
+        for segment in self.segments.iter_mut() {
+            if segment.region.seq_name == seq_name {
+                segment.region.seq_name = new_seq_name.to_owned();
+            }
+        }
+    }
+}
+
+fn clip_len(op: &Cigar) -> Option<u32> {
+    match op {
+        Cigar::SoftClip(len) | Cigar::HardClip(len) => Some(*len),
+        _ => None,
+    }
+}
+
+/// Get the span of an alignment in the original read sequence from its CIGAR, counting both soft
+/// and hard clipped bases.
+fn query_span(cigar: &[Cigar], is_reverse: bool) -> (u32, u32) {
+    let aligned_len: u32 = cigar
+        .iter()
+        .map(|op| match op {
+            Cigar::Match(len) | Cigar::Ins(len) | Cigar::Equal(len) | Cigar::Diff(len) => *len,
+            _ => 0,
+        })
+        .sum();
+    let start: u32 = if is_reverse {
+        cigar.iter().rev().map_while(clip_len).sum()
+    } else {
+        cigar.iter().map_while(clip_len).sum()
+    };
+    (start, start + aligned_len)
+}
+
+/// Get the number of reference bases which an alignment spans from its CIGAR.
+fn reference_len(cigar: &[Cigar]) -> u64 {
+    cigar
+        .iter()
+        .map(|op| match op {
+            Cigar::Match(len)
+            | Cigar::Del(len)
+            | Cigar::RefSkip(len)
+            | Cigar::Equal(len)
+            | Cigar::Diff(len) => *len as u64,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Parse an SA tag (e.g "chr2,1001,-,50S100M,60,0;") into the alignments it lists.
+fn parse_sa_tag(sa: &str) -> Result<Vec<ChainSegment>> {
+    let mut segments = Vec::new();
+    for entry in sa.split(';').filter(|entry| !entry.is_empty()) {
+        let fields: Vec<&str> = entry.split(',').collect();
+        let (seq_name, pos, strand, cigar, mapq) = match fields[..] {
+            [seq_name, pos, strand, cigar, mapq, _] => (seq_name, pos, strand, cigar, mapq),
+            _ => bail!("Invalid SA tag entry: {}", entry),
+        };
+        // Positions in the SA tag are 1-based
+        let start = pos
+            .parse::<u64>()
+            .ok()
+            .and_then(|pos| pos.checked_sub(1))
+            .with_context(|| format!("Invalid position in SA tag entry: {}", entry))?;
+        let is_reverse = match strand {
+            "+" => false,
+            "-" => true,
+            _ => bail!("Invalid strand in SA tag entry: {}", entry),
+        };
+        let cigar = CigarString::try_from(cigar)
+            .map_err(|err| anyhow!("Invalid CIGAR in SA tag entry {}: {}", entry, err))?;
+        let (query_start, query_end) = query_span(&cigar, is_reverse);
+        segments.push(ChainSegment {
+            region: GenomicRegion::new(seq_name, start, start + reference_len(&cigar))?,
+            is_reverse,
+            query_start,
+            query_end,
+            mapq: mapq
+                .parse()
+                .with_context(|| format!("Invalid mapping quality in SA tag entry: {}", entry))?,
+        });
+    }
+    Ok(segments)
+}
+
+/// Get the chain of alignments which a read belongs to from its SA tag.
+///
+/// Reads without an SA tag aren't chained.
+///
+/// # Arguments
+///
+/// * `seq_name` - Name of the contig which the read is aligned to.
+/// * `id` - Identifier which is shared by all alignments of the read.
+pub fn get_alignment_chain(
+    record: &Record,
+    seq_name: &str,
+    id: &str,
+) -> Result<Option<AlignmentChain>> {
+    let sa = match record.aux(b"SA") {
+        Ok(Aux::String(sa)) => sa,
+        Ok(_) => bail!("SA tag must be a string"),
+        Err(_) => return Ok(None),
+    };
+    let start = u64::try_from(record.pos()).context("Chained read has an invalid position")?;
+    let cigar = record.cigar();
+    let (query_start, query_end) = query_span(&cigar, record.is_reverse());
+    let segment = ChainSegment {
+        region: GenomicRegion::new(seq_name, start, start + reference_len(&cigar))?,
+        is_reverse: record.is_reverse(),
+        query_start,
+        query_end,
+        mapq: record.mapq(),
+    };
+    let mut segments: Vec<(bool, ChainSegment)> = std::iter::once((true, segment))
+        .chain(parse_sa_tag(sa)?.into_iter().map(|segment| (false, segment)))
+        .collect();
+    segments.sort_by_key(|(_, segment)| (segment.query_start, segment.query_end));
+    let index = segments.iter().position(|(is_record, _)| *is_record).unwrap_or_default();
+    let segments = segments.into_iter().map(|(_, segment)| segment).collect();
+    Ok(Some(AlignmentChain { id: id.to_owned(), segments, index }))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::test_util::htslib_records::RecordBuilder;
+
+    fn segment(
+        seq_name: &str,
+        start: u64,
+        end: u64,
+        is_reverse: bool,
+        query: (u32, u32),
+        mapq: u8,
+    ) -> ChainSegment {
+        ChainSegment {
+            region: GenomicRegion::new(seq_name, start, end).unwrap(),
+            is_reverse,
+            query_start: query.0,
+            query_end: query.1,
+            mapq,
+        }
+    }
+
+    #[test]
+    fn test_query_span() {
+        let cigar = CigarString::try_from("5H10S20M2I3D8M").unwrap();
+        assert_eq!(query_span(&cigar, false), (15, 45));
+        assert_eq!(query_span(&cigar, true), (0, 30));
+        let cigar = CigarString::try_from("20M15S").unwrap();
+        assert_eq!(query_span(&cigar, true), (15, 35));
+        assert_eq!(reference_len(&CigarString::try_from("5S10M2I3D100N4M").unwrap()), 117);
+    }
+
+    #[test]
+    fn test_parse_sa_tag() {
+        let segments = parse_sa_tag("Y,101,-,6M4S,30,1;X,1,+,6S4M,60,0;").unwrap();
+        let expected =
+            vec![segment("Y", 100, 106, true, (4, 10), 30), segment("X", 0, 4, false, (6, 10), 60)];
+        assert_eq!(segments, expected);
+        assert!(parse_sa_tag("Y,0,-,6M4S,30,1").is_err());
+        assert!(parse_sa_tag("Y,1,*,6M4S,30,1").is_err());
+        assert!(parse_sa_tag("Y,1,+,6M4S,30").is_err());
+    }
+
+    #[test]
+    fn test_get_alignment_chain() {
+        let cigar = CigarString::try_from("4M6S").unwrap();
+        let record = RecordBuilder::new(b"read", b"ACGTACGTAC", Some(&cigar), &[30; 10])
+            .pos(1000)
+            .aux(b"SA", Aux::String("Y,101,+,4S3M3S,30,1;X,2001,+,7S3M,60,0;"))
+            .record;
+        let chain = get_alignment_chain(&record, "X", "read/1").unwrap().unwrap();
+        let expected = AlignmentChain {
+            id: "read/1".to_owned(),
+            segments: vec![
+                segment("X", 1000, 1004, false, (0, 4), 0),
+                segment("Y", 100, 103, false, (4, 7), 30),
+                segment("X", 2000, 2003, false, (7, 10), 60),
+            ],
+            index: 0,
+        };
+        assert_eq!(chain, expected);
+
+        let record = RecordBuilder::default().record;
+        assert_eq!(get_alignment_chain(&record, "X", "read/1").unwrap(), None);
+    }
+}
@@ -2,9 +2,11 @@ use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use anyhow::Result;
-use rust_htslib::bam;
 use rust_htslib::bam::Read;
 
+use crate::bio_util::contig_aliases::resolve_contig_name;
+use crate::file_formats::sam_bam::reader::open_bam;
+
 /// Maps target ids (tids) from a bam to human-readable sequence names.
 #[derive(Debug)]
 pub struct TidMap {
@@ -13,7 +15,7 @@ pub struct TidMap {
 
 impl TidMap {
     pub fn new<P: Into<PathBuf>>(bam_path: P) -> Result<Self> {
-        let reader = bam::IndexedReader::from_path(&bam_path.into())?;
+        let reader = open_bam(bam_path.into())?;
         let bam_header = reader.header();
         let mut map = BTreeMap::new();
         for target_name in bam_header.target_names().iter() {
@@ -37,9 +39,17 @@ impl TidMap {
         self.map.get(&tid_unsigned)
     }
 
+    /// Get the tid of a sequence. Aliases of the sequence's name (e.g chr1 for 1) are matched if
+    /// the exact name isn't present.
     pub fn get_tid(&self, seq_name: &str) -> Option<&u32> {
+        let seq_name = self.resolve_seq_name(seq_name)?;
         self.map.iter().find_map(|(tid, val)| if val == seq_name { Some(tid) } else { None })
     }
+
+    /// Get the name which the bam uses for a sequence (which may be an alias of `seq_name`).
+    pub fn resolve_seq_name(&self, seq_name: &str) -> Option<&str> {
+        resolve_contig_name(seq_name, self.map.values().map(String::as_str))
+    }
 }
 
 impl From<BTreeMap<u32, String>> for TidMap {
@@ -64,4 +74,16 @@ mod tests {
         assert_eq!(tid_map.get_tid("euk_genes"), Some(&0));
         assert_eq!(tid_map.get_tid("mt"), Some(&1));
     }
+
+    #[test]
+    pub fn test_get_tid_of_alias() {
+        let tid_map: TidMap = [(0, "1".to_owned()), (1, "MT".to_owned())]
+            .into_iter()
+            .collect::<BTreeMap<_, _>>()
+            .into();
+        assert_eq!(tid_map.get_tid("chr1"), Some(&0));
+        assert_eq!(tid_map.get_tid("chrM"), Some(&1));
+        assert_eq!(tid_map.resolve_seq_name("chrM"), Some("MT"));
+        assert_eq!(tid_map.get_tid("chr2"), None);
+    }
 }
@@ -0,0 +1,130 @@
+//! Reader for bedGraph files, optionally bgzipped and tabix indexed.
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use rust_htslib::tbx::{self, Read};
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::signal::bins::SignalInterval;
+
+/// Parse a single line of a bedGraph file.
+///
+/// Returns None for header, comment and blank lines.
+pub fn parse_bedgraph_line(line: &str) -> Result<Option<(String, SignalInterval)>> {
+    let line = line.trim_end();
+    if line.is_empty()
+        || line.starts_with('#')
+        || line.starts_with("track")
+        || line.starts_with("browser")
+    {
+        return Ok(None);
+    }
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 4 {
+        bail!("Expected 4 columns in bedGraph line: {}", line);
+    }
+    let parse_field = |i: usize| {
+        fields[i].parse::<u64>().with_context(|| format!("Invalid position in line: {}", line))
+    };
+    let interval = SignalInterval {
+        start: parse_field(1)?,
+        end: parse_field(2)?,
+        value: fields[3].parse().with_context(|| format!("Invalid value in line: {}", line))?,
+    };
+    Ok(Some((fields[0].to_owned(), interval)))
+}
+
+fn is_bgzipped<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().extension() == Some(OsStr::new("gz"))
+}
+
+/// Reader for signal values from a bedGraph file.
+///
+/// Bgzipped files must have a tabix index. Plain text files are scanned in full on each read so
+/// they should only be used for small files.
+#[derive(Debug)]
+pub struct BedGraphReader {
+    pub path: PathBuf,
+}
+
+impl BedGraphReader {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        if !path.exists() {
+            bail!("bedGraph file does not exist: {}", path.to_string_lossy());
+        }
+        Ok(Self { path })
+    }
+
+    /// Read the intervals which overlap a region.
+    pub fn read(&self, region: &GenomicRegion) -> Result<Vec<SignalInterval>> {
+        let lines = if is_bgzipped(&self.path) {
+            self.fetch_indexed_lines(region)?
+        } else {
+            let file = File::open(&self.path).with_context(|| {
+                format!("Failed to open bedGraph file: {}", self.path.to_string_lossy())
+            })?;
+            BufReader::new(file).lines().collect::<std::io::Result<_>>()?
+        };
+        let mut intervals = Vec::new();
+        for line in lines {
+            if let Some((seq_name, interval)) = parse_bedgraph_line(&line)? {
+                if seq_name == region.seq_name
+                    && interval.start < region.end()
+                    && interval.end > region.start()
+                {
+                    intervals.push(interval);
+                }
+            }
+        }
+        Ok(intervals)
+    }
+
+    fn fetch_indexed_lines(&self, region: &GenomicRegion) -> Result<Vec<String>> {
+        let mut reader = tbx::Reader::from_path(&self.path).with_context(|| {
+            format!("Failed to open indexed bedGraph file: {}", self.path.to_string_lossy())
+        })?;
+        let tid = match reader.tid(&region.seq_name) {
+            Ok(tid) => tid,
+            // Sequences without any records are not present in the index
+            Err(_) => return Ok(Vec::new()),
+        };
+        reader.fetch(tid, region.start(), region.end())?;
+        reader.records().map(|record| Ok(String::from_utf8_lossy(&record?).into_owned())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_parse_bedgraph_line() {
+        assert_eq!(parse_bedgraph_line("track type=bedGraph").unwrap(), None);
+        assert_eq!(
+            parse_bedgraph_line("mt\t10\t20\t1.5").unwrap(),
+            Some(("mt".to_owned(), SignalInterval { start: 10, end: 20, value: 1.5 }))
+        );
+        assert!(parse_bedgraph_line("mt\t10\t20").is_err());
+    }
+
+    #[test]
+    fn test_read_bedgraph() {
+        let region = GenomicRegion::new("mt", 15, 35).unwrap();
+        let expected = vec![
+            SignalInterval { start: 10, end: 20, value: 1.5 },
+            SignalInterval { start: 20, end: 30, value: 3.0 },
+            SignalInterval { start: 30, end: 40, value: 0.5 },
+        ];
+        for filename in ["fake-genome.bedgraph", "fake-genome.bedgraph.gz"] {
+            let reader = BedGraphReader::new(get_test_data_path(filename)).unwrap();
+            assert_eq!(reader.read(&region).unwrap(), expected);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Binned signal values (e.g coverage) for rendering quantitative tracks.
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+
+/// Number of bins which signal values are summarized into across a region.
+pub const SIGNAL_BINS: u64 = 200;
+
+/// A region of the genome with a constant signal value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignalInterval {
+    pub start: u64,
+    pub end: u64,
+    pub value: f64,
+}
+
+/// Signal values summarized into fixed-width bins across a genomic region.
+///
+/// This is the payload for all signal track formats so that the frontend doesn't need to know which
+/// file format the values came from.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignalBins {
+    pub region: GenomicRegion,
+    #[serde_as(as = "DisplayFromStr")]
+    pub bin_size: u64,
+    /// Mean signal value of the covered bases in each bin (None if no bases are covered).
+    pub values: Vec<Option<f64>>,
+}
+
+impl SignalBins {
+    /// Summarize signal intervals into SIGNAL_BINS bins (or one bin per base for small regions).
+    pub fn summarize<'a, I: IntoIterator<Item = &'a SignalInterval>>(
+        region: &GenomicRegion,
+        intervals: I,
+    ) -> Self {
+        let bin_size = (region.len() + SIGNAL_BINS - 1) / SIGNAL_BINS;
+        Self::from_intervals(region, bin_size, intervals)
+    }
+
+    /// Summarize signal intervals into bins.
+    ///
+    /// Intervals are expected to be non-overlapping. Values are weighted by the number of bases
+    /// which overlap each bin.
+    pub fn from_intervals<'a, I: IntoIterator<Item = &'a SignalInterval>>(
+        region: &GenomicRegion,
+        bin_size: u64,
+        intervals: I,
+    ) -> Self {
+        let bin_size = bin_size.max(1);
+        let num_bins = ((region.len() + bin_size - 1) / bin_size) as usize;
+        let mut sums = vec![0.0; num_bins];
+        let mut covered = vec![0u64; num_bins];
+        for interval in intervals {
+            let start = interval.start.max(region.start());
+            let end = interval.end.min(region.end());
+            if start >= end {
+                continue;
+            }
+            let first_bin = ((start - region.start()) / bin_size) as usize;
+            let last_bin = ((end - 1 - region.start()) / bin_size) as usize;
+            for bin in first_bin..=last_bin {
+                let bin_start = region.start() + bin as u64 * bin_size;
+                let bin_end = bin_start + bin_size;
+                let overlap = end.min(bin_end) - start.max(bin_start);
+                sums[bin] += interval.value * overlap as f64;
+                covered[bin] += overlap;
+            }
+        }
+        let values = sums
+            .into_iter()
+            .zip(covered)
+            .map(|(sum, covered)| if covered > 0 { Some(sum / covered as f64) } else { None })
+            .collect();
+        Self { region: region.clone(), bin_size, values }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_bin_signal_intervals() {
+        let region = GenomicRegion::new("X", 100, 130).unwrap();
+        let intervals = vec![
+            SignalInterval { start: 90, end: 105, value: 2.0 },
+            SignalInterval { start: 105, end: 110, value: 4.0 },
+            SignalInterval { start: 125, end: 200, value: 1.0 },
+        ];
+        let bins = SignalBins::from_intervals(&region, 10, &intervals);
+        assert_eq!(bins.values, vec![Some(3.0), None, Some(1.0)]);
+    }
+
+    #[test]
+    fn test_summarize_signal_intervals() {
+        let intervals = vec![SignalInterval { start: 0, end: 1000, value: 2.0 }];
+        let bins = SignalBins::summarize(&GenomicRegion::new("X", 0, 1000).unwrap(), &intervals);
+        assert_eq!(bins.bin_size, 5);
+        assert_eq!(bins.values, vec![Some(2.0); SIGNAL_BINS as usize]);
+        let bins = SignalBins::summarize(&GenomicRegion::new("X", 0, 10).unwrap(), &intervals);
+        assert_eq!(bins.bin_size, 1);
+        assert_eq!(bins.values.len(), 10);
+    }
+}
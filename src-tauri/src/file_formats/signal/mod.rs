@@ -0,0 +1,4 @@
+pub mod bedgraph;
+pub mod bins;
+pub mod reader;
+pub mod wig;
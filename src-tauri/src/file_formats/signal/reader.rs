@@ -0,0 +1,56 @@
+//! Reader for signal tracks, whichever file format their values are stored in.
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::enums::{get_file_kind, FileKind};
+use crate::file_formats::signal::bedgraph::BedGraphReader;
+use crate::file_formats::signal::bins::SignalInterval;
+use crate::file_formats::signal::wig::WigReader;
+
+#[derive(Debug)]
+pub enum SignalReader {
+    BedGraph(BedGraphReader),
+    Wig(WigReader),
+}
+
+impl SignalReader {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        match get_file_kind(&path)? {
+            FileKind::BedGraph => Ok(Self::BedGraph(BedGraphReader::new(path)?)),
+            FileKind::Wig => Ok(Self::Wig(WigReader::new(path)?)),
+            file_kind => {
+                bail!("{} files don't contain signal values: {}", file_kind, path.to_string_lossy())
+            }
+        }
+    }
+
+    /// Read the intervals which overlap a region.
+    pub fn read(&self, region: &GenomicRegion) -> Result<Vec<SignalInterval>> {
+        match self {
+            Self::BedGraph(reader) => reader.read(region),
+            Self::Wig(reader) => Ok(reader.read(region)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_read_signal_formats() {
+        let region = GenomicRegion::new("euk_genes", 0, 50).unwrap();
+        let expected = vec![SignalInterval { start: 0, end: 100, value: 4.0 }];
+        for filename in ["fake-genome.bedgraph", "fake-genome.bedgraph.gz", "fake-genome.wig"] {
+            let reader = SignalReader::new(get_test_data_path(filename)).unwrap();
+            assert_eq!(reader.read(&region).unwrap(), expected);
+        }
+        assert!(SignalReader::new(get_test_data_path("fake-genome.paf")).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Reader for wiggle (.wig) files.
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::signal::bins::SignalInterval;
+
+/// Parameters from a variableStep/fixedStep declaration line.
+#[derive(Clone, Debug)]
+enum StepDeclaration {
+    Variable { chrom: String, span: u64 },
+    Fixed { chrom: String, start: u64, step: u64, span: u64 },
+}
+
+fn parse_declaration(line: &str) -> Result<StepDeclaration> {
+    let mut fields = line.split_whitespace();
+    let kind = fields.next().unwrap_or_default();
+    let mut chrom = None;
+    let mut start = None;
+    let mut step = None;
+    let mut span = 1;
+    for field in fields {
+        let (key, value) = field
+            .split_once('=')
+            .with_context(|| format!("Invalid wig declaration field: {}", field))?;
+        let parse_value =
+            || value.parse::<u64>().with_context(|| format!("Invalid wig {} value", key));
+        match key {
+            "chrom" => chrom = Some(value.to_owned()),
+            "start" => start = Some(parse_value()?),
+            "step" => step = Some(parse_value()?),
+            "span" => span = parse_value()?,
+            _ => (),
+        }
+    }
+    let chrom = chrom.with_context(|| format!("Missing chrom in wig declaration: {}", line))?;
+    match kind {
+        "variableStep" => Ok(StepDeclaration::Variable { chrom, span }),
+        "fixedStep" => {
+            let start = start.context("Missing start in fixedStep declaration")?;
+            // Wig coordinates are 1-based
+            Ok(StepDeclaration::Fixed {
+                chrom,
+                start: start.saturating_sub(1),
+                step: step.unwrap_or(span),
+                span,
+            })
+        }
+        _ => bail!("Unrecognized wig declaration: {}", line),
+    }
+}
+
+/// Parse all of the intervals in a wig file.
+///
+/// Returns (sequence name, interval) tuples in the order in which they appear in the file.
+pub fn parse_wig<R: BufRead>(reader: R) -> Result<Vec<(String, SignalInterval)>> {
+    let mut intervals = Vec::new();
+    let mut declaration = None;
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        if line.starts_with("variableStep") || line.starts_with("fixedStep") {
+            declaration = Some(parse_declaration(line)?);
+            continue;
+        }
+        let invalid_line = || format!("Invalid wig data line: {}", line);
+        match declaration.as_mut() {
+            Some(StepDeclaration::Variable { chrom, span }) => {
+                let (pos, value) =
+                    line.split_once(char::is_whitespace).with_context(invalid_line)?;
+                let start = pos.parse::<u64>().with_context(invalid_line)?.saturating_sub(1);
+                let value = value.trim().parse().with_context(invalid_line)?;
+                intervals
+                    .push((chrom.clone(), SignalInterval { start, end: start + *span, value }));
+            }
+            Some(StepDeclaration::Fixed { chrom, start, step, span }) => {
+                let value = line.parse().with_context(invalid_line)?;
+                intervals.push((
+                    chrom.clone(),
+                    SignalInterval { start: *start, end: *start + *span, value },
+                ));
+                *start += *step;
+            }
+            None => bail!("Wig data line precedes any step declaration: {}", line),
+        }
+    }
+    Ok(intervals)
+}
+
+/// Reader for signal values from a wig file.
+///
+/// Wig files can't be indexed so the file is parsed in full on initialization.
+#[derive(Debug)]
+pub struct WigReader {
+    pub path: PathBuf,
+    intervals: Vec<(String, SignalInterval)>,
+}
+
+impl WigReader {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let file = File::open(&path)
+            .with_context(|| format!("Failed to open wig file: {}", path.to_string_lossy()))?;
+        let intervals = parse_wig(BufReader::new(file))
+            .with_context(|| format!("Failed to parse wig file: {}", path.to_string_lossy()))?;
+        Ok(Self { path, intervals })
+    }
+
+    /// Read the intervals which overlap a region.
+    pub fn read(&self, region: &GenomicRegion) -> Vec<SignalInterval> {
+        self.intervals
+            .iter()
+            .filter(|(seq_name, interval)| {
+                *seq_name == region.seq_name
+                    && interval.start < region.end()
+                    && interval.end > region.start()
+            })
+            .map(|(_, interval)| interval.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_parse_wig() {
+        let wig = "track type=wiggle_0\n\
+                   variableStep chrom=mt span=5\n\
+                   11 2.0\n\
+                   fixedStep chrom=euk_genes start=101 step=10 span=5\n\
+                   1.0\n\
+                   3.0\n";
+        let intervals = parse_wig(wig.as_bytes()).unwrap();
+        assert_eq!(
+            intervals,
+            vec![
+                ("mt".to_owned(), SignalInterval { start: 10, end: 15, value: 2.0 }),
+                ("euk_genes".to_owned(), SignalInterval { start: 100, end: 105, value: 1.0 }),
+                ("euk_genes".to_owned(), SignalInterval { start: 110, end: 115, value: 3.0 }),
+            ]
+        );
+        assert!(parse_wig("1.0\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_read_wig() {
+        let reader = WigReader::new(get_test_data_path("fake-genome.wig")).unwrap();
+        let region = GenomicRegion::new("mt", 15, 35).unwrap();
+        assert_eq!(
+            reader.read(&region),
+            vec![
+                SignalInterval { start: 10, end: 20, value: 1.5 },
+                SignalInterval { start: 20, end: 30, value: 3.0 },
+                SignalInterval { start: 30, end: 40, value: 0.5 },
+            ]
+        );
+    }
+}
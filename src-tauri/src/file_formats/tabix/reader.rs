@@ -0,0 +1,94 @@
+//! Reader for arbitrary bgzipped, tabix-indexed interval files.
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use rust_htslib::tbx::{self, Read};
+
+use crate::alignments::alignment_reader::{AlignmentReader, SkippedRead, SkippedReads};
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::bio_util::sequence::SequenceView;
+use crate::file_formats::tabix::record::{IntervalColumns, IntervalRecord};
+
+/// Reads intervals from a tabix-indexed file (e.g a custom annotation dump).
+///
+/// Lines which can't be parsed using the column mapping are reported as skipped.
+#[derive(Debug)]
+pub struct TabixReader {
+    pub path: PathBuf,
+    pub columns: IntervalColumns,
+    reader: tbx::Reader,
+}
+
+impl TabixReader {
+    pub fn new<P: Into<PathBuf>>(path: P, columns: IntervalColumns) -> Result<Self> {
+        let path = path.into();
+        columns.validate()?;
+        let reader = tbx::Reader::from_path(&path).with_context(|| {
+            format!("Failed to open tabix-indexed file: {}", path.to_string_lossy())
+        })?;
+        Ok(Self { path, columns, reader })
+    }
+}
+
+impl AlignmentReader for TabixReader {
+    type Item = IntervalRecord;
+
+    fn read(
+        &mut self,
+        region: &GenomicRegion,
+        _refseq: &SequenceView,
+    ) -> Result<(Vec<Self::Item>, SkippedReads)> {
+        let mut records = Vec::new();
+        let mut skipped_reads = SkippedReads::default();
+        let tid = match self.reader.tid(&region.seq_name) {
+            Ok(tid) => tid,
+            // Sequences without any records are not present in the index
+            Err(_) => return Ok((records, skipped_reads)),
+        };
+        self.reader.fetch(tid, region.start(), region.end())?;
+        // Identical lines would otherwise produce duplicate ids
+        let mut id_counts: HashMap<String, usize> = HashMap::new();
+        for line in self.reader.records() {
+            let line = String::from_utf8_lossy(&line?).into_owned();
+            match self.columns.parse_line(&line) {
+                Ok((_, mut record)) => {
+                    let count = id_counts.entry(record.id.clone()).or_default();
+                    if *count > 0 {
+                        record.id = format!("{}#{}", record.id, count);
+                    }
+                    *count += 1;
+                    records.push(record);
+                }
+                Err(err) => {
+                    skipped_reads.push(SkippedRead { qname: line, reason: format!("{:#}", err) })
+                }
+            }
+        }
+        Ok((records, skipped_reads))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::alignments::alignment::Alignment;
+    use crate::file_formats::fasta::reader::FastaReader;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_read_tabix_intervals() {
+        let columns = IntervalColumns { seq_name: 1, start: 2, end: 3, name: Some(0) };
+        let path = get_test_data_path("fake-genome.annotations.tsv.gz");
+        let mut reader = TabixReader::new(path, columns).unwrap();
+        let mut fasta_reader = FastaReader::new(get_test_data_path("fake-genome.fa")).unwrap();
+        let region = GenomicRegion::new("euk_genes", 0, 500).unwrap();
+        let sequence_view = fasta_reader.read(&region).unwrap();
+        let (records, skipped_reads) = reader.read(&region, &sequence_view).unwrap();
+        let ids: Vec<&str> = records.iter().map(|record| record.id()).collect();
+        assert_eq!(ids, vec!["euk_genes:100-200/geneA", "euk_genes:150-400/geneB"]);
+        assert_eq!(skipped_reads.count, 0);
+    }
+}
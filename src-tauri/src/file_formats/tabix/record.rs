@@ -0,0 +1,115 @@
+//! Generic interval records from tab-delimited files.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::alignments::alignment::Alignment;
+use crate::bio_util::genomic_coordinates::GenomicInterval;
+use crate::impl_alignment;
+
+/// Mapping of the (0-based) columns in a tab-delimited file to interval fields.
+///
+/// Start/end coordinates are assumed to be 0-based, half-open (i.e BED-style).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntervalColumns {
+    pub seq_name: usize,
+    pub start: usize,
+    pub end: usize,
+    pub name: Option<usize>,
+}
+
+impl Default for IntervalColumns {
+    fn default() -> Self {
+        Self { seq_name: 0, start: 1, end: 2, name: Some(3) }
+    }
+}
+
+impl IntervalColumns {
+    pub fn validate(&self) -> Result<()> {
+        let mut columns = vec![self.seq_name, self.start, self.end];
+        columns.extend(self.name);
+        columns.sort();
+        columns.dedup();
+        if columns.len() != 3 + self.name.iter().count() {
+            bail!("Interval columns must be distinct: {:?}", self);
+        }
+        Ok(())
+    }
+
+    fn get<'a>(&self, fields: &[&'a str], column: usize, line: &str) -> Result<&'a str> {
+        fields
+            .get(column)
+            .copied()
+            .with_context(|| format!("Missing column {} in line: {}", column + 1, line))
+    }
+
+    /// Parse a single line from a tab-delimited file.
+    ///
+    /// Returns the sequence name along with the record.
+    pub fn parse_line(&self, line: &str) -> Result<(String, IntervalRecord)> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let seq_name = self.get(&fields, self.seq_name, line)?;
+        let parse_position = |column: usize| -> Result<u64> {
+            self.get(&fields, column, line)?
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid position in line: {}", line))
+        };
+        let interval =
+            GenomicInterval::new(parse_position(self.start)?, parse_position(self.end)?)?;
+        let name = match self.name {
+            Some(column) => Some(self.get(&fields, column, line)?.to_owned()),
+            None => None,
+        };
+        let id = format!(
+            "{}:{}-{}/{}",
+            seq_name,
+            interval.start,
+            interval.end,
+            name.as_deref().unwrap_or_default()
+        );
+        Ok((seq_name.to_owned(), IntervalRecord { id, interval, name }))
+    }
+}
+
+/// A single interval from a tab-delimited file.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename = "intervalRecordKind", rename_all = "camelCase", tag = "type")]
+pub struct IntervalRecord {
+    pub id: String,
+    pub interval: GenomicInterval,
+    pub name: Option<String>,
+}
+
+impl_alignment!(IntervalRecord);
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_line_with_custom_columns() {
+        let columns = IntervalColumns { seq_name: 1, start: 2, end: 3, name: Some(0) };
+        let (seq_name, record) = columns.parse_line("geneA\teuk_genes\t100\t200\t5").unwrap();
+        assert_eq!(seq_name, "euk_genes");
+        assert_eq!(
+            record,
+            IntervalRecord {
+                id: "euk_genes:100-200/geneA".to_owned(),
+                interval: GenomicInterval::new(100, 200).unwrap(),
+                name: Some("geneA".to_owned()),
+            }
+        );
+        assert!(columns.parse_line("geneA\teuk_genes\t100").is_err());
+        assert!(columns.parse_line("geneA\teuk_genes\tfoo\t200").is_err());
+    }
+
+    #[test]
+    fn test_validate_interval_columns() {
+        assert!(IntervalColumns::default().validate().is_ok());
+        let columns = IntervalColumns { seq_name: 0, start: 1, end: 2, name: Some(1) };
+        assert!(columns.validate().is_err());
+    }
+}
@@ -1,29 +1,342 @@
-use anyhow::Result;
-use parking_lot::RwLock;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
 
-use crate::interface::events::{EmitEvent, Event};
+use anyhow::{bail, Result};
+use parking_lot::{Mutex, RwLock};
+
+use crate::bio_util::contaminants::ContaminantDb;
+use crate::bio_util::contig_aliases::configure_contig_aliases;
+use crate::bio_util::coverage_alerts::CoverageRule;
+use crate::bio_util::genome_registry::{GenomeRegistry, RegistryGenomeStatus};
+use crate::bio_util::genomic_coordinates::{configure_numeric_coordinates, GenomicRegion};
+use crate::bio_util::refseq::{
+    get_default_reference, get_startup_reference, LastReference, ReferenceSequence,
+};
+use crate::file_formats::enums::{get_file_kind, FileKind};
+use crate::file_formats::fasta::index::{build_fasta_index, has_fasta_index};
+use crate::file_formats::fasta::reader::{get_fasta_paths, validate_reference_path};
+use crate::file_formats::remote::{
+    configure_host_auth, configure_remote_cache, get_gcs_token_path, get_remote_scheme, is_remote,
+    GcsTokenManager, RemoteScheme,
+};
+use crate::file_formats::sam_bam::coverage_pyramid::{BinnedCoverage, CoveragePyramidCache};
+use crate::file_formats::sam_bam::decompression_pool::configure_decompression_threads;
+use crate::file_formats::sam_bam::diff::configure_min_mismatch_base_quality;
+use crate::file_formats::sam_bam::pileup::configure_mismatch_highlight_threshold;
+use crate::interface::events::{
+    DeferredEventEmitter, EmitEvent, Event, IndexProgressPayload, IndexStatus,
+};
+use crate::interface::grid_actor::GridActor;
+use crate::interface::payload_encoding::{
+    configure_payload_compression_threshold, configure_payload_encoding,
+};
+use crate::interface::session::Session;
 use crate::interface::split_grid::SplitGrid;
-use crate::interface::user_config::{read_user_config, UserConfig};
+use crate::interface::track::TrackId;
+use crate::interface::user_config::{
+    get_default_user_config, read_user_config, ContaminantCheckConfig, HostAuthConfig, UserConfig,
+};
+use crate::paths::{get_coverage_cache_dir, get_genomes_dir, get_last_reference_path};
+
+/// Command line flag which starts the app in safe mode.
+pub const SAFE_MODE_ARG: &str = "--safe-mode";
+
+/// Check whether the app was launched with the safe mode flag.
+pub fn find_safe_mode_arg<I: IntoIterator<Item = OsString>>(args: I) -> bool {
+    args.into_iter().skip(1).any(|arg| arg == SAFE_MODE_ARG)
+}
+
+/// Read the user config which the app starts with (the default config in safe mode).
+pub fn read_startup_user_config(safe_mode: bool) -> Result<UserConfig> {
+    if safe_mode {
+        get_default_user_config()
+    } else {
+        read_user_config()
+    }
+}
+
+/// Build the coverage rules defined in the user config.
+///
+/// Rules which fail to load (e.g because their BED file is missing) are skipped rather than
+/// preventing the app from starting.
+fn get_coverage_rules(user_config: &UserConfig) -> Vec<CoverageRule> {
+    let mut coverage_rules = Vec::new();
+    for rule_config in user_config.coverage_rules.iter() {
+        let rule = CoverageRule::new(&rule_config.name, rule_config.min_depth);
+        let rule = match &rule_config.targets_path {
+            Some(targets_path) => rule.with_targets_from_bed(targets_path),
+            None => Ok(rule),
+        };
+        match rule {
+            Ok(rule) => coverage_rules.push(rule),
+            Err(err) => {
+                log::error!("Failed to load coverage rule {}: {:#}", rule_config.name, err)
+            }
+        }
+    }
+    coverage_rules
+}
+
+/// Apply the settings from the user config which are stored globally rather than in the backend.
+///
+/// S3/GCS settings are set in the environment once at startup instead (see set_remote_env_vars).
+fn configure_globals(user_config: &UserConfig) {
+    configure_remote_cache(&user_config.remote_cache);
+    if let Err(err) = configure_host_auth(&user_config.host_auth) {
+        log::error!("Failed to configure auth for remote hosts: {:#}", err);
+    }
+    configure_contig_aliases(&user_config.contig_aliases);
+    if let Err(err) = configure_decompression_threads(user_config.general.decompression_threads) {
+        log::error!("Failed to configure decompression threads: {:#}", err);
+    }
+    configure_mismatch_highlight_threshold(user_config.general.mismatch_highlight_threshold);
+    configure_min_mismatch_base_quality(user_config.general.min_mismatch_base_quality);
+    configure_payload_encoding(user_config.general.payload_encoding);
+    configure_payload_compression_threshold(user_config.general.payload_compression_threshold);
+    configure_numeric_coordinates(user_config.general.numeric_coordinates);
+}
+
+/// Apply the settings from the user config which aren't needed to construct the split grid.
+fn configure_split_grid(split_grid: &SplitGrid, user_config: &UserConfig) {
+    split_grid.set_coverage_rules(get_coverage_rules(user_config));
+    split_grid.set_target_alignments_per_window(user_config.general.target_alignments_per_window);
+}
 
 #[derive(Debug)]
 pub struct Backend {
-    pub split_grid: RwLock<SplitGrid>,
+    /// Operations which change the layout of the grid are sent to the actor's thread, other
+    /// operations use the grid directly.
+    pub split_grid: RwLock<GridActor>,
     pub user_config: RwLock<UserConfig>,
+    /// Session file which should be loaded when the frontend initializes the backend (e.g if the
+    /// app was launched by opening a session file).
+    startup_session: Mutex<Option<PathBuf>>,
+    /// Whether the frontend has initialized the backend. Only changed while the startup session is
+    /// locked, so that a session which the OS asks the app to open is either loaded on startup or
+    /// loaded straight away.
+    initialized: AtomicBool,
+    /// If set, the user config, last used reference and startup session are ignored so that the
+    /// app can still be opened if any of them are corrupt.
+    safe_mode: AtomicBool,
+    gcs_tokens: Arc<GcsTokenManager>,
+    pub genome_registry: GenomeRegistry,
+    /// File which the current reference is saved to on exit so that it can be restored on startup.
+    pub last_reference_path: PathBuf,
+    /// Binned coverage of SAM/BAM tracks, for regions too large to read alignments for.
+    pub coverage_pyramids: Arc<CoveragePyramidCache>,
+    /// Contaminant k-mers along with the config they were built from, so that they're only
+    /// rebuilt when the config changes.
+    contaminant_db: Mutex<Option<(ContaminantCheckConfig, Arc<ContaminantDb>)>>,
 }
 
 impl Backend {
     pub fn new() -> Result<Self> {
-        let user_config = RwLock::new(read_user_config()?);
-        let max_render_window = user_config.read().general.max_render_window;
-        let split_grid = RwLock::new(SplitGrid::new(max_render_window)?);
-        Ok(Self { user_config, split_grid })
+        Self::with_safe_mode(false)
+    }
+
+    /// Create the backend, optionally in safe mode.
+    ///
+    /// In safe mode the default user config and the default reference are used, and the startup
+    /// session isn't loaded.
+    pub fn with_safe_mode(safe_mode: bool) -> Result<Self> {
+        if safe_mode {
+            log::warn!("Starting in safe mode");
+        }
+        let user_config = RwLock::new(read_startup_user_config(safe_mode)?);
+        configure_globals(&user_config.read());
+        let gcs_tokens =
+            Arc::new(GcsTokenManager::new(&user_config.read().gcs, get_gcs_token_path()));
+        let last_reference_path = get_last_reference_path();
+        let split_grid = if safe_mode {
+            Self::get_default_split_grid(&user_config.read())?
+        } else {
+            Self::get_startup_split_grid(&user_config.read(), &last_reference_path)?
+        };
+        let split_grid = RwLock::new(GridActor::spawn(split_grid)?);
+        Ok(Self {
+            user_config,
+            split_grid,
+            startup_session: Mutex::new(None),
+            initialized: AtomicBool::new(false),
+            safe_mode: AtomicBool::new(safe_mode),
+            gcs_tokens,
+            genome_registry: GenomeRegistry::new(get_genomes_dir()),
+            last_reference_path,
+            coverage_pyramids: Arc::new(CoveragePyramidCache::new(get_coverage_cache_dir())),
+            contaminant_db: Mutex::new(None),
+        })
+    }
+
+    /// Build a split grid using the last used reference (or the default reference if there isn't
+    /// one).
+    fn get_startup_split_grid(
+        user_config: &UserConfig,
+        last_reference_path: &Path,
+    ) -> Result<SplitGrid> {
+        let general_config = &user_config.general;
+        let reference = get_startup_reference(last_reference_path)?
+            .with_preload(general_config.preload_reference);
+        let split_grid = SplitGrid::from_reference(reference, general_config.max_render_window)?;
+        configure_split_grid(&split_grid, user_config);
+        Ok(split_grid)
+    }
+
+    /// Build a split grid with a single split on the default reference, without preloading it.
+    fn get_default_split_grid(user_config: &UserConfig) -> Result<SplitGrid> {
+        let split_grid = SplitGrid::from_reference(
+            get_default_reference()?,
+            user_config.general.max_render_window,
+        )?;
+        configure_split_grid(&split_grid, user_config);
+        Ok(split_grid)
+    }
+
+    pub fn is_safe_mode(&self) -> bool {
+        self.safe_mode.load(Ordering::SeqCst)
+    }
+
+    /// Make sure that a file can be accessed before it's opened (e.g by fetching credentials).
+    fn prepare_file_access(&self, path: &Path) -> Result<()> {
+        match get_remote_scheme(path) {
+            Some(RemoteScheme::Gcs) => self.gcs_tokens.start(),
+            Some(RemoteScheme::S3 | RemoteScheme::Http | RemoteScheme::Https) | None => Ok(()),
+        }
+    }
+
+    /// Add a track to the split grid. The file may be local or remote.
+    pub fn add_track<E: EmitEvent + Send + 'static, P: Into<PathBuf>>(
+        &self,
+        event_emitter: E,
+        file_path: P,
+    ) -> Result<TrackId> {
+        let file_path = file_path.into();
+        self.prepare_file_access(&file_path)?;
+        let track_path = file_path.clone();
+        let track_id = self
+            .split_grid
+            .read()
+            .call(move |split_grid| split_grid.add_track(&event_emitter, &track_path))??;
+        self.prepare_coverage_pyramid(&file_path);
+        Ok(track_id)
+    }
+
+    /// Start computing the binned coverage of a local SAM/BAM file in the background if it isn't
+    /// already cached, so that it's ready by the time the user zooms out.
+    ///
+    /// Remote files are skipped since every alignment in the file would need to be downloaded.
+    fn prepare_coverage_pyramid(&self, file_path: &Path) {
+        if is_remote(file_path) || self.coverage_pyramids.contains(file_path) {
+            return;
+        }
+        match get_file_kind(file_path) {
+            Ok(FileKind::Bam | FileKind::Sam) => (),
+            _ => return,
+        }
+        let coverage_pyramids = self.coverage_pyramids.clone();
+        let file_path = file_path.to_owned();
+        thread::spawn(move || {
+            if let Err(err) = coverage_pyramids.get(&file_path) {
+                log::error!("Failed to compute coverage of {}: {:#}", file_path.display(), err);
+            }
+        });
+    }
+
+    /// Get the binned coverage of a SAM/BAM track across a region, with at most `max_bins` bins
+    /// (unless the region is larger than the coarsest bins).
+    ///
+    /// The coverage is computed from the whole file if it hasn't been cached yet, which may be
+    /// slow for large files.
+    pub fn get_binned_coverage(
+        &self,
+        track_id: &TrackId,
+        region: &GenomicRegion,
+        max_bins: usize,
+    ) -> Result<BinnedCoverage> {
+        let file_path = match self.split_grid.read().tracks.get(track_id) {
+            Some(track) => track.read().file_path().clone(),
+            None => bail!("Failed to find track for id={}", track_id),
+        };
+        if is_remote(&file_path) {
+            bail!("Binned coverage isn't available for remote files");
+        }
+        match get_file_kind(&file_path)? {
+            FileKind::Bam | FileKind::Sam => (),
+            _ => bail!("Binned coverage is only available for SAM/BAM tracks"),
+        }
+        self.coverage_pyramids.get(&file_path)?.get_binned_coverage(region, max_bins)
+    }
+
+    /// Get the contaminant database for the current contaminant check config.
+    ///
+    /// The database is cached, and only rebuilt (e.g re-reading the user's contaminant FASTA) once
+    /// the config changes.
+    pub fn get_contaminant_db(&self) -> Result<Arc<ContaminantDb>> {
+        let config = self.user_config.read().contaminant_check.clone();
+        let mut contaminant_db = self.contaminant_db.lock();
+        if let Some((cached_config, db)) = &*contaminant_db {
+            if *cached_config == config {
+                return Ok(Arc::clone(db));
+            }
+        }
+        let db = Arc::new(match &config.contaminants_path {
+            Some(path) => ContaminantDb::from_fasta(path, config.kmer_size)?,
+            None => ContaminantDb::bundled(config.kmer_size)?,
+        });
+        *contaminant_db = Some((config, Arc::clone(&db)));
+        Ok(db)
+    }
+
+    pub fn set_startup_session<P: Into<PathBuf>>(&self, path: P) {
+        *self.startup_session.lock() = Some(path.into());
+    }
+
+    /// Open a session file which the OS asked the app to open while it's running (e.g because the
+    /// user double-clicked it on macOS). If the backend hasn't been initialized yet, the session is
+    /// loaded once it is.
+    pub fn open_session_file<E: EmitEvent, P: Into<PathBuf>>(
+        &self,
+        event_emitter: &E,
+        path: P,
+    ) -> Result<()> {
+        let mut startup_session = self.startup_session.lock();
+        if !self.initialized.load(Ordering::SeqCst) {
+            *startup_session = Some(path.into());
+            return Ok(());
+        }
+        drop(startup_session);
+        self.load_session(event_emitter, path.into())
     }
 
     pub fn initialize<E: EmitEvent>(&self, event_emitter: &E) -> Result<()> {
         log::info!("Initializing backend");
-        let max_render_window = self.user_config.read().general.max_render_window;
-        *self.split_grid.write() = SplitGrid::new(max_render_window)?;
+        let split_grid = if self.is_safe_mode() {
+            Self::get_default_split_grid(&self.user_config.read())?
+        } else {
+            Self::get_startup_split_grid(&self.user_config.read(), &self.last_reference_path)?
+        };
+        *self.split_grid.write() = GridActor::spawn(split_grid)?;
         event_emitter.emit(Event::UserConfigUpdated, &*self.user_config.read())?;
+        let startup_session = {
+            let mut startup_session = self.startup_session.lock();
+            self.initialized.store(true, Ordering::SeqCst);
+            startup_session.take()
+        };
+        match startup_session {
+            Some(path) if self.is_safe_mode() => {
+                log::warn!("Skipping session {} in safe mode", path.to_string_lossy());
+            }
+            Some(path) => {
+                // Fall back to an empty grid rather than failing to start the app
+                if let Err(err) = self.load_session(event_emitter, &path) {
+                    log::error!("Failed to load session {}: {:#}", path.to_string_lossy(), err);
+                }
+            }
+            None => {}
+        }
         // let mut refseq = state.reference_sequence.write();
         // *refseq = get_default_reference()?;
         // let mut splits = state.splits.write();
@@ -40,4 +353,457 @@ impl Backend {
         log::info!("Backend initialization complete");
         Ok(())
     }
+    /// Reset the app to its default state, so that users can recover if their config, last used
+    /// reference or session is corrupt.
+    ///
+    /// The default user config is restored, the saved last used reference is deleted and the split
+    /// grid is replaced with a single split on the default reference. Safe mode is switched off
+    /// since the state which it avoids loading no longer exists.
+    pub fn recover_default_state<E: EmitEvent>(&self, event_emitter: &E) -> Result<()> {
+        log::info!("Recovering default state");
+        if self.last_reference_path.exists() {
+            fs::remove_file(&self.last_reference_path)?;
+        }
+        self.startup_session.lock().take();
+        let user_config = get_default_user_config()?;
+        configure_globals(&user_config);
+        let split_grid = Self::get_default_split_grid(&user_config)?;
+        *self.user_config.write() = user_config;
+        *self.split_grid.write() = GridActor::spawn(split_grid)?;
+        self.safe_mode.store(false, Ordering::SeqCst);
+        event_emitter.emit(Event::UserConfigUpdated, &*self.user_config.read())?;
+        let split_grid = self.split_grid.read();
+        event_emitter.emit(Event::RefSeqFileUpdated, &*split_grid.reference.read())?;
+        event_emitter.emit(Event::SplitGridCleared, ())?;
+        Ok(())
+    }
+
+    /// Replace the split grid with the state stored in a session file.
+    pub fn load_session<E: EmitEvent, P: AsRef<Path>>(
+        &self,
+        event_emitter: &E,
+        path: P,
+    ) -> Result<()> {
+        log::info!("Loading session from {}", path.as_ref().to_string_lossy());
+        let session = Session::read(path)?;
+        self.prepare_file_access(&session.reference.path)?;
+        for track in session.tracks.iter() {
+            self.prepare_file_access(&track.file_path)?;
+        }
+        let general_config = self.user_config.read().general.clone();
+        // The new grid's events are held back until it has replaced the current grid, so that the
+        // frontend doesn't look up its splits and tracks in the current grid
+        let grid_events = DeferredEventEmitter::new();
+        let split_grid = session.load(
+            &grid_events,
+            general_config.max_render_window,
+            general_config.preload_reference,
+        )?;
+        configure_split_grid(&split_grid, &self.user_config.read());
+        *self.split_grid.write() = GridActor::spawn(split_grid)?;
+        grid_events.release(event_emitter)?;
+        event_emitter.emit(Event::SessionLoaded, &session)?;
+        Ok(())
+    }
+
+    /// Replace the split grid with an empty grid using a new reference sequence.
+    ///
+    /// The reference may be a FASTA file or a directory of FASTA files. Any FASTA files which don't
+    /// already have an index are indexed first.
+    pub fn open_reference<E: EmitEvent, P: Into<PathBuf>>(
+        &self,
+        event_emitter: &E,
+        path: P,
+    ) -> Result<()> {
+        let path = path.into();
+        log::info!("Opening reference sequence {}", path.to_string_lossy());
+        self.prepare_file_access(&path)?;
+        for fasta_path in get_fasta_paths(&path)? {
+            // Remote references must already be indexed
+            if is_remote(&fasta_path) || has_fasta_index(&fasta_path) {
+                continue;
+            }
+            let emit_progress = |status| {
+                event_emitter.emit(
+                    Event::FastaIndexProgress,
+                    IndexProgressPayload { file_path: &fasta_path, status },
+                )
+            };
+            emit_progress(IndexStatus::Started)?;
+            if let Err(err) = build_fasta_index(&fasta_path) {
+                emit_progress(IndexStatus::Failed)?;
+                return Err(err);
+            }
+            emit_progress(IndexStatus::Finished)?;
+        }
+        let name = path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().to_string();
+        let general_config = self.user_config.read().general.clone();
+        let reference =
+            ReferenceSequence::new(name, &path)?.with_preload(general_config.preload_reference);
+        let split_grid = SplitGrid::from_reference(reference, general_config.max_render_window)?;
+        configure_split_grid(&split_grid, &self.user_config.read());
+        *self.split_grid.write() = GridActor::spawn(split_grid)?;
+        let split_grid = self.split_grid.read();
+        event_emitter.emit(Event::RefSeqFileUpdated, &*split_grid.reference.read())?;
+        event_emitter.emit(Event::SplitGridCleared, ())?;
+        Ok(())
+    }
+
+    /// Switch to a new reference sequence, keeping the tracks which are open.
+    ///
+    /// Splits are reset to the new reference's default region and tracks are re-read from their
+    /// files. Tracks which can no longer be opened are dropped.
+    pub fn load_reference<E: EmitEvent, P: Into<PathBuf>>(
+        &self,
+        event_emitter: &E,
+        path: P,
+    ) -> Result<()> {
+        let path = path.into();
+        validate_reference_path(&path)?;
+        let tracks = Session::from_split_grid(&self.split_grid.read())?.tracks;
+        self.open_reference(event_emitter, path)?;
+        let split_grid = self.split_grid.read();
+        for track in tracks {
+            let result = split_grid
+                .add_track_with_columns(event_emitter, &track.file_path, track.interval_columns)
+                .and_then(|track_id| {
+                    split_grid.update_track_customization(
+                        event_emitter,
+                        &track_id,
+                        track.customization,
+                    )
+                });
+            if let Err(err) = result {
+                log::error!("Failed to reload track {}: {:#}", track.file_path.display(), err);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn list_registry_genomes(&self) -> Vec<RegistryGenomeStatus> {
+        self.genome_registry.list_genomes()
+    }
+
+    /// Open a genome from the registry as the reference sequence, downloading it first if it
+    /// hasn't been downloaded before.
+    pub fn open_registry_genome<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        genome_id: &str,
+    ) -> Result<()> {
+        let fasta_path = self.genome_registry.download(event_emitter, genome_id)?;
+        self.open_reference(event_emitter, fasta_path)
+    }
+
+    /// Save the current reference and the region focused in it, to be restored on the next startup.
+    pub fn save_last_reference(&self) -> Result<()> {
+        let split_grid = self.split_grid.read();
+        let focused_split_id = split_grid.focus.read().split_id;
+        let focused_region = split_grid.get_split(&focused_split_id)?.read().focused_region.clone();
+        let reference = split_grid.reference.read();
+        let last_reference = LastReference {
+            name: reference.name.clone(),
+            path: reference.path.clone(),
+            focused_region: Some(focused_region),
+        };
+        log::info!("Saving last used reference to {}", self.last_reference_path.to_string_lossy());
+        last_reference.write(&self.last_reference_path)
+    }
+
+    /// Add or replace the headers which are sent with requests for remote files on a host.
+    ///
+    /// Applies to files which are opened afterwards.
+    pub fn set_host_auth<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        config: HostAuthConfig,
+    ) -> Result<()> {
+        let mut user_config = self.user_config.write();
+        let mut host_auth = user_config.host_auth.clone();
+        host_auth.retain(|existing| !existing.host.eq_ignore_ascii_case(config.host.trim()));
+        host_auth.push(config);
+        configure_host_auth(&host_auth)?;
+        user_config.host_auth = host_auth;
+        event_emitter.emit(Event::UserConfigUpdated, &*user_config)
+    }
+
+    /// Stop sending headers with requests for remote files on a host.
+    pub fn remove_host_auth<E: EmitEvent>(&self, event_emitter: &E, host: &str) -> Result<()> {
+        let mut user_config = self.user_config.write();
+        let mut host_auth = user_config.host_auth.clone();
+        host_auth.retain(|existing| !existing.host.eq_ignore_ascii_case(host.trim()));
+        configure_host_auth(&host_auth)?;
+        user_config.host_auth = host_auth;
+        event_emitter.emit(Event::UserConfigUpdated, &*user_config)
+    }
+
+    /// Save the current state of the split grid to a session file.
+    pub fn export_session<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        log::info!("Exporting session to {}", path.as_ref().to_string_lossy());
+        Session::from_split_grid(&self.split_grid.read())?.write(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::bio_util::genome_registry::RegistryGenome;
+    use crate::file_formats::fasta::index::get_fai_path;
+    use crate::interface::events::StubEventEmitter;
+    use crate::interface::track::TrackCustomization;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_set_host_auth() {
+        let backend = Backend::new().unwrap();
+        let event_emitter = StubEventEmitter::new();
+        let config = |host: &str, token: &str| HostAuthConfig {
+            host: host.to_owned(),
+            bearer_token: Some(token.to_owned()),
+            headers: Default::default(),
+        };
+        backend.set_host_auth(&event_emitter, config("auth-test.example.org", "a")).unwrap();
+        backend.set_host_auth(&event_emitter, config("AUTH-TEST.example.org", "b")).unwrap();
+        let host_auth = backend.user_config.read().host_auth.clone();
+        assert_eq!(host_auth.len(), 1);
+        assert_eq!(host_auth[0].bearer_token.as_deref(), Some("b"));
+        // Tokens aren't sent to the frontend
+        event_emitter.pop_until(&Event::UserConfigUpdated);
+        let payload = event_emitter.pop_until(&Event::UserConfigUpdated);
+        assert_eq!(payload["hostAuth"], serde_json::json!([{ "host": "AUTH-TEST.example.org" }]));
+
+        assert!(backend
+            .set_host_auth(&event_emitter, config("auth-test.example.org/", "c"))
+            .is_err());
+        assert_eq!(backend.user_config.read().host_auth.len(), 1);
+        backend.remove_host_auth(&event_emitter, "auth-test.example.org").unwrap();
+        assert!(backend.user_config.read().host_auth.is_empty());
+    }
+
+    #[test]
+    fn test_open_reference_builds_missing_index() {
+        let backend = Backend::new().unwrap();
+        let event_emitter = StubEventEmitter::new();
+        let fasta_path = std::env::temp_dir().join(format!("{}.fa", uuid::Uuid::new_v4()));
+        std::fs::copy(get_test_data_path("fake-genome.fa"), &fasta_path).unwrap();
+        backend.open_reference(&event_emitter, &fasta_path).unwrap();
+        let payload = event_emitter.pop_until(&Event::FastaIndexProgress);
+        assert_eq!(payload.get("status").unwrap(), "started");
+        let payload = event_emitter.pop_until(&Event::FastaIndexProgress);
+        assert_eq!(payload.get("status").unwrap(), "finished");
+        let split_grid = backend.split_grid.read();
+        assert_eq!(split_grid.reference.read().path, fasta_path);
+        assert_eq!(split_grid.reference.read().get_seq_length("mt").unwrap(), 16569);
+        std::fs::remove_file(&fasta_path).unwrap();
+        std::fs::remove_file(get_fai_path(&fasta_path)).unwrap();
+    }
+
+    #[test]
+    fn test_open_reference_directory() {
+        let backend = Backend::new().unwrap();
+        let event_emitter = StubEventEmitter::new();
+        let fasta_dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir(&fasta_dir).unwrap();
+        for filename in ["euk_genes.fa", "mt.fa"] {
+            let src_path = get_test_data_path("fake-genome-contigs").join(filename);
+            std::fs::copy(src_path, fasta_dir.join(filename)).unwrap();
+        }
+        backend.open_reference(&event_emitter, &fasta_dir).unwrap();
+        let payload = event_emitter.pop_until(&Event::FastaIndexProgress);
+        assert!(payload.get("filePath").unwrap().as_str().unwrap().ends_with("euk_genes.fa"));
+        let split_grid = backend.split_grid.read();
+        let reference = split_grid.reference.read();
+        assert_eq!(reference.get_seq_length("euk_genes").unwrap(), 7185);
+        assert_eq!(reference.get_seq_length("mt").unwrap(), 16569);
+        std::fs::remove_dir_all(&fasta_dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_registry_genome() {
+        let mut backend = Backend::new().unwrap();
+        let fasta_url = get_test_data_path("fake-genome.fa.gz").to_string_lossy().to_string();
+        let genome = RegistryGenome {
+            id: "fake".to_owned(),
+            name: "Fake genome".to_owned(),
+            species: "None".to_owned(),
+            fasta_url,
+        };
+        let genomes_dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        backend.genome_registry = GenomeRegistry::with_genomes(&genomes_dir, vec![genome]);
+        let event_emitter = StubEventEmitter::new();
+        assert!(backend.open_registry_genome(&event_emitter, "hg38").is_err());
+        backend.open_registry_genome(&event_emitter, "fake").unwrap();
+        let payload = event_emitter.pop_until(&Event::GenomeDownloadProgress);
+        assert_eq!(payload["genomeId"], "fake");
+        let payload = event_emitter.pop_until(&Event::RefSeqFileUpdated);
+        assert_eq!(payload["name"], "fake");
+        let split_grid = backend.split_grid.read();
+        assert_eq!(split_grid.reference.read().get_seq_length("mt").unwrap(), 16569);
+        assert!(backend.list_registry_genomes()[0].path.is_some());
+        std::fs::remove_dir_all(&genomes_dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_last_reference() {
+        let mut backend = Backend::new().unwrap();
+        backend.last_reference_path =
+            std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        let event_emitter = Arc::new(StubEventEmitter::new());
+        let fasta_path = get_test_data_path("fake-genome.fa.gz");
+        backend.open_reference(&event_emitter, &fasta_path).unwrap();
+        let split_grid = backend.split_grid.read().grid();
+        let split_id = split_grid.get_split_ids()[0];
+        let focused_region = GenomicRegion::new("mt", 500, 600).unwrap();
+        split_grid
+            .spawn_update_focused_region(
+                Arc::clone(&event_emitter),
+                split_id,
+                focused_region.clone(),
+            )
+            .unwrap();
+        event_emitter.wait_for(&Event::FocusedRegionLoaded);
+        drop(split_grid);
+        backend.save_last_reference().unwrap();
+
+        backend.initialize(&event_emitter).unwrap();
+        let split_grid = backend.split_grid.read();
+        assert_eq!(split_grid.reference.read().path, fasta_path);
+        let split_id = split_grid.get_split_ids()[0];
+        assert_eq!(split_grid.get_split(&split_id).unwrap().read().focused_region, focused_region);
+        std::fs::remove_file(&backend.last_reference_path).unwrap();
+    }
+
+    #[test]
+    fn test_find_safe_mode_arg() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        assert!(find_safe_mode_arg(args(&["gensketch", "--safe-mode"])));
+        assert!(!find_safe_mode_arg(args(&["gensketch", "session.gensketch"])));
+        assert!(!find_safe_mode_arg(args(&["--safe-mode"])));
+    }
+
+    #[test]
+    fn test_safe_mode_skips_startup_state() {
+        let mut backend = Backend::with_safe_mode(true).unwrap();
+        backend.last_reference_path =
+            std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        let event_emitter = StubEventEmitter::new();
+        backend.open_reference(&event_emitter, get_test_data_path("fake-genome.fa.gz")).unwrap();
+        backend.save_last_reference().unwrap();
+        backend.set_startup_session(get_test_data_path("missing.gensketch"));
+
+        backend.initialize(&event_emitter).unwrap();
+        let default_reference_path = get_default_reference().unwrap().path;
+        assert_eq!(backend.split_grid.read().reference.read().path, default_reference_path);
+        assert!(backend.startup_session.lock().is_none());
+        std::fs::remove_file(&backend.last_reference_path).unwrap();
+    }
+
+    #[test]
+    fn test_recover_default_state() {
+        let mut backend = Backend::with_safe_mode(true).unwrap();
+        backend.last_reference_path =
+            std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        let event_emitter = Arc::new(StubEventEmitter::new());
+        backend.open_reference(&event_emitter, get_test_data_path("fake-genome.fa.gz")).unwrap();
+        backend
+            .add_track(Arc::clone(&event_emitter), get_test_data_path("fake-genome.tiny.bam"))
+            .unwrap();
+        backend.save_last_reference().unwrap();
+        backend.user_config.write().general.max_render_window = 5;
+
+        backend.recover_default_state(&event_emitter).unwrap();
+        assert!(!backend.last_reference_path.exists());
+        assert!(!backend.is_safe_mode());
+        assert_eq!(backend.user_config.read().general.max_render_window, 10000);
+        let split_grid = backend.split_grid.read();
+        assert_eq!(split_grid.reference.read().path, get_default_reference().unwrap().path);
+        assert!(split_grid.tracks.is_empty());
+        assert_eq!(split_grid.get_split_ids().len(), 1);
+        drop(split_grid);
+        event_emitter.pop_until(&Event::UserConfigUpdated);
+        event_emitter.pop_until(&Event::RefSeqFileUpdated);
+        event_emitter.pop_until(&Event::SplitGridCleared);
+    }
+
+    #[test]
+    fn test_load_reference_keeps_tracks() {
+        let backend = Backend::new().unwrap();
+        let event_emitter = Arc::new(StubEventEmitter::new());
+        let bam_path = get_test_data_path("fake-genome.tiny.bam");
+        let track_id = backend.add_track(Arc::clone(&event_emitter), &bam_path).unwrap();
+        let customization =
+            TrackCustomization { label: Some("Tumor".to_owned()), ..Default::default() };
+        let split_grid = backend.split_grid.read();
+        split_grid.update_track_customization(&event_emitter, &track_id, customization).unwrap();
+        drop(split_grid);
+
+        let bam_error = backend.load_reference(&event_emitter, &bam_path);
+        assert!(bam_error.is_err());
+        assert!(backend.split_grid.read().tracks.contains_key(&track_id));
+
+        let fasta_path = get_test_data_path("fake-genome.fa.gz");
+        backend.load_reference(&event_emitter, &fasta_path).unwrap();
+        event_emitter.pop_until(&Event::RefSeqFileUpdated);
+        event_emitter.pop_until(&Event::SplitGridCleared);
+        event_emitter.pop_until(&Event::TrackAdded);
+        let split_grid = backend.split_grid.read();
+        assert_eq!(split_grid.reference.read().path, fasta_path);
+        assert_eq!(split_grid.tracks.len(), 1);
+        let track = split_grid.tracks.iter().next().unwrap();
+        assert_eq!(track.read().file_path(), &bam_path);
+        assert_eq!(track.read().customization().label.as_deref(), Some("Tumor"));
+    }
+
+    #[test]
+    fn test_open_reference_preloads_if_configured() {
+        let backend = Backend::new().unwrap();
+        let event_emitter = StubEventEmitter::new();
+        let fasta_path = get_test_data_path("fake-genome.fa");
+        backend.open_reference(&event_emitter, &fasta_path).unwrap();
+        assert!(!backend.split_grid.read().reference.read().is_preloaded());
+        backend.user_config.write().general.preload_reference = true;
+        backend.open_reference(&event_emitter, &fasta_path).unwrap();
+        assert!(backend.split_grid.read().reference.read().is_preloaded());
+    }
+
+    #[test]
+    fn test_get_binned_coverage() {
+        let mut backend = Backend::new().unwrap();
+        let cache_dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        backend.coverage_pyramids = Arc::new(CoveragePyramidCache::new(&cache_dir));
+        let event_emitter = StubEventEmitter::new();
+        let bam_path = get_test_data_path("fake-genome.reads.bam");
+        let track_id = backend.add_track(event_emitter, &bam_path).unwrap();
+        let region = GenomicRegion::new("mt", 0, 16569).unwrap();
+        let coverage = backend.get_binned_coverage(&track_id, &region, 100).unwrap();
+        assert_eq!(coverage.region, region);
+        assert_eq!(coverage.bin_size, 256);
+        assert!(coverage.depths.iter().any(|depth| *depth > 0.0));
+        assert!(backend.coverage_pyramids.contains(&bam_path));
+        let missing_track_id = TrackId::new();
+        assert!(backend.get_binned_coverage(&missing_track_id, &region, 100).is_err());
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_contaminant_db() {
+        let backend = Backend::new().unwrap();
+        let db = backend.get_contaminant_db().unwrap();
+        assert!(Arc::ptr_eq(&db, &backend.get_contaminant_db().unwrap()));
+        assert_eq!(db.check(b"AGATCGGAAGAGCACACGTCTG")[0].name, "Illumina TruSeq adapter (read 1)");
+
+        let fasta_path = std::env::temp_dir().join(format!("{}.fa", uuid::Uuid::new_v4()));
+        std::fs::write(&fasta_path, ">custom\nACGTTGCAACGTTGCAACGT\n").unwrap();
+        backend.user_config.write().contaminant_check.contaminants_path = Some(fasta_path.clone());
+        let db = backend.get_contaminant_db().unwrap();
+        assert_eq!(db.check(b"ACGTTGCAACGTTGCA")[0].name, "custom");
+        assert!(db.check(b"AGATCGGAAGAGCACACGTCTG").is_empty());
+        // The FASTA isn't read again until the config changes
+        std::fs::remove_file(&fasta_path).unwrap();
+        assert!(Arc::ptr_eq(&db, &backend.get_contaminant_db().unwrap()));
+        backend.user_config.write().contaminant_check.kmer_size = 10;
+        assert!(backend.get_contaminant_db().is_err());
+    }
 }
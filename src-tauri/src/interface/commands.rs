@@ -1,13 +1,30 @@
 /// Tauri commands to be called from the frontend
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
+use crate::alignments::field_mask::AlignmentFieldMask;
+use crate::alignments::sorting::SortBy;
+use crate::bio_util::genome_registry::RegistryGenomeStatus;
 use crate::bio_util::genomic_coordinates::GenomicRegion;
-use crate::errors::CommandResult;
+use crate::bio_util::pileup_comparison::{PileupComparison, MAX_PILEUP_COMPARISON_WINDOW};
+use crate::bio_util::sequence_logo::{SequenceLogo, MAX_SEQUENCE_LOGO_WINDOW};
+use crate::errors::{CommandError, CommandResult};
+use crate::file_formats::fasta::reader::validate_reference_path;
+use crate::file_formats::fastq::reader::{read_fastq, MAX_FASTQ_PAGE_SIZE};
+use crate::file_formats::remote::{parse_remote_url, validate_host_auth};
+use crate::file_formats::sam_bam::pileup::MAX_PILEUP_WINDOW;
+use crate::file_formats::sam_bam::read_lengths::ReadLengthScope;
+use crate::file_formats::sam_bam::reader::{ReadFilter, TagGroup, TagValues};
+use crate::file_formats::sam_bam::samples::ReadGroupField;
+use crate::file_formats::tabix::record::IntervalColumns;
 use crate::interface::backend::Backend;
 use crate::interface::events::{EventEmitter, FocusedSequenceUpdatedPayload};
-use crate::interface::split::SplitId;
+use crate::interface::schema::{set_emitted_schema_version, ApiSchemaVersion};
+use crate::interface::session::{is_session_file, SESSION_FILE_EXTENSION};
+use crate::interface::split::{SplitAnnotation, SplitId, SEQUENCE_CHUNK_LENGTH};
 use crate::interface::split_grid::GridCoord;
-use crate::interface::track::TrackId;
+use crate::interface::track::{TrackCustomization, TrackId};
+use crate::interface::user_config::HostAuthConfig;
 use crate::util::Direction;
 
 #[tauri::command(async)]
@@ -15,11 +32,86 @@ pub fn add_alignment_track(
     app: tauri::AppHandle,
     state: tauri::State<Backend>,
     file_path: PathBuf,
+    request_id: Option<String>,
 ) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app).with_request_id(request_id);
+    state.add_track(event_emitter, file_path)?;
+    Ok(())
+}
+#[tauri::command(async)]
+pub fn add_interval_track(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    file_path: PathBuf,
+    interval_columns: IntervalColumns,
+) -> CommandResult<()> {
+    if let Err(err) = interval_columns.validate() {
+        return Err(CommandError::ValidationError(format!("{:#}", err)));
+    }
     let event_emitter = EventEmitter::new(&app);
-    state.split_grid.read().add_track(&event_emitter, file_path)?;
+    state.split_grid.read().call(move |split_grid| {
+        split_grid.add_track_with_columns(&event_emitter, file_path, Some(interval_columns))
+    })??;
     Ok(())
 }
+
+#[tauri::command(async)]
+pub fn add_remote_track(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    url: String,
+    request_id: Option<String>,
+) -> CommandResult<()> {
+    if let Err(err) = parse_remote_url(&url) {
+        return Err(CommandError::ValidationError(format!("{:#}", err)));
+    }
+    let event_emitter = EventEmitter::new(&app).with_request_id(request_id);
+    state.add_track(event_emitter, url)?;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn set_host_auth(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    host: String,
+    bearer_token: Option<String>,
+    headers: BTreeMap<String, String>,
+) -> CommandResult<()> {
+    let config = HostAuthConfig { host, bearer_token, headers };
+    if let Err(err) = validate_host_auth(&config) {
+        return Err(CommandError::ValidationError(format!("{:#}", err)));
+    }
+    let event_emitter = EventEmitter::new(&app);
+    state.set_host_auth(&event_emitter, config)?;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn remove_host_auth(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    host: String,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state.remove_host_auth(&event_emitter, &host)?;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn get_api_schema_version() -> CommandResult<ApiSchemaVersion> {
+    Ok(ApiSchemaVersion::current())
+}
+
+#[tauri::command(async)]
+pub fn set_api_schema_version(schema_version: u32) -> CommandResult<ApiSchemaVersion> {
+    if let Err(err) = set_emitted_schema_version(schema_version) {
+        return Err(CommandError::ValidationError(format!("{:#}", err)));
+    }
+    log::info!("Emitting events with API schema version {}", schema_version);
+    Ok(ApiSchemaVersion::current())
+}
+
 #[tauri::command(async)]
 pub fn get_user_config(state: tauri::State<Backend>) -> CommandResult<serde_json::Value> {
     let user_config = serde_json::to_value(&*state.user_config.read())?;
@@ -49,27 +141,309 @@ pub fn get_focused_sequence(
         split_id: &split_id,
         focused_region: &split.read().focused_region,
         buffered_region: &split.read().buffered_region,
-        focused_sequence: &split.read().focused_sequence_as_string()?,
-        buffered_sequence: &split.read().buffered_sequence_as_string()?,
+        focused_sequence: &split.read().inline_focused_sequence_as_string()?,
+        buffered_sequence: &split.read().inline_buffered_sequence_as_string()?,
+        buffered_sequence_chunked: split.read().is_buffered_sequence_chunked(),
     };
     let json = serde_json::to_value(payload)?;
     Ok(json)
 }
 
+#[tauri::command(async)]
+pub fn get_sequence_chunk(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    region: GenomicRegion,
+) -> CommandResult<String> {
+    if region.len() > SEQUENCE_CHUNK_LENGTH {
+        return Err(CommandError::ValidationError(format!(
+            "Sequence chunks must be <= {}bp",
+            SEQUENCE_CHUNK_LENGTH
+        )));
+    }
+    let split_grid = state.split_grid.read();
+    let split = split_grid.get_split(&split_id)?;
+    let chunk = split.read().read_sequence_chunk(&region)?;
+    Ok(chunk)
+}
+
+#[tauri::command(async)]
+pub fn get_fastq_reads(
+    state: tauri::State<Backend>,
+    file_path: PathBuf,
+    offset: usize,
+    limit: usize,
+    align_to_split: Option<SplitId>,
+) -> CommandResult<serde_json::Value> {
+    if limit > MAX_FASTQ_PAGE_SIZE {
+        return Err(CommandError::ValidationError(format!(
+            "At most {} FASTQ reads can be fetched at once",
+            MAX_FASTQ_PAGE_SIZE
+        )));
+    }
+    let mut page = read_fastq(file_path)?.page(offset, limit);
+    if let Some(split_id) = align_to_split {
+        state.split_grid.read().align_to_focused_region(&split_id, &mut page.reads)?;
+    }
+    let json = serde_json::to_value(page)?;
+    Ok(json)
+}
+
 #[tauri::command(async)]
 pub fn get_reference_sequence(state: tauri::State<Backend>) -> CommandResult<serde_json::Value> {
     let json = serde_json::to_value(&*state.split_grid.read().reference.read())?;
     Ok(json)
 }
 
+#[tauri::command(async)]
+pub fn check_softclip_contaminants(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+) -> CommandResult<serde_json::Value> {
+    let db = state.get_contaminant_db()?;
+    let min_cluster_reads = state.user_config.read().contaminant_check.min_cluster_reads;
+    let clusters =
+        state.split_grid.read().get_softclip_clusters(&split_id, &track_id, min_cluster_reads)?;
+    let json = serde_json::to_value(db.check_softclips(clusters))?;
+    Ok(json)
+}
+
+#[tauri::command(async)]
+pub fn compare_split_pileups(
+    state: tauri::State<Backend>,
+    split_id_a: SplitId,
+    track_id_a: TrackId,
+    split_id_b: SplitId,
+    track_id_b: TrackId,
+) -> CommandResult<serde_json::Value> {
+    let split_grid = state.split_grid.read();
+    let region_a = split_grid.get_split(&split_id_a)?.read().focused_region.clone();
+    let region_b = split_grid.get_split(&split_id_b)?.read().focused_region.clone();
+    if region_a.len().max(region_b.len()) > MAX_PILEUP_COMPARISON_WINDOW {
+        return Err(CommandError::ValidationError(format!(
+            "Pileups can only be compared for regions <= {}bp",
+            MAX_PILEUP_COMPARISON_WINDOW
+        )));
+    }
+    let pileup_a = split_grid.get_pileup(&split_id_a, &track_id_a, &region_a)?;
+    let pileup_b = split_grid.get_pileup(&split_id_b, &track_id_b, &region_b)?;
+    let json = serde_json::to_value(PileupComparison::new(&pileup_a, &pileup_b))?;
+    Ok(json)
+}
+
+#[tauri::command(async)]
+pub fn export_session(state: tauri::State<Backend>, file_path: PathBuf) -> CommandResult<()> {
+    if !is_session_file(&file_path) {
+        return Err(CommandError::ValidationError(format!(
+            "Session files must have a .{} extension",
+            SESSION_FILE_EXTENSION
+        )));
+    }
+    state.export_session(file_path)?;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn focus_next_split(app: tauri::AppHandle, state: tauri::State<Backend>) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state.split_grid.read().call(move |split_grid| {
+        split_grid.focus_adjacent_split(&event_emitter, &Direction::Right)
+    })??;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn focus_previous_split(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state.split_grid.read().call(move |split_grid| {
+        split_grid.focus_adjacent_split(&event_emitter, &Direction::Left)
+    })??;
+    Ok(())
+}
+
 #[tauri::command(async)]
 pub fn get_alignments(
     state: tauri::State<Backend>,
     track_id: TrackId,
     split_id: SplitId,
+    field_mask: Option<AlignmentFieldMask>,
+    row_start: Option<usize>,
+    row_end: Option<usize>,
+) -> CommandResult<serde_json::Value> {
+    if let (Some(row_start), Some(row_end)) = (row_start, row_end) {
+        if row_start > row_end {
+            return Err(CommandError::ValidationError(format!(
+                "Invalid row range: {}-{} (start must not be after end)",
+                row_start, row_end
+            )));
+        }
+    }
+    let stack_reader = state.split_grid.read().get_stack_reader(&split_id, &track_id)?;
+    // A mask passed with the request only applies to this request, the track's mask is only
+    // changed by set_alignment_field_mask
+    let field_mask = field_mask.unwrap_or_else(|| stack_reader.field_mask());
+    let json = if row_start.is_none() && row_end.is_none() {
+        stack_reader.serialize_stack_masked(&field_mask)?
+    } else {
+        stack_reader.serialize_stack_rows_masked(&field_mask, row_start.unwrap_or(0), row_end)?
+    };
+    Ok(json)
+}
+
+#[tauri::command(async)]
+pub fn get_diff_labels(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+    nuc_width: f64,
+) -> CommandResult<serde_json::Value> {
+    if !nuc_width.is_finite() || nuc_width <= 0.0 {
+        return Err(CommandError::ValidationError(format!(
+            "Invalid base width {} (must be a positive number of pixels)",
+            nuc_width
+        )));
+    }
+    let labels = state.split_grid.read().get_diff_labels(&split_id, &track_id, nuc_width)?;
+    let json = serde_json::to_value(labels)?;
+    Ok(json)
+}
+
+#[tauri::command(async)]
+pub fn get_view_summary(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+) -> CommandResult<serde_json::Value> {
+    let summary = state.split_grid.read().get_view_summary(&split_id, &track_id)?;
+    let json = serde_json::to_value(summary)?;
+    Ok(json)
+}
+
+#[tauri::command(async)]
+pub fn get_coverage(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+) -> CommandResult<serde_json::Value> {
+    let coverage = state.split_grid.read().get_coverage(&split_id, &track_id)?;
+    let json = serde_json::to_value(coverage)?;
+    Ok(json)
+}
+
+#[tauri::command(async)]
+pub fn get_binned_coverage(
+    state: tauri::State<Backend>,
+    track_id: TrackId,
+    region: GenomicRegion,
+    max_bins: usize,
+) -> CommandResult<serde_json::Value> {
+    if max_bins == 0 {
+        return Err(CommandError::ValidationError("max_bins must be at least 1".to_owned()));
+    }
+    let coverage = state.get_binned_coverage(&track_id, &region, max_bins)?;
+    let json = serde_json::to_value(coverage)?;
+    Ok(json)
+}
+
+#[tauri::command(async)]
+pub fn get_read_length_histogram(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+    scope: ReadLengthScope,
+    num_bins: usize,
 ) -> CommandResult<serde_json::Value> {
-    let alignments = state.split_grid.read().get_stack_reader(&split_id, &track_id)?.read().stack();
-    let json = serde_json::to_value(&*alignments.read())?;
+    if num_bins == 0 {
+        return Err(CommandError::ValidationError("num_bins must be at least 1".to_owned()));
+    }
+    let histogram =
+        state.split_grid.read().get_read_length_histogram(&split_id, &track_id, scope, num_bins)?;
+    let json = serde_json::to_value(histogram)?;
+    Ok(json)
+}
+
+#[tauri::command(async)]
+pub fn get_unmapped_mate(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+    read_id: String,
+) -> CommandResult<serde_json::Value> {
+    let mate = state.split_grid.read().get_unmapped_mate(&split_id, &track_id, &read_id)?;
+    let json = serde_json::to_value(mate)?;
+    Ok(json)
+}
+
+#[tauri::command(async)]
+pub fn find_mate(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+    read_id: String,
+    jump: bool,
+    request_id: Option<String>,
+) -> CommandResult<Option<GenomicRegion>> {
+    let split_grid = state.split_grid.read().grid();
+    let mate_region = if jump {
+        let event_emitter = EventEmitter::new(&app).with_request_id(request_id);
+        split_grid.jump_to_mate(event_emitter, &split_id, &track_id, &read_id)?
+    } else {
+        split_grid.find_mate(&split_id, &track_id, &read_id)?
+    };
+    Ok(mate_region)
+}
+
+#[tauri::command(async)]
+pub fn get_read_details(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+    read_id: String,
+) -> CommandResult<serde_json::Value> {
+    let details = state.split_grid.read().get_read_details(&split_id, &track_id, &read_id)?;
+    let json = serde_json::to_value(details)?;
+    Ok(json)
+}
+
+#[tauri::command(async)]
+pub fn get_insertion_sequence(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+    read_id: String,
+    position: String,
+) -> CommandResult<String> {
+    let position: u64 = match position.parse() {
+        Ok(position) => position,
+        Err(_) => {
+            return Err(CommandError::ValidationError(format!("Invalid position: {}", position)))
+        }
+    };
+    let sequence =
+        state.split_grid.read().get_insertion_sequence(&split_id, &track_id, &read_id, position)?;
+    Ok(sequence)
+}
+
+#[tauri::command(async)]
+pub fn get_reads_at(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+    position: String,
+) -> CommandResult<serde_json::Value> {
+    let position: u64 = match position.parse() {
+        Ok(position) => position,
+        Err(_) => {
+            return Err(CommandError::ValidationError(format!("Invalid position: {}", position)))
+        }
+    };
+    let reads = state.split_grid.read().get_reads_at(&split_id, &track_id, position)?;
+    let json = serde_json::to_value(reads)?;
     Ok(json)
 }
 
@@ -79,6 +453,42 @@ pub fn get_grid_focus(state: tauri::State<Backend>) -> CommandResult<serde_json:
     Ok(json)
 }
 
+#[tauri::command(async)]
+pub fn get_pileup(
+    state: tauri::State<Backend>,
+    track_id: TrackId,
+    split_id: SplitId,
+    region: GenomicRegion,
+) -> CommandResult<serde_json::Value> {
+    if region.len() > MAX_PILEUP_WINDOW {
+        return Err(CommandError::ValidationError(format!(
+            "Pileups can only be computed for regions <= {}bp",
+            MAX_PILEUP_WINDOW
+        )));
+    }
+    let pileup = state.split_grid.read().get_or_read_pileup(&split_id, &track_id, &region)?;
+    let json = serde_json::to_value(pileup)?;
+    Ok(json)
+}
+
+#[tauri::command(async)]
+pub fn get_sequence_logo(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+    genomic_region: GenomicRegion,
+) -> CommandResult<serde_json::Value> {
+    if genomic_region.len() > MAX_SEQUENCE_LOGO_WINDOW {
+        return Err(CommandError::ValidationError(format!(
+            "Sequence logos can only be computed for regions <= {}bp",
+            MAX_SEQUENCE_LOGO_WINDOW
+        )));
+    }
+    let pileup = state.split_grid.read().get_pileup(&split_id, &track_id, &genomic_region)?;
+    let json = serde_json::to_value(SequenceLogo::from(&pileup))?;
+    Ok(json)
+}
+
 #[tauri::command(async)]
 pub fn get_splits(state: tauri::State<Backend>) -> CommandResult<serde_json::Value> {
     let json = serde_json::to_value(&state.split_grid.read().splits)?;
@@ -97,10 +507,77 @@ pub fn add_split(
     app: tauri::AppHandle,
     state: tauri::State<Backend>,
     focused_region: Option<GenomicRegion>,
+    request_id: Option<String>,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app).with_request_id(request_id);
+    state
+        .split_grid
+        .read()
+        .call(move |split_grid| split_grid.add_split(&event_emitter, focused_region))??;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn open_session(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    file_path: PathBuf,
 ) -> CommandResult<()> {
     let event_emitter = EventEmitter::new(&app);
-    let split_grid = state.split_grid.read();
-    split_grid.add_split(&event_emitter, focused_region)?;
+    state.load_session(&event_emitter, file_path)?;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn open_reference(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    file_path: PathBuf,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state.open_reference(&event_emitter, file_path)?;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn recover_default_state(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state.recover_default_state(&event_emitter)?;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn list_registry_genomes(
+    state: tauri::State<Backend>,
+) -> CommandResult<Vec<RegistryGenomeStatus>> {
+    Ok(state.list_registry_genomes())
+}
+
+#[tauri::command(async)]
+pub fn select_registry_genome(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    genome_id: String,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state.open_registry_genome(&event_emitter, &genome_id)?;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn load_reference(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    file_path: PathBuf,
+) -> CommandResult<()> {
+    if let Err(err) = validate_reference_path(&file_path) {
+        return Err(CommandError::ValidationError(format!("{:#}", err)));
+    }
+    let event_emitter = EventEmitter::new(&app);
+    state.load_reference(&event_emitter, file_path)?;
     Ok(())
 }
 
@@ -109,9 +586,12 @@ pub fn pan_focused_split(
     app: tauri::AppHandle,
     state: tauri::State<Backend>,
     direction: Direction,
+    request_id: Option<String>,
 ) -> CommandResult<()> {
-    let event_emitter = EventEmitter::new(&app);
-    state.split_grid.read().pan_focused_split(&event_emitter, &direction)?;
+    let event_emitter = EventEmitter::new(&app).with_request_id(request_id);
+    let contig_rollover = state.user_config.read().general.contig_rollover;
+    let split_grid = state.split_grid.read().grid();
+    split_grid.pan_focused_split(event_emitter, &direction, contig_rollover)?;
     Ok(())
 }
 
@@ -121,9 +601,12 @@ pub fn update_focused_region(
     state: tauri::State<Backend>,
     split_id: SplitId,
     genomic_region: GenomicRegion,
+    request_id: Option<String>,
 ) -> CommandResult<()> {
-    let event_emitter = EventEmitter::new(&app);
-    Ok(state.split_grid.read().update_focused_region(&event_emitter, &split_id, genomic_region)?)
+    let event_emitter = EventEmitter::new(&app).with_request_id(request_id);
+    let split_grid = state.split_grid.read().grid();
+    split_grid.spawn_update_focused_region(event_emitter, split_id, genomic_region)?;
+    Ok(())
 }
 
 #[tauri::command(async)]
@@ -133,6 +616,181 @@ pub fn update_grid_focus(
     grid_coord: GridCoord,
 ) -> CommandResult<()> {
     let event_emitter = EventEmitter::new(&app);
-    state.split_grid.read().update_grid_focus(&event_emitter, grid_coord)?;
+    state
+        .split_grid
+        .read()
+        .call(move |split_grid| split_grid.update_grid_focus(&event_emitter, grid_coord))??;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn load_sequencing_summary(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    track_id: TrackId,
+    file_path: PathBuf,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state.split_grid.read().load(move |split_grid| {
+        split_grid.load_sequencing_summary(&event_emitter, &track_id, file_path)
+    })??;
     Ok(())
 }
+
+#[tauri::command(async)]
+pub fn set_alignment_field_mask(
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+    field_mask: AlignmentFieldMask,
+) -> CommandResult<()> {
+    state.split_grid.read().call(move |split_grid| {
+        split_grid.set_alignment_field_mask(&split_id, &track_id, field_mask)
+    })??;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn update_split_annotation(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    annotation: SplitAnnotation,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state.split_grid.read().call(move |split_grid| {
+        split_grid.update_split_annotation(&event_emitter, &split_id, annotation)
+    })??;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn update_track_customization(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    track_id: TrackId,
+    customization: TrackCustomization,
+    request_id: Option<String>,
+) -> CommandResult<()> {
+    customization.validate().map_err(|err| CommandError::ValidationError(err.to_string()))?;
+    let event_emitter = EventEmitter::new(&app).with_request_id(request_id);
+    state.split_grid.read().load(move |split_grid| {
+        split_grid.update_track_customization(&event_emitter, &track_id, customization)
+    })??;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn set_track_filter(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    track_id: TrackId,
+    filter: ReadFilter,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state
+        .split_grid
+        .read()
+        .load(move |split_grid| split_grid.set_track_filter(&event_emitter, &track_id, filter))??;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn set_hide_duplicates(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    track_id: TrackId,
+    hide_duplicates: bool,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state.split_grid.read().load(move |split_grid| {
+        split_grid.set_hide_duplicates(&event_emitter, &track_id, hide_duplicates)
+    })??;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn set_hide_secondary(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    track_id: TrackId,
+    hide_secondary: bool,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state.split_grid.read().load(move |split_grid| {
+        split_grid.set_hide_secondary(&event_emitter, &track_id, hide_secondary)
+    })??;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn set_hide_supplementary(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    track_id: TrackId,
+    hide_supplementary: bool,
+) -> CommandResult<()> {
+    let event_emitter = EventEmitter::new(&app);
+    state.split_grid.read().load(move |split_grid| {
+        split_grid.set_hide_supplementary(&event_emitter, &track_id, hide_supplementary)
+    })??;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn sort_alignments(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    split_id: SplitId,
+    track_id: TrackId,
+    position: String,
+    by: SortBy,
+) -> CommandResult<()> {
+    // Positions are sent as strings since they're u64s
+    let position: u64 = match position.parse() {
+        Ok(position) => position,
+        Err(_) => {
+            return Err(CommandError::ValidationError(format!("Invalid position: {}", position)))
+        }
+    };
+    let event_emitter = EventEmitter::new(&app);
+    state.split_grid.read().load(move |split_grid| {
+        split_grid.sort_alignments(&event_emitter, &split_id, &track_id, position, by)
+    })??;
+    Ok(())
+}
+
+#[tauri::command(async)]
+pub fn split_track_by_read_group(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    track_id: TrackId,
+    group_by: ReadGroupField,
+) -> CommandResult<Vec<TrackId>> {
+    let event_emitter = EventEmitter::new(&app);
+    let track_ids = state.split_grid.read().call(move |split_grid| {
+        split_grid.split_track_by_read_group(&event_emitter, &track_id, group_by)
+    })??;
+    Ok(track_ids)
+}
+
+#[tauri::command(async)]
+pub fn split_track_by_tag(
+    app: tauri::AppHandle,
+    state: tauri::State<Backend>,
+    track_id: TrackId,
+    tag: String,
+    groups: Vec<TagGroup>,
+) -> CommandResult<Vec<TrackId>> {
+    for group in groups.iter() {
+        let tag_values = TagValues { tag: tag.clone(), values: group.values.clone() };
+        if let Err(err) = tag_values.validate() {
+            return Err(CommandError::ValidationError(format!("{:#}", err)));
+        }
+    }
+    let event_emitter = EventEmitter::new(&app);
+    let track_ids = state.split_grid.read().call(move |split_grid| {
+        split_grid.split_track_by_tag(&event_emitter, &track_id, &tag, groups)
+    })??;
+    Ok(track_ids)
+}
@@ -1,14 +1,29 @@
 use std::collections::VecDeque;
 use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use parking_lot::Mutex;
 use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
 use tauri::{AppHandle, Manager};
 
+use crate::alignments::alignment_reader::SkippedReads;
+use crate::alignments::downsampling::Downsampling;
+use crate::bio_util::coverage_alerts::QualityAlert;
+use crate::bio_util::formatting::{format_length, format_region};
 use crate::bio_util::genomic_coordinates::GenomicRegion;
-use crate::file_formats::enums::AlignmentStackKind;
-use crate::interface::split::SplitId;
+use crate::bio_util::reference_check::ContigMismatch;
+use crate::file_formats::sam_bam::coverage_pyramid::BinnedCoverage;
+use crate::file_formats::sam_bam::pileup::{Coverage, MismatchHighlight};
+use crate::file_formats::sam_bam::splice_junctions::SpliceJunction;
+use crate::file_formats::signal::bins::SignalBins;
+use crate::interface::payload_encoding::PayloadEncoding;
+use crate::interface::schema::{get_emitted_schema_version, SchemaPayload, API_SCHEMA_VERSION};
+use crate::interface::split::{SplitId, SplitOperation};
 use crate::interface::track::TrackId;
 use crate::util::same_enum_variant;
 
@@ -16,60 +31,113 @@ use crate::util::same_enum_variant;
 const MAX_LOGGED_EVENT_LEN: usize = 1000;
 
 pub enum Event {
+    AlignmentsAppended,
     AlignmentsUpdated,
     AlignmentsUpdateQueued,
+    BamIndexProgress,
+    ContigChanged,
+    CoverageUpdated,
+    FastaIndexProgress,
     RegionPanned,
     RegionZoomed,
     RegionBuffering,
+    FocusedRegionLoaded,
     FocusedRegionUpdated,
     FocusedSequenceUpdated,
     FocusedSequenceUpdateQueued,
+    FocusedSplitUpdated,
+    GenomeDownloadProgress,
     GridFocusUpdated,
+    QualityAlert,
+    ReadsDownsampled,
+    ReferenceMismatch,
     RefSeqFileUpdated,
+    SessionLoaded,
+    SignalUpdated,
     SplitAdded,
+    SplitBusy,
     SplitGridCleared,
+    SplitIdle,
+    SplitUpdated,
     TrackAdded,
+    TrackUpdated,
     UserConfigUpdated,
 }
 
 impl fmt::Display for Event {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Event::AlignmentsAppended => write!(f, "alignments-appended"),
             Event::AlignmentsUpdated => write!(f, "alignments-updated"),
             Event::AlignmentsUpdateQueued => write!(f, "alignments-update-queued"),
+            Event::BamIndexProgress => write!(f, "bam-index-progress"),
+            Event::ContigChanged => write!(f, "contig-changed"),
+            Event::CoverageUpdated => write!(f, "coverage-updated"),
+            Event::FastaIndexProgress => write!(f, "fasta-index-progress"),
             Event::RegionZoomed => write!(f, "region-zoomed"),
             Event::RegionPanned => write!(f, "region-panned"),
             Event::RegionBuffering => write!(f, "region-buffering"),
+            Event::FocusedRegionLoaded => write!(f, "focused-region-loaded"),
             Event::FocusedRegionUpdated => write!(f, "focused-region-updated"),
             Event::FocusedSequenceUpdated => write!(f, "focused-sequence-updated"),
             Event::FocusedSequenceUpdateQueued => write!(f, "focused-sequence-update-queued"),
+            Event::FocusedSplitUpdated => write!(f, "focused-split-updated"),
+            Event::GenomeDownloadProgress => write!(f, "genome-download-progress"),
             Event::GridFocusUpdated => write!(f, "grid-focus-updated"),
+            Event::QualityAlert => write!(f, "quality-alert"),
+            Event::ReadsDownsampled => write!(f, "reads-downsampled"),
+            Event::ReferenceMismatch => write!(f, "reference-mismatch"),
             Event::RefSeqFileUpdated => write!(f, "ref-seq-file-updated"),
+            Event::SessionLoaded => write!(f, "session-loaded"),
+            Event::SignalUpdated => write!(f, "signal-updated"),
             Event::SplitAdded => write!(f, "split-added"),
+            Event::SplitBusy => write!(f, "split-busy"),
             Event::SplitGridCleared => write!(f, "split-grid-cleared"),
+            Event::SplitIdle => write!(f, "split-idle"),
+            Event::SplitUpdated => write!(f, "split-updated"),
             Event::TrackAdded => write!(f, "track-added"),
+            Event::TrackUpdated => write!(f, "track-updated"),
             Event::UserConfigUpdated => write!(f, "user-config-updated"),
         }
     }
 }
 
 pub trait EmitEvent {
+    /// Emit an event to the frontend. Payloads are converted to the negotiated schema version (see
+    /// `schema`).
     fn emit<S: Serialize + Clone>(&self, event: Event, payload: S) -> Result<()>;
 }
 
-pub struct EventEmitter<'a> {
-    app: &'a AppHandle,
+impl<E: EmitEvent> EmitEvent for Arc<E> {
+    fn emit<S: Serialize + Clone>(&self, event: Event, payload: S) -> Result<()> {
+        (**self).emit(event, payload)
+    }
+}
+
+/// Emits events to every window of the app. Owns a handle to the app so that events can be
+/// emitted from background threads after the command which created it has returned.
+pub struct EventEmitter {
+    app: AppHandle,
+    /// Id sent by the frontend with the command being run, which is echoed in every event.
+    request_id: Option<String>,
 }
 
-impl<'a> EventEmitter<'a> {
-    pub fn new(app: &'a AppHandle) -> Self {
-        Self { app }
+impl EventEmitter {
+    pub fn new(app: &AppHandle) -> Self {
+        Self { app: app.clone(), request_id: None }
+    }
+
+    pub fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
     }
 }
 
-impl<'a> EmitEvent for EventEmitter<'a> {
+impl EmitEvent for EventEmitter {
     fn emit<S: Serialize + Clone>(&self, event: Event, payload: S) -> Result<()> {
         let event_name = event.to_string();
+        let payload = SchemaPayload::new(&payload, get_emitted_schema_version())
+            .with_request_id(self.request_id.as_deref());
         self.app.emit_all(&event_name, &payload)?;
         if cfg!(debug_assertions) {
             let mut json = serde_json::to_string(&payload)?;
@@ -83,6 +151,34 @@ impl<'a> EmitEvent for EventEmitter<'a> {
     }
 }
 
+/// Holds events back until they're released, e.g so that the events of a split grid which is being
+/// built are only emitted once it has replaced the current grid.
+#[derive(Default)]
+pub struct DeferredEventEmitter {
+    events: Mutex<Vec<(Event, serde_json::Value)>>,
+}
+
+impl DeferredEventEmitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Emit the events which were held back, in the order in which they were emitted.
+    pub fn release<E: EmitEvent>(self, event_emitter: &E) -> Result<()> {
+        for (event, payload) in self.events.into_inner() {
+            event_emitter.emit(event, payload)?;
+        }
+        Ok(())
+    }
+}
+
+impl EmitEvent for DeferredEventEmitter {
+    fn emit<S: Serialize + Clone>(&self, event: Event, payload: S) -> Result<()> {
+        self.events.lock().push((event, serde_json::to_value(&payload)?));
+        Ok(())
+    }
+}
+
 fn parse_object(json: serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
     if let serde_json::value::Value::Object(payload) = json {
         payload
@@ -94,11 +190,17 @@ fn parse_object(json: serde_json::Value) -> serde_json::Map<String, serde_json::
 #[derive(Default)]
 pub struct StubEventEmitter {
     pub calls: Mutex<VecDeque<(Event, serde_json::Value)>>,
+    request_id: Option<String>,
 }
 
 impl StubEventEmitter {
     pub fn new() -> Self {
-        Self { calls: Mutex::new(VecDeque::new()) }
+        Self { calls: Mutex::new(VecDeque::new()), request_id: None }
+    }
+
+    pub fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
     }
 
     pub fn pop_event(&self, event_type: &Event) -> serde_json::Map<String, serde_json::Value> {
@@ -115,10 +217,24 @@ impl StubEventEmitter {
             }
         }
     }
+
+    /// Wait until an event is emitted (e.g by a background load), without removing any events.
+    pub fn wait_for(&self, event_type: &Event) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let is_emitted = |calls: &VecDeque<(Event, serde_json::Value)>| {
+            calls.iter().any(|(event, _)| same_enum_variant(event, event_type))
+        };
+        while !is_emitted(&self.calls.lock()) {
+            assert!(Instant::now() < deadline, "Timed out waiting for {} event", event_type);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
 }
 
 impl EmitEvent for StubEventEmitter {
     fn emit<S: Serialize + Clone>(&self, event: Event, payload: S) -> Result<()> {
+        let payload = SchemaPayload::new(&payload, API_SCHEMA_VERSION)
+            .with_request_id(self.request_id.as_deref());
         self.calls.lock().push_back((event, serde_json::to_value(&payload)?));
         Ok(())
     }
@@ -129,6 +245,67 @@ impl EmitEvent for StubEventEmitter {
 pub struct FocusedRegionUpdatedPayload<'a> {
     pub split_id: &'a SplitId,
     pub genomic_region: &'a GenomicRegion,
+    /// Human-friendly genomic region (e.g chr1:1,001-2,000)
+    pub display_region: String,
+    /// Human-friendly length of the genomic region (e.g 1 kb)
+    pub display_length: String,
+}
+
+impl<'a> FocusedRegionUpdatedPayload<'a> {
+    pub fn new(split_id: &'a SplitId, genomic_region: &'a GenomicRegion) -> Self {
+        Self {
+            split_id,
+            genomic_region,
+            display_region: format_region(genomic_region),
+            display_length: format_length(genomic_region.len()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContigChangedPayload<'a> {
+    pub split_id: &'a SplitId,
+    pub previous_seq_name: &'a str,
+    pub seq_name: &'a str,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IndexStatus {
+    Started,
+    Finished,
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexProgressPayload<'a> {
+    pub file_path: &'a PathBuf,
+    pub status: IndexStatus,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GenomeDownloadStatus {
+    Started,
+    Downloading,
+    Indexing,
+    Finished,
+    Failed,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenomeDownloadProgressPayload<'a> {
+    pub genome_id: &'a str,
+    pub status: GenomeDownloadStatus,
+    #[serde_as(as = "DisplayFromStr")]
+    pub downloaded_bytes: u64,
+    /// Size of the (possibly compressed) file being downloaded, if the server reports it.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub total_bytes: Option<u64>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -137,13 +314,121 @@ pub struct RegionBufferingPayload<'a> {
     pub split_id: &'a SplitId,
 }
 
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitBusyPayload<'a> {
+    pub split_id: &'a SplitId,
+    pub operation: &'a SplitOperation,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitIdlePayload<'a> {
+    pub split_id: &'a SplitId,
+}
+
+/// Sent once the alignments of a focused region update which ran in the background are loaded.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusedRegionLoadedPayload<'a> {
+    pub split_id: &'a SplitId,
+    /// Message of the error which the load failed with, if it did.
+    pub error: Option<String>,
+}
+
+#[serde_as]
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AlignmentsUpdatedPayload<'a> {
     pub split_id: &'a SplitId,
     pub track_id: &'a TrackId,
     pub focused_region: &'a GenomicRegion,
-    pub alignments: &'a AlignmentStackKind,
+    /// Serialized stack, encoded as a string unless the encoding is JSON.
+    pub alignments: &'a serde_json::Value,
+    pub encoding: PayloadEncoding,
+    /// Whether the encoded stack was gzip compressed (and then sent as base64).
+    pub compressed: bool,
+    pub skipped_reads: &'a SkippedReads,
+    /// Render window tuned to the track's read density (None if render windows aren't tuned).
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub render_window: Option<u64>,
+    /// Read depth sampled across the region if it's too large for alignments to be loaded.
+    pub binned_coverage: Option<&'a BinnedCoverage>,
+    /// Positions with a high fraction of mismatched bases (i.e likely SNVs).
+    pub mismatch_highlights: &'a [MismatchHighlight],
+    /// Splice junctions of the stacked reads, with the number of reads spanning each.
+    pub splice_junctions: &'a [SpliceJunction],
+}
+
+/// Sent in place of AlignmentsUpdatedPayload when the buffered region is extended, with only the
+/// alignments which were added to and removed from the stack.
+#[serde_as]
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlignmentsAppendedPayload<'a> {
+    pub split_id: &'a SplitId,
+    pub track_id: &'a TrackId,
+    pub focused_region: &'a GenomicRegion,
+    pub delta: &'a serde_json::Value,
+    pub skipped_reads: &'a SkippedReads,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub render_window: Option<u64>,
+    pub mismatch_highlights: &'a [MismatchHighlight],
+    pub splice_junctions: &'a [SpliceJunction],
+}
+
+/// Read depth across the buffered region of a track/split, sent whenever its alignments are
+/// reloaded.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageUpdatedPayload<'a> {
+    pub split_id: &'a SplitId,
+    pub track_id: &'a TrackId,
+    pub coverage: &'a Coverage,
+}
+
+/// Signal values across the buffered region of a bedGraph/wig track/split, sent whenever they're
+/// reloaded.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignalUpdatedPayload<'a> {
+    pub split_id: &'a SplitId,
+    pub track_id: &'a TrackId,
+    pub bins: &'a SignalBins,
+}
+
+/// Sent when reads in a track/split's buffered region were dropped because they exceeded the
+/// track's maximum depth.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadsDownsampledPayload<'a> {
+    pub split_id: &'a SplitId,
+    pub track_id: &'a TrackId,
+    #[serde(flatten)]
+    pub downsampling: &'a Downsampling,
+    pub sampled_fraction: f64,
+}
+
+/// Result of checking the coverage rules against a track/split's focused region.
+///
+/// Sent after every check (with no alerts if all rules passed) so that stale alerts can be cleared.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityAlertPayload<'a> {
+    pub split_id: &'a SplitId,
+    pub track_id: &'a TrackId,
+    pub focused_region: &'a GenomicRegion,
+    pub alerts: &'a [QualityAlert],
+}
+
+/// Sent when a track's file lists sequences which don't match the reference (e.g because it was
+/// aligned to a different build of the genome).
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferenceMismatchPayload<'a> {
+    pub track_id: &'a TrackId,
+    pub file_path: &'a PathBuf,
+    pub mismatches: &'a [ContigMismatch],
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -154,4 +439,6 @@ pub struct FocusedSequenceUpdatedPayload<'a> {
     pub buffered_region: &'a GenomicRegion,
     pub focused_sequence: &'a Option<String>,
     pub buffered_sequence: &'a Option<String>,
+    /// True if the buffered sequence is too long to be included and must be fetched in chunks.
+    pub buffered_sequence_chunked: bool,
 }
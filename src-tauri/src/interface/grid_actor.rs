@@ -0,0 +1,173 @@
+//! Running the commands which change a split grid on a single thread.
+//!
+//! Commands which add splits or tracks, or change the state of tracks, touch the split, track and
+//! stack reader maps of the grid together, and would otherwise need to take their locks in a
+//! consistent order to avoid deadlocks with each other. Sending them to a single owner thread
+//! instead means that at most one of them runs at a time.
+//!
+//! Commands which read files (e.g re-reading a track's alignments after its filter changes) run on
+//! the grid's load pool instead, alongside focused region loads, so that slow file IO doesn't hold
+//! up the actor thread. These only change tracks and splits which already exist.
+//!
+//! The actor isn't the only owner of the grid's state. Reads of the grid (through `Deref`) and
+//! loads still access it directly, so the grid keeps its inner locks and the deadlock detection
+//! thread in main is still needed.
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, Context, Result};
+use parking_lot::Mutex;
+
+use crate::interface::split_grid::SplitGrid;
+
+/// An operation which is run on the actor thread.
+type GridMessage = Box<dyn FnOnce(&SplitGrid) + Send>;
+
+/// Owns a split grid and runs the operations which are sent to it in order on its own thread.
+///
+/// The thread stops once the actor is dropped and any operations which were already sent have run.
+/// An operation which panics fails its call, but doesn't stop the thread.
+#[derive(Debug)]
+pub struct GridActor {
+    grid: Arc<SplitGrid>,
+    sender: Mutex<Sender<GridMessage>>,
+}
+
+impl GridActor {
+    pub fn spawn(grid: SplitGrid) -> Result<Self> {
+        let grid = Arc::new(grid);
+        let (sender, receiver) = mpsc::channel::<GridMessage>();
+        let actor_grid = Arc::clone(&grid);
+        thread::Builder::new()
+            .name("split-grid-actor".to_owned())
+            .spawn(move || {
+                while let Ok(message) = receiver.recv() {
+                    // Locks aren't poisoned, so later operations can still use the grid
+                    if panic::catch_unwind(AssertUnwindSafe(|| message(&actor_grid))).is_err() {
+                        log::error!("Split grid operation panicked");
+                    }
+                }
+            })
+            .context("Failed to start split grid actor thread")?;
+        Ok(Self { grid, sender: Mutex::new(sender) })
+    }
+
+    /// Shared handle to the grid, for work which outlives the caller (e.g background loads).
+    pub fn grid(&self) -> Arc<SplitGrid> {
+        Arc::clone(&self.grid)
+    }
+
+    /// Run an operation on the actor thread and wait for its result.
+    pub fn call<T, F>(&self, func: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&SplitGrid) -> T + Send + 'static,
+    {
+        let (reply_sender, reply_receiver) = mpsc::sync_channel(1);
+        let message: GridMessage = Box::new(move |grid| {
+            // The caller only stops waiting for the reply if it panicked. If the operation panics,
+            // the reply sender is dropped without a reply instead.
+            let _ = reply_sender.send(func(grid));
+        });
+        self.sender.lock().send(message).map_err(|_| anyhow!("Split grid actor has stopped"))?;
+        reply_receiver.recv().map_err(|_| anyhow!("Split grid operation failed without replying"))
+    }
+
+    /// Run an operation which reads files on the grid's load pool and wait for its result.
+    ///
+    /// Unlike `call`, the operation may run concurrently with the actor thread and other loads.
+    pub fn load<T, F>(&self, func: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&SplitGrid) -> T + Send + 'static,
+    {
+        let (reply_sender, reply_receiver) = mpsc::sync_channel(1);
+        self.grid.spawn_load(move |grid| {
+            let _ = reply_sender.send(func(grid));
+        });
+        reply_receiver.recv().map_err(|_| anyhow!("Split grid load failed without replying"))
+    }
+}
+
+impl Deref for GridActor {
+    type Target = SplitGrid;
+
+    fn deref(&self) -> &SplitGrid {
+        &self.grid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::RwLock;
+    use pretty_assertions::assert_eq;
+
+    use crate::interface::events::StubEventEmitter;
+
+    use super::*;
+
+    #[test]
+    fn test_call_runs_on_actor_thread() {
+        let actor = GridActor::spawn(SplitGrid::new(10000).unwrap()).unwrap();
+        let thread_name = actor.call(|_| thread::current().name().map(String::from)).unwrap();
+        assert_eq!(thread_name.as_deref(), Some("split-grid-actor"));
+    }
+
+    #[test]
+    fn test_call_survives_panic() {
+        let actor = GridActor::spawn(SplitGrid::new(10000).unwrap()).unwrap();
+        let result = actor.call(|_| -> usize { panic!("Failed operation") });
+        assert!(result.is_err());
+        let num_splits = actor.call(|grid| grid.get_split_ids().len()).unwrap();
+        assert_eq!(num_splits, 1);
+    }
+
+    #[test]
+    fn test_call_mutates_shared_grid() {
+        let actor = GridActor::spawn(SplitGrid::new(10000).unwrap()).unwrap();
+        let num_splits = actor
+            .call(|grid| {
+                grid.add_split(&StubEventEmitter::new(), None)?;
+                Ok::<_, anyhow::Error>(grid.get_split_ids().len())
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(num_splits, 2);
+        assert_eq!(actor.get_split_ids().len(), 2);
+        assert_eq!(actor.grid().get_split_ids().len(), 2);
+    }
+
+    #[test]
+    fn test_load_runs_on_load_pool() {
+        let actor = GridActor::spawn(SplitGrid::new(10000).unwrap()).unwrap();
+        let thread_name = actor.load(|_| thread::current().name().map(String::from)).unwrap();
+        assert!(thread_name.unwrap().starts_with("region-load-"));
+    }
+
+    #[test]
+    fn test_load_survives_panic() {
+        let actor = GridActor::spawn(SplitGrid::new(10000).unwrap()).unwrap();
+        let result = actor.load(|_| -> usize { panic!("Failed load") });
+        assert!(result.is_err());
+        let num_splits = actor.load(|grid| grid.get_split_ids().len()).unwrap();
+        assert_eq!(num_splits, 1);
+    }
+
+    #[test]
+    fn test_call_doesnt_wait_for_load() {
+        let actor = Arc::new(GridActor::spawn(SplitGrid::new(10000).unwrap()).unwrap());
+        let gate = Arc::new(RwLock::new(()));
+        let gate_guard = gate.write();
+        let load_actor = Arc::clone(&actor);
+        let load_gate = Arc::clone(&gate);
+        let load = thread::spawn(move || load_actor.load(move |_| drop(load_gate.read())));
+        let num_splits = actor.call(|grid| grid.get_split_ids().len()).unwrap();
+        assert_eq!(num_splits, 1);
+        assert!(!load.is_finished());
+        drop(gate_guard);
+        load.join().unwrap().unwrap();
+    }
+}
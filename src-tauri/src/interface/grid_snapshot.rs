@@ -0,0 +1,240 @@
+//! Deterministic snapshots of the split grid for comparing states (e.g in integration tests).
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::interface::split_grid::SplitGrid;
+
+/// Canonical representation of the state of a split grid, including the contents of each stack.
+///
+/// Ids are replaced by labels based on position so that snapshots of equivalent grids are identical
+/// (e.g the first split in the grid is always `split-0`). Splits are ordered as they are displayed
+/// and tracks are ordered by file path. Object keys are sorted.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GridSnapshot {
+    pub reference: Value,
+    pub focus: Value,
+    pub splits: Vec<Value>,
+    pub tracks: Vec<Value>,
+    /// Stack contents keyed by `<track label>/<split label>`.
+    pub stacks: BTreeMap<String, Value>,
+}
+
+/// A single value which differs between two snapshots.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotDifference {
+    /// Location of the value within the snapshot (e.g `splits[0].focusedRegion`).
+    pub path: String,
+    pub left: Option<Value>,
+    pub right: Option<Value>,
+}
+
+impl fmt::Display for SnapshotDifference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format_value = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "<missing>".to_owned(),
+        };
+        write!(f, "{}: {} != {}", self.path, format_value(&self.left), format_value(&self.right))
+    }
+}
+
+/// Replaces UUIDs with labels which only depend on the order in which they're encountered.
+#[derive(Debug, Default)]
+struct IdLabeler {
+    labels: HashMap<String, String>,
+    num_other_ids: usize,
+}
+
+impl IdLabeler {
+    fn add(&mut self, id: &Uuid, label: String) {
+        self.labels.insert(id.to_string(), label);
+    }
+
+    /// Relabel every id in a JSON value (in place).
+    ///
+    /// Unknown UUIDs (e.g stack ids) are labeled `id-<n>` in the order in which they're found.
+    fn relabel(&mut self, value: &mut Value) {
+        match value {
+            Value::String(s) => {
+                if let Some(label) = self.labels.get(s.as_str()) {
+                    *s = label.clone();
+                } else if Uuid::parse_str(s).is_ok() {
+                    let label = format!("id-{}", self.num_other_ids);
+                    self.num_other_ids += 1;
+                    self.labels.insert(s.clone(), label.clone());
+                    *s = label;
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.relabel(value)),
+            Value::Object(map) => map.values_mut().for_each(|value| self.relabel(value)),
+            _ => (),
+        }
+    }
+
+    fn relabeled_value<T: Serialize>(&mut self, item: &T) -> Result<Value> {
+        let mut value = serde_json::to_value(item)?;
+        self.relabel(&mut value);
+        Ok(value)
+    }
+}
+
+impl GridSnapshot {
+    pub fn from_split_grid(split_grid: &SplitGrid) -> Result<Self> {
+        let mut labeler = IdLabeler::default();
+        let split_ids = split_grid.get_split_ids();
+        for (i, split_id) in split_ids.iter().enumerate() {
+            labeler.add(split_id, format!("split-{}", i));
+        }
+        let mut tracks: Vec<_> = split_grid
+            .tracks
+            .iter()
+            .map(|track| {
+                let track = track.read();
+                (track.file_path().clone(), track.customization().label.clone(), track.id())
+            })
+            .collect();
+        tracks.sort();
+        let track_ids: Vec<_> = tracks.into_iter().map(|(_, _, track_id)| track_id).collect();
+        for (i, track_id) in track_ids.iter().enumerate() {
+            labeler.add(track_id, format!("track-{}", i));
+        }
+
+        let reference = labeler.relabeled_value(&*split_grid.reference.read())?;
+        let focus = labeler.relabeled_value(&*split_grid.focus.read())?;
+        let splits = split_ids
+            .iter()
+            .map(|split_id| labeler.relabeled_value(&*split_grid.get_split(split_id)?.read()))
+            .collect::<Result<_>>()?;
+        let tracks = track_ids
+            .iter()
+            .filter_map(|track_id| split_grid.tracks.get(track_id))
+            .map(|track| labeler.relabeled_value(&*track.read()))
+            .collect::<Result<_>>()?;
+        let mut stacks = BTreeMap::new();
+        for (i, track_id) in track_ids.iter().enumerate() {
+            for (j, split_id) in split_ids.iter().enumerate() {
+                let stack = split_grid.get_stack_reader(split_id, track_id)?.stack();
+                let value = labeler.relabeled_value(&*stack.read())?;
+                stacks.insert(format!("track-{}/split-{}", i, j), value);
+            }
+        }
+        Ok(Self { reference, focus, splits, tracks, stacks })
+    }
+
+    pub fn to_value(&self) -> Result<Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    /// List every value which differs between two snapshots.
+    pub fn diff(&self, other: &GridSnapshot) -> Result<Vec<SnapshotDifference>> {
+        let mut differences = Vec::new();
+        diff_values("", Some(&self.to_value()?), Some(&other.to_value()?), &mut differences);
+        Ok(differences)
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn diff_values(
+    path: &str,
+    left: Option<&Value>,
+    right: Option<&Value>,
+    differences: &mut Vec<SnapshotDifference>,
+) {
+    match (left, right) {
+        (Some(Value::Object(left)), Some(Value::Object(right))) => {
+            let mut keys: Vec<&String> = left.keys().chain(right.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                diff_values(&join_path(path, key), left.get(key), right.get(key), differences);
+            }
+        }
+        (Some(Value::Array(left)), Some(Value::Array(right))) => {
+            for i in 0..left.len().max(right.len()) {
+                let item_path = format!("{}[{}]", path, i);
+                diff_values(&item_path, left.get(i), right.get(i), differences);
+            }
+        }
+        (left, right) if left != right => differences.push(SnapshotDifference {
+            path: path.to_owned(),
+            left: left.cloned(),
+            right: right.cloned(),
+        }),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+    use crate::bio_util::genomic_coordinates::GenomicRegion;
+    use crate::interface::events::StubEventEmitter;
+    use crate::paths::get_test_data_path;
+
+    fn init_split_grid() -> SplitGrid {
+        let split_grid = SplitGrid::new(10000).unwrap();
+        let event_emitter = StubEventEmitter::new();
+        split_grid.add_track(&event_emitter, get_test_data_path("fake-genome.tiny.bam")).unwrap();
+        split_grid.add_track(&event_emitter, get_test_data_path("fake-genome.paf")).unwrap();
+        let region = GenomicRegion::new("euk_genes", 100, 200).unwrap();
+        split_grid.add_split(&event_emitter, Some(region)).unwrap();
+        split_grid
+    }
+
+    #[test]
+    fn test_snapshots_of_equivalent_grids_are_identical() {
+        let snapshot = GridSnapshot::from_split_grid(&init_split_grid()).unwrap();
+        let other_snapshot = GridSnapshot::from_split_grid(&init_split_grid()).unwrap();
+        assert_eq!(snapshot.diff(&other_snapshot).unwrap(), vec![]);
+        assert_eq!(snapshot, other_snapshot);
+        assert_eq!(snapshot.focus.get("splitId").unwrap(), "split-1");
+        // Tracks are labelled in order of file path, and the last added track (the PAF) is focused
+        assert_eq!(snapshot.focus.get("trackId").unwrap(), "track-0");
+        assert_eq!(snapshot.splits[0].get("id").unwrap(), "split-0");
+        let stack_keys: Vec<&str> = snapshot.stacks.keys().map(|key| key.as_str()).collect();
+        assert_eq!(
+            stack_keys,
+            vec!["track-0/split-0", "track-0/split-1", "track-1/split-0", "track-1/split-1"]
+        );
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let split_grid = init_split_grid();
+        let snapshot = GridSnapshot::from_split_grid(&split_grid).unwrap();
+        let split_id = split_grid.get_split_ids()[1];
+        let region = GenomicRegion::new("euk_genes", 100, 250).unwrap();
+        split_grid.update_focused_region(&StubEventEmitter::new(), &split_id, region).unwrap();
+        let other_snapshot = GridSnapshot::from_split_grid(&split_grid).unwrap();
+        let differences = snapshot.diff(&other_snapshot).unwrap();
+        let difference = differences
+            .iter()
+            .find(|difference| difference.path == "splits[1].focusedRegion.interval.end")
+            .unwrap();
+        assert_eq!(difference.left, Some(json!("200")));
+        assert_eq!(difference.right, Some(json!("250")));
+        assert_eq!(
+            difference.to_string(),
+            r#"splits[1].focusedRegion.interval.end: "200" != "250""#
+        );
+        assert!(differences.iter().all(|difference| difference.path.starts_with("splits[1]")
+            || difference.path.starts_with("stacks.track-0/split-1")
+            || difference.path.starts_with("stacks.track-1/split-1")));
+    }
+}
@@ -0,0 +1,108 @@
+//! Cleaning up the state which is tied to splits and tracks once they're removed from the grid.
+use anyhow::Result;
+use dashmap::DashSet;
+use thiserror::Error;
+
+use crate::interface::split::SplitId;
+use crate::interface::track::TrackId;
+
+/// Returned by work on a split or track which was removed while the work was in progress.
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum RemovedError {
+    #[error("Split {0} was removed")]
+    Split(SplitId),
+    #[error("Track {0} was removed")]
+    Track(TrackId),
+}
+
+/// Whether an error was caused by the split or track being worked on having been removed.
+pub fn is_removed_error(err: &anyhow::Error) -> bool {
+    err.is::<RemovedError>()
+}
+
+/// Convert the error from work on a removed split or track into None, so that the work can be
+/// skipped.
+pub fn skip_removed<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if is_removed_error(&err) => {
+            log::debug!("Skipping cancelled work: {}", err);
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Records which splits and tracks have been removed from a grid.
+///
+/// Work on a split or track can still be in progress when it's removed (e.g its alignments may be
+/// being read on another thread). Such work checks whether its split and track still exist before
+/// adding anything to the grid, and again after, so that nothing which is tied to them outlives
+/// their removal. Ids are never reused so removed ids are kept for the lifetime of the grid.
+#[derive(Debug, Default)]
+pub struct GridLifecycle {
+    removed_splits: DashSet<SplitId>,
+    removed_tracks: DashSet<TrackId>,
+}
+
+impl GridLifecycle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mark_split_removed(&self, split_id: SplitId) {
+        self.removed_splits.insert(split_id);
+    }
+
+    pub fn mark_track_removed(&self, track_id: TrackId) {
+        self.removed_tracks.insert(track_id);
+    }
+
+    pub fn is_split_removed(&self, split_id: &SplitId) -> bool {
+        self.removed_splits.contains(split_id)
+    }
+
+    pub fn is_track_removed(&self, track_id: &TrackId) -> bool {
+        self.removed_tracks.contains(track_id)
+    }
+
+    /// Fail with a RemovedError if either the split or the track has been removed.
+    pub fn check(&self, split_id: &SplitId, track_id: &TrackId) -> Result<()> {
+        if self.is_split_removed(split_id) {
+            return Err(RemovedError::Split(*split_id).into());
+        }
+        if self.is_track_removed(track_id) {
+            return Err(RemovedError::Track(*track_id).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_check_removed() {
+        let lifecycle = GridLifecycle::new();
+        let (split_id, track_id) = (SplitId::new(), TrackId::new());
+        assert!(lifecycle.check(&split_id, &track_id).is_ok());
+        lifecycle.mark_track_removed(track_id);
+        let err = lifecycle.check(&split_id, &track_id).unwrap_err();
+        assert_eq!(err.downcast_ref::<RemovedError>(), Some(&RemovedError::Track(track_id)));
+        assert!(lifecycle.check(&split_id, &TrackId::new()).is_ok());
+        lifecycle.mark_split_removed(split_id);
+        assert!(is_removed_error(&lifecycle.check(&split_id, &TrackId::new()).unwrap_err()));
+    }
+
+    #[test]
+    fn test_skip_removed() {
+        assert_eq!(skip_removed(Ok(1)).unwrap(), Some(1));
+        let removed: Result<()> = Err(RemovedError::Split(SplitId::new()).into());
+        assert_eq!(skip_removed(removed).unwrap(), None);
+        assert!(skip_removed::<()>(Err(anyhow!("Failed to read"))).is_err());
+    }
+}
@@ -1,6 +1,15 @@
 pub mod backend;
 pub mod commands;
 pub mod events;
+pub mod grid_actor;
+pub mod grid_snapshot;
+pub mod lifecycle;
+#[cfg(target_os = "macos")]
+pub mod open_files;
+pub mod payload_encoding;
+pub mod region_loads;
+pub mod schema;
+pub mod session;
 pub mod split;
 pub mod split_grid;
 pub mod system_menu;
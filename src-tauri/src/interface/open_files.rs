@@ -0,0 +1,104 @@
+//! Opening the files which macOS asks the app to open (e.g when the user double-clicks a session
+//! file).
+//!
+//! Windows and Linux pass these files to a new process as command line arguments, but macOS sends
+//! them to the app delegate (including on launch), which tauri doesn't expose. The delegate's class
+//! is extended with the method which receives them.
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::path::PathBuf;
+use std::thread;
+
+use objc::runtime::{class_addMethod, object_getClass, Class, Object, Sel, BOOL, NO};
+use objc::{class, msg_send, sel, sel_impl};
+use parking_lot::{const_mutex, Mutex};
+use tauri::{AppHandle, Manager};
+
+use crate::interface::backend::Backend;
+use crate::interface::events::EventEmitter;
+use crate::interface::session::is_session_file;
+
+/// Reply which tells macOS that the files were opened (NSApplicationDelegateReplySuccess).
+const REPLY_SUCCESS: usize = 0;
+
+/// Handle to the app, which the delegate method uses to reach the backend.
+static APP_HANDLE: Mutex<Option<AppHandle>> = const_mutex(None);
+
+/// Start receiving the files which macOS asks the app to open.
+///
+/// Needs to be called before the app finishes launching so that the file which launched it isn't
+/// missed.
+pub fn handle_open_files(app: &AppHandle) {
+    let mut app_handle = APP_HANDLE.lock();
+    if app_handle.is_some() {
+        return;
+    }
+    *app_handle = Some(app.clone());
+    unsafe {
+        let ns_app: *mut Object = msg_send![class!(NSApplication), sharedApplication];
+        let delegate: *mut Object = msg_send![ns_app, delegate];
+        if delegate.is_null() {
+            log::error!("Failed to find the app delegate, so opened files will be ignored");
+            return;
+        }
+        let open_files: extern "C" fn(&Object, Sel, *mut Object, *mut Object) =
+            application_open_files;
+        let added: BOOL = class_addMethod(
+            object_getClass(delegate) as *mut Class,
+            sel!(application:openFiles:),
+            std::mem::transmute(open_files),
+            b"v@:@@\0".as_ptr() as *const c_char,
+        );
+        if added == NO {
+            log::error!("The app delegate already handles opened files, so they'll be ignored");
+        }
+    }
+}
+
+/// Implementation of `-[NSApplicationDelegate application:openFiles:]`.
+extern "C" fn application_open_files(
+    _this: &Object,
+    _cmd: Sel,
+    sender: *mut Object,
+    filenames: *mut Object,
+) {
+    let paths = unsafe { get_paths(filenames) };
+    if let Some(app) = APP_HANDLE.lock().clone() {
+        for path in paths {
+            open_file(&app, path);
+        }
+    }
+    unsafe {
+        let _: () = msg_send![sender, replyToOpenOrPrint: REPLY_SUCCESS];
+    }
+}
+
+/// Convert an NSArray of file paths.
+unsafe fn get_paths(filenames: *mut Object) -> Vec<PathBuf> {
+    let count: usize = msg_send![filenames, count];
+    (0..count)
+        .filter_map(|index| {
+            let filename: *mut Object = msg_send![filenames, objectAtIndex: index];
+            let chars: *const c_char = msg_send![filename, UTF8String];
+            if chars.is_null() {
+                return None;
+            }
+            Some(PathBuf::from(CStr::from_ptr(chars).to_string_lossy().into_owned()))
+        })
+        .collect()
+}
+
+/// Load a session file on a background thread, so that the main thread isn't blocked.
+fn open_file(app: &AppHandle, path: PathBuf) {
+    if !is_session_file(&path) {
+        log::warn!("Ignoring request to open {}, which isn't a session file", path.display());
+        return;
+    }
+    let app = app.clone();
+    thread::spawn(move || {
+        let backend: tauri::State<Backend> = app.state();
+        if let Err(err) = backend.open_session_file(&EventEmitter::new(&app), &path) {
+            log::error!("Failed to load session {}: {:#}", path.display(), err);
+        }
+    });
+}
@@ -0,0 +1,166 @@
+//! Binary encoding of alignment event payloads, which are otherwise slow to serialize and parse as
+//! JSON when a stack contains thousands of reads.
+//!
+//! Stacks are serialized to MessagePack directly (with rmp-serde), using the same field names as
+//! their JSON, so the frontend decodes it to the same object which it would have parsed from JSON.
+//! Tauri events can only contain JSON, so MessagePack is sent as a base64 string.
+//!
+//! Payloads over a configurable size are also gzip compressed, which reduces the time spent passing
+//! them over the webview bridge for dense regions.
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use parking_lot::{const_rwlock, RwLock};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How the alignments in AlignmentsUpdated payloads are encoded.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PayloadEncoding {
+    #[default]
+    Json,
+    /// Base64 encoded MessagePack.
+    MessagePack,
+}
+
+static PAYLOAD_ENCODING: RwLock<PayloadEncoding> = const_rwlock(PayloadEncoding::Json);
+
+/// Set how the alignments in AlignmentsUpdated payloads are encoded.
+pub fn configure_payload_encoding(encoding: PayloadEncoding) {
+    *PAYLOAD_ENCODING.write() = encoding;
+}
+
+pub fn get_payload_encoding() -> PayloadEncoding {
+    *PAYLOAD_ENCODING.read()
+}
+
+/// Encoded payloads larger than this many bytes are compressed. Zero disables compression.
+static PAYLOAD_COMPRESSION_THRESHOLD: AtomicUsize = AtomicUsize::new(0);
+
+/// Set the size in bytes above which encoded payloads are gzip compressed (None disables
+/// compression).
+pub fn configure_payload_compression_threshold(threshold: Option<usize>) {
+    PAYLOAD_COMPRESSION_THRESHOLD.store(threshold.unwrap_or(0), Ordering::SeqCst);
+}
+
+/// A payload field encoded with the configured encoding/compression.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EncodedPayload {
+    /// The encoded value. Compressed values are sent as a base64 string of the gzipped bytes.
+    pub value: Value,
+    pub encoding: PayloadEncoding,
+    pub compressed: bool,
+}
+
+/// Encode a payload field with the configured encoding, compressing it if it's larger than the
+/// compression threshold.
+pub fn encode_payload<T: Serialize + ?Sized>(value: &T) -> Result<EncodedPayload> {
+    encode_payload_with(
+        value,
+        get_payload_encoding(),
+        PAYLOAD_COMPRESSION_THRESHOLD.load(Ordering::SeqCst),
+    )
+}
+
+fn encode_payload_with<T: Serialize + ?Sized>(
+    value: &T,
+    encoding: PayloadEncoding,
+    compression_threshold: usize,
+) -> Result<EncodedPayload> {
+    let (value, bytes) = match encoding {
+        PayloadEncoding::Json => {
+            let value = serde_json::to_value(value)?;
+            if compression_threshold == 0 {
+                return Ok(EncodedPayload { value, encoding, compressed: false });
+            }
+            let bytes = serde_json::to_vec(&value)?;
+            (Some(value), bytes)
+        }
+        PayloadEncoding::MessagePack => (None, encode_msgpack(value)?),
+    };
+    if compression_threshold > 0 && bytes.len() > compression_threshold {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&bytes)?;
+        let value = Value::String(base64::encode(encoder.finish()?));
+        return Ok(EncodedPayload { value, encoding, compressed: true });
+    }
+    let value = value.unwrap_or_else(|| Value::String(base64::encode(bytes)));
+    Ok(EncodedPayload { value, encoding, compressed: false })
+}
+
+/// Encode a value as MessagePack with the same field names and values as its JSON.
+///
+/// Types such as ids serialize to bytes in formats which aren't human readable, so the serializer
+/// is marked as human readable to keep them as strings.
+fn encode_msgpack<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut serializer =
+        rmp_serde::Serializer::new(&mut bytes).with_struct_map().with_human_readable();
+    value.serialize(&mut serializer)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_payload() {
+        let value = json!({"rows": []});
+        let encoded = encode_payload_with(&value, PayloadEncoding::Json, 0).unwrap();
+        assert_eq!(encoded, EncodedPayload { value: value.clone(), ..Default::default() });
+        // 0x81 0xa4 "rows" 0x90
+        let encoded = encode_payload_with(&value, PayloadEncoding::MessagePack, 0).unwrap();
+        assert_eq!(encoded.value, json!("gaRyb3dzkA=="));
+        assert!(!encoded.compressed);
+    }
+
+    #[test]
+    fn test_encode_typed_payload() {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Row {
+            id: uuid::Uuid,
+            row_index: usize,
+            read_id: Option<String>,
+        }
+        let rows = vec![Row { id: uuid::Uuid::nil(), row_index: 1, read_id: None }];
+        let encoded = encode_payload_with(&rows, PayloadEncoding::MessagePack, 0).unwrap();
+        let bytes = base64::decode(encoded.value.as_str().unwrap()).unwrap();
+        let decoded: Value = rmp_serde::from_slice(&bytes).unwrap();
+        // Ids are encoded as strings, as they are in JSON
+        assert_eq!(
+            decoded,
+            json!([{"id": uuid::Uuid::nil().to_string(), "rowIndex": 1, "readId": null}])
+        );
+    }
+
+    #[test]
+    fn test_compress_large_payloads() {
+        let value = json!({"rows": [[1, 2, 3]]});
+        let encoded = encode_payload_with(&value, PayloadEncoding::Json, 1000).unwrap();
+        assert_eq!(encoded, EncodedPayload { value: value.clone(), ..Default::default() });
+
+        let encoded = encode_payload_with(&value, PayloadEncoding::Json, 5).unwrap();
+        assert!(encoded.compressed);
+        let compressed = base64::decode(encoded.value.as_str().unwrap()).unwrap();
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(&compressed[..]).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&decompressed).unwrap(), value);
+
+        let encoded = encode_payload_with(&value, PayloadEncoding::MessagePack, 5).unwrap();
+        let compressed = base64::decode(encoded.value.as_str().unwrap()).unwrap();
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(&compressed[..]).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Value>(&decompressed).unwrap(), value);
+    }
+}
@@ -0,0 +1,99 @@
+//! Queueing loads of a split's focused region, so that bursts of updates (e.g from scroll-wheel
+//! zooming) only load the region which was requested last.
+use parking_lot::Mutex;
+
+use crate::alignments::cancellation::CancellationToken;
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+
+#[derive(Debug, Default)]
+struct QueueState {
+    /// Region of the load which is waiting to start. Replaced if another load is queued first.
+    pending: Option<GenomicRegion>,
+    /// Token of the most recently started load.
+    cancellation: CancellationToken,
+    /// Whether a caller is currently running the queued loads.
+    active: bool,
+}
+
+/// The focused region loads of a single split.
+///
+/// Loads are run one at a time by whichever caller queued a load while no others were running,
+/// until the queue is empty. Queueing a load cancels the load which is in progress, so that it
+/// stops reading alignments which will never be shown, and replaces any load which is waiting to
+/// start.
+#[derive(Debug, Default)]
+pub struct RegionLoads {
+    state: Mutex<QueueState>,
+}
+
+/// A started load.
+#[derive(Debug)]
+pub struct RegionLoad {
+    pub region: GenomicRegion,
+    pub cancellation: CancellationToken,
+}
+
+impl RegionLoads {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a load of a region and cancel the load which is in progress.
+    ///
+    /// Returns true if no loads were running, in which case the caller must run the queued loads
+    /// (see next). Otherwise the load is run by the caller which is already running loads.
+    pub fn queue(&self, region: GenomicRegion) -> bool {
+        let mut state = self.state.lock();
+        state.pending = Some(region);
+        state.cancellation.cancel();
+        !std::mem::replace(&mut state.active, true)
+    }
+
+    /// Start the most recently queued load.
+    ///
+    /// Returns None once the queue is empty, after which loads need to be run by the caller which
+    /// queues the next one.
+    pub fn next(&self) -> Option<RegionLoad> {
+        let mut state = self.state.lock();
+        match state.pending.take() {
+            Some(region) => {
+                state.cancellation = CancellationToken::new();
+                Some(RegionLoad { region, cancellation: state.cancellation.clone() })
+            }
+            None => {
+                state.active = false;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_queue_cancels_running_load() {
+        let loads = RegionLoads::new();
+        assert!(loads.queue(GenomicRegion::new("X", 0, 100).unwrap()));
+        let load = loads.next().unwrap();
+        assert!(!load.cancellation.is_cancelled());
+        assert!(!loads.queue(GenomicRegion::new("X", 100, 200).unwrap()));
+        assert!(load.cancellation.is_cancelled());
+        assert!(!loads.next().unwrap().cancellation.is_cancelled());
+        assert!(loads.next().is_none());
+        assert!(loads.queue(GenomicRegion::new("X", 200, 300).unwrap()));
+    }
+
+    #[test]
+    fn test_queued_loads_are_coalesced() {
+        let loads = RegionLoads::new();
+        for start in [0, 100, 200] {
+            loads.queue(GenomicRegion::new("X", start, start + 100).unwrap());
+        }
+        assert_eq!(loads.next().unwrap().region, GenomicRegion::new("X", 200, 300).unwrap());
+        assert!(loads.next().is_none());
+    }
+}
@@ -0,0 +1,167 @@
+//! Versioning of the event payloads sent to the frontend.
+//!
+//! The frontend and backend may be released separately, so the frontend checks which schema
+//! versions the backend supports when it starts and can ask the backend to keep emitting an older
+//! schema rather than silently misreading payloads.
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+/// Version of the event payload schema. Incremented whenever payloads change in a way which would
+/// break a frontend built against the previous version.
+pub const API_SCHEMA_VERSION: u32 = 3;
+
+/// First schema version in which extending the buffered region emits the changes to each stack
+/// (AlignmentsAppended) rather than the whole stack.
+pub const ALIGNMENTS_APPENDED_SCHEMA_VERSION: u32 = 3;
+
+/// Oldest schema version which the backend can still emit. Version 1 payloads don't include their
+/// schema version.
+pub const MIN_API_SCHEMA_VERSION: u32 = 1;
+
+/// Schema version which events are emitted with, as negotiated with the frontend.
+static EMITTED_SCHEMA_VERSION: AtomicU32 = AtomicU32::new(API_SCHEMA_VERSION);
+
+/// Schema versions which the backend supports, sent in response to the frontend's version check.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiSchemaVersion {
+    /// Latest schema version which the backend can emit.
+    pub schema_version: u32,
+    pub min_schema_version: u32,
+    /// Schema version which events are currently emitted with.
+    pub emitted_schema_version: u32,
+}
+
+impl ApiSchemaVersion {
+    pub fn current() -> Self {
+        Self {
+            schema_version: API_SCHEMA_VERSION,
+            min_schema_version: MIN_API_SCHEMA_VERSION,
+            emitted_schema_version: get_emitted_schema_version(),
+        }
+    }
+}
+
+pub fn get_emitted_schema_version() -> u32 {
+    EMITTED_SCHEMA_VERSION.load(Ordering::SeqCst)
+}
+
+/// Set the schema version which events are emitted with.
+///
+/// Errors if the backend can't emit the version (e.g because the frontend is newer than the
+/// backend).
+pub fn set_emitted_schema_version(version: u32) -> Result<()> {
+    if !(MIN_API_SCHEMA_VERSION..=API_SCHEMA_VERSION).contains(&version) {
+        bail!(
+            "Unsupported API schema version {} (supported versions are {} to {})",
+            version,
+            MIN_API_SCHEMA_VERSION,
+            API_SCHEMA_VERSION
+        );
+    }
+    EMITTED_SCHEMA_VERSION.store(version, Ordering::SeqCst);
+    Ok(())
+}
+
+/// A payload with its schema version added as an extra field.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionedPayload<'a, S: Serialize> {
+    pub schema_version: u32,
+    /// Id sent by the frontend with the command which caused the event, so that the frontend can
+    /// match events to its own actions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<&'a str>,
+    #[serde(flatten)]
+    pub payload: &'a S,
+}
+
+/// A payload in the format of a particular schema version.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum SchemaPayload<'a, S: Serialize> {
+    V1(&'a S),
+    Current(VersionedPayload<'a, S>),
+}
+
+impl<'a, S: Serialize> SchemaPayload<'a, S> {
+    /// Convert a payload to a schema version. Versions outside the supported range are clamped to
+    /// it.
+    pub fn new(payload: &'a S, version: u32) -> Self {
+        match version {
+            version if version <= 1 => SchemaPayload::V1(payload),
+            _ => SchemaPayload::Current(VersionedPayload {
+                schema_version: version.min(API_SCHEMA_VERSION),
+                request_id: None,
+                payload,
+            }),
+        }
+    }
+
+    /// Add the id of the request which caused the event. Version 1 payloads can't include a
+    /// request id so are left unchanged.
+    pub fn with_request_id(mut self, request_id: Option<&'a str>) -> Self {
+        if let SchemaPayload::Current(versioned) = &mut self {
+            versioned.request_id = request_id;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+    use crate::interface::events::RegionBufferingPayload;
+    use crate::interface::split::SplitId;
+
+    #[test]
+    fn test_schema_payload() {
+        let split_id = SplitId::new();
+        let payload = RegionBufferingPayload { split_id: &split_id };
+        assert_eq!(
+            serde_json::to_value(SchemaPayload::new(&payload, API_SCHEMA_VERSION)).unwrap(),
+            json!({ "splitId": split_id, "schemaVersion": 3 })
+        );
+        assert_eq!(
+            serde_json::to_value(SchemaPayload::new(&payload, 2)).unwrap(),
+            json!({ "splitId": split_id, "schemaVersion": 2 })
+        );
+        assert_eq!(
+            serde_json::to_value(SchemaPayload::new(&payload, 1)).unwrap(),
+            json!({ "splitId": split_id })
+        );
+        // Payloads without any fields still include the schema version
+        assert_eq!(
+            serde_json::to_value(SchemaPayload::new(&(), API_SCHEMA_VERSION)).unwrap(),
+            json!({ "schemaVersion": 3 })
+        );
+        assert_eq!(serde_json::to_value(SchemaPayload::new(&(), 1)).unwrap(), json!(null));
+    }
+
+    #[test]
+    fn test_schema_payload_with_request_id() {
+        let split_id = SplitId::new();
+        let payload = RegionBufferingPayload { split_id: &split_id };
+        let versioned =
+            SchemaPayload::new(&payload, API_SCHEMA_VERSION).with_request_id(Some("a1"));
+        assert_eq!(
+            serde_json::to_value(versioned).unwrap(),
+            json!({ "splitId": split_id, "schemaVersion": 3, "requestId": "a1" })
+        );
+        let v1 = SchemaPayload::new(&payload, 1).with_request_id(Some("a1"));
+        assert_eq!(serde_json::to_value(v1).unwrap(), json!({ "splitId": split_id }));
+    }
+
+    #[test]
+    fn test_set_emitted_schema_version() {
+        assert!(set_emitted_schema_version(0).is_err());
+        assert!(set_emitted_schema_version(API_SCHEMA_VERSION + 1).is_err());
+        assert_eq!(get_emitted_schema_version(), API_SCHEMA_VERSION);
+        assert_eq!(ApiSchemaVersion::current().min_schema_version, MIN_API_SCHEMA_VERSION);
+    }
+}
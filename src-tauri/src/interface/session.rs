@@ -0,0 +1,269 @@
+//! Saving and restoring the state of the split grid to/from `.gensketch` session files.
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::bio_util::refseq::ReferenceSequence;
+use crate::file_formats::tabix::record::IntervalColumns;
+use crate::interface::events::EmitEvent;
+use crate::interface::split::SplitAnnotation;
+use crate::interface::split_grid::SplitGrid;
+use crate::interface::track::TrackCustomization;
+
+/// File extension which is associated with gensketch in the OS.
+pub const SESSION_FILE_EXTENSION: &str = "gensketch";
+
+/// Incremented whenever a backwards incompatible change is made to the session format.
+pub const SESSION_FORMAT_VERSION: u32 = 1;
+
+pub fn is_session_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().extension() == Some(OsStr::new(SESSION_FILE_EXTENSION))
+}
+
+/// Find the session file (if any) in the app's command line arguments.
+///
+/// Windows and Linux pass the path of the file as an argument when a user opens a file which is
+/// associated with the app.
+pub fn find_session_arg<I: IntoIterator<Item = OsString>>(args: I) -> Option<PathBuf> {
+    args.into_iter().skip(1).map(PathBuf::from).find(|path| is_session_file(path))
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionReference {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSplit {
+    pub focused_region: GenomicRegion,
+    #[serde(flatten)]
+    pub annotation: SplitAnnotation,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionTrack {
+    pub file_path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_columns: Option<IntervalColumns>,
+    #[serde(flatten)]
+    pub customization: TrackCustomization,
+}
+
+/// Serializable state of the split grid.
+///
+/// Only the state which is needed to reconstruct the grid is stored (e.g alignments are re-read
+/// from the track files on load).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub version: u32,
+    pub reference: SessionReference,
+    pub splits: Vec<SessionSplit>,
+    pub tracks: Vec<SessionTrack>,
+}
+
+impl Session {
+    pub fn from_split_grid(split_grid: &SplitGrid) -> Result<Self> {
+        let reference = split_grid.reference.read();
+        let reference =
+            SessionReference { name: reference.name.clone(), path: reference.path.clone() };
+        let splits = split_grid
+            .get_split_ids()
+            .iter()
+            .map(|split_id| {
+                let split = split_grid.get_split(split_id)?;
+                let split = split.read();
+                Ok(SessionSplit {
+                    focused_region: split.focused_region.clone(),
+                    annotation: split.annotation.clone(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        // Tracks which are removed in the meantime are skipped
+        let tracks = split_grid
+            .get_track_ids()
+            .iter()
+            .filter_map(|track_id| split_grid.tracks.get(track_id))
+            .map(|track| {
+                let track = track.read();
+                SessionTrack {
+                    file_path: track.file_path().clone(),
+                    interval_columns: track.interval_columns().cloned(),
+                    customization: track.customization().clone(),
+                }
+            })
+            .collect();
+        Ok(Self { version: SESSION_FORMAT_VERSION, reference, splits, tracks })
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open session file: {}", path.to_string_lossy()))?;
+        let session: Self = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Failed to parse session file: {}", path.to_string_lossy()))?;
+        if session.version > SESSION_FORMAT_VERSION {
+            bail!(
+                "Session file {} was created by a newer version of gensketch (format version {})",
+                path.to_string_lossy(),
+                session.version
+            );
+        }
+        Ok(session)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| {
+            format!("Failed to create session file: {}", path.to_string_lossy())
+        })?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Construct a new split grid from the session.
+    pub fn load<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        max_render_window: u64,
+        preload_reference: bool,
+    ) -> Result<SplitGrid> {
+        log::info!(
+            "Loading session with {} splits and {} tracks",
+            self.splits.len(),
+            self.tracks.len()
+        );
+        let mut reference =
+            ReferenceSequence::new(self.reference.name.clone(), &self.reference.path)?
+                .with_preload(preload_reference);
+        // The grid's first split is created on the first split's region, rather than reading the
+        // reference's default region and then moving it
+        if let Some(split) = self.splits.first() {
+            reference = reference.with_default_focused_region(split.focused_region.clone())?;
+        }
+        let split_grid = SplitGrid::from_reference(reference, max_render_window)?;
+        let initial_split_id = split_grid.get_split_ids()[0];
+        for (i, split) in self.splits.iter().enumerate() {
+            let split_id = if i == 0 {
+                initial_split_id
+            } else {
+                split_grid.add_split(event_emitter, Some(split.focused_region.clone()))?
+            };
+            split_grid.update_split_annotation(
+                event_emitter,
+                &split_id,
+                split.annotation.clone(),
+            )?;
+        }
+        for track in self.tracks.iter() {
+            track.customization.validate()?;
+            let track_id = split_grid.add_track_with_columns(
+                event_emitter,
+                &track.file_path,
+                track.interval_columns.clone(),
+            )?;
+            split_grid.update_track_customization(
+                event_emitter,
+                &track_id,
+                track.customization.clone(),
+            )?;
+        }
+        Ok(split_grid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::alignments::stack::{PackingStrategy, StackGrouping};
+    use crate::file_formats::sam_bam::pileup::CoverageStrand;
+    use crate::interface::events::StubEventEmitter;
+    use crate::interface::track::TrackPriority;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_find_session_arg() {
+        let args = ["gensketch", "--flag", "/data/example.gensketch"].map(OsString::from);
+        assert_eq!(find_session_arg(args), Some(PathBuf::from("/data/example.gensketch")));
+        let args = ["gensketch", "/data/example.bam"].map(OsString::from);
+        assert_eq!(find_session_arg(args), None);
+    }
+
+    #[test]
+    fn test_session_track_order() {
+        let event_emitter = StubEventEmitter::new();
+        let split_grid = SplitGrid::new(10000).unwrap();
+        let bam_paths: Vec<_> =
+            ["fake-genome.tiny.bam", "fake-genome.reads.bam", "fake-genome.empty.bam"]
+                .into_iter()
+                .map(get_test_data_path)
+                .collect();
+        let track_ids: Vec<_> = bam_paths
+            .iter()
+            .map(|path| split_grid.add_track(&event_emitter, path).unwrap())
+            .collect();
+        split_grid.remove_track(&event_emitter, &track_ids[1]).unwrap();
+        let session = Session::from_split_grid(&split_grid).unwrap();
+        let file_paths: Vec<_> = session.tracks.iter().map(|track| &track.file_path).collect();
+        assert_eq!(file_paths, vec![&bam_paths[0], &bam_paths[2]]);
+    }
+
+    #[test]
+    fn test_session_roundtrip() {
+        let event_emitter = StubEventEmitter::new();
+        let split_grid = SplitGrid::new(10000).unwrap();
+        let bam_path = get_test_data_path("fake-genome.tiny.bam");
+        let track_id = split_grid.add_track(&event_emitter, &bam_path).unwrap();
+        let customization = TrackCustomization {
+            color: Some("#ff0000".to_owned()),
+            label: Some("Proband".to_owned()),
+            description: None,
+            priority: TrackPriority::High,
+            stack_padding: Some(5),
+            packing: PackingStrategy::PileupOrder,
+            grouping: StackGrouping::Haplotype,
+            max_depth: Some(100),
+            max_rows: Some(50),
+            base_qualities: true,
+            coverage_strand: CoverageStrand::SecondOfPair,
+        };
+        split_grid
+            .update_track_customization(&event_emitter, &track_id, customization.clone())
+            .unwrap();
+        let region = GenomicRegion::new("euk_genes", 100, 200).unwrap();
+        let split_id = split_grid.add_split(&event_emitter, Some(region.clone())).unwrap();
+        let annotation =
+            SplitAnnotation { name: Some("Breakpoint A".to_owned()), description: None };
+        split_grid.update_split_annotation(&event_emitter, &split_id, annotation.clone()).unwrap();
+
+        let session = Session::from_split_grid(&split_grid).unwrap();
+        let path = std::env::temp_dir().join(format!("{}.gensketch", uuid::Uuid::new_v4()));
+        session.write(&path).unwrap();
+        let read_session = Session::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read_session, session);
+
+        let loaded_grid = read_session.load(&event_emitter, 10000, false).unwrap();
+        let split_ids = loaded_grid.get_split_ids();
+        assert_eq!(split_ids.len(), 2);
+        let loaded_split = loaded_grid.get_split(&split_ids[1]).unwrap();
+        assert_eq!(loaded_split.read().focused_region, region);
+        assert_eq!(loaded_split.read().annotation, annotation);
+        assert_eq!(loaded_grid.tracks.len(), 1);
+        let loaded_track = loaded_grid.tracks.iter().next().unwrap();
+        assert_eq!(loaded_track.read().file_path(), &bam_path);
+        assert_eq!(loaded_track.read().customization(), &customization);
+        assert_eq!(Session::from_split_grid(&loaded_grid).unwrap(), session);
+    }
+}
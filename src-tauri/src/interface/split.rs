@@ -1,12 +1,14 @@
-use std::path::PathBuf;
+use std::fmt;
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use uuid::Uuid;
 
 use crate::bio_util::genomic_coordinates::GenomicRegion;
 use crate::bio_util::sequence::SequenceView;
-use crate::file_formats::fasta::reader::FastaReader;
+use crate::file_formats::fasta::reader::SequenceReader;
 use crate::impl_wrapped_uuid;
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
@@ -20,6 +22,37 @@ const BUFFER_SIZE: u64 = 1;
 
 const REFRESH_FRACTION: u64 = 2;
 
+/// Sequences longer than this aren't sent to the frontend in a single payload. Instead the frontend
+/// requests the sequence in chunks of up to this length.
+pub const SEQUENCE_CHUNK_LENGTH: u64 = 1_000_000;
+
+/// Long-running operations which mark a split as busy.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SplitOperation {
+    IndexingTrack,
+    LoadingTrack,
+    UpdatingFocusedRegion,
+}
+
+impl fmt::Display for SplitOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SplitOperation::IndexingTrack => write!(f, "indexing track"),
+            SplitOperation::LoadingTrack => write!(f, "loading track"),
+            SplitOperation::UpdatingFocusedRegion => write!(f, "updating focused region"),
+        }
+    }
+}
+
+/// Returned when a state-mutating operation is attempted on a split which is already busy.
+#[derive(Debug, Error)]
+#[error("Split {split_id} is busy ({operation})")]
+pub struct SplitBusyError {
+    pub split_id: SplitId,
+    pub operation: SplitOperation,
+}
+
 #[derive(Debug)]
 pub enum BoundState {
     OutsideBuffered,
@@ -28,8 +61,13 @@ pub enum BoundState {
     OutsideRenderRange,
 }
 
-fn map_seqview_to_string(opt_seqview: &Option<SequenceView>) -> Result<Option<String>> {
-    opt_seqview.as_ref().map(|sv| sv.to_string()).transpose()
+fn map_seqview_to_string(opt_seqview: Option<&SequenceView>) -> Result<Option<String>> {
+    opt_seqview.map(|sv| sv.to_string()).transpose()
+}
+
+/// Same as map_seqview_to_string but sequences which must be fetched in chunks are excluded.
+fn map_inline_seqview_to_string(opt_seqview: Option<&SequenceView>) -> Result<Option<String>> {
+    map_seqview_to_string(opt_seqview.filter(|sv| sv.end() - sv.start() <= SEQUENCE_CHUNK_LENGTH))
 }
 
 /// Get the region around the focused region which should be buffered.
@@ -62,48 +100,111 @@ pub fn get_refresh_bound_region(
     Ok(expanded)
 }
 
+/// Immutable copy of a split's buffered region state.
+///
+/// Long-running operations (e.g reading alignments) work from a snapshot so that they don't need to
+/// hold a lock on the split while they run.
+#[derive(Clone, Debug)]
+pub struct BufferedRegionSnapshot {
+    pub focused_region: GenomicRegion,
+    pub buffered_region: GenomicRegion,
+    pub buffered_sequence: Option<Arc<SequenceView>>,
+}
+
+/// Precomputed state for an update to a split's focused region.
+///
+/// This is computed without mutating the split so that the reference sequence can be read without
+/// holding a write lock.
+#[derive(Debug)]
+pub struct FocusedRegionUpdate {
+    focused_region: GenomicRegion,
+    buffered_region: GenomicRegion,
+    refresh_bound_region: GenomicRegion,
+    buffered_sequence: Option<Arc<SequenceView>>,
+}
+
+/// User-settable name and notes for a split (e.g "Breakpoint A").
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitAnnotation {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Split {
     pub id: SplitId,
+    #[serde(flatten)]
+    pub annotation: SplitAnnotation,
     pub focused_region: GenomicRegion,
     pub buffered_region: GenomicRegion,
     pub refresh_bound_region: GenomicRegion,
     #[serde(skip_serializing)]
     pub max_render_window: u64,
     #[serde(skip_serializing)]
-    pub buffered_sequence: Option<SequenceView>,
+    pub buffered_sequence: Option<Arc<SequenceView>>,
+    #[serde(skip_serializing)]
+    ref_seq_reader: SequenceReader,
+    /// Set if a load of the buffered region was cancelled part way through, in which case the
+    /// alignments of some tracks may not match the buffered region until it's loaded again.
     #[serde(skip_serializing)]
-    ref_seq_reader: FastaReader,
+    buffer_stale: bool,
 }
 
 impl Split {
-    pub fn new<P: Into<PathBuf>>(
-        reference_path: P,
+    pub fn new(
+        ref_seq_reader: SequenceReader,
         focused_region: GenomicRegion,
         max_render_window: u64,
         // TODO seq_length should be fetched cached in ref_seq_reader
         seq_length: u64,
     ) -> Result<Self> {
-        let mut ref_seq_reader = FastaReader::new(reference_path)?;
+        let buffered_region = get_buffered_region(&focused_region, seq_length)?;
+        let refresh_bound_region = get_refresh_bound_region(&focused_region, seq_length)?;
         let mut buffered_sequence = None;
+        // The whole buffered region is read (not just the focused region) so that the buffered
+        // sequence matches the buffered region, as it does after the focused region is updated.
         if focused_region.len() <= max_render_window {
-            buffered_sequence = Some(ref_seq_reader.read(&focused_region)?);
+            buffered_sequence = Some(Arc::new(ref_seq_reader.read(&buffered_region)?));
         }
-        let buffered_region = get_buffered_region(&focused_region, seq_length)?;
-        let refresh_bound_region = get_refresh_bound_region(&focused_region, seq_length)?;
 
         Ok(Self {
             id: SplitId::new(),
+            annotation: SplitAnnotation::default(),
             focused_region,
             buffered_region,
             buffered_sequence,
             refresh_bound_region,
             max_render_window,
             ref_seq_reader,
+            buffer_stale: false,
         })
     }
 
+    /// Initialize a split which shares the buffered sequence of an existing split.
+    pub fn from_sibling(sibling: &Split, ref_seq_reader: SequenceReader) -> Self {
+        Self {
+            id: SplitId::new(),
+            annotation: SplitAnnotation::default(),
+            focused_region: sibling.focused_region.clone(),
+            buffered_region: sibling.buffered_region.clone(),
+            buffered_sequence: sibling.buffered_sequence.clone(),
+            refresh_bound_region: sibling.refresh_bound_region.clone(),
+            max_render_window: sibling.max_render_window,
+            ref_seq_reader,
+            buffer_stale: sibling.buffer_stale,
+        }
+    }
+
+    pub fn snapshot(&self) -> BufferedRegionSnapshot {
+        BufferedRegionSnapshot {
+            focused_region: self.focused_region.clone(),
+            buffered_region: self.buffered_region.clone(),
+            buffered_sequence: self.buffered_sequence.clone(),
+        }
+    }
+
     pub fn focused_sequence(&self) -> Result<Option<SequenceView>> {
         let seq = self
             .buffered_sequence
@@ -116,11 +217,44 @@ impl Split {
     }
 
     pub fn focused_sequence_as_string(&self) -> Result<Option<String>> {
-        map_seqview_to_string(&self.focused_sequence()?)
+        map_seqview_to_string(self.focused_sequence()?.as_ref())
     }
 
     pub fn buffered_sequence_as_string(&self) -> Result<Option<String>> {
-        map_seqview_to_string(&self.buffered_sequence)
+        map_seqview_to_string(self.buffered_sequence.as_deref())
+    }
+
+    /// True if the buffered sequence is too long to be sent to the frontend in a single payload.
+    pub fn is_buffered_sequence_chunked(&self) -> bool {
+        self.buffered_region.len() > SEQUENCE_CHUNK_LENGTH
+    }
+
+    /// Focused sequence as a string, excluded if it must be fetched in chunks.
+    pub fn inline_focused_sequence_as_string(&self) -> Result<Option<String>> {
+        map_inline_seqview_to_string(self.focused_sequence()?.as_ref())
+    }
+
+    /// Buffered sequence as a string, excluded if it must be fetched in chunks.
+    pub fn inline_buffered_sequence_as_string(&self) -> Result<Option<String>> {
+        map_inline_seqview_to_string(self.buffered_sequence.as_deref())
+    }
+
+    /// Read a chunk of the reference sequence from within the buffered region.
+    ///
+    /// The chunk is taken from the buffered sequence if it's loaded, otherwise it's read from the
+    /// reference file (e.g when the region is too large to buffer).
+    pub fn read_sequence_chunk(&self, region: &GenomicRegion) -> Result<String> {
+        if region.len() > SEQUENCE_CHUNK_LENGTH {
+            bail!("Sequence chunks must be <= {}bp (requested {})", SEQUENCE_CHUNK_LENGTH, region);
+        }
+        if !self.buffered_region.contains(region.clone()) {
+            bail!("Region {} is outside of the buffered region for split={}", region, self.id);
+        }
+        let seqview = match &self.buffered_sequence {
+            Some(seqview) => seqview.subseq(region.start(), region.end())?,
+            None => self.ref_seq_reader.read(region)?,
+        };
+        seqview.to_string()
     }
 
     pub fn set_max_render_window(&mut self, max_render_window: u64) -> Result<()> {
@@ -132,7 +266,8 @@ impl Split {
             }
             None => {
                 if self.focused_region.len() <= max_render_window {
-                    self.buffered_sequence = Some(self.ref_seq_reader.read(&self.buffered_region)?);
+                    let sequence = self.ref_seq_reader.read(&self.buffered_region)?;
+                    self.buffered_sequence = Some(Arc::new(sequence));
                 }
             }
         };
@@ -143,7 +278,8 @@ impl Split {
     pub fn check_bounds(&self, region: &GenomicRegion) -> BoundState {
         if region.len() > self.max_render_window {
             BoundState::OutsideRenderRange
-        } else if region.seq_name != self.buffered_region.seq_name
+        } else if self.buffer_stale
+            || region.seq_name != self.buffered_region.seq_name
             || region.start() < self.buffered_region.start()
             || region.end() > self.buffered_region.end()
             || self.buffered_region.len() > self.max_render_window
@@ -158,32 +294,134 @@ impl Split {
         }
     }
 
-    /// Set the focused region of a split.
+    /// Compute the state required to set the focused region of a split.
     ///
     /// # Arguments
     ///
     /// * `focused_region` - Focused genomic region.
     /// * `seq_length` - The length of the focused contig/chromosome (i.e the max possible end
     ///     position for a genomic region on that contig/chromosome).
-    pub fn set_focused_region(
-        &mut self,
+    pub fn prepare_focused_region(
+        &self,
         focused_region: GenomicRegion,
         // TODO seq_length should be fetched cached in ref_seq_reader
         seq_length: u64,
-    ) -> Result<()> {
+    ) -> Result<FocusedRegionUpdate> {
         let buffered_region = get_buffered_region(&focused_region, seq_length)?;
         let refresh_bound_region = get_refresh_bound_region(&focused_region, seq_length)?;
-        match self.check_bounds(&focused_region) {
+        let buffered_sequence = match self.check_bounds(&focused_region) {
             BoundState::OutsideBuffered
             | BoundState::OutsideRefreshBound
             | BoundState::WithinRefreshBound => {
-                self.buffered_sequence = Some(self.ref_seq_reader.read(&buffered_region)?);
+                Some(Arc::new(self.ref_seq_reader.read(&buffered_region)?))
             }
-            BoundState::OutsideRenderRange => self.buffered_sequence = None,
-        }
-        self.buffered_region = buffered_region;
-        self.refresh_bound_region = refresh_bound_region;
-        self.focused_region = focused_region;
+            BoundState::OutsideRenderRange => None,
+        };
+        Ok(FocusedRegionUpdate {
+            focused_region,
+            buffered_region,
+            refresh_bound_region,
+            buffered_sequence,
+        })
+    }
+
+    /// Apply a focused region update which was computed with prepare_focused_region.
+    pub fn apply_focused_region(&mut self, update: FocusedRegionUpdate) {
+        self.buffered_sequence = update.buffered_sequence;
+        self.buffered_region = update.buffered_region;
+        self.refresh_bound_region = update.refresh_bound_region;
+        self.focused_region = update.focused_region;
+        self.buffer_stale = false;
+    }
+
+    /// Mark the buffered region as needing to be loaded again (e.g because its load was
+    /// cancelled), so that the next update of the focused region is treated as being outside of it.
+    pub fn mark_buffer_stale(&mut self) {
+        self.buffer_stale = true;
+    }
+
+    pub fn is_buffer_stale(&self) -> bool {
+        self.buffer_stale
+    }
+
+    /// Set the focused region of a split.
+    ///
+    /// # Arguments
+    ///
+    /// * `focused_region` - Focused genomic region.
+    /// * `seq_length` - The length of the focused contig/chromosome (i.e the max possible end
+    ///     position for a genomic region on that contig/chromosome).
+    pub fn set_focused_region(
+        &mut self,
+        focused_region: GenomicRegion,
+        // TODO seq_length should be fetched cached in ref_seq_reader
+        seq_length: u64,
+    ) -> Result<()> {
+        let update = self.prepare_focused_region(focused_region, seq_length)?;
+        self.apply_focused_region(update);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::file_formats::fasta::reader::FastaReader;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    fn test_read_sequence_chunk() {
+        let fasta_path = get_test_data_path("fake-genome.fa");
+        let focused_region = GenomicRegion::new("euk_genes", 1000, 2000).unwrap();
+        let get_reader = || {
+            SequenceReader::Indexed(Box::new(Mutex::new(FastaReader::new(&fasta_path).unwrap())))
+        };
+        let buffered_split = Split::new(get_reader(), focused_region.clone(), 10000, 7185).unwrap();
+        // Regions larger than the max render window aren't buffered so chunks are read from file
+        let unbuffered_split = Split::new(get_reader(), focused_region, 100, 7185).unwrap();
+        assert!(unbuffered_split.buffered_sequence.is_none());
+        let chunk_region = GenomicRegion::new("euk_genes", 500, 2500).unwrap();
+        let chunk = buffered_split.read_sequence_chunk(&chunk_region).unwrap();
+        assert_eq!(chunk.len(), 2000);
+        assert_eq!(unbuffered_split.read_sequence_chunk(&chunk_region).unwrap(), chunk);
+        let outside_region = GenomicRegion::new("euk_genes", 5000, 5100).unwrap();
+        assert!(buffered_split.read_sequence_chunk(&outside_region).is_err());
+    }
+
+    #[test]
+    fn test_new_split_buffers_buffered_region() {
+        let fasta_path = get_test_data_path("fake-genome.fa");
+        let mut reader = FastaReader::new(&fasta_path).unwrap();
+        let focused_region = GenomicRegion::new("euk_genes", 1000, 2000).unwrap();
+        let split = Split::new(
+            SequenceReader::Indexed(Box::new(Mutex::new(FastaReader::new(&fasta_path).unwrap()))),
+            focused_region.clone(),
+            10000,
+            7185,
+        )
+        .unwrap();
+        assert!(split.buffered_region.len() > focused_region.len());
+        let buffered_sequence = split.buffered_sequence.as_ref().unwrap().to_string().unwrap();
+        let expected = reader.read(&split.buffered_region).unwrap().to_string().unwrap();
+        assert_eq!(buffered_sequence, expected);
+        let expected = reader.read(&focused_region).unwrap().to_string().unwrap();
+        assert_eq!(split.focused_sequence_as_string().unwrap().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_stale_buffer_is_reloaded() {
+        let fasta_path = get_test_data_path("fake-genome.fa");
+        let reader =
+            SequenceReader::Indexed(Box::new(Mutex::new(FastaReader::new(fasta_path).unwrap())));
+        let focused_region = GenomicRegion::new("euk_genes", 1000, 2000).unwrap();
+        let mut split = Split::new(reader, focused_region.clone(), 10000, 7185).unwrap();
+        assert!(matches!(split.check_bounds(&focused_region), BoundState::WithinRefreshBound));
+        split.mark_buffer_stale();
+        assert!(matches!(split.check_bounds(&focused_region), BoundState::OutsideBuffered));
+        split.set_focused_region(focused_region.clone(), 7185).unwrap();
+        assert!(matches!(split.check_bounds(&focused_region), BoundState::WithinRefreshBound));
+    }
+}
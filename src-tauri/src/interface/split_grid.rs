@@ -1,28 +1,91 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use dashmap::mapref::entry::Entry;
 use dashmap::mapref::one::Ref;
-use dashmap::DashMap;
-use parking_lot::RwLock;
+use dashmap::{DashMap, DashSet};
+use parking_lot::{Mutex, RwLock};
 use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use serde::{Deserialize, Serialize};
 
-use crate::alignments::stack_reader::StackReader;
+use crate::alignments::alignment_reader::AlignmentReader;
+use crate::alignments::cancellation::{is_cancelled_error, CancellationToken};
+use crate::alignments::diff_labels::{place_diff_labels, DiffLabel};
+use crate::alignments::field_mask::AlignmentFieldMask;
+use crate::alignments::render_window::tune_render_window;
+use crate::alignments::sorting::SortBy;
+use crate::alignments::stack_reader::{ReadAtPosition, StackReader};
+use crate::bio_util::coverage_alerts::{evaluate_coverage_rules, CoverageRule};
+use crate::bio_util::formatting::{format_length, format_region};
 use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::bio_util::reference_check::check_reference_contigs;
 use crate::bio_util::refseq::{get_default_reference, ReferenceSequence};
+use crate::bio_util::sequence::SequenceView;
+use crate::bio_util::view_summary::ViewSummary;
+use crate::file_formats::enums::{get_file_kind, AlignmentStackKind, FileKind};
+use crate::file_formats::fastq::alignment::align_read;
+use crate::file_formats::fastq::reader::FastqRead;
+use crate::file_formats::nanopore::summary::SequencingSummary;
+use crate::file_formats::remote::is_remote;
+use crate::file_formats::sam_bam::diff::SequenceDiff;
+use crate::file_formats::sam_bam::header::get_seq_dictionary;
+use crate::file_formats::sam_bam::index::{build_bam_index, find_bam_index};
+use crate::file_formats::sam_bam::pileup::{Coverage, Pileup};
+use crate::file_formats::sam_bam::read_details::ReadDetails;
+use crate::file_formats::sam_bam::read_lengths::{
+    ReadLengthHistogram, ReadLengthScope, MAX_READ_LENGTH_SAMPLES,
+};
+use crate::file_formats::sam_bam::reader::{
+    BamReader, ReadFilter, TagGroup, TagValues, UnmappedRead,
+};
+use crate::file_formats::sam_bam::samples::{get_read_groups, group_read_groups, ReadGroupField};
+use crate::file_formats::sam_bam::softclip::{cluster_softclips, SoftClipCluster};
+use crate::file_formats::tabix::record::IntervalColumns;
 use crate::interface::events::{
-    AlignmentsUpdatedPayload, EmitEvent, Event, FocusedRegionUpdatedPayload,
-    FocusedSequenceUpdatedPayload, RegionBufferingPayload,
+    AlignmentsAppendedPayload, AlignmentsUpdatedPayload, ContigChangedPayload,
+    CoverageUpdatedPayload, EmitEvent, Event, FocusedRegionLoadedPayload,
+    FocusedRegionUpdatedPayload, FocusedSequenceUpdatedPayload, IndexProgressPayload, IndexStatus,
+    QualityAlertPayload, ReadsDownsampledPayload, ReferenceMismatchPayload, RegionBufferingPayload,
+    SignalUpdatedPayload, SplitBusyPayload, SplitIdlePayload,
 };
-use crate::interface::split::{BoundState, Split, SplitId};
-use crate::interface::track::{AlignmentTrack, Track, TrackId};
+use crate::interface::lifecycle::{skip_removed, GridLifecycle};
+use crate::interface::payload_encoding::{encode_payload, EncodedPayload};
+use crate::interface::region_loads::{RegionLoad, RegionLoads};
+use crate::interface::schema::{get_emitted_schema_version, ALIGNMENTS_APPENDED_SCHEMA_VERSION};
+use crate::interface::split::{
+    BoundState, BufferedRegionSnapshot, Split, SplitAnnotation, SplitBusyError, SplitId,
+    SplitOperation,
+};
+use crate::interface::track::{AlignmentTrack, Track, TrackCustomization, TrackId, TrackPriority};
 use crate::util::Direction;
 
+/// Number of threads which read alignments for focused region updates in the background. Each
+/// split only runs one update at a time.
+const REGION_LOAD_THREADS: usize = 4;
+
+/// The part of the grid which is targeted by keyboard input.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FocusTarget {
+    /// An entire split (i.e column).
+    Split,
+    /// An entire track lane (i.e row).
+    Track,
+    /// A single track within a single split.
+    #[default]
+    Cell,
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GridCoord {
-    track_id: Option<TrackId>,
-    split_id: SplitId,
+    pub track_id: Option<TrackId>,
+    pub split_id: SplitId,
+    #[serde(default)]
+    pub target: FocusTarget,
 }
 
 #[derive(Debug)]
@@ -31,28 +94,92 @@ pub struct SplitGrid {
     pub tracks: DashMap<TrackId, RwLock<Track>>,
     pub reference: RwLock<ReferenceSequence>,
     pub focus: RwLock<GridCoord>,
-    alignments: DashMap<(TrackId, SplitId), RwLock<StackReader>>,
+    /// Stack readers are reference counted so that they can be used without holding a lock on the
+    /// map while alignments are read from disk.
+    alignments: DashMap<(TrackId, SplitId), Arc<StackReader>>,
     max_render_window: RwLock<u64>,
+    /// Split ids in the order in which they are displayed in the grid.
+    split_order: RwLock<Vec<SplitId>>,
+    /// Track ids in the order in which they are displayed in the grid (i.e the order in which they
+    /// were added).
+    track_order: RwLock<Vec<TrackId>>,
+    /// Splits which are currently running a long operation, mapped to the operation.
+    busy_splits: DashMap<SplitId, SplitOperation>,
+    /// Rules which are checked against the focused region of each SAM/BAM track whenever it
+    /// changes.
+    coverage_rules: RwLock<Vec<CoverageRule>>,
+    /// Target for the number of alignments rendered in each track/split. If set, the render window
+    /// of each track is tuned to its read density rather than only using max_render_window.
+    target_alignments_per_window: RwLock<Option<u64>>,
+    /// Render windows tuned to the read density of each track.
+    track_render_windows: DashMap<TrackId, u64>,
+    /// Track/splits whose alignments are hidden because the split's focused region is larger than
+    /// the track's tuned render window.
+    hidden_alignments: DashSet<(TrackId, SplitId)>,
+    /// Splits and tracks which have been removed, so that work which was in progress for them
+    /// doesn't leave stale state behind.
+    lifecycle: GridLifecycle,
+    /// Held while searching for another split's alignments to share and claiming a region to read,
+    /// so that splits which need overlapping regions at the same time only read them once.
+    fetch_lock: Mutex<()>,
+    /// Queued focused region loads of each split, so that loads which are superseded by a newer
+    /// load of the same split can be cancelled or skipped.
+    region_loads: DashMap<SplitId, Arc<RegionLoads>>,
+    /// Threads which read the alignments of focused region updates in the background.
+    load_pool: ThreadPool,
 }
 
 impl SplitGrid {
     pub fn new(max_render_window: u64) -> Result<Self> {
-        let reference = RwLock::new(get_default_reference()?);
+        Self::from_reference(get_default_reference()?, max_render_window)
+    }
+
+    /// Initialize a grid containing a single split on a reference sequence.
+    pub fn from_reference(reference: ReferenceSequence, max_render_window: u64) -> Result<Self> {
+        let reference = RwLock::new(reference);
         let default_focused_region = reference.read().default_focused_region.clone();
         let splits = DashMap::new();
         let tracks = DashMap::new();
-        let seq_length = default_focused_region.end();
+        let seq_length = reference.read().get_seq_length(&default_focused_region.seq_name)?;
         let split = Split::new(
-            reference.read().path.clone(),
+            reference.read().get_reader()?,
             default_focused_region,
             max_render_window,
             seq_length,
         )?;
-        let focus = RwLock::new(GridCoord { track_id: None, split_id: split.id });
+        let focus = RwLock::new(GridCoord {
+            track_id: None,
+            split_id: split.id,
+            target: FocusTarget::Split,
+        });
+        let split_order = RwLock::new(vec![split.id]);
         splits.insert(split.id, RwLock::new(split));
         let alignments = DashMap::new();
         let max_render_window = RwLock::new(max_render_window);
-        Ok(Self { splits, tracks, reference, alignments, max_render_window, focus })
+        Ok(Self {
+            splits,
+            tracks,
+            reference,
+            alignments,
+            max_render_window,
+            focus,
+            split_order,
+            track_order: RwLock::new(Vec::new()),
+            busy_splits: DashMap::new(),
+            coverage_rules: RwLock::new(Vec::new()),
+            target_alignments_per_window: RwLock::new(None),
+            track_render_windows: DashMap::new(),
+            hidden_alignments: DashSet::new(),
+            lifecycle: GridLifecycle::new(),
+            fetch_lock: Mutex::new(()),
+            region_loads: DashMap::new(),
+            load_pool: ThreadPoolBuilder::new()
+                .num_threads(REGION_LOAD_THREADS)
+                .thread_name(|index| format!("region-load-{}", index))
+                // Without a handler, a panic in a spawned load aborts the process
+                .panic_handler(|_| log::error!("Split grid load panicked"))
+                .build()?,
+        })
     }
 
     pub fn set_max_render_window(&self, max_render_window: u64) -> Result<()> {
@@ -63,15 +190,43 @@ impl SplitGrid {
         Ok(())
     }
 
+    pub fn set_coverage_rules(&self, coverage_rules: Vec<CoverageRule>) {
+        *self.coverage_rules.write() = coverage_rules;
+    }
+
+    /// Tune the render window of each track so that roughly `target` alignments are rendered, or
+    /// disable tuning if `target` is None.
+    ///
+    /// Tuned render windows are estimated from the alignments read into each track and take
+    /// effect the next time that a split's alignments are updated.
+    pub fn set_target_alignments_per_window(&self, target: Option<u64>) {
+        *self.target_alignments_per_window.write() = target;
+        self.track_render_windows.clear();
+    }
+
+    /// Get the tuned render window of a track, or None if render windows aren't being tuned.
+    pub fn get_track_render_window(&self, track_id: &TrackId) -> Option<u64> {
+        self.target_alignments_per_window.read().map(|_| {
+            self.track_render_windows
+                .get(track_id)
+                .map(|window| *window)
+                .unwrap_or_else(|| *self.max_render_window.read())
+        })
+    }
+
     pub fn get_stack_reader(
         &self,
         split_id: &SplitId,
         track_id: &TrackId,
-    ) -> Result<Ref<(TrackId, SplitId), RwLock<StackReader>>> {
-        let stack_reader = self.alignments.get(&(*track_id, *split_id)).with_context(|| {
-            format!("Failed to find a stack reader for track={}, split={}", track_id, split_id)
-        })?;
-        Ok(stack_reader)
+    ) -> Result<Arc<StackReader>> {
+        let stack_reader = match self.alignments.get(&(*track_id, *split_id)) {
+            Some(stack_reader) => stack_reader,
+            None => {
+                self.lifecycle.check(split_id, track_id)?;
+                bail!("Failed to find a stack reader for track={}, split={}", track_id, split_id)
+            }
+        };
+        Ok(Arc::clone(&stack_reader))
     }
 
     pub fn get_split(&self, split_id: &SplitId) -> Result<Ref<SplitId, RwLock<Split>>> {
@@ -82,408 +237,2825 @@ impl SplitGrid {
         Ok(focused_region_manager)
     }
 
-    fn update_alignments(&self, split_id: &SplitId, track_id: &TrackId) -> Result<()> {
-        let stack_reader = self.get_stack_reader(split_id, track_id)?;
-        let split = self.get_split(split_id)?;
-        let buffered_region = split.read().buffered_region.clone();
-
-        // Cloning here so that the focused_region_manager isn't write-locked while alignments are
-        // being read. Users need to be able to update the focused region even if we are currently
-        // reading from a bam file.
-        let buffered_sequence = split.read().buffered_sequence.clone();
-        match buffered_sequence {
-            Some(seq) => stack_reader.write().read_stacked(&buffered_region, &seq)?,
-            None => stack_reader.write().clear_stack(&buffered_region)?,
+    /// Mark a split as busy so that other state-mutating operations are rejected until it is idle.
+    fn mark_busy<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        split_id: &SplitId,
+        operation: SplitOperation,
+    ) -> Result<()> {
+        match self.busy_splits.entry(*split_id) {
+            Entry::Occupied(entry) => {
+                return Err(SplitBusyError { split_id: *split_id, operation: *entry.get() }.into());
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(operation);
+            }
         }
+        event_emitter
+            .emit(Event::SplitBusy, SplitBusyPayload { split_id, operation: &operation })?;
         Ok(())
     }
 
-    fn add_stack_reader(
-        &self,
-        file_path: &PathBuf,
-        split_id: &SplitId,
-        track_id: &TrackId,
-    ) -> Result<()> {
-        let stack_reader = StackReader::new(file_path)?;
-        self.alignments.insert((*track_id, *split_id), RwLock::new(stack_reader));
+    fn mark_idle<E: EmitEvent>(&self, event_emitter: &E, split_id: &SplitId) -> Result<()> {
+        if self.busy_splits.remove(split_id).is_some() {
+            event_emitter.emit(Event::SplitIdle, SplitIdlePayload { split_id })?;
+        }
         Ok(())
     }
 
-    fn get_split_ids(&self) -> Vec<SplitId> {
-        self.splits.iter().map(|entry| *entry.key()).collect()
+    /// Run an operation with a set of splits marked as busy.
+    ///
+    /// Fails with a SplitBusyError if any of the splits is already busy. The splits are always
+    /// marked idle again once the operation completes, even if it fails.
+    fn run_busy<E: EmitEvent, T, F: FnOnce() -> Result<T>>(
+        &self,
+        event_emitter: &E,
+        split_ids: &[SplitId],
+        operation: SplitOperation,
+        func: F,
+    ) -> Result<T> {
+        let mut marked = Vec::with_capacity(split_ids.len());
+        let mut result = Ok(());
+        for split_id in split_ids {
+            result = self.mark_busy(event_emitter, split_id, operation);
+            if result.is_err() {
+                break;
+            }
+            marked.push(*split_id);
+        }
+        let result = result.and_then(|_| func());
+        for split_id in marked.iter() {
+            if let Err(err) = self.mark_idle(event_emitter, split_id) {
+                log::error!("Failed to mark split {} as idle: {:#}", split_id, err);
+            }
+        }
+        result
     }
 
-    fn init_track_alignments(&self, track_id: &TrackId) -> Result<()> {
-        let track = self
-            .tracks
-            .get_mut(track_id)
-            .context(format!("Failed to find track for id={}", &track_id))?;
-        let file_path = track.read().file_path().clone();
-        drop(track);
+    pub fn is_busy(&self, split_id: &SplitId) -> bool {
+        self.busy_splits.contains_key(split_id)
+    }
 
-        let split_ids = self.get_split_ids();
-        for split_id in split_ids.iter() {
-            self.add_stack_reader(&file_path, split_id, track_id)?;
+    /// Compute per-position nucleotide counts from the stacked alignments for a track/split.
+    ///
+    /// The region must fall within the split's buffered region.
+    pub fn get_pileup(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        region: &GenomicRegion,
+    ) -> Result<Pileup> {
+        let snapshot = self.get_split(split_id)?.read().snapshot();
+        if !snapshot.buffered_region.contains(region.clone()) {
+            bail!("Region {} is outside of the buffered region for split={}", region, split_id);
         }
-        split_ids
-            .par_iter()
-            .map(|split_id| {
-                self.update_alignments(split_id, track_id)?;
-                Ok(())
-            })
-            .collect::<Result<_>>()?;
-        Ok(())
+        let buffered_sequence = snapshot.buffered_sequence.as_ref().with_context(|| {
+            format!("Alignments are not loaded for split={} at the current zoom level", split_id)
+        })?;
+        let stack = self.get_stack_reader(split_id, track_id)?.stack();
+        let pileup = match &*stack.read() {
+            AlignmentStackKind::AlignedPairKind(stack) => Pileup::from_reads(
+                region,
+                stack.alignments().flat_map(|pair| pair.reads()),
+                buffered_sequence,
+            )?,
+            AlignmentStackKind::PafRecordKind(_) | AlignmentStackKind::IntervalRecordKind(_) => {
+                bail!("Pileups are only available for SAM/BAM tracks")
+            }
+        };
+        Ok(pileup)
     }
 
-    fn update_split_alignments(&self, split_id: &SplitId) -> Result<()> {
-        let split = self.get_split(split_id)?;
-        self.tracks
-            .par_iter()
-            .map(|entry| {
-                let stack_reader = self.get_stack_reader(&split.read().id, entry.key())?;
-                match &split.read().buffered_sequence {
-                    Some(buffered_sequence) => stack_reader
-                        .write()
-                        .read_stacked(&split.read().buffered_region, buffered_sequence),
-                    None => stack_reader.write().clear_stack(&split.read().buffered_region),
-                }
-            })
-            .collect()
+    /// Compute the nucleotide counts of a track in a region of a split.
+    ///
+    /// Counted from the track/split's stack if the region is buffered, otherwise the alignments
+    /// are read from the file (e.g for a region which was just panned out of the buffer).
+    pub fn get_or_read_pileup(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        region: &GenomicRegion,
+    ) -> Result<Pileup> {
+        let snapshot = self.get_split(split_id)?.read().snapshot();
+        if snapshot.buffered_sequence.is_some() && snapshot.buffered_region.contains(region.clone())
+        {
+            return self.get_pileup(split_id, track_id, region);
+        }
+        let stack_reader = self.get_stack_reader(split_id, track_id)?;
+        if !matches!(&*stack_reader.stack().read(), AlignmentStackKind::AlignedPairKind(_)) {
+            bail!("Pileups are only available for SAM/BAM tracks");
+        }
+        let region = self.reference.read().resolve_region(region.clone());
+        let seq = self.reference.read().get_reader()?.read(&region)?;
+        let (reads, _) = BamReader::new(&stack_reader.path)?.read(&region, &seq)?;
+        Pileup::from_reads(&region, &reads, &seq)
     }
 
-    pub fn add_track<E: EmitEvent, P: Into<PathBuf>>(
+    /// Decide which diff labels fit in the focused region of a track/split when it's drawn with
+    /// bases `nuc_width` px wide (e.g for exporting a figure).
+    pub fn get_diff_labels(
         &self,
-        event_emitter: &E,
-        file_path: P,
-    ) -> Result<TrackId> {
-        let file_path: PathBuf = file_path.into();
-        log::info!("Adding alignment track for {}", file_path.to_string_lossy().to_string());
-        let track = Track::Alignment(AlignmentTrack::new(file_path)?);
-        let track_id = track.id();
-        self.tracks.insert(track.id(), RwLock::new(track));
-        self.init_track_alignments(&track_id)?;
-        let track = self.tracks.get(&track_id).unwrap();
-        if self.tracks.len() == 1 {
-            self.focus.write().track_id = Some(track.read().id());
-            event_emitter.emit(Event::GridFocusUpdated, &*self.focus.read())?;
-        }
-        event_emitter.emit(Event::TrackAdded, &*track.read())?;
-        Ok(track_id)
+        split_id: &SplitId,
+        track_id: &TrackId,
+        nuc_width: f64,
+    ) -> Result<Vec<DiffLabel>> {
+        let focused_region = self.get_split(split_id)?.read().focused_region.clone();
+        let stack = self.get_stack_reader(split_id, track_id)?.stack();
+        let labels = match &*stack.read() {
+            AlignmentStackKind::AlignedPairKind(stack) => place_diff_labels(
+                stack.rows.iter().map(|row| row.iter().flat_map(|pair| pair.reads())),
+                &focused_region,
+                nuc_width,
+            ),
+            AlignmentStackKind::PafRecordKind(_) | AlignmentStackKind::IntervalRecordKind(_) => {
+                bail!("Diff labels are only available for SAM/BAM tracks")
+            }
+        };
+        Ok(labels)
     }
 
-    fn get_default_focused_region(&self) -> Result<GenomicRegion> {
-        let focused_region = if !self.splits.is_empty() {
-            self.get_split(&self.focus.read().split_id)
-                .context(format!("Failed to find split id={}", &self.focus.read().split_id))?
-                .read()
-                .focused_region
-                .clone()
-        } else {
-            self.reference.read().default_focused_region.clone()
+    /// Summarize the alignments of a track in the focused region of a split as text (e.g for
+    /// screen readers).
+    pub fn get_view_summary(&self, split_id: &SplitId, track_id: &TrackId) -> Result<ViewSummary> {
+        let snapshot = self.get_split(split_id)?.read().snapshot();
+        let pileup = self.get_pileup(split_id, track_id, &snapshot.focused_region)?;
+        let buffered_sequence = snapshot.buffered_sequence.as_ref().with_context(|| {
+            format!("Alignments are not loaded for split={} at the current zoom level", split_id)
+        })?;
+        let stack = self.get_stack_reader(split_id, track_id)?.stack();
+        let summary = match &*stack.read() {
+            AlignmentStackKind::AlignedPairKind(stack) => {
+                ViewSummary::new(&pileup, buffered_sequence, stack.alignments())?
+            }
+            AlignmentStackKind::PafRecordKind(_) | AlignmentStackKind::IntervalRecordKind(_) => {
+                bail!("View summaries are only available for SAM/BAM tracks")
+            }
         };
-        Ok(focused_region)
+        Ok(summary)
     }
 
-    pub fn add_split<E: EmitEvent>(
+    /// Compute the read length distribution of a track, from either the reads which overlap the
+    /// focused region of a split or from the reads at the start of the file.
+    pub fn get_read_length_histogram(
         &self,
-        event_emitter: &E,
-        focused_region: Option<GenomicRegion>,
-    ) -> Result<SplitId> {
-        let focused_region = match focused_region {
-            Some(region) => region,
-            None => self.get_default_focused_region()?,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        scope: ReadLengthScope,
+        num_bins: usize,
+    ) -> Result<ReadLengthHistogram> {
+        let stack_reader = self.get_stack_reader(split_id, track_id)?;
+        let region = match scope {
+            ReadLengthScope::FocusedRegion => {
+                Some(self.get_split(split_id)?.read().focused_region.clone())
+            }
+            ReadLengthScope::File => None,
         };
-        let seq_length = self.reference.read().get_seq_length(&focused_region.seq_name)?;
-        let split = Split::new(
-            self.reference.read().path.clone(),
-            focused_region,
-            *self.max_render_window.read(),
-            seq_length,
-        )?;
-        self.focus.write().split_id = split.id;
-        let split_id = split.id;
-        self.splits.insert(split.id, RwLock::new(split));
-        let tracks_info: Vec<(TrackId, PathBuf)> = self
+        let (lengths, is_estimate) =
+            stack_reader.sample_read_lengths(region.as_ref(), MAX_READ_LENGTH_SAMPLES)?;
+        Ok(ReadLengthHistogram::new(&lengths, num_bins, region, is_estimate))
+    }
+
+    /// Compute the read depth across the buffered region of a track/split, split by strand if the
+    /// track's coverage strand isn't Combined.
+    ///
+    /// None unless the track is a SAM/BAM track with alignments loaded at the current zoom level.
+    pub fn get_coverage(&self, split_id: &SplitId, track_id: &TrackId) -> Result<Option<Coverage>> {
+        let strand = self
             .tracks
-            .iter()
-            .map(|track| (track.read().id(), track.read().file_path().clone()))
-            .collect();
-        for (track_id, file_path) in tracks_info.iter() {
-            self.add_stack_reader(file_path, &split_id, track_id)?;
-        }
-        tracks_info
-            .par_iter()
-            .map(|(track_id, _)| {
-                self.update_alignments(&split_id, track_id)?;
-                Ok(())
-            })
-            .collect::<Result<_>>()?;
-        let split = self.splits.get(&split_id).unwrap();
-        event_emitter.emit(Event::SplitAdded, &*split.read())?;
-        event_emitter.emit(Event::GridFocusUpdated, &split_id)?;
-        Ok(split_id)
+            .get(track_id)
+            .with_context(|| format!("Failed to find track for id={}", track_id))?
+            .read()
+            .customization()
+            .coverage_strand;
+        let snapshot = self.get_split(split_id)?.read().snapshot();
+        let buffered_sequence = match &snapshot.buffered_sequence {
+            Some(seq) => seq,
+            None => return Ok(None),
+        };
+        let stack = self.get_stack_reader(split_id, track_id)?.stack();
+        let coverage = match &*stack.read() {
+            AlignmentStackKind::AlignedPairKind(stack) => Coverage::from_reads(
+                &snapshot.buffered_region,
+                stack.alignments().flat_map(|pair| pair.reads()),
+                buffered_sequence,
+                strand,
+            )?,
+            AlignmentStackKind::PafRecordKind(_) | AlignmentStackKind::IntervalRecordKind(_) => {
+                return Ok(None)
+            }
+        };
+        Ok(Some(coverage))
     }
 
-    pub fn pan_focused_split<E: EmitEvent>(
+    /// Send the read depth of a track/split to the frontend (if it has any), or its signal values
+    /// if it's a bedGraph/wig track.
+    fn emit_coverage<E: EmitEvent>(
         &self,
         event_emitter: &E,
-        direction: &Direction,
+        split_id: &SplitId,
+        track_id: &TrackId,
     ) -> Result<()> {
-        let focused_split_id = self.focus.read().split_id;
-        let mut updated_region = self.get_split(&focused_split_id)?.read().focused_region.clone();
-        let mut panned_bp = updated_region.len() / 10;
-        match direction {
-            Direction::Left => {
-                if updated_region.start().saturating_sub(panned_bp) == 0 {
-                    panned_bp = updated_region.start();
-                }
-                log::debug!("Panning focused split={} left by {}bp", focused_split_id, panned_bp);
-                updated_region.interval.start -= panned_bp;
-                updated_region.interval.end -= panned_bp;
-            }
-            Direction::Right => {
-                let seq_length = self.reference.read().get_seq_length(&updated_region.seq_name)?;
-                if updated_region.end() + panned_bp > seq_length {
-                    panned_bp = seq_length - updated_region.end();
-                }
-                log::debug!("Panning focused split={} right by {}bp", focused_split_id, panned_bp);
-                updated_region.interval.start += panned_bp;
-                updated_region.interval.end += panned_bp;
-            }
-        };
-        self.update_focused_region(event_emitter, &focused_split_id, updated_region)?;
+        if let Some(coverage) = self.get_coverage(split_id, track_id)? {
+            event_emitter.emit(
+                Event::CoverageUpdated,
+                CoverageUpdatedPayload { split_id, track_id, coverage: &coverage },
+            )?;
+        }
+        if let Some(bins) = self.get_stack_reader(split_id, track_id)?.signal_bins() {
+            event_emitter.emit(
+                Event::SignalUpdated,
+                SignalUpdatedPayload { split_id, track_id, bins: &bins },
+            )?;
+        }
         Ok(())
     }
 
-    pub fn update_grid_focus<E: EmitEvent>(
+    /// Notify the frontend if reads were dropped from a track/split by downsampling.
+    fn emit_downsampling<E: EmitEvent>(
         &self,
         event_emitter: &E,
-        grid_coord: GridCoord,
+        split_id: &SplitId,
+        track_id: &TrackId,
     ) -> Result<()> {
-        if grid_coord == *self.focus.read() {
-            return Ok(());
+        if let Some(downsampling) = self.get_stack_reader(split_id, track_id)?.downsampling() {
+            event_emitter.emit(
+                Event::ReadsDownsampled,
+                ReadsDownsampledPayload {
+                    split_id,
+                    track_id,
+                    downsampling: &downsampling,
+                    sampled_fraction: downsampling.sampled_fraction(),
+                },
+            )?;
         }
-        *self.focus.write() = grid_coord;
-        event_emitter.emit(Event::GridFocusUpdated, self.focus.read().clone())?;
         Ok(())
     }
 
-    pub fn update_focused_region<E: EmitEvent>(
+    /// Check the coverage rules against the pileup of a track/split's focused region.
+    ///
+    /// Only SAM/BAM tracks are checked, and only if alignments are loaded at the current zoom
+    /// level.
+    fn check_coverage<E: EmitEvent>(
         &self,
         event_emitter: &E,
         split_id: &SplitId,
-        genomic_region: GenomicRegion,
+        track_id: &TrackId,
     ) -> Result<()> {
-        log::info!("Updating focused region for split {} to {}", &split_id, &genomic_region);
-        let split = self.get_split(split_id)?;
-        if split.read().focused_region == genomic_region {
+        let coverage_rules = self.coverage_rules.read();
+        if coverage_rules.is_empty() {
             return Ok(());
         }
-        let prev_region_len = split.read().focused_region.len();
-        let seq_length = self.reference.read().get_seq_length(&genomic_region.seq_name)?;
-        let bound_state = split.read().check_bounds(&genomic_region);
-
-        // We notify the frontend of the update before actually making the change on the backend
-        // Need to make sure that the split is write locked until the frontend and backend are back
-        // in sync.
-        let mut split_write_lock = split.write();
-        let focused_region_update_payload =
-            FocusedRegionUpdatedPayload { split_id, genomic_region: &genomic_region };
-        event_emitter.emit(Event::FocusedRegionUpdated, &focused_region_update_payload)?;
-
-        // If the frontend already has the necessary alignments cached we can just inform it that a
-        // zoom or pan is necessay.
-        match &bound_state {
-            BoundState::WithinRefreshBound | BoundState::OutsideRefreshBound => {
-                if genomic_region.len() == prev_region_len {
-                    event_emitter.emit(Event::RegionPanned, &focused_region_update_payload)?;
-                } else {
-                    event_emitter.emit(Event::RegionZoomed, &focused_region_update_payload)?;
-                }
-            }
-            BoundState::OutsideBuffered => {
-                event_emitter.emit(Event::RegionBuffering, RegionBufferingPayload { split_id })?;
-            }
-            _ => (),
+        let is_aligned_pair_track = matches!(
+            &*self.get_stack_reader(split_id, track_id)?.stack().read(),
+            AlignmentStackKind::AlignedPairKind(_)
+        );
+        let split = self.get_split(split_id)?;
+        let focused_region = split.read().focused_region.clone();
+        let has_sequence = split.read().buffered_sequence.is_some();
+        drop(split);
+        if !is_aligned_pair_track || !has_sequence {
+            return Ok(());
         }
+        let pileup = self.get_pileup(split_id, track_id, &focused_region)?;
+        let alerts = evaluate_coverage_rules(&coverage_rules, &pileup);
+        event_emitter.emit(
+            Event::QualityAlert,
+            QualityAlertPayload {
+                split_id,
+                track_id,
+                focused_region: &focused_region,
+                alerts: &alerts,
+            },
+        )?;
+        Ok(())
+    }
 
-        split_write_lock.set_focused_region(genomic_region.clone(), seq_length)?;
-        drop(split_write_lock);
-
-        let buffered_sequence = split.read().buffered_sequence_as_string()?;
-        let focused_sequence = split.read().focused_sequence_as_string()?;
+    /// Align unanchored reads (e.g from a FASTQ file) to a split's focused region.
+    ///
+    /// Reads which don't align are left without an alignment.
+    pub fn align_to_focused_region(
+        &self,
+        split_id: &SplitId,
+        reads: &mut [FastqRead],
+    ) -> Result<()> {
+        let split = self.get_split(split_id)?;
+        let focused_region = split.read().focused_region.clone();
+        let refseq = split.read().focused_sequence()?.with_context(|| {
+            format!(
+                "Reference sequence is not loaded for split={} at the current zoom level",
+                split_id
+            )
+        })?;
+        drop(split);
+        reads.par_iter_mut().for_each(|read| {
+            read.alignment = align_read(read.sequence.as_bytes(), &focused_region.seq_name, &refseq)
+        });
+        Ok(())
+    }
 
-        let focused_sequence_update_payload = FocusedSequenceUpdatedPayload {
-            split_id,
-            focused_region: &genomic_region,
-            buffered_region: &split.read().buffered_region,
-            buffered_sequence: &buffered_sequence,
-            focused_sequence: &focused_sequence,
-        };
-        match &bound_state {
-            BoundState::OutsideBuffered | BoundState::OutsideRenderRange => {
-                event_emitter
-                    .emit(Event::FocusedSequenceUpdated, focused_sequence_update_payload)?;
-            }
-            BoundState::OutsideRefreshBound => {
-                event_emitter
-                    .emit(Event::FocusedSequenceUpdateQueued, focused_sequence_update_payload)?;
+    /// Group the softclips of reads in a split's focused region by breakpoint.
+    pub fn get_softclip_clusters(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        min_reads: usize,
+    ) -> Result<Vec<SoftClipCluster>> {
+        let focused_region = self.get_split(split_id)?.read().focused_region.clone();
+        let stack = self.get_stack_reader(split_id, track_id)?.stack();
+        let clusters = match &*stack.read() {
+            AlignmentStackKind::AlignedPairKind(stack) => cluster_softclips(
+                stack.alignments().flat_map(|pair| pair.reads()),
+                &focused_region,
+                min_reads,
+            ),
+            AlignmentStackKind::PafRecordKind(_) | AlignmentStackKind::IntervalRecordKind(_) => {
+                Vec::new()
             }
-            BoundState::WithinRefreshBound => (),
         };
+        Ok(clusters)
+    }
 
-        // TODO Emit event if error is encountered for a particular track
-        self.update_split_alignments(split_id)?;
+    /// Set the fields which are included when alignments for a track/split are sent to the
+    /// frontend.
+    pub fn set_alignment_field_mask(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        field_mask: AlignmentFieldMask,
+    ) -> Result<()> {
+        self.get_stack_reader(split_id, track_id)?.set_field_mask(field_mask);
+        Ok(())
+    }
 
-        for entry in self.tracks.iter() {
-            let track_id = entry.key();
-            let stack_reader = self.get_stack_reader(split_id, track_id)?;
-            let alignments = stack_reader.read().stack();
-            let payload = AlignmentsUpdatedPayload {
-                split_id,
-                track_id,
-                focused_region: &genomic_region,
-                alignments: &alignments.read(),
-            };
-            // Depending on whether the new region falls within our already buffered region we may need to
-            // load new alignments from the filesystem and notify the frontend.
-            match &bound_state {
-                BoundState::OutsideBuffered | BoundState::OutsideRenderRange => {
-                    event_emitter.emit(Event::AlignmentsUpdated, payload)?;
-                }
-                BoundState::OutsideRefreshBound => {
-                    event_emitter.emit(Event::AlignmentsUpdateQueued, payload)?;
-                }
-                BoundState::WithinRefreshBound => (),
-            };
+    /// Join the per-read run metadata from a nanopore sequencing summary onto a track's reads.
+    ///
+    /// The alignments of every split are re-sent to the frontend with the metadata attached.
+    pub fn load_sequencing_summary<E: EmitEvent, P: AsRef<Path>>(
+        &self,
+        event_emitter: &E,
+        track_id: &TrackId,
+        summary_path: P,
+    ) -> Result<()> {
+        let summary_path = summary_path.as_ref();
+        log::info!("Loading sequencing summary {}", summary_path.to_string_lossy());
+        let summary = Arc::new(SequencingSummary::read(summary_path)?);
+        if summary.is_empty() {
+            log::warn!("Sequencing summary {} is empty", summary_path.to_string_lossy());
+        }
+        for split_id in self.get_split_ids() {
+            let stack_reader = self.get_stack_reader(&split_id, track_id)?;
+            stack_reader.set_sequencing_summary(Arc::clone(&summary))?;
+            self.emit_stack(event_emitter, &split_id, track_id, &stack_reader)?;
         }
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::paths::get_test_data_path;
+    /// Re-send the alignments of a track/split to the frontend after they're modified in place.
+    fn emit_stack<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        stack_reader: &StackReader,
+    ) -> Result<()> {
+        let focused_region = self.get_split(split_id)?.read().focused_region.clone();
+        let alignments = stack_reader.serialize_stack_with(encode_payload)?;
+        let skipped_reads = stack_reader.skipped_reads();
+        let binned_coverage = stack_reader.binned_coverage();
+        let mismatch_highlights = stack_reader.mismatch_highlights();
+        let splice_junctions = stack_reader.splice_junctions();
+        event_emitter.emit(
+            Event::AlignmentsUpdated,
+            AlignmentsUpdatedPayload {
+                split_id,
+                track_id,
+                focused_region: &focused_region,
+                alignments: &alignments.value,
+                encoding: alignments.encoding,
+                compressed: alignments.compressed,
+                skipped_reads: &skipped_reads,
+                render_window: self.get_track_render_window(track_id),
+                binned_coverage: binned_coverage.as_ref(),
+                mismatch_highlights: &mismatch_highlights,
+                splice_junctions: &splice_junctions,
+            },
+        )
+    }
+
+    /// Fetch the unmapped mate of a read which is currently loaded in a track/split.
+    pub fn get_unmapped_mate(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        read_id: &str,
+    ) -> Result<Option<UnmappedRead>> {
+        let stack_reader = self.get_stack_reader(split_id, track_id)?;
+        let read = stack_reader.find_read(read_id).with_context(|| {
+            format!("Failed to find read {} in track={}, split={}", read_id, track_id, split_id)
+        })?;
+        stack_reader.fetch_unmapped_mate(&read)
+    }
+
+    /// Find the aligned region of the mate of a read which is currently loaded in a track/split.
+    ///
+    /// None if the read is unpaired or its mate is unmapped.
+    pub fn find_mate(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        read_id: &str,
+    ) -> Result<Option<GenomicRegion>> {
+        let stack_reader = self.get_stack_reader(split_id, track_id)?;
+        let read = stack_reader.find_read(read_id).with_context(|| {
+            format!("Failed to find read {} in track={}, split={}", read_id, track_id, split_id)
+        })?;
+        stack_reader.fetch_mate_region(&read)
+    }
+
+    /// Move the focused region of a split onto the mate of a read (e.g to follow a discordant
+    /// pair), keeping the length of the focused region.
+    ///
+    /// Returns the aligned region of the mate, or None (without moving) if the read is unpaired or
+    /// its mate is unmapped. The split's alignments are read in the background (see
+    /// spawn_update_focused_region).
+    pub fn jump_to_mate<E: EmitEvent + Send + 'static>(
+        self: &Arc<Self>,
+        event_emitter: E,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        read_id: &str,
+    ) -> Result<Option<GenomicRegion>> {
+        let mate_region = match self.find_mate(split_id, track_id, read_id)? {
+            Some(mate_region) => mate_region,
+            None => return Ok(None),
+        };
+        let previous_region = self.get_split(split_id)?.read().focused_region.clone();
+        let seq_length = self.reference.read().get_seq_length(&mate_region.seq_name)?;
+        let len = previous_region.len().max(mate_region.len()).min(seq_length);
+        let center = mate_region.start() + mate_region.len() / 2;
+        let start = center.saturating_sub(len / 2).min(seq_length - len);
+        let focused_region = GenomicRegion::new(&mate_region.seq_name, start, start + len)?;
+        if previous_region.seq_name != mate_region.seq_name {
+            event_emitter.emit(
+                Event::ContigChanged,
+                ContigChangedPayload {
+                    split_id,
+                    previous_seq_name: &previous_region.seq_name,
+                    seq_name: &mate_region.seq_name,
+                },
+            )?;
+        }
+        self.spawn_update_focused_region(event_emitter, *split_id, focused_region)?;
+        Ok(Some(mate_region))
+    }
+
+    /// Get the full sequence of an insertion in a read which is currently loaded in a track/split.
+    ///
+    /// Insertions longer than MAX_INSERTION_SEQUENCE_LENGTH are truncated in the read's diffs, so
+    /// their sequence is fetched from the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - Start of the insertion's interval.
+    pub fn get_insertion_sequence(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        read_id: &str,
+        position: u64,
+    ) -> Result<String> {
+        let stack_reader = self.get_stack_reader(split_id, track_id)?;
+        let read = stack_reader.find_read(read_id).with_context(|| {
+            format!("Failed to find read {} in track={}, split={}", read_id, track_id, split_id)
+        })?;
+        let insertion = read.diffs.iter().find_map(|diff| match diff {
+            SequenceDiff::Ins { interval, sequence, full_length } if interval.start == position => {
+                Some((sequence, full_length))
+            }
+            _ => None,
+        });
+        match insertion {
+            Some((sequence, None)) => Ok(sequence.clone()),
+            Some((_, Some(_))) => {
+                let snapshot = self.get_split(split_id)?.read().snapshot();
+                let buffered_sequence = snapshot.buffered_sequence.as_ref().with_context(|| {
+                    format!(
+                        "Alignments are not loaded for split={} at the current zoom level",
+                        split_id
+                    )
+                })?;
+                stack_reader.fetch_insertion_sequence(&read, position, buffered_sequence)
+            }
+            None => bail!("Read {} has no insertion at {}", read_id, position),
+        }
+    }
+
+    /// Find the reads which overlap a position in a track/split, with the index of their row (e.g
+    /// for hit testing clicks on huge stacks).
+    pub fn get_reads_at(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        position: u64,
+    ) -> Result<Vec<ReadAtPosition>> {
+        self.get_stack_reader(split_id, track_id)?.reads_at(position)
+    }
+
+    /// Fetch the full details of a read (e.g all of its tags) which is currently loaded in a
+    /// track/split.
+    pub fn get_read_details(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        read_id: &str,
+    ) -> Result<ReadDetails> {
+        let stack_reader = self.get_stack_reader(split_id, track_id)?;
+        let read = stack_reader.find_read(read_id).with_context(|| {
+            format!("Failed to find read {} in track={}, split={}", read_id, track_id, split_id)
+        })?;
+        let snapshot = self.get_split(split_id)?.read().snapshot();
+        let buffered_sequence = snapshot.buffered_sequence.as_ref().with_context(|| {
+            format!("Alignments are not loaded for split={} at the current zoom level", split_id)
+        })?;
+        stack_reader.fetch_read_details(&read, buffered_sequence)
+    }
+
+    /// Read alignments for a single track/split into its stack.
+    ///
+    /// Reads from a snapshot of the split rather than the split itself so that neither the split
+    /// nor the grid's maps are locked while reading from disk. Users need to be able to interact
+    /// with other splits even if we are currently reading from a bam file.
+    ///
+    /// Returns whether the alignments were hidden or unhidden by the track's tuned render window.
+    /// Fails with a CancelledError if the read is cancelled before it finishes.
+    fn read_alignments(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        snapshot: &BufferedRegionSnapshot,
+        cancellation: &CancellationToken,
+    ) -> Result<bool> {
+        self.lifecycle.check(split_id, track_id)?;
+        let stack_reader = self.get_stack_reader(split_id, track_id)?;
+        let seq = match &snapshot.buffered_sequence {
+            Some(seq) => seq,
+            None => {
+                stack_reader.clear_stack(&snapshot.buffered_region)?;
+                return Ok(self.set_alignments_hidden(split_id, track_id, false));
+            }
+        };
+        let target = match *self.target_alignments_per_window.read() {
+            Some(target) => target,
+            None => {
+                let region = &snapshot.buffered_region;
+                self.read_stacked_shared(
+                    split_id,
+                    track_id,
+                    &stack_reader,
+                    region,
+                    seq,
+                    cancellation,
+                )?;
+                return Ok(self.set_alignments_hidden(split_id, track_id, false));
+            }
+        };
+        let focused_len = snapshot.focused_region.len();
+        // Skip reading regions which are expected to be too dense to render. The window is
+        // re-estimated once the user zooms in far enough for the alignments to be read.
+        let is_hidden = match self.get_track_render_window(track_id) {
+            Some(render_window) if focused_len > render_window => true,
+            _ => {
+                let region = &snapshot.buffered_region;
+                self.read_stacked_shared(
+                    split_id,
+                    track_id,
+                    &stack_reader,
+                    region,
+                    seq,
+                    cancellation,
+                )?;
+                let num_alignments = stack_reader.stack().read().count_alignments();
+                let render_window = tune_render_window(
+                    num_alignments,
+                    snapshot.buffered_region.len(),
+                    target,
+                    *self.max_render_window.read(),
+                );
+                self.track_render_windows.insert(*track_id, render_window);
+                focused_len > render_window
+            }
+        };
+        if is_hidden {
+            stack_reader.clear_stack(&snapshot.buffered_region)?;
+        }
+        Ok(self.set_alignments_hidden(split_id, track_id, is_hidden))
+    }
+
+    /// Read a track/split's alignments into its stack, sharing the alignments which are being read
+    /// for the same track in another split if they span the region rather than reading the file
+    /// again.
+    fn read_stacked_shared(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        stack_reader: &StackReader,
+        region: &GenomicRegion,
+        seq: &SequenceView,
+        cancellation: &CancellationToken,
+    ) -> Result<()> {
+        let fetch_lock = self.fetch_lock.lock();
+        let sibling = self
+            .alignments
+            .iter()
+            .find(|entry| {
+                let (sibling_track_id, sibling_split_id) = entry.key();
+                sibling_track_id == track_id
+                    && sibling_split_id != split_id
+                    && entry.value().has_fetched(region)
+            })
+            .map(|entry| Arc::clone(entry.value()));
+        if let Some(sibling) = sibling {
+            // Pinned before the fetch lock is released so that the sibling doesn't drop its
+            // alignments once it's stacked them
+            let pin = sibling.pin_fetched();
+            drop(fetch_lock);
+            if stack_reader.read_stacked_from(pin, region, seq)? {
+                log::debug!(
+                    "Shared alignments of track={} in {} with split={}",
+                    track_id,
+                    region,
+                    split_id
+                );
+                return Ok(());
+            }
+            return stack_reader.read_stacked_cancellable(region, seq, cancellation);
+        }
+        // Other splits which need alignments in the region wait for this read rather than
+        // starting their own
+        let claim = stack_reader.claim_fetch(region);
+        drop(fetch_lock);
+        stack_reader.read_claimed(claim, region, seq, cancellation)
+    }
+
+    /// Record whether a track/split's alignments are hidden by its tuned render window, returning
+    /// whether this changed.
+    fn set_alignments_hidden(&self, split_id: &SplitId, track_id: &TrackId, hidden: bool) -> bool {
+        if hidden {
+            self.hidden_alignments.insert((*track_id, *split_id))
+        } else {
+            self.hidden_alignments.remove(&(*track_id, *split_id)).is_some()
+        }
+    }
+
+    fn update_alignments(&self, split_id: &SplitId, track_id: &TrackId) -> Result<bool> {
+        let snapshot = self.get_split(split_id)?.read().snapshot();
+        self.read_alignments(split_id, track_id, &snapshot, &CancellationToken::new())
+    }
+
+    /// Add a stack reader to the grid unless its split or track has been removed.
+    ///
+    /// Checked after inserting as well as before, since the split or track may be removed while the
+    /// reader is being opened.
+    fn insert_stack_reader(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        stack_reader: StackReader,
+    ) -> Result<()> {
+        self.lifecycle.check(split_id, track_id)?;
+        self.alignments.insert((*track_id, *split_id), Arc::new(stack_reader));
+        if let Err(err) = self.lifecycle.check(split_id, track_id) {
+            self.alignments.remove(&(*track_id, *split_id));
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn add_stack_reader(&self, split_id: &SplitId, track_id: &TrackId) -> Result<()> {
+        self.lifecycle.check(split_id, track_id)?;
+        let track = self
+            .tracks
+            .get(track_id)
+            .with_context(|| format!("Failed to find track for id={}", track_id))?;
+        let file_path = track.read().file_path().clone();
+        let interval_columns = track.read().interval_columns().cloned();
+        let stack_padding = track.read().customization().stack_padding();
+        let packing = track.read().customization().packing;
+        let grouping = track.read().customization().grouping;
+        let max_depth = track.read().customization().max_depth;
+        let max_rows = track.read().customization().max_rows;
+        let base_qualities = track.read().customization().base_qualities;
+        let filter = track.read().filter();
+        drop(track);
+        let stack_reader = StackReader::with_interval_columns(file_path, interval_columns)?;
+        stack_reader.set_stack_layout(stack_padding, packing, grouping)?;
+        stack_reader.set_max_rows(max_rows);
+        stack_reader.set_max_depth(max_depth);
+        if filter != ReadFilter::default() {
+            stack_reader.set_read_filter(filter)?;
+        }
+        if base_qualities {
+            stack_reader.set_base_qualities(true);
+        }
+        self.insert_stack_reader(split_id, track_id, stack_reader)
+    }
+
+    /// Add a stack reader which copies the alignments of the same track in another split.
+    fn add_sibling_stack_reader(
+        &self,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        sibling_split_id: &SplitId,
+    ) -> Result<()> {
+        self.lifecycle.check(split_id, track_id)?;
+        let interval_columns = self
+            .tracks
+            .get(track_id)
+            .with_context(|| format!("Failed to find track for id={}", track_id))?
+            .read()
+            .interval_columns()
+            .cloned();
+        let sibling = self.get_stack_reader(sibling_split_id, track_id)?;
+        let stack_reader = StackReader::from_sibling(&sibling, interval_columns)?;
+        self.insert_stack_reader(split_id, track_id, stack_reader)
+    }
+
+    /// Get split ids in the order in which they are displayed in the grid.
+    pub fn get_split_ids(&self) -> Vec<SplitId> {
+        self.split_order.read().clone()
+    }
+
+    /// Get track ids in the order in which they are displayed in the grid.
+    pub fn get_track_ids(&self) -> Vec<TrackId> {
+        self.track_order.read().clone()
+    }
+
+    /// Read a track's alignments into every split. Splits which are removed in the meantime are
+    /// skipped.
+    fn init_track_alignments(&self, track_id: &TrackId) -> Result<()> {
+        let split_ids = self.get_split_ids();
+        for split_id in split_ids.iter() {
+            skip_removed(self.add_stack_reader(split_id, track_id))?;
+        }
+        split_ids
+            .par_iter()
+            .filter(|split_id| !self.lifecycle.is_split_removed(split_id))
+            .map(|split_id| {
+                skip_removed(self.update_alignments(split_id, track_id))?;
+                Ok(())
+            })
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    /// Group track ids by priority class, with the highest priority tracks first.
+    fn get_track_ids_by_priority(&self) -> Vec<Vec<TrackId>> {
+        let mut track_ids: BTreeMap<TrackPriority, Vec<TrackId>> = BTreeMap::new();
+        for entry in self.tracks.iter() {
+            let priority = entry.value().read().customization().priority;
+            track_ids.entry(priority).or_default().push(*entry.key());
+        }
+        track_ids.into_values().collect()
+    }
+
+    /// Read alignments for several tracks within a split. Tracks which are removed in the meantime
+    /// are skipped.
+    ///
+    /// Returns the tracks whose alignments were hidden or unhidden by their tuned render window.
+    fn update_split_alignments(
+        &self,
+        split_id: &SplitId,
+        track_ids: &[TrackId],
+        cancellation: &CancellationToken,
+    ) -> Result<Vec<TrackId>> {
+        let snapshot = self.get_split(split_id)?.read().snapshot();
+        let hidden_changed = track_ids
+            .par_iter()
+            .map(|track_id| {
+                let changed = skip_removed(self.read_alignments(
+                    split_id,
+                    track_id,
+                    &snapshot,
+                    cancellation,
+                ))?;
+                Ok(changed.unwrap_or(false))
+            })
+            .collect::<Result<Vec<bool>>>()?;
+        let changed_track_ids =
+            track_ids.iter().zip(hidden_changed).filter(|(_, changed)| *changed);
+        Ok(changed_track_ids.map(|(track_id, _)| *track_id).collect())
+    }
+
+    pub fn add_track<E: EmitEvent, P: Into<PathBuf>>(
+        &self,
+        event_emitter: &E,
+        file_path: P,
+    ) -> Result<TrackId> {
+        self.add_track_with_columns(event_emitter, file_path, None)
+    }
+
+    /// Build an index for a BAM file which doesn't have one.
+    fn index_bam<E: EmitEvent>(&self, event_emitter: &E, file_path: &PathBuf) -> Result<()> {
+        let emit_progress = |status| {
+            event_emitter.emit(Event::BamIndexProgress, IndexProgressPayload { file_path, status })
+        };
+        emit_progress(IndexStatus::Started)?;
+        let split_ids = self.get_split_ids();
+        let result =
+            self.run_busy(event_emitter, &split_ids, SplitOperation::IndexingTrack, || {
+                build_bam_index(file_path)
+            });
+        match result {
+            Ok(_) => emit_progress(IndexStatus::Finished),
+            Err(err) => {
+                emit_progress(IndexStatus::Failed)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Warn if the sequences in a SAM/BAM track's header don't match the reference, since its
+    /// alignments would otherwise be shown with misleading diffs.
+    fn check_track_reference<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        track_id: &TrackId,
+        file_path: &PathBuf,
+    ) -> Result<()> {
+        match get_file_kind(file_path)? {
+            FileKind::Bam | FileKind::Sam => (),
+            _ => return Ok(()),
+        }
+        let dictionary = get_seq_dictionary(file_path)?;
+        let mismatches = check_reference_contigs(&self.reference.read(), &dictionary)?;
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        log::warn!(
+            "{} sequences in {} don't match the reference",
+            mismatches.len(),
+            file_path.display()
+        );
+        let payload = ReferenceMismatchPayload { track_id, file_path, mismatches: &mismatches };
+        event_emitter.emit(Event::ReferenceMismatch, payload)
+    }
+
+    /// Add a track with a column mapping for generic tabix-indexed interval files.
+    pub fn add_track_with_columns<E: EmitEvent, P: Into<PathBuf>>(
+        &self,
+        event_emitter: &E,
+        file_path: P,
+        interval_columns: Option<IntervalColumns>,
+    ) -> Result<TrackId> {
+        let file_path: PathBuf = file_path.into();
+        log::info!("Adding alignment track for {}", file_path.to_string_lossy().to_string());
+        // Indexes can't be built for remote files, so they must already be indexed
+        if let FileKind::Bam = get_file_kind(&file_path)? {
+            if !is_remote(&file_path) && find_bam_index(&file_path).is_none() {
+                self.index_bam(event_emitter, &file_path)?;
+            }
+        }
+        let mut alignment_track = AlignmentTrack::new(file_path)?;
+        alignment_track.interval_columns = interval_columns;
+        self.insert_alignment_track(event_emitter, alignment_track)
+    }
+
+    /// Add a track to the grid and read its alignments into every split.
+    fn insert_alignment_track<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        alignment_track: AlignmentTrack,
+    ) -> Result<TrackId> {
+        let file_path = alignment_track.file_path.clone();
+        let track = Track::Alignment(alignment_track);
+        let track_id = track.id();
+        let split_ids = self.get_split_ids();
+        self.run_busy(event_emitter, &split_ids, SplitOperation::LoadingTrack, || {
+            self.tracks.insert(track.id(), RwLock::new(track));
+            self.track_order.write().push(track_id);
+            self.init_track_alignments(&track_id)
+        })?;
+        let track = self.tracks.get(&track_id).unwrap();
+        let mut focus = self.focus.read().clone();
+        focus.track_id = Some(track.read().id());
+        focus.target = FocusTarget::Cell;
+        self.set_focus(event_emitter, focus)?;
+        event_emitter.emit(Event::TrackAdded, &*track.read())?;
+        drop(track);
+        if let Err(err) = self.check_track_reference(event_emitter, &track_id, &file_path) {
+            log::error!("Failed to check {} against the reference: {:#}", file_path.display(), err);
+        }
+        for split_id in split_ids.iter() {
+            skip_removed(self.emit_coverage(event_emitter, split_id, &track_id))?;
+            skip_removed(self.emit_downsampling(event_emitter, split_id, &track_id))?;
+            skip_removed(self.check_coverage(event_emitter, split_id, &track_id))?;
+        }
+        Ok(track_id)
+    }
+
+    /// Update the user-settable name and description of a split.
+    pub fn update_split_annotation<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        split_id: &SplitId,
+        annotation: SplitAnnotation,
+    ) -> Result<()> {
+        let split = self.get_split(split_id)?;
+        split.write().annotation = annotation;
+        event_emitter.emit(Event::SplitUpdated, &*split.read())?;
+        Ok(())
+    }
+
+    /// Update the user-settable display options (color, label etc) of a track.
+    ///
+    /// The track's alignments are restacked if its padding, packing strategy, grouping or maximum
+    /// number of rows changes.
+    pub fn update_track_customization<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        track_id: &TrackId,
+        customization: TrackCustomization,
+    ) -> Result<()> {
+        let track = self
+            .tracks
+            .get(track_id)
+            .with_context(|| format!("Failed to find track for id={}", track_id))?;
+        let (stack_padding, packing) = (customization.stack_padding(), customization.packing);
+        let (grouping, max_depth) = (customization.grouping, customization.max_depth);
+        let max_rows = customization.max_rows;
+        let (base_qualities, coverage_strand) =
+            (customization.base_qualities, customization.coverage_strand);
+        let (layout_changed, max_depth_changed, base_qualities_changed, coverage_strand_changed) = {
+            let track = track.read();
+            let previous = track.customization();
+            (
+                stack_padding != previous.stack_padding()
+                    || packing != previous.packing
+                    || grouping != previous.grouping
+                    || max_rows != previous.max_rows,
+                max_depth != previous.max_depth,
+                base_qualities != previous.base_qualities,
+                coverage_strand != previous.coverage_strand,
+            )
+        };
+        track.write().set_customization(customization);
+        event_emitter.emit(Event::TrackUpdated, &*track.read())?;
+        drop(track);
+        if layout_changed {
+            // Restacked in place rather than re-read from the file
+            for split_id in self.get_split_ids() {
+                let stack_reader = match self.alignments.get(&(*track_id, split_id)) {
+                    Some(stack_reader) => Arc::clone(&stack_reader),
+                    None => continue,
+                };
+                stack_reader.set_stack_layout(stack_padding, packing, grouping)?;
+                stack_reader.set_max_rows(max_rows);
+                self.emit_stack(event_emitter, &split_id, track_id, &stack_reader)?;
+            }
+        }
+        if max_depth_changed {
+            for split_id in self.get_split_ids() {
+                if let Some(stack_reader) = self.alignments.get(&(*track_id, split_id)) {
+                    stack_reader.set_max_depth(max_depth);
+                }
+            }
+        }
+        if base_qualities_changed {
+            for split_id in self.get_split_ids() {
+                if let Some(stack_reader) = self.alignments.get(&(*track_id, split_id)) {
+                    stack_reader.set_base_qualities(base_qualities);
+                }
+            }
+        }
+        if max_depth_changed || base_qualities_changed {
+            // Alignments aren't kept after they're stacked, so they're read from the file again
+            self.reread_track_alignments(event_emitter, track_id)?;
+        }
+        if coverage_strand_changed {
+            for split_id in self.get_split_ids() {
+                if self.alignments.contains_key(&(*track_id, split_id)) {
+                    self.emit_coverage(event_emitter, &split_id, track_id)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Set which reads are excluded from a SAM/BAM track, re-reading its alignments in every split.
+    pub fn set_track_filter<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        track_id: &TrackId,
+        filter: ReadFilter,
+    ) -> Result<()> {
+        let track = self
+            .tracks
+            .get(track_id)
+            .with_context(|| format!("Failed to find track for id={}", track_id))?;
+        match get_file_kind(track.read().file_path())? {
+            FileKind::Bam | FileKind::Sam => (),
+            _ => bail!("Read filters can only be applied to SAM/BAM tracks"),
+        }
+        track.write().set_filter(filter.clone());
+        event_emitter.emit(Event::TrackUpdated, &*track.read())?;
+        drop(track);
+        let split_ids = self.get_split_ids();
+        // Every reader is updated before any are re-read so that alignments which were read
+        // without the filter are never shared between splits
+        for split_id in split_ids.iter() {
+            if let Some(stack_reader) = self.alignments.get(&(*track_id, *split_id)) {
+                stack_reader.set_read_filter(filter.clone())?;
+            }
+        }
+        self.reread_track_alignments(event_emitter, track_id)
+    }
+
+    /// Re-read a track's alignments from the file in every split and send them to the frontend.
+    fn reread_track_alignments<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        track_id: &TrackId,
+    ) -> Result<()> {
+        let split_ids = self.get_split_ids();
+        split_ids
+            .par_iter()
+            .filter(|split_id| !self.lifecycle.is_split_removed(split_id))
+            .map(|split_id| {
+                skip_removed(self.update_alignments(split_id, track_id))?;
+                Ok(())
+            })
+            .collect::<Result<_>>()?;
+        for split_id in split_ids.iter() {
+            let stack_reader = match skip_removed(self.get_stack_reader(split_id, track_id))? {
+                Some(stack_reader) => stack_reader,
+                None => continue,
+            };
+            self.emit_stack(event_emitter, split_id, track_id, &stack_reader)?;
+            skip_removed(self.emit_coverage(event_emitter, split_id, track_id))?;
+            skip_removed(self.emit_downsampling(event_emitter, split_id, track_id))?;
+        }
+        Ok(())
+    }
+
+    /// Change part of a SAM/BAM track's read filter, leaving the rest of it as is.
+    fn modify_track_filter<E: EmitEvent, F: FnOnce(&mut ReadFilter)>(
+        &self,
+        event_emitter: &E,
+        track_id: &TrackId,
+        modify: F,
+    ) -> Result<()> {
+        let mut filter = self
+            .tracks
+            .get(track_id)
+            .with_context(|| format!("Failed to find track for id={}", track_id))?
+            .read()
+            .filter();
+        modify(&mut filter);
+        self.set_track_filter(event_emitter, track_id, filter)
+    }
+
+    /// Show or hide the reads of a SAM/BAM track which are flagged as duplicates.
+    pub fn set_hide_duplicates<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        track_id: &TrackId,
+        hide_duplicates: bool,
+    ) -> Result<()> {
+        self.modify_track_filter(event_emitter, track_id, |filter| {
+            filter.hide_duplicates = hide_duplicates
+        })
+    }
+
+    /// Show or hide the secondary alignments of a SAM/BAM track.
+    pub fn set_hide_secondary<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        track_id: &TrackId,
+        hide_secondary: bool,
+    ) -> Result<()> {
+        self.modify_track_filter(event_emitter, track_id, |filter| {
+            filter.hide_secondary = hide_secondary
+        })
+    }
+
+    /// Show or hide the supplementary alignments of a SAM/BAM track.
+    pub fn set_hide_supplementary<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        track_id: &TrackId,
+        hide_supplementary: bool,
+    ) -> Result<()> {
+        self.modify_track_filter(event_emitter, track_id, |filter| {
+            filter.hide_supplementary = hide_supplementary
+        })
+    }
+
+    /// Get the file, display options and read filter of a SAM/BAM track, which tracks for groups
+    /// of its reads are copied from.
+    ///
+    /// The label is cleared since it would otherwise hide the names of the group tracks.
+    fn get_group_track_template(
+        &self,
+        track_id: &TrackId,
+    ) -> Result<(PathBuf, TrackCustomization, ReadFilter)> {
+        let track = self
+            .tracks
+            .get(track_id)
+            .with_context(|| format!("Failed to find track for id={}", track_id))?;
+        let track = track.read();
+        match get_file_kind(track.file_path())? {
+            FileKind::Bam | FileKind::Sam => (),
+            _ => bail!("Only SAM/BAM tracks can be split into groups of reads"),
+        }
+        let customization = TrackCustomization { label: None, ..track.customization().clone() };
+        Ok((track.file_path().clone(), customization, track.filter()))
+    }
+
+    /// Add a track for a group of the reads in a SAM/BAM file.
+    fn add_group_track<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        file_path: &Path,
+        name: &str,
+        customization: &TrackCustomization,
+        filter: ReadFilter,
+    ) -> Result<TrackId> {
+        let mut alignment_track = AlignmentTrack::new(file_path)?;
+        alignment_track.name = name.to_owned();
+        alignment_track.customization = customization.clone();
+        alignment_track.filter = filter;
+        self.insert_alignment_track(event_emitter, alignment_track)
+    }
+
+    /// Expand a SAM/BAM track into one track per sample (or library) of its read groups, so that
+    /// each sample in a merged file is shown in its own block of rows.
+    ///
+    /// The track itself is narrowed to the first group and a track is added for each of the
+    /// others, with the same display options and read filter. Reads without a read group aren't
+    /// shown in any of the tracks.
+    ///
+    /// Returns the ids of the tracks in the order in which the groups appear in the header.
+    pub fn split_track_by_read_group<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        track_id: &TrackId,
+        group_by: ReadGroupField,
+    ) -> Result<Vec<TrackId>> {
+        let (file_path, customization, filter) = self.get_group_track_template(track_id)?;
+        let groups = group_read_groups(&get_read_groups(&file_path)?, group_by);
+        if groups.len() < 2 {
+            bail!("{} doesn't have multiple read groups to split by", file_path.display());
+        }
+        log::info!("Splitting track {} into {} tracks by read group", track_id, groups.len());
+        let group_filter = |read_groups: &Vec<String>| ReadFilter {
+            read_groups: Some(read_groups.clone()),
+            ..filter.clone()
+        };
+
+        let (first_name, first_read_groups) = &groups[0];
+        let track = self
+            .tracks
+            .get(track_id)
+            .with_context(|| format!("Failed to find track for id={}", track_id))?;
+        track.write().set_name(first_name.clone());
+        track.write().set_customization(customization.clone());
+        drop(track);
+        self.set_track_filter(event_emitter, track_id, group_filter(first_read_groups))?;
+
+        let mut track_ids = vec![*track_id];
+        for (name, read_groups) in groups.iter().skip(1) {
+            let filter = group_filter(read_groups);
+            track_ids.push(self.add_group_track(
+                event_emitter,
+                &file_path,
+                name,
+                &customization,
+                filter,
+            )?);
+        }
+        Ok(track_ids)
+    }
+
+    /// Add a track for each group of values of a tag in a SAM/BAM track's reads, e.g single cell
+    /// barcodes (CB tag) or clusters of barcodes which are aggregated into a pseudo-bulk track.
+    ///
+    /// The tracks have the same display options and read filter as the track, which is left as
+    /// is. Returns the ids of the added tracks in the order of the groups.
+    pub fn split_track_by_tag<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        track_id: &TrackId,
+        tag: &str,
+        groups: Vec<TagGroup>,
+    ) -> Result<Vec<TrackId>> {
+        let (file_path, customization, filter) = self.get_group_track_template(track_id)?;
+        log::info!(
+            "Adding {} tracks for groups of {} tags in track {}",
+            groups.len(),
+            tag,
+            track_id
+        );
+        // Validated up front so that no tracks are added if any of the groups are invalid
+        let tag_values = groups
+            .into_iter()
+            .map(|group| {
+                let tag_values = TagValues { tag: tag.to_owned(), values: group.values };
+                tag_values.validate()?;
+                Ok((group.name, tag_values))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        tag_values
+            .into_iter()
+            .map(|(name, tag_values)| {
+                let filter = ReadFilter { tag_values: Some(tag_values), ..filter.clone() };
+                self.add_group_track(event_emitter, &file_path, &name, &customization, filter)
+            })
+            .collect()
+    }
+
+    /// Sort the rows of a track's alignments in a split by the alignments at a position, e.g so
+    /// that reads with the alternate allele of a SNV are stacked together.
+    pub fn sort_alignments<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        split_id: &SplitId,
+        track_id: &TrackId,
+        position: u64,
+        by: SortBy,
+    ) -> Result<()> {
+        let stack_reader = self.get_stack_reader(split_id, track_id)?;
+        stack_reader.sort_rows(position, by)?;
+        self.emit_stack(event_emitter, split_id, track_id, &stack_reader)
+    }
+
+    /// Remove a track from the grid, dropping its stack readers and other per-track state.
+    ///
+    /// Work which is in progress for the track (e.g reading its alignments into a split) skips the
+    /// track from then on, and anything it adds to the grid for the track is dropped.
+    pub fn remove_track<E: EmitEvent>(&self, event_emitter: &E, track_id: &TrackId) -> Result<()> {
+        if !self.tracks.contains_key(track_id) {
+            bail!("Failed to find track for id={}", track_id);
+        }
+        log::info!("Removing track {}", track_id);
+        // Marked first so that stack readers which are added concurrently are removed again
+        self.lifecycle.mark_track_removed(*track_id);
+        self.tracks.remove(track_id);
+        self.track_order.write().retain(|id| id != track_id);
+        self.alignments.retain(|(id, _), _| id != track_id);
+        self.hidden_alignments.retain(|(id, _)| id != track_id);
+        self.track_render_windows.remove(track_id);
+        let mut focus = self.focus.read().clone();
+        if focus.track_id == Some(*track_id) {
+            focus.track_id = self.tracks.iter().next().map(|track| *track.key());
+            if focus.track_id.is_none() {
+                focus.target = FocusTarget::Split;
+            }
+            self.set_focus(event_emitter, focus)?;
+        }
+        Ok(())
+    }
+
+    /// Remove a split from the grid, dropping its stack readers and buffered sequence.
+    ///
+    /// The last split can't be removed. Loads which are in progress for the split are cancelled
+    /// once they finish reading the current priority class of tracks.
+    pub fn remove_split<E: EmitEvent>(&self, event_emitter: &E, split_id: &SplitId) -> Result<()> {
+        let mut split_order = self.split_order.write();
+        let position = split_order
+            .iter()
+            .position(|id| id == split_id)
+            .with_context(|| format!("Failed to find split with id={}", split_id))?;
+        if split_order.len() == 1 {
+            bail!("Can't remove the only split in the grid");
+        }
+        log::info!("Removing split {}", split_id);
+        self.lifecycle.mark_split_removed(*split_id);
+        split_order.remove(position);
+        let next_split_id = split_order[position.min(split_order.len() - 1)];
+        drop(split_order);
+        self.splits.remove(split_id);
+        self.region_loads.remove(split_id);
+        self.alignments.retain(|(_, id), _| id != split_id);
+        self.hidden_alignments.retain(|(_, id)| id != split_id);
+        // Dropped now rather than when the running operation finishes so that the frontend isn't
+        // told that the removed split is idle
+        self.busy_splits.remove(split_id);
+        let mut focus = self.focus.read().clone();
+        if focus.split_id == *split_id {
+            focus.split_id = next_split_id;
+            self.set_focus(event_emitter, focus)?;
+        }
+        Ok(())
+    }
+
+    fn get_default_focused_region(&self) -> Result<GenomicRegion> {
+        let focused_region = if !self.splits.is_empty() {
+            self.get_split(&self.focus.read().split_id)
+                .context(format!("Failed to find split id={}", &self.focus.read().split_id))?
+                .read()
+                .focused_region
+                .clone()
+        } else {
+            self.reference.read().default_focused_region.clone()
+        };
+        Ok(focused_region)
+    }
+
+    pub fn add_split<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        focused_region: Option<GenomicRegion>,
+    ) -> Result<SplitId> {
+        let focused_region = match focused_region {
+            Some(region) => self.reference.read().resolve_region(region),
+            None => self.get_default_focused_region()?,
+        };
+        let track_ids: Vec<TrackId> = self.tracks.iter().map(|track| track.read().id()).collect();
+
+        // If the new split shows the same region as the focused split we can reuse its buffers
+        // rather than re-reading every file.
+        let sibling_split_id = self.focus.read().split_id;
+        let sibling_split = self
+            .splits
+            .get(&sibling_split_id)
+            .filter(|sibling| sibling.read().focused_region == focused_region);
+        if let Some(sibling_split) = sibling_split {
+            log::debug!("Initializing new split from buffers of split={}", sibling_split_id);
+            let split =
+                Split::from_sibling(&sibling_split.read(), self.reference.read().get_reader()?);
+            drop(sibling_split);
+            let split_id = split.id;
+            self.splits.insert(split.id, RwLock::new(split));
+            self.split_order.write().push(split_id);
+            for track_id in track_ids.iter() {
+                skip_removed(self.add_sibling_stack_reader(
+                    &split_id,
+                    track_id,
+                    &sibling_split_id,
+                ))?;
+            }
+            return self.finish_add_split(event_emitter, split_id);
+        }
+
+        let seq_length = self.reference.read().get_seq_length(&focused_region.seq_name)?;
+        let split = Split::new(
+            self.reference.read().get_reader()?,
+            focused_region,
+            *self.max_render_window.read(),
+            seq_length,
+        )?;
+        let split_id = split.id;
+        self.splits.insert(split.id, RwLock::new(split));
+        self.split_order.write().push(split_id);
+        for track_id in track_ids.iter() {
+            skip_removed(self.add_stack_reader(&split_id, track_id))?;
+        }
+        track_ids
+            .par_iter()
+            .filter(|track_id| !self.lifecycle.is_track_removed(track_id))
+            .map(|track_id| {
+                skip_removed(self.update_alignments(&split_id, track_id))?;
+                Ok(())
+            })
+            .collect::<Result<_>>()?;
+        self.finish_add_split(event_emitter, split_id)
+    }
+
+    fn finish_add_split<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        split_id: SplitId,
+    ) -> Result<SplitId> {
+        let split = self.splits.get(&split_id).unwrap();
+        event_emitter.emit(Event::SplitAdded, &*split.read())?;
+        drop(split);
+        let mut focus = self.focus.read().clone();
+        focus.split_id = split_id;
+        self.set_focus(event_emitter, focus)?;
+        let track_ids: Vec<TrackId> = self.tracks.iter().map(|track| track.read().id()).collect();
+        for track_id in track_ids.iter() {
+            skip_removed(self.check_coverage(event_emitter, &split_id, track_id))?;
+        }
+        Ok(split_id)
+    }
+
+    /// Pan the focused split left or right by a tenth of the focused region's length.
+    ///
+    /// Panning stops at the ends of the contig unless `contig_rollover` is set, in which case
+    /// panning past the end of a contig moves onto the adjacent contig (if there is one). The
+    /// split's alignments are read in the background (see spawn_update_focused_region).
+    pub fn pan_focused_split<E: EmitEvent + Send + 'static>(
+        self: &Arc<Self>,
+        event_emitter: E,
+        direction: &Direction,
+        contig_rollover: bool,
+    ) -> Result<()> {
+        let focused_split_id = self.focus.read().split_id;
+        let mut updated_region = self.get_split(&focused_split_id)?.read().focused_region.clone();
+        let mut panned_bp = updated_region.len() / 10;
+        let seq_length = self.reference.read().get_seq_length(&updated_region.seq_name)?;
+        let exceeds_contig = match direction {
+            Direction::Left => updated_region.start() < panned_bp,
+            Direction::Right => updated_region.end() + panned_bp > seq_length,
+        };
+        if contig_rollover && exceeds_contig {
+            if let Some(rolled_region) = self.get_rollover_region(&updated_region, direction)? {
+                log::debug!(
+                    "Panning focused split={} onto contig {}",
+                    focused_split_id,
+                    rolled_region.seq_name
+                );
+                event_emitter.emit(
+                    Event::ContigChanged,
+                    ContigChangedPayload {
+                        split_id: &focused_split_id,
+                        previous_seq_name: &updated_region.seq_name,
+                        seq_name: &rolled_region.seq_name,
+                    },
+                )?;
+                return self.spawn_update_focused_region(
+                    event_emitter,
+                    focused_split_id,
+                    rolled_region,
+                );
+            }
+        }
+        match direction {
+            Direction::Left => {
+                if updated_region.start().saturating_sub(panned_bp) == 0 {
+                    panned_bp = updated_region.start();
+                }
+                log::debug!("Panning focused split={} left by {}bp", focused_split_id, panned_bp);
+                updated_region.interval.start -= panned_bp;
+                updated_region.interval.end -= panned_bp;
+            }
+            Direction::Right => {
+                if updated_region.end() + panned_bp > seq_length {
+                    panned_bp = seq_length - updated_region.end();
+                }
+                log::debug!("Panning focused split={} right by {}bp", focused_split_id, panned_bp);
+                updated_region.interval.start += panned_bp;
+                updated_region.interval.end += panned_bp;
+            }
+        };
+        self.spawn_update_focused_region(event_emitter, focused_split_id, updated_region)
+    }
+
+    /// Get the region on the contig adjacent to `region` which should be focused when panning past
+    /// the end of the contig. The region length is preserved where possible.
+    fn get_rollover_region(
+        &self,
+        region: &GenomicRegion,
+        direction: &Direction,
+    ) -> Result<Option<GenomicRegion>> {
+        let reference = self.reference.read();
+        let seq_name = match direction {
+            Direction::Left => reference.prev_seq_name(&region.seq_name),
+            Direction::Right => reference.next_seq_name(&region.seq_name),
+        };
+        let seq_name = match seq_name {
+            Some(seq_name) => seq_name,
+            None => return Ok(None),
+        };
+        let seq_length = reference.get_seq_length(seq_name)?;
+        let region_length = region.len().min(seq_length);
+        let rolled_region = match direction {
+            Direction::Left => {
+                GenomicRegion::new(seq_name, seq_length - region_length, seq_length)?
+            }
+            Direction::Right => GenomicRegion::new(seq_name, 0, region_length)?,
+        };
+        Ok(Some(rolled_region))
+    }
+
+    pub fn update_grid_focus<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        grid_coord: GridCoord,
+    ) -> Result<()> {
+        if grid_coord == *self.focus.read() {
+            return Ok(());
+        }
+        self.get_split(&grid_coord.split_id)?;
+        match grid_coord.track_id {
+            Some(track_id) if !self.tracks.contains_key(&track_id) => {
+                bail!("Failed to find track for id={}", track_id);
+            }
+            None if grid_coord.target != FocusTarget::Split => {
+                bail!("A track must be provided to focus a {:?}", grid_coord.target);
+            }
+            _ => (),
+        }
+        self.set_focus(event_emitter, grid_coord)
+    }
+
+    /// Move focus to the next split to the left or right, wrapping around at the grid's edges.
+    pub fn focus_adjacent_split<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        direction: &Direction,
+    ) -> Result<()> {
+        let split_ids = self.get_split_ids();
+        let mut focus = self.focus.read().clone();
+        let index = split_ids
+            .iter()
+            .position(|split_id| *split_id == focus.split_id)
+            .with_context(|| format!("Failed to find focused split id={}", focus.split_id))?;
+        let new_index = match direction {
+            Direction::Left => (index + split_ids.len() - 1) % split_ids.len(),
+            Direction::Right => (index + 1) % split_ids.len(),
+        };
+        if new_index == index {
+            return Ok(());
+        }
+        focus.split_id = split_ids[new_index];
+        log::debug!("Moving focus to split={}", focus.split_id);
+        self.set_focus(event_emitter, focus)
+    }
+
+    fn set_focus<E: EmitEvent>(&self, event_emitter: &E, grid_coord: GridCoord) -> Result<()> {
+        let prev_split_id = self.focus.read().split_id;
+        *self.focus.write() = grid_coord.clone();
+        event_emitter.emit(Event::GridFocusUpdated, &grid_coord)?;
+        if grid_coord.split_id != prev_split_id {
+            event_emitter.emit(Event::FocusedSplitUpdated, &grid_coord)?;
+        }
+        Ok(())
+    }
+
+    /// Update the focused region of a split, waiting for its alignments to be read.
+    ///
+    /// Commands use spawn_update_focused_region instead, so that they aren't blocked by file IO.
+    pub fn update_focused_region<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        split_id: &SplitId,
+        genomic_region: GenomicRegion,
+    ) -> Result<()> {
+        match self.queue_focused_region(split_id, genomic_region)? {
+            Some(region_loads) => self.run_region_loads(event_emitter, split_id, &region_loads),
+            None => Ok(()),
+        }
+    }
+
+    /// Update the focused region of a split without waiting for its alignments to be read.
+    ///
+    /// Alignments are read on a background thread so that slow file IO doesn't block the command
+    /// which requested the update. FocusedRegionLoaded is emitted once the split's queued loads are
+    /// finished, along with the error if the last load failed.
+    pub fn spawn_update_focused_region<E: EmitEvent + Send + 'static>(
+        self: &Arc<Self>,
+        event_emitter: E,
+        split_id: SplitId,
+        genomic_region: GenomicRegion,
+    ) -> Result<()> {
+        let region_loads = match self.queue_focused_region(&split_id, genomic_region)? {
+            Some(region_loads) => region_loads,
+            None => return Ok(()),
+        };
+        let grid = Arc::clone(self);
+        self.load_pool.spawn(move || {
+            let result = grid.run_region_loads(&event_emitter, &split_id, &region_loads);
+            if let Err(err) = &result {
+                log::error!("Failed to update focused region of split {}: {:#}", split_id, err);
+            }
+            let payload = FocusedRegionLoadedPayload {
+                split_id: &split_id,
+                error: result.err().map(|err| format!("{:#}", err)),
+            };
+            if let Err(err) = event_emitter.emit(Event::FocusedRegionLoaded, payload) {
+                log::error!("Failed to emit focused region loaded event: {:#}", err);
+            }
+        });
+        Ok(())
+    }
+
+    /// Run an operation which reads files on the grid's load pool, without waiting for it.
+    pub fn spawn_load<F: FnOnce(&SplitGrid) + Send + 'static>(self: &Arc<Self>, func: F) {
+        let grid = Arc::clone(self);
+        self.load_pool.spawn(move || func(&grid));
+    }
+
+    /// Queue a load of a split's focused region.
+    ///
+    /// Returns the split's queue if the caller needs to run it, or None if the load will be run by
+    /// the update which is already in progress.
+    fn queue_focused_region(
+        &self,
+        split_id: &SplitId,
+        genomic_region: GenomicRegion,
+    ) -> Result<Option<Arc<RegionLoads>>> {
+        let genomic_region = self.reference.read().resolve_region(genomic_region);
+        log::info!(
+            "Updating focused region for split {} to {} ({})",
+            &split_id,
+            format_region(&genomic_region),
+            format_length(genomic_region.len())
+        );
+        self.get_split(split_id)?;
+        // Any load of the split which is still in progress is superseded by this one. Updates
+        // which arrive while it stops are coalesced, so only the latest is loaded.
+        let region_loads = Arc::clone(self.region_loads.entry(*split_id).or_default().value());
+        if !region_loads.queue(genomic_region) {
+            log::debug!("Queued update of split {} behind the update in progress", split_id);
+            return Ok(None);
+        }
+        Ok(Some(region_loads))
+    }
+
+    /// Run a split's queued focused region loads until the queue is empty, returning the result of
+    /// the last load.
+    fn run_region_loads<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        split_id: &SplitId,
+        region_loads: &RegionLoads,
+    ) -> Result<()> {
+        let mut result = Ok(());
+        while let Some(load) = region_loads.next() {
+            result = self.run_region_load(event_emitter, split_id, load);
+        }
+        result
+    }
+
+    fn run_region_load<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        split_id: &SplitId,
+        load: RegionLoad,
+    ) -> Result<()> {
+        // A stale buffer is loaded again even if the region hasn't changed, since the focused
+        // region is updated before the load which was cancelled had read the alignments.
+        let split = self.get_split(split_id)?;
+        if split.read().focused_region == load.region && !split.read().is_buffer_stale() {
+            return Ok(());
+        }
+        drop(split);
+        self.run_busy(event_emitter, &[*split_id], SplitOperation::UpdatingFocusedRegion, || {
+            self.load_focused_region(event_emitter, split_id, load.region, &load.cancellation)
+        })
+    }
+
+    /// Stop a superseded load of a split's focused region. Tracks which the load had already read
+    /// may not have been sent to the frontend, so the buffered region is loaded again by the next
+    /// update.
+    fn abort_focused_region_load(&self, split_id: &SplitId) -> Result<()> {
+        log::debug!("Cancelled superseded load of split {}", split_id);
+        if let Some(split) = self.splits.get(split_id) {
+            split.write().mark_buffer_stale();
+        }
+        Ok(())
+    }
+
+    /// Set the focused region of a split and load any alignments which are required.
+    ///
+    /// The load stops without sending any more alignments to the frontend once it's cancelled.
+    fn load_focused_region<E: EmitEvent>(
+        &self,
+        event_emitter: &E,
+        split_id: &SplitId,
+        genomic_region: GenomicRegion,
+        cancellation: &CancellationToken,
+    ) -> Result<()> {
+        let seq_length = self.reference.read().get_seq_length(&genomic_region.seq_name)?;
+        let split = self.get_split(split_id)?;
+        let prev_region_len = split.read().focused_region.len();
+        let bound_state = split.read().check_bounds(&genomic_region);
+
+        // Read the new reference sequence before locking the split so that readers aren't blocked
+        // by file IO.
+        let update = split.read().prepare_focused_region(genomic_region.clone(), seq_length)?;
+
+        // We notify the frontend of the update before actually making the change on the backend
+        // Need to make sure that the split is write locked until the frontend and backend are back
+        // in sync.
+        let mut split_write_lock = split.write();
+        let focused_region_update_payload =
+            FocusedRegionUpdatedPayload::new(split_id, &genomic_region);
+        event_emitter.emit(Event::FocusedRegionUpdated, &focused_region_update_payload)?;
+
+        // If the frontend already has the necessary alignments cached we can just inform it that a
+        // zoom or pan is necessay.
+        match &bound_state {
+            BoundState::WithinRefreshBound | BoundState::OutsideRefreshBound => {
+                if genomic_region.len() == prev_region_len {
+                    event_emitter.emit(Event::RegionPanned, &focused_region_update_payload)?;
+                } else {
+                    event_emitter.emit(Event::RegionZoomed, &focused_region_update_payload)?;
+                }
+            }
+            BoundState::OutsideBuffered => {
+                event_emitter.emit(Event::RegionBuffering, RegionBufferingPayload { split_id })?;
+            }
+            _ => (),
+        }
+
+        split_write_lock.apply_focused_region(update);
+        drop(split_write_lock);
+
+        let split_lock = split.read();
+        let buffered_sequence = split_lock.inline_buffered_sequence_as_string()?;
+        let focused_sequence = split_lock.inline_focused_sequence_as_string()?;
+        let buffered_sequence_chunked = split_lock.is_buffered_sequence_chunked();
+        let buffered_region = split_lock.buffered_region.clone();
+        drop(split_lock);
+        drop(split);
+
+        let focused_sequence_update_payload = FocusedSequenceUpdatedPayload {
+            split_id,
+            focused_region: &genomic_region,
+            buffered_region: &buffered_region,
+            buffered_sequence: &buffered_sequence,
+            focused_sequence: &focused_sequence,
+            buffered_sequence_chunked,
+        };
+        match &bound_state {
+            BoundState::OutsideBuffered | BoundState::OutsideRenderRange => {
+                event_emitter
+                    .emit(Event::FocusedSequenceUpdated, focused_sequence_update_payload)?;
+            }
+            BoundState::OutsideRefreshBound => {
+                event_emitter
+                    .emit(Event::FocusedSequenceUpdateQueued, focused_sequence_update_payload)?;
+            }
+            BoundState::WithinRefreshBound => (),
+        };
+
+        // Tracks are fetched one priority class at a time so that the frontend receives the most
+        // important tracks first.
+        for track_ids in self.get_track_ids_by_priority() {
+            // The rest of the load is cancelled if the split was removed in the meantime
+            if self.lifecycle.is_split_removed(split_id) {
+                return Ok(());
+            }
+            // TODO Emit event if error is encountered for a particular track
+            let hidden_changed =
+                match self.update_split_alignments(split_id, &track_ids, cancellation) {
+                    Err(err) if is_cancelled_error(&err) => {
+                        return self.abort_focused_region_load(split_id);
+                    }
+                    result => result?,
+                };
+
+            for track_id in track_ids.iter() {
+                if cancellation.is_cancelled() {
+                    return self.abort_focused_region_load(split_id);
+                }
+                let stack_reader = match skip_removed(self.get_stack_reader(split_id, track_id))? {
+                    Some(stack_reader) => stack_reader,
+                    None => continue,
+                };
+                // Extending the buffered region leaves most of a stack unchanged, so only the
+                // changes are sent if the frontend can apply them
+                let delta = match &bound_state {
+                    BoundState::OutsideBuffered
+                        if get_emitted_schema_version() >= ALIGNMENTS_APPENDED_SCHEMA_VERSION =>
+                    {
+                        stack_reader.serialize_delta()?
+                    }
+                    _ => None,
+                };
+                let alignments = match &delta {
+                    Some(_) => EncodedPayload::default(),
+                    None => stack_reader.serialize_stack_with(encode_payload)?,
+                };
+                let skipped_reads = stack_reader.skipped_reads();
+                let binned_coverage = stack_reader.binned_coverage();
+                let mismatch_highlights = stack_reader.mismatch_highlights();
+                let splice_junctions = stack_reader.splice_junctions();
+                let payload = AlignmentsUpdatedPayload {
+                    split_id,
+                    track_id,
+                    focused_region: &genomic_region,
+                    alignments: &alignments.value,
+                    encoding: alignments.encoding,
+                    compressed: alignments.compressed,
+                    skipped_reads: &skipped_reads,
+                    render_window: self.get_track_render_window(track_id),
+                    binned_coverage: binned_coverage.as_ref(),
+                    mismatch_highlights: &mismatch_highlights,
+                    splice_junctions: &splice_junctions,
+                };
+                // Depending on whether the new region falls within our already buffered region we may
+                // need to load new alignments from the filesystem and notify the frontend.
+                match (&bound_state, &delta) {
+                    (BoundState::OutsideBuffered, Some(delta)) => {
+                        let payload = AlignmentsAppendedPayload {
+                            split_id,
+                            track_id,
+                            focused_region: &genomic_region,
+                            delta,
+                            skipped_reads: &skipped_reads,
+                            render_window: payload.render_window,
+                            mismatch_highlights: &mismatch_highlights,
+                            splice_junctions: &splice_junctions,
+                        };
+                        event_emitter.emit(Event::AlignmentsAppended, payload)?;
+                        skip_removed(self.emit_coverage(event_emitter, split_id, track_id))?;
+                        skip_removed(self.emit_downsampling(event_emitter, split_id, track_id))?;
+                    }
+                    (BoundState::OutsideBuffered | BoundState::OutsideRenderRange, _) => {
+                        event_emitter.emit(Event::AlignmentsUpdated, payload)?;
+                        skip_removed(self.emit_coverage(event_emitter, split_id, track_id))?;
+                        skip_removed(self.emit_downsampling(event_emitter, split_id, track_id))?;
+                    }
+                    // Zooming within the buffered region can cross the track's render window
+                    _ if hidden_changed.contains(track_id) => {
+                        event_emitter.emit(Event::AlignmentsUpdated, payload)?;
+                        skip_removed(self.emit_coverage(event_emitter, split_id, track_id))?;
+                        skip_removed(self.emit_downsampling(event_emitter, split_id, track_id))?;
+                    }
+                    (BoundState::OutsideRefreshBound, _) => {
+                        event_emitter.emit(Event::AlignmentsUpdateQueued, payload)?;
+                    }
+                    (BoundState::WithinRefreshBound, _) => (),
+                };
+                skip_removed(self.check_coverage(event_emitter, split_id, track_id))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alignments::alignment::Alignment;
+    use crate::alignments::render_window::MIN_TUNED_RENDER_WINDOW;
+    use crate::alignments::stack::{PackingStrategy, StackGrouping, MAX_STACK_PADDING};
+    use crate::file_formats::fasta::index::build_fasta_index;
+    use crate::file_formats::fastq::reader::read_fastq;
+    use crate::file_formats::sam_bam::pileup::CoverageStrand;
+    use crate::file_formats::signal::bins::SignalBins;
+    use crate::file_formats::signal::reader::SignalReader;
+    use crate::paths::get_test_data_path;
+
+    use crate::interface::events::StubEventEmitter;
+    use crate::interface::lifecycle::is_removed_error;
+
+    use super::*;
+
+    fn check_max_render_window(grid: &SplitGrid, expected_max_render_window: u64) {
+        for entry in grid.splits.iter() {
+            let split = entry.value();
+            assert_eq!(split.read().max_render_window, expected_max_render_window);
+        }
+    }
+
+    struct GridTestState {
+        pub event_emitter: Arc<StubEventEmitter>,
+        pub max_render_window: u64,
+        pub bam_path: PathBuf,
+        pub grid: Arc<SplitGrid>,
+        pub split_id: SplitId,
+        pub track_id: TrackId,
+        pub buffered_region: GenomicRegion,
+        pub focused_sequence: String,
+        pub buffered_sequence: String,
+    }
+
+    fn init_basic_split_grid() -> GridTestState {
+        let max_render_window = 10000;
+        let grid = Arc::new(SplitGrid::new(max_render_window).unwrap());
+        let event_emitter = Arc::new(StubEventEmitter::new());
+        let bam_path = get_test_data_path("fake-genome.tiny.bam");
+        let track_id = grid.add_track(&event_emitter, bam_path.clone()).unwrap();
+        let split_id = grid.get_split_ids()[0];
+        let split = grid.get_split(&split_id).unwrap();
+        let buffered_region = split.read().buffered_region.clone();
+        let focused_sequence =
+            split.read().focused_sequence().unwrap().unwrap().to_string().unwrap();
+        let buffered_sequence =
+            split.read().buffered_sequence.clone().unwrap().to_string().unwrap();
+        drop(split);
+        GridTestState {
+            event_emitter,
+            max_render_window,
+            bam_path,
+            split_id: grid.get_split_ids()[0],
+            grid,
+            track_id,
+            buffered_region,
+            focused_sequence,
+            buffered_sequence,
+        }
+    }
 
-    use crate::interface::events::StubEventEmitter;
+    #[test]
+    fn test_initialization() {
+        let test_state = init_basic_split_grid();
+        assert_eq!(test_state.grid.splits.len(), 1);
+        assert_eq!(test_state.grid.tracks.len(), 1);
+        assert_eq!(test_state.grid.reference.read().name, "HG19");
+        check_max_render_window(&test_state.grid, test_state.max_render_window)
+    }
 
-    use super::*;
+    #[test]
+    fn test_set_max_render_window() {
+        let test_state = init_basic_split_grid();
+        let new_render_window = 10000;
+        test_state.grid.set_max_render_window(new_render_window).unwrap();
+        let focused_region = GenomicRegion::new("euk_genes", 0, 1000).unwrap();
+        // Ensuring that updating max_render_window affects both existing splits and any added in
+        // future
+        test_state.grid.add_split(&test_state.event_emitter, Some(focused_region)).unwrap();
+        check_max_render_window(&test_state.grid, new_render_window);
+    }
+
+    #[test]
+    fn test_add_split() {
+        let test_state = init_basic_split_grid();
+        let focused_region = GenomicRegion::new("euk_genes", 0, 1000).unwrap();
+        let split_id = test_state
+            .grid
+            .add_split(&test_state.event_emitter, Some(focused_region.clone()))
+            .unwrap();
+        assert_eq!(test_state.grid.splits.len(), 2);
+        let payload = test_state.event_emitter.pop_until(&Event::SplitAdded);
+        assert_eq!(
+            payload.get("focusedRegion").unwrap(),
+            &serde_json::to_value(&focused_region).unwrap()
+        );
+        assert_eq!(payload.get("id").unwrap().as_str().unwrap(), split_id.to_string());
+    }
+
+    #[test]
+    fn test_remove_track_drops_stack_readers() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let event_emitter = &test_state.event_emitter;
+        let other_split_id = grid.add_split(event_emitter, None).unwrap();
+        let stack_reader =
+            grid.get_stack_reader(&test_state.split_id, &test_state.track_id).unwrap();
+        grid.remove_track(event_emitter, &test_state.track_id).unwrap();
+        assert!(grid.tracks.is_empty());
+        assert!(grid.alignments.is_empty());
+        assert_eq!(Arc::strong_count(&stack_reader), 1);
+        assert_eq!(grid.focus.read().track_id, None);
+        assert!(grid.remove_track(event_emitter, &test_state.track_id).is_err());
+        // Work which was in progress for the track when it was removed doesn't add it back
+        let err = grid.add_stack_reader(&other_split_id, &test_state.track_id).unwrap_err();
+        assert!(is_removed_error(&err));
+        assert!(grid.alignments.is_empty());
+        let region = GenomicRegion::new("euk_genes", 5000, 6000).unwrap();
+        grid.update_focused_region(event_emitter, &test_state.split_id, region).unwrap();
+    }
+
+    #[test]
+    fn test_add_split_reads_overlapping_alignments() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let focused_region =
+            grid.get_split(&test_state.split_id).unwrap().read().focused_region.clone();
+        let quarter = focused_region.len() / 4;
+        let start = focused_region.start() + 3 * quarter / 2;
+        let region = GenomicRegion::new(&focused_region.seq_name, start, start + quarter).unwrap();
+        let split_id = grid.add_split(&test_state.event_emitter, Some(region)).unwrap();
+        let buffered_region = grid.get_split(&split_id).unwrap().read().buffered_region.clone();
+        assert!(test_state.buffered_region.contains(buffered_region.clone()));
+
+        let stack_reader = grid.get_stack_reader(&split_id, &test_state.track_id).unwrap();
+        let sibling = grid.get_stack_reader(&test_state.split_id, &test_state.track_id).unwrap();
+        // Alignments are only shared by splits which are loaded at the same time, and aren't kept
+        // once they're stacked
+        assert!(stack_reader.fetched().is_none());
+        assert!(sibling.fetched().is_none());
+        let num_alignments = stack_reader.stack().read().count_alignments();
+        let expected = StackReader::new(&test_state.bam_path).unwrap();
+        let seq = grid.get_split(&split_id).unwrap().read().buffered_sequence.clone().unwrap();
+        expected.read_stacked(&buffered_region, &seq).unwrap();
+        assert_eq!(num_alignments, expected.stack().read().count_alignments());
+    }
+
+    #[test]
+    fn test_remove_split_drops_stack_readers() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let event_emitter = &test_state.event_emitter;
+        let other_split_id = grid.add_split(event_emitter, None).unwrap();
+        assert_eq!(grid.focus.read().split_id, other_split_id);
+        let stack_reader = grid.get_stack_reader(&other_split_id, &test_state.track_id).unwrap();
+        grid.remove_split(event_emitter, &other_split_id).unwrap();
+        assert_eq!(grid.get_split_ids(), vec![test_state.split_id]);
+        assert!(grid.get_split(&other_split_id).is_err());
+        assert_eq!(grid.alignments.len(), 1);
+        assert_eq!(Arc::strong_count(&stack_reader), 1);
+        assert_eq!(grid.focus.read().split_id, test_state.split_id);
+        let err = grid.get_stack_reader(&other_split_id, &test_state.track_id).unwrap_err();
+        assert!(is_removed_error(&err));
+        // Tracks which are added afterwards skip the removed split
+        grid.add_track(event_emitter, test_state.bam_path.clone()).unwrap();
+        assert_eq!(grid.alignments.len(), 2);
+        assert!(grid.remove_split(event_emitter, &test_state.split_id).is_err());
+    }
+
+    #[test]
+    fn test_get_split() {
+        let test_state = init_basic_split_grid();
+        let split = test_state.grid.get_split(&test_state.split_id).unwrap();
+        assert_eq!(split.read().id, test_state.split_id);
+    }
+
+    #[test]
+    fn test_get_stack_reader() {
+        let test_state = init_basic_split_grid();
+        let stack_reader =
+            test_state.grid.get_stack_reader(&test_state.split_id, &test_state.track_id).unwrap();
+        assert_eq!(stack_reader.path, test_state.bam_path);
+    }
+
+    #[test]
+    fn test_get_pileup() {
+        let test_state = init_basic_split_grid();
+        let region = GenomicRegion::new("euk_genes", 100, 200).unwrap();
+        let pileup = test_state
+            .grid
+            .get_pileup(&test_state.split_id, &test_state.track_id, &region)
+            .unwrap();
+        assert_eq!(pileup.columns.len(), 100);
+        assert_eq!(pileup.columns[0].position, 100);
+    }
+
+    #[test]
+    fn test_get_or_read_pileup() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let (split_id, track_id) = (&test_state.split_id, &test_state.track_id);
+        let region = GenomicRegion::new("euk_genes", 100, 200).unwrap();
+        let buffered = grid.get_pileup(split_id, track_id, &region).unwrap();
+        let pileup = grid.get_or_read_pileup(split_id, track_id, &region).unwrap();
+        assert_eq!(pileup.columns, buffered.columns);
+        // Regions outside of the buffered region are read from the file
+        let region = GenomicRegion::new("chrM", 1000, 1100).unwrap();
+        assert!(grid.get_pileup(split_id, track_id, &region).is_err());
+        let pileup = grid.get_or_read_pileup(split_id, track_id, &region).unwrap();
+        assert_eq!(pileup.region, GenomicRegion::new("mt", 1000, 1100).unwrap());
+        assert_eq!(pileup.columns.len(), 100);
+    }
+
+    #[test]
+    fn test_align_to_focused_region() {
+        let test_state = init_basic_split_grid();
+        let region = GenomicRegion::new("mt", 0, 200).unwrap();
+        test_state
+            .grid
+            .update_focused_region(&test_state.event_emitter, &test_state.split_id, region)
+            .unwrap();
+        let mut reads = read_fastq(get_test_data_path("fake-genome.reads.fastq")).unwrap().reads;
+        test_state.grid.align_to_focused_region(&test_state.split_id, &mut reads).unwrap();
+        let alignment = reads[0].alignment.as_ref().unwrap();
+        assert_eq!(alignment.region, GenomicRegion::new("mt", 0, 44).unwrap());
+        let alignment = reads[1].alignment.as_ref().unwrap();
+        assert_eq!(alignment.region, GenomicRegion::new("mt", 100, 150).unwrap());
+        assert!(alignment.is_reverse);
+        assert_eq!(reads[2].alignment, None);
+    }
+
+    #[test]
+    fn test_coverage_rules_checked_on_focused_region_update() {
+        let test_state = init_basic_split_grid();
+        test_state.grid.set_coverage_rules(vec![
+            CoverageRule::new("unreachable depth", u32::MAX),
+            CoverageRule::new("no depth", 0),
+        ]);
+        let region = GenomicRegion::new("euk_genes", 100, 200).unwrap();
+        test_state
+            .grid
+            .update_focused_region(&test_state.event_emitter, &test_state.split_id, region)
+            .unwrap();
+        let payload = test_state.event_emitter.pop_until(&Event::QualityAlert);
+        assert_eq!(
+            payload.get("trackId").unwrap().as_str().unwrap(),
+            test_state.track_id.to_string()
+        );
+        let alerts = payload.get("alerts").unwrap().as_array().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].get("ruleName").unwrap(), "unreachable depth");
+        assert_eq!(alerts[0].get("lowDepthPositions").unwrap(), "100");
+    }
+
+    #[test]
+    fn test_get_unmapped_mate_for_missing_read() {
+        let test_state = init_basic_split_grid();
+        let result = test_state.grid.get_unmapped_mate(
+            &test_state.split_id,
+            &test_state.track_id,
+            "missing",
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_get_insertion_sequence() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let stack_reader =
+            grid.get_stack_reader(&test_state.split_id, &test_state.track_id).unwrap();
+        let insertion = match &*stack_reader.stack().read() {
+            AlignmentStackKind::AlignedPairKind(stack) => {
+                stack.rows.iter().flatten().flat_map(|pair| pair.reads()).find_map(|read| {
+                    read.diffs.iter().find_map(|diff| match diff {
+                        SequenceDiff::Ins { interval, sequence, .. } => {
+                            Some((read.id.clone(), interval.start, sequence.clone()))
+                        }
+                        _ => None,
+                    })
+                })
+            }
+            _ => panic!("Expected a SAM/BAM stack"),
+        };
+        let (read_id, position, sequence) = insertion.unwrap();
+        let split_id = &test_state.split_id;
+        let track_id = &test_state.track_id;
+        assert_eq!(
+            grid.get_insertion_sequence(split_id, track_id, &read_id, position).unwrap(),
+            sequence
+        );
+        assert!(grid.get_insertion_sequence(split_id, track_id, &read_id, position + 1).is_err());
+        assert!(grid.get_insertion_sequence(split_id, track_id, "missing", position).is_err());
+    }
+
+    #[test]
+    fn test_jump_to_mate() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let (split_id, track_id) = (&test_state.split_id, &test_state.track_id);
+        let stack_reader = grid.get_stack_reader(split_id, track_id).unwrap();
+        let read = match &*stack_reader.stack().read() {
+            AlignmentStackKind::AlignedPairKind(stack) => stack
+                .rows
+                .iter()
+                .flatten()
+                .flat_map(|pair| pair.reads())
+                .find(|read| read.mate_pos.is_some())
+                .unwrap()
+                .clone(),
+            _ => panic!("Expected a SAM/BAM stack"),
+        };
+        let mate_region = grid.find_mate(split_id, track_id, &read.id).unwrap().unwrap();
+        assert_eq!(mate_region.start(), read.mate_pos.unwrap().start());
+        let previous_len = grid.get_split(split_id).unwrap().read().focused_region.len();
+        let event_emitter = Arc::clone(&test_state.event_emitter);
+        let jumped_region = grid.jump_to_mate(event_emitter, split_id, track_id, &read.id).unwrap();
+        assert_eq!(jumped_region, Some(mate_region.clone()));
+        test_state.event_emitter.wait_for(&Event::FocusedRegionLoaded);
+        let focused_region = grid.get_split(split_id).unwrap().read().focused_region.clone();
+        assert_eq!(focused_region.len(), previous_len);
+        assert!(focused_region.contains(mate_region));
+        assert!(grid.find_mate(split_id, track_id, "missing").is_err());
+    }
+
+    #[test]
+    fn test_get_read_details() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let (split_id, track_id) = (&test_state.split_id, &test_state.track_id);
+        let stack_reader = grid.get_stack_reader(split_id, track_id).unwrap();
+        let read_id = match &*stack_reader.stack().read() {
+            AlignmentStackKind::AlignedPairKind(stack) => {
+                stack.rows[0][0].reads().next().unwrap().id.clone()
+            }
+            _ => panic!("Expected a SAM/BAM stack"),
+        };
+        let details = grid.get_read_details(split_id, track_id, &read_id).unwrap();
+        assert_eq!(details.read.id, read_id);
+        assert!(grid.get_read_details(split_id, track_id, "missing").is_err());
+    }
+
+    #[test]
+    fn test_get_reads_at() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let (split_id, track_id) = (&test_state.split_id, &test_state.track_id);
+        let stack_reader = grid.get_stack_reader(split_id, track_id).unwrap();
+        let (pair_id, read_id, position) = match &*stack_reader.stack().read() {
+            AlignmentStackKind::AlignedPairKind(stack) => {
+                let pair = &stack.rows[0][0];
+                let read = pair.reads().next().unwrap();
+                (pair.id().to_owned(), read.id.clone(), read.region.start())
+            }
+            _ => panic!("Expected a SAM/BAM stack"),
+        };
+        let reads = grid.get_reads_at(split_id, track_id, position).unwrap();
+        let expected = ReadAtPosition { row_index: 0, pair_id, read_id };
+        assert_eq!(reads.iter().find(|read| read.row_index == 0), Some(&expected));
+    }
+
+    #[test]
+    fn test_load_sequencing_summary() {
+        let test_state = init_basic_split_grid();
+        let summary_path = get_test_data_path("fake-genome.sequencing_summary.txt");
+        test_state
+            .grid
+            .load_sequencing_summary(&test_state.event_emitter, &test_state.track_id, summary_path)
+            .unwrap();
+        let payload = test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+        assert_eq!(
+            payload.get("trackId").unwrap().as_str().unwrap(),
+            test_state.track_id.to_string()
+        );
+        let missing_path = get_test_data_path("missing.sequencing_summary.txt");
+        let result = test_state.grid.load_sequencing_summary(
+            &test_state.event_emitter,
+            &test_state.track_id,
+            missing_path,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_add_track_with_mismatched_reference() {
+        // A reference which is missing the mt sequence of the BAM
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir(&dir).unwrap();
+        let fasta_path = dir.join("genome.fa");
+        let region = GenomicRegion::new("euk_genes", 0, 7185).unwrap();
+        let sequence = get_default_reference().unwrap().read_sequence(&region).unwrap();
+        let fasta = format!(">euk_genes\n{}\n", sequence.to_string().unwrap());
+        std::fs::write(&fasta_path, fasta).unwrap();
+        build_fasta_index(&fasta_path).unwrap();
+        let reference = ReferenceSequence::new("genome".to_owned(), &fasta_path).unwrap();
+        let grid = SplitGrid::from_reference(reference, 10000).unwrap();
+        let event_emitter = StubEventEmitter::new();
+        let bam_path = get_test_data_path("fake-genome.tiny.bam");
+        let track_id = grid.add_track(&event_emitter, &bam_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let payload = event_emitter.pop_until(&Event::ReferenceMismatch);
+        assert_eq!(payload.get("trackId").unwrap().as_str().unwrap(), track_id.to_string());
+        let mismatches = payload.get("mismatches").unwrap().as_array().unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].get("seqName").unwrap(), "mt");
+        assert_eq!(mismatches[0].get("kind").unwrap(), "missing");
+
+        // Tracks which match the reference don't emit a warning
+        let test_state = init_basic_split_grid();
+        let calls = test_state.event_emitter.calls.lock();
+        assert!(!calls.iter().any(|(event, _)| matches!(event, Event::ReferenceMismatch)));
+    }
+
+    #[test]
+    fn test_get_diff_labels() {
+        let test_state = init_basic_split_grid();
+        let (split_id, track_id) = (&test_state.split_id, &test_state.track_id);
+        let zoomed_in = test_state.grid.get_diff_labels(split_id, track_id, 20.0).unwrap();
+        assert!(!zoomed_in.is_empty());
+        assert!(zoomed_in.iter().any(|label| label.show));
+        let zoomed_out = test_state.grid.get_diff_labels(split_id, track_id, 0.5).unwrap();
+        assert_eq!(zoomed_out.len(), zoomed_in.len());
+        let num_shown = |labels: &[DiffLabel]| labels.iter().filter(|label| label.show).count();
+        assert!(num_shown(&zoomed_out) < num_shown(&zoomed_in));
+    }
+
+    #[test]
+    fn test_get_view_summary() {
+        let test_state = init_basic_split_grid();
+        let (split_id, track_id) = (&test_state.split_id, &test_state.track_id);
+        let summary = test_state.grid.get_view_summary(split_id, track_id).unwrap();
+        let focused_region =
+            test_state.grid.get_split(split_id).unwrap().read().focused_region.clone();
+        assert_eq!(summary.region, focused_region);
+        assert!(summary.min_depth <= summary.max_depth);
+        assert!(summary.text.starts_with(&format!("{} (", format_region(&focused_region))));
+    }
+
+    #[test]
+    fn test_get_coverage() {
+        let test_state = init_basic_split_grid();
+        let payload = test_state.event_emitter.pop_until(&Event::CoverageUpdated);
+        assert_eq!(
+            payload.get("trackId").unwrap().as_str().unwrap(),
+            test_state.track_id.to_string()
+        );
+        let coverage = test_state
+            .grid
+            .get_coverage(&test_state.split_id, &test_state.track_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(coverage.region, test_state.buffered_region);
+        assert_eq!(coverage.depths.len() as u64, test_state.buffered_region.len());
+        assert_eq!(payload.get("coverage").unwrap(), &serde_json::to_value(&coverage).unwrap());
+        let pileup = test_state
+            .grid
+            .get_pileup(&test_state.split_id, &test_state.track_id, &test_state.buffered_region)
+            .unwrap();
+        let expected_depths: Vec<u32> =
+            pileup.columns.iter().map(|column| column.depth()).collect();
+        assert_eq!(coverage.depths, expected_depths);
+        assert!(coverage.depths.iter().any(|depth| *depth > 0));
+    }
+
+    #[test]
+    fn test_add_track() {
+        let test_state = init_basic_split_grid();
+        assert_eq!(test_state.grid.tracks.len(), 1);
+        let track_id = test_state
+            .grid
+            .add_track(&test_state.event_emitter, test_state.bam_path.clone())
+            .unwrap();
+        assert_eq!(test_state.grid.tracks.len(), 2);
+        let payload = test_state.event_emitter.pop_until(&Event::TrackAdded);
+        assert_eq!(
+            payload.get("filePath").unwrap().as_str().unwrap(),
+            test_state.bam_path.to_str().unwrap()
+        );
+        assert_eq!(track_id, test_state.grid.focus.read().track_id.unwrap());
+        assert_eq!(payload.get("id").unwrap().as_str().unwrap(), test_state.track_id.to_string());
+    }
+
+    #[test]
+    fn test_add_bedgraph_track_emits_signal() {
+        let test_state = init_basic_split_grid();
+        let (grid, event_emitter) = (&test_state.grid, &test_state.event_emitter);
+        let bedgraph_path = get_test_data_path("fake-genome.bedgraph.gz");
+        let signal_reader = SignalReader::new(&bedgraph_path).unwrap();
+        let expected_bins = |split_id: &SplitId| {
+            let buffered_region = grid.get_split(split_id).unwrap().read().buffered_region.clone();
+            let intervals = signal_reader.read(&buffered_region).unwrap();
+            serde_json::to_value(SignalBins::summarize(&buffered_region, &intervals)).unwrap()
+        };
+        let region = GenomicRegion::new("mt", 0, 100).unwrap();
+        grid.update_focused_region(event_emitter, &test_state.split_id, region).unwrap();
+        let track_id = grid.add_track(event_emitter, &bedgraph_path).unwrap();
+        let payload = event_emitter.pop_until(&Event::SignalUpdated);
+        assert_eq!(payload["splitId"], test_state.split_id.to_string());
+        assert_eq!(payload["trackId"], track_id.to_string());
+        assert_eq!(payload["bins"], expected_bins(&test_state.split_id));
+        assert!(payload["bins"]["values"][0].is_number());
+
+        // Signal values are re-sent when the focused region moves
+        let region = GenomicRegion::new("euk_genes", 0, 50).unwrap();
+        grid.update_focused_region(event_emitter, &test_state.split_id, region).unwrap();
+        let payload = event_emitter.pop_until(&Event::SignalUpdated);
+        assert_eq!(payload["bins"], expected_bins(&test_state.split_id));
+        assert_eq!(payload["bins"]["values"][0], 4.0);
+    }
+
+    #[test]
+    fn test_update_split_annotation() {
+        let test_state = init_basic_split_grid();
+        let annotation =
+            SplitAnnotation { name: Some("Breakpoint A".to_owned()), description: None };
+        test_state
+            .grid
+            .update_split_annotation(
+                &test_state.event_emitter,
+                &test_state.split_id,
+                annotation.clone(),
+            )
+            .unwrap();
+        let split = test_state.grid.get_split(&test_state.split_id).unwrap();
+        assert_eq!(split.read().annotation, annotation);
+        let payload = test_state.event_emitter.pop_until(&Event::SplitUpdated);
+        assert_eq!(payload.get("name").unwrap().as_str().unwrap(), "Breakpoint A");
+        assert!(payload.get("description").unwrap().is_null());
+    }
+
+    #[test]
+    fn test_update_track_customization() {
+        let test_state = init_basic_split_grid();
+        let customization = TrackCustomization {
+            color: Some("#1f77b4".to_owned()),
+            label: Some("Tumor".to_owned()),
+            description: None,
+            priority: TrackPriority::Low,
+            stack_padding: None,
+            packing: PackingStrategy::Tight,
+            grouping: StackGrouping::Ungrouped,
+            max_depth: None,
+            max_rows: None,
+            base_qualities: false,
+            coverage_strand: CoverageStrand::Combined,
+        };
+        test_state
+            .grid
+            .update_track_customization(
+                &test_state.event_emitter,
+                &test_state.track_id,
+                customization.clone(),
+            )
+            .unwrap();
+        let track = test_state.grid.tracks.get(&test_state.track_id).unwrap();
+        assert_eq!(track.read().customization(), &customization);
+        let payload = test_state.event_emitter.pop_until(&Event::TrackUpdated);
+        assert_eq!(payload.get("color").unwrap().as_str().unwrap(), "#1f77b4");
+        assert_eq!(payload.get("label").unwrap().as_str().unwrap(), "Tumor");
+    }
+
+    #[test]
+    fn test_update_track_base_qualities() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let has_base_qualities = || {
+            let stack_reader =
+                grid.get_stack_reader(&test_state.split_id, &test_state.track_id).unwrap();
+            let stack = stack_reader.stack();
+            let stack = stack.read();
+            match &*stack {
+                AlignmentStackKind::AlignedPairKind(stack) => stack
+                    .rows
+                    .iter()
+                    .flatten()
+                    .flat_map(|pair| pair.reads())
+                    .all(|read| read.base_qualities.is_some()),
+                _ => panic!("Expected a SAM/BAM stack"),
+            }
+        };
+        assert!(!has_base_qualities());
+        let customization = TrackCustomization { base_qualities: true, ..Default::default() };
+        grid.update_track_customization(
+            &test_state.event_emitter,
+            &test_state.track_id,
+            customization,
+        )
+        .unwrap();
+        assert!(has_base_qualities());
+        test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+    }
+
+    #[test]
+    fn test_update_track_coverage_strand() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let customization =
+            TrackCustomization { coverage_strand: CoverageStrand::Read, ..Default::default() };
+        grid.update_track_customization(
+            &test_state.event_emitter,
+            &test_state.track_id,
+            customization,
+        )
+        .unwrap();
+        test_state.event_emitter.pop_until(&Event::TrackUpdated);
+        let payload = test_state.event_emitter.pop_until(&Event::CoverageUpdated);
+        let coverage =
+            grid.get_coverage(&test_state.split_id, &test_state.track_id).unwrap().unwrap();
+        assert_eq!(payload.get("coverage").unwrap(), &serde_json::to_value(&coverage).unwrap());
+        let stranded_depths = coverage.stranded_depths.unwrap();
+        let summed_depths: Vec<u32> = stranded_depths
+            .forward
+            .iter()
+            .zip(stranded_depths.reverse.iter())
+            .map(|(forward, reverse)| forward + reverse)
+            .collect();
+        assert_eq!(summed_depths, coverage.depths);
+    }
+
+    #[test]
+    fn test_update_track_max_depth() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let track_id = grid
+            .add_track(&test_state.event_emitter, get_test_data_path("fake-genome.reads.bam"))
+            .unwrap();
+        let count_alignments = |split_id: &SplitId| {
+            let stack_reader = grid.get_stack_reader(split_id, &track_id).unwrap();
+            let count = stack_reader.stack().read().count_alignments();
+            count
+        };
+        let num_alignments = count_alignments(&test_state.split_id);
+        let update_max_depth = |max_depth: Option<u32>| {
+            let customization = TrackCustomization { max_depth, ..Default::default() };
+            grid.update_track_customization(&test_state.event_emitter, &track_id, customization)
+                .unwrap();
+        };
+        update_max_depth(Some(2));
+        let num_sampled = count_alignments(&test_state.split_id);
+        assert!(num_sampled < num_alignments);
+        let payload = test_state.event_emitter.pop_until(&Event::ReadsDownsampled);
+        assert_eq!(payload["maxDepth"], 2);
+        assert_eq!(payload["numAlignments"], num_alignments.to_string());
+        assert_eq!(payload["numSampled"], num_sampled.to_string());
+        assert!(payload["sampledFraction"].as_f64().unwrap() < 1.0);
+        // Readers for new splits use the track's max depth
+        let split_id = grid.add_split(&test_state.event_emitter, None).unwrap();
+        assert_eq!(count_alignments(&split_id), num_sampled);
+
+        update_max_depth(None);
+        assert_eq!(count_alignments(&test_state.split_id), num_alignments);
+    }
+
+    #[test]
+    fn test_set_track_filter() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let track_id = grid
+            .add_track(&test_state.event_emitter, get_test_data_path("fake-genome.reads.bam"))
+            .unwrap();
+        let count_alignments = |split_id: &SplitId| {
+            let stack_reader = grid.get_stack_reader(split_id, &track_id).unwrap();
+            let count = stack_reader.stack().read().count_alignments();
+            count
+        };
+        let num_alignments = count_alignments(&test_state.split_id);
+        let filter = ReadFilter { min_mapq: 40, ..Default::default() };
+        grid.set_track_filter(&test_state.event_emitter, &track_id, filter.clone()).unwrap();
+        let payload = test_state.event_emitter.pop_until(&Event::TrackUpdated);
+        assert_eq!(payload["filter"]["minMapq"], 40);
+        let num_filtered = count_alignments(&test_state.split_id);
+        assert!(num_filtered > 0 && num_filtered < num_alignments);
+        let payload = test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+        assert_eq!(payload["trackId"], track_id.to_string());
+        assert!(payload["skippedReads"]["filteredCount"].as_u64().unwrap() > 0);
+        // Readers for new splits use the track's filter
+        let split_id = grid.add_split(&test_state.event_emitter, None).unwrap();
+        assert_eq!(count_alignments(&split_id), num_filtered);
+
+        grid.set_track_filter(&test_state.event_emitter, &track_id, ReadFilter::default()).unwrap();
+        assert_eq!(count_alignments(&test_state.split_id), num_alignments);
+        assert_eq!(count_alignments(&split_id), num_alignments);
+
+        // Expressions are equivalent to the corresponding filter options
+        let expression = Some("mapq >= 40".parse().unwrap());
+        let expression_filter = ReadFilter { expression, ..Default::default() };
+        grid.set_track_filter(&test_state.event_emitter, &track_id, expression_filter).unwrap();
+        let payload = test_state.event_emitter.pop_until(&Event::TrackUpdated);
+        assert_eq!(payload["filter"]["expression"], serde_json::Value::Null);
+        let payload = test_state.event_emitter.pop_until(&Event::TrackUpdated);
+        assert_eq!(payload["filter"]["expression"], "mapq >= 40");
+        assert_eq!(count_alignments(&test_state.split_id), num_filtered);
+
+        let paf_track_id = grid
+            .add_track(&test_state.event_emitter, get_test_data_path("fake-genome.paf"))
+            .unwrap();
+        assert!(grid.set_track_filter(&test_state.event_emitter, &paf_track_id, filter).is_err());
+    }
+
+    #[test]
+    fn test_split_track_by_tag() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let track_id = test_state.track_id;
+        let filter = ReadFilter { min_mapq: 10, ..Default::default() };
+        grid.set_track_filter(&test_state.event_emitter, &track_id, filter).unwrap();
+        let group = |name: &str, values: &[&str]| TagGroup {
+            name: name.to_owned(),
+            values: values.iter().map(|value| value.to_string()).collect(),
+        };
+        let groups = vec![group("T cells", &["AAAC-1", "AAAG-1"]), group("AAAT-1", &["AAAT-1"])];
+        // Skip the event for the original track
+        test_state.event_emitter.pop_until(&Event::TrackAdded);
+        let track_ids =
+            grid.split_track_by_tag(&test_state.event_emitter, &track_id, "CB", groups).unwrap();
+        assert_eq!(track_ids.len(), 2);
+        assert_eq!(grid.tracks.len(), 3);
+        let payload = test_state.event_emitter.pop_until(&Event::TrackAdded);
+        assert_eq!(payload["name"], "T cells");
+        assert_eq!(payload["filter"]["minMapq"], 10);
+        assert_eq!(
+            payload["filter"]["tagValues"]["values"],
+            serde_json::json!(["AAAC-1", "AAAG-1"])
+        );
+        // None of the test reads have cell barcodes
+        let stack_reader = grid.get_stack_reader(&test_state.split_id, &track_ids[0]).unwrap();
+        assert_eq!(stack_reader.stack().read().count_alignments(), 0);
+        // The original track is left as is
+        let track = grid.tracks.get(&track_id).unwrap();
+        assert!(track.read().filter().tag_values.is_none());
+        drop(track);
 
-    fn check_max_render_window(grid: &SplitGrid, expected_max_render_window: u64) {
-        for entry in grid.splits.iter() {
-            let split = entry.value();
-            assert_eq!(split.read().max_render_window, expected_max_render_window);
-        }
+        // No tracks are added if any group is invalid
+        let groups = vec![group("valid", &["AAAC-1"]), group("empty", &[])];
+        let result = grid.split_track_by_tag(&test_state.event_emitter, &track_id, "CB", groups);
+        assert!(result.is_err());
+        assert_eq!(grid.tracks.len(), 3);
     }
 
-    struct GridTestState {
-        pub event_emitter: StubEventEmitter,
-        pub max_render_window: u64,
-        pub bam_path: PathBuf,
-        pub grid: SplitGrid,
-        pub split_id: SplitId,
-        pub track_id: TrackId,
-        pub buffered_region: GenomicRegion,
-        pub focused_sequence: String,
-        pub buffered_sequence: String,
+    #[test]
+    fn test_sort_alignments() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let split_id = test_state.split_id;
+        let buffered_region = grid.get_split(&split_id).unwrap().read().buffered_region.clone();
+        let position = buffered_region.start() + buffered_region.len() / 2;
+        grid.sort_alignments(
+            &test_state.event_emitter,
+            &split_id,
+            &test_state.track_id,
+            position,
+            SortBy::Base,
+        )
+        .unwrap();
+        let payload = test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+        assert_eq!(payload["trackId"], test_state.track_id.to_string());
+        let result = grid.sort_alignments(
+            &test_state.event_emitter,
+            &split_id,
+            &test_state.track_id,
+            buffered_region.end() + 1,
+            SortBy::Base,
+        );
+        assert!(result.is_err());
     }
 
-    fn init_basic_split_grid() -> GridTestState {
-        let max_render_window = 10000;
-        let grid = SplitGrid::new(max_render_window).unwrap();
-        let event_emitter = StubEventEmitter::new();
-        let bam_path = get_test_data_path("fake-genome.tiny.bam");
-        let track_id = grid.add_track(&event_emitter, bam_path.clone()).unwrap();
-        let split_id = grid.get_split_ids()[0];
-        let split = grid.get_split(&split_id).unwrap();
-        let buffered_region = split.read().buffered_region.clone();
-        let focused_sequence =
-            split.read().focused_sequence().unwrap().unwrap().to_string().unwrap();
-        let buffered_sequence =
-            split.read().buffered_sequence.clone().unwrap().to_string().unwrap();
-        drop(split);
-        GridTestState {
-            event_emitter,
-            max_render_window,
-            bam_path,
-            split_id: grid.get_split_ids()[0],
-            grid,
-            track_id,
-            buffered_region,
-            focused_sequence,
-            buffered_sequence,
-        }
+    #[test]
+    fn test_get_read_length_histogram() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let split_id = test_state.split_id;
+        let focused_region = grid.get_split(&split_id).unwrap().read().focused_region.clone();
+        let histogram = grid
+            .get_read_length_histogram(
+                &split_id,
+                &test_state.track_id,
+                ReadLengthScope::FocusedRegion,
+                10,
+            )
+            .unwrap();
+        assert_eq!(histogram.region, Some(focused_region));
+        assert!(!histogram.is_estimate);
+        let counts: u64 = histogram.bins.iter().map(|bin| bin.count).sum();
+        assert_eq!(counts, histogram.num_reads);
     }
 
     #[test]
-    fn test_initialization() {
+    fn test_split_track_without_read_groups() {
         let test_state = init_basic_split_grid();
-        assert_eq!(test_state.grid.splits.len(), 1);
-        assert_eq!(test_state.grid.tracks.len(), 1);
-        assert_eq!(test_state.grid.reference.read().name, "HG19");
-        check_max_render_window(&test_state.grid, test_state.max_render_window)
+        let grid = &test_state.grid;
+        let track_id = test_state.track_id;
+        let result = grid.split_track_by_read_group(
+            &test_state.event_emitter,
+            &track_id,
+            ReadGroupField::Sample,
+        );
+        assert!(result.is_err());
+        // The track is left as is
+        assert_eq!(grid.tracks.len(), 1);
+        assert_eq!(grid.tracks.get(&track_id).unwrap().read().filter(), ReadFilter::default());
+
+        let paf_track_id = grid
+            .add_track(&test_state.event_emitter, get_test_data_path("fake-genome.paf"))
+            .unwrap();
+        let result = grid.split_track_by_read_group(
+            &test_state.event_emitter,
+            &paf_track_id,
+            ReadGroupField::Library,
+        );
+        assert!(result.is_err());
     }
 
     #[test]
-    fn test_set_max_render_window() {
+    fn test_set_hide_duplicates() {
         let test_state = init_basic_split_grid();
-        let new_render_window = 10000;
-        test_state.grid.set_max_render_window(new_render_window).unwrap();
-        let focused_region = GenomicRegion::new("euk_genes", 0, 1000).unwrap();
-        // Ensuring that updating max_render_window affects both existing splits and any added in
-        // future
-        test_state.grid.add_split(&test_state.event_emitter, Some(focused_region)).unwrap();
-        check_max_render_window(&test_state.grid, new_render_window);
+        let grid = &test_state.grid;
+        let track_id = test_state.track_id;
+        let filter = ReadFilter { min_mapq: 10, ..Default::default() };
+        grid.set_track_filter(&test_state.event_emitter, &track_id, filter).unwrap();
+        let payload = test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+        let filtered_count = payload["skippedReads"]["filteredCount"].clone();
+        grid.set_hide_duplicates(&test_state.event_emitter, &track_id, true).unwrap();
+        let track = grid.tracks.get(&track_id).unwrap();
+        let expected = ReadFilter { min_mapq: 10, hide_duplicates: true, ..Default::default() };
+        assert_eq!(track.read().filter(), expected);
+        drop(track);
+        let payload = test_state.event_emitter.pop_until(&Event::TrackUpdated);
+        assert_eq!(payload["filter"]["hideDuplicates"], true);
+        // None of the test reads are flagged as duplicates
+        let payload = test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+        assert_eq!(payload["skippedReads"]["filteredCount"], filtered_count);
+
+        grid.set_hide_secondary(&test_state.event_emitter, &track_id, true).unwrap();
+        grid.set_hide_supplementary(&test_state.event_emitter, &track_id, true).unwrap();
+        let track = grid.tracks.get(&track_id).unwrap();
+        let expected = ReadFilter {
+            min_mapq: 10,
+            hide_duplicates: true,
+            hide_secondary: true,
+            hide_supplementary: true,
+            expression: None,
+            read_groups: None,
+            tag_values: None,
+        };
+        assert_eq!(track.read().filter(), expected);
     }
 
     #[test]
-    fn test_add_split() {
+    fn test_update_track_stack_padding() {
         let test_state = init_basic_split_grid();
-        let focused_region = GenomicRegion::new("euk_genes", 0, 1000).unwrap();
-        let split_id = test_state
+        let count_rows = || {
+            let stack_reader =
+                test_state.grid.get_stack_reader(&test_state.split_id, &test_state.track_id);
+            let stack = stack_reader.unwrap().serialize_stack().unwrap();
+            stack["rows"].as_array().unwrap().len()
+        };
+        let num_rows = count_rows();
+        let customization =
+            TrackCustomization { stack_padding: Some(MAX_STACK_PADDING), ..Default::default() };
+        test_state
             .grid
-            .add_split(&test_state.event_emitter, Some(focused_region.clone()))
+            .update_track_customization(
+                &test_state.event_emitter,
+                &test_state.track_id,
+                customization,
+            )
             .unwrap();
-        assert_eq!(test_state.grid.splits.len(), 2);
-        let payload = test_state.event_emitter.pop_until(&Event::SplitAdded);
-        assert_eq!(
-            payload.get("focusedRegion").unwrap(),
-            &serde_json::to_value(&focused_region).unwrap()
-        );
-        assert_eq!(payload.get("id").unwrap().as_str().unwrap(), split_id.to_string());
+        let num_padded_rows = count_rows();
+        assert!(num_padded_rows > num_rows);
+        let payload = test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+        assert_eq!(payload["alignments"]["rows"].as_array().unwrap().len(), num_padded_rows);
+        // Readers for new splits use the track's padding
+        let split_id = test_state.grid.add_split(&test_state.event_emitter, None).unwrap();
+        let stack_reader = test_state.grid.get_stack_reader(&split_id, &test_state.track_id);
+        let stack = stack_reader.unwrap().serialize_stack().unwrap();
+        assert_eq!(stack["rows"].as_array().unwrap().len(), num_padded_rows);
+
+        // Tight packing never needs more rows than greedy packing
+        let customization =
+            TrackCustomization { packing: PackingStrategy::Tight, ..Default::default() };
+        test_state
+            .grid
+            .update_track_customization(
+                &test_state.event_emitter,
+                &test_state.track_id,
+                customization,
+            )
+            .unwrap();
+        assert!(count_rows() <= num_rows);
     }
 
     #[test]
-    fn test_get_split() {
+    fn test_update_track_max_rows() {
         let test_state = init_basic_split_grid();
-        let split = test_state.grid.get_split(&test_state.split_id).unwrap();
-        assert_eq!(split.read().id, test_state.split_id);
+        let grid = &test_state.grid;
+        let track_id = grid
+            .add_track(&test_state.event_emitter, get_test_data_path("fake-genome.reads.bam"))
+            .unwrap();
+        let stack_reader = grid.get_stack_reader(&test_state.split_id, &track_id).unwrap();
+        let num_rows = stack_reader.serialize_stack().unwrap()["rows"].as_array().unwrap().len();
+        assert!(num_rows > 1);
+        let customization = TrackCustomization { max_rows: Some(1), ..Default::default() };
+        grid.update_track_customization(&test_state.event_emitter, &track_id, customization)
+            .unwrap();
+        let payload = test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+        assert_eq!(payload["alignments"]["rows"].as_array().unwrap().len(), 1);
+        assert_eq!(payload["alignments"]["hiddenRowCount"], num_rows - 1);
+        let hidden_read_count = payload["alignments"]["hiddenReadCount"].as_u64().unwrap();
+        assert!(hidden_read_count >= num_rows as u64 - 1);
     }
 
     #[test]
-    fn test_get_stack_reader() {
+    fn test_update_track_grouping() {
         let test_state = init_basic_split_grid();
-        let stack_reader =
-            test_state.grid.get_stack_reader(&test_state.split_id, &test_state.track_id).unwrap();
-        assert_eq!(stack_reader.read().path, test_state.bam_path);
+        let grid = &test_state.grid;
+        let num_rows = {
+            let stack_reader = grid.get_stack_reader(&test_state.split_id, &test_state.track_id);
+            let stack = stack_reader.unwrap().serialize_stack().unwrap();
+            assert_eq!(stack["groups"], serde_json::json!([]));
+            stack["rows"].as_array().unwrap().len()
+        };
+        let customization =
+            TrackCustomization { grouping: StackGrouping::Haplotype, ..Default::default() };
+        grid.update_track_customization(
+            &test_state.event_emitter,
+            &test_state.track_id,
+            customization,
+        )
+        .unwrap();
+        // None of the test reads are phased, so they're all in the untagged group
+        let payload = test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+        let expected_groups = serde_json::json!([{"key": null, "startRow": 0, "endRow": num_rows}]);
+        assert_eq!(payload["alignments"]["groups"], expected_groups);
+        // Readers for new splits use the track's grouping
+        let split_id = grid.add_split(&test_state.event_emitter, None).unwrap();
+        let stack_reader = grid.get_stack_reader(&split_id, &test_state.track_id);
+        let stack = stack_reader.unwrap().serialize_stack().unwrap();
+        assert_eq!(stack["groups"].as_array().unwrap().len(), 1);
     }
 
     #[test]
-    fn test_add_track() {
+    fn test_add_track_marks_splits_busy() {
         let test_state = init_basic_split_grid();
-        assert_eq!(test_state.grid.tracks.len(), 1);
-        let track_id = test_state
+        test_state.event_emitter.pop_until(&Event::CoverageUpdated);
+        test_state.grid.add_track(&test_state.event_emitter, test_state.bam_path.clone()).unwrap();
+        let payload = test_state.event_emitter.pop_event(&Event::SplitBusy);
+        assert_eq!(payload.get("operation").unwrap().as_str().unwrap(), "loadingTrack");
+        let payload = test_state.event_emitter.pop_event(&Event::SplitIdle);
+        assert_eq!(
+            payload.get("splitId").unwrap().as_str().unwrap(),
+            test_state.split_id.to_string()
+        );
+        assert!(!test_state.grid.is_busy(&test_state.split_id));
+    }
+
+    #[test]
+    fn test_update_focused_region_on_busy_split_returns_error() {
+        let test_state = init_basic_split_grid();
+        test_state
             .grid
-            .add_track(&test_state.event_emitter, test_state.bam_path.clone())
+            .mark_busy(
+                &test_state.event_emitter,
+                &test_state.split_id,
+                SplitOperation::LoadingTrack,
+            )
             .unwrap();
-        assert_eq!(test_state.grid.tracks.len(), 2);
-        let payload = test_state.event_emitter.pop_event(&Event::TrackAdded);
-        assert_eq!(
-            payload.get("filePath").unwrap().as_str().unwrap(),
-            test_state.bam_path.to_str().unwrap()
+        let result = test_state.grid.update_focused_region(
+            &test_state.event_emitter,
+            &test_state.split_id,
+            GenomicRegion::new("euk_genes", 0, 1000).unwrap(),
         );
-        assert_eq!(track_id, test_state.grid.focus.read().track_id.unwrap());
-        assert_eq!(payload.get("id").unwrap().as_str().unwrap(), test_state.track_id.to_string());
+        let error = result.unwrap_err().downcast::<SplitBusyError>().unwrap();
+        assert_eq!(error.split_id, test_state.split_id);
+        assert_eq!(error.operation, SplitOperation::LoadingTrack);
+        test_state.grid.mark_idle(&test_state.event_emitter, &test_state.split_id).unwrap();
+        assert!(!test_state.grid.is_busy(&test_state.split_id));
     }
 
     #[test]
@@ -500,7 +3072,8 @@ mod tests {
             .unwrap();
         let split = test_state.grid.get_split(&test_state.split_id).unwrap();
         assert_eq!(split.read().focused_region, new_focused_region);
-        test_state.event_emitter.pop_event(&Event::TrackAdded);
+        test_state.event_emitter.pop_until(&Event::TrackAdded);
+        test_state.event_emitter.pop_event(&Event::SplitBusy);
 
         let payload = test_state.event_emitter.pop_event(&Event::FocusedRegionUpdated);
         assert_eq!(
@@ -534,6 +3107,73 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tuned_render_window_hides_dense_tracks() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let track_id = grid
+            .add_track(&test_state.event_emitter, get_test_data_path("fake-genome.reads.bam"))
+            .unwrap();
+        let stack_reader = grid.get_stack_reader(&test_state.split_id, &track_id).unwrap();
+        let count_alignments = || stack_reader.stack().read().count_alignments();
+        let update_focused_region = |start, end| {
+            let region = GenomicRegion::new("euk_genes", start, end).unwrap();
+            grid.update_focused_region(&test_state.event_emitter, &test_state.split_id, region)
+                .unwrap();
+        };
+        assert_eq!(grid.get_track_render_window(&track_id), None);
+        grid.set_target_alignments_per_window(Some(50));
+        assert_eq!(grid.get_track_render_window(&track_id), Some(test_state.max_render_window));
+
+        update_focused_region(0, 100);
+        assert!(count_alignments() > 0);
+        assert_eq!(grid.get_track_render_window(&track_id), Some(MIN_TUNED_RENDER_WINDOW));
+
+        // The focused region is within the split's max render window but not the track's
+        test_state.event_emitter.calls.lock().clear();
+        update_focused_region(0, 1000);
+        assert_eq!(count_alignments(), 0);
+        let payload = loop {
+            let payload = test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+            if payload.get("trackId").unwrap().as_str().unwrap() == track_id.to_string() {
+                break payload;
+            }
+        };
+        let render_window = MIN_TUNED_RENDER_WINDOW.to_string();
+        assert_eq!(payload.get("renderWindow").unwrap().as_str().unwrap(), render_window);
+        // Sampled coverage is sent in place of the hidden alignments
+        let binned_coverage = payload.get("binnedCoverage").unwrap();
+        assert_eq!(binned_coverage["region"]["seqName"], "euk_genes");
+        assert!(!binned_coverage["depths"].as_array().unwrap().is_empty());
+
+        update_focused_region(0, 100);
+        assert!(count_alignments() > 0);
+        assert_eq!(stack_reader.binned_coverage(), None);
+
+        grid.set_target_alignments_per_window(None);
+        update_focused_region(0, 1000);
+        assert!(count_alignments() > 0);
+    }
+
+    #[test]
+    fn test_update_focused_region_echoes_request_id() {
+        let test_state = init_basic_split_grid();
+        let event_emitter = StubEventEmitter::new().with_request_id(Some("req-1".to_owned()));
+        let new_focused_region = GenomicRegion::new("euk_genes", 500, 600).unwrap();
+        test_state
+            .grid
+            .update_focused_region(&event_emitter, &test_state.split_id, new_focused_region)
+            .unwrap();
+        let calls = event_emitter.calls.lock();
+        assert!(calls.iter().any(|(event, _)| matches!(event, Event::FocusedRegionUpdated)));
+        for (_, payload) in calls.iter() {
+            assert_eq!(payload.get("requestId").unwrap(), "req-1");
+        }
+        // Events caused by other commands don't include the request id
+        let calls = test_state.event_emitter.calls.lock();
+        assert!(calls.iter().all(|(_, payload)| payload.get("requestId").is_none()));
+    }
+
     #[test]
     fn test_update_focused_region_outside_of_buffered_region() {
         let test_state = init_basic_split_grid();
@@ -575,6 +3215,169 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cancelled_focused_region_load_doesnt_emit_alignments() {
+        let test_state = init_basic_split_grid();
+        let (grid, split_id) = (&test_state.grid, &test_state.split_id);
+        let region = GenomicRegion::new("euk_genes", 0, 100).unwrap();
+        grid.update_focused_region(&test_state.event_emitter, split_id, region).unwrap();
+        test_state.event_emitter.calls.lock().clear();
+
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let region = GenomicRegion::new("euk_genes", 500, 600).unwrap();
+        grid.load_focused_region(
+            &test_state.event_emitter,
+            split_id,
+            region.clone(),
+            &cancellation,
+        )
+        .unwrap();
+        let calls = test_state.event_emitter.calls.lock();
+        assert!(calls.iter().any(|(event, _)| matches!(event, Event::FocusedRegionUpdated)));
+        assert!(!calls.iter().any(|(event, _)| matches!(event, Event::AlignmentsUpdated)));
+        // The region is read again by the next update even though it's within the buffered region
+        let split = grid.get_split(split_id).unwrap();
+        assert!(matches!(split.read().check_bounds(&region), BoundState::OutsideBuffered));
+        drop(split);
+        drop(calls);
+
+        // Requesting the same region again reloads its alignments
+        grid.update_focused_region(&test_state.event_emitter, split_id, region.clone()).unwrap();
+        test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+        let split = grid.get_split(split_id).unwrap();
+        assert!(!split.read().is_buffer_stale());
+    }
+
+    #[test]
+    fn test_spawn_update_focused_region_emits_loaded_event() {
+        let test_state = init_basic_split_grid();
+        let (split_id, track_id) = (test_state.split_id, test_state.track_id);
+        let grid = &test_state.grid;
+        let event_emitter = Arc::new(StubEventEmitter::new());
+        let region = GenomicRegion::new("euk_genes", 0, 100).unwrap();
+        grid.update_focused_region(&event_emitter, &split_id, region).unwrap();
+        event_emitter.calls.lock().clear();
+        let region = GenomicRegion::new("euk_genes", 500, 600).unwrap();
+        grid.spawn_update_focused_region(Arc::clone(&event_emitter), split_id, region.clone())
+            .unwrap();
+
+        event_emitter.wait_for(&Event::FocusedRegionLoaded);
+        let payload = event_emitter.pop_until(&Event::AlignmentsUpdated);
+        assert_eq!(payload.get("trackId").unwrap().as_str().unwrap(), track_id.to_string());
+        let payload = event_emitter.pop_until(&Event::FocusedRegionLoaded);
+        assert_eq!(payload.get("error"), Some(&serde_json::Value::Null));
+        assert_eq!(grid.get_split(&split_id).unwrap().read().focused_region, region);
+    }
+
+    #[test]
+    fn test_update_focused_region_emits_appended_alignments() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let track_id = grid
+            .add_track(&test_state.event_emitter, get_test_data_path("fake-genome.reads.bam"))
+            .unwrap();
+        let update_region = |start: u64, end: u64| {
+            let region = GenomicRegion::new("mt", start, end).unwrap();
+            grid.update_focused_region(&test_state.event_emitter, &test_state.split_id, region)
+                .unwrap();
+        };
+        update_region(1000, 1100);
+        let stack_reader = grid.get_stack_reader(&test_state.split_id, &track_id).unwrap();
+        let previous_revision = stack_reader.serialize_stack().unwrap()["revision"].clone();
+        // Outside of the buffered region, but the new buffered region overlaps the old one
+        update_region(1150, 1250);
+
+        let payload = loop {
+            let payload = test_state.event_emitter.pop_until(&Event::AlignmentsAppended);
+            if payload["trackId"].as_str().unwrap() == track_id.to_string() {
+                break payload;
+            }
+        };
+        let delta = &payload["delta"];
+        let stack = stack_reader.serialize_stack().unwrap();
+        assert_eq!(delta["previousRevision"], previous_revision);
+        assert_eq!(delta["revision"], stack["revision"]);
+        assert_eq!(delta["numRows"], stack["rows"].as_array().unwrap().len());
+        let num_changes = delta["added"].as_array().unwrap().len()
+            + delta["removedIds"].as_array().unwrap().len();
+        assert!(num_changes > 0);
+        let num_alignments: usize =
+            stack["rows"].as_array().unwrap().iter().map(|row| row.as_array().unwrap().len()).sum();
+        assert!(num_changes < num_alignments);
+    }
+
+    #[test]
+    fn test_update_focused_region_fetches_high_priority_tracks_first() {
+        let test_state = init_basic_split_grid();
+        let bam_path = get_test_data_path("fake-genome.reads.bam");
+        let high_priority_track_id =
+            test_state.grid.add_track(&test_state.event_emitter, bam_path).unwrap();
+        let customization =
+            TrackCustomization { priority: TrackPriority::High, ..Default::default() };
+        test_state
+            .grid
+            .update_track_customization(
+                &test_state.event_emitter,
+                &high_priority_track_id,
+                customization,
+            )
+            .unwrap();
+        test_state
+            .grid
+            .update_focused_region(
+                &test_state.event_emitter,
+                &test_state.split_id,
+                GenomicRegion::new("mt", 1000, 1100).unwrap(),
+            )
+            .unwrap();
+
+        let payload = test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+        assert_eq!(
+            payload.get("trackId").unwrap().as_str().unwrap(),
+            high_priority_track_id.to_string()
+        );
+        let payload = test_state.event_emitter.pop_until(&Event::AlignmentsUpdated);
+        assert_eq!(
+            payload.get("trackId").unwrap().as_str().unwrap(),
+            test_state.track_id.to_string()
+        );
+    }
+
+    #[test]
+    fn test_add_track_builds_missing_bam_index() {
+        let test_state = init_basic_split_grid();
+        let bam_path = std::env::temp_dir().join(format!("{}.bam", uuid::Uuid::new_v4()));
+        std::fs::copy(get_test_data_path("fake-genome.reads.bam"), &bam_path).unwrap();
+        test_state.grid.add_track(&test_state.event_emitter, &bam_path).unwrap();
+        let payload = test_state.event_emitter.pop_until(&Event::BamIndexProgress);
+        assert_eq!(payload.get("status").unwrap(), "started");
+        let payload = test_state.event_emitter.pop_until(&Event::BamIndexProgress);
+        assert_eq!(payload.get("status").unwrap(), "finished");
+        let index_path = find_bam_index(&bam_path).unwrap();
+        std::fs::remove_file(&bam_path).unwrap();
+        std::fs::remove_file(&index_path).unwrap();
+    }
+
+    #[test]
+    fn test_add_split_reuses_focused_split_buffers() {
+        let test_state = init_basic_split_grid();
+        let new_split_id = test_state.grid.add_split(&test_state.event_emitter, None).unwrap();
+        let split = test_state.grid.get_split(&test_state.split_id).unwrap();
+        let new_split = test_state.grid.get_split(&new_split_id).unwrap();
+        assert_eq!(new_split.read().focused_region, split.read().focused_region);
+        assert!(Arc::ptr_eq(
+            new_split.read().buffered_sequence.as_ref().unwrap(),
+            split.read().buffered_sequence.as_ref().unwrap()
+        ));
+        let stack = test_state.grid.get_stack_reader(&test_state.split_id, &test_state.track_id);
+        let new_stack = test_state.grid.get_stack_reader(&new_split_id, &test_state.track_id);
+        assert_eq!(
+            new_stack.unwrap().serialize_stack().unwrap().get("rows"),
+            stack.unwrap().serialize_stack().unwrap().get("rows")
+        );
+    }
+
     #[test]
     fn test_update_focused_region_doesnt_affect_other_splits() {
         let test_state = init_basic_split_grid();
@@ -606,6 +3409,53 @@ mod tests {
             &serde_json::to_value(&new_focused_region).unwrap()
         );
     }
+    #[test]
+    fn test_update_grid_focus() {
+        let test_state = init_basic_split_grid();
+        let grid_coord = GridCoord {
+            track_id: Some(test_state.track_id),
+            split_id: test_state.split_id,
+            target: FocusTarget::Track,
+        };
+        let event_emitter = StubEventEmitter::new();
+        test_state.grid.update_grid_focus(&event_emitter, grid_coord.clone()).unwrap();
+        assert_eq!(*test_state.grid.focus.read(), grid_coord);
+        let payload = event_emitter.pop_event(&Event::GridFocusUpdated);
+        assert_eq!(payload.get("target").unwrap().as_str().unwrap(), "track");
+    }
+
+    #[test]
+    fn test_update_grid_focus_requires_track_for_cell() {
+        let test_state = init_basic_split_grid();
+        let grid_coord =
+            GridCoord { track_id: None, split_id: test_state.split_id, target: FocusTarget::Cell };
+        assert!(test_state.grid.update_grid_focus(&test_state.event_emitter, grid_coord).is_err());
+        let grid_coord = GridCoord {
+            track_id: Some(TrackId::new()),
+            split_id: test_state.split_id,
+            target: FocusTarget::Cell,
+        };
+        assert!(test_state.grid.update_grid_focus(&test_state.event_emitter, grid_coord).is_err());
+    }
+
+    #[test]
+    fn test_focus_adjacent_split() {
+        let test_state = init_basic_split_grid();
+        let split_id = test_state.grid.add_split(&test_state.event_emitter, None).unwrap();
+        assert_eq!(test_state.grid.get_split_ids(), vec![test_state.split_id, split_id]);
+        assert_eq!(test_state.grid.focus.read().split_id, split_id);
+        let event_emitter = StubEventEmitter::new();
+        test_state.grid.focus_adjacent_split(&event_emitter, &Direction::Right).unwrap();
+        assert_eq!(test_state.grid.focus.read().split_id, test_state.split_id);
+        let payload = event_emitter.pop_until(&Event::FocusedSplitUpdated);
+        assert_eq!(
+            payload.get("splitId").unwrap().as_str().unwrap(),
+            test_state.split_id.to_string()
+        );
+        test_state.grid.focus_adjacent_split(&event_emitter, &Direction::Left).unwrap();
+        assert_eq!(test_state.grid.focus.read().split_id, split_id);
+    }
+
     #[test]
     fn test_pan_focused_region() {
         let test_state = init_basic_split_grid();
@@ -629,4 +3479,75 @@ mod tests {
             &serde_json::to_value(&new_focused_region).unwrap()
         );
     }
+
+    fn pan_from(test_state: &GridTestState, start_region: GenomicRegion, contig_rollover: bool) {
+        test_state
+            .grid
+            .update_focused_region(&test_state.event_emitter, &test_state.split_id, start_region)
+            .unwrap();
+        test_state.event_emitter.calls.lock().clear();
+        let event_emitter = Arc::clone(&test_state.event_emitter);
+        test_state
+            .grid
+            .pan_focused_split(event_emitter, &Direction::Right, contig_rollover)
+            .unwrap();
+        test_state.event_emitter.wait_for(&Event::FocusedRegionLoaded);
+    }
+
+    #[test]
+    fn test_pan_focused_split_doesnt_wait_for_load() {
+        let test_state = init_basic_split_grid();
+        let grid = &test_state.grid;
+        let start_region = GenomicRegion::new("euk_genes", 1000, 2000).unwrap();
+        grid.update_focused_region(&test_state.event_emitter, &test_state.split_id, start_region)
+            .unwrap();
+        test_state.event_emitter.calls.lock().clear();
+        // Every load thread is kept busy so that the pan's load can't start until they're released
+        let gate = Arc::new(RwLock::new(()));
+        let gate_lock = gate.write();
+        for _ in 0..REGION_LOAD_THREADS {
+            let gate = Arc::clone(&gate);
+            grid.load_pool.spawn(move || drop(gate.read()));
+        }
+        let event_emitter = Arc::clone(&test_state.event_emitter);
+        grid.pan_focused_split(event_emitter, &Direction::Right, false).unwrap();
+        let focused_region =
+            || grid.get_split(&test_state.split_id).unwrap().read().focused_region.clone();
+        assert_eq!(focused_region(), GenomicRegion::new("euk_genes", 1000, 2000).unwrap());
+        assert!(test_state.event_emitter.calls.lock().is_empty());
+
+        drop(gate_lock);
+        test_state.event_emitter.wait_for(&Event::FocusedRegionLoaded);
+        assert_eq!(focused_region(), GenomicRegion::new("euk_genes", 1100, 2100).unwrap());
+        let payload = test_state.event_emitter.pop_until(&Event::FocusedRegionLoaded);
+        assert_eq!(payload.get("error"), Some(&serde_json::Value::Null));
+    }
+
+    #[test]
+    fn test_pan_focused_split_clamps_to_contig_end() {
+        let test_state = init_basic_split_grid();
+        pan_from(&test_state, GenomicRegion::new("euk_genes", 6100, 7100).unwrap(), false);
+        let split = test_state.grid.get_split(&test_state.split_id).unwrap();
+        assert_eq!(
+            split.read().focused_region,
+            GenomicRegion::new("euk_genes", 6185, 7185).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_pan_focused_split_rolls_over_to_next_contig() {
+        let test_state = init_basic_split_grid();
+        pan_from(&test_state, GenomicRegion::new("euk_genes", 6100, 7100).unwrap(), true);
+        let split = test_state.grid.get_split(&test_state.split_id).unwrap();
+        assert_eq!(split.read().focused_region, GenomicRegion::new("mt", 0, 1000).unwrap());
+        drop(split);
+        let payload = test_state.event_emitter.pop_until(&Event::ContigChanged);
+        assert_eq!(payload.get("previousSeqName").unwrap(), "euk_genes");
+        assert_eq!(payload.get("seqName").unwrap(), "mt");
+
+        // There's no contig after the last one so the pan is clamped
+        pan_from(&test_state, GenomicRegion::new("mt", 15520, 16520).unwrap(), true);
+        let split = test_state.grid.get_split(&test_state.split_id).unwrap();
+        assert_eq!(split.read().focused_region, GenomicRegion::new("mt", 15569, 16569).unwrap());
+    }
 }
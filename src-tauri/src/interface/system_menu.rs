@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use anyhow::Result;
 use tauri::api::dialog::FileDialogBuilder;
 use tauri::{AppHandle, CustomMenuItem, Manager, Menu, MenuItem, Submenu};
@@ -19,10 +21,10 @@ pub fn setup_system_menu() -> Result<Menu> {
 pub fn open_files(app: AppHandle) {
     FileDialogBuilder::new().pick_files(move |file_paths| {
         if let Some(file_paths) = file_paths {
-            let event_emitter = EventEmitter::new(&app);
+            let event_emitter = Arc::new(EventEmitter::new(&app));
             let state: tauri::State<Backend> = app.state();
             for file_path in file_paths {
-                let result = state.split_grid.read().add_track(&event_emitter, file_path.clone());
+                let result = state.add_track(Arc::clone(&event_emitter), file_path.clone());
                 if result.is_err() {
                     log::error!("Failed to add track from file: {}", file_path.to_string_lossy());
                 }
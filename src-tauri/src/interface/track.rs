@@ -1,10 +1,18 @@
 use std::ffi::OsStr;
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::alignments::stack::{
+    PackingStrategy, StackGrouping, DEFAULT_STACK_PADDING, MAX_STACK_PADDING,
+};
+use crate::file_formats::enums::{get_file_kind, FileKind};
+use crate::file_formats::sam_bam::pileup::CoverageStrand;
+use crate::file_formats::sam_bam::reader::ReadFilter;
+use crate::file_formats::sam_bam::samples::get_sample_names;
+use crate::file_formats::tabix::record::IntervalColumns;
 use crate::impl_wrapped_uuid;
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
@@ -30,11 +38,142 @@ impl Track {
         }
     }
 
+    pub fn set_name(&mut self, new_name: String) {
+        match self {
+            Self::Alignment(AlignmentTrack { name, .. }) => *name = new_name,
+        }
+    }
+
     pub fn file_path(&self) -> &PathBuf {
         match self {
             Self::Alignment(AlignmentTrack { file_path, .. }) => file_path,
         }
     }
+
+    pub fn interval_columns(&self) -> Option<&IntervalColumns> {
+        match self {
+            Self::Alignment(AlignmentTrack { interval_columns, .. }) => interval_columns.as_ref(),
+        }
+    }
+
+    pub fn customization(&self) -> &TrackCustomization {
+        match self {
+            Self::Alignment(AlignmentTrack { customization, .. }) => customization,
+        }
+    }
+
+    pub fn filter(&self) -> ReadFilter {
+        match self {
+            Self::Alignment(AlignmentTrack { filter, .. }) => filter.clone(),
+        }
+    }
+
+    pub fn set_filter(&mut self, new_filter: ReadFilter) {
+        match self {
+            Self::Alignment(AlignmentTrack { filter, .. }) => *filter = new_filter,
+        }
+    }
+
+    pub fn set_customization(&mut self, new_customization: TrackCustomization) {
+        match self {
+            Self::Alignment(AlignmentTrack { customization, .. }) => {
+                *customization = new_customization
+            }
+        }
+    }
+}
+
+/// Priority class which determines the order in which tracks are fetched on region changes.
+///
+/// Higher priority tracks (e.g a proband) are read and sent to the frontend before lower priority
+/// tracks (e.g controls) so that they appear first on slow storage.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum TrackPriority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+/// User-settable display options for a track.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackCustomization {
+    /// Hex color code (e.g #1f77b4) used when drawing the track.
+    pub color: Option<String>,
+
+    /// Label to display in place of the track name.
+    pub label: Option<String>,
+
+    pub description: Option<String>,
+
+    #[serde(default)]
+    pub priority: TrackPriority,
+
+    /// Minimum gap in bp between adjacent alignments in a row (DEFAULT_STACK_PADDING if unset).
+    #[serde(default)]
+    pub stack_padding: Option<u64>,
+
+    /// How the track's alignments are packed into rows.
+    #[serde(default)]
+    pub packing: PackingStrategy,
+
+    /// Field by which the track's alignments are grouped into separate sections of rows.
+    #[serde(default)]
+    pub grouping: StackGrouping,
+
+    /// Maximum read depth, above which reads are randomly downsampled (SAM/BAM files only).
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+
+    /// Maximum number of rows in the track's stacks, beyond which rows are hidden.
+    #[serde(default)]
+    pub max_rows: Option<u32>,
+
+    /// Include base qualities with the track's reads so that low quality bases can be dimmed
+    /// (SAM/BAM files only). Qualities are omitted for regions over MAX_BASE_QUALITY_WINDOW.
+    #[serde(default)]
+    pub base_qualities: bool,
+
+    /// How the track's read depth is split by strand (SAM/BAM files only).
+    #[serde(default)]
+    pub coverage_strand: CoverageStrand,
+}
+
+impl TrackCustomization {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(color) = &self.color {
+            let is_hex_color = color.len() == 7
+                && color.starts_with('#')
+                && color.chars().skip(1).all(|c| c.is_ascii_hexdigit());
+            if !is_hex_color {
+                bail!("Invalid track color: {} (expected a hex color code e.g #1f77b4)", color);
+            }
+        }
+        if let Some(padding) = self.stack_padding {
+            if padding > MAX_STACK_PADDING {
+                bail!(
+                    "Invalid stack padding: {} (must be at most {}bp)",
+                    padding,
+                    MAX_STACK_PADDING
+                );
+            }
+        }
+        if self.max_depth == Some(0) {
+            bail!("Invalid max depth: 0 (must be at least 1)");
+        }
+        if self.max_rows == Some(0) {
+            bail!("Invalid max rows: 0 (must be at least 1)");
+        }
+        Ok(())
+    }
+
+    pub fn stack_padding(&self) -> u64 {
+        self.stack_padding.unwrap_or(DEFAULT_STACK_PADDING)
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -43,13 +182,105 @@ pub struct AlignmentTrack {
     pub id: TrackId,
     pub file_path: PathBuf,
     pub name: String,
+
+    /// Sample names from the file's read groups (multiple for multi-sample files).
+    pub sample_names: Vec<String>,
+
+    /// Column mapping for generic tabix-indexed interval files (BED-style columns if unset).
+    pub interval_columns: Option<IntervalColumns>,
+
+    #[serde(flatten)]
+    pub customization: TrackCustomization,
+
+    /// Reads which are excluded from the track (SAM/BAM files only).
+    pub filter: ReadFilter,
 }
 
 impl AlignmentTrack {
+    /// The track name defaults to the sample name if the file contains a single sample, otherwise
+    /// the filename is used.
     pub fn new<P: Into<PathBuf>>(file_path: P) -> Result<Self> {
         let file_path: PathBuf = file_path.into();
-        let name =
-            file_path.file_name().unwrap_or(OsStr::new("unknown")).to_string_lossy().to_string();
-        Ok(Self { id: TrackId::new(), file_path, name })
+        let sample_names = match get_file_kind(&file_path)? {
+            FileKind::Bam | FileKind::Sam => get_sample_names(&file_path)?,
+            _ => Vec::new(),
+        };
+        let name = match sample_names.as_slice() {
+            [sample_name] => sample_name.clone(),
+            _ => {
+                file_path.file_name().unwrap_or(OsStr::new("unknown")).to_string_lossy().to_string()
+            }
+        };
+        Ok(Self {
+            id: TrackId::new(),
+            file_path,
+            name,
+            sample_names,
+            interval_columns: None,
+            customization: TrackCustomization::default(),
+            filter: ReadFilter::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::get_test_data_path;
+
+    #[test]
+    pub fn test_init_paf_alignment_track() {
+        let track = AlignmentTrack::new(get_test_data_path("fake-genome.paf")).unwrap();
+        assert_eq!(track.name, "fake-genome.paf");
+        assert!(track.sample_names.is_empty());
+    }
+
+    #[test]
+    pub fn test_init_alignment_track_with_sample_name() {
+        let sam_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir(&sam_dir).unwrap();
+        let write_sam = |filename: &str, read_groups: &str| {
+            let sam_path = sam_dir.join(filename);
+            let header = format!("@HD\tVN:1.6\n@SQ\tSN:mt\tLN:16569\n{}", read_groups);
+            std::fs::write(&sam_path, header).unwrap();
+            sam_path
+        };
+        let track =
+            AlignmentTrack::new(write_sam("with-sample.sam", "@RG\tID:rg1\tSM:tumor\n")).unwrap();
+        assert_eq!(track.name, "tumor");
+        assert_eq!(track.sample_names, vec!["tumor".to_owned()]);
+        let track = AlignmentTrack::new(write_sam("no-sample.sam", "@RG\tID:rg1\n")).unwrap();
+        assert_eq!(track.name, "no-sample.sam");
+        assert!(track.sample_names.is_empty());
+        let track = AlignmentTrack::new(get_test_data_path("fake-genome.reads.bam")).unwrap();
+        assert_eq!(track.name, "fake-genome.reads.bam");
+        std::fs::remove_dir_all(&sam_dir).unwrap();
+    }
+
+    #[test]
+    pub fn test_validate_track_customization() {
+        let mut customization = TrackCustomization::default();
+        assert!(customization.validate().is_ok());
+        customization.color = Some("#1f77B4".to_owned());
+        assert!(customization.validate().is_ok());
+        customization.color = Some("blue".to_owned());
+        assert!(customization.validate().is_err());
+        customization.color = Some("#1f77b".to_owned());
+        assert!(customization.validate().is_err());
+        customization.color = None;
+        customization.stack_padding = Some(0);
+        assert!(customization.validate().is_ok());
+        customization.stack_padding = Some(MAX_STACK_PADDING + 1);
+        assert!(customization.validate().is_err());
+        customization.stack_padding = None;
+        customization.max_depth = Some(1);
+        assert!(customization.validate().is_ok());
+        customization.max_depth = Some(0);
+        assert!(customization.validate().is_err());
+        customization.max_depth = None;
+        customization.max_rows = Some(1);
+        assert!(customization.validate().is_ok());
+        customization.max_rows = Some(0);
+        assert!(customization.validate().is_err());
     }
 }
@@ -1,6 +1,14 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::file_formats::sam_bam::decompression_pool::DEFAULT_DECOMPRESSION_THREADS;
+use crate::file_formats::sam_bam::diff::DEFAULT_MIN_MISMATCH_BASE_QUALITY;
+use crate::file_formats::sam_bam::pileup::DEFAULT_MISMATCH_HIGHLIGHT_THRESHOLD;
+use crate::interface::payload_encoding::PayloadEncoding;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct NucleotideColorConfig {
@@ -56,6 +64,125 @@ pub struct StyleConfig {
 pub struct GeneralConfig {
     /// Maximum length genomic region for which individual alignments are rendered in the GUI.
     pub max_render_window: u64,
+    /// Load the entire reference sequence into memory when it's opened. Only useful for small
+    /// genomes (e.g bacteria or viruses).
+    pub preload_reference: bool,
+    /// Pan onto the adjacent contig when panning past the end of a contig (rather than stopping at
+    /// the end of the contig).
+    pub contig_rollover: bool,
+    /// If set, the render window of each track is tuned to its read density so that roughly this
+    /// many alignments are rendered (up to max_render_window).
+    pub target_alignments_per_window: Option<u64>,
+    /// Number of threads in the pool which every BAM file is decompressed with. Zero disables
+    /// multi-threaded decompression.
+    pub decompression_threads: usize,
+    /// Positions where more than this fraction of reads have a mismatched base are highlighted
+    /// above the alignments.
+    pub mismatch_highlight_threshold: f64,
+    /// Mismatches with a lower base quality than this are hidden (e.g to reduce the noise of
+    /// nanopore reads). Zero shows every mismatch.
+    pub min_mismatch_base_quality: u8,
+    /// Encoding of the alignments sent to the frontend. MessagePack is faster to produce and parse
+    /// than JSON for large stacks.
+    pub payload_encoding: PayloadEncoding,
+    /// Alignment payloads larger than this many bytes are gzip compressed before they're sent to
+    /// the frontend. Compression is disabled if this is unset.
+    #[serde(default)]
+    pub payload_compression_threshold: Option<usize>,
+    /// Send genomic coordinates to the frontend as JSON numbers rather than strings, which avoids
+    /// parsing them in the frontend. Numbers are exact for coordinates up to 2^53.
+    #[serde(default)]
+    pub numeric_coordinates: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContaminantCheckConfig {
+    /// Length of the k-mers which are compared between softclips and contaminant sequences.
+    pub kmer_size: usize,
+    /// Minimum number of reads which must share a softclip breakpoint for it to be checked.
+    pub min_cluster_reads: usize,
+    /// FASTA file of contaminant sequences to use in place of the bundled set, which only contains
+    /// sequencing adapters and vector primer sites (e.g to also screen for PhiX or whole vectors).
+    pub contaminants_path: Option<PathBuf>,
+}
+
+/// Alert when the depth of the alignments on screen falls below a threshold.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageRuleConfig {
+    /// Name which is displayed alongside alerts triggered by the rule.
+    pub name: String,
+    /// Positions with fewer reads than this trigger an alert.
+    pub min_depth: u32,
+    /// BED file of target regions to check. All positions are checked if this is omitted.
+    pub targets_path: Option<PathBuf>,
+}
+
+/// Access to files in S3 (s3:// paths). Omitted settings fall back to the standard AWS environment
+/// variables and config files.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct S3Config {
+    /// Named profile from the AWS config/credentials files.
+    pub profile: Option<String>,
+    /// Region of the buckets which are accessed.
+    pub region: Option<String>,
+    /// Host of an S3-compatible service to use in place of AWS (e.g a MinIO server).
+    pub endpoint: Option<String>,
+}
+
+/// Access to files in Google Cloud Storage (gs:// paths).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GcsConfig {
+    /// Command which prints an OAuth access token. Run again whenever the token is about to expire.
+    pub token_command: Vec<String>,
+    /// Project which is billed for access to requester pays buckets.
+    pub requester_pays_project: Option<String>,
+}
+
+impl Default for GcsConfig {
+    fn default() -> Self {
+        let token_command = ["gcloud", "auth", "print-access-token"];
+        Self {
+            token_command: token_command.iter().map(|arg| arg.to_string()).collect(),
+            requester_pays_project: None,
+        }
+    }
+}
+
+/// On-disk cache of the blocks of remote files which have been read.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteCacheConfig {
+    pub enabled: bool,
+    /// Directory in which blocks are cached. Defaults to a directory in the user's cache directory.
+    pub dir: Option<PathBuf>,
+    /// Maximum total size of the cached blocks in bytes. The least recently used blocks are
+    /// evicted once the cache is full.
+    pub max_size: u64,
+}
+
+impl Default for RemoteCacheConfig {
+    fn default() -> Self {
+        Self { enabled: true, dir: None, max_size: 2 * 1024 * 1024 * 1024 }
+    }
+}
+
+/// Headers which are sent with requests for http(s) files on a host, so that files behind
+/// authenticated endpoints (e.g htsget servers or presigned URL gateways) can be opened.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostAuthConfig {
+    /// Host name, optionally with a port (e.g `data.example.org:8443`).
+    pub host: String,
+    /// Sent as an `Authorization: Bearer` header. Never sent to the frontend.
+    #[serde(default, skip_serializing)]
+    pub bearer_token: Option<String>,
+    /// Other headers to send (e.g API keys). Never sent to the frontend.
+    #[serde(default, skip_serializing)]
+    pub headers: BTreeMap<String, String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -63,6 +190,16 @@ pub struct GeneralConfig {
 pub struct UserConfig {
     pub styles: StyleConfig,
     pub general: GeneralConfig,
+    pub contaminant_check: ContaminantCheckConfig,
+    pub coverage_rules: Vec<CoverageRuleConfig>,
+    pub s3: S3Config,
+    pub gcs: GcsConfig,
+    pub remote_cache: RemoteCacheConfig,
+    pub host_auth: Vec<HostAuthConfig>,
+    /// Extra contig name aliases (e.g NC_012920.1 -> chrM), used in addition to the built-in
+    /// UCSC/Ensembl aliases when matching up contig names between files.
+    #[serde(default)]
+    pub contig_aliases: BTreeMap<String, String>,
 }
 
 /// Parse a hex code string to its u32 representation
@@ -75,8 +212,35 @@ fn parse_hex(hex_string: &str) -> Result<u32> {
 /// Read the user's config file
 pub fn read_user_config() -> Result<UserConfig> {
     // TODO Read from JSON file
+    get_default_user_config()
+}
+
+/// Get the config which is used if the user hasn't changed any settings.
+pub fn get_default_user_config() -> Result<UserConfig> {
     let config = UserConfig {
-        general: GeneralConfig { max_render_window: 10000 },
+        general: GeneralConfig {
+            max_render_window: 10000,
+            preload_reference: false,
+            contig_rollover: false,
+            target_alignments_per_window: None,
+            decompression_threads: DEFAULT_DECOMPRESSION_THREADS,
+            mismatch_highlight_threshold: DEFAULT_MISMATCH_HIGHLIGHT_THRESHOLD,
+            min_mismatch_base_quality: DEFAULT_MIN_MISMATCH_BASE_QUALITY,
+            payload_encoding: PayloadEncoding::default(),
+            payload_compression_threshold: None,
+            numeric_coordinates: false,
+        },
+        contaminant_check: ContaminantCheckConfig {
+            kmer_size: 12,
+            min_cluster_reads: 3,
+            contaminants_path: None,
+        },
+        coverage_rules: Vec::new(),
+        s3: S3Config::default(),
+        gcs: GcsConfig::default(),
+        remote_cache: RemoteCacheConfig::default(),
+        host_auth: Vec::new(),
+        contig_aliases: BTreeMap::new(),
         styles: StyleConfig {
             fonts: FontConfig { tooltip_font_size: 12 },
             colors: ColorConfig {
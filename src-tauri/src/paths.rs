@@ -12,6 +12,62 @@ fn dir_contains(dir: &Path, filename: &str) -> bool {
     false
 }
 
+/// Directory in which data is cached between sessions.
+///
+/// Uses the platform's cache directory (e.g ~/.cache on Linux), falling back to the temp directory
+/// if it can't be found.
+pub fn get_cache_dir() -> PathBuf {
+    let base_dir = if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Caches"))
+    } else if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+    base_dir.unwrap_or_else(env::temp_dir).join("gensketch")
+}
+
+/// Directory in which data which should persist between sessions is stored.
+///
+/// Uses the platform's data directory (e.g ~/.local/share on Linux), falling back to the temp
+/// directory if it can't be found.
+pub fn get_data_dir() -> PathBuf {
+    let base_dir = if cfg!(target_os = "macos") {
+        env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join("Library").join("Application Support"))
+    } else if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_DATA_HOME").map(PathBuf::from).filter(|dir| dir.is_absolute()).or_else(
+            || env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")),
+        )
+    };
+    base_dir.unwrap_or_else(env::temp_dir).join("gensketch")
+}
+
+/// Default directory which reference genomes from the registry are downloaded to.
+pub fn get_genomes_dir() -> PathBuf {
+    get_data_dir().join("genomes")
+}
+
+/// File in which the most recently used reference sequence is saved between sessions.
+pub fn get_last_reference_path() -> PathBuf {
+    get_data_dir().join("last-reference.json")
+}
+
+/// Default directory for the on-disk cache of remote file blocks.
+pub fn get_remote_cache_dir() -> PathBuf {
+    get_cache_dir().join("remote")
+}
+
+/// Directory in which binned coverage summaries of SAM/BAM files are cached.
+pub fn get_coverage_cache_dir() -> PathBuf {
+    get_cache_dir().join("coverage")
+}
+
 // Slight hack
 // This function and get_test_data_path are only really needed for benchmarks and tests but we're
 // not conditionally compiling them because the benchmarks can only import public functions.
@@ -0,0 +1,47 @@
+use crate::bio_util::genomic_coordinates::GenomicRegion;
+use crate::file_formats::sam_bam::aligned_read::AlignedRead;
+use crate::file_formats::sam_bam::diff::SequenceDiff;
+
+const DEFAULT_SEQ_NAME: &str = "X";
+
+/// An unpaired forward read which matches the reference, so that tests only need to set the fields
+/// which they check.
+impl Default for AlignedRead {
+    fn default() -> Self {
+        AlignedRead {
+            id: "read".to_owned(),
+            qname: "read".to_owned(),
+            region: GenomicRegion::new(DEFAULT_SEQ_NAME, 0, 100).unwrap(),
+            mate_pos: None,
+            cigar_string: "100M".to_owned(),
+            diffs: Vec::new(),
+            is_reverse: false,
+            is_second_of_pair: false,
+            parse_error: None,
+            clipped_start: false,
+            clipped_end: false,
+            num_hidden_diffs: 0,
+            run_metadata: None,
+            haplotype: None,
+            mapq: 0,
+            template_length: 0,
+            pair_orientation: None,
+            base_qualities: None,
+            base_modifications: Vec::new(),
+            is_supplementary: false,
+            chain: None,
+        }
+    }
+}
+
+/// Build an unpaired forward read aligned to start-end of contig X.
+pub fn gen_read(id: &str, start: u64, end: u64, diffs: Vec<SequenceDiff>) -> AlignedRead {
+    AlignedRead {
+        id: id.to_owned(),
+        qname: id.to_owned(),
+        region: GenomicRegion::new(DEFAULT_SEQ_NAME, start, end).unwrap(),
+        cigar_string: format!("{}M", end - start),
+        diffs,
+        ..Default::default()
+    }
+}
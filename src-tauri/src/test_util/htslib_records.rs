@@ -1,4 +1,4 @@
-use rust_htslib::bam::record::CigarString;
+use rust_htslib::bam::record::{Aux, CigarString};
 use rust_htslib::bam::Record;
 
 const DEFAULT_POS: i64 = 1003;
@@ -41,6 +41,16 @@ impl RecordBuilder {
         self.record.set_mpos(mpos);
         self
     }
+
+    pub fn flags(mut self, flags: u16) -> Self {
+        self.record.set_flags(flags);
+        self
+    }
+
+    pub fn aux(mut self, tag: &[u8], value: Aux) -> Self {
+        self.record.push_aux(tag, value).unwrap();
+        self
+    }
 }
 
 impl Default for RecordBuilder {
@@ -1 +1,2 @@
+pub mod aligned_reads;
 pub mod htslib_records;
@@ -0,0 +1,55 @@
+import type { AlignmentStackKind, AlignmentsDelta, IntervalRecord } from "@lib/bindings";
+import { applyAlignmentsDelta } from "./alignmentsDelta";
+
+const record = (id: string, start: bigint, end: bigint): IntervalRecord => {
+  return { type: "intervalRecordKind", id, interval: { start, end }, name: null };
+};
+
+const stack: AlignmentStackKind = {
+  type: "intervalRecordKind",
+  id: "stack",
+  diffSchemaVersion: 2,
+  revision: 1,
+  rows: [[record("a", 0n, 10n), record("c", 12n, 20n)], [record("b", 5n, 15n)]],
+  groups: [],
+  strandBoundary: null,
+  hiddenRowCount: 0,
+  hiddenReadCount: 0,
+  bufferedRegion: { seqName: "X", interval: { start: 0n, end: 20n } },
+};
+
+const delta: AlignmentsDelta = {
+  stackId: "stack",
+  previousRevision: 1,
+  revision: 2,
+  numRows: 3,
+  groups: [],
+  strandBoundary: null,
+  hiddenRowCount: 0,
+  hiddenReadCount: 0,
+  bufferedRegion: { seqName: "X", interval: { start: 11n, end: 30n } },
+  removedIds: ["a", "b"],
+  added: [
+    { rowIndex: 0, index: 1, alignment: record("e", 22n, 30n) },
+    { rowIndex: 1, index: 0, alignment: record("b", 5n, 16n) },
+    { rowIndex: 2, index: 0, alignment: record("d", 16n, 25n) },
+  ],
+};
+
+test("applyAlignmentsDelta removes and inserts alignments", async () => {
+  const patched = applyAlignmentsDelta(stack, delta)!;
+  expect(patched.rows).toEqual([
+    [record("c", 12n, 20n), record("e", 22n, 30n)],
+    [record("b", 5n, 16n)],
+    [record("d", 16n, 25n)],
+  ]);
+  expect(patched.revision).toEqual(2);
+  expect(patched.bufferedRegion).toEqual(delta.bufferedRegion);
+  // The original stack is unchanged
+  expect(stack.rows[0].length).toEqual(2);
+});
+
+test("applyAlignmentsDelta rejects deltas for other revisions", async () => {
+  expect(applyAlignmentsDelta({ ...stack, revision: 3 }, delta)).toBeNull();
+  expect(applyAlignmentsDelta({ ...stack, id: "other" }, delta)).toBeNull();
+});
@@ -0,0 +1,43 @@
+/** Patching of alignment stacks with the changes which the backend sends when panning. */
+import type { AlignmentStackKind, AlignmentsDelta } from "@lib/bindings";
+
+/**
+ * Apply the changes from an alignments-appended event to a stack.
+ *
+ * Returns null if the delta doesn't apply to the stack (i.e the stack isn't at the delta's
+ * previous revision, or only contains a range of its rows), in which case the whole stack needs to
+ * be fetched again.
+ */
+export const applyAlignmentsDelta = (
+  stack: AlignmentStackKind,
+  delta: AlignmentsDelta
+): AlignmentStackKind | null => {
+  if (
+    stack.id !== delta.stackId ||
+    stack.revision !== delta.previousRevision ||
+    stack.rowStart !== undefined
+  ) {
+    return null;
+  }
+  const removedIds = new Set(delta.removedIds);
+  const rows: Array<Array<{ id: string }>> = stack.rows
+    .slice(0, delta.numRows)
+    .map((row: Array<{ id: string }>) => row.filter((alignment) => !removedIds.has(alignment.id)));
+  while (rows.length < delta.numRows) {
+    rows.push([]);
+  }
+  // Alignments are ordered within each row, so inserting them in order puts each at its index
+  for (const { rowIndex, index, alignment } of delta.added) {
+    rows[rowIndex].splice(index, 0, alignment);
+  }
+  return {
+    ...stack,
+    rows,
+    revision: delta.revision,
+    groups: delta.groups,
+    strandBoundary: delta.strandBoundary,
+    hiddenRowCount: delta.hiddenRowCount,
+    hiddenReadCount: delta.hiddenReadCount,
+    bufferedRegion: delta.bufferedRegion,
+  } as AlignmentStackKind;
+};
@@ -7,28 +7,59 @@ import { listen } from "@tauri-apps/api/event";
 import type { InvokeArgs } from "@tauri-apps/api/tauri";
 import * as logApi from "tauri-plugin-log-api";
 
+import { API_SCHEMA_VERSION } from "@lib/constants";
+import { decodeCompressedPayloadField, decodePayloadField } from "@lib/payloadEncoding";
 import type { EventListener } from "@lib/types";
 import type {
   AlertData,
+  ApiSchemaVersion,
   AlertStatusUpdateParams,
+  AlignmentFieldMask,
   AlignmentStackKind,
   AlignmentTrackData,
+  AlignmentsAppendedPayload,
   AlignmentsUpdatedPayload,
+  BinnedCoverage,
+  ContigChangedPayload,
+  Coverage,
+  CoverageUpdatedPayload,
+  DiffLabel,
+  FastqPage,
+  IntervalColumns,
+  FocusedRegionLoadedPayload,
   FocusedRegionUpdatedPayload,
   FocusedSequenceUpdatedPayload,
+  GenomeDownloadProgressPayload,
   GenomicRegion,
+  IndexProgressPayload,
+  Pileup,
+  QualityAlertPayload,
+  ReadAtPosition,
+  ReadDetails,
+  ReadFilter,
+  ReadGroupField,
+  ReadLengthHistogram,
+  ReadLengthScope,
+  ReadsDownsampledPayload,
+  ReferenceMismatchPayload,
   ReferenceSequence,
   RegionBufferingPayload,
+  RegistryGenomeStatus,
+  SignalUpdatedPayload,
   SplitData,
+  SortBy,
   SplitMap,
+  TagGroup,
   UserConfig,
+  ViewSummary,
   Direction,
   GridCoord,
 } from "@lib/bindings";
 
 /**
  * Iterates through every key/value pair in an object and converts any strings which are valid ints
- * to BigInts.
+ * to BigInts. Interval coordinates are also converted if the backend sends them as numbers (see the
+ * numericCoordinates setting).
  *
  * @param obj - The object to be updated.
  */
@@ -37,7 +68,10 @@ const convertBigInts = (obj: any): void => {
     if (!Object.prototype.hasOwnProperty.call(obj, key)) {
       continue;
     }
-    if (typeof obj[key] === "string" && !isNaN(obj[key])) {
+    if (key === "interval" && typeof obj[key]?.start === "number") {
+      obj[key].start = BigInt(obj[key].start);
+      obj[key].end = BigInt(obj[key].end);
+    } else if (typeof obj[key] === "string" && !isNaN(obj[key])) {
       obj[key] = BigInt(obj[key]);
     } else if (typeof obj[key] === "object") {
       convertBigInts(obj[key]);
@@ -62,32 +96,151 @@ const runCommand = async <T>(cmd: string, args?: InvokeArgs): Promise<T> => {
  * Listen for a tauri event and do some processing on the event before returning.
  * @param event - The event name to listen for.
  * @param handler - The handler to call when the event is received.
+ * @param decode - Decodes any encoded fields of the payload in place, before bigints are converted.
+ *   Decoding may be asynchronous (e.g for compressed payloads), in which case events are still
+ *   passed to the handler in the order they were received.
  * @returns A promise containing the unlisten function.
  */
-const tauriListen = async <T>(event: EventName, handler: EventCallback<T>): Promise<UnlistenFn> => {
+const tauriListen = async <T>(
+  event: EventName,
+  handler: EventCallback<T>,
+  decode?: (payload: T) => void | Promise<void>
+): Promise<UnlistenFn> => {
+  let pending: Promise<void> = Promise.resolve();
   const wrappedHandler = (event: Event<T>): void => {
-    convertBigInts(event);
-    return handler(event);
+    if (decode === undefined) {
+      convertBigInts(event);
+      return handler(event);
+    }
+    pending = pending
+      .then(() => decode(event.payload))
+      .then(() => {
+        convertBigInts(event);
+        handler(event);
+      })
+      .catch((err) => logApi.error(`Failed to decode ${event.event} payload: ${err}`));
   };
   return listen(event, wrappedHandler);
 };
 
-export const addAlignmentTrack = async ({ filePath }: { filePath: string }): Promise<null> => {
+export const addAlignmentTrack = async ({
+  filePath,
+  requestId,
+}: {
+  filePath: string;
+  requestId?: string;
+}): Promise<null> => {
   return runCommand<null>("add_alignment_track", {
     filePath,
+    requestId,
+  });
+};
+
+export const addIntervalTrack = async ({
+  filePath,
+  intervalColumns,
+}: {
+  filePath: string;
+  intervalColumns: IntervalColumns;
+}): Promise<null> => {
+  return runCommand<null>("add_interval_track", {
+    filePath,
+    intervalColumns,
+  });
+};
+
+export const addRemoteTrack = async ({
+  url,
+  requestId,
+}: {
+  url: string;
+  requestId?: string;
+}): Promise<null> => {
+  return runCommand<null>("add_remote_track", {
+    url,
+    requestId,
+  });
+};
+
+export const setHostAuth = async ({
+  host,
+  bearerToken,
+  headers,
+}: {
+  host: string;
+  bearerToken: string | null;
+  headers: Record<string, string>;
+}): Promise<null> => {
+  return runCommand<null>("set_host_auth", {
+    host,
+    bearerToken,
+    headers,
+  });
+};
+
+export const removeHostAuth = async ({ host }: { host: string }): Promise<null> => {
+  return runCommand<null>("remove_host_auth", {
+    host,
   });
 };
 
 export const addSplit = async ({
   focusedRegion,
+  requestId,
 }: {
   readonly focusedRegion: GenomicRegion | GenomicRegion | null;
+  requestId?: string;
 }): Promise<null> => {
   return runCommand<null>("add_split", {
     focusedRegion,
+    requestId,
   });
 };
 
+export const getSequenceChunk = async ({
+  splitId,
+  region,
+}: {
+  splitId: string;
+  region: GenomicRegion;
+}): Promise<string> => {
+  return runCommand<string>("get_sequence_chunk", { splitId, region });
+};
+
+export const getFastqReads = async ({
+  filePath,
+  offset,
+  limit,
+  alignToSplit = null,
+}: {
+  filePath: string;
+  offset: number;
+  limit: number;
+  alignToSplit?: string | null;
+}): Promise<FastqPage> => {
+  return runCommand<FastqPage>("get_fastq_reads", { filePath, offset, limit, alignToSplit });
+};
+
+export const openReference = async ({ filePath }: { filePath: string }): Promise<null> => {
+  return runCommand<null>("open_reference", { filePath });
+};
+
+export const loadReference = async ({ filePath }: { filePath: string }): Promise<null> => {
+  return runCommand<null>("load_reference", { filePath });
+};
+
+export const recoverDefaultState = async (): Promise<null> => {
+  return runCommand<null>("recover_default_state");
+};
+
+export const listRegistryGenomes = async (): Promise<RegistryGenomeStatus[]> => {
+  return runCommand<RegistryGenomeStatus[]>("list_registry_genomes");
+};
+
+export const selectRegistryGenome = async ({ genomeId }: { genomeId: string }): Promise<null> => {
+  return runCommand<null>("select_registry_genome", { genomeId });
+};
+
 export const getUserConfig = async (): Promise<UserConfig> => {
   return runCommand<UserConfig>("get_user_config");
 };
@@ -114,32 +267,314 @@ export const getSplits = async (): Promise<SplitData[]> => {
   return runCommand<SplitMap>("get_splits").then((splitMap) => Object.values(splitMap));
 };
 
+/**
+ * Fetch the alignment stack of a track/split. If a row range is given then only those rows are
+ * included (e.g the visible rows of a deep stack), and the stack has rowStart and totalRows set.
+ */
 export const getAlignments = async ({
   trackId,
   splitId,
+  fieldMask,
+  rowStart,
+  rowEnd,
 }: {
   trackId: string;
   splitId: string;
+  fieldMask?: AlignmentFieldMask;
+  rowStart?: number;
+  rowEnd?: number;
 }): Promise<AlignmentStackKind> => {
-  return runCommand<AlignmentStackKind>("get_alignments", { trackId, splitId });
+  return runCommand<AlignmentStackKind>("get_alignments", {
+    trackId,
+    splitId,
+    fieldMask,
+    rowStart,
+    rowEnd,
+  });
+};
+
+export const getDiffLabels = async ({
+  splitId,
+  trackId,
+  nucWidth,
+}: {
+  splitId: string;
+  trackId: string;
+  nucWidth: number;
+}): Promise<DiffLabel[]> => {
+  return runCommand<DiffLabel[]>("get_diff_labels", { splitId, trackId, nucWidth });
+};
+
+export const getViewSummary = async ({
+  splitId,
+  trackId,
+}: {
+  splitId: string;
+  trackId: string;
+}): Promise<ViewSummary> => {
+  return runCommand<ViewSummary>("get_view_summary", { splitId, trackId });
+};
+
+export const getCoverage = async ({
+  splitId,
+  trackId,
+}: {
+  splitId: string;
+  trackId: string;
+}): Promise<Coverage | null> => {
+  return runCommand<Coverage | null>("get_coverage", { splitId, trackId });
+};
+
+export const getPileup = async ({
+  trackId,
+  splitId,
+  region,
+}: {
+  trackId: string;
+  splitId: string;
+  region: GenomicRegion;
+}): Promise<Pileup> => {
+  return runCommand<Pileup>("get_pileup", { trackId, splitId, region });
+};
+
+export const getBinnedCoverage = async ({
+  trackId,
+  region,
+  maxBins,
+}: {
+  trackId: string;
+  region: GenomicRegion;
+  maxBins: number;
+}): Promise<BinnedCoverage> => {
+  return runCommand<BinnedCoverage>("get_binned_coverage", { trackId, region, maxBins });
+};
+
+export const getReadLengthHistogram = async ({
+  splitId,
+  trackId,
+  scope,
+  numBins,
+}: {
+  splitId: string;
+  trackId: string;
+  scope: ReadLengthScope;
+  numBins: number;
+}): Promise<ReadLengthHistogram> => {
+  return runCommand<ReadLengthHistogram>("get_read_length_histogram", {
+    splitId,
+    trackId,
+    scope,
+    numBins,
+  });
+};
+
+/**
+ * Find the aligned region of a read's mate (null if the read is unpaired or its mate is
+ * unmapped). If `jump` is set then the split's focused region is moved onto the mate.
+ */
+export const findMate = async ({
+  splitId,
+  trackId,
+  readId,
+  jump,
+  requestId,
+}: {
+  splitId: string;
+  trackId: string;
+  readId: string;
+  jump: boolean;
+  requestId?: string;
+}): Promise<GenomicRegion | null> => {
+  return runCommand<GenomicRegion | null>("find_mate", {
+    splitId,
+    trackId,
+    readId,
+    jump,
+    requestId,
+  });
+};
+
+export const getReadDetails = async ({
+  splitId,
+  trackId,
+  readId,
+}: {
+  splitId: string;
+  trackId: string;
+  readId: string;
+}): Promise<ReadDetails> => {
+  return runCommand<ReadDetails>("get_read_details", { splitId, trackId, readId });
+};
+
+export const getInsertionSequence = async ({
+  splitId,
+  trackId,
+  readId,
+  position,
+}: {
+  splitId: string;
+  trackId: string;
+  readId: string;
+  position: bigint;
+}): Promise<string> => {
+  return runCommand<string>("get_insertion_sequence", {
+    splitId,
+    trackId,
+    readId,
+    position: position.toString(),
+  });
+};
+
+/** Find the reads overlapping a position in a track/split (e.g to hit test a click). */
+export const getReadsAt = async ({
+  splitId,
+  trackId,
+  position,
+}: {
+  splitId: string;
+  trackId: string;
+  position: bigint;
+}): Promise<ReadAtPosition[]> => {
+  return runCommand<ReadAtPosition[]>("get_reads_at", {
+    splitId,
+    trackId,
+    position: position.toString(),
+  });
+};
+
+export const loadSequencingSummary = async ({
+  trackId,
+  filePath,
+}: {
+  trackId: string;
+  filePath: string;
+}): Promise<null> => {
+  return runCommand<null>("load_sequencing_summary", { trackId, filePath });
+};
+
+export const setTrackFilter = async ({
+  trackId,
+  filter,
+}: {
+  trackId: string;
+  filter: ReadFilter;
+}): Promise<null> => {
+  return runCommand<null>("set_track_filter", { trackId, filter });
+};
+
+export const setHideDuplicates = async ({
+  trackId,
+  hideDuplicates,
+}: {
+  trackId: string;
+  hideDuplicates: boolean;
+}): Promise<null> => {
+  return runCommand<null>("set_hide_duplicates", { trackId, hideDuplicates });
+};
+
+export const setHideSecondary = async ({
+  trackId,
+  hideSecondary,
+}: {
+  trackId: string;
+  hideSecondary: boolean;
+}): Promise<null> => {
+  return runCommand<null>("set_hide_secondary", { trackId, hideSecondary });
+};
+
+export const setHideSupplementary = async ({
+  trackId,
+  hideSupplementary,
+}: {
+  trackId: string;
+  hideSupplementary: boolean;
+}): Promise<null> => {
+  return runCommand<null>("set_hide_supplementary", { trackId, hideSupplementary });
+};
+
+export const sortAlignments = async ({
+  splitId,
+  trackId,
+  position,
+  by,
+}: {
+  splitId: string;
+  trackId: string;
+  position: bigint;
+  by: SortBy;
+}): Promise<null> => {
+  return runCommand<null>("sort_alignments", {
+    splitId,
+    trackId,
+    position: position.toString(),
+    by,
+  });
+};
+
+export const splitTrackByReadGroup = async ({
+  trackId,
+  groupBy,
+}: {
+  trackId: string;
+  groupBy: ReadGroupField;
+}): Promise<string[]> => {
+  return runCommand<string[]>("split_track_by_read_group", { trackId, groupBy });
+};
+
+export const splitTrackByTag = async ({
+  trackId,
+  tag,
+  groups,
+}: {
+  trackId: string;
+  tag: string;
+  groups: TagGroup[];
+}): Promise<string[]> => {
+  return runCommand<string[]>("split_track_by_tag", { trackId, tag, groups });
+};
+
+/**
+ * Make sure that the backend emits events in the schema which the frontend was built against,
+ * asking a newer backend to emit the older schema if necessary.
+ */
+const negotiateApiSchemaVersion = async (): Promise<ApiSchemaVersion> => {
+  const backendVersion = await runCommand<ApiSchemaVersion>("get_api_schema_version");
+  if (backendVersion.emittedSchemaVersion === API_SCHEMA_VERSION) {
+    return backendVersion;
+  }
+  if (
+    API_SCHEMA_VERSION < backendVersion.minSchemaVersion ||
+    API_SCHEMA_VERSION > backendVersion.schemaVersion
+  ) {
+    throw new Error(
+      `Frontend API schema version ${API_SCHEMA_VERSION} is not supported by the backend ` +
+        `(supports ${backendVersion.minSchemaVersion} to ${backendVersion.schemaVersion})`
+    );
+  }
+  return runCommand<ApiSchemaVersion>("set_api_schema_version", {
+    schemaVersion: API_SCHEMA_VERSION,
+  });
 };
 
 export const initializeBackend = async (): Promise<null> => {
+  await negotiateApiSchemaVersion();
   return runCommand<null>("initialize");
 };
 
-export const panFocusedSplit = async (direction: Direction): Promise<null> => {
-  return runCommand<null>("pan_focused_split", { direction });
+export const panFocusedSplit = async (direction: Direction, requestId?: string): Promise<null> => {
+  return runCommand<null>("pan_focused_split", { direction, requestId });
 };
 
 export const updateFocusedRegion = async ({
   splitId,
   genomicRegion,
+  requestId,
 }: {
   splitId: string;
   readonly genomicRegion: GenomicRegion | GenomicRegion;
+  requestId?: string;
 }): Promise<null> => {
-  return runCommand<null>("update_focused_region", { splitId, genomicRegion });
+  return runCommand<null>("update_focused_region", { splitId, genomicRegion, requestId });
 };
 
 export const updateGridFocus = async (gridCoord: GridCoord): Promise<null> => {
@@ -156,6 +591,12 @@ export const listenForFocusedRegionUpdated: EventListener<FocusedRegionUpdatedPa
   return tauriListen<FocusedRegionUpdatedPayload>("focused-region-updated", handler);
 };
 
+export const listenForFocusedRegionLoaded: EventListener<FocusedRegionLoadedPayload> = async (
+  handler
+) => {
+  return tauriListen<FocusedRegionLoadedPayload>("focused-region-loaded", handler);
+};
+
 export const listenForTrackAdded: EventListener<AlignmentTrackData> = async (handler) => {
   return tauriListen("track-added", handler);
 };
@@ -186,16 +627,85 @@ export const listenForFocusedSequenceUpdateQueued: EventListener<
   return tauriListen<FocusedSequenceUpdatedPayload>("focused-sequence-update-queued", handler);
 };
 
+const decodeAlignments = async (payload: AlignmentsUpdatedPayload): Promise<void> => {
+  if (payload.compressed) {
+    payload.alignments = await decodeCompressedPayloadField(
+      payload.alignments as unknown as string,
+      payload.encoding
+    );
+  } else {
+    payload.alignments = decodePayloadField(payload.alignments, payload.encoding);
+  }
+  payload.encoding = "json";
+  payload.compressed = false;
+};
+
 export const listenForAlignmentsUpdated: EventListener<AlignmentsUpdatedPayload> = async (
   handler
 ) => {
-  return tauriListen<AlignmentsUpdatedPayload>("alignments-updated", handler);
+  return tauriListen<AlignmentsUpdatedPayload>("alignments-updated", handler, decodeAlignments);
+};
+
+export const listenForAlignmentsAppended: EventListener<AlignmentsAppendedPayload> = async (
+  handler
+) => {
+  return tauriListen<AlignmentsAppendedPayload>("alignments-appended", handler);
 };
 
 export const listenForAlignmentsUpdateQueued: EventListener<AlignmentsUpdatedPayload> = async (
   handler
 ) => {
-  return tauriListen<AlignmentsUpdatedPayload>("alignments-update-queued", handler);
+  return tauriListen<AlignmentsUpdatedPayload>(
+    "alignments-update-queued",
+    handler,
+    decodeAlignments
+  );
+};
+
+export const listenForBamIndexProgress: EventListener<IndexProgressPayload> = async (
+  handler
+) => {
+  return tauriListen<IndexProgressPayload>("bam-index-progress", handler);
+};
+
+export const listenForContigChanged: EventListener<ContigChangedPayload> = async (handler) => {
+  return tauriListen<ContigChangedPayload>("contig-changed", handler);
+};
+
+export const listenForCoverageUpdated: EventListener<CoverageUpdatedPayload> = async (handler) => {
+  return tauriListen<CoverageUpdatedPayload>("coverage-updated", handler);
+};
+
+export const listenForSignalUpdated: EventListener<SignalUpdatedPayload> = async (handler) => {
+  return tauriListen<SignalUpdatedPayload>("signal-updated", handler);
+};
+
+export const listenForQualityAlert: EventListener<QualityAlertPayload> = async (handler) => {
+  return tauriListen<QualityAlertPayload>("quality-alert", handler);
+};
+
+export const listenForReadsDownsampled: EventListener<ReadsDownsampledPayload> = async (
+  handler
+) => {
+  return tauriListen<ReadsDownsampledPayload>("reads-downsampled", handler);
+};
+
+export const listenForReferenceMismatch: EventListener<ReferenceMismatchPayload> = async (
+  handler
+) => {
+  return tauriListen<ReferenceMismatchPayload>("reference-mismatch", handler);
+};
+
+export const listenForFastaIndexProgress: EventListener<IndexProgressPayload> = async (
+  handler
+) => {
+  return tauriListen<IndexProgressPayload>("fasta-index-progress", handler);
+};
+
+export const listenForGenomeDownloadProgress: EventListener<
+  GenomeDownloadProgressPayload
+> = async (handler) => {
+  return tauriListen<GenomeDownloadProgressPayload>("genome-download-progress", handler);
 };
 
 export const listenForRegionBuffering: EventListener<RegionBufferingPayload> = async (handler) => {
@@ -219,7 +729,7 @@ export const listenForRefSeqFileUpdated: EventListener<ReferenceSequence> = asyn
 };
 
 export const listenForGridFocusUpdated: EventListener<GridCoord> = async (handler) => {
-  return tauriListen<GridCoord>("focused-split-updated", handler);
+  return tauriListen<GridCoord>("grid-focus-updated", handler);
 };
 
 /**
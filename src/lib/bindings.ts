@@ -13,38 +13,444 @@ export type AlignedPair =
 export interface AlignedRead {
   id: string;
   region: GenomicRegion;
-  matePos: GenomicRegion | null;
-  cigarString: string;
-  diffs: Array<SequenceDiff>;
+  // Optional fields may be excluded by an AlignmentFieldMask
+  qname?: string;
+  matePos?: GenomicRegion | null;
+  cigarString?: string;
+  diffs?: Array<CompactSequenceDiff>;
   isReverse: boolean;
+  isSecondOfPair: boolean;
+  parseError: string | null;
+  clippedStart: boolean;
+  clippedEnd: boolean;
+  numHiddenDiffs: number;
+  runMetadata: ReadRunMetadata | null;
+  /** Haplotype which the read is phased to (HP tag). */
+  haplotype: number | null;
+  mapq: number;
+  /** Observed template length (TLEN), negative for the rightmost read of a pair. */
+  templateLength: number;
+  pairOrientation: PairOrientation | null;
+  /** Run-length encoded base qualities, in the order of the read's sequence. */
+  baseQualities?: QualityRun[] | null;
+  /** Base modification calls from the MM/ML tags, ordered by position. */
+  baseModifications?: BaseModification[];
+  isSupplementary: boolean;
+  /** All alignments of the read if it has supplementary alignments (SA tag). */
+  chain: AlignmentChain | null;
+}
+
+export interface ChainSegment {
+  region: GenomicRegion;
+  isReverse: boolean;
+  /** Span of the alignment in the original (unreversed) read sequence. */
+  queryStart: number;
+  queryEnd: number;
+  mapq: number;
+}
+
+export interface AlignmentChain {
+  /** Shared by every alignment of the read. */
+  id: string;
+  /** Alignments ordered by their position in the read. */
+  segments: ChainSegment[];
+  /** Index of the segment which this alignment is. */
+  index: number;
+}
+
+export interface BaseModification {
+  position: bigint;
+  /** Modification code, e.g "m" (5mC), "h" (5hmC), "a" (6mA) or a ChEBI number. */
+  code: string;
+  /** Probability that the base is modified, scaled to 0-255. */
+  probability: number;
+}
+
+export interface QualityRun {
+  length: number;
+  quality: number;
+}
+
+/** Orientation of a read pair from the leftmost read to the rightmost read. */
+export type PairOrientation = "fr" | "rf" | "tandem";
+
+/** Insert size relative to the range sampled from the pairs in the loaded region. */
+export type InsertSizeClass = "small" | "normal" | "large";
+
+export interface ReadRunMetadata {
+  channel: number | null;
+  meanQscore: number | null;
+  duration: number | null;
+  runId: string | null;
+}
+
+export interface AlignmentFieldMask {
+  qname: boolean;
+  cigarString: boolean;
+  diffs: boolean;
+  matePos: boolean;
+  baseQualities: boolean;
+  baseModifications: boolean;
+}
+
+export interface StackGroup {
+  /** Value of the grouped field, null for alignments which don't have one. */
+  key: string | null;
+  startRow: number;
+  endRow: number;
 }
 
 export interface AlignmentStack<T> {
   id: string;
+  diffSchemaVersion: number;
+  /** Incremented whenever the stack changes. */
+  revision: number;
   rows: Array<Array<T>>;
+  /** Sections of the rows which each contain a single group. Empty if the stack is ungrouped. */
+  groups: StackGroup[];
+  /** First row of reverse strand alignments when stacked by strand, null otherwise. */
+  strandBoundary: number | null;
+  /** Rows beyond the track's maximum number of rows, which aren't included. */
+  hiddenRowCount: number;
+  hiddenReadCount: number;
+  /** Index of the first included row and the number of rows in the whole stack, only set when a
+   * range of rows was requested. Row indices in groups and strandBoundary are then relative to
+   * rowStart. */
+  rowStart?: number;
+  totalRows?: number;
   bufferedRegion: GenomicRegion;
 }
 
-export type AlignmentStackKind = { type: "alignedPairKind" } & AlignmentStack<AlignedPair>;
+export type AlignmentStackKind =
+  | ({ type: "alignedPairKind" } & AlignmentStack<AlignedPair>)
+  | ({ type: "pafRecordKind" } & AlignmentStack<PafRecord>)
+  | ({ type: "intervalRecordKind" } & AlignmentStack<IntervalRecord>);
+
+export interface AddedAlignment<T> {
+  rowIndex: number;
+  /** Index in the row once removed alignments are removed and earlier alignments are added. */
+  index: number;
+  alignment: T;
+}
+
+/** Changes made to a stack when the buffered region is extended. */
+export interface AlignmentsDelta<T = AlignedPair | PafRecord | IntervalRecord> {
+  stackId: string;
+  /** Revision of the stack which the delta applies to. */
+  previousRevision: number;
+  revision: number;
+  /** Number of rows in the updated stack. */
+  numRows: number;
+  groups: StackGroup[];
+  strandBoundary: number | null;
+  hiddenRowCount: number;
+  hiddenReadCount: number;
+  bufferedRegion: GenomicRegion;
+  /** Alignments to remove before inserting the added alignments. */
+  removedIds: string[];
+  /** Ordered by row and index within the row. */
+  added: AddedAlignment<T>[];
+}
+
+/** Criteria which reads must meet to be shown in a SAM/BAM track. */
+export interface ReadFilter {
+  minMapq: number;
+  hideDuplicates: boolean;
+  hideSecondary: boolean;
+  hideSupplementary: boolean;
+  /** samtools-style filter expression e.g `mapq >= 30 && flag.proper_pair && [NM] <= 2`. */
+  expression: string | null;
+  /** Only reads from these read groups (RG tag) are shown, if set. */
+  readGroups: string[] | null;
+  /** Only reads with one of these values of a tag are shown, if set. */
+  tagValues: TagValues | null;
+}
+
+export interface TagValues {
+  tag: string;
+  values: string[];
+}
 
-export interface AlignmentTrackData {
+/** Named group of tag values (e.g the cell barcodes of a cluster) which get their own track. */
+export interface TagGroup {
+  name: string;
+  values: string[];
+}
+
+export type ReadGroupField = "sample" | "library";
+
+export interface AlignmentTrackData extends TrackCustomization {
   id: string;
   filePath: string;
   name: string;
+  sampleNames: Array<string>;
+  intervalColumns: IntervalColumns | null;
+  filter: ReadFilter;
 }
 
+/** Encoding of the alignments in AlignmentsUpdatedPayload. MessagePack is sent as base64. */
+export type PayloadEncoding = "json" | "messagePack";
+
 export interface AlignmentsUpdatedPayload {
   trackId: string;
   splitId: string;
   focusedRegion: GenomicRegion;
   alignments: AlignmentStackKind;
+  encoding: PayloadEncoding;
+  // Whether the encoded alignments were gzipped (and sent as base64)
+  compressed: boolean;
+  skippedReads: SkippedReads;
+  renderWindow: string | null;
+  // Sampled read depth, sent in place of alignments when the region is too large to load
+  binnedCoverage: BinnedCoverage | null;
+  // Positions where enough reads have a mismatched base to be highlighted as likely SNVs
+  mismatchHighlights: MismatchHighlight[];
+  // Splice junctions of the stacked reads, for drawing sashimi arcs
+  spliceJunctions: SpliceJunction[];
+}
+
+/** Sent in place of AlignmentsUpdatedPayload when panning extends the buffered region. */
+export interface AlignmentsAppendedPayload {
+  trackId: string;
+  splitId: string;
+  focusedRegion: GenomicRegion;
+  delta: AlignmentsDelta;
+  skippedReads: SkippedReads;
+  renderWindow: string | null;
+  mismatchHighlights: MismatchHighlight[];
+  spliceJunctions: SpliceJunction[];
+}
+
+export interface SpliceJunction {
+  /** The skipped (intronic) region. */
+  region: GenomicRegion;
+  numReads: number;
+}
+
+export interface SkippedRead {
+  qname: string;
+  reason: string;
+}
+
+export interface SkippedReads {
+  count: number;
+  reads: Array<SkippedRead>;
+  /** Valid reads which were excluded by the track's read filter. */
+  filteredCount: number;
+  /** Unmapped reads which were placed in the region and can't be displayed. */
+  unmappedCount: number;
+}
+
+export interface ContigChangedPayload {
+  splitId: string;
+  previousSeqName: string;
+  seqName: string;
+}
+
+export interface QualityAlert {
+  ruleName: string;
+  targetName: string | null;
+  region: GenomicRegion;
+  minDepth: number;
+  observedDepth: number;
+  lowDepthPositions: bigint;
+}
+
+export interface DiffLabel {
+  readId: string;
+  diffIndex: number;
+  row: number;
+  // Indel lengths are numeric strings, so are converted to BigInts
+  text: string | bigint;
+  xStart: number;
+  xEnd: number;
+  show: boolean;
+}
+
+export interface PileupColumn {
+  position: bigint;
+  a: number;
+  c: number;
+  g: number;
+  t: number;
+  n: number;
+  del: number;
+  ins: number;
+}
+
+export interface Pileup {
+  region: GenomicRegion;
+  columns: PileupColumn[];
+}
+
+export interface MismatchHighlight extends PileupColumn {
+  mismatchFraction: number;
+}
+
+export type Allele = "A" | "C" | "G" | "T" | "DEL";
+
+export interface NotableVariant {
+  region: GenomicRegion;
+  reference: string;
+  allele: Allele;
+  count: number;
+  depth: number;
+}
+
+export interface DiscordantCluster {
+  region: GenomicRegion;
+  mateRegion: GenomicRegion;
+  numReads: number;
+}
+
+export interface ViewSummary {
+  region: GenomicRegion;
+  minDepth: number;
+  maxDepth: number;
+  meanDepth: number;
+  variants: NotableVariant[];
+  discordantClusters: DiscordantCluster[];
+  text: string;
+}
+
+export interface Coverage {
+  region: GenomicRegion;
+  depths: number[];
+  /** Depths split by strand, null if the track's coverage strands are combined. */
+  strandedDepths: StrandedDepths | null;
+}
+
+export interface StrandedDepths {
+  forward: number[];
+  reverse: number[];
+}
+
+/** How a track's read depth is split by strand (e.g to show RNA-seq strandedness). */
+export type CoverageStrand = "combined" | "read" | "firstOfPair" | "secondOfPair";
+
+/** Mean read depth in fixed-size bins, for regions too large to load alignments for. */
+export interface BinnedCoverage {
+  region: GenomicRegion;
+  binSize: bigint;
+  depths: number[];
+}
+
+export type ReadLengthScope = "focusedRegion" | "file";
+
+export interface ReadLengthBin {
+  start: bigint;
+  end: bigint;
+  count: bigint;
+}
+
+export interface ReadLengthHistogram {
+  /** Null if the reads were taken from the start of the file. */
+  region: GenomicRegion | null;
+  numReads: bigint;
+  minLength: bigint;
+  maxLength: bigint;
+  meanLength: number;
+  n50: bigint;
+  bins: ReadLengthBin[];
+  /** True if the histogram was computed from a sample of the reads. */
+  isEstimate: boolean;
+}
+
+/** Long-running operations which mark a split as busy. */
+export type SplitOperation = "indexingTrack" | "loadingTrack" | "updatingFocusedRegion";
+
+/** Returned by a command which is attempted on a split which is already busy. */
+export interface SplitBusyError {
+  kind: "splitBusy";
+  splitId: string;
+  operation: SplitOperation;
+  message: string;
+}
+
+/** Errors returned by commands. Errors other than SplitBusyError are sent as their message. */
+export type CommandError = SplitBusyError | string;
+
+export interface CoverageUpdatedPayload {
+  splitId: string;
+  trackId: string;
+  coverage: Coverage;
+}
+
+/** Signal values (e.g from a bedGraph/wig file) summarized into fixed-width bins. */
+export interface SignalBins {
+  region: GenomicRegion;
+  binSize: bigint;
+  /** Mean signal value of the covered bases in each bin (null if no bases are covered). */
+  values: (number | null)[];
+}
+
+export interface SignalUpdatedPayload {
+  splitId: string;
+  trackId: string;
+  bins: SignalBins;
+}
+
+/** Sent when reads were dropped from a track/split because they exceeded its max depth. */
+export interface ReadsDownsampledPayload {
+  splitId: string;
+  trackId: string;
+  region: GenomicRegion;
+  maxDepth: number;
+  numAlignments: bigint;
+  numSampled: bigint;
+  sampledFraction: number;
+}
+
+export interface QualityAlertPayload {
+  splitId: string;
+  trackId: string;
+  focusedRegion: GenomicRegion;
+  alerts: QualityAlert[];
+}
+
+export type ContigMismatchKind = "missing" | "length" | "md5";
+
+export interface ContigMismatch {
+  seqName: string;
+  kind: ContigMismatchKind;
+  length: bigint;
+  referenceLength: bigint | null;
+}
+
+export interface ReferenceMismatchPayload {
+  trackId: string;
+  filePath: string;
+  mismatches: ContigMismatch[];
+}
+
+export type IndexStatus = "started" | "finished" | "failed";
+
+export interface IndexProgressPayload {
+  filePath: string;
+  status: IndexStatus;
+}
+
+export type GenomeDownloadStatus = "started" | "downloading" | "indexing" | "finished" | "failed";
+
+export interface GenomeDownloadProgressPayload {
+  genomeId: string;
+  status: GenomeDownloadStatus;
+  downloadedBytes: bigint;
+  totalBytes: bigint | null;
 }
 
 export interface RegionBufferingPayload {
   splitId: string;
 }
 
+/** Sent once the alignments of a focused region update are loaded (error is set if it failed). */
+export interface FocusedRegionLoadedPayload {
+  splitId: string;
+  error: string | null;
+}
+
 export interface DiscordantRead {
+  id: string;
   read: AlignedRead;
   interval: GenomicInterval;
 }
@@ -52,6 +458,8 @@ export interface DiscordantRead {
 export interface FocusedRegionUpdatedPayload {
   splitId: string;
   genomicRegion: GenomicRegion;
+  displayRegion: string;
+  displayLength: string;
 }
 
 export interface FocusedSequenceUpdatedPayload {
@@ -60,6 +468,8 @@ export interface FocusedSequenceUpdatedPayload {
   bufferedSequence: string | null;
   focusedRegion: GenomicRegion;
   bufferedRegion: GenomicRegion;
+  // If true the buffered sequence is too long to be included and must be fetched in chunks
+  bufferedSequenceChunked: boolean;
 }
 
 export interface GenomicInterval {
@@ -72,10 +482,47 @@ export interface GenomicRegion {
   interval: GenomicInterval;
 }
 
+/** 0-based column indices of interval fields in a tabix-indexed file. */
+export interface IntervalColumns {
+  seqName: number;
+  start: number;
+  end: number;
+  name: number | null;
+}
+
+export interface IntervalRecord {
+  type: "intervalRecordKind";
+  id: string;
+  interval: GenomicInterval;
+  name: string | null;
+}
+
+export interface PafRecord {
+  type: "pafRecordKind";
+  id: string;
+  queryName: string;
+  queryLength: bigint;
+  queryStart: bigint;
+  queryEnd: bigint;
+  targetName: string;
+  interval: GenomicInterval;
+  isReverse: boolean;
+  numMatches: bigint;
+  blockLength: bigint;
+  mapq: number;
+  cigarString: string | null;
+}
+
 export interface PairedReads {
+  id: string;
   read1: AlignedRead;
   read2: AlignedRead | null;
   interval: GenomicInterval;
+  leftmostEnd: bigint;
+  rightmostStart: bigint;
+  gapLength: bigint;
+  orientation: PairOrientation | null;
+  insertSizeClass: InsertSizeClass | null;
 }
 
 export type Mismatch = {
@@ -88,6 +535,8 @@ export type Insertion = {
   type: "ins";
   interval: GenomicInterval;
   sequence: string;
+  /** Length of the full insertion if the sequence was truncated (see getInsertionSequence). */
+  fullLength: number | null;
 };
 
 export type Deletion = {
@@ -101,9 +550,37 @@ export type SoftClip = {
   sequence: string;
 };
 
-export type SequenceDiff = Mismatch | Insertion | Deletion | SoftClip;
+export type RefSkip = {
+  type: "refSkip";
+  interval: GenomicInterval;
+};
 
-export interface SplitData {
+export type SequenceDiff = Mismatch | Insertion | Deletion | SoftClip | RefSkip;
+
+export enum DiffKind {
+  Mismatch = 0,
+  Ins = 1,
+  Del = 2,
+  SoftClip = 3,
+  RefSkip = 4,
+}
+
+/**
+ * Diffs are sent as [kind, start, end] or [kind, start, end, sequence] to reduce payload size.
+ * Truncated insertions are sent as [kind, start, end, sequence, fullLength]. Positions are always
+ * numbers, and are converted to bigints when the diff is decoded.
+ */
+export type CompactSequenceDiff =
+  | [DiffKind, number, number]
+  | [DiffKind, number, number, string]
+  | [DiffKind, number, number, string, number];
+
+export interface SplitAnnotation {
+  name: string | null;
+  description: string | null;
+}
+
+export interface SplitData extends SplitAnnotation {
   id: string;
   focusedRegion: GenomicRegion;
   bufferedRegion: GenomicRegion;
@@ -118,7 +595,131 @@ export type SplitMap = {
 
 export type TrackData = AlignmentTrackData;
 
+/** Diffs are excluded from squished and collapsed stacks. Collapsed stacks have a single row. */
+export type PackingStrategy = "greedy" | "pileupOrder" | "tight" | "squished" | "collapsed";
+
+export type StackGrouping = "ungrouped" | "haplotype" | "strand";
+
+export type SortBy = "base" | "start" | "templateLength" | "mappingQuality" | "strand";
+
+export type TrackPriority = "high" | "normal" | "low";
+
+export interface TrackCustomization {
+  color: string | null;
+  label: string | null;
+  description: string | null;
+  priority: TrackPriority;
+  // Minimum gap in bp between adjacent alignments in a row
+  stackPadding: number | null;
+  packing: PackingStrategy;
+  grouping: StackGrouping;
+  maxDepth: number | null;
+  /** Maximum number of rows in the track's stacks, beyond which rows are hidden. */
+  maxRows: number | null;
+  /** Include base qualities with the track's reads (omitted for large regions). */
+  baseQualities: boolean;
+  coverageStrand: CoverageStrand;
+}
+
+export interface UnmappedRead {
+  qname: string;
+  sequence: string;
+  isReverse: boolean;
+}
+
+/** An optional field of a read, formatted as in a SAM file. */
+export interface SamTag {
+  tag: string;
+  typeCode: string;
+  /** Arrays are formatted as their element type followed by their values (e.g "c,1,2,3"). */
+  value: string;
+}
+
+export interface ReadDetails {
+  read: AlignedRead;
+  flags: number;
+  /** Names of the flag bits which are set (e.g PAIRED, READ1), as used by samtools. */
+  flagNames: string[];
+  sequence: string;
+  /** Phred scaled base qualities, null if the read doesn't have qualities. */
+  baseQualities: number[] | null;
+  isMateReverse: boolean;
+  isMateUnmapped: boolean;
+  tags: SamTag[];
+}
+
+/** A read in a stack which overlaps a position. */
+export interface ReadAtPosition {
+  rowIndex: number;
+  pairId: string;
+  readId: string;
+}
+
+export type ClipSide = "left" | "right";
+
+export interface SessionReference {
+  name: string;
+  path: string;
+}
+
+export interface SessionSplit extends SplitAnnotation {
+  focusedRegion: GenomicRegion;
+}
+
+export interface SessionTrack extends TrackCustomization {
+  filePath: string;
+}
+
+export interface Session {
+  version: number;
+  reference: SessionReference;
+  splits: SessionSplit[];
+  tracks: SessionTrack[];
+}
+
+export interface SoftClipCluster {
+  position: string;
+  side: ClipSide;
+  numReads: number;
+  consensus: string;
+}
+
+export interface ContaminantHit {
+  name: string;
+  sharedKmers: number;
+  fraction: number;
+}
+
+export interface FastqAlignment {
+  region: GenomicRegion;
+  isReverse: boolean;
+  score: number;
+  cigarString: string;
+}
+
+export interface FastqRead {
+  id: string;
+  description: string | null;
+  sequence: string;
+  qualities: string;
+  meanQuality: number;
+  alignment: FastqAlignment | null;
+}
+
+export interface FastqPage {
+  reads: FastqRead[];
+  offset: number;
+  totalReads: number;
+  truncated: boolean;
+}
+
+export interface SoftClipContaminantCheck {
+  cluster: SoftClipCluster;
+  hits: ContaminantHit[];
+}
+
 export interface UnpairedRead {
+  id: string;
   read: AlignedRead;
   interval: GenomicInterval;
 }
@@ -161,8 +762,36 @@ export interface StyleConfig {
   fonts: FontConfig;
 }
 
+/**
+ * Event payloads caused by a command which was sent with a request id include the id. Ids should
+ * be non-numeric (e.g UUIDs) since numeric strings are converted to BigInts.
+ */
+export type WithRequestId<T> = T & { requestId?: string };
+
+export interface ApiSchemaVersion {
+  schemaVersion: number;
+  minSchemaVersion: number;
+  emittedSchemaVersion: number;
+}
+
+export interface RegistryGenomeStatus {
+  id: string;
+  name: string;
+  species: string;
+  fastaUrl: string;
+  path: string | null;
+  isDownloading: boolean;
+}
+
+// Tokens and headers are never sent to the frontend
+export interface HostAuthConfig {
+  host: string;
+}
+
 export interface UserConfig {
   styles: StyleConfig;
+  hostAuth: HostAuthConfig[];
+  contigAliases: { [alias: string]: string };
 }
 
 export type SeqLengthMap = {
@@ -176,7 +805,10 @@ export interface ReferenceSequence {
   seqLengths: SeqLengthMap;
 }
 
+export type FocusTarget = "split" | "track" | "cell";
+
 export interface GridCoord {
-  trackId: string;
+  trackId: string | null;
   splitId: string;
+  target: FocusTarget;
 }
@@ -5,6 +5,9 @@ import type { IUPACNucleotide } from "@lib/types";
 
 export const DIVIDER_PX = 2;
 
+/** Version of the backend's event payload schema which the frontend was built against. */
+export const API_SCHEMA_VERSION = 3;
+
 export const PRIMARY_IUPAC_NUCLEOTIDES: IUPACNucleotide[] = ["A", "G", "C", "T"];
 export const SECONDARY_IUPAC_NUCLEOTIDES: IUPACNucleotide[] = [
   "N",
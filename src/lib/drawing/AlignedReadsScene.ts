@@ -22,6 +22,8 @@ import type {
   Deletion,
   GenomicRegion,
   Insertion,
+  IntervalRecord,
+  PafRecord,
   PairedReads,
   SoftClip,
 } from "@lib/bindings";
@@ -38,6 +40,7 @@ import {
 } from "@lib/drawing/drawing";
 import { getLength, to1IndexedString } from "@lib/genomicCoordinates";
 import LOG from "@lib/logger";
+import { DIFF_SCHEMA_VERSION, decodeSequenceDiff } from "@lib/sequenceDiffs";
 import type { Dimensions, Position } from "@lib/types";
 import { range } from "@lib/util";
 
@@ -231,7 +234,7 @@ class AlignedReadTooltip extends Container {
     this._maxFieldWidth = 0;
     this._updateField(TOOLTIP_FIELD_NAME, read.id);
     this._updateField(TOOLTIP_FIELD_COORDINATES, to1IndexedString(read.region));
-    this._updateField(TOOLTIP_FIELD_CIGAR, read.cigarString);
+    this._updateField(TOOLTIP_FIELD_CIGAR, read.cigarString ?? "");
     this._background.width = 2 * TOOLTIP_HPAD + this._maxFieldWidth;
   };
 }
@@ -675,6 +678,8 @@ export class AlignedReadsScene extends Scene {
 
   /**
    * Render a line connecting two paired reads.
+   *
+   * The line spans the gap between the reads so nothing is drawn if the reads overlap.
    */
   _displayPairLine = ({
     pos,
@@ -683,9 +688,14 @@ export class AlignedReadsScene extends Scene {
     readonly pos: Position;
     readonly alignment: PairedReads;
   }): void => {
-    const linePos = { x: pos.x, y: pos.y + READ_HEIGHT / 2 };
+    if (alignment.gapLength === 0n) {
+      return;
+    }
+    const lineX =
+      pos.x + Number(alignment.leftmostEnd - alignment.interval.start) * this._nucWidth;
+    const linePos = { x: lineX, y: pos.y + READ_HEIGHT / 2 };
     const dim = {
-      width: Number(getLength(alignment.interval)) * this._nucWidth,
+      width: Number(alignment.gapLength) * this._nucWidth,
       height: DEFAULT_PAIR_LINE_HEIGHT,
     };
     this._drawPool.draw(PAIR_LINE_POOL, { pos: linePos, dim });
@@ -757,7 +767,7 @@ export class AlignedReadsScene extends Scene {
    *  variant types)
    */
   _displayInsertion = ({ diff, pos }: { diff: Insertion; pos: Position }): void => {
-    const insertionLength = diff.sequence.length;
+    const insertionLength = diff.fullLength ?? diff.sequence.length;
     const labelText = insertionLength == 1 ? diff.sequence : String(insertionLength);
     const width =
       FONT_CHAR_WIDTH * INSERTION_FONTSIZE * labelText.length + 2 * INSERTION_LABEL_PADDING + 5;
@@ -788,7 +798,8 @@ export class AlignedReadsScene extends Scene {
    * Render all variants in an aligned read.
    */
   _displayDiffs = ({ read, pos }: { readonly read: AlignedRead; readonly pos: Position }): void => {
-    read.diffs.forEach((diff) => {
+    read.diffs?.forEach((compactDiff) => {
+      const diff = decodeSequenceDiff(compactDiff);
       const diffX =
         Number(diff.interval.start - this._focusedRegion!.interval.start) * this._nucWidth;
       switch (diff.type) {
@@ -883,13 +894,62 @@ export class AlignedReadsScene extends Scene {
     this._displayDiffs({ read, pos });
   };
 
+  /**
+   * Display a query alignment from a PAF file.
+   *
+   * PAF records don't include the query sequence so only the aligned span and strand are drawn.
+   */
+  _displayPafRecord = ({
+    record,
+    pos,
+  }: {
+    readonly record: PafRecord;
+    readonly pos: Position;
+  }): void => {
+    const width = Number(record.interval.end - record.interval.start) * this._nucWidth;
+    if (record.isReverse) {
+      this._drawPool.draw(REVERSE_READ_CAP_POOL, {
+        pos: { x: pos.x - CAP_WIDTH, y: pos.y },
+        dim: { width: CAP_WIDTH, height: READ_HEIGHT },
+      });
+    } else {
+      this._drawPool.draw(FORWARD_READ_CAP_POOL, {
+        pos: { x: pos.x + width, y: pos.y },
+        dim: { width: CAP_WIDTH, height: READ_HEIGHT },
+      });
+    }
+    this._drawPool.draw(READ_BODY_POOL, { pos, dim: { width, height: READ_HEIGHT } });
+  };
+
+  /**
+   * Display an interval from a generic tabix-indexed file.
+   */
+  _displayIntervalRecord = ({
+    record,
+    pos,
+  }: {
+    readonly record: IntervalRecord;
+    readonly pos: Position;
+  }): void => {
+    const width = Number(record.interval.end - record.interval.start) * this._nucWidth;
+    this._drawPool.draw(READ_BODY_POOL, { pos, dim: { width, height: READ_HEIGHT } });
+  };
+
   _displayAlignment = ({
     alignment,
     pos,
   }: {
-    readonly alignment: AlignedPair;
+    readonly alignment: AlignedPair | PafRecord | IntervalRecord;
     readonly pos: Position;
   }): void => {
+    if (alignment.type == "pafRecordKind") {
+      this._displayPafRecord({ record: alignment, pos });
+      return;
+    }
+    if (alignment.type == "intervalRecordKind") {
+      this._displayIntervalRecord({ record: alignment, pos });
+      return;
+    }
     let reads;
     if (alignment.type == "pairedReadsKind") {
       reads = [alignment.read1, alignment.read2];
@@ -920,6 +980,11 @@ export class AlignedReadsScene extends Scene {
     viewportWidth = this._dim.width,
     viewportHeight = this._dim.height,
   }: Partial<AlignedReadsSceneState>): void => {
+    if (alignments !== null && alignments.diffSchemaVersion !== DIFF_SCHEMA_VERSION) {
+      LOG.error(
+        `Unsupported diff schema version ${alignments.diffSchemaVersion} (expected ${DIFF_SCHEMA_VERSION})`
+      );
+    }
     this._alignments = alignments;
     this._focusedRegion = focusedRegion;
     this.resize({ width: viewportWidth, height: viewportHeight });
@@ -980,7 +1045,8 @@ export class AlignedReadsScene extends Scene {
       }, bufferHeight=${this._bufferDim.height}`
     );
     let y = 0;
-    this._alignments.rows.forEach((row) => {
+    const rows: Array<Array<AlignedPair | PafRecord | IntervalRecord>> = this._alignments.rows;
+    rows.forEach((row) => {
       row.forEach((alignment) => {
         const x =
           Number(BigInt(alignment.interval.start) - this._focusedRegion!.interval.start) *
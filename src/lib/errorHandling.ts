@@ -1,4 +1,5 @@
 /** Utilities for handling errors. */
+import type { SplitBusyError } from "@lib/bindings";
 import { ALERT_STORE } from "@lib/stores/AlertStore";
 import LOG from "@lib/logger";
 import { isString } from "@lib/util";
@@ -7,14 +8,20 @@ const isError = (e: unknown): e is Error => {
   return e instanceof Error;
 };
 
+export const isSplitBusyError = (e: unknown): e is SplitBusyError => {
+  return typeof e === "object" && e !== null && (e as SplitBusyError).kind === "splitBusy";
+};
+
 const parseUntypedErrorMsg = (msg: unknown): string => {
   let msgString;
   if (isError(msg)) {
     msgString = msg.message;
   } else if (isString(msg)) {
     msgString = msg;
+  } else if (isSplitBusyError(msg)) {
+    msgString = msg.message;
   } else {
-    throw new Error("`msg` must be a string or an Error object");
+    throw new Error("`msg` must be a string, an Error or a SplitBusyError object");
   }
   return msgString;
 };
@@ -1,6 +1,9 @@
 import {
+  formatLength,
+  formatPosition,
   to0IndexedString,
   to1IndexedString,
+  toDisplayString,
   parse1IndexedCoordinates,
   getLength,
 } from "./genomicCoordinates";
@@ -29,6 +32,11 @@ test("parse1IndexedCoordinates works with missing end coordinate", async () => {
   expect(parse1IndexedCoordinates("X:1")).toEqual(expectedRegion);
 });
 
+test("parse1IndexedCoordinates works with comma separated coordinates", async () => {
+  const expectedRegion = { seqName: "X", interval: { start: 999n, end: 2000000n } };
+  expect(parse1IndexedCoordinates("X:1,000-2,000,000")).toEqual(expectedRegion);
+});
+
 test("parse1IndexedCoordinates throws error for missing seq name", async () => {
   expect(() => parse1IndexedCoordinates("1-10")).toThrow(ParseError);
 });
@@ -55,3 +63,24 @@ test("getLength with 0 length GenomicInterval", async () => {
   const interval = { start: 0n, end: 0n };
   expect(getLength(interval)).toEqual(0n);
 });
+
+test("formatPosition", async () => {
+  expect(formatPosition(0n)).toEqual("0");
+  expect(formatPosition(999n)).toEqual("999");
+  expect(formatPosition(1000n)).toEqual("1,000");
+  expect(formatPosition(1234567n)).toEqual("1,234,567");
+});
+
+test("formatLength", async () => {
+  expect(formatLength(999n)).toEqual("999 bp");
+  expect(formatLength(1000n)).toEqual("1 kb");
+  expect(formatLength(16569n)).toEqual("16.6 kb");
+  expect(formatLength(999999n)).toEqual("1 Mb");
+  expect(formatLength(1234567n)).toEqual("1.2 Mb");
+  expect(formatLength(3100000000n)).toEqual("3.1 Gb");
+});
+
+test("toDisplayString", async () => {
+  const region = { seqName: "X", interval: { start: 1000n, end: 2000000n } };
+  expect(toDisplayString(region)).toEqual("X:1,001-2,000,000");
+});
@@ -1,7 +1,55 @@
 import type { GenomicInterval, GenomicRegion } from "@lib/bindings";
 import { ParseError } from "@lib/errors";
 
-const GENOMIC_REGION_REGEX = /(?<seqName>.*):(?<start>[0-9]+)-?(?<end>[0-9]+)?/;
+// Coordinates may contain comma separated thousands (e.g X:1,000-2,000)
+const GENOMIC_REGION_REGEX = /(?<seqName>.*):(?<start>[0-9][0-9,]*)-?(?<end>[0-9][0-9,]*)?/;
+
+// Units used to abbreviate lengths, largest first. Must match src-tauri/src/bio_util/formatting.rs
+const LENGTH_UNITS: [number, string][] = [
+  [1_000_000_000, "Gb"],
+  [1_000_000, "Mb"],
+  [1_000, "kb"],
+];
+
+const stripCommas = (value: string): string => value.replace(/,/g, "");
+
+/**
+ * Format a position with comma separated thousands (e.g 1,234,567).
+ */
+export const formatPosition = (pos: bigint | number): string => {
+  return pos.toString().replace(/\B(?=(\d{3})+(?!\d))/g, ",");
+};
+
+/**
+ * Format a length with a unit suffix, rounded to one decimal place (e.g 850 bp, 1.2 Mb).
+ */
+export const formatLength = (len: bigint | number): string => {
+  const value = Number(len);
+  if (value >= 1_000) {
+    for (const [scale, unit] of LENGTH_UNITS) {
+      // Round before choosing the unit so that e.g 999,999bp is shown as 1 Mb not 1000 kb
+      const tenths = Math.round((value / scale) * 10);
+      if (tenths >= 10) {
+        return `${(tenths / 10).toFixed(1).replace(/\.0$/, "")} ${unit}`;
+      }
+    }
+  }
+  return `${value} bp`;
+};
+
+/**
+ * Format a region using 1-indexed coordinates with comma separated thousands (e.g X:1,001-2,000).
+ */
+export const toDisplayString = (region: GenomicRegion): string => {
+  const reindexedStart = region.interval.start + 1n;
+  if (reindexedStart == region.interval.end) {
+    return `${region.seqName}:${formatPosition(reindexedStart)}`;
+  } else {
+    return `${region.seqName}:${formatPosition(reindexedStart)}-${formatPosition(
+      region.interval.end
+    )}`;
+  }
+};
 
 export const to1IndexedString = (region: GenomicRegion): string => {
   const reindexedStart = region.interval.start + 1n;
@@ -26,8 +74,8 @@ export const parse1IndexedCoordinates = (value: string): GenomicRegion => {
   ) {
     throw new ParseError(`${value} is not a valid genomic region`);
   }
-  const start = BigInt(parsed.groups.start) - 1n;
-  let end = parsed.groups.end === undefined ? null : BigInt(parsed.groups.end);
+  const start = BigInt(stripCommas(parsed.groups.start)) - 1n;
+  let end = parsed.groups.end === undefined ? null : BigInt(stripCommas(parsed.groups.end));
   if (isNaN(Number(start)) || (end !== null && isNaN(Number(end)))) {
     throw new ParseError(`${value} is not a valid genomic region (invalid start/end coordinate)`);
   }
@@ -0,0 +1,14 @@
+import { encode } from "@msgpack/msgpack";
+
+import { decodePayloadField } from "./payloadEncoding";
+
+test("decodePayloadField decodes base64 MessagePack", async () => {
+  expect(decodePayloadField("gaRyb3dzkA==", "messagePack")).toEqual({ rows: [] });
+  expect(decodePayloadField({ rows: [] }, "json")).toEqual({ rows: [] });
+});
+
+test("decodePayloadField decodes nested values", async () => {
+  const value = { rows: [[{ id: "a", start: 5000000000, diffs: [], mate: null }]], revision: -1 };
+  const base64 = btoa(String.fromCharCode(...encode(value)));
+  expect(decodePayloadField(base64, "messagePack")).toEqual(value);
+});
@@ -0,0 +1,41 @@
+/**
+ * Decoding of event payload fields which the backend encodes as MessagePack rather than JSON.
+ *
+ * The backend uses the same field names and values in MessagePack as it would in JSON, so decoding
+ * it gives the same object.
+ */
+import { decode } from "@msgpack/msgpack";
+
+import type { PayloadEncoding } from "@lib/bindings";
+
+const textDecoder = new TextDecoder();
+
+const decodeBase64 = (value: string): Uint8Array => {
+  return Uint8Array.from(atob(value), (char) => char.charCodeAt(0));
+};
+
+const gunzip = async (bytes: Uint8Array): Promise<Uint8Array> => {
+  const stream = new Blob([bytes]).stream().pipeThrough(new DecompressionStream("gzip"));
+  return new Uint8Array(await new Response(stream).arrayBuffer());
+};
+
+/** Decode a payload field which was encoded by the backend with the given encoding. */
+export const decodePayloadField = <T>(value: T | string, encoding: PayloadEncoding): T => {
+  if (encoding === "json") {
+    return value as T;
+  }
+  const bytes = decodeBase64(value as string);
+  return decode(bytes) as T;
+};
+
+/** Decode a payload field which was encoded and then gzip compressed by the backend. */
+export const decodeCompressedPayloadField = async <T>(
+  value: string,
+  encoding: PayloadEncoding
+): Promise<T> => {
+  const bytes = await gunzip(decodeBase64(value));
+  if (encoding === "json") {
+    return JSON.parse(textDecoder.decode(bytes)) as T;
+  }
+  return decode(bytes) as T;
+};
@@ -0,0 +1,27 @@
+import { DiffKind } from "@lib/bindings";
+import { decodeSequenceDiff } from "./sequenceDiffs";
+
+test("decodeSequenceDiff decodes diffs with a sequence", async () => {
+  expect(decodeSequenceDiff([DiffKind.Ins, 10, 10, "ACG"])).toEqual({
+    type: "ins",
+    interval: { start: 10n, end: 10n },
+    sequence: "ACG",
+    fullLength: null,
+  });
+});
+
+test("decodeSequenceDiff decodes truncated insertions", async () => {
+  expect(decodeSequenceDiff([DiffKind.Ins, 10, 10, "ACG", 5000])).toEqual({
+    type: "ins",
+    interval: { start: 10n, end: 10n },
+    sequence: "ACG",
+    fullLength: 5000,
+  });
+});
+
+test("decodeSequenceDiff decodes diffs without a sequence", async () => {
+  expect(decodeSequenceDiff([DiffKind.Del, 10, 12])).toEqual({
+    type: "del",
+    interval: { start: 10n, end: 12n },
+  });
+});
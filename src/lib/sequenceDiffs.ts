@@ -0,0 +1,32 @@
+/** Decoding of the compact sequence diffs which are sent by the backend. */
+import type { CompactSequenceDiff, SequenceDiff } from "@lib/bindings";
+import { DiffKind } from "@lib/bindings";
+
+/**
+ * Version of the compact diff format which this module can decode.
+ *
+ * Must match DIFF_SCHEMA_VERSION in the backend.
+ */
+export const DIFF_SCHEMA_VERSION = 2;
+
+/**
+ * Convert a compact diff tuple into a tagged object.
+ *
+ * Diffs are decoded lazily at render time so that reads which are never drawn don't pay the cost.
+ */
+export const decodeSequenceDiff = (diff: CompactSequenceDiff): SequenceDiff => {
+  const [kind, start, end, sequence, fullLength] = diff;
+  const interval = { start: BigInt(start), end: BigInt(end) };
+  switch (kind) {
+    case DiffKind.Mismatch:
+      return { type: "mismatch", interval, sequence: sequence! };
+    case DiffKind.Ins:
+      return { type: "ins", interval, sequence: sequence!, fullLength: fullLength ?? null };
+    case DiffKind.Del:
+      return { type: "del", interval };
+    case DiffKind.SoftClip:
+      return { type: "softClip", interval, sequence: sequence! };
+    case DiffKind.RefSkip:
+      return { type: "refSkip", interval };
+  }
+};